@@ -0,0 +1,45 @@
+//! Benchmarks the built-in backends with the standard suite.
+//!
+//! The MongoDB benchmarks require the `mongodb` feature and a server to run against,
+//! configured through `DOCLAYER_BENCH_MONGODB_DSN` and `DOCLAYER_BENCH_MONGODB_DATABASE`.
+//! They are skipped when the DSN is not set.
+//!
+//! ```text
+//! cargo bench -p doclayer-bench
+//! DOCLAYER_BENCH_MONGODB_DSN=mongodb://localhost:27017 DOCLAYER_BENCH_MONGODB_DATABASE=bench \
+//!     cargo bench -p doclayer-bench --features mongodb
+//! ```
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use doclayer::{backend::StoreBackendBuilder, memory::InMemoryStore};
+use doclayer_bench::{Dataset, StoreBench};
+
+fn memory(c: &mut Criterion) {
+    StoreBench::new("memory", || InMemoryStore::builder().build())
+        .with_dataset(Dataset::medium())
+        .run(c);
+}
+
+#[cfg(feature = "mongodb")]
+fn mongodb(c: &mut Criterion) {
+    use doclayer::mongodb::MongoDbStoreBuilder;
+
+    if std::env::var("DOCLAYER_BENCH_MONGODB_DSN").is_err() {
+        eprintln!("DOCLAYER_BENCH_MONGODB_DSN is not set, skipping the MongoDB benchmarks");
+        return;
+    }
+
+    StoreBench::new("mongodb", || async {
+        MongoDbStoreBuilder::from_env("DOCLAYER_BENCH_MONGODB")?
+            .build()
+            .await
+    })
+    .with_dataset(Dataset::medium())
+    .run(c);
+}
+
+#[cfg(not(feature = "mongodb"))]
+criterion_group!(benches, memory);
+#[cfg(feature = "mongodb")]
+criterion_group!(benches, memory, mongodb);
+criterion_main!(benches);
@@ -0,0 +1,152 @@
+//! Standardized benchmark datasets.
+//!
+//! A [`Dataset`] generates [`BenchDocument`]s from a seed, so every run, and every backend,
+//! benchmarks against exactly the same documents.
+
+use doclayer::{Document, bson::Uuid};
+use serde::{Deserialize, Serialize};
+
+/// The number of documents in [`Dataset::small`].
+pub const SMALL: usize = 1_000;
+/// The number of documents in [`Dataset::medium`].
+pub const MEDIUM: usize = 10_000;
+/// The number of documents in [`Dataset::large`].
+pub const LARGE: usize = 100_000;
+
+/// The number of distinct [`BenchDocument::group`] values.
+pub const GROUPS: i32 = 100;
+
+const DEFAULT_SEED: u64 = 0x5EED_D0C5;
+
+const TAGS: [&str; 8] = [
+    "alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta",
+];
+
+/// A document shaped like a typical application record, with fields of the common types.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Document)]
+#[document(collection = "bench_documents")]
+pub struct BenchDocument {
+    pub id: Uuid,
+    pub name: String,
+    pub email: String,
+    /// Uniform in `18..=80`, for range filters.
+    pub age: i32,
+    /// Uniform in `0.0..1000.0`, for sorting.
+    pub score: f64,
+    /// True for roughly half of the documents.
+    pub active: bool,
+    /// Uniform in `0..GROUPS`, for equality filters matching about 1% of the documents.
+    pub group: i32,
+    pub tags: Vec<String>,
+}
+
+/// A deterministic set of [`BenchDocument`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dataset {
+    size: usize,
+    seed: u64,
+}
+
+impl Dataset {
+    /// Creates a dataset of `size` documents.
+    pub fn new(size: usize) -> Self {
+        Self { size, seed: DEFAULT_SEED }
+    }
+
+    /// A dataset of [`SMALL`] documents.
+    pub fn small() -> Self {
+        Self::new(SMALL)
+    }
+
+    /// A dataset of [`MEDIUM`] documents.
+    pub fn medium() -> Self {
+        Self::new(MEDIUM)
+    }
+
+    /// A dataset of [`LARGE`] documents.
+    pub fn large() -> Self {
+        Self::new(LARGE)
+    }
+
+    /// Generates the documents from `seed` instead of the default. Datasets with the same
+    /// size and seed are identical.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Returns the number of documents in the dataset.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the seed the documents are generated from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Generates the documents.
+    pub fn documents(&self) -> Vec<BenchDocument> {
+        let mut rng = SplitMix64::new(self.seed);
+
+        (0..self.size)
+            .map(|index| rng.document(index))
+            .collect()
+    }
+}
+
+/// The SplitMix64 generator, small enough to inline and stable across releases, unlike the
+/// generators of general-purpose random crates.
+pub(crate) struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self
+            .state
+            .wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    pub(crate) fn uuid(&mut self) -> Uuid {
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&self.next_u64().to_le_bytes());
+        bytes[8..].copy_from_slice(&self.next_u64().to_le_bytes());
+
+        // Mark the bytes as a version 4 UUID, so they look like the IDs applications generate
+        bytes[6] = (bytes[6] & 0x0F) | 0x40;
+        bytes[8] = (bytes[8] & 0x3F) | 0x80;
+
+        Uuid::from_bytes(bytes)
+    }
+
+    pub(crate) fn document(&mut self, index: usize) -> BenchDocument {
+        let tags = (0..self.below(4))
+            .map(|_| TAGS[self.below(TAGS.len() as u64) as usize].to_string())
+            .collect();
+
+        BenchDocument {
+            id: self.uuid(),
+            name: format!("user-{:06}", index),
+            email: format!("user-{:06}@example.com", index),
+            age: 18 + self.below(63) as i32,
+            score: self.below(1_000_000) as f64 / 1000.0,
+            active: self.below(2) == 0,
+            group: self.below(GROUPS as u64) as i32,
+            tags,
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! Benchmarks for doclayer backends.
+//!
+//! This crate provides the standardized datasets and [criterion](https://docs.rs/criterion)
+//! suite the doclayer backends are benchmarked with, so third-party [`StoreBackend`]
+//! implementations can be measured the same way and compared against the built-in ones.
+//!
+//! - [`Dataset`] - deterministic sets of [`BenchDocument`]s in standard sizes
+//! - [`StoreBench`] - insert, query and update benchmarks run against any backend
+//!
+//! # Quick Start
+//!
+//! Add the crate and criterion as dev-dependencies, then create `benches/store.rs` with
+//! `harness = false` set for it in `Cargo.toml`:
+//!
+//! ```ignore
+//! use criterion::{Criterion, criterion_group, criterion_main};
+//! use doclayer_bench::{Dataset, StoreBench};
+//!
+//! fn benches(c: &mut Criterion) {
+//!     StoreBench::new("my_backend", || MyBackend::connect("..."))
+//!         .with_dataset(Dataset::medium())
+//!         .run(c);
+//! }
+//!
+//! criterion_group!(store, benches);
+//! criterion_main!(store);
+//! ```
+//!
+//! `cargo bench` then reports each benchmark as `my_backend/insert`, `my_backend/get` and so
+//! on. Save a baseline with `cargo bench -- --save-baseline main` and pass
+//! `--baseline main` on later runs to have criterion flag regressions against it.
+//!
+//! [`StoreBackend`]: doclayer::backend::StoreBackend
+
+pub mod dataset;
+pub mod suite;
+
+pub use dataset::{BenchDocument, Dataset};
+pub use suite::StoreBench;
@@ -0,0 +1,342 @@
+//! The standard benchmark suite run against a backend.
+
+use criterion::{BenchmarkGroup, Criterion, Throughput, measurement::WallTime};
+use doclayer::{
+    backend::StoreBackend,
+    collection::TypedCollection,
+    document::Document,
+    error::DocumentStoreResult,
+    query::{Filter, Query, SortDirection},
+    store::DocumentStore,
+    update::Update,
+};
+use std::{
+    future::Future,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+use tokio::runtime::Runtime;
+
+use crate::dataset::{BenchDocument, Dataset, GROUPS, SplitMix64};
+
+/// The number of documents inserted per call when seeding the dataset.
+const SEED_CHUNK_SIZE: usize = 1_000;
+
+/// Runs the standard insert, query and update benchmarks against a backend.
+///
+/// Every benchmark runs against the same [`Dataset`], seeded into the backend once before
+/// the first benchmark, so results are comparable across backends and across runs. The
+/// benchmarks are reported in a criterion group named after the backend:
+///
+/// - `insert` - inserts a batch of new documents
+/// - `get` - fetches one document by ID
+/// - `query_eq` - queries with an equality filter matching about 1% of the dataset
+/// - `query_range_sorted` - queries with a range filter, sorted and limited to 50 documents
+/// - `count` - counts the documents matching a filter matching about half the dataset
+/// - `update` - replaces a batch of existing documents
+/// - `update_where` - increments a field on the documents matching an equality filter
+///
+/// # Example
+///
+/// ```ignore
+/// use criterion::{Criterion, criterion_group, criterion_main};
+/// use doclayer_bench::{Dataset, StoreBench};
+///
+/// fn benches(c: &mut Criterion) {
+///     StoreBench::new("my_backend", || MyBackend::connect("..."))
+///         .with_dataset(Dataset::medium())
+///         .run(c);
+/// }
+///
+/// criterion_group!(store, benches);
+/// criterion_main!(store);
+/// ```
+pub struct StoreBench<F> {
+    name: String,
+    factory: F,
+    dataset: Dataset,
+    batch_size: usize,
+}
+
+impl<B, F, Fut> StoreBench<F>
+where
+    B: StoreBackend + 'static,
+    F: Fn() -> Fut,
+    Fut: Future<Output = DocumentStoreResult<B>>,
+{
+    /// Creates a suite for a backend.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the criterion group the results are reported in
+    /// * `factory` - Creates the backend the benchmarks run against
+    pub fn new(name: impl Into<String>, factory: F) -> Self {
+        Self {
+            name: name.into(),
+            factory,
+            dataset: Dataset::small(),
+            batch_size: 100,
+        }
+    }
+
+    /// Benchmarks against `dataset` instead of [`Dataset::small`].
+    pub fn with_dataset(mut self, dataset: Dataset) -> Self {
+        self.dataset = dataset;
+        self
+    }
+
+    /// Sets the number of documents written per call by the `insert` and `update`
+    /// benchmarks. Defaults to 100.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Creates the backend, seeds the dataset and runs the benchmarks. The dataset's
+    /// collection is dropped before seeding and again once the benchmarks finish.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the backend cannot be created or any operation fails, which criterion
+    /// reports as a failed benchmark.
+    pub fn run(&self, c: &mut Criterion) {
+        let runtime = Runtime::new().expect("failed to start the tokio runtime");
+        let store = DocumentStore::new(
+            runtime
+                .block_on((self.factory)())
+                .expect("failed to create the backend"),
+        );
+        let collection = store.typed_collection::<BenchDocument>();
+        let documents = self.dataset.documents();
+
+        runtime
+            .block_on(seed(&store, &collection, &documents))
+            .expect("failed to seed the dataset");
+
+        let mut group = c.benchmark_group(&self.name);
+        self.bench_writes(&mut group, &runtime, &collection, &documents);
+        self.bench_reads(&mut group, &runtime, &collection, &documents);
+        group.finish();
+
+        runtime
+            .block_on(async {
+                store
+                    .drop_collection(BenchDocument::collection_name())
+                    .await?;
+                store.close().await
+            })
+            .expect("failed to clean up the dataset");
+    }
+
+    fn bench_writes(
+        &self,
+        group: &mut BenchmarkGroup<'_, WallTime>,
+        runtime: &Runtime,
+        collection: &TypedCollection<'_, B, BenchDocument>,
+        documents: &[BenchDocument],
+    ) {
+        group.throughput(Throughput::Elements(self.batch_size as u64));
+
+        // Inserted documents come from their own generator, so their IDs never collide with
+        // the dataset's, and are deleted again outside the timed section to keep the dataset
+        // the same size for the benchmarks that follow
+        let mut rng = SplitMix64::new(self.dataset.seed().wrapping_add(1));
+        group.bench_function("insert", |b| {
+            b.to_async(runtime)
+                .iter_custom(|iters| {
+                    let batches = (0..iters)
+                        .map(|_| {
+                            (0..self.batch_size)
+                                .map(|index| rng.document(self.dataset.size() + index))
+                                .collect::<Vec<_>>()
+                        })
+                        .collect::<Vec<_>>();
+
+                    async move {
+                        let mut elapsed = Duration::ZERO;
+
+                        for batch in batches {
+                            let ids = batch
+                                .iter()
+                                .map(|document| document.id)
+                                .collect::<Vec<_>>();
+
+                            let started = Instant::now();
+                            collection
+                                .insert(batch)
+                                .await
+                                .expect("insert failed");
+                            elapsed += started.elapsed();
+
+                            collection
+                                .delete(ids)
+                                .await
+                                .expect("cleanup failed");
+                        }
+
+                        elapsed
+                    }
+                })
+        });
+
+        let mut offset = 0;
+        group.bench_function("update", |b| {
+            b.to_async(runtime).iter(|| {
+                let batch = window(documents, offset, self.batch_size)
+                    .map(|document| BenchDocument {
+                        score: document.score + 1.0,
+                        ..document.clone()
+                    })
+                    .collect::<Vec<_>>();
+                offset += self.batch_size;
+
+                async move {
+                    collection
+                        .update(batch)
+                        .await
+                        .expect("update failed")
+                }
+            })
+        });
+
+        group.throughput(Throughput::Elements(1));
+
+        let mut group_id = 0;
+        group.bench_function("update_where", |b| {
+            b.to_async(runtime).iter(|| {
+                let filter = Filter::eq("group", group_id % GROUPS);
+                group_id += 1;
+
+                async move {
+                    collection
+                        .update_where(
+                            filter,
+                            Update::builder()
+                                .inc("score", 1.0)
+                                .build(),
+                        )
+                        .await
+                        .expect("update_where failed")
+                }
+            })
+        });
+    }
+
+    fn bench_reads(
+        &self,
+        group: &mut BenchmarkGroup<'_, WallTime>,
+        runtime: &Runtime,
+        collection: &TypedCollection<'_, B, BenchDocument>,
+        documents: &[BenchDocument],
+    ) {
+        group.throughput(Throughput::Elements(1));
+
+        let mut offset = 0;
+        group.bench_function("get", |b| {
+            b.to_async(runtime).iter(|| {
+                let id = documents[offset % documents.len()].id;
+                offset += 1;
+
+                async move {
+                    black_box(
+                        collection
+                            .get_one(id)
+                            .await
+                            .expect("get failed"),
+                    )
+                }
+            })
+        });
+
+        let mut group_id = 0;
+        group.bench_function("query_eq", |b| {
+            b.to_async(runtime).iter(|| {
+                let query = Query::builder()
+                    .filter(Filter::eq("group", group_id % GROUPS))
+                    .build();
+                group_id += 1;
+
+                async move {
+                    black_box(
+                        collection
+                            .query(query)
+                            .await
+                            .expect("query failed"),
+                    )
+                }
+            })
+        });
+
+        let mut age = 0;
+        group.bench_function("query_range_sorted", |b| {
+            b.to_async(runtime).iter(|| {
+                let query = Query::builder()
+                    .filter(Filter::gte("age", 18 + age % 50).and(Filter::eq("active", true)))
+                    .sort("score", SortDirection::Desc)
+                    .limit(50)
+                    .build();
+                age += 1;
+
+                async move {
+                    black_box(
+                        collection
+                            .query(query)
+                            .await
+                            .expect("query failed"),
+                    )
+                }
+            })
+        });
+
+        group.bench_function("count", |b| {
+            b.to_async(runtime).iter(|| async {
+                let query = Query::builder()
+                    .filter(Filter::eq("active", true))
+                    .build();
+
+                black_box(
+                    collection
+                        .count(query)
+                        .await
+                        .expect("count failed"),
+                )
+            })
+        });
+    }
+}
+
+/// Replaces the dataset's collection with a fresh copy of the dataset.
+async fn seed<B: StoreBackend + 'static>(
+    store: &DocumentStore<B>,
+    collection: &TypedCollection<'_, B, BenchDocument>,
+    documents: &[BenchDocument],
+) -> DocumentStoreResult<()> {
+    let name = BenchDocument::collection_name();
+
+    if store.collection_exists(name).await? {
+        store.drop_collection(name).await?;
+    }
+    store.create_collection(name).await?;
+
+    for chunk in documents.chunks(SEED_CHUNK_SIZE) {
+        collection
+            .insert(chunk.to_vec())
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Iterates over `len` documents starting at `offset`, wrapping around the end of the
+/// dataset.
+fn window(
+    documents: &[BenchDocument],
+    offset: usize,
+    len: usize,
+) -> impl Iterator<Item = &BenchDocument> {
+    documents
+        .iter()
+        .cycle()
+        .skip(offset % documents.len())
+        .take(len.min(documents.len()))
+}
@@ -0,0 +1,180 @@
+//! Grouped aggregation for document stores.
+//!
+//! This module provides the [`Aggregate`] type describing a group-by computation that
+//! backends evaluate close to the data, so totals and averages don't require pulling every
+//! document to the client.
+//!
+//! # Aggregate Building
+//!
+//! Aggregates are constructed using the fluent builder API:
+//!
+//! ```ignore
+//! use doclayer::aggregate::Aggregate;
+//!
+//! let aggregate = Aggregate::builder()
+//!     .filter(Filter::eq("status", "paid"))
+//!     .group_by("category")
+//!     .count("orders")
+//!     .sum("revenue", "total")
+//!     .avg("average_order", "total")
+//!     .build();
+//!
+//! // One document per category, e.g. { "category": "books", "orders": 12, "revenue": 340.5, ... }
+//! let rows = orders.aggregate(aggregate).await?;
+//! ```
+//!
+//! # Results
+//!
+//! Every result document holds the group-by fields under their own names (dotted names are
+//! kept as literal keys), followed by one field per accumulator. Documents missing a group-by
+//! field are grouped under `null`. Without group-by fields, a single result document covers
+//! all matched documents; no documents at all produce no result. Groups are returned in no
+//! particular order.
+//!
+//! # Accumulators
+//!
+//! - [`Accumulator::Count`] - Number of documents in the group
+//! - [`Accumulator::Sum`] - Sum of the numeric values of a field
+//! - [`Accumulator::Avg`] - Average of the numeric values of a field, `null` if there are none
+//! - [`Accumulator::Min`] - Smallest value of a field, `null` if there is none
+//! - [`Accumulator::Max`] - Largest value of a field, `null` if there is none
+//!
+//! Missing and `null` values are ignored by every accumulator but `Count`, and non-numeric
+//! values are ignored by `Sum` and `Avg`.
+
+use crate::query::Expr;
+
+/// A computation over the documents of a group.
+#[derive(Debug, Clone)]
+pub enum Accumulator {
+    /// Counts the documents in the group.
+    Count,
+    /// Sums the numeric values of the field.
+    Sum(String),
+    /// Averages the numeric values of the field.
+    Avg(String),
+    /// Takes the smallest value of the field.
+    Min(String),
+    /// Takes the largest value of the field.
+    Max(String),
+}
+
+impl Accumulator {
+    /// Returns the name of the field this accumulator reads, if any.
+    pub fn field(&self) -> Option<&str> {
+        match self {
+            Accumulator::Count => None,
+            Accumulator::Sum(field)
+            | Accumulator::Avg(field)
+            | Accumulator::Min(field)
+            | Accumulator::Max(field) => Some(field),
+        }
+    }
+}
+
+/// A group-by aggregation over the documents of a collection.
+///
+/// Use [`AggregateBuilder`] for ergonomic construction.
+#[derive(Debug, Clone, Default)]
+pub struct Aggregate {
+    /// Optional filter selecting the documents to aggregate.
+    pub filter: Option<Expr>,
+    /// The fields whose values define the groups.
+    pub group_by: Vec<String>,
+    /// The accumulators computed for each group, keyed by their output field name.
+    pub accumulators: Vec<(String, Accumulator)>,
+}
+
+impl Aggregate {
+    /// Creates a new aggregate over all documents, without groups or accumulators.
+    pub fn new() -> Self {
+        Aggregate {
+            filter: None,
+            group_by: Vec::new(),
+            accumulators: Vec::new(),
+        }
+    }
+
+    /// Creates a new aggregate builder for fluent construction.
+    pub fn builder() -> AggregateBuilder {
+        AggregateBuilder::new()
+    }
+}
+
+/// Builder for constructing [`Aggregate`] instances with a fluent API.
+#[derive(Debug, Clone, Default)]
+pub struct AggregateBuilder {
+    aggregate: Aggregate,
+}
+
+impl AggregateBuilder {
+    /// Creates a new aggregate builder.
+    pub fn new() -> Self {
+        AggregateBuilder { aggregate: Aggregate::new() }
+    }
+
+    /// Sets the filter selecting the documents to aggregate.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The filter expression to apply
+    pub fn filter(mut self, filter: Expr) -> Self {
+        self.aggregate.filter = Some(filter);
+        self
+    }
+
+    /// Adds a field to group documents by.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field whose values define the groups
+    pub fn group_by(mut self, field: impl Into<String>) -> Self {
+        self.aggregate
+            .group_by
+            .push(field.into());
+        self
+    }
+
+    /// Adds an accumulator to compute for each group.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The output field holding the result
+    /// * `accumulator` - The computation to perform
+    pub fn accumulate(mut self, name: impl Into<String>, accumulator: Accumulator) -> Self {
+        self.aggregate
+            .accumulators
+            .push((name.into(), accumulator));
+        self
+    }
+
+    /// Counts the documents of each group into `name`.
+    pub fn count(self, name: impl Into<String>) -> Self {
+        self.accumulate(name, Accumulator::Count)
+    }
+
+    /// Sums the numeric values of `field` into `name`.
+    pub fn sum(self, name: impl Into<String>, field: impl Into<String>) -> Self {
+        self.accumulate(name, Accumulator::Sum(field.into()))
+    }
+
+    /// Averages the numeric values of `field` into `name`.
+    pub fn avg(self, name: impl Into<String>, field: impl Into<String>) -> Self {
+        self.accumulate(name, Accumulator::Avg(field.into()))
+    }
+
+    /// Stores the smallest value of `field` into `name`.
+    pub fn min(self, name: impl Into<String>, field: impl Into<String>) -> Self {
+        self.accumulate(name, Accumulator::Min(field.into()))
+    }
+
+    /// Stores the largest value of `field` into `name`.
+    pub fn max(self, name: impl Into<String>, field: impl Into<String>) -> Self {
+        self.accumulate(name, Accumulator::Max(field.into()))
+    }
+
+    /// Builds and returns the final aggregate.
+    pub fn build(self) -> Aggregate {
+        self.aggregate
+    }
+}
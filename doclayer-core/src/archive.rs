@@ -0,0 +1,417 @@
+//! Declarative archival of old documents.
+//!
+//! An [`ArchivePolicy`] describes which documents of a collection are due for archival and
+//! where they go: another collection of the same store, or a collection of a separate (usually
+//! cheaper) store. Policies are applied once through [`ArchiveHost::archive`], or periodically
+//! by an [`Archiver`] running as a background task.
+//!
+//! Documents are moved in batches: each batch is upserted into the target and then deleted from
+//! the source, so a run interrupted between the two steps can simply be repeated.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use chrono::Duration;
+//! use doclayer::archive::{ArchivePolicy, ArchiveHost, Archiver};
+//!
+//! let policy = ArchivePolicy::builder::<Ticket>()
+//!     .older_than("closed_at", Duration::days(180))
+//!     .filter(Filter::eq("status", "closed"))
+//!     .to_collection("tickets_archive")
+//!     .build();
+//!
+//! // Review what would be moved
+//! let report = store.archive_dry_run(&policy).await?;
+//! println!("{} tickets would be archived", report.count());
+//!
+//! // Archive every hour in the background
+//! let archiver = Archiver::new(store).with_policy(policy);
+//! tokio::spawn(async move {
+//!     archiver
+//!         .run(std::time::Duration::from_secs(3600), tokio::time::sleep, |report| {
+//!             println!("{report:?}");
+//!         })
+//!         .await
+//! });
+//! ```
+
+use async_trait::async_trait;
+use bson::{Bson, Uuid};
+use chrono::{TimeDelta, Utc};
+use futures::TryStreamExt;
+use std::{future::Future, sync::Arc, time::Duration};
+
+use crate::{
+    document::{Document, DocumentExt},
+    error::{DocumentStoreError, DocumentStoreResult},
+    query::{Expr, Filter, Query},
+    store::{AsDynDocumentStore, DynDocumentStore},
+};
+
+/// The default number of documents moved per batch.
+pub const DEFAULT_ARCHIVE_BATCH_SIZE: usize = 500;
+
+/// Where an [`ArchivePolicy`] moves documents to.
+#[derive(Debug, Clone)]
+pub enum ArchiveTarget {
+    /// A collection of the store the documents are archived from.
+    Collection(String),
+    /// A collection of another store, such as a cold storage backend.
+    Store {
+        /// The store receiving the archived documents.
+        store: Arc<DynDocumentStore>,
+        /// The collection of the store receiving the archived documents.
+        collection: String,
+    },
+}
+
+/// A declarative rule moving matching documents out of a collection.
+///
+/// Use [`ArchivePolicyBuilder`] for ergonomic construction.
+#[derive(Debug, Clone)]
+pub struct ArchivePolicy {
+    /// The collection documents are archived from.
+    pub collection: String,
+    /// Optional filter selecting the documents to archive.
+    pub filter: Option<Expr>,
+    /// Optional datetime field and age after which documents are archived.
+    pub older_than: Option<(String, TimeDelta)>,
+    /// Where archived documents are moved to.
+    pub target: ArchiveTarget,
+    /// The number of documents moved per batch.
+    pub batch_size: usize,
+    /// Reads the ID of a stored document of the policy's document type.
+    id_of: fn(Bson) -> DocumentStoreResult<Uuid>,
+}
+
+impl ArchivePolicy {
+    /// Creates a new archive policy builder for the document type `D`.
+    ///
+    /// Without further configuration, the policy archives every document of `D`'s collection
+    /// into a collection of the same name suffixed with `_archive`.
+    pub fn builder<D: Document>() -> ArchivePolicyBuilder {
+        ArchivePolicyBuilder::new::<D>()
+    }
+
+    /// Builds the filter selecting the documents due for archival at the current time.
+    pub fn due_filter(&self) -> Option<Expr> {
+        // The cutoff is computed on every run, so a scheduled policy keeps a sliding window
+        let age = self
+            .older_than
+            .as_ref()
+            .map(|(field, age)| {
+                Filter::lt(
+                    field.clone(),
+                    Bson::DateTime(bson::DateTime::from_chrono(Utc::now() - *age)),
+                )
+            });
+
+        match (self.filter.clone(), age) {
+            (Some(filter), Some(age)) => Some(Filter::and(vec![filter, age])),
+            (filter, age) => filter.or(age),
+        }
+    }
+}
+
+/// Builder for constructing [`ArchivePolicy`] instances with a fluent API.
+#[derive(Debug, Clone)]
+pub struct ArchivePolicyBuilder {
+    policy: ArchivePolicy,
+}
+
+impl ArchivePolicyBuilder {
+    /// Creates a new archive policy builder for the document type `D`.
+    pub fn new<D: Document>() -> Self {
+        ArchivePolicyBuilder {
+            policy: ArchivePolicy {
+                collection: D::collection_name().to_string(),
+                filter: None,
+                older_than: None,
+                target: ArchiveTarget::Collection(format!("{}_archive", D::collection_name())),
+                batch_size: DEFAULT_ARCHIVE_BATCH_SIZE,
+                id_of: |bson| Ok(*D::from_bson(bson)?.id()),
+            },
+        }
+    }
+
+    /// Sets the filter selecting the documents to archive.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The filter expression to apply
+    pub fn filter(mut self, filter: Expr) -> Self {
+        self.policy.filter = Some(filter);
+        self
+    }
+
+    /// Archives documents once a datetime field is older than the given age.
+    ///
+    /// The field must be stored as a BSON datetime. Combined with [`filter`](Self::filter),
+    /// only documents matching both are archived.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The datetime field to compare (dot notation is supported)
+    /// * `age` - How long ago the field's value must be
+    pub fn older_than(mut self, field: impl Into<String>, age: TimeDelta) -> Self {
+        self.policy.older_than = Some((field.into(), age));
+        self
+    }
+
+    /// Moves archived documents to another collection of the same store.
+    ///
+    /// # Arguments
+    ///
+    /// * `collection` - The name of the archive collection
+    pub fn to_collection(mut self, collection: impl Into<String>) -> Self {
+        self.policy.target = ArchiveTarget::Collection(collection.into());
+        self
+    }
+
+    /// Moves archived documents to a collection of another store.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store receiving the archived documents
+    /// * `collection` - The name of the archive collection in that store
+    pub fn to_store(mut self, store: Arc<DynDocumentStore>, collection: impl Into<String>) -> Self {
+        self.policy.target = ArchiveTarget::Store { store, collection: collection.into() };
+        self
+    }
+
+    /// Sets the number of documents moved per batch.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch_size` - The batch size, at least 1
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.policy.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Builds and returns the final archive policy.
+    pub fn build(self) -> ArchivePolicy {
+        self.policy
+    }
+}
+
+/// The outcome of applying an [`ArchivePolicy`].
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveReport {
+    /// The collection documents were archived from.
+    pub collection: String,
+    /// Whether the run was a dry run that didn't move anything.
+    pub dry_run: bool,
+    /// The IDs of the archived documents, or of those a dry run would archive.
+    pub ids: Vec<Uuid>,
+}
+
+impl ArchiveReport {
+    /// Returns the number of archived documents.
+    pub fn count(&self) -> usize {
+        self.ids.len()
+    }
+}
+
+/// Extension trait for applying archive policies to a store.
+///
+/// This trait is automatically implemented for every store type.
+#[async_trait]
+pub trait ArchiveHost: Send + Sync {
+    /// Moves the documents due for archival under a policy to its target.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The policy to apply
+    ///
+    /// # Returns
+    ///
+    /// A report listing the archived documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the target is the source collection or an operation fails.
+    /// Batches moved before the failure stay archived.
+    async fn archive(&self, policy: &ArchivePolicy) -> DocumentStoreResult<ArchiveReport>;
+
+    /// Lists the documents a policy would archive, without moving them.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The policy to evaluate
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails.
+    async fn archive_dry_run(&self, policy: &ArchivePolicy) -> DocumentStoreResult<ArchiveReport>;
+}
+
+#[async_trait]
+impl<T> ArchiveHost for T
+where
+    T: AsDynDocumentStore + Send + Sync,
+{
+    async fn archive(&self, policy: &ArchivePolicy) -> DocumentStoreResult<ArchiveReport> {
+        let store = self.as_dyn();
+        let (target_store, target_collection) = match &policy.target {
+            ArchiveTarget::Collection(collection) => {
+                // Moving documents within their own collection would never run out of batches
+                if *collection == policy.collection {
+                    return Err(DocumentStoreError::InvalidDocument(format!(
+                        "Archive policy for {} targets its own collection",
+                        policy.collection
+                    )));
+                }
+
+                (store.as_dyn(), collection)
+            }
+            ArchiveTarget::Store { store, collection } => (store.as_dyn(), collection),
+        };
+
+        target_store
+            .ensure_collection(target_collection)
+            .await?;
+
+        let source = store.collection(&policy.collection);
+        let target = target_store.collection(target_collection);
+        let query = Query {
+            filter: policy.due_filter(),
+            limit: Some(policy.batch_size),
+            ..Query::default()
+        };
+        let mut report = ArchiveReport {
+            collection: policy.collection.clone(),
+            dry_run: false,
+            ids: Vec::new(),
+        };
+
+        loop {
+            let batch = source
+                .query(query.clone())
+                .await?
+                .into_iter()
+                .map(|doc| Ok(((policy.id_of)(doc.clone())?, doc)))
+                .collect::<DocumentStoreResult<Vec<(Uuid, Bson)>>>()?;
+
+            if batch.is_empty() {
+                break;
+            }
+
+            let ids = batch
+                .iter()
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>();
+
+            // Upserting first keeps a document in at least one collection if the delete fails
+            target.upsert(batch).await?;
+
+            // Stop rather than loop forever if the backend didn't remove the batch
+            if source.delete(ids.clone()).await? == 0 {
+                break;
+            }
+
+            report.ids.extend(ids);
+        }
+
+        Ok(report)
+    }
+
+    async fn archive_dry_run(&self, policy: &ArchivePolicy) -> DocumentStoreResult<ArchiveReport> {
+        let store = self.as_dyn();
+        let ids = store
+            .collection(&policy.collection)
+            .query_stream(Query {
+                filter: policy.due_filter(),
+                ..Query::default()
+            })
+            .await?
+            .and_then(async |doc| (policy.id_of)(doc))
+            .try_collect()
+            .await?;
+
+        Ok(ArchiveReport {
+            collection: policy.collection.clone(),
+            dry_run: true,
+            ids,
+        })
+    }
+}
+
+/// Applies a set of archive policies to a store on a schedule.
+///
+/// The archiver doesn't depend on an async runtime: [`run`](Archiver::run) takes the runtime's
+/// sleep function and is meant to be spawned as a background task.
+#[derive(Debug)]
+pub struct Archiver<S> {
+    store: S,
+    policies: Vec<ArchivePolicy>,
+    dry_run: bool,
+}
+
+impl<S> Archiver<S>
+where
+    S: AsDynDocumentStore + Send + Sync,
+{
+    /// Creates a new archiver for the given store, without policies.
+    pub fn new(store: S) -> Self {
+        Archiver {
+            store,
+            policies: Vec::new(),
+            dry_run: false,
+        }
+    }
+
+    /// Adds a policy to apply on every run.
+    pub fn with_policy(mut self, policy: ArchivePolicy) -> Self {
+        self.policies.push(policy);
+        self
+    }
+
+    /// Only reports the documents due for archival instead of moving them.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Applies every policy once, in the order they were added.
+    ///
+    /// # Returns
+    ///
+    /// The outcome of each policy. A failing policy doesn't prevent the others from running.
+    pub async fn run_once(&self) -> Vec<DocumentStoreResult<ArchiveReport>> {
+        let mut reports = Vec::with_capacity(self.policies.len());
+
+        for policy in &self.policies {
+            reports.push(match self.dry_run {
+                true => self.store.archive_dry_run(policy).await,
+                false => self.store.archive(policy).await,
+            });
+        }
+
+        reports
+    }
+
+    /// Applies every policy repeatedly, waiting `interval` between runs.
+    ///
+    /// This never returns; cancel it by dropping the future or aborting its task.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - The time to wait after each run
+    /// * `sleep` - The async runtime's sleep function, such as `tokio::time::sleep`
+    /// * `on_report` - Called with the outcome of each policy after every run
+    pub async fn run<F, Fut, R>(&self, interval: Duration, sleep: F, mut on_report: R)
+    where
+        F: Fn(Duration) -> Fut,
+        Fut: Future<Output = ()>,
+        R: FnMut(DocumentStoreResult<ArchiveReport>),
+    {
+        loop {
+            for report in self.run_once().await {
+                on_report(report);
+            }
+
+            sleep(interval).await;
+        }
+    }
+}
@@ -0,0 +1,922 @@
+//! Recording who changed which document, and when.
+//!
+//! [`AuditedStore`] wraps a backend and records every insert, update and delete it applies as
+//! an [`AuditEntry`] in the [`AUDIT_COLLECTION`] of the same backend. An entry names the
+//! changed document, the action, the actor and the time of the change, along with the values
+//! of the changed fields before and after it. Entries are ordinary documents, queried through
+//! the store like any other collection.
+//!
+//! The actor is read from a function called on every write, which usually looks it up in a
+//! task-local set by the request handler.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::audit::{AuditEntry, AuditedStore};
+//!
+//! tokio::task_local! {
+//!     static ACTOR: String;
+//! }
+//!
+//! let store = DocumentStore::new(
+//!     AuditedStore::new(backend).with_actor(|| ACTOR.try_with(Clone::clone).ok()),
+//! );
+//!
+//! ACTOR
+//!     .scope("alice".to_string(), async {
+//!         store.typed_collection::<User>().update(vec![user]).await
+//!     })
+//!     .await?;
+//!
+//! // Who changed this user, and when?
+//! let history = store
+//!     .typed_collection::<AuditEntry>()
+//!     .find()
+//!     .filter(Filter::eq("document_id", user_id))
+//!     .sort_asc("timestamp")
+//!     .await?;
+//! ```
+//!
+//! # Limitations
+//!
+//! - The previous state of the documents is read just before each write, so a concurrent
+//!   write in between can make an entry inexact. Writes made in a transaction are recorded in
+//!   the same transaction, and committed or rolled back along with it.
+//! - Writes by query read the matching documents first, and need to find their IDs in the
+//!   [ID field](AuditedStore::with_id_field) to record them. Writes by ID are recorded by the
+//!   IDs they were given.
+//! - Collection-level operations, such as dropping a collection or renaming a field in every
+//!   document, aren't recorded, and neither are writes to the audit collection itself.
+//! - A failure to record a write is returned as the write's error, although the write was
+//!   applied.
+
+use async_trait::async_trait;
+use bson::{Bson, RawDocumentBuf, Uuid};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use crate::{
+    aggregate::Aggregate,
+    backend::{
+        DocumentStream, DynStoreBackend, InsertPolicy, MissingDocumentPolicy, QueryPlan,
+        ReturnDocument, StoreBackend, WriteReport,
+    },
+    cache::DEFAULT_ID_FIELD,
+    document::{Document, DocumentExt, stored_id},
+    error::{DocumentStoreError, DocumentStoreResult},
+    query::{Expr, Query, Sort},
+    transaction::{Transaction, TransactionBackend},
+    update::Update,
+};
+
+/// The collection holding the [`AuditEntry`] documents.
+pub const AUDIT_COLLECTION: &str = "_audit";
+
+type ActorFn = Arc<dyn Fn() -> Option<String> + Send + Sync>;
+
+/// What a write did to a document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// The document was inserted.
+    Insert,
+    /// The document was replaced or partially updated.
+    Update,
+    /// The document was deleted.
+    Delete,
+}
+
+/// A change to a document, recorded by an [`AuditedStore`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// The ID of the entry.
+    pub id: Uuid,
+    /// The collection of the changed document.
+    pub collection: String,
+    /// The ID of the changed document.
+    pub document_id: Uuid,
+    /// What the write did to the document.
+    pub action: AuditAction,
+    /// Who made the change, if the store's actor function returned someone.
+    pub actor: Option<String>,
+    /// When the change was recorded.
+    pub timestamp: bson::DateTime,
+    /// The names of the top-level fields the change added, changed or removed.
+    pub fields: Vec<String>,
+    /// The values of the changed fields before the change, leaving out added fields.
+    pub before: bson::Document,
+    /// The values of the changed fields after the change, leaving out removed fields.
+    pub after: bson::Document,
+}
+
+impl AuditEntry {
+    /// Describes the change between two states of a document, or returns `None` if nothing
+    /// changed.
+    fn new(
+        collection: &str,
+        document_id: Uuid,
+        before: Option<Bson>,
+        after: Option<Bson>,
+        actor: Option<String>,
+        timestamp: bson::DateTime,
+    ) -> Option<Self> {
+        let action = match (&before, &after) {
+            (None, Some(_)) => AuditAction::Insert,
+            (Some(_), Some(_)) => AuditAction::Update,
+            (Some(_), None) => AuditAction::Delete,
+            (None, None) => return None,
+        };
+        let before = fields_of(before);
+        let after = fields_of(after);
+
+        let mut fields = Vec::new();
+        let mut changed_before = bson::Document::new();
+        let mut changed_after = bson::Document::new();
+
+        for (field, value) in &before {
+            if after.get(field) != Some(value) {
+                fields.push(field.clone());
+                changed_before.insert(field.clone(), value.clone());
+            }
+        }
+        for (field, value) in &after {
+            if before.get(field) != Some(value) {
+                if !before.contains_key(field) {
+                    fields.push(field.clone());
+                }
+                changed_after.insert(field.clone(), value.clone());
+            }
+        }
+
+        if fields.is_empty() && action == AuditAction::Update {
+            return None;
+        }
+
+        Some(AuditEntry {
+            id: Uuid::new(),
+            collection: collection.to_string(),
+            document_id,
+            action,
+            actor,
+            timestamp,
+            fields,
+            before: changed_before,
+            after: changed_after,
+        })
+    }
+}
+
+impl Document for AuditEntry {
+    fn id(&self) -> &Uuid {
+        &self.id
+    }
+
+    fn collection_name() -> &'static str {
+        AUDIT_COLLECTION
+    }
+}
+
+/// The fields of a stored document, or none if there is no document.
+fn fields_of(document: Option<Bson>) -> bson::Document {
+    match document {
+        Some(Bson::Document(fields)) => fields,
+        _ => bson::Document::new(),
+    }
+}
+
+/// A backend recording the changes of every write in its [`AUDIT_COLLECTION`].
+///
+/// See the [module documentation](self) for what is recorded.
+pub struct AuditedStore<B> {
+    backend: B,
+    id_field: String,
+    actor: Option<ActorFn>,
+}
+
+impl<B: StoreBackend> AuditedStore<B> {
+    /// Wraps a backend so its writes are recorded, without an actor until one is set.
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            id_field: DEFAULT_ID_FIELD.to_string(),
+            actor: None,
+        }
+    }
+
+    /// Sets the field holding the ID of stored documents, `id` by default.
+    pub fn with_id_field(mut self, field: impl Into<String>) -> Self {
+        self.id_field = field.into();
+        self
+    }
+
+    /// Sets the function returning who is making a write, called once per write.
+    pub fn with_actor(
+        mut self,
+        actor: impl Fn() -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.actor = Some(Arc::new(actor));
+        self
+    }
+
+    /// Returns the wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.backend
+    }
+
+    /// Unwraps the backend.
+    pub fn into_inner(self) -> B {
+        self.backend
+    }
+
+    /// Wraps another backend with the same configuration.
+    fn wrap<T: StoreBackend>(&self, backend: T) -> AuditedStore<T> {
+        AuditedStore {
+            backend,
+            id_field: self.id_field.clone(),
+            actor: self.actor.clone(),
+        }
+    }
+
+    /// Returns `true` if writes to a collection are recorded.
+    fn is_audited(collection: &str) -> bool {
+        collection != AUDIT_COLLECTION
+    }
+
+    /// Reads the ID of a stored document, failing if it has none.
+    fn document_id(&self, document: &Bson, collection: &str) -> DocumentStoreResult<Uuid> {
+        stored_id(document, &self.id_field).ok_or_else(|| {
+            DocumentStoreError::InvalidDocument(format!(
+                "A document changed in {} has no ID in its {} field to audit it by",
+                collection, self.id_field
+            ))
+        })
+    }
+
+    /// Reads the current state of documents by their IDs.
+    ///
+    /// The documents are keyed by the IDs they were read by, so writes by ID are recorded
+    /// whether or not the documents hold their ID in the ID field.
+    async fn current(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<HashMap<Uuid, Bson>> {
+        if ids.is_empty() || !Self::is_audited(collection) {
+            return Ok(HashMap::new());
+        }
+
+        let documents = self
+            .backend
+            .get_documents(ids.clone(), collection)
+            .await?;
+        let keyed = documents
+            .iter()
+            .map(|document| stored_id(document, &self.id_field).map(|id| (id, document.clone())))
+            .collect::<Option<HashMap<_, _>>>();
+
+        if let Some(keyed) = keyed {
+            return Ok(keyed);
+        }
+
+        // A batch read doesn't say which document was read by which ID, so documents without
+        // their ID are read one at a time
+        let reads = ids.into_iter().map(|id| async move {
+            let document = self
+                .backend
+                .get_documents(vec![id], collection)
+                .await?
+                .into_iter()
+                .next();
+
+            Ok::<_, DocumentStoreError>(document.map(|document| (id, document)))
+        });
+
+        Ok(futures::future::try_join_all(reads)
+            .await?
+            .into_iter()
+            .flatten()
+            .collect())
+    }
+
+    /// Reads the current state of the documents matching a filter.
+    async fn matching(
+        &self,
+        filter: &Expr,
+        collection: &str,
+    ) -> DocumentStoreResult<HashMap<Uuid, Bson>> {
+        if !Self::is_audited(collection) {
+            return Ok(HashMap::new());
+        }
+
+        self.backend
+            .query_documents(
+                Query {
+                    filter: Some(filter.clone()),
+                    ..Query::default()
+                },
+                collection,
+            )
+            .await?
+            .into_iter()
+            .map(|document| Ok((self.document_id(&document, collection)?, document)))
+            .collect()
+    }
+
+    /// Records the changes of a write, as the states of each document before and after it.
+    async fn record(
+        &self,
+        collection: &str,
+        changes: Vec<(Uuid, Option<Bson>, Option<Bson>)>,
+    ) -> DocumentStoreResult<()> {
+        if !Self::is_audited(collection) {
+            return Ok(());
+        }
+
+        let actor = self
+            .actor
+            .as_ref()
+            .and_then(|actor| actor());
+        let timestamp = bson::DateTime::now();
+        let entries = changes
+            .into_iter()
+            .filter_map(|(id, before, after)| {
+                AuditEntry::new(collection, id, before, after, actor.clone(), timestamp)
+            })
+            .map(|entry| {
+                entry
+                    .to_bson_encoded(self.backend.encoding())
+                    .map(|bson| (entry.id, bson))
+            })
+            .collect::<DocumentStoreResult<Vec<(Uuid, Bson)>>>()?;
+
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        match self
+            .backend
+            .insert_documents(entries.clone(), AUDIT_COLLECTION, InsertPolicy::ErrorOnConflict)
+            .await
+        {
+            // Backends that don't create collections on first write
+            Err(DocumentStoreError::CollectionNotFound(_)) => {
+                self.backend
+                    .ensure_collection(AUDIT_COLLECTION)
+                    .await?;
+                self.backend
+                    .insert_documents(entries, AUDIT_COLLECTION, InsertPolicy::ErrorOnConflict)
+                    .await
+                    .map(|_| ())
+            }
+            result => result.map(|_| ()),
+        }
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for AuditedStore<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuditedStore")
+            .field("backend", &self.backend)
+            .field("id_field", &self.id_field)
+            .field("actor", &self.actor.is_some())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<B: StoreBackend + 'static> StoreBackend for AuditedStore<B> {
+    async fn insert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        // Inserts that fail on conflict never replace a document
+        let mut before = match policy {
+            InsertPolicy::ErrorOnConflict => HashMap::new(),
+            InsertPolicy::Replace | InsertPolicy::Skip => {
+                self.current(
+                    documents
+                        .iter()
+                        .map(|(id, _)| *id)
+                        .collect(),
+                    collection,
+                )
+                .await?
+            }
+        };
+        let report = self
+            .backend
+            .insert_documents(documents.clone(), collection, policy)
+            .await?;
+
+        self.record(
+            collection,
+            documents
+                .into_iter()
+                .filter_map(|(id, document)| match (before.remove(&id), policy) {
+                    (Some(_), InsertPolicy::Skip) => None,
+                    (previous, _) => Some((id, previous, Some(document))),
+                })
+                .collect(),
+        )
+        .await?;
+
+        Ok(report)
+    }
+
+    async fn update_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        let mut before = self
+            .current(
+                documents
+                    .iter()
+                    .map(|(id, _)| *id)
+                    .collect(),
+                collection,
+            )
+            .await?;
+        let report = self
+            .backend
+            .update_documents(documents.clone(), collection, policy)
+            .await?;
+
+        self.record(
+            collection,
+            documents
+                .into_iter()
+                .filter_map(|(id, document)| match (before.remove(&id), policy) {
+                    (None, MissingDocumentPolicy::Error | MissingDocumentPolicy::Skip) => None,
+                    (previous, _) => Some((id, previous, Some(document))),
+                })
+                .collect(),
+        )
+        .await?;
+
+        Ok(report)
+    }
+
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        let mut before = self
+            .current(
+                documents
+                    .iter()
+                    .map(|(id, _, _)| *id)
+                    .collect(),
+                collection,
+            )
+            .await?;
+        let report = self
+            .backend
+            .update_documents_if_version(documents.clone(), version_field, collection)
+            .await?;
+
+        self.record(
+            collection,
+            documents
+                .into_iter()
+                .filter_map(|(id, document, _)| {
+                    before
+                        .remove(&id)
+                        .map(|previous| (id, Some(previous), Some(document)))
+                })
+                .collect(),
+        )
+        .await?;
+
+        Ok(report)
+    }
+
+    async fn update_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, Bson, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        let mut before = self
+            .current(
+                documents
+                    .iter()
+                    .map(|(id, _, _)| *id)
+                    .collect(),
+                collection,
+            )
+            .await?;
+        let report = self
+            .backend
+            .update_documents_if_match(documents.clone(), collection)
+            .await?;
+
+        self.record(
+            collection,
+            documents
+                .into_iter()
+                .filter_map(|(id, document, _)| {
+                    before
+                        .remove(&id)
+                        .map(|previous| (id, Some(previous), Some(document)))
+                })
+                .collect(),
+        )
+        .await?;
+
+        Ok(report)
+    }
+
+    async fn upsert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+    ) -> DocumentStoreResult<()> {
+        let mut before = self
+            .current(
+                documents
+                    .iter()
+                    .map(|(id, _)| *id)
+                    .collect(),
+                collection,
+            )
+            .await?;
+        self.backend
+            .upsert_documents(documents.clone(), collection)
+            .await?;
+
+        self.record(
+            collection,
+            documents
+                .into_iter()
+                .map(|(id, document)| (id, before.remove(&id), Some(document)))
+                .collect(),
+        )
+        .await
+    }
+
+    async fn delete_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        let before = self
+            .current(ids.clone(), collection)
+            .await?;
+        let deleted = self
+            .backend
+            .delete_documents(ids, collection)
+            .await?;
+
+        self.record(
+            collection,
+            before
+                .into_iter()
+                .map(|(id, previous)| (id, Some(previous), None))
+                .collect(),
+        )
+        .await?;
+
+        Ok(deleted)
+    }
+
+    async fn delete_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        let before = self
+            .current(
+                documents
+                    .iter()
+                    .map(|(id, _)| *id)
+                    .collect(),
+                collection,
+            )
+            .await?;
+        let deleted = self
+            .backend
+            .delete_documents_if_match(documents, collection)
+            .await?;
+
+        self.record(
+            collection,
+            before
+                .into_iter()
+                .map(|(id, previous)| (id, Some(previous), None))
+                .collect(),
+        )
+        .await?;
+
+        Ok(deleted)
+    }
+
+    async fn update_by_query(
+        &self,
+        filter: Expr,
+        update: Update,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        let before = self
+            .matching(&filter, collection)
+            .await?;
+        let report = self
+            .backend
+            .update_by_query(filter, update, collection)
+            .await?;
+        let mut after = self
+            .current(before.keys().copied().collect(), collection)
+            .await?;
+
+        self.record(
+            collection,
+            before
+                .into_iter()
+                .map(|(id, previous)| (id, Some(previous), after.remove(&id)))
+                .collect(),
+        )
+        .await?;
+
+        Ok(report)
+    }
+
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize> {
+        let before = self
+            .matching(&filter, collection)
+            .await?;
+        let deleted = self
+            .backend
+            .delete_by_query(filter, collection)
+            .await?;
+
+        self.record(
+            collection,
+            before
+                .into_iter()
+                .map(|(id, previous)| (id, Some(previous), None))
+                .collect(),
+        )
+        .await?;
+
+        Ok(deleted)
+    }
+
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        if !Self::is_audited(collection) {
+            return self
+                .backend
+                .find_one_and_update(filter, sort, update, returned, collection)
+                .await;
+        }
+
+        // The document before the update is only available from the update itself
+        let Some(before) = self
+            .backend
+            .find_one_and_update(filter, sort, update, ReturnDocument::Before, collection)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let id = self.document_id(&before, collection)?;
+        let after = self
+            .current(vec![id], collection)
+            .await?
+            .remove(&id);
+
+        self.record(collection, vec![(id, Some(before.clone()), after.clone())])
+            .await?;
+
+        Ok(match returned {
+            ReturnDocument::Before => Some(before),
+            ReturnDocument::After => after,
+        })
+    }
+
+    async fn find_one_and_delete(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        let Some(before) = self
+            .backend
+            .find_one_and_delete(filter, sort, collection)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        if Self::is_audited(collection) {
+            let id = self.document_id(&before, collection)?;
+            self.record(collection, vec![(id, Some(before.clone()), None)])
+                .await?;
+        }
+
+        Ok(Some(before))
+    }
+
+    async fn get_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .get_documents(ids, collection)
+            .await
+    }
+
+    async fn query_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .query_documents(query, collection)
+            .await
+    }
+
+    async fn query_stream(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<DocumentStream> {
+        self.backend
+            .query_stream(query, collection)
+            .await
+    }
+
+    async fn query_raw_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<RawDocumentBuf>> {
+        self.backend
+            .query_raw_documents(query, collection)
+            .await
+    }
+
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize> {
+        self.backend
+            .count_documents(query, collection)
+            .await
+    }
+
+    async fn distinct(
+        &self,
+        field: &str,
+        filter: Option<Expr>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .distinct(field, filter, collection)
+            .await
+    }
+
+    async fn aggregate(
+        &self,
+        aggregate: Aggregate,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .aggregate(aggregate, collection)
+            .await
+    }
+
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan> {
+        self.backend
+            .explain(query, collection)
+            .await
+    }
+
+    async fn current_revision_id(&self) -> DocumentStoreResult<Option<String>> {
+        self.backend.current_revision_id().await
+    }
+
+    async fn set_revision_id(&self, revision_id: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .set_revision_id(revision_id)
+            .await
+    }
+
+    async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .create_collection(name)
+            .await
+    }
+
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        self.backend
+            .collection_exists(name)
+            .await
+    }
+
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .ensure_collection(name)
+            .await
+    }
+
+    async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.backend.drop_collection(name).await
+    }
+
+    async fn list_collections(&self) -> DocumentStoreResult<Vec<String>> {
+        self.backend.list_collections().await
+    }
+
+    async fn add_field(
+        &self,
+        collection: &str,
+        field: &str,
+        default: Bson,
+    ) -> DocumentStoreResult<()> {
+        self.backend
+            .add_field(collection, field, default)
+            .await
+    }
+
+    async fn drop_field(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .drop_field(collection, field)
+            .await
+    }
+
+    async fn rename_field(
+        &self,
+        collection: &str,
+        field: &str,
+        new: &str,
+    ) -> DocumentStoreResult<()> {
+        self.backend
+            .rename_field(collection, field, new)
+            .await
+    }
+
+    async fn add_index(
+        &self,
+        collection: &str,
+        field: &str,
+        unique: bool,
+    ) -> DocumentStoreResult<()> {
+        self.backend
+            .add_index(collection, field, unique)
+            .await
+    }
+
+    async fn drop_index(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .drop_index(collection, field)
+            .await
+    }
+
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .expire_documents(collection, field)
+            .await
+    }
+
+    async fn begin_transaction(&self) -> DocumentStoreResult<Transaction> {
+        let transaction = self.backend.begin_transaction().await?;
+
+        Ok(Transaction::new(self.wrap(transaction)))
+    }
+
+    fn inner_backend(&self) -> Option<&dyn DynStoreBackend> {
+        Some(&self.backend)
+    }
+
+    async fn close(&self) -> DocumentStoreResult<()> {
+        self.backend.close().await
+    }
+
+    async fn shutdown(self) -> DocumentStoreResult<()> {
+        self.backend.shutdown().await
+    }
+}
+
+// Writes made in a transaction are recorded in the same transaction
+#[async_trait]
+impl TransactionBackend for AuditedStore<Transaction> {
+    fn backend(&self) -> &dyn DynStoreBackend {
+        self
+    }
+
+    async fn commit(self: Box<Self>) -> DocumentStoreResult<()> {
+        self.backend.commit().await
+    }
+
+    async fn rollback(self: Box<Self>) -> DocumentStoreResult<()> {
+        self.backend.rollback().await
+    }
+}
@@ -14,11 +14,12 @@
 //! - [`StoreBackend`]: The core trait for storage backends
 //! - [`DynStoreBackend`]: A trait for dynamic dispatch over backend implementations
 //! - [`StoreBackendBuilder`]: Factory trait for creating backend instances
+//! - [`StoreLayer`]: Middleware applied to every operation of a backend wrapped in [`Layered`]
 //!
 //! # Examples
 //!
 //! ```ignore
-//! use doclayer::backend::StoreBackend;
+//! use doclayer::backend::{InsertPolicy, StoreBackend};
 //! use bson::{Uuid, Bson, doc};
 //!
 //! // Use a concrete backend implementation
@@ -27,15 +28,113 @@
 //! // Insert a document into a collection
 //! let uuid = Uuid::new();
 //! let doc = Bson::Document(doc! { "name": "Alice", "age": 30 });
-//! backend.insert_documents(vec![(uuid, doc)], "users").await?;
+//! backend.insert_documents(vec![(uuid, doc)], "users", InsertPolicy::ErrorOnConflict).await?;
 //! # Ok::<(), Box<dyn std::error::Error>>(())
 //! ```
 
 use async_trait::async_trait;
-use bson::{Bson, Uuid};
-use std::{any::Any, fmt::Debug};
+use bson::{Bson, RawDocumentBuf, Uuid};
+use futures::stream::{self, BoxStream, StreamExt};
+use std::{any::Any, fmt::Debug, time::Duration};
+
+use crate::{
+    aggregate::Aggregate,
+    encoding::{DEFAULT_ENCODING, Encoding},
+    error::{DocumentStoreError, DocumentStoreResult},
+    query::{Expr, Query, Sort},
+    transaction::Transaction,
+    update::Update,
+};
+
+/// A stream of documents produced by [`StoreBackend::query_stream`].
+pub type DocumentStream = BoxStream<'static, DocumentStoreResult<Bson>>;
+
+/// How [`StoreBackend::update_documents`] treats documents that don't exist.
+///
+/// All backends enforce the policy identically, so code behaves the same against the
+/// in-memory store in tests as against a persistent backend in production.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingDocumentPolicy {
+    /// Fail with [`DocumentStoreError::DocumentNotFound`] (or
+    /// [`DocumentStoreError::CollectionNotFound`]) before writing anything.
+    #[default]
+    Error,
+    /// Update the documents that exist and skip the rest.
+    Skip,
+    /// Insert documents that don't exist, like [`StoreBackend::upsert_documents`].
+    Upsert,
+}
+
+/// How [`StoreBackend::insert_documents`] treats documents whose ID already exists.
+///
+/// All backends enforce the policy identically, so code behaves the same against the
+/// in-memory store in tests as against a persistent backend in production.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InsertPolicy {
+    /// Fail with [`DocumentStoreError::DocumentAlreadyExists`] before writing anything.
+    #[default]
+    ErrorOnConflict,
+    /// Replace the existing documents entirely, like [`StoreBackend::upsert_documents`].
+    Replace,
+    /// Keep the existing documents and insert the rest.
+    Skip,
+}
+
+/// Which version of the document [`StoreBackend::find_one_and_update`] returns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReturnDocument {
+    /// The document as it was before the update.
+    #[default]
+    Before,
+    /// The document as it is after the update.
+    After,
+}
+
+/// Counts reported by backend write operations.
+///
+/// Distinguishes documents that were found from those actually changed, so callers can
+/// detect writes that silently matched nothing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteReport {
+    /// Number of existing documents matched by the write.
+    pub matched: usize,
+    /// Number of matched documents whose content changed.
+    pub modified: usize,
+    /// Number of documents inserted because they didn't exist.
+    pub upserted: usize,
+}
+
+impl WriteReport {
+    /// Returns `true` if the write neither matched nor inserted any document.
+    pub fn is_empty(&self) -> bool {
+        self.matched == 0 && self.upserted == 0
+    }
+}
+
+/// How a backend executes a query, as reported by [`StoreBackend::explain`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryPlan {
+    /// How the backend finds the matching documents.
+    pub strategy: ScanStrategy,
+    /// The backend's own description of the plan, such as MongoDB's explain output.
+    pub details: Bson,
+}
+
+impl QueryPlan {
+    /// Returns `true` if the query reads every document of the collection.
+    pub fn is_full_scan(&self) -> bool {
+        self.strategy == ScanStrategy::FullScan
+    }
+}
 
-use crate::{error::DocumentStoreResult, query::Query};
+/// How a backend finds the documents matching a query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanStrategy {
+    /// Every document of the collection is read and tested against the filter.
+    FullScan,
+    /// Candidate documents are looked up through indexes on the given fields.
+    IndexScan(Vec<String>),
+}
 
 /// Abstract interface for document storage backends.
 ///
@@ -62,49 +161,134 @@ use crate::{error::DocumentStoreResult, query::Query};
 /// variants may be returned by each operation.
 #[async_trait]
 pub trait StoreBackend: Send + Sync + Debug {
-    /// Inserts new documents into a collection, overwriting any existing documents with the same IDs.
+    /// Inserts new documents into a collection.
     ///
     /// This method batches the insertion of multiple documents into a single collection.
-    /// If a document with the same ID already exists, it is replaced entirely.
+    /// Documents whose ID already exists, in the collection or earlier in the batch, are
+    /// handled according to `policy`, which every backend must enforce the same way (see
+    /// [`InsertPolicy`]).
     ///
     /// # Arguments
     ///
     /// * `documents` - A vector of (UUID, BSON document) pairs to insert
     /// * `collection` - The name of the collection to insert into. Created automatically if it doesn't exist.
+    /// * `policy` - How to handle documents whose ID already exists
     ///
     /// # Returns
     ///
-    /// Returns `Ok(())` on success, or a [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
+    /// Returns a [`WriteReport`] counting the existing documents as matched, the replaced ones
+    /// that changed as modified and the new ones as upserted, or a
+    /// [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
     async fn insert_documents(
         &self,
         documents: Vec<(Uuid, Bson)>,
         collection: &str,
-    ) -> DocumentStoreResult<()>;
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport>;
 
     /// Updates existing documents in a collection, replacing them entirely.
     ///
-    /// This method updates multiple documents in a single collection. If a document with the
-    /// specified ID does not exist, this may be treated as an error depending on the backend
-    /// implementation. Check the specific backend documentation for its behavior.
+    /// This method updates multiple documents in a single collection. Documents whose ID does
+    /// not exist are handled according to `policy`, which every backend must enforce the same
+    /// way (see [`MissingDocumentPolicy`]).
     ///
     /// # Arguments
     ///
     /// * `documents` - A vector of (UUID, BSON document) pairs with updated content
     /// * `collection` - The name of the collection containing the documents
+    /// * `policy` - How to handle documents that don't exist
     ///
     /// # Returns
     ///
-    /// Returns `Ok(())` on success, or a [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
+    /// Returns a [`WriteReport`] with the matched, modified and inserted counts, or a [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
     async fn update_documents(
         &self,
         documents: Vec<(Uuid, Bson)>,
         collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport>;
+
+    /// Replaces existing documents in a collection only if they are still at the versions the
+    /// writer read, for optimistic concurrency control.
+    ///
+    /// Each document comes with the version expected in the stored document's
+    /// `version_field`, read with [`stored_version`](crate::document::stored_version). The
+    /// new document carries its own, incremented version. Backends check and write each
+    /// document atomically, so of two writers updating from the same version only one
+    /// succeeds.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of (UUID, BSON document, expected version) triples
+    /// * `version_field` - The stored field holding the version
+    /// * `collection` - The name of the collection containing the documents
+    ///
+    /// # Returns
+    ///
+    /// Returns a [`WriteReport`] with the matched and modified counts, or a
+    /// [`DocumentStoreError::DocumentNotFound`](crate::error::DocumentStoreError::DocumentNotFound)
+    /// if a document doesn't exist and a
+    /// [`DocumentStoreError::VersionConflict`](crate::error::DocumentStoreError::VersionConflict)
+    /// if it is at another version. The in-memory backends write nothing when a document
+    /// fails; other backends may have written the documents before it.
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport>;
+
+    /// Replaces existing documents in a collection only if they are still at the given
+    /// revisions, the `If-Match` semantics of HTTP.
+    ///
+    /// Each document comes with the revision of the stored document it replaces, as computed
+    /// by [`document_revision`](crate::versioned::document_revision) from the stored document
+    /// [`get_documents`](StoreBackend::get_documents) returns. Backends compare the revision
+    /// and write each document atomically, so of two writers holding the same revision only
+    /// one succeeds.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of (UUID, BSON document, expected revision) triples
+    /// * `collection` - The name of the collection containing the documents
+    ///
+    /// # Returns
+    ///
+    /// Returns a [`WriteReport`] with the matched and modified counts, or a
+    /// [`DocumentStoreError::PreconditionFailed`](crate::error::DocumentStoreError::PreconditionFailed)
+    /// if a document doesn't exist or is at another revision. The in-memory backends write
+    /// nothing when a document fails; other backends may have written the documents before it.
+    async fn update_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, Bson, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport>;
+
+    /// Inserts documents into a collection, replacing any existing documents with the same IDs.
+    ///
+    /// Unlike [`insert_documents`](StoreBackend::insert_documents) and
+    /// [`update_documents`](StoreBackend::update_documents), this method never fails because a
+    /// document does or does not already exist, which makes it suitable for idempotent writers.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of (UUID, BSON document) pairs to insert or replace
+    /// * `collection` - The name of the collection to write to. Created automatically if it doesn't exist.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` on success, or a [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
+    async fn upsert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
     ) -> DocumentStoreResult<()>;
 
     /// Deletes documents from a collection by their IDs.
     ///
     /// This method removes the specified documents from the collection. If a document with
-    /// a given ID doesn't exist, it is silently skipped (idempotent operation).
+    /// a given ID doesn't exist, or the collection itself doesn't exist, it is silently skipped
+    /// (idempotent operation). Backends must not abort the batch because of a missing ID.
     ///
     /// # Arguments
     ///
@@ -113,8 +297,117 @@ pub trait StoreBackend: Send + Sync + Debug {
     ///
     /// # Returns
     ///
-    /// Returns `Ok(())` on success, or a [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
-    async fn delete_documents(&self, ids: Vec<Uuid>, collection: &str) -> DocumentStoreResult<()>;
+    /// Returns the number of deleted documents, or a [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
+    async fn delete_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize>;
+
+    /// Deletes documents from a collection only if they are still at the given revisions.
+    ///
+    /// Revisions are compared like in
+    /// [`update_documents_if_match`](StoreBackend::update_documents_if_match). Unlike
+    /// [`delete_documents`](StoreBackend::delete_documents), a missing document fails the
+    /// delete, since it can't be at the expected revision.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of (UUID, expected revision) pairs
+    /// * `collection` - The name of the collection to delete from
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of deleted documents, or a
+    /// [`DocumentStoreError::PreconditionFailed`](crate::error::DocumentStoreError::PreconditionFailed)
+    /// if a document doesn't exist or is at another revision.
+    async fn delete_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize>;
+
+    /// Applies a partial update to every document in a collection that matches a filter.
+    ///
+    /// The update is applied by the backend in place, so callers do not need to fetch,
+    /// modify and re-insert whole documents.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The [`Expr`] selecting the documents to update
+    /// * `update` - The [`Update`] operations to apply
+    /// * `collection` - The name of the collection to update
+    ///
+    /// # Returns
+    ///
+    /// Returns a [`WriteReport`] with the matched and modified counts, or a [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
+    async fn update_by_query(
+        &self,
+        filter: Expr,
+        update: Update,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport>;
+
+    /// Deletes every document in a collection that matches a filter expression.
+    ///
+    /// This removes matching documents in a single backend operation, without first
+    /// querying for their IDs.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The [`Expr`] selecting the documents to delete
+    /// * `collection` - The name of the collection to delete from
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of deleted documents, or a [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize>;
+
+    /// Applies a partial update to the first document matching a filter and returns it, as a
+    /// single atomic operation.
+    ///
+    /// No other write can change the document between it being matched and updated, so of
+    /// several callers racing for the same documents each gets a different one. This makes
+    /// it suitable for claiming work, such as setting the status of one pending job.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The [`Expr`] selecting the documents to consider
+    /// * `sort` - The order in which matching documents are considered; empty for any order
+    /// * `update` - The [`Update`] operations to apply
+    /// * `returned` - Whether to return the document as it was before or after the update
+    /// * `collection` - The name of the collection to update
+    ///
+    /// # Returns
+    ///
+    /// Returns the document, or `None` if no document matches.
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>>;
+
+    /// Deletes the first document matching a filter and returns it, as a single atomic
+    /// operation.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The [`Expr`] selecting the documents to consider
+    /// * `sort` - The order in which matching documents are considered; empty for any order
+    /// * `collection` - The name of the collection to delete from
+    ///
+    /// # Returns
+    ///
+    /// Returns the deleted document, or `None` if no document matches.
+    async fn find_one_and_delete(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>>;
 
     /// Retrieves documents from a collection by their IDs.
     ///
@@ -160,6 +453,161 @@ pub trait StoreBackend: Send + Sync + Debug {
         collection: &str,
     ) -> DocumentStoreResult<Vec<Bson>>;
 
+    /// Queries documents in a collection, yielding them as a stream.
+    ///
+    /// Unlike [`query_documents`](StoreBackend::query_documents), results are produced
+    /// incrementally, so large result sets don't have to fit in memory at once. The
+    /// default implementation collects the results of `query_documents` into a stream;
+    /// backends should override it to stream natively.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, limits, offsets and projection
+    /// * `collection` - The name of the collection to query
+    ///
+    /// # Returns
+    ///
+    /// Returns a [`DocumentStream`] of the matching documents. Errors that occur while
+    /// reading are yielded by the stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`] if the query cannot be started.
+    async fn query_stream(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<DocumentStream> {
+        let documents = self
+            .query_documents(query, collection)
+            .await?;
+
+        Ok(stream::iter(documents.into_iter().map(Ok)).boxed())
+    }
+
+    /// Queries documents in a collection, returning them as raw BSON.
+    ///
+    /// Raw documents defer deserialization to the caller, which can read individual fields
+    /// without decoding the whole document. The default implementation encodes the results
+    /// of [`query_documents`](StoreBackend::query_documents); backends that receive raw BSON
+    /// from their storage should override it to skip the round trip.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, limits, offsets and projection
+    /// * `collection` - The name of the collection to query
+    ///
+    /// # Returns
+    ///
+    /// Returns the matching documents as [`RawDocumentBuf`]s.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`] if the query fails or a stored value is not a document.
+    async fn query_raw_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<RawDocumentBuf>> {
+        self.query_documents(query, collection)
+            .await?
+            .into_iter()
+            .map(|doc| match doc {
+                Bson::Document(doc) => Ok(RawDocumentBuf::try_from(&doc)?),
+                other => Err(DocumentStoreError::InvalidDocument(format!(
+                    "Expected a document, found a stored value of type {:?}",
+                    other.element_type()
+                ))),
+            })
+            .collect()
+    }
+
+    /// Counts the documents in a collection that match a structured query.
+    ///
+    /// The query's filter, offset, and limit are honored so the result equals the number
+    /// of documents [`query_documents`](StoreBackend::query_documents) would return.
+    /// Sorting is ignored. Backends should count natively rather than fetching documents.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] selecting the documents to count
+    /// * `collection` - The name of the collection to count in
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of matching documents, or a [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize>;
+
+    /// Retrieves the unique values of a field across the documents of a collection.
+    ///
+    /// Array values are unwound, so each element counts as a value of its own. Documents
+    /// missing the field are skipped, and numbers of equal value are reported once.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field to collect values of (dot notation is supported)
+    /// * `filter` - An optional [`Expr`] restricting the documents considered
+    /// * `collection` - The name of the collection to scan
+    ///
+    /// # Returns
+    ///
+    /// Returns the unique values in no particular order, or an empty vector if the
+    /// collection doesn't exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`] if the operation fails.
+    async fn distinct(
+        &self,
+        field: &str,
+        filter: Option<Expr>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>>;
+
+    /// Computes a grouped aggregation over the documents of a collection.
+    ///
+    /// The aggregation is evaluated by the backend, so per-group totals don't require
+    /// fetching every document. See the [`aggregate`](crate::aggregate) module for the
+    /// shape of the results.
+    ///
+    /// # Arguments
+    ///
+    /// * `aggregate` - The [`Aggregate`] to compute
+    /// * `collection` - The name of the collection to aggregate
+    ///
+    /// # Returns
+    ///
+    /// Returns one document per group, or an empty vector if the collection doesn't exist
+    /// or no document matches.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`] if the aggregation fails.
+    async fn aggregate(
+        &self,
+        aggregate: Aggregate,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>>;
+
+    /// Describes how the backend would execute a query, without running it.
+    ///
+    /// Useful to check whether a slow query uses an index or scans the whole collection.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] to explain
+    /// * `collection` - The name of the collection to query
+    ///
+    /// # Returns
+    ///
+    /// Returns a [`QueryPlan`] summarizing the chosen strategy along with the backend's own
+    /// description of the plan.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`] if the query cannot be planned.
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan>;
+
     /// Retrieves the current revision/version ID of the store.
     ///
     /// Some backends track the overall revision of the store (useful for change detection,
@@ -186,10 +634,57 @@ pub trait StoreBackend: Send + Sync + Debug {
     /// Returns `Ok(())` on success, or a [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
     async fn set_revision_id(&self, revision_id: &str) -> DocumentStoreResult<()>;
 
+    /// Tries to take the advisory lock that keeps concurrent migration runners apart.
+    ///
+    /// Only one owner holds the lock at a time. It's held until
+    /// [`release_migration_lock`](StoreBackend::release_migration_lock) or until `ttl` has
+    /// passed, so a runner that crashes doesn't block migrations forever. Acquiring a lock the
+    /// owner already holds succeeds and extends it by `ttl`.
+    ///
+    /// The default implementation stores the lock as a document of the
+    /// [`MIGRATION_LOCK_COLLECTION`](crate::migrate::MIGRATION_LOCK_COLLECTION), created with
+    /// [`InsertPolicy::ErrorOnConflict`] and taken over from an expired owner with
+    /// [`update_documents_if_match`](StoreBackend::update_documents_if_match). Backends with a
+    /// native locking primitive may override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - A unique name of the runner taking the lock
+    /// * `ttl` - How long the lock is held unless released
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(true)` if the lock was acquired, `Ok(false)` if another owner holds it,
+    /// or a [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
+    async fn acquire_migration_lock(
+        &self,
+        owner: &str,
+        ttl: Duration,
+    ) -> DocumentStoreResult<bool> {
+        crate::migrate::acquire_lock(self, owner, ttl).await
+    }
+
+    /// Releases the migration lock, if it's held by `owner`.
+    ///
+    /// Releasing a lock that expired or was taken over by another owner does nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - The name the lock was acquired with
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` on success, or a [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
+    async fn release_migration_lock(&self, owner: &str) -> DocumentStoreResult<()> {
+        crate::migrate::release_lock(self, owner).await
+    }
+
     /// Creates a new collection with the specified name.
     ///
-    /// Creates an empty collection. If the collection already exists, this may be treated
-    /// as an error depending on the backend implementation.
+    /// Creates an empty collection. If the collection already exists, backends either succeed
+    /// or return [`DocumentStoreError::CollectionAlreadyExists`](crate::error::DocumentStoreError::CollectionAlreadyExists),
+    /// never a generic backend error. Use [`ensure_collection`](StoreBackend::ensure_collection)
+    /// when several processes may create the same collection concurrently.
     ///
     /// # Arguments
     ///
@@ -200,6 +695,47 @@ pub trait StoreBackend: Send + Sync + Debug {
     /// Returns `Ok(())` on success, or a [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
     async fn create_collection(&self, name: &str) -> DocumentStoreResult<()>;
 
+    /// Checks whether a collection with the specified name exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the collection to look up
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(true)` if the collection exists, `Ok(false)` otherwise, or a
+    /// [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool>;
+
+    /// Creates a collection if it does not already exist.
+    ///
+    /// Unlike [`create_collection`](StoreBackend::create_collection), this never fails because
+    /// the collection already exists, which makes it suitable for idempotent startup code.
+    ///
+    /// The default implementation checks [`collection_exists`](StoreBackend::collection_exists)
+    /// before calling [`create_collection`](StoreBackend::create_collection), and treats a
+    /// [`CollectionAlreadyExists`](crate::error::DocumentStoreError::CollectionAlreadyExists)
+    /// error from a concurrent creator as success. Backends with a native create-if-missing
+    /// operation should override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the collection to ensure
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` on success, or a [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        if self.collection_exists(name).await? {
+            return Ok(());
+        }
+
+        match self.create_collection(name).await {
+            Err(DocumentStoreError::CollectionAlreadyExists(_)) => Ok(()),
+            result => result,
+        }
+    }
+
     /// Drops (deletes) a collection and all its documents.
     ///
     /// This is a destructive operation that permanently removes the collection and all
@@ -299,8 +835,10 @@ pub trait StoreBackend: Send + Sync + Debug {
     ///
     /// # Note
     ///
-    /// If `unique` is true and existing documents violate the uniqueness constraint,
-    /// the backend may return an error.
+    /// Writes that would give two documents the same value of a unique field fail with
+    /// [`DocumentStoreError::UniqueViolation`](crate::error::DocumentStoreError::UniqueViolation),
+    /// as does adding a unique index to a collection whose documents already violate it. Like
+    /// MongoDB, a missing field counts as `null`.
     async fn add_index(
         &self,
         collection: &str,
@@ -320,37 +858,120 @@ pub trait StoreBackend: Send + Sync + Debug {
     /// Returns `Ok(())` on success, or a [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
     async fn drop_index(&self, collection: &str, field: &str) -> DocumentStoreResult<()>;
 
-    /// Cleanly shuts down the backend, releasing all resources.
+    /// Makes a collection delete its documents once the datetime in a field has passed.
     ///
-    /// This method is called when the backend is being dropped. Implementers should
-    /// use this to close connections, flush caches, and perform other cleanup operations.
+    /// Only documents holding a BSON datetime in `field` expire. Deletion isn't immediate:
+    /// MongoDB removes expired documents through a TTL index about once a minute, and the
+    /// other backends remove them whenever their sweeper runs (see the
+    /// [`expiry`](crate::expiry) module). Queries that must never see an expired document
+    /// should also filter on the field.
     ///
-    /// The default implementation is a no-op, but backends with persistent storage or
-    /// external connections should override this.
+    /// Calling this again with the same field is a no-op. Dropping the collection removes
+    /// the expiry.
+    ///
+    /// # Arguments
+    ///
+    /// * `collection` - The name of the collection
+    /// * `field` - The field holding each document's expiry
     ///
     /// # Returns
     ///
     /// Returns `Ok(())` on success, or a [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
-    async fn shutdown(self) -> DocumentStoreResult<()>
-    where
-        Self: Sized,
-    {
-        Ok(())
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()>;
+
+    /// Begins a transaction, whose writes are applied together when it is committed.
+    ///
+    /// Operations run through the returned [`Transaction`] see the transaction's own writes,
+    /// and no other operation sees them until [`commit`](Transaction::commit) succeeds.
+    /// A transaction dropped without being committed is rolled back.
+    ///
+    /// The default implementation fails with [`DocumentStoreError::Unsupported`]; backends
+    /// able to apply several writes atomically should override it.
+    ///
+    /// # Returns
+    ///
+    /// Returns the transaction, or a [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
+    async fn begin_transaction(&self) -> DocumentStoreResult<Transaction> {
+        Err(DocumentStoreError::Unsupported(format!(
+            "{} doesn't support transactions",
+            std::any::type_name::<Self>()
+        )))
     }
-}
 
-#[async_trait]
-impl<B> StoreBackend for &B
-where
-    B: StoreBackend,
-{
-    async fn insert_documents(
-        &self,
-        documents: Vec<(Uuid, Bson)>,
-        collection: &str,
-    ) -> DocumentStoreResult<()> {
+    /// Returns the backend wrapped by this one, if this backend is a wrapper.
+    ///
+    /// Wrapper backends (caches, instrumentation, middleware, ...) should override this to
+    /// expose their inner backend so that callers can reach a concrete backend through a
+    /// stack of wrappers with [`find_backend`](DynStoreBackend::find_backend) and
+    /// [`unwrap_layers`](DynStoreBackend::unwrap_layers).
+    ///
+    /// The default implementation returns `None`, marking this backend as the innermost layer.
+    fn inner_backend(&self) -> Option<&dyn DynStoreBackend> {
+        None
+    }
+
+    /// Returns the encoding documents are stored in.
+    ///
+    /// Typed collections convert documents, and the UUIDs in filters and updates, with this
+    /// [`Encoding`], and backends apply it to the document IDs they use as keys. Backends
+    /// configured with an encoding should override this.
+    ///
+    /// The default implementation returns the encoding of the inner backend, so wrappers
+    /// don't need to forward it, or the default encoding for the innermost layer.
+    fn encoding(&self) -> &Encoding {
+        match self.inner_backend() {
+            Some(inner) => inner.encoding(),
+            None => &DEFAULT_ENCODING,
+        }
+    }
+
+    /// Closes the backend, releasing its connections and flushing pending writes.
+    ///
+    /// Unlike [`shutdown`](StoreBackend::shutdown), this only borrows the backend, so it can be
+    /// called through references, trait objects and wrappers. Wrapper backends should forward
+    /// it to their inner backend. Operations issued after closing may fail, and closing an
+    /// already closed backend should succeed.
+    ///
+    /// The default implementation is a no-op, but backends with persistent storage or
+    /// external connections should override this.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` on success, or a [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
+    async fn close(&self) -> DocumentStoreResult<()> {
+        Ok(())
+    }
+
+    /// Cleanly shuts down the backend, consuming it.
+    ///
+    /// This is the owned finalizer of the backend. The default implementation calls
+    /// [`close`](StoreBackend::close), which is enough for most backends; override it only
+    /// when teardown needs ownership, and release at least what `close` releases.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` on success, or a [`DocumentStoreError`](crate::error::DocumentStoreError) on failure.
+    async fn shutdown(self) -> DocumentStoreResult<()>
+    where
+        Self: Sized,
+    {
+        self.close().await
+    }
+}
+
+#[async_trait]
+impl<B> StoreBackend for &B
+where
+    B: StoreBackend,
+{
+    async fn insert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
         (*self)
-            .insert_documents(documents, collection)
+            .insert_documents(documents, collection, policy)
             .await
     }
 
@@ -358,18 +979,105 @@ where
         &self,
         documents: Vec<(Uuid, Bson)>,
         collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        (*self)
+            .update_documents(documents, collection, policy)
+            .await
+    }
+
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        (*self)
+            .update_documents_if_version(documents, version_field, collection)
+            .await
+    }
+
+    async fn update_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, Bson, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        (*self)
+            .update_documents_if_match(documents, collection)
+            .await
+    }
+
+    async fn upsert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
     ) -> DocumentStoreResult<()> {
         (*self)
-            .update_documents(documents, collection)
+            .upsert_documents(documents, collection)
             .await
     }
 
-    async fn delete_documents(&self, ids: Vec<Uuid>, collection: &str) -> DocumentStoreResult<()> {
+    async fn delete_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
         (*self)
             .delete_documents(ids, collection)
             .await
     }
 
+    async fn delete_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        (*self)
+            .delete_documents_if_match(documents, collection)
+            .await
+    }
+
+    async fn update_by_query(
+        &self,
+        filter: Expr,
+        update: Update,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        (*self)
+            .update_by_query(filter, update, collection)
+            .await
+    }
+
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize> {
+        (*self)
+            .delete_by_query(filter, collection)
+            .await
+    }
+
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        (*self)
+            .find_one_and_update(filter, sort, update, returned, collection)
+            .await
+    }
+
+    async fn find_one_and_delete(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        (*self)
+            .find_one_and_delete(filter, sort, collection)
+            .await
+    }
+
     async fn get_documents(
         &self,
         ids: Vec<Uuid>,
@@ -390,6 +1098,57 @@ where
             .await
     }
 
+    async fn query_stream(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<DocumentStream> {
+        (*self)
+            .query_stream(query, collection)
+            .await
+    }
+
+    async fn query_raw_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<RawDocumentBuf>> {
+        (*self)
+            .query_raw_documents(query, collection)
+            .await
+    }
+
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize> {
+        (*self)
+            .count_documents(query, collection)
+            .await
+    }
+
+    async fn distinct(
+        &self,
+        field: &str,
+        filter: Option<Expr>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        (*self)
+            .distinct(field, filter, collection)
+            .await
+    }
+
+    async fn aggregate(
+        &self,
+        aggregate: Aggregate,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        (*self)
+            .aggregate(aggregate, collection)
+            .await
+    }
+
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan> {
+        (*self).explain(query, collection).await
+    }
+
     async fn current_revision_id(&self) -> DocumentStoreResult<Option<String>> {
         (*self).current_revision_id().await
     }
@@ -400,10 +1159,34 @@ where
             .await
     }
 
+    async fn acquire_migration_lock(
+        &self,
+        owner: &str,
+        ttl: Duration,
+    ) -> DocumentStoreResult<bool> {
+        (*self)
+            .acquire_migration_lock(owner, ttl)
+            .await
+    }
+
+    async fn release_migration_lock(&self, owner: &str) -> DocumentStoreResult<()> {
+        (*self)
+            .release_migration_lock(owner)
+            .await
+    }
+
     async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
         (*self).create_collection(name).await
     }
 
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        (*self).collection_exists(name).await
+    }
+
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        (*self).ensure_collection(name).await
+    }
+
     async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
         (*self).drop_collection(name).await
     }
@@ -456,6 +1239,28 @@ where
             .drop_index(collection, field)
             .await
     }
+
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        (*self)
+            .expire_documents(collection, field)
+            .await
+    }
+
+    async fn begin_transaction(&self) -> DocumentStoreResult<Transaction> {
+        (*self).begin_transaction().await
+    }
+
+    fn inner_backend(&self) -> Option<&dyn DynStoreBackend> {
+        (*self).inner_backend()
+    }
+
+    fn encoding(&self) -> &Encoding {
+        (*self).encoding()
+    }
+
+    async fn close(&self) -> DocumentStoreResult<()> {
+        (*self).close().await
+    }
 }
 
 #[async_trait]
@@ -467,9 +1272,10 @@ where
         &self,
         documents: Vec<(Uuid, Bson)>,
         collection: &str,
-    ) -> DocumentStoreResult<()> {
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
         (**self)
-            .insert_documents(documents, collection)
+            .insert_documents(documents, collection, policy)
             .await
     }
 
@@ -477,18 +1283,105 @@ where
         &self,
         documents: Vec<(Uuid, Bson)>,
         collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        (**self)
+            .update_documents(documents, collection, policy)
+            .await
+    }
+
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        (**self)
+            .update_documents_if_version(documents, version_field, collection)
+            .await
+    }
+
+    async fn update_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, Bson, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        (**self)
+            .update_documents_if_match(documents, collection)
+            .await
+    }
+
+    async fn upsert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
     ) -> DocumentStoreResult<()> {
         (**self)
-            .update_documents(documents, collection)
+            .upsert_documents(documents, collection)
             .await
     }
 
-    async fn delete_documents(&self, ids: Vec<Uuid>, collection: &str) -> DocumentStoreResult<()> {
+    async fn delete_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
         (**self)
             .delete_documents(ids, collection)
             .await
     }
 
+    async fn delete_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        (**self)
+            .delete_documents_if_match(documents, collection)
+            .await
+    }
+
+    async fn update_by_query(
+        &self,
+        filter: Expr,
+        update: Update,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        (**self)
+            .update_by_query(filter, update, collection)
+            .await
+    }
+
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize> {
+        (**self)
+            .delete_by_query(filter, collection)
+            .await
+    }
+
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        (**self)
+            .find_one_and_update(filter, sort, update, returned, collection)
+            .await
+    }
+
+    async fn find_one_and_delete(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        (**self)
+            .find_one_and_delete(filter, sort, collection)
+            .await
+    }
+
     async fn get_documents(
         &self,
         ids: Vec<Uuid>,
@@ -509,6 +1402,59 @@ where
             .await
     }
 
+    async fn query_stream(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<DocumentStream> {
+        (**self)
+            .query_stream(query, collection)
+            .await
+    }
+
+    async fn query_raw_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<RawDocumentBuf>> {
+        (**self)
+            .query_raw_documents(query, collection)
+            .await
+    }
+
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize> {
+        (**self)
+            .count_documents(query, collection)
+            .await
+    }
+
+    async fn distinct(
+        &self,
+        field: &str,
+        filter: Option<Expr>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        (**self)
+            .distinct(field, filter, collection)
+            .await
+    }
+
+    async fn aggregate(
+        &self,
+        aggregate: Aggregate,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        (**self)
+            .aggregate(aggregate, collection)
+            .await
+    }
+
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan> {
+        (**self)
+            .explain(query, collection)
+            .await
+    }
+
     async fn current_revision_id(&self) -> DocumentStoreResult<Option<String>> {
         (**self).current_revision_id().await
     }
@@ -519,10 +1465,34 @@ where
             .await
     }
 
+    async fn acquire_migration_lock(
+        &self,
+        owner: &str,
+        ttl: Duration,
+    ) -> DocumentStoreResult<bool> {
+        (**self)
+            .acquire_migration_lock(owner, ttl)
+            .await
+    }
+
+    async fn release_migration_lock(&self, owner: &str) -> DocumentStoreResult<()> {
+        (**self)
+            .release_migration_lock(owner)
+            .await
+    }
+
     async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
         (**self).create_collection(name).await
     }
 
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        (**self).collection_exists(name).await
+    }
+
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        (**self).ensure_collection(name).await
+    }
+
     async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
         (**self).drop_collection(name).await
     }
@@ -575,21 +1545,91 @@ where
             .drop_index(collection, field)
             .await
     }
-}
 
-#[async_trait]
-pub trait DynStoreBackend: Send + Sync + Debug {
-    async fn insert_documents(
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        (**self)
+            .expire_documents(collection, field)
+            .await
+    }
+
+    async fn begin_transaction(&self) -> DocumentStoreResult<Transaction> {
+        (**self).begin_transaction().await
+    }
+
+    fn inner_backend(&self) -> Option<&dyn DynStoreBackend> {
+        (**self).inner_backend()
+    }
+
+    fn encoding(&self) -> &Encoding {
+        (**self).encoding()
+    }
+
+    async fn close(&self) -> DocumentStoreResult<()> {
+        (**self).close().await
+    }
+}
+
+#[async_trait]
+pub trait DynStoreBackend: Send + Sync + Debug {
+    async fn insert_documents(
         &self,
         documents: Vec<(Uuid, Bson)>,
         collection: &str,
-    ) -> DocumentStoreResult<()>;
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport>;
     async fn update_documents(
         &self,
         documents: Vec<(Uuid, Bson)>,
         collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport>;
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport>;
+    async fn update_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, Bson, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport>;
+    async fn upsert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
     ) -> DocumentStoreResult<()>;
-    async fn delete_documents(&self, ids: Vec<Uuid>, collection: &str) -> DocumentStoreResult<()>;
+    async fn delete_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize>;
+    async fn delete_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize>;
+    async fn update_by_query(
+        &self,
+        filter: Expr,
+        update: Update,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport>;
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize>;
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>>;
+    async fn find_one_and_delete(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>>;
     async fn get_documents(
         &self,
         ids: Vec<Uuid>,
@@ -600,9 +1640,37 @@ pub trait DynStoreBackend: Send + Sync + Debug {
         query: Query,
         collection: &str,
     ) -> DocumentStoreResult<Vec<Bson>>;
+    async fn query_stream(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<DocumentStream>;
+    async fn query_raw_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<RawDocumentBuf>>;
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize>;
+    async fn distinct(
+        &self,
+        field: &str,
+        filter: Option<Expr>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>>;
+    async fn aggregate(
+        &self,
+        aggregate: Aggregate,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>>;
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan>;
     async fn current_revision_id(&self) -> DocumentStoreResult<Option<String>>;
     async fn set_revision_id(&self, revision_id: &str) -> DocumentStoreResult<()>;
+    async fn acquire_migration_lock(&self, owner: &str, ttl: Duration)
+    -> DocumentStoreResult<bool>;
+    async fn release_migration_lock(&self, owner: &str) -> DocumentStoreResult<()>;
     async fn create_collection(&self, name: &str) -> DocumentStoreResult<()>;
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool>;
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()>;
     async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()>;
     async fn list_collections(&self) -> DocumentStoreResult<Vec<String>>;
     async fn add_field(
@@ -625,21 +1693,64 @@ pub trait DynStoreBackend: Send + Sync + Debug {
         unique: bool,
     ) -> DocumentStoreResult<()>;
     async fn drop_index(&self, collection: &str, field: &str) -> DocumentStoreResult<()>;
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()>;
+    async fn begin_transaction(&self) -> DocumentStoreResult<Transaction>;
+    async fn close(&self) -> DocumentStoreResult<()>;
     async fn shutdown_boxed(self: Box<Self>) -> DocumentStoreResult<()>;
 
+    fn inner_backend(&self) -> Option<&dyn DynStoreBackend>;
+    fn encoding(&self) -> &Encoding;
+
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
     fn into_any(self: Box<Self>) -> Box<dyn Any>;
 }
 
+impl dyn DynStoreBackend + '_ {
+    /// Returns this backend followed by every backend it wraps, outermost first.
+    ///
+    /// The chain is built by repeatedly following
+    /// [`inner_backend`](StoreBackend::inner_backend) until a backend that doesn't
+    /// wrap another one is reached.
+    pub fn unwrap_layers(&self) -> Vec<&dyn DynStoreBackend> {
+        let mut layers = vec![self];
+
+        while let Some(inner) = layers
+            .last()
+            .and_then(|layer| layer.inner_backend())
+        {
+            layers.push(inner);
+        }
+
+        layers
+    }
+
+    /// Finds a backend of the concrete type `B` anywhere in the wrapper chain.
+    ///
+    /// Returns `Some(&B)` for the outermost layer of type `B`, or `None` if no layer matches.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let store = DocumentStore::new(CachedStore::new(InMemoryStore::new(), mongo)).into_dyn();
+    /// let mongo: Option<&MongoDbStore> = store.find_backend::<MongoDbStore>();
+    /// ```
+    pub fn find_backend<B: StoreBackend + 'static>(&self) -> Option<&B> {
+        self.unwrap_layers()
+            .into_iter()
+            .find_map(|layer| layer.as_any().downcast_ref::<B>())
+    }
+}
+
 #[async_trait]
 impl<B: StoreBackend + Send + Sync + 'static> DynStoreBackend for B {
     async fn insert_documents(
         &self,
         documents: Vec<(Uuid, Bson)>,
         collection: &str,
-    ) -> DocumentStoreResult<()> {
-        self.insert_documents(documents, collection)
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.insert_documents(documents, collection, policy)
             .await
     }
 
@@ -647,16 +1758,95 @@ impl<B: StoreBackend + Send + Sync + 'static> DynStoreBackend for B {
         &self,
         documents: Vec<(Uuid, Bson)>,
         collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.update_documents(documents, collection, policy)
+            .await
+    }
+
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.update_documents_if_version(documents, version_field, collection)
+            .await
+    }
+
+    async fn update_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, Bson, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.update_documents_if_match(documents, collection)
+            .await
+    }
+
+    async fn upsert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
     ) -> DocumentStoreResult<()> {
-        self.update_documents(documents, collection)
+        self.upsert_documents(documents, collection)
             .await
     }
 
-    async fn delete_documents(&self, ids: Vec<Uuid>, collection: &str) -> DocumentStoreResult<()> {
+    async fn delete_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
         self.delete_documents(ids, collection)
             .await
     }
 
+    async fn delete_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        self.delete_documents_if_match(documents, collection)
+            .await
+    }
+
+    async fn update_by_query(
+        &self,
+        filter: Expr,
+        update: Update,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.update_by_query(filter, update, collection)
+            .await
+    }
+
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize> {
+        self.delete_by_query(filter, collection)
+            .await
+    }
+
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        self.find_one_and_update(filter, sort, update, returned, collection)
+            .await
+    }
+
+    async fn find_one_and_delete(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        self.find_one_and_delete(filter, sort, collection)
+            .await
+    }
+
     async fn get_documents(
         &self,
         ids: Vec<Uuid>,
@@ -675,6 +1865,52 @@ impl<B: StoreBackend + Send + Sync + 'static> DynStoreBackend for B {
             .await
     }
 
+    async fn query_stream(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<DocumentStream> {
+        self.query_stream(query, collection)
+            .await
+    }
+
+    async fn query_raw_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<RawDocumentBuf>> {
+        self.query_raw_documents(query, collection)
+            .await
+    }
+
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize> {
+        self.count_documents(query, collection)
+            .await
+    }
+
+    async fn distinct(
+        &self,
+        field: &str,
+        filter: Option<Expr>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.distinct(field, filter, collection)
+            .await
+    }
+
+    async fn aggregate(
+        &self,
+        aggregate: Aggregate,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.aggregate(aggregate, collection)
+            .await
+    }
+
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan> {
+        self.explain(query, collection).await
+    }
+
     async fn current_revision_id(&self) -> DocumentStoreResult<Option<String>> {
         self.current_revision_id().await
     }
@@ -683,10 +1919,31 @@ impl<B: StoreBackend + Send + Sync + 'static> DynStoreBackend for B {
         self.set_revision_id(revision_id).await
     }
 
+    async fn acquire_migration_lock(
+        &self,
+        owner: &str,
+        ttl: Duration,
+    ) -> DocumentStoreResult<bool> {
+        self.acquire_migration_lock(owner, ttl)
+            .await
+    }
+
+    async fn release_migration_lock(&self, owner: &str) -> DocumentStoreResult<()> {
+        self.release_migration_lock(owner).await
+    }
+
     async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
         self.create_collection(name).await
     }
 
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        self.collection_exists(name).await
+    }
+
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.ensure_collection(name).await
+    }
+
     async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
         self.drop_collection(name).await
     }
@@ -733,10 +1990,31 @@ impl<B: StoreBackend + Send + Sync + 'static> DynStoreBackend for B {
         self.drop_index(collection, field).await
     }
 
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.expire_documents(collection, field)
+            .await
+    }
+
+    async fn begin_transaction(&self) -> DocumentStoreResult<Transaction> {
+        StoreBackend::begin_transaction(self).await
+    }
+
+    async fn close(&self) -> DocumentStoreResult<()> {
+        StoreBackend::close(self).await
+    }
+
     async fn shutdown_boxed(self: Box<Self>) -> DocumentStoreResult<()> {
         self.shutdown().await
     }
 
+    fn inner_backend(&self) -> Option<&dyn DynStoreBackend> {
+        StoreBackend::inner_backend(self)
+    }
+
+    fn encoding(&self) -> &Encoding {
+        StoreBackend::encoding(self)
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -756,3 +2034,1168 @@ pub trait StoreBackendBuilder {
 
     async fn build(self) -> DocumentStoreResult<Self::Backend>;
 }
+
+/// A backend operation with its arguments, as seen by a [`StoreLayer`].
+///
+/// Each variant corresponds to the [`StoreBackend`] method of the same name.
+#[derive(Debug, Clone)]
+pub enum Operation {
+    InsertDocuments {
+        documents: Vec<(Uuid, Bson)>,
+        collection: String,
+        policy: InsertPolicy,
+    },
+    UpdateDocuments {
+        documents: Vec<(Uuid, Bson)>,
+        collection: String,
+        policy: MissingDocumentPolicy,
+    },
+    UpdateDocumentsIfVersion {
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: String,
+        collection: String,
+    },
+    UpdateDocumentsIfMatch {
+        documents: Vec<(Uuid, Bson, String)>,
+        collection: String,
+    },
+    UpsertDocuments {
+        documents: Vec<(Uuid, Bson)>,
+        collection: String,
+    },
+    DeleteDocuments {
+        ids: Vec<Uuid>,
+        collection: String,
+    },
+    DeleteDocumentsIfMatch {
+        documents: Vec<(Uuid, String)>,
+        collection: String,
+    },
+    UpdateByQuery {
+        filter: Expr,
+        update: Update,
+        collection: String,
+    },
+    DeleteByQuery {
+        filter: Expr,
+        collection: String,
+    },
+    FindOneAndUpdate {
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: String,
+    },
+    FindOneAndDelete {
+        filter: Expr,
+        sort: Vec<Sort>,
+        collection: String,
+    },
+    GetDocuments {
+        ids: Vec<Uuid>,
+        collection: String,
+    },
+    QueryDocuments {
+        query: Query,
+        collection: String,
+    },
+    QueryStream {
+        query: Query,
+        collection: String,
+    },
+    QueryRawDocuments {
+        query: Query,
+        collection: String,
+    },
+    CountDocuments {
+        query: Query,
+        collection: String,
+    },
+    Distinct {
+        field: String,
+        filter: Option<Expr>,
+        collection: String,
+    },
+    Aggregate {
+        aggregate: Aggregate,
+        collection: String,
+    },
+    Explain {
+        query: Query,
+        collection: String,
+    },
+    CurrentRevisionId,
+    SetRevisionId {
+        revision_id: String,
+    },
+    CreateCollection {
+        name: String,
+    },
+    CollectionExists {
+        name: String,
+    },
+    EnsureCollection {
+        name: String,
+    },
+    DropCollection {
+        name: String,
+    },
+    ListCollections,
+    AddField {
+        collection: String,
+        field: String,
+        default: Bson,
+    },
+    DropField {
+        collection: String,
+        field: String,
+    },
+    RenameField {
+        collection: String,
+        field: String,
+        new: String,
+    },
+    AddIndex {
+        collection: String,
+        field: String,
+        unique: bool,
+    },
+    DropIndex {
+        collection: String,
+        field: String,
+    },
+    ExpireDocuments {
+        collection: String,
+        field: String,
+    },
+}
+
+impl Operation {
+    /// Returns the name of the [`StoreBackend`] method, such as `insert_documents`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::InsertDocuments { .. } => "insert_documents",
+            Operation::UpdateDocuments { .. } => "update_documents",
+            Operation::UpdateDocumentsIfVersion { .. } => "update_documents_if_version",
+            Operation::UpdateDocumentsIfMatch { .. } => "update_documents_if_match",
+            Operation::UpsertDocuments { .. } => "upsert_documents",
+            Operation::DeleteDocuments { .. } => "delete_documents",
+            Operation::DeleteDocumentsIfMatch { .. } => "delete_documents_if_match",
+            Operation::UpdateByQuery { .. } => "update_by_query",
+            Operation::DeleteByQuery { .. } => "delete_by_query",
+            Operation::FindOneAndUpdate { .. } => "find_one_and_update",
+            Operation::FindOneAndDelete { .. } => "find_one_and_delete",
+            Operation::GetDocuments { .. } => "get_documents",
+            Operation::QueryDocuments { .. } => "query_documents",
+            Operation::QueryStream { .. } => "query_stream",
+            Operation::QueryRawDocuments { .. } => "query_raw_documents",
+            Operation::CountDocuments { .. } => "count_documents",
+            Operation::Distinct { .. } => "distinct",
+            Operation::Aggregate { .. } => "aggregate",
+            Operation::Explain { .. } => "explain",
+            Operation::CurrentRevisionId => "current_revision_id",
+            Operation::SetRevisionId { .. } => "set_revision_id",
+            Operation::CreateCollection { .. } => "create_collection",
+            Operation::CollectionExists { .. } => "collection_exists",
+            Operation::EnsureCollection { .. } => "ensure_collection",
+            Operation::DropCollection { .. } => "drop_collection",
+            Operation::ListCollections => "list_collections",
+            Operation::AddField { .. } => "add_field",
+            Operation::DropField { .. } => "drop_field",
+            Operation::RenameField { .. } => "rename_field",
+            Operation::AddIndex { .. } => "add_index",
+            Operation::DropIndex { .. } => "drop_index",
+            Operation::ExpireDocuments { .. } => "expire_documents",
+        }
+    }
+
+    /// Returns the collection the operation targets, if it targets one.
+    pub fn collection(&self) -> Option<&str> {
+        match self {
+            Operation::InsertDocuments { collection, .. }
+            | Operation::UpdateDocuments { collection, .. }
+            | Operation::UpdateDocumentsIfVersion { collection, .. }
+            | Operation::UpdateDocumentsIfMatch { collection, .. }
+            | Operation::UpsertDocuments { collection, .. }
+            | Operation::DeleteDocuments { collection, .. }
+            | Operation::DeleteDocumentsIfMatch { collection, .. }
+            | Operation::UpdateByQuery { collection, .. }
+            | Operation::DeleteByQuery { collection, .. }
+            | Operation::FindOneAndUpdate { collection, .. }
+            | Operation::FindOneAndDelete { collection, .. }
+            | Operation::GetDocuments { collection, .. }
+            | Operation::QueryDocuments { collection, .. }
+            | Operation::QueryStream { collection, .. }
+            | Operation::QueryRawDocuments { collection, .. }
+            | Operation::CountDocuments { collection, .. }
+            | Operation::Distinct { collection, .. }
+            | Operation::Aggregate { collection, .. }
+            | Operation::Explain { collection, .. }
+            | Operation::AddField { collection, .. }
+            | Operation::DropField { collection, .. }
+            | Operation::RenameField { collection, .. }
+            | Operation::AddIndex { collection, .. }
+            | Operation::DropIndex { collection, .. }
+            | Operation::ExpireDocuments { collection, .. }
+            | Operation::CreateCollection { name: collection }
+            | Operation::CollectionExists { name: collection }
+            | Operation::EnsureCollection { name: collection }
+            | Operation::DropCollection { name: collection } => Some(collection.as_str()),
+            Operation::CurrentRevisionId
+            | Operation::SetRevisionId { .. }
+            | Operation::ListCollections => None,
+        }
+    }
+
+    /// Returns the collection the operation targets mutably, so a layer can redirect it.
+    pub fn collection_mut(&mut self) -> Option<&mut String> {
+        match self {
+            Operation::InsertDocuments { collection, .. }
+            | Operation::UpdateDocuments { collection, .. }
+            | Operation::UpdateDocumentsIfVersion { collection, .. }
+            | Operation::UpdateDocumentsIfMatch { collection, .. }
+            | Operation::UpsertDocuments { collection, .. }
+            | Operation::DeleteDocuments { collection, .. }
+            | Operation::DeleteDocumentsIfMatch { collection, .. }
+            | Operation::UpdateByQuery { collection, .. }
+            | Operation::DeleteByQuery { collection, .. }
+            | Operation::FindOneAndUpdate { collection, .. }
+            | Operation::FindOneAndDelete { collection, .. }
+            | Operation::GetDocuments { collection, .. }
+            | Operation::QueryDocuments { collection, .. }
+            | Operation::QueryStream { collection, .. }
+            | Operation::QueryRawDocuments { collection, .. }
+            | Operation::CountDocuments { collection, .. }
+            | Operation::Distinct { collection, .. }
+            | Operation::Aggregate { collection, .. }
+            | Operation::Explain { collection, .. }
+            | Operation::AddField { collection, .. }
+            | Operation::DropField { collection, .. }
+            | Operation::RenameField { collection, .. }
+            | Operation::AddIndex { collection, .. }
+            | Operation::DropIndex { collection, .. }
+            | Operation::ExpireDocuments { collection, .. }
+            | Operation::CreateCollection { name: collection }
+            | Operation::CollectionExists { name: collection }
+            | Operation::EnsureCollection { name: collection }
+            | Operation::DropCollection { name: collection } => Some(collection),
+            Operation::CurrentRevisionId
+            | Operation::SetRevisionId { .. }
+            | Operation::ListCollections => None,
+        }
+    }
+
+    /// Returns `true` if the operation may change documents, collections or the revision.
+    pub fn is_write(&self) -> bool {
+        !matches!(
+            self,
+            Operation::GetDocuments { .. }
+                | Operation::QueryDocuments { .. }
+                | Operation::QueryStream { .. }
+                | Operation::QueryRawDocuments { .. }
+                | Operation::CountDocuments { .. }
+                | Operation::Distinct { .. }
+                | Operation::Aggregate { .. }
+                | Operation::Explain { .. }
+                | Operation::CurrentRevisionId
+                | Operation::CollectionExists { .. }
+                | Operation::ListCollections
+        )
+    }
+
+    /// Runs the operation on a backend.
+    pub async fn apply(self, backend: &dyn DynStoreBackend) -> DocumentStoreResult<Outcome> {
+        Ok(match self {
+            Operation::InsertDocuments { documents, collection, policy } => Outcome::Report(
+                backend
+                    .insert_documents(documents, &collection, policy)
+                    .await?,
+            ),
+            Operation::UpdateDocuments { documents, collection, policy } => Outcome::Report(
+                backend
+                    .update_documents(documents, &collection, policy)
+                    .await?,
+            ),
+            Operation::UpdateDocumentsIfVersion { documents, version_field, collection } => {
+                Outcome::Report(
+                    backend
+                        .update_documents_if_version(documents, &version_field, &collection)
+                        .await?,
+                )
+            }
+            Operation::UpdateDocumentsIfMatch { documents, collection } => Outcome::Report(
+                backend
+                    .update_documents_if_match(documents, &collection)
+                    .await?,
+            ),
+            Operation::UpsertDocuments { documents, collection } => {
+                backend
+                    .upsert_documents(documents, &collection)
+                    .await?;
+                Outcome::Done
+            }
+            Operation::DeleteDocuments { ids, collection } => Outcome::Count(
+                backend
+                    .delete_documents(ids, &collection)
+                    .await?,
+            ),
+            Operation::DeleteDocumentsIfMatch { documents, collection } => Outcome::Count(
+                backend
+                    .delete_documents_if_match(documents, &collection)
+                    .await?,
+            ),
+            Operation::UpdateByQuery { filter, update, collection } => Outcome::Report(
+                backend
+                    .update_by_query(filter, update, &collection)
+                    .await?,
+            ),
+            Operation::DeleteByQuery { filter, collection } => Outcome::Count(
+                backend
+                    .delete_by_query(filter, &collection)
+                    .await?,
+            ),
+            Operation::FindOneAndUpdate {
+                filter,
+                sort,
+                update,
+                returned,
+                collection,
+            } => Outcome::Document(
+                backend
+                    .find_one_and_update(filter, sort, update, returned, &collection)
+                    .await?,
+            ),
+            Operation::FindOneAndDelete { filter, sort, collection } => Outcome::Document(
+                backend
+                    .find_one_and_delete(filter, sort, &collection)
+                    .await?,
+            ),
+            Operation::GetDocuments { ids, collection } => Outcome::Documents(
+                backend
+                    .get_documents(ids, &collection)
+                    .await?,
+            ),
+            Operation::QueryDocuments { query, collection } => Outcome::Documents(
+                backend
+                    .query_documents(query, &collection)
+                    .await?,
+            ),
+            Operation::QueryStream { query, collection } => Outcome::Stream(
+                backend
+                    .query_stream(query, &collection)
+                    .await?,
+            ),
+            Operation::QueryRawDocuments { query, collection } => Outcome::RawDocuments(
+                backend
+                    .query_raw_documents(query, &collection)
+                    .await?,
+            ),
+            Operation::CountDocuments { query, collection } => Outcome::Count(
+                backend
+                    .count_documents(query, &collection)
+                    .await?,
+            ),
+            Operation::Distinct { field, filter, collection } => Outcome::Documents(
+                backend
+                    .distinct(&field, filter, &collection)
+                    .await?,
+            ),
+            Operation::Aggregate { aggregate, collection } => Outcome::Documents(
+                backend
+                    .aggregate(aggregate, &collection)
+                    .await?,
+            ),
+            Operation::Explain { query, collection } => Outcome::Plan(
+                backend
+                    .explain(query, &collection)
+                    .await?,
+            ),
+            Operation::CurrentRevisionId => Outcome::Revision(backend.current_revision_id().await?),
+            Operation::SetRevisionId { revision_id } => {
+                backend
+                    .set_revision_id(&revision_id)
+                    .await?;
+                Outcome::Done
+            }
+            Operation::CreateCollection { name } => {
+                backend.create_collection(&name).await?;
+                Outcome::Done
+            }
+            Operation::CollectionExists { name } => {
+                Outcome::Exists(backend.collection_exists(&name).await?)
+            }
+            Operation::EnsureCollection { name } => {
+                backend.ensure_collection(&name).await?;
+                Outcome::Done
+            }
+            Operation::DropCollection { name } => {
+                backend.drop_collection(&name).await?;
+                Outcome::Done
+            }
+            Operation::ListCollections => Outcome::Collections(backend.list_collections().await?),
+            Operation::AddField { collection, field, default } => {
+                backend
+                    .add_field(&collection, &field, default)
+                    .await?;
+                Outcome::Done
+            }
+            Operation::DropField { collection, field } => {
+                backend
+                    .drop_field(&collection, &field)
+                    .await?;
+                Outcome::Done
+            }
+            Operation::RenameField { collection, field, new } => {
+                backend
+                    .rename_field(&collection, &field, &new)
+                    .await?;
+                Outcome::Done
+            }
+            Operation::AddIndex { collection, field, unique } => {
+                backend
+                    .add_index(&collection, &field, unique)
+                    .await?;
+                Outcome::Done
+            }
+            Operation::DropIndex { collection, field } => {
+                backend
+                    .drop_index(&collection, &field)
+                    .await?;
+                Outcome::Done
+            }
+            Operation::ExpireDocuments { collection, field } => {
+                backend
+                    .expire_documents(&collection, &field)
+                    .await?;
+                Outcome::Done
+            }
+        })
+    }
+}
+
+/// The result of an [`Operation`].
+///
+/// A layer answering an operation itself must return the variant the operation's method
+/// returns, or the call fails with [`DocumentStoreError::Backend`].
+pub enum Outcome {
+    /// The operation returns nothing.
+    Done,
+    /// The counts of `insert_documents`, `update_documents` and `update_by_query`.
+    Report(WriteReport),
+    /// The number returned by `delete_documents`, `delete_by_query` and `count_documents`.
+    Count(usize),
+    /// The documents or values returned by `get_documents`, `query_documents`, `distinct`
+    /// and `aggregate`.
+    Documents(Vec<Bson>),
+    /// The document returned by `find_one_and_update` and `find_one_and_delete`.
+    Document(Option<Bson>),
+    /// The stream returned by `query_stream`.
+    Stream(DocumentStream),
+    /// The documents returned by `query_raw_documents`.
+    RawDocuments(Vec<RawDocumentBuf>),
+    /// The plan returned by `explain`.
+    Plan(QueryPlan),
+    /// The revision returned by `current_revision_id`.
+    Revision(Option<String>),
+    /// The answer of `collection_exists`.
+    Exists(bool),
+    /// The names returned by `list_collections`.
+    Collections(Vec<String>),
+}
+
+impl Outcome {
+    fn kind(&self) -> &'static str {
+        match self {
+            Outcome::Done => "Done",
+            Outcome::Report(_) => "Report",
+            Outcome::Count(_) => "Count",
+            Outcome::Documents(_) => "Documents",
+            Outcome::Document(_) => "Document",
+            Outcome::Stream(_) => "Stream",
+            Outcome::RawDocuments(_) => "RawDocuments",
+            Outcome::Plan(_) => "Plan",
+            Outcome::Revision(_) => "Revision",
+            Outcome::Exists(_) => "Exists",
+            Outcome::Collections(_) => "Collections",
+        }
+    }
+
+    /// The error returned when a layer answers an operation with the wrong variant.
+    fn mismatch(self, operation: &str) -> DocumentStoreError {
+        DocumentStoreError::Backend(format!(
+            "A store layer answered {} with an outcome of kind {}",
+            operation,
+            self.kind()
+        ))
+    }
+}
+
+impl Debug for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Done => write!(f, "Done"),
+            Outcome::Report(report) => f
+                .debug_tuple("Report")
+                .field(report)
+                .finish(),
+            Outcome::Count(count) => f
+                .debug_tuple("Count")
+                .field(count)
+                .finish(),
+            Outcome::Documents(documents) => f
+                .debug_tuple("Documents")
+                .field(documents)
+                .finish(),
+            Outcome::Document(document) => f
+                .debug_tuple("Document")
+                .field(document)
+                .finish(),
+            Outcome::Stream(_) => f
+                .debug_tuple("Stream")
+                .finish_non_exhaustive(),
+            Outcome::RawDocuments(documents) => f
+                .debug_tuple("RawDocuments")
+                .field(documents)
+                .finish(),
+            Outcome::Plan(plan) => f
+                .debug_tuple("Plan")
+                .field(plan)
+                .finish(),
+            Outcome::Revision(revision) => f
+                .debug_tuple("Revision")
+                .field(revision)
+                .finish(),
+            Outcome::Exists(exists) => f
+                .debug_tuple("Exists")
+                .field(exists)
+                .finish(),
+            Outcome::Collections(names) => f
+                .debug_tuple("Collections")
+                .field(names)
+                .finish(),
+        }
+    }
+}
+
+/// The rest of the stack below a [`StoreLayer`], down to the wrapped backend.
+#[derive(Debug, Clone, Copy)]
+pub struct Next<'a> {
+    backend: &'a dyn DynStoreBackend,
+}
+
+impl<'a> Next<'a> {
+    /// Passes an operation on to the rest of the stack.
+    ///
+    /// May be called more than once, for instance to retry a failed operation, or not at all
+    /// to answer the operation without reaching the backend.
+    pub async fn run(&self, operation: Operation) -> DocumentStoreResult<Outcome> {
+        operation.apply(self.backend).await
+    }
+
+    /// Returns the backend the stack wraps.
+    pub fn backend(&self) -> &'a dyn DynStoreBackend {
+        self.backend
+    }
+}
+
+/// Middleware applied to every operation of a backend by [`Layered`].
+///
+/// A layer receives each operation before the wrapped backend does, and decides whether and
+/// how to pass it on through [`Next::run`]. One implementation covers every backend method,
+/// so concerns such as logging, metrics or retries don't need a wrapper backend of their own.
+///
+/// # Example
+///
+/// ```ignore
+/// use doclayer::backend::{Layered, Next, Operation, Outcome, StoreLayer};
+///
+/// #[derive(Debug)]
+/// struct Timing;
+///
+/// #[async_trait]
+/// impl StoreLayer for Timing {
+///     async fn call(&self, operation: Operation, next: Next<'_>) -> DocumentStoreResult<Outcome> {
+///         let name = operation.name();
+///         let started = Instant::now();
+///         let outcome = next.run(operation).await;
+///         println!("{} took {:?}", name, started.elapsed());
+///         outcome
+///     }
+/// }
+///
+/// let store = DocumentStore::new(Layered::new(Timing, backend));
+/// ```
+#[async_trait]
+pub trait StoreLayer: Send + Sync + Debug {
+    /// Handles an operation, usually by passing it on to `next`.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The operation with its arguments
+    /// * `next` - The rest of the stack, down to the wrapped backend
+    ///
+    /// # Returns
+    ///
+    /// The [`Outcome`] variant matching the operation.
+    async fn call(&self, operation: Operation, next: Next<'_>) -> DocumentStoreResult<Outcome>;
+}
+
+/// A backend whose operations all pass through a [`StoreLayer`] first.
+///
+/// Layers stack by wrapping a `Layered` backend in another one, outermost last:
+///
+/// ```ignore
+/// let backend = Layered::new(Retry::new(3), mongo).layer(Timing);
+/// ```
+#[derive(Debug)]
+pub struct Layered<L, B> {
+    layer: L,
+    inner: B,
+}
+
+impl<L: StoreLayer, B: StoreBackend + 'static> Layered<L, B> {
+    /// Wraps `inner` so every operation passes through `layer`.
+    pub fn new(layer: L, inner: B) -> Self {
+        Self { layer, inner }
+    }
+
+    /// Wraps this backend in another layer, which sees operations before this one.
+    pub fn layer<M: StoreLayer>(self, outer: M) -> Layered<M, Self>
+    where
+        L: 'static,
+    {
+        Layered::new(outer, self)
+    }
+
+    /// Returns the layer.
+    pub fn get_layer(&self) -> &L {
+        &self.layer
+    }
+
+    /// Returns the wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Unwraps the layer and the wrapped backend.
+    pub fn into_inner(self) -> (L, B) {
+        (self.layer, self.inner)
+    }
+
+    async fn call(&self, operation: Operation) -> DocumentStoreResult<Outcome> {
+        self.layer
+            .call(operation, Next { backend: &self.inner })
+            .await
+    }
+}
+
+#[async_trait]
+impl<L: StoreLayer, B: StoreBackend + 'static> StoreBackend for Layered<L, B> {
+    async fn insert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        match self
+            .call(Operation::InsertDocuments {
+                documents,
+                collection: collection.to_string(),
+                policy,
+            })
+            .await?
+        {
+            Outcome::Report(report) => Ok(report),
+            outcome => Err(outcome.mismatch("insert_documents")),
+        }
+    }
+
+    async fn update_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        match self
+            .call(Operation::UpdateDocuments {
+                documents,
+                collection: collection.to_string(),
+                policy,
+            })
+            .await?
+        {
+            Outcome::Report(report) => Ok(report),
+            outcome => Err(outcome.mismatch("update_documents")),
+        }
+    }
+
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        match self
+            .call(Operation::UpdateDocumentsIfVersion {
+                documents,
+                version_field: version_field.to_string(),
+                collection: collection.to_string(),
+            })
+            .await?
+        {
+            Outcome::Report(report) => Ok(report),
+            outcome => Err(outcome.mismatch("update_documents_if_version")),
+        }
+    }
+
+    async fn update_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, Bson, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        match self
+            .call(Operation::UpdateDocumentsIfMatch {
+                documents,
+                collection: collection.to_string(),
+            })
+            .await?
+        {
+            Outcome::Report(report) => Ok(report),
+            outcome => Err(outcome.mismatch("update_documents_if_match")),
+        }
+    }
+
+    async fn upsert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+    ) -> DocumentStoreResult<()> {
+        match self
+            .call(Operation::UpsertDocuments {
+                documents,
+                collection: collection.to_string(),
+            })
+            .await?
+        {
+            Outcome::Done => Ok(()),
+            outcome => Err(outcome.mismatch("upsert_documents")),
+        }
+    }
+
+    async fn delete_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        match self
+            .call(Operation::DeleteDocuments { ids, collection: collection.to_string() })
+            .await?
+        {
+            Outcome::Count(deleted) => Ok(deleted),
+            outcome => Err(outcome.mismatch("delete_documents")),
+        }
+    }
+
+    async fn delete_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        match self
+            .call(Operation::DeleteDocumentsIfMatch {
+                documents,
+                collection: collection.to_string(),
+            })
+            .await?
+        {
+            Outcome::Count(deleted) => Ok(deleted),
+            outcome => Err(outcome.mismatch("delete_documents_if_match")),
+        }
+    }
+
+    async fn update_by_query(
+        &self,
+        filter: Expr,
+        update: Update,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        match self
+            .call(Operation::UpdateByQuery {
+                filter,
+                update,
+                collection: collection.to_string(),
+            })
+            .await?
+        {
+            Outcome::Report(report) => Ok(report),
+            outcome => Err(outcome.mismatch("update_by_query")),
+        }
+    }
+
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize> {
+        match self
+            .call(Operation::DeleteByQuery {
+                filter,
+                collection: collection.to_string(),
+            })
+            .await?
+        {
+            Outcome::Count(deleted) => Ok(deleted),
+            outcome => Err(outcome.mismatch("delete_by_query")),
+        }
+    }
+
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        match self
+            .call(Operation::FindOneAndUpdate {
+                filter,
+                sort,
+                update,
+                returned,
+                collection: collection.to_string(),
+            })
+            .await?
+        {
+            Outcome::Document(document) => Ok(document),
+            outcome => Err(outcome.mismatch("find_one_and_update")),
+        }
+    }
+
+    async fn find_one_and_delete(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        match self
+            .call(Operation::FindOneAndDelete {
+                filter,
+                sort,
+                collection: collection.to_string(),
+            })
+            .await?
+        {
+            Outcome::Document(document) => Ok(document),
+            outcome => Err(outcome.mismatch("find_one_and_delete")),
+        }
+    }
+
+    async fn get_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        match self
+            .call(Operation::GetDocuments { ids, collection: collection.to_string() })
+            .await?
+        {
+            Outcome::Documents(documents) => Ok(documents),
+            outcome => Err(outcome.mismatch("get_documents")),
+        }
+    }
+
+    async fn query_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        match self
+            .call(Operation::QueryDocuments {
+                query,
+                collection: collection.to_string(),
+            })
+            .await?
+        {
+            Outcome::Documents(documents) => Ok(documents),
+            outcome => Err(outcome.mismatch("query_documents")),
+        }
+    }
+
+    async fn query_stream(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<DocumentStream> {
+        match self
+            .call(Operation::QueryStream {
+                query,
+                collection: collection.to_string(),
+            })
+            .await?
+        {
+            Outcome::Stream(stream) => Ok(stream),
+            outcome => Err(outcome.mismatch("query_stream")),
+        }
+    }
+
+    async fn query_raw_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<RawDocumentBuf>> {
+        match self
+            .call(Operation::QueryRawDocuments {
+                query,
+                collection: collection.to_string(),
+            })
+            .await?
+        {
+            Outcome::RawDocuments(documents) => Ok(documents),
+            outcome => Err(outcome.mismatch("query_raw_documents")),
+        }
+    }
+
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize> {
+        match self
+            .call(Operation::CountDocuments {
+                query,
+                collection: collection.to_string(),
+            })
+            .await?
+        {
+            Outcome::Count(count) => Ok(count),
+            outcome => Err(outcome.mismatch("count_documents")),
+        }
+    }
+
+    async fn distinct(
+        &self,
+        field: &str,
+        filter: Option<Expr>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        match self
+            .call(Operation::Distinct {
+                field: field.to_string(),
+                filter,
+                collection: collection.to_string(),
+            })
+            .await?
+        {
+            Outcome::Documents(values) => Ok(values),
+            outcome => Err(outcome.mismatch("distinct")),
+        }
+    }
+
+    async fn aggregate(
+        &self,
+        aggregate: Aggregate,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        match self
+            .call(Operation::Aggregate {
+                aggregate,
+                collection: collection.to_string(),
+            })
+            .await?
+        {
+            Outcome::Documents(documents) => Ok(documents),
+            outcome => Err(outcome.mismatch("aggregate")),
+        }
+    }
+
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan> {
+        match self
+            .call(Operation::Explain {
+                query,
+                collection: collection.to_string(),
+            })
+            .await?
+        {
+            Outcome::Plan(plan) => Ok(plan),
+            outcome => Err(outcome.mismatch("explain")),
+        }
+    }
+
+    async fn current_revision_id(&self) -> DocumentStoreResult<Option<String>> {
+        match self
+            .call(Operation::CurrentRevisionId)
+            .await?
+        {
+            Outcome::Revision(revision_id) => Ok(revision_id),
+            outcome => Err(outcome.mismatch("current_revision_id")),
+        }
+    }
+
+    async fn set_revision_id(&self, revision_id: &str) -> DocumentStoreResult<()> {
+        match self
+            .call(Operation::SetRevisionId { revision_id: revision_id.to_string() })
+            .await?
+        {
+            Outcome::Done => Ok(()),
+            outcome => Err(outcome.mismatch("set_revision_id")),
+        }
+    }
+
+    async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        match self
+            .call(Operation::CreateCollection { name: name.to_string() })
+            .await?
+        {
+            Outcome::Done => Ok(()),
+            outcome => Err(outcome.mismatch("create_collection")),
+        }
+    }
+
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        match self
+            .call(Operation::CollectionExists { name: name.to_string() })
+            .await?
+        {
+            Outcome::Exists(exists) => Ok(exists),
+            outcome => Err(outcome.mismatch("collection_exists")),
+        }
+    }
+
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        match self
+            .call(Operation::EnsureCollection { name: name.to_string() })
+            .await?
+        {
+            Outcome::Done => Ok(()),
+            outcome => Err(outcome.mismatch("ensure_collection")),
+        }
+    }
+
+    async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        match self
+            .call(Operation::DropCollection { name: name.to_string() })
+            .await?
+        {
+            Outcome::Done => Ok(()),
+            outcome => Err(outcome.mismatch("drop_collection")),
+        }
+    }
+
+    async fn list_collections(&self) -> DocumentStoreResult<Vec<String>> {
+        match self
+            .call(Operation::ListCollections)
+            .await?
+        {
+            Outcome::Collections(names) => Ok(names),
+            outcome => Err(outcome.mismatch("list_collections")),
+        }
+    }
+
+    async fn add_field(
+        &self,
+        collection: &str,
+        field: &str,
+        default: Bson,
+    ) -> DocumentStoreResult<()> {
+        match self
+            .call(Operation::AddField {
+                collection: collection.to_string(),
+                field: field.to_string(),
+                default,
+            })
+            .await?
+        {
+            Outcome::Done => Ok(()),
+            outcome => Err(outcome.mismatch("add_field")),
+        }
+    }
+
+    async fn drop_field(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        match self
+            .call(Operation::DropField {
+                collection: collection.to_string(),
+                field: field.to_string(),
+            })
+            .await?
+        {
+            Outcome::Done => Ok(()),
+            outcome => Err(outcome.mismatch("drop_field")),
+        }
+    }
+
+    async fn rename_field(
+        &self,
+        collection: &str,
+        field: &str,
+        new: &str,
+    ) -> DocumentStoreResult<()> {
+        match self
+            .call(Operation::RenameField {
+                collection: collection.to_string(),
+                field: field.to_string(),
+                new: new.to_string(),
+            })
+            .await?
+        {
+            Outcome::Done => Ok(()),
+            outcome => Err(outcome.mismatch("rename_field")),
+        }
+    }
+
+    async fn add_index(
+        &self,
+        collection: &str,
+        field: &str,
+        unique: bool,
+    ) -> DocumentStoreResult<()> {
+        match self
+            .call(Operation::AddIndex {
+                collection: collection.to_string(),
+                field: field.to_string(),
+                unique,
+            })
+            .await?
+        {
+            Outcome::Done => Ok(()),
+            outcome => Err(outcome.mismatch("add_index")),
+        }
+    }
+
+    async fn drop_index(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        match self
+            .call(Operation::DropIndex {
+                collection: collection.to_string(),
+                field: field.to_string(),
+            })
+            .await?
+        {
+            Outcome::Done => Ok(()),
+            outcome => Err(outcome.mismatch("drop_index")),
+        }
+    }
+
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        match self
+            .call(Operation::ExpireDocuments {
+                collection: collection.to_string(),
+                field: field.to_string(),
+            })
+            .await?
+        {
+            Outcome::Done => Ok(()),
+            outcome => Err(outcome.mismatch("expire_documents")),
+        }
+    }
+
+    // A transaction runs its operations on the backend directly, so they don't pass through
+    // the layer
+    async fn begin_transaction(&self) -> DocumentStoreResult<Transaction> {
+        self.inner.begin_transaction().await
+    }
+
+    fn inner_backend(&self) -> Option<&dyn DynStoreBackend> {
+        Some(&self.inner)
+    }
+
+    async fn close(&self) -> DocumentStoreResult<()> {
+        self.inner.close().await
+    }
+
+    async fn shutdown(self) -> DocumentStoreResult<()> {
+        self.inner.shutdown().await
+    }
+}
@@ -0,0 +1,621 @@
+//! Caching documents of a persistent backend in a faster one.
+//!
+//! [`CachedStore`] layers two backends: lookups by ID are served from the front backend,
+//! typically an in-memory store, and fall back to the back backend on a miss, caching what it
+//! returns. Every write goes through to the back backend and then invalidates the documents
+//! it touched in the front, so hot documents are read from memory without going stale.
+//!
+//! Only [`get_documents`](StoreBackend::get_documents) is cached. Queries, counts and
+//! aggregations always run against the back backend, which has the complete collections.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::{cache::CachedStore, memory::InMemoryStore};
+//!
+//! let store = DocumentStore::new(CachedStore::new(InMemoryStore::new(), mongo));
+//!
+//! // Read from MongoDB once, then from memory until the user is written again
+//! let user = store.typed_collection::<User>().get_one(user_id).await?;
+//! ```
+//!
+//! Invalidation only sees writes made through the cached store. Documents changed by other
+//! processes stay cached until they are written through this store, so the front should be
+//! cleared with [`CachedStore::clear`] when the back is shared with other writers.
+//!
+//! Documents the back deletes on its own once they expire (see the [`expiry`](crate::expiry)
+//! module) aren't written through the cache either. Collections made to expire through the
+//! cached store are recorded instead: cached documents whose expiry has passed are dropped
+//! from the front when looked up and read from the back again, and expired documents aren't
+//! cached.
+
+use async_trait::async_trait;
+use bson::{Bson, RawDocumentBuf, Uuid};
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{
+    aggregate::Aggregate,
+    backend::{
+        DocumentStream, DynStoreBackend, InsertPolicy, MissingDocumentPolicy, QueryPlan,
+        ReturnDocument, StoreBackend, WriteReport,
+    },
+    document::stored_id,
+    error::DocumentStoreResult,
+    expiry::{ExpiringCollections, has_expired},
+    query::{Expr, Query, Sort},
+    update::Update,
+};
+
+/// The field holding a document's ID, unless configured otherwise.
+pub const DEFAULT_ID_FIELD: &str = "id";
+
+/// A backend serving lookups by ID from a front cache, writing through to a back backend.
+///
+/// Documents fetched from the back backend are cached under the ID read from their
+/// [ID field](CachedStore::with_id_field). Documents without a readable ID are returned but
+/// not cached.
+#[derive(Debug)]
+pub struct CachedStore<F, B> {
+    front: F,
+    back: B,
+    id_field: String,
+    /// The collections made to expire through this store, whose expired documents aren't served
+    expiring: ExpiringCollections,
+    /// Incremented by every write, so a lookup racing a write discards what it cached
+    generation: AtomicU64,
+}
+
+impl<F: StoreBackend, B: StoreBackend> CachedStore<F, B> {
+    /// Caches the documents of `back` in `front`.
+    ///
+    /// # Arguments
+    ///
+    /// * `front` - The cache, such as an empty in-memory store
+    /// * `back` - The persistent backend holding the documents
+    pub fn new(front: F, back: B) -> Self {
+        Self {
+            front,
+            back,
+            id_field: DEFAULT_ID_FIELD.to_string(),
+            expiring: ExpiringCollections::new(),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Sets the field documents hold their ID in, `id` by default.
+    pub fn with_id_field(mut self, field: impl Into<String>) -> Self {
+        self.id_field = field.into();
+        self
+    }
+
+    /// Returns the cache.
+    pub fn front(&self) -> &F {
+        &self.front
+    }
+
+    /// Returns the backend holding the documents.
+    pub fn back(&self) -> &B {
+        &self.back
+    }
+
+    /// Unwraps the cache and the backend holding the documents.
+    pub fn into_inner(self) -> (F, B) {
+        (self.front, self.back)
+    }
+
+    /// Empties the cache, so every document is read from the back backend again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a cached collection can't be dropped.
+    pub async fn clear(&self) -> DocumentStoreResult<()> {
+        self.generation
+            .fetch_add(1, Ordering::SeqCst);
+
+        for collection in self.front.list_collections().await? {
+            self.front
+                .drop_collection(&collection)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the ID of a document from its ID field.
+    fn document_id(&self, document: &Bson) -> Option<Uuid> {
+        stored_id(document, &self.id_field)
+    }
+
+    /// Removes documents from the cache after they were written.
+    async fn invalidate(&self, ids: Vec<Uuid>, collection: &str) -> DocumentStoreResult<()> {
+        self.generation
+            .fetch_add(1, Ordering::SeqCst);
+        self.front
+            .delete_documents(ids, collection)
+            .await
+            .map(|_| ())
+    }
+
+    /// Removes a whole collection from the cache, after writes whose documents aren't known.
+    async fn invalidate_collection(&self, collection: &str) -> DocumentStoreResult<()> {
+        self.generation
+            .fetch_add(1, Ordering::SeqCst);
+
+        if self
+            .front
+            .collection_exists(collection)
+            .await?
+        {
+            self.front
+                .drop_collection(collection)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<F: StoreBackend + 'static, B: StoreBackend + 'static> StoreBackend for CachedStore<F, B> {
+    async fn insert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        let ids = documents
+            .iter()
+            .map(|(id, _)| *id)
+            .collect();
+
+        // A failed insert may still have written the documents before the failing one
+        let result = self
+            .back
+            .insert_documents(documents, collection, policy)
+            .await;
+        self.invalidate(ids, collection).await?;
+
+        result
+    }
+
+    async fn update_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        let ids = documents
+            .iter()
+            .map(|(id, _)| *id)
+            .collect();
+
+        let result = self
+            .back
+            .update_documents(documents, collection, policy)
+            .await;
+        self.invalidate(ids, collection).await?;
+
+        result
+    }
+
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        let ids = documents
+            .iter()
+            .map(|(id, ..)| *id)
+            .collect();
+
+        let result = self
+            .back
+            .update_documents_if_version(documents, version_field, collection)
+            .await;
+        self.invalidate(ids, collection).await?;
+
+        result
+    }
+
+    async fn update_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, Bson, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        let ids = documents
+            .iter()
+            .map(|(id, ..)| *id)
+            .collect();
+
+        let result = self
+            .back
+            .update_documents_if_match(documents, collection)
+            .await;
+        self.invalidate(ids, collection).await?;
+
+        result
+    }
+
+    async fn upsert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+    ) -> DocumentStoreResult<()> {
+        let ids = documents
+            .iter()
+            .map(|(id, _)| *id)
+            .collect();
+
+        let result = self
+            .back
+            .upsert_documents(documents, collection)
+            .await;
+        self.invalidate(ids, collection).await?;
+
+        result
+    }
+
+    async fn delete_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        let result = self
+            .back
+            .delete_documents(ids.clone(), collection)
+            .await;
+        self.invalidate(ids, collection).await?;
+
+        result
+    }
+
+    async fn delete_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        let ids = documents
+            .iter()
+            .map(|(id, _)| *id)
+            .collect();
+
+        let result = self
+            .back
+            .delete_documents_if_match(documents, collection)
+            .await;
+        self.invalidate(ids, collection).await?;
+
+        result
+    }
+
+    async fn update_by_query(
+        &self,
+        filter: Expr,
+        update: Update,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        let result = self
+            .back
+            .update_by_query(filter, update, collection)
+            .await;
+        self.invalidate_collection(collection)
+            .await?;
+
+        result
+    }
+
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize> {
+        let result = self
+            .back
+            .delete_by_query(filter, collection)
+            .await;
+        self.invalidate_collection(collection)
+            .await?;
+
+        result
+    }
+
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        let result = self
+            .back
+            .find_one_and_update(filter, sort, update, returned, collection)
+            .await;
+        self.invalidate_collection(collection)
+            .await?;
+
+        result
+    }
+
+    async fn find_one_and_delete(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        let result = self
+            .back
+            .find_one_and_delete(filter, sort, collection)
+            .await;
+        self.invalidate_collection(collection)
+            .await?;
+
+        result
+    }
+
+    async fn get_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        let generation = self.generation.load(Ordering::SeqCst);
+        let expiry = self.expiring.field(collection).await;
+
+        let mut documents = self
+            .front
+            .get_documents(ids.clone(), collection)
+            .await?;
+
+        // The back deletes expired documents without the cache seeing it, so they're dropped
+        // from the front and looked up again
+        if let Some(field) = &expiry {
+            let expired = documents
+                .iter()
+                .filter(|document| has_expired(document, field))
+                .filter_map(|document| self.document_id(document))
+                .collect::<Vec<_>>();
+
+            if !expired.is_empty() {
+                documents.retain(|document| !has_expired(document, field));
+                self.front
+                    .delete_documents(expired, collection)
+                    .await?;
+            }
+        }
+
+        let cached = documents
+            .iter()
+            .filter_map(|document| self.document_id(document))
+            .collect::<HashSet<_>>();
+        let missing = ids
+            .into_iter()
+            .filter(|id| !cached.contains(id))
+            .collect::<Vec<_>>();
+
+        if missing.is_empty() {
+            return Ok(documents);
+        }
+
+        let fetched = self
+            .back
+            .get_documents(missing, collection)
+            .await?;
+        let entries = fetched
+            .iter()
+            .filter(|document| {
+                expiry
+                    .as_ref()
+                    .is_none_or(|field| !has_expired(document, field))
+            })
+            .filter_map(|document| {
+                self.document_id(document)
+                    .map(|id| (id, document.clone()))
+            })
+            .collect::<Vec<_>>();
+
+        if !entries.is_empty() && self.generation.load(Ordering::SeqCst) == generation {
+            let ids = entries
+                .iter()
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>();
+
+            self.front
+                .upsert_documents(entries, collection)
+                .await?;
+
+            // A write that landed meanwhile may have invalidated the documents before they
+            // were cached, so they are dropped again
+            if self.generation.load(Ordering::SeqCst) != generation {
+                self.front
+                    .delete_documents(ids, collection)
+                    .await?;
+            }
+        }
+
+        documents.extend(fetched);
+
+        Ok(documents)
+    }
+
+    async fn query_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.back
+            .query_documents(query, collection)
+            .await
+    }
+
+    async fn query_stream(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<DocumentStream> {
+        self.back
+            .query_stream(query, collection)
+            .await
+    }
+
+    async fn query_raw_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<RawDocumentBuf>> {
+        self.back
+            .query_raw_documents(query, collection)
+            .await
+    }
+
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize> {
+        self.back
+            .count_documents(query, collection)
+            .await
+    }
+
+    async fn distinct(
+        &self,
+        field: &str,
+        filter: Option<Expr>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.back
+            .distinct(field, filter, collection)
+            .await
+    }
+
+    async fn aggregate(
+        &self,
+        aggregate: Aggregate,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.back
+            .aggregate(aggregate, collection)
+            .await
+    }
+
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan> {
+        self.back
+            .explain(query, collection)
+            .await
+    }
+
+    async fn current_revision_id(&self) -> DocumentStoreResult<Option<String>> {
+        self.back.current_revision_id().await
+    }
+
+    async fn set_revision_id(&self, revision_id: &str) -> DocumentStoreResult<()> {
+        self.back
+            .set_revision_id(revision_id)
+            .await
+    }
+
+    async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.back.create_collection(name).await
+    }
+
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        self.back.collection_exists(name).await
+    }
+
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.back.ensure_collection(name).await
+    }
+
+    async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        let result = self.back.drop_collection(name).await;
+        if result.is_ok() {
+            self.expiring.remove(name).await;
+        }
+        self.invalidate_collection(name).await?;
+
+        result
+    }
+
+    async fn list_collections(&self) -> DocumentStoreResult<Vec<String>> {
+        self.back.list_collections().await
+    }
+
+    async fn add_field(
+        &self,
+        collection: &str,
+        field: &str,
+        default: Bson,
+    ) -> DocumentStoreResult<()> {
+        let result = self
+            .back
+            .add_field(collection, field, default)
+            .await;
+        self.invalidate_collection(collection)
+            .await?;
+
+        result
+    }
+
+    async fn drop_field(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        let result = self
+            .back
+            .drop_field(collection, field)
+            .await;
+        self.invalidate_collection(collection)
+            .await?;
+
+        result
+    }
+
+    async fn rename_field(
+        &self,
+        collection: &str,
+        field: &str,
+        new: &str,
+    ) -> DocumentStoreResult<()> {
+        let result = self
+            .back
+            .rename_field(collection, field, new)
+            .await;
+        self.invalidate_collection(collection)
+            .await?;
+
+        result
+    }
+
+    async fn add_index(
+        &self,
+        collection: &str,
+        field: &str,
+        unique: bool,
+    ) -> DocumentStoreResult<()> {
+        self.back
+            .add_index(collection, field, unique)
+            .await
+    }
+
+    async fn drop_index(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.back
+            .drop_index(collection, field)
+            .await
+    }
+
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.back
+            .expire_documents(collection, field)
+            .await?;
+        self.expiring.insert(collection, field).await;
+
+        Ok(())
+    }
+
+    fn inner_backend(&self) -> Option<&dyn DynStoreBackend> {
+        Some(&self.back)
+    }
+
+    async fn close(&self) -> DocumentStoreResult<()> {
+        self.front.close().await?;
+        self.back.close().await
+    }
+
+    async fn shutdown(self) -> DocumentStoreResult<()> {
+        self.front.shutdown().await?;
+        self.back.shutdown().await
+    }
+}
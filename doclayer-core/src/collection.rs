@@ -37,16 +37,40 @@
 //! # Ok(()) }
 //! ```
 
-use bson::{Bson, Uuid};
-use std::marker::PhantomData;
+use bson::{Bson, Uuid, de::deserialize_from_bson};
+use futures::{
+    Stream,
+    future::BoxFuture,
+    stream::{BoxStream, StreamExt},
+};
+use serde::de::DeserializeOwned;
+use std::{any::type_name, collections::HashSet, future::IntoFuture, marker::PhantomData, time::Duration};
 
 use crate::{
-    backend::{DynStoreBackend, StoreBackend},
-    document::{Document, DocumentExt},
-    error::DocumentStoreResult,
-    query::Query,
+    aggregate::Aggregate,
+    backend::{
+        DocumentStream, DynStoreBackend, InsertPolicy, MissingDocumentPolicy, QueryPlan,
+        ReturnDocument, StoreBackend, WriteReport,
+    },
+    document::{AnyDocument, Document, DocumentExt, DocumentRegistry, FilledDocuments, RawDoc},
+    encoding::Encoding,
+    error::{DocumentStoreError, DocumentStoreResult},
+    expiry::expires_at,
+    import::{self, ImportOptions, ImportReport, ImportTarget},
+    page::{Page, PageRequest, PaginationParams, QueryPage},
+    query::{Expr, Field, Filter, IntoFilter, Query, Sort, SortDirection, TypedFields},
+    time::Instant,
+    update::Update,
+    validate::{Validator, Validators},
+    versioned::{Versioned, document_revision},
 };
 
+/// The default maximum number of IDs fetched per backend call when getting documents.
+///
+/// Backends build a single request from the IDs of a call, such as a MongoDB `$in` filter,
+/// which fails once it exceeds the backend's size limits.
+pub const DEFAULT_GET_CHUNK_SIZE: usize = 10_000;
+
 /// An untyped collection with a reference to a storage backend.
 ///
 /// This struct provides access to a collection with explicit BSON document handling.
@@ -61,12 +85,17 @@ use crate::{
 pub struct Collection<'a, B: StoreBackend> {
     name: String,
     backend: &'a B,
+    get_chunk_size: usize,
 }
 
 impl<'a, B: StoreBackend> Collection<'a, B> {
     /// Creates a new collection reference (internal use).
     pub(crate) fn new(name: String, backend: &'a B) -> Self {
-        Self { name, backend }
+        Self {
+            name,
+            backend,
+            get_chunk_size: DEFAULT_GET_CHUNK_SIZE,
+        }
     }
 
     /// Returns the name of this collection.
@@ -74,7 +103,19 @@ impl<'a, B: StoreBackend> Collection<'a, B> {
         &self.name
     }
 
-    /// Inserts new documents into the collection, overwriting existing documents with the same IDs.
+    /// Sets the maximum number of IDs fetched per backend call when getting documents.
+    ///
+    /// Longer ID lists are split into several calls and their results concatenated, keeping
+    /// each request within the backend's limits. Defaults to [`DEFAULT_GET_CHUNK_SIZE`].
+    pub fn with_get_chunk_size(mut self, size: usize) -> Self {
+        self.get_chunk_size = size.max(1);
+        self
+    }
+
+    /// Inserts new documents into the collection.
+    ///
+    /// Fails without writing anything if any of the documents already exists
+    /// ([`InsertPolicy::ErrorOnConflict`]).
     ///
     /// # Arguments
     ///
@@ -82,337 +123,396 @@ impl<'a, B: StoreBackend> Collection<'a, B> {
     ///
     /// # Errors
     ///
-    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    /// Returns [`DocumentStoreError::DocumentAlreadyExists`](crate::error::DocumentStoreError::DocumentAlreadyExists)
+    /// if a document with the same ID exists, or another
+    /// [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
     pub async fn insert(&self, documents: Vec<(Uuid, Bson)>) -> DocumentStoreResult<()> {
-        Ok(self
-            .backend
-            .insert_documents(documents, &self.name())
-            .await?)
+        self.insert_with_policy(documents, InsertPolicy::ErrorOnConflict)
+            .await
+            .map(|_| ())
     }
 
-    /// Updates existing documents in the collection.
+    /// Inserts documents into the collection, handling existing documents according to a policy.
     ///
     /// # Arguments
     ///
-    /// * `documents` - A vector of (ID, BSON document) pairs with updated content
+    /// * `documents` - A vector of (ID, BSON document) pairs to insert
+    /// * `policy` - How to handle documents whose ID already exists
+    ///
+    /// # Returns
+    ///
+    /// A [`WriteReport`] with the existing documents as matched, the replaced ones that changed
+    /// as modified and the new ones as upserted.
     ///
     /// # Errors
     ///
     /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
-    pub async fn update(&self, documents: Vec<(Uuid, Bson)>) -> DocumentStoreResult<()> {
-        Ok(self
-            .backend
-            .update_documents(documents, &self.name())
-            .await?)
+    pub async fn insert_with_policy(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.backend
+            .insert_documents(documents, self.name(), policy)
+            .await
     }
 
-    /// Deletes documents from the collection by their IDs.
+    /// Inserts new documents into the collection that expire after a time-to-live.
+    ///
+    /// Sets `field` of each document to the current time plus `ttl`. The documents are deleted
+    /// once expired only if the collection expires documents on that field (see the
+    /// [`expiry`](crate::expiry) module). Like [`insert`](Self::insert), fails without writing
+    /// anything if any of the documents already exists.
     ///
     /// # Arguments
     ///
-    /// * `ids` - A vector of document IDs to delete (must implement `Into<Uuid>`)
+    /// * `documents` - A vector of (ID, BSON document) pairs to insert
+    /// * `field` - The field to store the expiry in
+    /// * `ttl` - How long the documents live
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::InvalidDocument`] if a value isn't a BSON document, or
+    /// another [`DocumentStoreError`] if the insertion fails.
+    pub async fn insert_with_ttl(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        field: &str,
+        ttl: Duration,
+    ) -> DocumentStoreResult<()> {
+        self.insert(with_expiry(documents, field, Bson::DateTime(expires_at(ttl)))?)
+            .await
+    }
+
+    /// Updates existing documents in the collection.
+    ///
+    /// Fails without writing anything if any of the documents doesn't exist
+    /// ([`MissingDocumentPolicy::Error`]).
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of (ID, BSON document) pairs with updated content
     ///
     /// # Errors
     ///
     /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
-    pub async fn delete<U>(&self, ids: Vec<U>) -> DocumentStoreResult<()>
-    where
-        U: Into<Uuid> + Send + Sync + 'static,
-    {
-        Ok(self
-            .backend
-            .delete_documents(
-                ids.into_iter()
-                    .map(Into::into)
-                    .collect(),
-                &self.name(),
-            )
-            .await?)
+    pub async fn update(&self, documents: Vec<(Uuid, Bson)>) -> DocumentStoreResult<()> {
+        self.update_with_policy(documents, MissingDocumentPolicy::Error)
+            .await
+            .map(|_| ())
     }
 
-    /// Retrieves documents from the collection by their IDs.
+    /// Updates documents in the collection, handling missing documents according to a policy.
     ///
     /// # Arguments
     ///
-    /// * `ids` - A vector of document IDs to retrieve (must implement `Into<Uuid>`)
+    /// * `documents` - A vector of (ID, BSON document) pairs with updated content
+    /// * `policy` - How to handle documents that don't exist
     ///
     /// # Returns
     ///
-    /// A vector of BSON documents found. If a document ID doesn't exist, it is omitted from results.
+    /// A [`WriteReport`] with the matched, modified and inserted counts.
     ///
     /// # Errors
     ///
     /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
-    pub async fn get<U>(&self, ids: Vec<U>) -> DocumentStoreResult<Vec<Bson>>
-    where
-        U: Into<Uuid> + Send + Sync + 'static,
-    {
-        Ok(self
-            .backend
-            .get_documents(
-                ids.into_iter()
-                    .map(Into::into)
-                    .collect(),
-                &self.name(),
-            )
-            .await?)
+    pub async fn update_with_policy(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.backend
+            .update_documents(documents, self.name(), policy)
+            .await
     }
 
-    /// Queries documents in the collection using a structured query.
+    /// Updates existing documents only if the stored documents are still at the expected
+    /// versions, for optimistic concurrency control.
     ///
     /// # Arguments
     ///
-    /// * `query` - The [`Query`] specifying filters, sorting, limits, and offsets
+    /// * `documents` - A vector of (ID, BSON document, expected version) triples, each
+    ///   document carrying its new version
+    /// * `version_field` - The field holding the version
     ///
     /// # Returns
     ///
-    /// A vector of BSON documents matching the query criteria.
+    /// A [`WriteReport`] with the matched and modified counts.
     ///
     /// # Errors
     ///
-    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
-    pub async fn query(&self, query: Query) -> DocumentStoreResult<Vec<Bson>> {
-        Ok(self
-            .backend
-            .query_documents(query, &self.name())
-            .await?)
-    }
-}
-
-/// A dynamic (type-erased) collection with a reference to a backend trait object.
-///
-/// This struct provides access to a collection with explicit BSON document handling,
-/// similar to [`Collection`], but uses dynamic dispatch via trait objects for backend operations.
-/// This enables using different backend implementations at runtime without generic type parameters.
-///
-/// # Type Parameters
-///
-/// * `'a` - Lifetime of the backend trait object reference
-#[derive(Debug)]
-pub struct DynCollection<'a> {
-    name: String,
-    backend: &'a dyn DynStoreBackend,
-}
-
-impl<'a> DynCollection<'a> {
-    /// Creates a new dynamic collection reference (internal use).
-    pub(crate) fn new(name: String, backend: &'a dyn DynStoreBackend) -> Self {
-        Self { name, backend }
+    /// Returns [`DocumentStoreError::VersionConflict`] if a stored document is at another
+    /// version, or another [`DocumentStoreError`] if the operation fails.
+    pub async fn update_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.backend
+            .update_documents_if_version(documents, version_field, self.name())
+            .await
     }
 
-    /// Returns the name of this collection.
-    pub fn name(&self) -> &str {
-        &self.name
-    }
-
-    /// Inserts new documents into the collection, overwriting existing documents with the same IDs.
+    /// Updates existing documents only if they are still at the given revisions, as read
+    /// with [`get_versioned`](Self::get_versioned).
     ///
     /// # Arguments
     ///
-    /// * `documents` - A vector of (ID, BSON document) pairs to insert
+    /// * `documents` - A vector of (ID, BSON document, revision) triples
+    ///
+    /// # Returns
+    ///
+    /// A [`WriteReport`] with the matched and modified counts.
     ///
     /// # Errors
     ///
-    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
-    pub async fn insert(&self, documents: Vec<(Uuid, Bson)>) -> DocumentStoreResult<()> {
-        Ok(self
-            .backend
-            .insert_documents(documents, &self.name())
-            .await?)
+    /// Returns [`DocumentStoreError::PreconditionFailed`] if a document doesn't exist or is at
+    /// another revision, or another [`DocumentStoreError`] if the operation fails.
+    pub async fn update_if_match(
+        &self,
+        documents: Vec<(Uuid, Bson, String)>,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.backend
+            .update_documents_if_match(documents, self.name())
+            .await
     }
 
-    /// Updates existing documents in the collection.
+    /// Inserts documents into the collection, replacing any existing documents with the same IDs.
     ///
     /// # Arguments
     ///
-    /// * `documents` - A vector of (ID, BSON document) pairs with updated content
+    /// * `documents` - A vector of (ID, BSON document) pairs to insert or replace
     ///
     /// # Errors
     ///
     /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
-    pub async fn update(&self, documents: Vec<(Uuid, Bson)>) -> DocumentStoreResult<()> {
-        Ok(self
-            .backend
-            .update_documents(documents, &self.name())
-            .await?)
+    pub async fn upsert(&self, documents: Vec<(Uuid, Bson)>) -> DocumentStoreResult<()> {
+        self.backend
+            .upsert_documents(documents, self.name())
+            .await
     }
 
     /// Deletes documents from the collection by their IDs.
     ///
+    /// IDs that don't exist are skipped, so deleting the same documents twice is not an error.
+    /// Use [`delete_strict`](Self::delete_strict) to fail when documents are missing.
+    ///
     /// # Arguments
     ///
     /// * `ids` - A vector of document IDs to delete (must implement `Into<Uuid>`)
     ///
+    /// # Returns
+    ///
+    /// The number of deleted documents.
+    ///
     /// # Errors
     ///
     /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
-    pub async fn delete<U>(&self, ids: Vec<U>) -> DocumentStoreResult<()>
+    pub async fn delete<U>(&self, ids: Vec<U>) -> DocumentStoreResult<usize>
     where
         U: Into<Uuid> + Send + Sync + 'static,
     {
-        Ok(self
-            .backend
+        self.backend
             .delete_documents(
                 ids.into_iter()
                     .map(Into::into)
                     .collect(),
-                &self.name(),
+                self.name(),
             )
-            .await?)
+            .await
     }
 
-    /// Retrieves documents from the collection by their IDs.
+    /// Deletes documents from the collection by their IDs, failing if any of them is missing.
+    ///
+    /// Documents that do exist are still deleted; the error reports how many were missing.
     ///
     /// # Arguments
     ///
-    /// * `ids` - A vector of document IDs to retrieve (must implement `Into<Uuid>`)
+    /// * `ids` - A vector of document IDs to delete (must implement `Into<Uuid>`)
     ///
     /// # Returns
     ///
-    /// A vector of BSON documents found. If a document ID doesn't exist, it is omitted from results.
+    /// The number of deleted documents, which equals the number of distinct IDs given.
     ///
     /// # Errors
     ///
-    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
-    pub async fn get<U>(&self, ids: Vec<U>) -> DocumentStoreResult<Vec<Bson>>
+    /// Returns [`DocumentStoreError::DocumentNotFound`] if fewer documents were deleted
+    /// than requested, or another [`DocumentStoreError`] if the operation fails.
+    pub async fn delete_strict<U>(&self, ids: Vec<U>) -> DocumentStoreResult<usize>
     where
         U: Into<Uuid> + Send + Sync + 'static,
     {
-        Ok(self
+        let mut ids = ids
+            .into_iter()
+            .map(Into::into)
+            .collect::<Vec<Uuid>>();
+        ids.sort_unstable_by_key(|id| id.bytes());
+        ids.dedup();
+
+        let requested = ids.len();
+        let deleted = self
             .backend
-            .get_documents(
-                ids.into_iter()
-                    .map(Into::into)
-                    .collect(),
-                &self.name(),
-            )
-            .await?)
+            .delete_documents(ids, self.name())
+            .await?;
+
+        if deleted < requested {
+            return Err(DocumentStoreError::DocumentNotFound(
+                format!("{} of {} requested documents", requested - deleted, requested),
+                self.name().to_string(),
+            ));
+        }
+
+        Ok(deleted)
     }
 
-    /// Queries documents in the collection using a structured query.
+    /// Deletes documents from the collection only if they are still at the given revisions,
+    /// as read with `get_versioned`.
     ///
     /// # Arguments
     ///
-    /// * `query` - The [`Query`] specifying filters, sorting, limits, and offsets
+    /// * `revisions` - A vector of (ID, revision) pairs, such as an ID and the `If-Match`
+    ///   header of a request converted with
+    ///   [`revision_from_etag`](crate::versioned::revision_from_etag)
     ///
     /// # Returns
     ///
-    /// A vector of BSON documents matching the query criteria.
+    /// The number of deleted documents.
     ///
     /// # Errors
     ///
-    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
-    pub async fn query(&self, query: Query) -> DocumentStoreResult<Vec<Bson>> {
-        Ok(self
-            .backend
-            .query_documents(query, &self.name())
-            .await?)
-    }
-}
-
-#[derive(Debug)]
-pub struct TypedCollection<'a, B: StoreBackend, D: Document> {
-    name: String,
-    backend: &'a B,
-    _marker: PhantomData<D>,
-}
-
-impl<'a, B: StoreBackend, D: Document> TypedCollection<'a, B, D> {
-    pub(crate) fn new(name: String, backend: &'a B) -> Self {
-        Self { name, backend, _marker: PhantomData }
+    /// Returns [`DocumentStoreError::PreconditionFailed`] if a document doesn't exist or is at
+    /// another revision, or another [`DocumentStoreError`] if the operation fails.
+    pub async fn delete_if_match(
+        &self,
+        revisions: Vec<(Uuid, String)>,
+    ) -> DocumentStoreResult<usize> {
+        self.backend
+            .delete_documents_if_match(revisions, self.name())
+            .await
     }
 
-    /// Returns the name of this collection.
-    pub fn name(&self) -> &str {
-        &self.name
+    /// Applies a partial update to every document in the collection matching a filter expression.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The [`Expr`] selecting the documents to update
+    /// * `update` - The [`Update`] operations to apply
+    ///
+    /// # Returns
+    ///
+    /// A [`WriteReport`] with the number of matched and modified documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn update_where(
+        &self,
+        filter: Expr,
+        update: Update,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.backend
+            .update_by_query(filter, update, self.name())
+            .await
     }
 
-    /// Converts this typed collection to a different document type.
+    /// Deletes every document in the collection matching a filter expression.
     ///
-    /// This method allows switching between different document types for the same collection.
-    pub fn with_type<T: Document>(&self) -> TypedCollection<'a, B, T> {
-        TypedCollection {
-            name: self.name.clone(),
-            backend: self.backend,
-            _marker: PhantomData,
-        }
+    /// # Arguments
+    ///
+    /// * `filter` - The [`Expr`] selecting the documents to delete
+    ///
+    /// # Returns
+    ///
+    /// The number of deleted documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn delete_where(&self, filter: Expr) -> DocumentStoreResult<usize> {
+        self.backend
+            .delete_by_query(filter, self.name())
+            .await
     }
 
-    /// Inserts new documents into the collection, overwriting existing documents with the same IDs.
+    /// Applies a partial update to the first document matching a filter expression and
+    /// returns the document as it was before the update, as a single atomic operation.
+    ///
+    /// Of several callers racing for the same documents, each gets a different one, so this
+    /// can claim work such as a pending job.
     ///
     /// # Arguments
     ///
-    /// * `documents` - A vector of documents to insert
+    /// * `filter` - The [`Expr`] selecting the documents to consider
+    /// * `update` - The [`Update`] operations to apply
+    ///
+    /// # Returns
+    ///
+    /// The document before the update, or `None` if no document matches.
     ///
     /// # Errors
     ///
-    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if serialization or insertion fails.
-    pub async fn insert(&self, documents: Vec<D>) -> DocumentStoreResult<()> {
-        Ok(self
-            .backend
-            .insert_documents(
-                documents
-                    .into_iter()
-                    .map(|d| {
-                        d.to_bson()
-                            .map(move |b| (d.id().clone(), b))
-                    })
-                    .collect::<Result<Vec<(Uuid, Bson)>, _>>()?,
-                &self.name(),
-            )
-            .await?)
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        update: Update,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        self.backend
+            .find_one_and_update(filter, Vec::new(), update, ReturnDocument::Before, self.name())
+            .await
     }
 
-    /// Updates existing documents in the collection.
+    /// Deletes the first document matching a filter expression and returns it, as a single
+    /// atomic operation.
     ///
     /// # Arguments
     ///
-    /// * `documents` - A vector of documents with updated content
+    /// * `filter` - The [`Expr`] selecting the documents to consider
+    ///
+    /// # Returns
+    ///
+    /// The deleted document, or `None` if no document matches.
     ///
     /// # Errors
     ///
-    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if serialization or update fails.
-    pub async fn update(&self, documents: Vec<D>) -> DocumentStoreResult<()> {
-        Ok(self
-            .backend
-            .update_documents(
-                documents
-                    .into_iter()
-                    .map(|d| {
-                        d.to_bson()
-                            .map(move |b| (d.id().clone(), b))
-                    })
-                    .collect::<Result<Vec<(Uuid, Bson)>, _>>()?,
-                &self.name(),
-            )
-            .await?)
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn find_one_and_delete(&self, filter: Expr) -> DocumentStoreResult<Option<Bson>> {
+        self.backend
+            .find_one_and_delete(filter, Vec::new(), self.name())
+            .await
     }
 
-    /// Deletes documents from the collection by their IDs.
+    /// Retrieves documents from the collection by their IDs.
     ///
     /// # Arguments
     ///
-    /// * `ids` - A vector of document IDs to delete (must implement `Into<Uuid>`)
+    /// * `ids` - A vector of document IDs to retrieve (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// A vector of BSON documents found. If a document ID doesn't exist, it is omitted from results.
     ///
     /// # Errors
     ///
     /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
-    pub async fn delete<U>(&self, ids: Vec<U>) -> DocumentStoreResult<()>
+    pub async fn get<U>(&self, ids: Vec<U>) -> DocumentStoreResult<Vec<Bson>>
     where
         U: Into<Uuid> + Send + Sync + 'static,
     {
-        Ok(self
-            .backend
-            .delete_documents(
-                ids.into_iter()
-                    .map(Into::into)
-                    .collect(),
-                &self.name(),
-            )
-            .await?)
+        get_in_chunks(
+            ids.into_iter()
+                .map(Into::into)
+                .collect(),
+            self.get_chunk_size,
+            |ids| {
+                self.backend
+                    .get_documents(ids, self.name())
+            },
+        )
+        .await
     }
 
-    /// Retrieves documents from the collection by their IDs.
+    /// Retrieves documents from the collection by their IDs, along with their revisions.
     ///
     /// # Arguments
     ///
@@ -420,27 +520,20 @@ impl<'a, B: StoreBackend, D: Document> TypedCollection<'a, B, D> {
     ///
     /// # Returns
     ///
-    /// A vector of documents found. If a document ID doesn't exist, it is omitted from results.
+    /// A vector of the BSON documents found, each with its revision.
     ///
     /// # Errors
     ///
-    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or retrieval fails.
-    pub async fn get<U>(&self, ids: Vec<U>) -> DocumentStoreResult<Vec<D>>
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn get_versioned<U>(&self, ids: Vec<U>) -> DocumentStoreResult<Vec<Versioned<Bson>>>
     where
         U: Into<Uuid> + Send + Sync + 'static,
     {
-        Ok(self
-            .backend
-            .get_documents(
-                ids.into_iter()
-                    .map(Into::into)
-                    .collect(),
-                &self.name(),
-            )
+        self.get(ids)
             .await?
             .into_iter()
-            .map(|doc| D::from_bson(doc))
-            .collect::<Result<Vec<D>, _>>()?)
+            .map(|doc| document_revision(&doc).map(|revision| Versioned::new(doc, revision)))
+            .collect()
     }
 
     /// Queries documents in the collection using a structured query.
@@ -451,175 +544,4018 @@ impl<'a, B: StoreBackend, D: Document> TypedCollection<'a, B, D> {
     ///
     /// # Returns
     ///
-    /// A vector of documents matching the query criteria.
+    /// A vector of BSON documents matching the query criteria.
     ///
     /// # Errors
     ///
-    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or query fails.
-    pub async fn query(&self, query: Query) -> DocumentStoreResult<Vec<D>> {
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn query(&self, query: Query) -> DocumentStoreResult<Vec<Bson>> {
         Ok(self
             .backend
             .query_documents(query, &self.name())
+            .await?)
+    }
+
+    /// Queries documents and deserializes them into the types registered for this collection.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, limits, and offsets
+    /// * `registry` - The [`DocumentRegistry`] mapping this collection to document types
+    ///
+    /// # Returns
+    ///
+    /// A vector of type-erased documents, which can be downcast to their concrete types.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the query fails
+    /// or a document doesn't match any registered type.
+    pub async fn query_any(
+        &self,
+        query: Query,
+        registry: &DocumentRegistry,
+    ) -> DocumentStoreResult<Vec<Box<dyn AnyDocument>>> {
+        self.backend
+            .query_documents(query, self.name())
             .await?
             .into_iter()
-            .map(|doc| D::from_bson(doc))
-            .collect::<Result<Vec<D>, _>>()?)
+            .map(|doc| registry.deserialize(self.name(), doc))
+            .collect()
     }
-}
-
-#[derive(Debug)]
-pub struct DynTypedCollection<'a, D: Document> {
-    name: String,
-    backend: &'a dyn DynStoreBackend,
-    _marker: PhantomData<D>,
-}
 
-impl<'a, D: Document> DynTypedCollection<'a, D> {
-    pub(crate) fn new(name: String, backend: &'a dyn DynStoreBackend) -> Self {
-        Self { name, backend, _marker: PhantomData }
+    /// Counts the documents in the collection matching a structured query.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, limits, and offsets (sorting is ignored)
+    ///
+    /// # Returns
+    ///
+    /// The number of matching documents, counted by the backend without fetching them.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn count(&self, query: Query) -> DocumentStoreResult<usize> {
+        self.backend
+            .count_documents(query, self.name())
+            .await
     }
 
-    /// Returns the name of this collection.
-    pub fn name(&self) -> &str {
-        &self.name
+    /// Queries documents in the collection, yielding them as a stream.
+    ///
+    /// Results are read incrementally from the backend, so large result sets can be
+    /// processed without holding them in memory at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, limits, offsets and projection
+    ///
+    /// # Returns
+    ///
+    /// A [`DocumentStream`] of the matching BSON documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the query cannot
+    /// be started. Errors that occur while reading are yielded by the stream.
+    pub async fn query_stream(&self, query: Query) -> DocumentStoreResult<DocumentStream> {
+        self.backend
+            .query_stream(query, self.name())
+            .await
     }
 
-    /// Converts this typed collection to a different document type.
+    /// Retrieves the unique values of a field across the documents in the collection.
     ///
-    /// This method allows switching between different document types for the same collection.
-    pub fn with_type<T: Document>(&self) -> DynTypedCollection<'a, T> {
-        DynTypedCollection {
-            name: self.name.clone(),
-            backend: self.backend,
-            _marker: PhantomData,
-        }
+    /// Array values are unwound, so each element counts as a value of its own.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field to collect values of (dot notation is supported)
+    /// * `filter` - An optional filter restricting the documents considered
+    ///
+    /// # Returns
+    ///
+    /// The unique values of the field, in no particular order.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn distinct(
+        &self,
+        field: &str,
+        filter: Option<Expr>,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .distinct(field, filter, self.name())
+            .await
     }
 
-    /// Inserts new documents into the collection, overwriting existing documents with the same IDs.
+    /// Computes a grouped aggregation over the documents in the collection.
     ///
     /// # Arguments
     ///
-    /// * `documents` - A vector of documents to insert
+    /// * `aggregate` - The [`Aggregate`] describing the groups and accumulators
+    ///
+    /// # Returns
+    ///
+    /// One document per group, holding the group-by fields and the accumulator results.
     ///
     /// # Errors
     ///
-    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if serialization or insertion fails.
-    pub async fn insert(&self, documents: Vec<D>) -> DocumentStoreResult<()> {
-        Ok(self
-            .backend
-            .insert_documents(
-                documents
-                    .into_iter()
-                    .map(|d| {
-                        d.to_bson()
-                            .map(move |b| (d.id().clone(), b))
-                    })
-                    .collect::<Result<Vec<(Uuid, Bson)>, _>>()?,
-                &self.name(),
-            )
-            .await?)
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn aggregate(&self, aggregate: Aggregate) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .aggregate(aggregate, self.name())
+            .await
     }
 
-    /// Updates existing documents in the collection.
+    /// Describes how the backend would execute a query, without running it.
     ///
     /// # Arguments
     ///
-    /// * `documents` - A vector of documents with updated content
+    /// * `query` - The [`Query`] to explain
+    ///
+    /// # Returns
+    ///
+    /// A [`QueryPlan`] telling whether the query would scan the whole collection or use an
+    /// index, along with the backend's own description of the plan.
     ///
     /// # Errors
     ///
-    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if serialization or update fails.
-    pub async fn update(&self, documents: Vec<D>) -> DocumentStoreResult<()> {
-        Ok(self
-            .backend
-            .update_documents(
-                documents
-                    .into_iter()
-                    .map(|d| {
-                        d.to_bson()
-                            .map(move |b| (d.id().clone(), b))
-                    })
-                    .collect::<Result<Vec<(Uuid, Bson)>, _>>()?,
-                &self.name(),
-            )
-            .await?)
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn explain(&self, query: Query) -> DocumentStoreResult<QueryPlan> {
+        self.backend
+            .explain(query, self.name())
+            .await
     }
 
-    /// Deletes documents from the collection by their IDs.
+    /// Retrieves a single document from the collection by its ID.
     ///
     /// # Arguments
     ///
-    /// * `ids` - A vector of document IDs to delete (must implement `Into<Uuid>`)
+    /// * `id` - The ID of the document to retrieve (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// The BSON document, or `None` if no document with the ID exists.
     ///
     /// # Errors
     ///
     /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
-    pub async fn delete<U>(&self, ids: Vec<U>) -> DocumentStoreResult<()>
+    pub async fn get_one<U>(&self, id: U) -> DocumentStoreResult<Option<Bson>>
     where
         U: Into<Uuid> + Send + Sync + 'static,
     {
         Ok(self
-            .backend
-            .delete_documents(
-                ids.into_iter()
-                    .map(Into::into)
-                    .collect(),
-                &self.name(),
-            )
-            .await?)
+            .get(vec![id])
+            .await?
+            .into_iter()
+            .next())
     }
 
-    /// Retrieves documents from the collection by their IDs.
+    /// Queries the collection for the first document matching a structured query.
+    ///
+    /// Any limit set on the query is replaced with a limit of one.
     ///
     /// # Arguments
     ///
-    /// * `ids` - A vector of document IDs to retrieve (must implement `Into<Uuid>`)
+    /// * `query` - The [`Query`] specifying filters, sorting, and offsets
     ///
     /// # Returns
     ///
-    /// A vector of documents found. If a document ID doesn't exist, it is omitted from results.
+    /// The first matching BSON document, or `None` if nothing matches.
     ///
     /// # Errors
     ///
-    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or retrieval fails.
-    pub async fn get<U>(&self, ids: Vec<U>) -> DocumentStoreResult<Vec<D>>
-    where
-        U: Into<Uuid> + Send + Sync + 'static,
-    {
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn query_one(&self, query: Query) -> DocumentStoreResult<Option<Bson>> {
         Ok(self
-            .backend
-            .get_documents(
-                ids.into_iter()
-                    .map(Into::into)
-                    .collect(),
-                &self.name(),
-            )
+            .query(Query { limit: Some(1), ..query })
             .await?
             .into_iter()
-            .map(|doc| D::from_bson(doc))
-            .collect::<Result<Vec<D>, _>>()?)
+            .next())
     }
 
-    /// Queries documents in the collection using a structured query.
+    /// Queries the collection and deserializes each result into a projection type.
+    ///
+    /// Intended for queries built with `.project([...])`, where the returned documents only
+    /// contain a subset of fields. Use [`Bson`] as `P` to get the partial documents as-is.
     ///
     /// # Arguments
     ///
-    /// * `query` - The [`Query`] specifying filters, sorting, limits, and offsets
+    /// * `query` - The [`Query`] specifying filters, sorting, pagination and projection
     ///
     /// # Returns
     ///
-    /// A vector of documents matching the query criteria.
+    /// A vector of projected documents matching the query.
     ///
     /// # Errors
     ///
-    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or query fails.
-    pub async fn query(&self, query: Query) -> DocumentStoreResult<Vec<D>> {
-        Ok(self
-            .backend
-            .query_documents(query, &self.name())
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the query fails
+    /// or a document cannot be deserialized into `P`.
+    pub async fn query_projected<P>(&self, query: Query) -> DocumentStoreResult<Vec<P>>
+    where
+        P: DeserializeOwned,
+    {
+        self.backend
+            .query_documents(query, self.name())
             .await?
             .into_iter()
-            .map(|doc| D::from_bson(doc))
-            .collect::<Result<Vec<D>, _>>()?)
+            .map(|doc| Ok(deserialize_from_bson(doc)?))
+            .collect()
+    }
+}
+
+/// A dynamic (type-erased) collection with a reference to a backend trait object.
+///
+/// This struct provides access to a collection with explicit BSON document handling,
+/// similar to [`Collection`], but uses dynamic dispatch via trait objects for backend operations.
+/// This enables using different backend implementations at runtime without generic type parameters.
+///
+/// # Type Parameters
+///
+/// * `'a` - Lifetime of the backend trait object reference
+#[derive(Debug)]
+pub struct DynCollection<'a> {
+    name: String,
+    backend: &'a dyn DynStoreBackend,
+    get_chunk_size: usize,
+}
+
+impl<'a> DynCollection<'a> {
+    /// Creates a new dynamic collection reference (internal use).
+    pub(crate) fn new(name: String, backend: &'a dyn DynStoreBackend) -> Self {
+        Self {
+            name,
+            backend,
+            get_chunk_size: DEFAULT_GET_CHUNK_SIZE,
+        }
+    }
+
+    /// Returns the name of this collection.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sets the maximum number of IDs fetched per backend call when getting documents.
+    ///
+    /// Longer ID lists are split into several calls and their results concatenated, keeping
+    /// each request within the backend's limits. Defaults to [`DEFAULT_GET_CHUNK_SIZE`].
+    pub fn with_get_chunk_size(mut self, size: usize) -> Self {
+        self.get_chunk_size = size.max(1);
+        self
+    }
+
+    /// Inserts new documents into the collection.
+    ///
+    /// Fails without writing anything if any of the documents already exists
+    /// ([`InsertPolicy::ErrorOnConflict`]).
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of (ID, BSON document) pairs to insert
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::DocumentAlreadyExists`](crate::error::DocumentStoreError::DocumentAlreadyExists)
+    /// if a document with the same ID exists, or another
+    /// [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn insert(&self, documents: Vec<(Uuid, Bson)>) -> DocumentStoreResult<()> {
+        self.insert_with_policy(documents, InsertPolicy::ErrorOnConflict)
+            .await
+            .map(|_| ())
+    }
+
+    /// Inserts documents into the collection, handling existing documents according to a policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of (ID, BSON document) pairs to insert
+    /// * `policy` - How to handle documents whose ID already exists
+    ///
+    /// # Returns
+    ///
+    /// A [`WriteReport`] with the existing documents as matched, the replaced ones that changed
+    /// as modified and the new ones as upserted.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn insert_with_policy(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.backend
+            .insert_documents(documents, self.name(), policy)
+            .await
+    }
+
+    /// Inserts new documents into the collection that expire after a time-to-live.
+    ///
+    /// Sets `field` of each document to the current time plus `ttl`. The documents are deleted
+    /// once expired only if the collection expires documents on that field (see the
+    /// [`expiry`](crate::expiry) module). Like [`insert`](Self::insert), fails without writing
+    /// anything if any of the documents already exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of (ID, BSON document) pairs to insert
+    /// * `field` - The field to store the expiry in
+    /// * `ttl` - How long the documents live
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::InvalidDocument`] if a value isn't a BSON document, or
+    /// another [`DocumentStoreError`] if the insertion fails.
+    pub async fn insert_with_ttl(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        field: &str,
+        ttl: Duration,
+    ) -> DocumentStoreResult<()> {
+        self.insert(with_expiry(documents, field, Bson::DateTime(expires_at(ttl)))?)
+            .await
+    }
+
+    /// Updates existing documents in the collection.
+    ///
+    /// Fails without writing anything if any of the documents doesn't exist
+    /// ([`MissingDocumentPolicy::Error`]).
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of (ID, BSON document) pairs with updated content
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn update(&self, documents: Vec<(Uuid, Bson)>) -> DocumentStoreResult<()> {
+        self.update_with_policy(documents, MissingDocumentPolicy::Error)
+            .await
+            .map(|_| ())
+    }
+
+    /// Updates documents in the collection, handling missing documents according to a policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of (ID, BSON document) pairs with updated content
+    /// * `policy` - How to handle documents that don't exist
+    ///
+    /// # Returns
+    ///
+    /// A [`WriteReport`] with the matched, modified and inserted counts.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn update_with_policy(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.backend
+            .update_documents(documents, self.name(), policy)
+            .await
     }
+
+    /// Updates existing documents only if the stored documents are still at the expected
+    /// versions, for optimistic concurrency control.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of (ID, BSON document, expected version) triples, each
+    ///   document carrying its new version
+    /// * `version_field` - The field holding the version
+    ///
+    /// # Returns
+    ///
+    /// A [`WriteReport`] with the matched and modified counts.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::VersionConflict`] if a stored document is at another
+    /// version, or another [`DocumentStoreError`] if the operation fails.
+    pub async fn update_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.backend
+            .update_documents_if_version(documents, version_field, self.name())
+            .await
+    }
+
+    /// Updates existing documents only if they are still at the given revisions, as read
+    /// with [`get_versioned`](Self::get_versioned).
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of (ID, BSON document, revision) triples
+    ///
+    /// # Returns
+    ///
+    /// A [`WriteReport`] with the matched and modified counts.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::PreconditionFailed`] if a document doesn't exist or is at
+    /// another revision, or another [`DocumentStoreError`] if the operation fails.
+    pub async fn update_if_match(
+        &self,
+        documents: Vec<(Uuid, Bson, String)>,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.backend
+            .update_documents_if_match(documents, self.name())
+            .await
+    }
+
+    /// Inserts documents into the collection, replacing any existing documents with the same IDs.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of (ID, BSON document) pairs to insert or replace
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn upsert(&self, documents: Vec<(Uuid, Bson)>) -> DocumentStoreResult<()> {
+        self.backend
+            .upsert_documents(documents, self.name())
+            .await
+    }
+
+    /// Deletes documents from the collection by their IDs.
+    ///
+    /// IDs that don't exist are skipped, so deleting the same documents twice is not an error.
+    /// Use [`delete_strict`](Self::delete_strict) to fail when documents are missing.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - A vector of document IDs to delete (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// The number of deleted documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn delete<U>(&self, ids: Vec<U>) -> DocumentStoreResult<usize>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        self.backend
+            .delete_documents(
+                ids.into_iter()
+                    .map(Into::into)
+                    .collect(),
+                self.name(),
+            )
+            .await
+    }
+
+    /// Deletes documents from the collection by their IDs, failing if any of them is missing.
+    ///
+    /// Documents that do exist are still deleted; the error reports how many were missing.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - A vector of document IDs to delete (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// The number of deleted documents, which equals the number of distinct IDs given.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::DocumentNotFound`] if fewer documents were deleted
+    /// than requested, or another [`DocumentStoreError`] if the operation fails.
+    pub async fn delete_strict<U>(&self, ids: Vec<U>) -> DocumentStoreResult<usize>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        let mut ids = ids
+            .into_iter()
+            .map(Into::into)
+            .collect::<Vec<Uuid>>();
+        ids.sort_unstable_by_key(|id| id.bytes());
+        ids.dedup();
+
+        let requested = ids.len();
+        let deleted = self
+            .backend
+            .delete_documents(ids, self.name())
+            .await?;
+
+        if deleted < requested {
+            return Err(DocumentStoreError::DocumentNotFound(
+                format!("{} of {} requested documents", requested - deleted, requested),
+                self.name().to_string(),
+            ));
+        }
+
+        Ok(deleted)
+    }
+
+    /// Deletes documents from the collection only if they are still at the given revisions,
+    /// as read with `get_versioned`.
+    ///
+    /// # Arguments
+    ///
+    /// * `revisions` - A vector of (ID, revision) pairs, such as an ID and the `If-Match`
+    ///   header of a request converted with
+    ///   [`revision_from_etag`](crate::versioned::revision_from_etag)
+    ///
+    /// # Returns
+    ///
+    /// The number of deleted documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::PreconditionFailed`] if a document doesn't exist or is at
+    /// another revision, or another [`DocumentStoreError`] if the operation fails.
+    pub async fn delete_if_match(
+        &self,
+        revisions: Vec<(Uuid, String)>,
+    ) -> DocumentStoreResult<usize> {
+        self.backend
+            .delete_documents_if_match(revisions, self.name())
+            .await
+    }
+
+    /// Applies a partial update to every document in the collection matching a filter expression.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The [`Expr`] selecting the documents to update
+    /// * `update` - The [`Update`] operations to apply
+    ///
+    /// # Returns
+    ///
+    /// A [`WriteReport`] with the number of matched and modified documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn update_where(
+        &self,
+        filter: Expr,
+        update: Update,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.backend
+            .update_by_query(filter, update, self.name())
+            .await
+    }
+
+    /// Deletes every document in the collection matching a filter expression.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The [`Expr`] selecting the documents to delete
+    ///
+    /// # Returns
+    ///
+    /// The number of deleted documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn delete_where(&self, filter: Expr) -> DocumentStoreResult<usize> {
+        self.backend
+            .delete_by_query(filter, self.name())
+            .await
+    }
+
+    /// Applies a partial update to the first document matching a filter expression and
+    /// returns the document as it was before the update, as a single atomic operation.
+    ///
+    /// Of several callers racing for the same documents, each gets a different one, so this
+    /// can claim work such as a pending job.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The [`Expr`] selecting the documents to consider
+    /// * `update` - The [`Update`] operations to apply
+    ///
+    /// # Returns
+    ///
+    /// The document before the update, or `None` if no document matches.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        update: Update,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        self.backend
+            .find_one_and_update(filter, Vec::new(), update, ReturnDocument::Before, self.name())
+            .await
+    }
+
+    /// Deletes the first document matching a filter expression and returns it, as a single
+    /// atomic operation.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The [`Expr`] selecting the documents to consider
+    ///
+    /// # Returns
+    ///
+    /// The deleted document, or `None` if no document matches.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn find_one_and_delete(&self, filter: Expr) -> DocumentStoreResult<Option<Bson>> {
+        self.backend
+            .find_one_and_delete(filter, Vec::new(), self.name())
+            .await
+    }
+
+    /// Retrieves documents from the collection by their IDs.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - A vector of document IDs to retrieve (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// A vector of BSON documents found. If a document ID doesn't exist, it is omitted from results.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn get<U>(&self, ids: Vec<U>) -> DocumentStoreResult<Vec<Bson>>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        get_in_chunks(
+            ids.into_iter()
+                .map(Into::into)
+                .collect(),
+            self.get_chunk_size,
+            |ids| {
+                self.backend
+                    .get_documents(ids, self.name())
+            },
+        )
+        .await
+    }
+
+    /// Retrieves documents from the collection by their IDs, along with their revisions.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - A vector of document IDs to retrieve (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// A vector of the BSON documents found, each with its revision.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn get_versioned<U>(&self, ids: Vec<U>) -> DocumentStoreResult<Vec<Versioned<Bson>>>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        self.get(ids)
+            .await?
+            .into_iter()
+            .map(|doc| document_revision(&doc).map(|revision| Versioned::new(doc, revision)))
+            .collect()
+    }
+
+    /// Queries documents in the collection using a structured query.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, limits, and offsets
+    ///
+    /// # Returns
+    ///
+    /// A vector of BSON documents matching the query criteria.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn query(&self, query: Query) -> DocumentStoreResult<Vec<Bson>> {
+        Ok(self
+            .backend
+            .query_documents(query, &self.name())
+            .await?)
+    }
+
+    /// Queries documents and deserializes them into the types registered for this collection.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, limits, and offsets
+    /// * `registry` - The [`DocumentRegistry`] mapping this collection to document types
+    ///
+    /// # Returns
+    ///
+    /// A vector of type-erased documents, which can be downcast to their concrete types.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the query fails
+    /// or a document doesn't match any registered type.
+    pub async fn query_any(
+        &self,
+        query: Query,
+        registry: &DocumentRegistry,
+    ) -> DocumentStoreResult<Vec<Box<dyn AnyDocument>>> {
+        self.backend
+            .query_documents(query, self.name())
+            .await?
+            .into_iter()
+            .map(|doc| registry.deserialize(self.name(), doc))
+            .collect()
+    }
+
+    /// Counts the documents in the collection matching a structured query.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, limits, and offsets (sorting is ignored)
+    ///
+    /// # Returns
+    ///
+    /// The number of matching documents, counted by the backend without fetching them.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn count(&self, query: Query) -> DocumentStoreResult<usize> {
+        self.backend
+            .count_documents(query, self.name())
+            .await
+    }
+
+    /// Queries documents in the collection, yielding them as a stream.
+    ///
+    /// Results are read incrementally from the backend, so large result sets can be
+    /// processed without holding them in memory at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, limits, offsets and projection
+    ///
+    /// # Returns
+    ///
+    /// A [`DocumentStream`] of the matching BSON documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the query cannot
+    /// be started. Errors that occur while reading are yielded by the stream.
+    pub async fn query_stream(&self, query: Query) -> DocumentStoreResult<DocumentStream> {
+        self.backend
+            .query_stream(query, self.name())
+            .await
+    }
+
+    /// Retrieves the unique values of a field across the documents in the collection.
+    ///
+    /// Array values are unwound, so each element counts as a value of its own.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field to collect values of (dot notation is supported)
+    /// * `filter` - An optional filter restricting the documents considered
+    ///
+    /// # Returns
+    ///
+    /// The unique values of the field, in no particular order.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn distinct(
+        &self,
+        field: &str,
+        filter: Option<Expr>,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .distinct(field, filter, self.name())
+            .await
+    }
+
+    /// Computes a grouped aggregation over the documents in the collection.
+    ///
+    /// # Arguments
+    ///
+    /// * `aggregate` - The [`Aggregate`] describing the groups and accumulators
+    ///
+    /// # Returns
+    ///
+    /// One document per group, holding the group-by fields and the accumulator results.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn aggregate(&self, aggregate: Aggregate) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .aggregate(aggregate, self.name())
+            .await
+    }
+
+    /// Describes how the backend would execute a query, without running it.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] to explain
+    ///
+    /// # Returns
+    ///
+    /// A [`QueryPlan`] telling whether the query would scan the whole collection or use an
+    /// index, along with the backend's own description of the plan.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn explain(&self, query: Query) -> DocumentStoreResult<QueryPlan> {
+        self.backend
+            .explain(query, self.name())
+            .await
+    }
+
+    /// Retrieves a single document from the collection by its ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the document to retrieve (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// The BSON document, or `None` if no document with the ID exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn get_one<U>(&self, id: U) -> DocumentStoreResult<Option<Bson>>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        Ok(self
+            .get(vec![id])
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    /// Queries the collection for the first document matching a structured query.
+    ///
+    /// Any limit set on the query is replaced with a limit of one.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, and offsets
+    ///
+    /// # Returns
+    ///
+    /// The first matching BSON document, or `None` if nothing matches.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn query_one(&self, query: Query) -> DocumentStoreResult<Option<Bson>> {
+        Ok(self
+            .query(Query { limit: Some(1), ..query })
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    /// Queries the collection and deserializes each result into a projection type.
+    ///
+    /// Intended for queries built with `.project([...])`, where the returned documents only
+    /// contain a subset of fields. Use [`Bson`] as `P` to get the partial documents as-is.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, pagination and projection
+    ///
+    /// # Returns
+    ///
+    /// A vector of projected documents matching the query.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the query fails
+    /// or a document cannot be deserialized into `P`.
+    pub async fn query_projected<P>(&self, query: Query) -> DocumentStoreResult<Vec<P>>
+    where
+        P: DeserializeOwned,
+    {
+        self.backend
+            .query_documents(query, self.name())
+            .await?
+            .into_iter()
+            .map(|doc| Ok(deserialize_from_bson(doc)?))
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct TypedCollection<'a, B: StoreBackend, D: Document> {
+    name: String,
+    backend: &'a B,
+    get_chunk_size: usize,
+    validators: Validators<D>,
+    _marker: PhantomData<D>,
+}
+
+impl<'a, B: StoreBackend, D: Document> TypedCollection<'a, B, D> {
+    pub(crate) fn new(name: String, backend: &'a B) -> Self {
+        Self {
+            name,
+            backend,
+            get_chunk_size: DEFAULT_GET_CHUNK_SIZE,
+            validators: Validators::default(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the name of this collection.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sets the maximum number of IDs fetched per backend call when getting documents.
+    ///
+    /// Longer ID lists are split into several calls and their results concatenated, keeping
+    /// each request within the backend's limits. Defaults to [`DEFAULT_GET_CHUNK_SIZE`].
+    pub fn with_get_chunk_size(mut self, size: usize) -> Self {
+        self.get_chunk_size = size.max(1);
+        self
+    }
+
+    /// Attaches a validator run on every document the collection inserts, updates or upserts.
+    ///
+    /// Validators run in the order they're attached, before anything is written; a write with
+    /// a violation fails with [`DocumentStoreError::Validation`], listing the violations of all
+    /// its documents. Partial updates, such as [`update_where`](Self::update_where) and
+    /// [`find_one_and_update`](Self::find_one_and_update), are applied by the backend and
+    /// aren't validated. See the [`validate`](crate::validate) module.
+    pub fn with_validator(mut self, validator: impl Validator<D> + 'static) -> Self {
+        self.validators.push(validator);
+        self
+    }
+
+    /// Converts this typed collection to a different document type.
+    ///
+    /// This method allows switching between different document types for the same collection.
+    pub fn with_type<T: Document>(&self) -> TypedCollection<'a, B, T> {
+        TypedCollection {
+            name: self.name.clone(),
+            backend: self.backend,
+            get_chunk_size: self.get_chunk_size,
+            validators: Validators::default(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Inserts new documents into the collection.
+    ///
+    /// Fails without writing anything if any of the documents already exists
+    /// ([`InsertPolicy::ErrorOnConflict`]).
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of documents to insert
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::DocumentAlreadyExists`](crate::error::DocumentStoreError::DocumentAlreadyExists)
+    /// if a document with the same ID exists, or another
+    /// [`DocumentStoreError`](crate::error::DocumentStoreError) if serialization or insertion fails.
+    pub async fn insert(&self, documents: Vec<D>) -> DocumentStoreResult<()> {
+        self.insert_with_policy(documents, InsertPolicy::ErrorOnConflict)
+            .await
+            .map(|_| ())
+    }
+
+    /// Inserts documents into the collection, handling existing documents according to a policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of documents to insert
+    /// * `policy` - How to handle documents whose ID already exists
+    ///
+    /// # Returns
+    ///
+    /// A [`WriteReport`] with the existing documents as matched, the replaced ones that changed
+    /// as modified and the new ones as upserted.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if serialization or insertion fails.
+    pub async fn insert_with_policy(
+        &self,
+        documents: Vec<D>,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.validators.check(&documents)?;
+
+        self.backend
+            .insert_documents(
+                documents
+                    .into_iter()
+                    .map(|d| {
+                        d.to_bson_encoded(self.backend.encoding())
+                            .map(move |b| (*d.id(), b))
+                    })
+                    .collect::<Result<Vec<(Uuid, Bson)>, _>>()?,
+                self.name(),
+                policy,
+            )
+            .await
+    }
+
+    /// Inserts new documents into the collection that expire after a time-to-live.
+    ///
+    /// Sets the [`Document::expires_field`] of each document to the current time plus `ttl`,
+    /// replacing the value it was serialized with. The documents are deleted once expired if
+    /// the collection expires documents on that field, which
+    /// [`ensure_indexes`](crate::store::DocumentStore::ensure_indexes) enables (see the
+    /// [`expiry`](crate::expiry) module). Like [`insert`](Self::insert), fails without writing
+    /// anything if any of the documents already exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of documents to insert
+    /// * `ttl` - How long the documents live
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::InvalidDocument`] if `D` has no expiry field, or another
+    /// [`DocumentStoreError`] if serialization or insertion fails.
+    pub async fn insert_with_ttl(
+        &self,
+        documents: Vec<D>,
+        ttl: Duration,
+    ) -> DocumentStoreResult<()> {
+        self.validators.check(&documents)?;
+
+        let field = expires_field::<D>()?;
+        let expiry = self
+            .backend
+            .encoding()
+            .encode_value(Bson::DateTime(expires_at(ttl)));
+        let documents = documents
+            .into_iter()
+            .map(|d| {
+                d.to_bson_encoded(self.backend.encoding())
+                    .map(move |b| (*d.id(), b))
+            })
+            .collect::<Result<Vec<(Uuid, Bson)>, _>>()?;
+
+        self.backend
+            .insert_documents(
+                with_expiry(documents, field, expiry)?,
+                self.name(),
+                InsertPolicy::ErrorOnConflict,
+            )
+            .await
+            .map(|_| ())
+    }
+
+    /// Updates existing documents in the collection.
+    ///
+    /// Fails without writing anything if any of the documents doesn't exist
+    /// ([`MissingDocumentPolicy::Error`]).
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of documents with updated content
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if serialization or update fails.
+    pub async fn update(&self, documents: Vec<D>) -> DocumentStoreResult<()> {
+        self.update_with_policy(documents, MissingDocumentPolicy::Error)
+            .await
+            .map(|_| ())
+    }
+
+    /// Updates documents in the collection, handling missing documents according to a policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of documents with updated content
+    /// * `policy` - How to handle documents that don't exist
+    ///
+    /// # Returns
+    ///
+    /// A [`WriteReport`] with the matched, modified and inserted counts.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if serialization or update fails.
+    pub async fn update_with_policy(
+        &self,
+        documents: Vec<D>,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.validators.check(&documents)?;
+
+        self.backend
+            .update_documents(
+                documents
+                    .into_iter()
+                    .map(|d| {
+                        d.to_bson_encoded(self.backend.encoding())
+                            .map(move |b| (*d.id(), b))
+                    })
+                    .collect::<Result<Vec<(Uuid, Bson)>, _>>()?,
+                self.name(),
+                policy,
+            )
+            .await
+    }
+
+    /// Updates existing documents only if the stored documents are still at their
+    /// [`Document::version`], incrementing the version of each.
+    ///
+    /// Use this instead of [`update`](Self::update) when several writers may update the same
+    /// documents: a writer updating from a stale read fails instead of overwriting the
+    /// changes made since.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of documents with updated content, at the version they were read at
+    ///
+    /// # Returns
+    ///
+    /// The documents at their new versions.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::VersionConflict`] if a stored document is at another
+    /// version, [`DocumentStoreError::DocumentNotFound`] if it doesn't exist and
+    /// [`DocumentStoreError::InvalidDocument`] if `D` has no version field.
+    pub async fn update_if_version(&self, mut documents: Vec<D>) -> DocumentStoreResult<Vec<D>> {
+        self.validators.check(&documents)?;
+
+        let version_field = version_field::<D>()?;
+        let versioned = next_versions(&mut documents, self.backend.encoding())?;
+
+        self.backend
+            .update_documents_if_version(versioned, version_field, self.name())
+            .await?;
+
+        Ok(documents)
+    }
+
+    /// Updates existing documents only if they are still at the revisions they were read at
+    /// with [`get_versioned`](Self::get_versioned).
+    ///
+    /// Read the documents again for their new revisions.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of documents with updated content, along with the revision
+    ///   they were read at
+    ///
+    /// # Returns
+    ///
+    /// A [`WriteReport`] with the matched and modified counts.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::PreconditionFailed`] if a document doesn't exist or is at
+    /// another revision, or another [`DocumentStoreError`] if serialization or the update fails.
+    pub async fn update_if_match(
+        &self,
+        documents: Vec<Versioned<D>>,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.validators.check(
+            documents
+                .iter()
+                .map(|versioned| &versioned.document),
+        )?;
+
+        self.backend
+            .update_documents_if_match(
+                documents
+                    .into_iter()
+                    .map(|versioned| {
+                        versioned
+                            .document
+                            .to_bson_encoded(self.backend.encoding())
+                            .map(|bson| (*versioned.document.id(), bson, versioned.revision))
+                    })
+                    .collect::<DocumentStoreResult<Vec<_>>>()?,
+                self.name(),
+            )
+            .await
+    }
+
+    /// Inserts documents into the collection, replacing any existing documents with the same IDs.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of documents to insert or replace
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if serialization or the write fails.
+    pub async fn upsert(&self, documents: Vec<D>) -> DocumentStoreResult<()> {
+        self.validators.check(&documents)?;
+
+        self.backend
+            .upsert_documents(
+                documents
+                    .into_iter()
+                    .map(|d| {
+                        d.to_bson_encoded(self.backend.encoding())
+                            .map(move |b| (*d.id(), b))
+                    })
+                    .collect::<Result<Vec<(Uuid, Bson)>, _>>()?,
+                self.name(),
+            )
+            .await
+    }
+
+    /// Deletes documents from the collection by their IDs.
+    ///
+    /// IDs that don't exist are skipped, so deleting the same documents twice is not an error.
+    /// Use [`delete_strict`](Self::delete_strict) to fail when documents are missing.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - A vector of document IDs to delete (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// The number of deleted documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn delete<U>(&self, ids: Vec<U>) -> DocumentStoreResult<usize>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        self.backend
+            .delete_documents(
+                ids.into_iter()
+                    .map(Into::into)
+                    .collect(),
+                self.name(),
+            )
+            .await
+    }
+
+    /// Deletes documents from the collection by their IDs, failing if any of them is missing.
+    ///
+    /// Documents that do exist are still deleted; the error reports how many were missing.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - A vector of document IDs to delete (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// The number of deleted documents, which equals the number of distinct IDs given.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::DocumentNotFound`] if fewer documents were deleted
+    /// than requested, or another [`DocumentStoreError`] if the operation fails.
+    pub async fn delete_strict<U>(&self, ids: Vec<U>) -> DocumentStoreResult<usize>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        let mut ids = ids
+            .into_iter()
+            .map(Into::into)
+            .collect::<Vec<Uuid>>();
+        ids.sort_unstable_by_key(|id| id.bytes());
+        ids.dedup();
+
+        let requested = ids.len();
+        let deleted = self
+            .backend
+            .delete_documents(ids, self.name())
+            .await?;
+
+        if deleted < requested {
+            return Err(DocumentStoreError::DocumentNotFound(
+                format!("{} of {} requested documents", requested - deleted, requested),
+                self.name().to_string(),
+            ));
+        }
+
+        Ok(deleted)
+    }
+
+    /// Deletes documents from the collection only if they are still at the given revisions,
+    /// as read with `get_versioned`.
+    ///
+    /// # Arguments
+    ///
+    /// * `revisions` - A vector of (ID, revision) pairs, such as an ID and the `If-Match`
+    ///   header of a request converted with
+    ///   [`revision_from_etag`](crate::versioned::revision_from_etag)
+    ///
+    /// # Returns
+    ///
+    /// The number of deleted documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::PreconditionFailed`] if a document doesn't exist or is at
+    /// another revision, or another [`DocumentStoreError`] if the operation fails.
+    pub async fn delete_if_match(
+        &self,
+        revisions: Vec<(Uuid, String)>,
+    ) -> DocumentStoreResult<usize> {
+        self.backend
+            .delete_documents_if_match(revisions, self.name())
+            .await
+    }
+
+    /// Soft-deletes documents from the collection by their IDs.
+    ///
+    /// Sets the [`Document::deleted_field`] of each document to the current time, after which
+    /// reads leave the document out until it's [restored](Self::restore). Documents already
+    /// soft-deleted keep their deletion time. Each document is only written if it hasn't
+    /// changed since being read, so a concurrent update isn't overwritten.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - A vector of document IDs to soft-delete (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// The number of soft-deleted documents, leaving out missing and already soft-deleted ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::InvalidDocument`] if `D` has no deleted field,
+    /// [`DocumentStoreError::PreconditionFailed`] if a document changed while being
+    /// soft-deleted, or another [`DocumentStoreError`] if the operation fails.
+    pub async fn soft_delete<U>(&self, ids: Vec<U>) -> DocumentStoreResult<usize>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        let field = deleted_field::<D>()?;
+        let deleted_at = self
+            .backend
+            .encoding()
+            .encode_value(Bson::DateTime(bson::DateTime::now()));
+        let documents = get_in_chunks(
+            ids.into_iter()
+                .map(Into::into)
+                .collect(),
+            self.get_chunk_size,
+            |ids| {
+                self.backend
+                    .get_documents(ids, self.name())
+            },
+        )
+        .await?
+        .into_iter()
+        .filter(|doc| !is_deleted::<D>(doc))
+        .collect();
+        let documents = with_tombstone::<D>(documents, field, deleted_at, self.backend.encoding())?;
+
+        if documents.is_empty() {
+            return Ok(0);
+        }
+
+        self.backend
+            .update_documents_if_match(documents, self.name())
+            .await
+            .map(|report| report.matched)
+    }
+
+    /// Restores soft-deleted documents by their IDs, clearing their [`Document::deleted_field`].
+    ///
+    /// Like [`soft_delete`](Self::soft_delete), each document is only written if it hasn't
+    /// changed since being read.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - A vector of document IDs to restore (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// The number of restored documents, leaving out missing and not soft-deleted ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::InvalidDocument`] if `D` has no deleted field,
+    /// [`DocumentStoreError::PreconditionFailed`] if a document changed while being restored,
+    /// or another [`DocumentStoreError`] if the operation fails.
+    pub async fn restore<U>(&self, ids: Vec<U>) -> DocumentStoreResult<usize>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        let field = deleted_field::<D>()?;
+        let documents = get_in_chunks(
+            ids.into_iter()
+                .map(Into::into)
+                .collect(),
+            self.get_chunk_size,
+            |ids| {
+                self.backend
+                    .get_documents(ids, self.name())
+            },
+        )
+        .await?
+        .into_iter()
+        .filter(is_deleted::<D>)
+        .collect();
+        let documents = with_tombstone::<D>(documents, field, Bson::Null, self.backend.encoding())?;
+
+        if documents.is_empty() {
+            return Ok(0);
+        }
+
+        self.backend
+            .update_documents_if_match(documents, self.name())
+            .await
+            .map(|report| report.matched)
+    }
+
+    /// Applies a partial update to every document in the collection matching a filter expression.
+    ///
+    /// Soft-deleted documents are left out, like reads leave them out. The backend applies the
+    /// update to the stored documents, so the collection's validators don't run.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The [`Expr`] selecting the documents to update
+    /// * `update` - The [`Update`] operations to apply
+    ///
+    /// # Returns
+    ///
+    /// A [`WriteReport`] with the number of matched and modified documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn update_where(
+        &self,
+        filter: Expr,
+        update: Update,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.backend
+            .update_by_query(
+                self.backend.encoding().encode_filter(
+                    live_filter::<D>(Some(filter)).unwrap_or_else(|| Expr::And(Vec::new())),
+                ),
+                self.backend
+                    .encoding()
+                    .encode_update(update),
+                self.name(),
+            )
+            .await
+    }
+
+    /// Deletes every document in the collection matching a filter expression.
+    ///
+    /// Unlike reads and [`update_where`](Self::update_where), this matches soft-deleted
+    /// documents too, and removes them for good. To leave them out, add a condition on the
+    /// [`Document::deleted_field`] to the filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The [`Expr`] selecting the documents to delete
+    ///
+    /// # Returns
+    ///
+    /// The number of deleted documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn delete_where(&self, filter: Expr) -> DocumentStoreResult<usize> {
+        self.backend
+            .delete_by_query(
+                self.backend
+                    .encoding()
+                    .encode_filter(filter),
+                self.name(),
+            )
+            .await
+    }
+
+    /// Applies a partial update to the first document matching a filter expression and
+    /// returns the document as it was before the update, as a single atomic operation.
+    ///
+    /// Of several callers racing for the same documents, each gets a different one, so this
+    /// can claim work such as a pending job. To pick the document by sort order or return it
+    /// as updated, use [`find`](Self::find) with [`Find::update_one`]. Like
+    /// [`update_where`](Self::update_where), the update isn't validated.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The [`Expr`] selecting the documents to consider
+    /// * `update` - The [`Update`] operations to apply
+    ///
+    /// # Returns
+    ///
+    /// The document before the update, or `None` if no document matches.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or the update fails.
+    pub async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        update: Update,
+    ) -> DocumentStoreResult<Option<D>> {
+        self.find()
+            .filter(filter)
+            .update_one(update, ReturnDocument::Before)
+            .await
+    }
+
+    /// Deletes the first document matching a filter expression and returns it, as a single
+    /// atomic operation.
+    ///
+    /// To pick the document by sort order, use [`find`](Self::find) with
+    /// [`Find::delete_one`].
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The [`Expr`] selecting the documents to consider
+    ///
+    /// # Returns
+    ///
+    /// The deleted document, or `None` if no document matches.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or the delete fails.
+    pub async fn find_one_and_delete(&self, filter: Expr) -> DocumentStoreResult<Option<D>> {
+        self.find()
+            .filter(filter)
+            .delete_one()
+            .await
+    }
+
+    /// Retrieves documents from the collection by their IDs.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - A vector of document IDs to retrieve (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// A vector of documents found. If a document ID doesn't exist, it is omitted from results.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or retrieval fails.
+    pub async fn get<U>(&self, ids: Vec<U>) -> DocumentStoreResult<Vec<D>>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        get_in_chunks(
+            ids.into_iter()
+                .map(Into::into)
+                .collect(),
+            self.get_chunk_size,
+            |ids| {
+                self.backend
+                    .get_documents(ids, self.name())
+            },
+        )
+        .await?
+        .into_iter()
+        .filter(|doc| !is_deleted::<D>(doc))
+        .map(|doc| D::from_bson_encoded(doc, self.backend.encoding()))
+        .collect()
+    }
+
+    /// Queries documents in the collection using a structured query.
+    ///
+    /// Soft-deleted documents are left out, see [`Document::deleted_field`].
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, limits, and offsets
+    ///
+    /// # Returns
+    ///
+    /// A vector of documents matching the query criteria.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or query fails.
+    pub async fn query(&self, query: Query) -> DocumentStoreResult<Vec<D>> {
+        self.query_with_deleted(live_query::<D>(query))
+            .await
+    }
+
+    /// Queries documents in the collection using a structured query, including soft-deleted
+    /// documents.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, limits, and offsets
+    ///
+    /// # Returns
+    ///
+    /// A vector of documents matching the query criteria, whether soft-deleted or not.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or query fails.
+    pub async fn query_with_deleted(&self, query: Query) -> DocumentStoreResult<Vec<D>> {
+        Ok(self
+            .backend
+            .query_documents(
+                self.backend
+                    .encoding()
+                    .encode_query(query),
+                &self.name(),
+            )
+            .await?
+            .into_iter()
+            .map(|doc| D::from_bson_encoded(doc, self.backend.encoding()))
+            .collect::<Result<Vec<D>, _>>()?)
+    }
+
+    /// Queries the collection, keeping the matching documents as raw BSON.
+    ///
+    /// Use this when only a few fields of each document are needed: [`RawDoc::get_as`] reads
+    /// single fields cheaply, and [`RawDoc::parse`] deserializes a document only when asked.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, limits, and offsets
+    ///
+    /// # Returns
+    ///
+    /// The matching documents as [`RawDoc`]s.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the query fails.
+    pub async fn query_raw(&self, query: Query) -> DocumentStoreResult<Vec<RawDoc<D>>> {
+        Ok(self
+            .backend
+            .query_raw_documents(
+                self.backend
+                    .encoding()
+                    .encode_query(live_query::<D>(query)),
+                self.name(),
+            )
+            .await?
+            .into_iter()
+            .map(RawDoc::new)
+            .collect())
+    }
+
+    /// Starts a fluent query on the collection, executed when awaited.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let adults = users
+    ///     .find()
+    ///     .filter(Filter::gt("age", 18))
+    ///     .sort_desc("created_at")
+    ///     .limit(10)
+    ///     .await?;
+    /// ```
+    pub fn find(&self) -> Find<'_, Self> {
+        Find::new(self)
+    }
+
+    /// Counts the documents in the collection matching a structured query.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, limits, and offsets (sorting is ignored)
+    ///
+    /// # Returns
+    ///
+    /// The number of matching documents, counted by the backend without fetching them.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn count(&self, query: Query) -> DocumentStoreResult<usize> {
+        self.backend
+            .count_documents(
+                self.backend
+                    .encoding()
+                    .encode_query(live_query::<D>(query)),
+                self.name(),
+            )
+            .await
+    }
+
+    /// Retrieves one page of the documents matching a query, along with their total count.
+    ///
+    /// The page's limit and offset replace those of the query; the documents and the total
+    /// are fetched concurrently.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters and sorting
+    /// * `params` - The [`PaginationParams`] selecting the page
+    ///
+    /// # Returns
+    ///
+    /// A [`Page`] holding the page's documents, the total count of matching documents and the
+    /// numbers of the neighbouring pages.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or query fails.
+    pub async fn query_page(
+        &self,
+        query: Query,
+        params: PaginationParams,
+    ) -> DocumentStoreResult<Page<D>> {
+        let count_query = Query {
+            limit: None,
+            offset: None,
+            ..query.clone()
+        };
+        let page_query = Query {
+            limit: Some(params.per_page),
+            offset: Some(params.offset()),
+            ..query
+        };
+
+        let (items, count) = futures::try_join!(self.query(page_query), self.count(count_query))?;
+
+        Ok(params.to_page(items, count))
+    }
+
+    /// Queries a page of documents together with its total, next cursor and facet counts.
+    ///
+    /// The request's cursor and limit replace the offset and limit of the query. The page,
+    /// the total and each facet are fetched concurrently.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters and sorting
+    /// * `request` - The [`PageRequest`] selecting the page and its metadata
+    ///
+    /// # Returns
+    ///
+    /// A [`QueryPage`] holding the page's documents, the total if requested, the cursor of
+    /// the next page and the counts of each requested facet.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization,
+    /// a query or an aggregation fails.
+    pub async fn query_paged(
+        &self,
+        query: Query,
+        request: PageRequest,
+    ) -> DocumentStoreResult<QueryPage<D>> {
+        let total = async {
+            if request.includes_total() {
+                self.count(request.total_query(&query))
+                    .await
+                    .map(Some)
+            } else {
+                Ok(None)
+            }
+        };
+        let facets = futures::future::try_join_all(
+            request
+                .facets()
+                .iter()
+                .map(|field| async {
+                    self.aggregate(request.facet_aggregate(&query, field))
+                        .await
+                        .map(|rows| (field.clone(), rows))
+                }),
+        );
+
+        let (items, total, facets) =
+            futures::try_join!(self.query(request.page_query(&query)), total, facets)?;
+
+        Ok(request.to_page(items, total, facets))
+    }
+
+    /// Imports a stream of documents in batches.
+    ///
+    /// Each record goes through the options' transformations and duplicate check before being
+    /// inserted. Failed records are handled according to the options' [`ImportErrorPolicy`](crate::import::ImportErrorPolicy).
+    ///
+    /// # Arguments
+    ///
+    /// * `records` - The documents to import, or the errors of records that couldn't be read
+    /// * `options` - The [`ImportOptions`] configuring the import
+    ///
+    /// # Returns
+    ///
+    /// An [`ImportReport`] counting inserted, duplicate, dropped and failed records.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first failure when aborting on errors, or an error if the duplicate
+    /// check fails.
+    pub async fn import<S>(
+        &self,
+        records: S,
+        options: ImportOptions<D>,
+    ) -> DocumentStoreResult<ImportReport>
+    where
+        S: Stream<Item = DocumentStoreResult<D>> + Send,
+    {
+        import::run(records, &options, self).await
+    }
+
+    /// Queries documents in the collection, yielding them as a stream.
+    ///
+    /// Results are read incrementally from the backend and deserialized one at a time, so
+    /// large result sets can be processed without holding them in memory at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, limits and offsets
+    ///
+    /// # Returns
+    ///
+    /// A stream of the matching documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the query cannot
+    /// be started. Read and deserialization errors are yielded by the stream.
+    pub async fn query_stream(
+        &self,
+        query: Query,
+    ) -> DocumentStoreResult<BoxStream<'static, DocumentStoreResult<D>>> {
+        let encoding = self.backend.encoding().clone();
+
+        Ok(self
+            .backend
+            .query_stream(encoding.encode_query(live_query::<D>(query)), self.name())
+            .await?
+            .map(move |doc| doc.and_then(|doc| D::from_bson_encoded(doc, &encoding)))
+            .boxed())
+    }
+
+    /// Retrieves the unique values of a field across the documents in the collection.
+    ///
+    /// Array values are unwound, so each element counts as a value of its own.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field to collect values of (dot notation is supported)
+    /// * `filter` - An optional filter restricting the documents considered
+    ///
+    /// # Returns
+    ///
+    /// The unique values of the field, in no particular order.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn distinct(
+        &self,
+        field: &str,
+        filter: Option<Expr>,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .distinct(
+                field,
+                live_filter::<D>(filter).map(|filter| {
+                    self.backend
+                        .encoding()
+                        .encode_filter(filter)
+                }),
+                self.name(),
+            )
+            .await
+    }
+
+    /// Computes a grouped aggregation over the documents in the collection.
+    ///
+    /// # Arguments
+    ///
+    /// * `aggregate` - The [`Aggregate`] describing the groups and accumulators
+    ///
+    /// # Returns
+    ///
+    /// One document per group, holding the group-by fields and the accumulator results.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn aggregate(&self, aggregate: Aggregate) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .aggregate(
+                self.backend
+                    .encoding()
+                    .encode_aggregate(Aggregate {
+                        filter: live_filter::<D>(aggregate.filter),
+                        ..aggregate
+                    }),
+                self.name(),
+            )
+            .await
+    }
+
+    /// Describes how the backend would execute a query, without running it.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] to explain
+    ///
+    /// # Returns
+    ///
+    /// A [`QueryPlan`] telling whether the query would scan the whole collection or use an
+    /// index, along with the backend's own description of the plan.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn explain(&self, query: Query) -> DocumentStoreResult<QueryPlan> {
+        self.backend
+            .explain(
+                self.backend
+                    .encoding()
+                    .encode_query(live_query::<D>(query)),
+                self.name(),
+            )
+            .await
+    }
+
+    /// Retrieves a single document from the collection by its ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the document to retrieve (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// The document, or `None` if no document with the ID exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or retrieval fails.
+    pub async fn get_one<U>(&self, id: U) -> DocumentStoreResult<Option<D>>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        Ok(self
+            .get(vec![id])
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    /// Retrieves documents from the collection by their IDs, along with their revisions.
+    ///
+    /// Pass the revisions to [`update_if_match`](Self::update_if_match) or
+    /// [`delete_if_match`](Self::delete_if_match) to write the documents only if they haven't
+    /// changed since, or send them as `ETag`s with [`Versioned::etag`].
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - A vector of document IDs to retrieve (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// A vector of the documents found, each with its revision.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or retrieval fails.
+    pub async fn get_versioned<U>(&self, ids: Vec<U>) -> DocumentStoreResult<Vec<Versioned<D>>>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        get_in_chunks(
+            ids.into_iter()
+                .map(Into::into)
+                .collect(),
+            self.get_chunk_size,
+            |ids| {
+                self.backend
+                    .get_documents(ids, self.name())
+            },
+        )
+        .await?
+        .into_iter()
+        .filter(|doc| !is_deleted::<D>(doc))
+        .map(|doc| {
+            let revision = document_revision(&doc)?;
+            D::from_bson_encoded(doc, self.backend.encoding())
+                .map(|document| Versioned::new(document, revision))
+        })
+        .collect()
+    }
+
+    /// Retrieves a single document from the collection by its ID, along with its revision.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the document to retrieve (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// The document with its revision, or `None` if no document with the ID exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or retrieval fails.
+    pub async fn get_one_versioned<U>(&self, id: U) -> DocumentStoreResult<Option<Versioned<D>>>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        Ok(self
+            .get_versioned(vec![id])
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    /// Queries the collection for the first document matching a structured query.
+    ///
+    /// Any limit set on the query is replaced with a limit of one.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, and offsets
+    ///
+    /// # Returns
+    ///
+    /// The first matching document, or `None` if nothing matches.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or query fails.
+    pub async fn query_one(&self, query: Query) -> DocumentStoreResult<Option<D>> {
+        Ok(self
+            .query(Query { limit: Some(1), ..query })
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    /// Retrieves documents by their IDs, filling missing fields from `D::default()`.
+    ///
+    /// Unlike [`get`](Self::get), documents that lack fields (for example because a migration
+    /// adding them hasn't run yet) are still returned, and reported as incomplete.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - A vector of document IDs to retrieve (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// The documents found along with the IDs and fields of every incomplete document.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if retrieval fails or a
+    /// document still can't be deserialized.
+    pub async fn get_with_defaults<U>(&self, ids: Vec<U>) -> DocumentStoreResult<FilledDocuments<D>>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+        D: Default,
+    {
+        FilledDocuments::from_bson(
+            get_in_chunks(
+                ids.into_iter()
+                    .map(Into::into)
+                    .collect(),
+                self.get_chunk_size,
+                |ids| {
+                    self.backend
+                        .get_documents(ids, self.name())
+                },
+            )
+            .await?
+            .into_iter()
+            .filter(|doc| !is_deleted::<D>(doc))
+            .collect(),
+            self.backend.encoding(),
+        )
+    }
+
+    /// Queries the collection, filling missing fields of matched documents from `D::default()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, and pagination
+    ///
+    /// # Returns
+    ///
+    /// The matching documents along with the IDs and fields of every incomplete document.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the query fails or a
+    /// document still can't be deserialized.
+    pub async fn query_with_defaults(&self, query: Query) -> DocumentStoreResult<FilledDocuments<D>>
+    where
+        D: Default,
+    {
+        FilledDocuments::from_bson(
+            self.backend
+                .query_documents(
+                    self.backend
+                        .encoding()
+                        .encode_query(live_query::<D>(query)),
+                    self.name(),
+                )
+                .await?,
+            self.backend.encoding(),
+        )
+    }
+
+    /// Queries the collection and deserializes each result into a projection type.
+    ///
+    /// Intended for queries built with `.project([...])`, where the returned documents only
+    /// contain a subset of fields. Use [`Bson`] as `P` to get the partial documents as-is.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, pagination and projection
+    ///
+    /// # Returns
+    ///
+    /// A vector of projected documents matching the query.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the query fails
+    /// or a document cannot be deserialized into `P`.
+    pub async fn query_projected<P>(&self, query: Query) -> DocumentStoreResult<Vec<P>>
+    where
+        P: DeserializeOwned,
+    {
+        self.backend
+            .query_documents(
+                self.backend
+                    .encoding()
+                    .encode_query(live_query::<D>(query)),
+                self.name(),
+            )
+            .await?
+            .into_iter()
+            .map(|doc| self.backend.encoding().deserialize(doc))
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct DynTypedCollection<'a, D: Document> {
+    name: String,
+    backend: &'a dyn DynStoreBackend,
+    get_chunk_size: usize,
+    validators: Validators<D>,
+    _marker: PhantomData<D>,
+}
+
+impl<'a, D: Document> DynTypedCollection<'a, D> {
+    pub(crate) fn new(name: String, backend: &'a dyn DynStoreBackend) -> Self {
+        Self {
+            name,
+            backend,
+            get_chunk_size: DEFAULT_GET_CHUNK_SIZE,
+            validators: Validators::default(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the name of this collection.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sets the maximum number of IDs fetched per backend call when getting documents.
+    ///
+    /// Longer ID lists are split into several calls and their results concatenated, keeping
+    /// each request within the backend's limits. Defaults to [`DEFAULT_GET_CHUNK_SIZE`].
+    pub fn with_get_chunk_size(mut self, size: usize) -> Self {
+        self.get_chunk_size = size.max(1);
+        self
+    }
+
+    /// Attaches a validator run on every document the collection inserts, updates or upserts.
+    ///
+    /// Validators run in the order they're attached, before anything is written; a write with
+    /// a violation fails with [`DocumentStoreError::Validation`], listing the violations of all
+    /// its documents. Partial updates, such as [`update_where`](Self::update_where) and
+    /// [`find_one_and_update`](Self::find_one_and_update), are applied by the backend and
+    /// aren't validated. See the [`validate`](crate::validate) module.
+    pub fn with_validator(mut self, validator: impl Validator<D> + 'static) -> Self {
+        self.validators.push(validator);
+        self
+    }
+
+    /// Converts this typed collection to a different document type.
+    ///
+    /// This method allows switching between different document types for the same collection.
+    pub fn with_type<T: Document>(&self) -> DynTypedCollection<'a, T> {
+        DynTypedCollection {
+            name: self.name.clone(),
+            backend: self.backend,
+            get_chunk_size: self.get_chunk_size,
+            validators: Validators::default(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Inserts new documents into the collection.
+    ///
+    /// Fails without writing anything if any of the documents already exists
+    /// ([`InsertPolicy::ErrorOnConflict`]).
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of documents to insert
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::DocumentAlreadyExists`](crate::error::DocumentStoreError::DocumentAlreadyExists)
+    /// if a document with the same ID exists, or another
+    /// [`DocumentStoreError`](crate::error::DocumentStoreError) if serialization or insertion fails.
+    pub async fn insert(&self, documents: Vec<D>) -> DocumentStoreResult<()> {
+        self.insert_with_policy(documents, InsertPolicy::ErrorOnConflict)
+            .await
+            .map(|_| ())
+    }
+
+    /// Inserts documents into the collection, handling existing documents according to a policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of documents to insert
+    /// * `policy` - How to handle documents whose ID already exists
+    ///
+    /// # Returns
+    ///
+    /// A [`WriteReport`] with the existing documents as matched, the replaced ones that changed
+    /// as modified and the new ones as upserted.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if serialization or insertion fails.
+    pub async fn insert_with_policy(
+        &self,
+        documents: Vec<D>,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.validators.check(&documents)?;
+
+        self.backend
+            .insert_documents(
+                documents
+                    .into_iter()
+                    .map(|d| {
+                        d.to_bson_encoded(self.backend.encoding())
+                            .map(move |b| (*d.id(), b))
+                    })
+                    .collect::<Result<Vec<(Uuid, Bson)>, _>>()?,
+                self.name(),
+                policy,
+            )
+            .await
+    }
+
+    /// Inserts new documents into the collection that expire after a time-to-live.
+    ///
+    /// Sets the [`Document::expires_field`] of each document to the current time plus `ttl`,
+    /// replacing the value it was serialized with. The documents are deleted once expired if
+    /// the collection expires documents on that field, which
+    /// [`ensure_indexes`](crate::store::DocumentStore::ensure_indexes) enables (see the
+    /// [`expiry`](crate::expiry) module). Like [`insert`](Self::insert), fails without writing
+    /// anything if any of the documents already exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of documents to insert
+    /// * `ttl` - How long the documents live
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::InvalidDocument`] if `D` has no expiry field, or another
+    /// [`DocumentStoreError`] if serialization or insertion fails.
+    pub async fn insert_with_ttl(
+        &self,
+        documents: Vec<D>,
+        ttl: Duration,
+    ) -> DocumentStoreResult<()> {
+        self.validators.check(&documents)?;
+
+        let field = expires_field::<D>()?;
+        let expiry = self
+            .backend
+            .encoding()
+            .encode_value(Bson::DateTime(expires_at(ttl)));
+        let documents = documents
+            .into_iter()
+            .map(|d| {
+                d.to_bson_encoded(self.backend.encoding())
+                    .map(move |b| (*d.id(), b))
+            })
+            .collect::<Result<Vec<(Uuid, Bson)>, _>>()?;
+
+        self.backend
+            .insert_documents(
+                with_expiry(documents, field, expiry)?,
+                self.name(),
+                InsertPolicy::ErrorOnConflict,
+            )
+            .await
+            .map(|_| ())
+    }
+
+    /// Updates existing documents in the collection.
+    ///
+    /// Fails without writing anything if any of the documents doesn't exist
+    /// ([`MissingDocumentPolicy::Error`]).
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of documents with updated content
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if serialization or update fails.
+    pub async fn update(&self, documents: Vec<D>) -> DocumentStoreResult<()> {
+        self.update_with_policy(documents, MissingDocumentPolicy::Error)
+            .await
+            .map(|_| ())
+    }
+
+    /// Updates documents in the collection, handling missing documents according to a policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of documents with updated content
+    /// * `policy` - How to handle documents that don't exist
+    ///
+    /// # Returns
+    ///
+    /// A [`WriteReport`] with the matched, modified and inserted counts.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if serialization or update fails.
+    pub async fn update_with_policy(
+        &self,
+        documents: Vec<D>,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.validators.check(&documents)?;
+
+        self.backend
+            .update_documents(
+                documents
+                    .into_iter()
+                    .map(|d| {
+                        d.to_bson_encoded(self.backend.encoding())
+                            .map(move |b| (*d.id(), b))
+                    })
+                    .collect::<Result<Vec<(Uuid, Bson)>, _>>()?,
+                self.name(),
+                policy,
+            )
+            .await
+    }
+
+    /// Updates existing documents only if the stored documents are still at their
+    /// [`Document::version`], incrementing the version of each.
+    ///
+    /// Use this instead of [`update`](Self::update) when several writers may update the same
+    /// documents: a writer updating from a stale read fails instead of overwriting the
+    /// changes made since.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of documents with updated content, at the version they were read at
+    ///
+    /// # Returns
+    ///
+    /// The documents at their new versions.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::VersionConflict`] if a stored document is at another
+    /// version, [`DocumentStoreError::DocumentNotFound`] if it doesn't exist and
+    /// [`DocumentStoreError::InvalidDocument`] if `D` has no version field.
+    pub async fn update_if_version(&self, mut documents: Vec<D>) -> DocumentStoreResult<Vec<D>> {
+        self.validators.check(&documents)?;
+
+        let version_field = version_field::<D>()?;
+        let versioned = next_versions(&mut documents, self.backend.encoding())?;
+
+        self.backend
+            .update_documents_if_version(versioned, version_field, self.name())
+            .await?;
+
+        Ok(documents)
+    }
+
+    /// Updates existing documents only if they are still at the revisions they were read at
+    /// with [`get_versioned`](Self::get_versioned).
+    ///
+    /// Read the documents again for their new revisions.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of documents with updated content, along with the revision
+    ///   they were read at
+    ///
+    /// # Returns
+    ///
+    /// A [`WriteReport`] with the matched and modified counts.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::PreconditionFailed`] if a document doesn't exist or is at
+    /// another revision, or another [`DocumentStoreError`] if serialization or the update fails.
+    pub async fn update_if_match(
+        &self,
+        documents: Vec<Versioned<D>>,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.validators.check(
+            documents
+                .iter()
+                .map(|versioned| &versioned.document),
+        )?;
+
+        self.backend
+            .update_documents_if_match(
+                documents
+                    .into_iter()
+                    .map(|versioned| {
+                        versioned
+                            .document
+                            .to_bson_encoded(self.backend.encoding())
+                            .map(|bson| (*versioned.document.id(), bson, versioned.revision))
+                    })
+                    .collect::<DocumentStoreResult<Vec<_>>>()?,
+                self.name(),
+            )
+            .await
+    }
+
+    /// Inserts documents into the collection, replacing any existing documents with the same IDs.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - A vector of documents to insert or replace
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if serialization or the write fails.
+    pub async fn upsert(&self, documents: Vec<D>) -> DocumentStoreResult<()> {
+        self.validators.check(&documents)?;
+
+        self.backend
+            .upsert_documents(
+                documents
+                    .into_iter()
+                    .map(|d| {
+                        d.to_bson_encoded(self.backend.encoding())
+                            .map(move |b| (*d.id(), b))
+                    })
+                    .collect::<Result<Vec<(Uuid, Bson)>, _>>()?,
+                self.name(),
+            )
+            .await
+    }
+
+    /// Deletes documents from the collection by their IDs.
+    ///
+    /// IDs that don't exist are skipped, so deleting the same documents twice is not an error.
+    /// Use [`delete_strict`](Self::delete_strict) to fail when documents are missing.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - A vector of document IDs to delete (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// The number of deleted documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn delete<U>(&self, ids: Vec<U>) -> DocumentStoreResult<usize>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        self.backend
+            .delete_documents(
+                ids.into_iter()
+                    .map(Into::into)
+                    .collect(),
+                self.name(),
+            )
+            .await
+    }
+
+    /// Deletes documents from the collection by their IDs, failing if any of them is missing.
+    ///
+    /// Documents that do exist are still deleted; the error reports how many were missing.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - A vector of document IDs to delete (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// The number of deleted documents, which equals the number of distinct IDs given.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::DocumentNotFound`] if fewer documents were deleted
+    /// than requested, or another [`DocumentStoreError`] if the operation fails.
+    pub async fn delete_strict<U>(&self, ids: Vec<U>) -> DocumentStoreResult<usize>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        let mut ids = ids
+            .into_iter()
+            .map(Into::into)
+            .collect::<Vec<Uuid>>();
+        ids.sort_unstable_by_key(|id| id.bytes());
+        ids.dedup();
+
+        let requested = ids.len();
+        let deleted = self
+            .backend
+            .delete_documents(ids, self.name())
+            .await?;
+
+        if deleted < requested {
+            return Err(DocumentStoreError::DocumentNotFound(
+                format!("{} of {} requested documents", requested - deleted, requested),
+                self.name().to_string(),
+            ));
+        }
+
+        Ok(deleted)
+    }
+
+    /// Deletes documents from the collection only if they are still at the given revisions,
+    /// as read with `get_versioned`.
+    ///
+    /// # Arguments
+    ///
+    /// * `revisions` - A vector of (ID, revision) pairs, such as an ID and the `If-Match`
+    ///   header of a request converted with
+    ///   [`revision_from_etag`](crate::versioned::revision_from_etag)
+    ///
+    /// # Returns
+    ///
+    /// The number of deleted documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::PreconditionFailed`] if a document doesn't exist or is at
+    /// another revision, or another [`DocumentStoreError`] if the operation fails.
+    pub async fn delete_if_match(
+        &self,
+        revisions: Vec<(Uuid, String)>,
+    ) -> DocumentStoreResult<usize> {
+        self.backend
+            .delete_documents_if_match(revisions, self.name())
+            .await
+    }
+
+    /// Soft-deletes documents from the collection by their IDs.
+    ///
+    /// Sets the [`Document::deleted_field`] of each document to the current time, after which
+    /// reads leave the document out until it's [restored](Self::restore). Documents already
+    /// soft-deleted keep their deletion time. Each document is only written if it hasn't
+    /// changed since being read, so a concurrent update isn't overwritten.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - A vector of document IDs to soft-delete (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// The number of soft-deleted documents, leaving out missing and already soft-deleted ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::InvalidDocument`] if `D` has no deleted field,
+    /// [`DocumentStoreError::PreconditionFailed`] if a document changed while being
+    /// soft-deleted, or another [`DocumentStoreError`] if the operation fails.
+    pub async fn soft_delete<U>(&self, ids: Vec<U>) -> DocumentStoreResult<usize>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        let field = deleted_field::<D>()?;
+        let deleted_at = self
+            .backend
+            .encoding()
+            .encode_value(Bson::DateTime(bson::DateTime::now()));
+        let documents = get_in_chunks(
+            ids.into_iter()
+                .map(Into::into)
+                .collect(),
+            self.get_chunk_size,
+            |ids| {
+                self.backend
+                    .get_documents(ids, self.name())
+            },
+        )
+        .await?
+        .into_iter()
+        .filter(|doc| !is_deleted::<D>(doc))
+        .collect();
+        let documents = with_tombstone::<D>(documents, field, deleted_at, self.backend.encoding())?;
+
+        if documents.is_empty() {
+            return Ok(0);
+        }
+
+        self.backend
+            .update_documents_if_match(documents, self.name())
+            .await
+            .map(|report| report.matched)
+    }
+
+    /// Restores soft-deleted documents by their IDs, clearing their [`Document::deleted_field`].
+    ///
+    /// Like [`soft_delete`](Self::soft_delete), each document is only written if it hasn't
+    /// changed since being read.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - A vector of document IDs to restore (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// The number of restored documents, leaving out missing and not soft-deleted ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::InvalidDocument`] if `D` has no deleted field,
+    /// [`DocumentStoreError::PreconditionFailed`] if a document changed while being restored,
+    /// or another [`DocumentStoreError`] if the operation fails.
+    pub async fn restore<U>(&self, ids: Vec<U>) -> DocumentStoreResult<usize>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        let field = deleted_field::<D>()?;
+        let documents = get_in_chunks(
+            ids.into_iter()
+                .map(Into::into)
+                .collect(),
+            self.get_chunk_size,
+            |ids| {
+                self.backend
+                    .get_documents(ids, self.name())
+            },
+        )
+        .await?
+        .into_iter()
+        .filter(is_deleted::<D>)
+        .collect();
+        let documents = with_tombstone::<D>(documents, field, Bson::Null, self.backend.encoding())?;
+
+        if documents.is_empty() {
+            return Ok(0);
+        }
+
+        self.backend
+            .update_documents_if_match(documents, self.name())
+            .await
+            .map(|report| report.matched)
+    }
+
+    /// Applies a partial update to every document in the collection matching a filter expression.
+    ///
+    /// Soft-deleted documents are left out, like reads leave them out. The backend applies the
+    /// update to the stored documents, so the collection's validators don't run.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The [`Expr`] selecting the documents to update
+    /// * `update` - The [`Update`] operations to apply
+    ///
+    /// # Returns
+    ///
+    /// A [`WriteReport`] with the number of matched and modified documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn update_where(
+        &self,
+        filter: Expr,
+        update: Update,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.backend
+            .update_by_query(
+                self.backend.encoding().encode_filter(
+                    live_filter::<D>(Some(filter)).unwrap_or_else(|| Expr::And(Vec::new())),
+                ),
+                self.backend
+                    .encoding()
+                    .encode_update(update),
+                self.name(),
+            )
+            .await
+    }
+
+    /// Deletes every document in the collection matching a filter expression.
+    ///
+    /// Unlike reads and [`update_where`](Self::update_where), this matches soft-deleted
+    /// documents too, and removes them for good. To leave them out, add a condition on the
+    /// [`Document::deleted_field`] to the filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The [`Expr`] selecting the documents to delete
+    ///
+    /// # Returns
+    ///
+    /// The number of deleted documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn delete_where(&self, filter: Expr) -> DocumentStoreResult<usize> {
+        self.backend
+            .delete_by_query(
+                self.backend
+                    .encoding()
+                    .encode_filter(filter),
+                self.name(),
+            )
+            .await
+    }
+
+    /// Applies a partial update to the first document matching a filter expression and
+    /// returns the document as it was before the update, as a single atomic operation.
+    ///
+    /// Of several callers racing for the same documents, each gets a different one, so this
+    /// can claim work such as a pending job. To pick the document by sort order or return it
+    /// as updated, use [`find`](Self::find) with [`Find::update_one`]. Like
+    /// [`update_where`](Self::update_where), the update isn't validated.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The [`Expr`] selecting the documents to consider
+    /// * `update` - The [`Update`] operations to apply
+    ///
+    /// # Returns
+    ///
+    /// The document before the update, or `None` if no document matches.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or the update fails.
+    pub async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        update: Update,
+    ) -> DocumentStoreResult<Option<D>> {
+        self.find()
+            .filter(filter)
+            .update_one(update, ReturnDocument::Before)
+            .await
+    }
+
+    /// Deletes the first document matching a filter expression and returns it, as a single
+    /// atomic operation.
+    ///
+    /// To pick the document by sort order, use [`find`](Self::find) with
+    /// [`Find::delete_one`].
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The [`Expr`] selecting the documents to consider
+    ///
+    /// # Returns
+    ///
+    /// The deleted document, or `None` if no document matches.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or the delete fails.
+    pub async fn find_one_and_delete(&self, filter: Expr) -> DocumentStoreResult<Option<D>> {
+        self.find()
+            .filter(filter)
+            .delete_one()
+            .await
+    }
+
+    /// Retrieves documents from the collection by their IDs.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - A vector of document IDs to retrieve (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// A vector of documents found. If a document ID doesn't exist, it is omitted from results.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or retrieval fails.
+    pub async fn get<U>(&self, ids: Vec<U>) -> DocumentStoreResult<Vec<D>>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        get_in_chunks(
+            ids.into_iter()
+                .map(Into::into)
+                .collect(),
+            self.get_chunk_size,
+            |ids| {
+                self.backend
+                    .get_documents(ids, self.name())
+            },
+        )
+        .await?
+        .into_iter()
+        .filter(|doc| !is_deleted::<D>(doc))
+        .map(|doc| D::from_bson_encoded(doc, self.backend.encoding()))
+        .collect()
+    }
+
+    /// Queries documents in the collection using a structured query.
+    ///
+    /// Soft-deleted documents are left out, see [`Document::deleted_field`].
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, limits, and offsets
+    ///
+    /// # Returns
+    ///
+    /// A vector of documents matching the query criteria.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or query fails.
+    pub async fn query(&self, query: Query) -> DocumentStoreResult<Vec<D>> {
+        self.query_with_deleted(live_query::<D>(query))
+            .await
+    }
+
+    /// Queries documents in the collection using a structured query, including soft-deleted
+    /// documents.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, limits, and offsets
+    ///
+    /// # Returns
+    ///
+    /// A vector of documents matching the query criteria, whether soft-deleted or not.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or query fails.
+    pub async fn query_with_deleted(&self, query: Query) -> DocumentStoreResult<Vec<D>> {
+        Ok(self
+            .backend
+            .query_documents(
+                self.backend
+                    .encoding()
+                    .encode_query(query),
+                &self.name(),
+            )
+            .await?
+            .into_iter()
+            .map(|doc| D::from_bson_encoded(doc, self.backend.encoding()))
+            .collect::<Result<Vec<D>, _>>()?)
+    }
+
+    /// Queries the collection, keeping the matching documents as raw BSON.
+    ///
+    /// Use this when only a few fields of each document are needed: [`RawDoc::get_as`] reads
+    /// single fields cheaply, and [`RawDoc::parse`] deserializes a document only when asked.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, limits, and offsets
+    ///
+    /// # Returns
+    ///
+    /// The matching documents as [`RawDoc`]s.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the query fails.
+    pub async fn query_raw(&self, query: Query) -> DocumentStoreResult<Vec<RawDoc<D>>> {
+        Ok(self
+            .backend
+            .query_raw_documents(
+                self.backend
+                    .encoding()
+                    .encode_query(live_query::<D>(query)),
+                self.name(),
+            )
+            .await?
+            .into_iter()
+            .map(RawDoc::new)
+            .collect())
+    }
+
+    /// Starts a fluent query on the collection, executed when awaited.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let adults = users
+    ///     .find()
+    ///     .filter(Filter::gt("age", 18))
+    ///     .sort_desc("created_at")
+    ///     .limit(10)
+    ///     .await?;
+    /// ```
+    pub fn find(&self) -> Find<'_, Self> {
+        Find::new(self)
+    }
+
+    /// Counts the documents in the collection matching a structured query.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, limits, and offsets (sorting is ignored)
+    ///
+    /// # Returns
+    ///
+    /// The number of matching documents, counted by the backend without fetching them.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn count(&self, query: Query) -> DocumentStoreResult<usize> {
+        self.backend
+            .count_documents(
+                self.backend
+                    .encoding()
+                    .encode_query(live_query::<D>(query)),
+                self.name(),
+            )
+            .await
+    }
+
+    /// Retrieves one page of the documents matching a query, along with their total count.
+    ///
+    /// The page's limit and offset replace those of the query; the documents and the total
+    /// are fetched concurrently.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters and sorting
+    /// * `params` - The [`PaginationParams`] selecting the page
+    ///
+    /// # Returns
+    ///
+    /// A [`Page`] holding the page's documents, the total count of matching documents and the
+    /// numbers of the neighbouring pages.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or query fails.
+    pub async fn query_page(
+        &self,
+        query: Query,
+        params: PaginationParams,
+    ) -> DocumentStoreResult<Page<D>> {
+        let count_query = Query {
+            limit: None,
+            offset: None,
+            ..query.clone()
+        };
+        let page_query = Query {
+            limit: Some(params.per_page),
+            offset: Some(params.offset()),
+            ..query
+        };
+
+        let (items, count) = futures::try_join!(self.query(page_query), self.count(count_query))?;
+
+        Ok(params.to_page(items, count))
+    }
+
+    /// Queries a page of documents together with its total, next cursor and facet counts.
+    ///
+    /// The request's cursor and limit replace the offset and limit of the query. The page,
+    /// the total and each facet are fetched concurrently.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters and sorting
+    /// * `request` - The [`PageRequest`] selecting the page and its metadata
+    ///
+    /// # Returns
+    ///
+    /// A [`QueryPage`] holding the page's documents, the total if requested, the cursor of
+    /// the next page and the counts of each requested facet.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization,
+    /// a query or an aggregation fails.
+    pub async fn query_paged(
+        &self,
+        query: Query,
+        request: PageRequest,
+    ) -> DocumentStoreResult<QueryPage<D>> {
+        let total = async {
+            if request.includes_total() {
+                self.count(request.total_query(&query))
+                    .await
+                    .map(Some)
+            } else {
+                Ok(None)
+            }
+        };
+        let facets = futures::future::try_join_all(
+            request
+                .facets()
+                .iter()
+                .map(|field| async {
+                    self.aggregate(request.facet_aggregate(&query, field))
+                        .await
+                        .map(|rows| (field.clone(), rows))
+                }),
+        );
+
+        let (items, total, facets) =
+            futures::try_join!(self.query(request.page_query(&query)), total, facets)?;
+
+        Ok(request.to_page(items, total, facets))
+    }
+
+    /// Imports a stream of documents in batches.
+    ///
+    /// Each record goes through the options' transformations and duplicate check before being
+    /// inserted. Failed records are handled according to the options' [`ImportErrorPolicy`](crate::import::ImportErrorPolicy).
+    ///
+    /// # Arguments
+    ///
+    /// * `records` - The documents to import, or the errors of records that couldn't be read
+    /// * `options` - The [`ImportOptions`] configuring the import
+    ///
+    /// # Returns
+    ///
+    /// An [`ImportReport`] counting inserted, duplicate, dropped and failed records.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first failure when aborting on errors, or an error if the duplicate
+    /// check fails.
+    pub async fn import<S>(
+        &self,
+        records: S,
+        options: ImportOptions<D>,
+    ) -> DocumentStoreResult<ImportReport>
+    where
+        S: Stream<Item = DocumentStoreResult<D>> + Send,
+    {
+        import::run(records, &options, self).await
+    }
+
+    /// Queries documents in the collection, yielding them as a stream.
+    ///
+    /// Results are read incrementally from the backend and deserialized one at a time, so
+    /// large result sets can be processed without holding them in memory at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, limits and offsets
+    ///
+    /// # Returns
+    ///
+    /// A stream of the matching documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the query cannot
+    /// be started. Read and deserialization errors are yielded by the stream.
+    pub async fn query_stream(
+        &self,
+        query: Query,
+    ) -> DocumentStoreResult<BoxStream<'static, DocumentStoreResult<D>>> {
+        let encoding = self.backend.encoding().clone();
+
+        Ok(self
+            .backend
+            .query_stream(encoding.encode_query(live_query::<D>(query)), self.name())
+            .await?
+            .map(move |doc| doc.and_then(|doc| D::from_bson_encoded(doc, &encoding)))
+            .boxed())
+    }
+
+    /// Retrieves the unique values of a field across the documents in the collection.
+    ///
+    /// Array values are unwound, so each element counts as a value of its own.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field to collect values of (dot notation is supported)
+    /// * `filter` - An optional filter restricting the documents considered
+    ///
+    /// # Returns
+    ///
+    /// The unique values of the field, in no particular order.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn distinct(
+        &self,
+        field: &str,
+        filter: Option<Expr>,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .distinct(
+                field,
+                live_filter::<D>(filter).map(|filter| {
+                    self.backend
+                        .encoding()
+                        .encode_filter(filter)
+                }),
+                self.name(),
+            )
+            .await
+    }
+
+    /// Computes a grouped aggregation over the documents in the collection.
+    ///
+    /// # Arguments
+    ///
+    /// * `aggregate` - The [`Aggregate`] describing the groups and accumulators
+    ///
+    /// # Returns
+    ///
+    /// One document per group, holding the group-by fields and the accumulator results.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn aggregate(&self, aggregate: Aggregate) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .aggregate(
+                self.backend
+                    .encoding()
+                    .encode_aggregate(Aggregate {
+                        filter: live_filter::<D>(aggregate.filter),
+                        ..aggregate
+                    }),
+                self.name(),
+            )
+            .await
+    }
+
+    /// Describes how the backend would execute a query, without running it.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] to explain
+    ///
+    /// # Returns
+    ///
+    /// A [`QueryPlan`] telling whether the query would scan the whole collection or use an
+    /// index, along with the backend's own description of the plan.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn explain(&self, query: Query) -> DocumentStoreResult<QueryPlan> {
+        self.backend
+            .explain(
+                self.backend
+                    .encoding()
+                    .encode_query(live_query::<D>(query)),
+                self.name(),
+            )
+            .await
+    }
+
+    /// Retrieves a single document from the collection by its ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the document to retrieve (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// The document, or `None` if no document with the ID exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or retrieval fails.
+    pub async fn get_one<U>(&self, id: U) -> DocumentStoreResult<Option<D>>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        Ok(self
+            .get(vec![id])
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    /// Retrieves documents from the collection by their IDs, along with their revisions.
+    ///
+    /// Pass the revisions to [`update_if_match`](Self::update_if_match) or
+    /// [`delete_if_match`](Self::delete_if_match) to write the documents only if they haven't
+    /// changed since, or send them as `ETag`s with [`Versioned::etag`].
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - A vector of document IDs to retrieve (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// A vector of the documents found, each with its revision.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or retrieval fails.
+    pub async fn get_versioned<U>(&self, ids: Vec<U>) -> DocumentStoreResult<Vec<Versioned<D>>>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        get_in_chunks(
+            ids.into_iter()
+                .map(Into::into)
+                .collect(),
+            self.get_chunk_size,
+            |ids| {
+                self.backend
+                    .get_documents(ids, self.name())
+            },
+        )
+        .await?
+        .into_iter()
+        .filter(|doc| !is_deleted::<D>(doc))
+        .map(|doc| {
+            let revision = document_revision(&doc)?;
+            D::from_bson_encoded(doc, self.backend.encoding())
+                .map(|document| Versioned::new(document, revision))
+        })
+        .collect()
+    }
+
+    /// Retrieves a single document from the collection by its ID, along with its revision.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the document to retrieve (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// The document with its revision, or `None` if no document with the ID exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or retrieval fails.
+    pub async fn get_one_versioned<U>(&self, id: U) -> DocumentStoreResult<Option<Versioned<D>>>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+    {
+        Ok(self
+            .get_versioned(vec![id])
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    /// Queries the collection for the first document matching a structured query.
+    ///
+    /// Any limit set on the query is replaced with a limit of one.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, and offsets
+    ///
+    /// # Returns
+    ///
+    /// The first matching document, or `None` if nothing matches.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or query fails.
+    pub async fn query_one(&self, query: Query) -> DocumentStoreResult<Option<D>> {
+        Ok(self
+            .query(Query { limit: Some(1), ..query })
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    /// Retrieves documents by their IDs, filling missing fields from `D::default()`.
+    ///
+    /// Unlike [`get`](Self::get), documents that lack fields (for example because a migration
+    /// adding them hasn't run yet) are still returned, and reported as incomplete.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - A vector of document IDs to retrieve (must implement `Into<Uuid>`)
+    ///
+    /// # Returns
+    ///
+    /// The documents found along with the IDs and fields of every incomplete document.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if retrieval fails or a
+    /// document still can't be deserialized.
+    pub async fn get_with_defaults<U>(&self, ids: Vec<U>) -> DocumentStoreResult<FilledDocuments<D>>
+    where
+        U: Into<Uuid> + Send + Sync + 'static,
+        D: Default,
+    {
+        FilledDocuments::from_bson(
+            get_in_chunks(
+                ids.into_iter()
+                    .map(Into::into)
+                    .collect(),
+                self.get_chunk_size,
+                |ids| {
+                    self.backend
+                        .get_documents(ids, self.name())
+                },
+            )
+            .await?
+            .into_iter()
+            .filter(|doc| !is_deleted::<D>(doc))
+            .collect(),
+            self.backend.encoding(),
+        )
+    }
+
+    /// Queries the collection, filling missing fields of matched documents from `D::default()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, and pagination
+    ///
+    /// # Returns
+    ///
+    /// The matching documents along with the IDs and fields of every incomplete document.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the query fails or a
+    /// document still can't be deserialized.
+    pub async fn query_with_defaults(&self, query: Query) -> DocumentStoreResult<FilledDocuments<D>>
+    where
+        D: Default,
+    {
+        FilledDocuments::from_bson(
+            self.backend
+                .query_documents(
+                    self.backend
+                        .encoding()
+                        .encode_query(live_query::<D>(query)),
+                    self.name(),
+                )
+                .await?,
+            self.backend.encoding(),
+        )
+    }
+
+    /// Queries the collection and deserializes each result into a projection type.
+    ///
+    /// Intended for queries built with `.project([...])`, where the returned documents only
+    /// contain a subset of fields. Use [`Bson`] as `P` to get the partial documents as-is.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The [`Query`] specifying filters, sorting, pagination and projection
+    ///
+    /// # Returns
+    ///
+    /// A vector of projected documents matching the query.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the query fails
+    /// or a document cannot be deserialized into `P`.
+    pub async fn query_projected<P>(&self, query: Query) -> DocumentStoreResult<Vec<P>>
+    where
+        P: DeserializeOwned,
+    {
+        self.backend
+            .query_documents(
+                self.backend
+                    .encoding()
+                    .encode_query(live_query::<D>(query)),
+                self.name(),
+            )
+            .await?
+            .into_iter()
+            .map(|doc| self.backend.encoding().deserialize(doc))
+            .collect()
+    }
+}
+
+/// A fluent query on a typed collection, created by `find()`.
+///
+/// Awaiting the builder runs the query and returns the matching documents. Use
+/// [`one`](Find::one) to fetch the first match only, or [`count`](Find::count) to count matches.
+/// [`update_one`](Find::update_one) and [`delete_one`](Find::delete_one) atomically update or
+/// delete the first match in sort order and return it.
+///
+/// # Type Parameters
+///
+/// * `C` - The typed collection the query runs on
+#[derive(Debug)]
+pub struct Find<'c, C> {
+    collection: &'c C,
+    query: Query,
+    error: Option<DocumentStoreError>,
+}
+
+impl<'c, C> Find<'c, C> {
+    fn new(collection: &'c C) -> Self {
+        Find { collection, query: Query::default(), error: None }
+    }
+
+    /// Adds a filter expression.
+    ///
+    /// Filters added by repeated calls must all match.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The filter expression to apply
+    pub fn filter(mut self, filter: Expr) -> Self {
+        self.query.filter = Some(match self.query.filter.take() {
+            Some(existing) => existing.and(filter),
+            None => filter,
+        });
+        self
+    }
+
+    /// Adds an ascending sort key, after any previously added keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field name to sort by
+    pub fn sort_asc(mut self, field: impl Into<String>) -> Self {
+        self.query.sort.push(Sort {
+            field: field.into(),
+            direction: SortDirection::Asc,
+        });
+        self
+    }
+
+    /// Adds a descending sort key, after any previously added keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field name to sort by
+    pub fn sort_desc(mut self, field: impl Into<String>) -> Self {
+        self.query.sort.push(Sort {
+            field: field.into(),
+            direction: SortDirection::Desc,
+        });
+        self
+    }
+
+    /// Sets the maximum number of documents to return.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The maximum number of documents to return
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.query.limit = Some(limit);
+        self
+    }
+
+    /// Sets the number of documents to skip.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The number of documents to skip
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.query.offset = Some(offset);
+        self
+    }
+
+    /// Limits how long the backend may take to run the query.
+    ///
+    /// See [`QueryBuilder::timeout`](crate::query::QueryBuilder::timeout).
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The longest the query may run
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.query.timeout = Some(timeout);
+        self
+    }
+
+    /// Limits the query to run before `deadline`.
+    ///
+    /// See [`QueryBuilder::deadline`](crate::query::QueryBuilder::deadline).
+    ///
+    /// # Arguments
+    ///
+    /// * `deadline` - The instant the query must finish by
+    pub fn deadline(self, deadline: Instant) -> Self {
+        self.timeout(deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Returns the query built so far.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first filter that failed to build, such as one passed to
+    /// `filter_by`.
+    pub fn into_query(self) -> DocumentStoreResult<Query> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.query),
+        }
+    }
+
+    /// Adds a filter, or records the error building it, which the query then fails with.
+    fn filter_result(self, filter: impl IntoFilter) -> Self {
+        match filter.into_filter() {
+            Ok(filter) => self.filter(filter),
+            Err(error) => Find { error: self.error.or(Some(error)), ..self },
+        }
+    }
+}
+
+impl<'c, 'a, B: StoreBackend, D: Document> Find<'c, TypedCollection<'a, B, D>> {
+    /// Adds a filter built from the typed fields of `D`.
+    ///
+    /// A filter whose values fail to serialize fails the query when it runs, with the error
+    /// of the first such filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Builds the filter from `D`'s `<Name>Fields`
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let users = users
+    ///     .find()
+    ///     .filter_by(|f| f.name().eq("Alice").and_filter(f.age().gt(18)))
+    ///     .sort_desc_by(|f| f.created_at())
+    ///     .await?;
+    /// ```
+    pub fn filter_by<F: IntoFilter>(self, filter: impl FnOnce(D::Fields) -> F) -> Self
+    where
+        D: TypedFields,
+    {
+        self.filter_result(filter(D::FIELDS))
+    }
+
+    /// Adds an ascending sort key on a typed field of `D`, after any previously added keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - Picks the field to sort by from `D`'s `<Name>Fields`
+    pub fn sort_asc_by<T>(self, field: impl FnOnce(D::Fields) -> Field<T>) -> Self
+    where
+        D: TypedFields,
+    {
+        self.sort_asc(field(D::FIELDS))
+    }
+
+    /// Adds a descending sort key on a typed field of `D`, after any previously added keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - Picks the field to sort by from `D`'s `<Name>Fields`
+    pub fn sort_desc_by<T>(self, field: impl FnOnce(D::Fields) -> Field<T>) -> Self
+    where
+        D: TypedFields,
+    {
+        self.sort_desc(field(D::FIELDS))
+    }
+
+    /// Runs the query and returns the first matching document, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or query fails.
+    pub async fn one(self) -> DocumentStoreResult<Option<D>> {
+        let collection = self.collection;
+
+        collection
+            .query_one(self.into_query()?)
+            .await
+    }
+
+    /// Counts the matching documents, ignoring sort keys.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn count(self) -> DocumentStoreResult<usize> {
+        let collection = self.collection;
+
+        collection.count(self.into_query()?).await
+    }
+
+    /// Applies a partial update to the first matching document and returns it, as a single
+    /// atomic operation. The limit and offset are ignored, and the collection's validators
+    /// don't run.
+    ///
+    /// See [`StoreBackend::find_one_and_update`].
+    ///
+    /// # Arguments
+    ///
+    /// * `update` - The [`Update`] operations to apply
+    /// * `returned` - Whether to return the document as it was before or after the update
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or the update fails.
+    pub async fn update_one(
+        self,
+        update: Update,
+        returned: ReturnDocument,
+    ) -> DocumentStoreResult<Option<D>> {
+        let collection = self.collection;
+        let query = self.into_query()?;
+        let encoding = collection.backend.encoding();
+
+        collection
+            .backend
+            .find_one_and_update(
+                encoding.encode_filter(
+                    live_filter::<D>(query.filter).unwrap_or_else(|| Expr::And(Vec::new())),
+                ),
+                query.sort,
+                encoding.encode_update(update),
+                returned,
+                collection.name(),
+            )
+            .await?
+            .map(|doc| D::from_bson_encoded(doc, encoding))
+            .transpose()
+    }
+
+    /// Deletes the first matching document and returns it, as a single atomic operation.
+    /// The limit and offset are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or the delete fails.
+    pub async fn delete_one(self) -> DocumentStoreResult<Option<D>> {
+        let collection = self.collection;
+        let query = self.into_query()?;
+        let encoding = collection.backend.encoding();
+
+        collection
+            .backend
+            .find_one_and_delete(
+                encoding.encode_filter(
+                    live_filter::<D>(query.filter).unwrap_or_else(|| Expr::And(Vec::new())),
+                ),
+                query.sort,
+                collection.name(),
+            )
+            .await?
+            .map(|doc| D::from_bson_encoded(doc, encoding))
+            .transpose()
+    }
+}
+
+impl<'c, 'a, B: StoreBackend, D: Document> IntoFuture for Find<'c, TypedCollection<'a, B, D>> {
+    type Output = DocumentStoreResult<Vec<D>>;
+    type IntoFuture = BoxFuture<'c, Self::Output>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move {
+            let collection = self.collection;
+
+            collection.query(self.into_query()?).await
+        })
+    }
+}
+
+impl<'c, 'a, D: Document> Find<'c, DynTypedCollection<'a, D>> {
+    /// Adds a filter built from the typed fields of `D`.
+    ///
+    /// A filter whose values fail to serialize fails the query when it runs, with the error
+    /// of the first such filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Builds the filter from `D`'s `<Name>Fields`
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let users = users
+    ///     .find()
+    ///     .filter_by(|f| f.name().eq("Alice").and_filter(f.age().gt(18)))
+    ///     .sort_desc_by(|f| f.created_at())
+    ///     .await?;
+    /// ```
+    pub fn filter_by<F: IntoFilter>(self, filter: impl FnOnce(D::Fields) -> F) -> Self
+    where
+        D: TypedFields,
+    {
+        self.filter_result(filter(D::FIELDS))
+    }
+
+    /// Adds an ascending sort key on a typed field of `D`, after any previously added keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - Picks the field to sort by from `D`'s `<Name>Fields`
+    pub fn sort_asc_by<T>(self, field: impl FnOnce(D::Fields) -> Field<T>) -> Self
+    where
+        D: TypedFields,
+    {
+        self.sort_asc(field(D::FIELDS))
+    }
+
+    /// Adds a descending sort key on a typed field of `D`, after any previously added keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - Picks the field to sort by from `D`'s `<Name>Fields`
+    pub fn sort_desc_by<T>(self, field: impl FnOnce(D::Fields) -> Field<T>) -> Self
+    where
+        D: TypedFields,
+    {
+        self.sort_desc(field(D::FIELDS))
+    }
+
+    /// Runs the query and returns the first matching document, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or query fails.
+    pub async fn one(self) -> DocumentStoreResult<Option<D>> {
+        let collection = self.collection;
+
+        collection
+            .query_one(self.into_query()?)
+            .await
+    }
+
+    /// Counts the matching documents, ignoring sort keys.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if the operation fails.
+    pub async fn count(self) -> DocumentStoreResult<usize> {
+        let collection = self.collection;
+
+        collection.count(self.into_query()?).await
+    }
+
+    /// Applies a partial update to the first matching document and returns it, as a single
+    /// atomic operation. The limit and offset are ignored, and the collection's validators
+    /// don't run.
+    ///
+    /// See [`StoreBackend::find_one_and_update`].
+    ///
+    /// # Arguments
+    ///
+    /// * `update` - The [`Update`] operations to apply
+    /// * `returned` - Whether to return the document as it was before or after the update
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or the update fails.
+    pub async fn update_one(
+        self,
+        update: Update,
+        returned: ReturnDocument,
+    ) -> DocumentStoreResult<Option<D>> {
+        let collection = self.collection;
+        let query = self.into_query()?;
+        let encoding = collection.backend.encoding();
+
+        collection
+            .backend
+            .find_one_and_update(
+                encoding.encode_filter(
+                    live_filter::<D>(query.filter).unwrap_or_else(|| Expr::And(Vec::new())),
+                ),
+                query.sort,
+                encoding.encode_update(update),
+                returned,
+                collection.name(),
+            )
+            .await?
+            .map(|doc| D::from_bson_encoded(doc, encoding))
+            .transpose()
+    }
+
+    /// Deletes the first matching document and returns it, as a single atomic operation.
+    /// The limit and offset are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`](crate::error::DocumentStoreError) if deserialization or the delete fails.
+    pub async fn delete_one(self) -> DocumentStoreResult<Option<D>> {
+        let collection = self.collection;
+        let query = self.into_query()?;
+        let encoding = collection.backend.encoding();
+
+        collection
+            .backend
+            .find_one_and_delete(
+                encoding.encode_filter(
+                    live_filter::<D>(query.filter).unwrap_or_else(|| Expr::And(Vec::new())),
+                ),
+                query.sort,
+                collection.name(),
+            )
+            .await?
+            .map(|doc| D::from_bson_encoded(doc, encoding))
+            .transpose()
+    }
+}
+
+impl<'c, 'a, D: Document> IntoFuture for Find<'c, DynTypedCollection<'a, D>> {
+    type Output = DocumentStoreResult<Vec<D>>;
+    type IntoFuture = BoxFuture<'c, Self::Output>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move {
+            let collection = self.collection;
+
+            collection.query(self.into_query()?).await
+        })
+    }
+}
+
+impl<'a, B: StoreBackend, D: Document> ImportTarget<D> for TypedCollection<'a, B, D> {
+    fn insert_batch(&self, documents: Vec<D>) -> BoxFuture<'_, DocumentStoreResult<()>> {
+        Box::pin(self.insert(documents))
+    }
+
+    fn find(&self, query: Query) -> BoxFuture<'_, DocumentStoreResult<Vec<D>>> {
+        Box::pin(self.query(query))
+    }
+}
+
+impl<'a, D: Document> ImportTarget<D> for DynTypedCollection<'a, D> {
+    fn insert_batch(&self, documents: Vec<D>) -> BoxFuture<'_, DocumentStoreResult<()>> {
+        Box::pin(self.insert(documents))
+    }
+
+    fn find(&self, query: Query) -> BoxFuture<'_, DocumentStoreResult<Vec<D>>> {
+        Box::pin(self.query(query))
+    }
+}
+
+/// Returns the field holding the expiry of `D`, failing if `D` has none.
+fn expires_field<D: Document>() -> DocumentStoreResult<&'static str> {
+    D::expires_field().ok_or_else(|| {
+        DocumentStoreError::InvalidDocument(format!("{} has no expiry field", type_name::<D>()))
+    })
+}
+
+/// Sets `field` of each document to `expiry`.
+fn with_expiry(
+    documents: Vec<(Uuid, Bson)>,
+    field: &str,
+    expiry: Bson,
+) -> DocumentStoreResult<Vec<(Uuid, Bson)>> {
+    documents
+        .into_iter()
+        .map(|(id, mut document)| match &mut document {
+            Bson::Document(fields) => {
+                fields.insert(field, expiry.clone());
+                Ok((id, document))
+            }
+            _ => Err(DocumentStoreError::InvalidDocument(format!(
+                "Document {} is not a BSON document",
+                id
+            ))),
+        })
+        .collect()
+}
+
+/// Returns the field marking `D` as soft-deleted, failing if `D` has none.
+fn deleted_field<D: Document>() -> DocumentStoreResult<&'static str> {
+    D::deleted_field().ok_or_else(|| {
+        DocumentStoreError::InvalidDocument(format!("{} has no deleted field", type_name::<D>()))
+    })
+}
+
+/// Returns whether a stored document of `D` is soft-deleted.
+fn is_deleted<D: Document>(document: &Bson) -> bool {
+    D::deleted_field().is_some_and(|field| {
+        document
+            .as_document()
+            .and_then(|fields| fields.get(field))
+            .is_some_and(|value| *value != Bson::Null)
+    })
+}
+
+/// Restricts a filter to the documents of `D` that aren't soft-deleted.
+fn live_filter<D: Document>(filter: Option<Expr>) -> Option<Expr> {
+    let Some(field) = D::deleted_field() else {
+        return filter;
+    };
+    let live = Filter::or([Filter::not_exists(field), Filter::eq(field, Bson::Null)]);
+
+    Some(match filter {
+        Some(filter) => live.and(filter),
+        None => live,
+    })
+}
+
+/// Restricts a query to the documents of `D` that aren't soft-deleted.
+fn live_query<D: Document>(query: Query) -> Query {
+    Query {
+        filter: live_filter::<D>(query.filter),
+        ..query
+    }
+}
+
+/// Sets the deleted field of stored documents of `D` to `value`, returning the documents to
+/// write along with the revisions they replace.
+fn with_tombstone<D: Document>(
+    documents: Vec<Bson>,
+    field: &str,
+    value: Bson,
+    encoding: &Encoding,
+) -> DocumentStoreResult<Vec<(Uuid, Bson, String)>> {
+    documents
+        .into_iter()
+        .map(|mut document| {
+            let revision = document_revision(&document)?;
+            let id = *D::from_bson_encoded(document.clone(), encoding)?.id();
+            if let Bson::Document(fields) = &mut document {
+                fields.insert(field, value.clone());
+            }
+            Ok((id, document, revision))
+        })
+        .collect()
+}
+
+/// Returns the field holding the version of `D`, failing if `D` has none.
+fn version_field<D: Document>() -> DocumentStoreResult<&'static str> {
+    D::version_field().ok_or_else(|| {
+        DocumentStoreError::InvalidDocument(format!("{} has no version field", type_name::<D>()))
+    })
+}
+
+/// Increments the version of each document, returning the documents to write along with the
+/// versions they replace.
+fn next_versions<D: Document>(
+    documents: &mut [D],
+    encoding: &Encoding,
+) -> DocumentStoreResult<Vec<(Uuid, Bson, u64)>> {
+    documents
+        .iter_mut()
+        .map(|document| {
+            let version = document.version().unwrap_or_default();
+            document.set_version(version + 1);
+            document
+                .to_bson_encoded(encoding)
+                .map(|bson| (*document.id(), bson, version))
+        })
+        .collect()
+}
+
+/// Gets documents by ID, splitting the IDs into chunks of at most `chunk_size` per call.
+///
+/// Repeated IDs are requested once, so each document is returned once however the IDs are
+/// split.
+async fn get_in_chunks<'f, F>(
+    ids: Vec<Uuid>,
+    chunk_size: usize,
+    get: F,
+) -> DocumentStoreResult<Vec<Bson>>
+where
+    F: Fn(Vec<Uuid>) -> BoxFuture<'f, DocumentStoreResult<Vec<Bson>>>,
+{
+    let mut seen = HashSet::new();
+    let ids = ids
+        .into_iter()
+        .filter(|id| seen.insert(*id))
+        .collect::<Vec<_>>();
+
+    if ids.len() <= chunk_size {
+        return get(ids).await;
+    }
+
+    let mut documents = Vec::with_capacity(ids.len());
+    for chunk in ids.chunks(chunk_size) {
+        documents.extend(get(chunk.to_vec()).await?);
+    }
+
+    Ok(documents)
 }
@@ -3,12 +3,23 @@
 //! This module provides the fundamental traits that all stored documents must implement,
 //! as well as utilities for converting documents between different formats (BSON, JSON).
 
-use bson::{Bson, Uuid, de::deserialize_from_bson, ser::serialize_to_bson};
-use serde::{Deserialize, Serialize};
+use bson::{
+    Bson, RawBsonRef, RawDocument, RawDocumentBuf, Uuid,
+    de::{deserialize_from_bson, deserialize_from_slice},
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use serde_json::{Value, from_value, to_value};
-use std::any::Any;
+use std::{
+    any::{Any, TypeId, type_name},
+    collections::{BTreeMap, HashMap},
+    fmt,
+    marker::PhantomData,
+};
 
-use crate::error::DocumentStoreResult;
+use crate::{
+    encoding::{DEFAULT_ENCODING, Encoding},
+    error::{DocumentStoreError, DocumentStoreResult},
+};
 
 /// Core trait that all documents stored in a document store must implement.
 ///
@@ -17,11 +28,21 @@ use crate::error::DocumentStoreResult;
 ///
 /// # Deriving with `#[derive]`
 ///
-/// While `Document` cannot be automatically derived, you can derive its super-traits:
-/// - `Serialize` (from serde)
-/// - `Deserialize` (from serde)
-/// - `Clone`
-/// - `Debug`
+/// `Document` can be derived with `#[derive(Document)]` from the `doclayer` crate, alongside
+/// its super-traits (`Serialize`, `Deserialize` and `Clone`). The derive reads the `id` field
+/// and a `#[document(...)]` attribute:
+///
+/// ```ignore
+/// #[derive(Debug, Clone, Serialize, Deserialize, Document)]
+/// #[document(collection = "users", version = 2, upgrade_with = upgrade_user)]
+/// pub struct User {
+///     pub id: Uuid,
+///     pub email: String,
+/// }
+/// ```
+///
+/// Use `#[document(id)]` on a field to use a field other than `id` as the identifier, and
+/// `#[document(version)]` on an integer field to give documents a [`Document::version`].
 ///
 /// # Example
 ///
@@ -56,6 +77,169 @@ pub trait Document: Serialize + for<'de> Deserialize<'de> + Send + Sync + Clone
     /// This should be a static, lowercase identifier (e.g., "users", "products").
     /// The collection will be automatically created if it doesn't exist.
     fn collection_name() -> &'static str;
+
+    /// Returns the current schema version of this document type.
+    ///
+    /// Documents written with a version greater than `1` are stamped with
+    /// [`SCHEMA_VERSION_FIELD`]. When an older document is read, [`Document::upgrade_from`]
+    /// is called once per version step until it reaches the current version, so schema
+    /// changes don't always require an eager full-collection migration.
+    ///
+    /// Defaults to `1`. Documents without a stamp are treated as version `1`.
+    fn schema_version() -> u32 {
+        1
+    }
+
+    /// Upgrades a stored document from `version` to `version + 1`.
+    ///
+    /// Called lazily while reading documents older than [`Document::schema_version`].
+    /// The default implementation returns the document unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `version` - The version the document is currently at
+    /// * `document` - The raw stored document
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the document cannot be upgraded.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// fn upgrade_from(version: u32, mut document: Bson) -> DocumentStoreResult<Bson> {
+    ///     if version == 1 && let Some(doc) = document.as_document_mut() {
+    ///         // v2 renamed `mail` to `email`
+    ///         if let Some(email) = doc.remove("mail") {
+    ///             doc.insert("email", email);
+    ///         }
+    ///     }
+    ///     Ok(document)
+    /// }
+    /// ```
+    fn upgrade_from(version: u32, document: Bson) -> DocumentStoreResult<Bson> {
+        let _ = version;
+        Ok(document)
+    }
+
+    /// Returns the indexes this document type expects on its collection.
+    ///
+    /// Applied by [`DocumentStore::ensure_indexes`](crate::store::DocumentStore::ensure_indexes).
+    /// The derive fills this from `#[document(index(field = "..", unique))]` attributes.
+    ///
+    /// Defaults to no indexes.
+    fn index_definitions() -> Vec<IndexDefinition> {
+        Vec::new()
+    }
+
+    /// Returns the stored field holding the datetime at which this document expires.
+    ///
+    /// [`DocumentStore::ensure_indexes`](crate::store::DocumentStore::ensure_indexes) makes the
+    /// collection delete documents once it has passed (see the [`expiry`](crate::expiry)
+    /// module), and `insert_with_ttl` sets it. The derive fills this from
+    /// `#[document(expires = "..")]`.
+    ///
+    /// Defaults to `None`, for documents that don't expire.
+    fn expires_field() -> Option<&'static str> {
+        None
+    }
+
+    /// Returns the stored field marking this document as soft-deleted.
+    ///
+    /// Typed collections' `soft_delete` sets it to the deletion time and `restore` sets it back
+    /// to null, so it's usually an `Option<bson::DateTime>`. Reads through typed collections
+    /// leave out documents whose field is set, except for `query_with_deleted`. Writes, including
+    /// `update_where` and `delete_where`, apply to soft-deleted documents too, so `delete_where`
+    /// can purge them. The derive fills this from `#[document(soft_delete = "..")]`.
+    ///
+    /// Defaults to `None`, for documents without soft deletion.
+    fn deleted_field() -> Option<&'static str> {
+        None
+    }
+
+    /// Returns the version of this document, for optimistic concurrency control.
+    ///
+    /// [`TypedCollection::update_if_version`](crate::collection::TypedCollection::update_if_version)
+    /// replaces a stored document only if it is still at this version, and increments it, so
+    /// concurrent writers can't silently overwrite each other's changes. The derive reads the
+    /// field marked `#[document(version)]`, which is unrelated to the schema version set by
+    /// the container-level `#[document(version = N)]`.
+    ///
+    /// Defaults to `None`, for documents without a version.
+    fn version(&self) -> Option<u64> {
+        None
+    }
+
+    /// Sets the version returned by [`Document::version`].
+    ///
+    /// Does nothing by default.
+    fn set_version(&mut self, version: u64) {
+        let _ = version;
+    }
+
+    /// Returns the stored field holding the [`Document::version`].
+    ///
+    /// Defaults to `None`, for documents without a version.
+    fn version_field() -> Option<&'static str> {
+        None
+    }
+}
+
+/// An index declared by a [`Document`] type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IndexDefinition {
+    /// The indexed field, using dot notation for nested fields.
+    pub field: &'static str,
+    /// Whether the index enforces uniqueness.
+    pub unique: bool,
+}
+
+impl IndexDefinition {
+    /// Creates a non-unique index definition on a field.
+    pub const fn new(field: &'static str) -> Self {
+        Self { field, unique: false }
+    }
+
+    /// Makes the index enforce uniqueness.
+    pub const fn unique(mut self) -> Self {
+        self.unique = true;
+        self
+    }
+}
+
+/// The field used to stamp stored documents with their [`Document::schema_version`].
+pub const SCHEMA_VERSION_FIELD: &str = "_schema_version";
+
+/// Reads the version of a stored document from its version `field`, as compared by
+/// [`StoreBackend::update_documents_if_version`](crate::backend::StoreBackend::update_documents_if_version).
+///
+/// A document without the field is at version `0`, like documents written before their type
+/// had a version. Returns `None` if the field doesn't hold a non-negative integer.
+pub fn stored_version(document: &Bson, field: &str) -> Option<u64> {
+    match document
+        .as_document()
+        .and_then(|document| document.get(field))
+    {
+        None => Some(0),
+        Some(Bson::Int32(version)) => u64::try_from(*version).ok(),
+        Some(Bson::Int64(version)) => u64::try_from(*version).ok(),
+        Some(_) => None,
+    }
+}
+
+/// Reads the ID of a stored document from its ID `field`, whether stored as a binary or a
+/// string UUID.
+///
+/// Returns `None` if the field is missing or doesn't hold a UUID.
+pub fn stored_id(document: &Bson, field: &str) -> Option<Uuid> {
+    match document
+        .as_document()
+        .and_then(|document| document.get(field))?
+    {
+        Bson::Binary(binary) => binary.to_uuid().ok(),
+        Bson::String(id) => Uuid::parse_str(id).ok(),
+        _ => None,
+    }
 }
 
 /// Extension trait providing serialization/deserialization utilities for documents.
@@ -70,6 +254,15 @@ pub trait DocumentExt: Document {
     /// Returns an error if serialization fails.
     fn to_bson(&self) -> DocumentStoreResult<Bson>;
 
+    /// Converts this document to a BSON value for storage, in the given encoding.
+    ///
+    /// Typed collections use the encoding of their backend; see [`Encoding`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if serialization fails.
+    fn to_bson_encoded(&self, encoding: &Encoding) -> DocumentStoreResult<Bson>;
+
     /// Creates a document from a BSON value.
     ///
     /// # Errors
@@ -77,6 +270,29 @@ pub trait DocumentExt: Document {
     /// Returns an error if deserialization fails or the structure is invalid.
     fn from_bson(bson: Bson) -> DocumentStoreResult<Self>;
 
+    /// Creates a document from a BSON value stored in the given encoding.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if deserialization fails or the structure is invalid.
+    fn from_bson_encoded(bson: Bson, encoding: &Encoding) -> DocumentStoreResult<Self>;
+
+    /// Creates a document from a BSON value, filling missing fields from `Self::default()`.
+    ///
+    /// Fields absent from the stored document (including fields of embedded documents) are
+    /// taken from the serialized default value instead of failing deserialization.
+    ///
+    /// # Returns
+    ///
+    /// The document along with the dotted paths of every field that had to be filled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if deserialization still fails, e.g. because a field has the wrong type.
+    fn from_bson_with_defaults(bson: Bson) -> DocumentStoreResult<(Self, Vec<String>)>
+    where
+        Self: Default;
+
     /// Converts this document to a JSON value for serialization.
     ///
     /// # Errors
@@ -94,11 +310,34 @@ pub trait DocumentExt: Document {
 
 impl<D: Document> DocumentExt for D {
     fn to_bson(&self) -> DocumentStoreResult<Bson> {
-        Ok(serialize_to_bson(self)?)
+        self.to_bson_encoded(&DEFAULT_ENCODING)
+    }
+
+    fn to_bson_encoded(&self, encoding: &Encoding) -> DocumentStoreResult<Bson> {
+        let mut bson = encoding.serialize(self)?;
+
+        if Self::schema_version() > 1
+            && let Some(document) = bson.as_document_mut()
+        {
+            document.insert(SCHEMA_VERSION_FIELD, Self::schema_version() as i64);
+        }
+
+        Ok(bson)
     }
 
     fn from_bson(bson: Bson) -> DocumentStoreResult<Self> {
-        Ok(deserialize_from_bson(bson)?)
+        Self::from_bson_encoded(bson, &DEFAULT_ENCODING)
+    }
+
+    fn from_bson_encoded(bson: Bson, encoding: &Encoding) -> DocumentStoreResult<Self> {
+        encoding.deserialize(upgrade_document::<D>(bson)?)
+    }
+
+    fn from_bson_with_defaults(bson: Bson) -> DocumentStoreResult<(Self, Vec<String>)>
+    where
+        Self: Default,
+    {
+        from_bson_with_defaults_encoded(bson, &DEFAULT_ENCODING)
     }
 
     fn to_json(&self) -> DocumentStoreResult<Value> {
@@ -110,6 +349,236 @@ impl<D: Document> DocumentExt for D {
     }
 }
 
+/// Creates a document from a BSON value stored in the given encoding, filling missing fields
+/// from `D::default()`.
+///
+/// See [`DocumentExt::from_bson_with_defaults`].
+pub(crate) fn from_bson_with_defaults_encoded<D: Document + Default>(
+    bson: Bson,
+    encoding: &Encoding,
+) -> DocumentStoreResult<(D, Vec<String>)> {
+    let mut filled = encoding.serialize(&D::default())?;
+    let mut missing = Vec::new();
+
+    if let Some(defaults) = filled.as_document_mut() {
+        defaults.remove(SCHEMA_VERSION_FIELD);
+    }
+
+    match (filled.as_document_mut(), upgrade_document::<D>(bson)?) {
+        (Some(defaults), Bson::Document(stored)) => {
+            merge_over_defaults(defaults, stored, "", &mut missing)
+        }
+        (_, stored) => filled = stored,
+    }
+
+    Ok((encoding.deserialize(filled)?, missing))
+}
+
+/// Strips the schema version stamp from a stored document and applies
+/// [`Document::upgrade_from`] until it reaches the current schema version.
+pub(crate) fn upgrade_document<D: Document>(mut bson: Bson) -> DocumentStoreResult<Bson> {
+    let stored = match bson
+        .as_document_mut()
+        .and_then(|document| document.remove(SCHEMA_VERSION_FIELD))
+    {
+        Some(version) => version
+            .as_i64()
+            .or_else(|| version.as_i32().map(i64::from))
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| {
+                DocumentStoreError::InvalidDocument(format!(
+                    "Invalid {} value: {}",
+                    SCHEMA_VERSION_FIELD, version
+                ))
+            })?,
+        None => 1,
+    };
+
+    if stored > D::schema_version() {
+        return Err(DocumentStoreError::InvalidDocument(format!(
+            "Document schema version {} is newer than the supported version {} for collection {}",
+            stored,
+            D::schema_version(),
+            D::collection_name()
+        )));
+    }
+
+    for version in stored..D::schema_version() {
+        bson = D::upgrade_from(version, bson)?;
+    }
+
+    Ok(bson)
+}
+
+/// Overwrites `defaults` with the stored fields, recording the paths of defaulted fields.
+///
+/// Embedded documents present on both sides are merged recursively, so partially
+/// populated sub-documents are filled as well.
+fn merge_over_defaults(
+    defaults: &mut bson::Document,
+    mut stored: bson::Document,
+    prefix: &str,
+    missing: &mut Vec<String>,
+) {
+    for (key, default) in defaults.iter_mut() {
+        let path = format!("{}{}", prefix, key);
+
+        match (default, stored.remove(key)) {
+            (Bson::Document(default), Some(Bson::Document(value))) => {
+                merge_over_defaults(default, value, &format!("{}.", path), missing)
+            }
+            (default, Some(value)) => *default = value,
+            (_, None) => missing.push(path),
+        }
+    }
+
+    defaults.extend(stored);
+}
+
+/// Documents read with missing fields filled from defaults, along with a report of
+/// which documents were incomplete.
+///
+/// Returned by `get_with_defaults` and `query_with_defaults` on typed collections. This eases
+/// rollouts where a migration adding fields lags behind the code that reads them.
+#[derive(Debug, Clone)]
+pub struct FilledDocuments<D> {
+    /// The documents that were read, in backend order.
+    pub documents: Vec<D>,
+    /// The documents that had at least one field filled from defaults.
+    pub incomplete: Vec<IncompleteDocument>,
+}
+
+impl<D: Document + Default> FilledDocuments<D> {
+    pub(crate) fn from_bson(
+        documents: Vec<Bson>,
+        encoding: &Encoding,
+    ) -> DocumentStoreResult<Self> {
+        let mut filled = FilledDocuments {
+            documents: Vec::with_capacity(documents.len()),
+            incomplete: Vec::new(),
+        };
+
+        for bson in documents {
+            let (document, missing_fields) = from_bson_with_defaults_encoded::<D>(bson, encoding)?;
+
+            if !missing_fields.is_empty() {
+                filled
+                    .incomplete
+                    .push(IncompleteDocument { id: *document.id(), missing_fields });
+            }
+            filled.documents.push(document);
+        }
+
+        Ok(filled)
+    }
+}
+
+impl<D> FilledDocuments<D> {
+    /// Returns `true` if no document needed any field filled.
+    pub fn is_complete(&self) -> bool {
+        self.incomplete.is_empty()
+    }
+}
+
+/// A document that was missing fields when it was read.
+#[derive(Debug, Clone)]
+pub struct IncompleteDocument {
+    /// The ID of the incomplete document.
+    pub id: Uuid,
+    /// Dotted paths of the fields that were filled from defaults.
+    pub missing_fields: Vec<String>,
+}
+
+/// A stored document kept as raw BSON bytes until it is needed.
+///
+/// Returned by `query_raw` on typed collections. Reading a few fields with [`get`](RawDoc::get)
+/// or [`get_as`](RawDoc::get_as) avoids decoding the rest of the document, while
+/// [`parse`](RawDoc::parse) deserializes it into `D` on demand.
+///
+/// Fields read directly are the stored values, before any [`Document::upgrade_from`] step;
+/// only [`parse`](RawDoc::parse) applies schema upgrades.
+#[derive(Debug, Clone)]
+pub struct RawDoc<D> {
+    raw: RawDocumentBuf,
+    _marker: PhantomData<fn() -> D>,
+}
+
+impl<D: Document> RawDoc<D> {
+    /// Wraps raw BSON bytes of a stored document of type `D`.
+    pub fn new(raw: RawDocumentBuf) -> Self {
+        RawDoc { raw, _marker: PhantomData }
+    }
+
+    /// Deserializes the whole document into `D`, applying schema upgrades.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the document doesn't deserialize into `D`.
+    pub fn parse(&self) -> DocumentStoreResult<D> {
+        // Documents without upgrade steps are decoded straight from the bytes
+        if D::schema_version() == 1 {
+            return Ok(deserialize_from_slice(self.raw.as_bytes())?);
+        }
+
+        D::from_bson(Bson::Document(bson::Document::try_from(&*self.raw)?))
+    }
+
+    /// Returns the raw value of a field, without decoding the rest of the document.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field name, with dot notation selecting fields of embedded documents
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bytes along the path are malformed.
+    pub fn get(&self, field: &str) -> DocumentStoreResult<Option<RawBsonRef<'_>>> {
+        let mut segments = field.split('.');
+        let mut value = match segments.next() {
+            Some(segment) => self.raw.get(segment)?,
+            None => None,
+        };
+
+        for segment in segments {
+            value = match value {
+                Some(RawBsonRef::Document(document)) => document.get(segment)?,
+                _ => return Ok(None),
+            };
+        }
+
+        Ok(value)
+    }
+
+    /// Deserializes a single field into `T`.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field name, with dot notation selecting fields of embedded documents
+    ///
+    /// # Returns
+    ///
+    /// The field's value, or `None` if the field is missing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value doesn't deserialize into `T`.
+    pub fn get_as<T: DeserializeOwned>(&self, field: &str) -> DocumentStoreResult<Option<T>> {
+        self.get(field)?
+            .map(|value| Ok(deserialize_from_bson(Bson::try_from(value)?)?))
+            .transpose()
+    }
+
+    /// Returns the underlying raw document.
+    pub fn as_raw(&self) -> &RawDocument {
+        &self.raw
+    }
+
+    /// Consumes the wrapper, returning the raw document.
+    pub fn into_raw(self) -> RawDocumentBuf {
+        self.raw
+    }
+}
+
 /// Type-erased document trait that allows working with documents of different types uniformly.
 ///
 /// This trait enables dynamic dispatch for documents when the concrete type is not known
@@ -197,6 +666,367 @@ impl<D: Document> AnyDocument for D {
     }
 }
 
+/// Deserializes a stored document into a type-erased [`AnyDocument`].
+pub type AnyDocumentDeserializer = fn(Bson) -> DocumentStoreResult<Box<dyn AnyDocument>>;
+
+/// Runtime registry mapping collections to the document types stored in them.
+///
+/// The registry lets code deserialize documents without compile-time knowledge of every
+/// type, e.g. in plugin architectures where each plugin registers its own documents. A
+/// collection can hold a single type, or several types told apart by a discriminator field.
+///
+/// # Example
+///
+/// ```ignore
+/// use doclayer::document::DocumentRegistry;
+///
+/// let registry = DocumentRegistry::new()
+///     .with_document::<User>()
+///     .with_variant::<Circle>("kind", "circle")
+///     .with_variant::<Square>("kind", "square");
+///
+/// for document in store.collection("shapes").query_any(Query::new(), &registry).await? {
+///     if let Some(circle) = document.downcast_ref::<Circle>() {
+///         println!("circle with radius {}", circle.radius);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DocumentRegistry {
+    documents: HashMap<String, AnyDocumentDeserializer>,
+    variants: HashMap<String, Vec<(String, Bson, AnyDocumentDeserializer)>>,
+}
+
+impl DocumentRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `D` as the type of every document in `D::collection_name()`.
+    ///
+    /// Registered variants of the collection take precedence over this type.
+    pub fn with_document<D: Document>(mut self) -> Self {
+        self.documents
+            .insert(D::collection_name().to_string(), Self::deserializer::<D>());
+        self
+    }
+
+    /// Registers `D` for documents in `D::collection_name()` whose `field` equals `value`.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The discriminator field
+    /// * `value` - The discriminator value identifying `D`
+    pub fn with_variant<D: Document>(
+        mut self,
+        field: impl Into<String>,
+        value: impl Into<Bson>,
+    ) -> Self {
+        self.variants
+            .entry(D::collection_name().to_string())
+            .or_default()
+            .push((field.into(), value.into(), Self::deserializer::<D>()));
+        self
+    }
+
+    /// Adds every registration from `other` to this registry.
+    ///
+    /// Document types registered in `other` replace those of this registry for the same
+    /// collection; variants of both registries are kept.
+    pub fn merge(mut self, other: DocumentRegistry) -> Self {
+        self.documents.extend(other.documents);
+        for (collection, variants) in other.variants {
+            self.variants
+                .entry(collection)
+                .or_default()
+                .extend(variants);
+        }
+        self
+    }
+
+    /// Returns `true` if any type is registered for the collection.
+    pub fn contains(&self, collection: &str) -> bool {
+        self.documents.contains_key(collection) || self.variants.contains_key(collection)
+    }
+
+    /// Deserializes a document from a collection into its registered type.
+    ///
+    /// # Arguments
+    ///
+    /// * `collection` - The collection the document was read from
+    /// * `document` - The stored document
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::InvalidDocument`] if no registered type matches the
+    /// document, or a serialization error if deserialization fails.
+    pub fn deserialize(
+        &self,
+        collection: &str,
+        document: Bson,
+    ) -> DocumentStoreResult<Box<dyn AnyDocument>> {
+        let variant = self
+            .variants
+            .get(collection)
+            .and_then(|variants| {
+                variants
+                    .iter()
+                    .find(|(field, value, _)| {
+                        document
+                            .as_document()
+                            .and_then(|doc| doc.get(field))
+                            .is_some_and(|discriminator| discriminator == value)
+                    })
+            });
+
+        match variant
+            .map(|(_, _, deserializer)| deserializer)
+            .or_else(|| self.documents.get(collection))
+        {
+            Some(deserializer) => deserializer(document),
+            None => Err(DocumentStoreError::InvalidDocument(format!(
+                "No document type registered for a document in collection {}",
+                collection
+            ))),
+        }
+    }
+
+    fn deserializer<D: Document>() -> AnyDocumentDeserializer {
+        |bson| Ok(Box::new(D::from_bson(bson)?))
+    }
+}
+
+/// A document type recorded by [`register_documents!`](crate::register_documents).
+#[derive(Debug, Clone)]
+pub struct DocumentType {
+    type_id: TypeId,
+    type_name: &'static str,
+    collection: &'static str,
+    schema_version: u32,
+    discriminator: Option<(&'static str, Bson)>,
+    register: fn(DocumentRegistry, Option<(&'static str, Bson)>) -> DocumentRegistry,
+}
+
+impl DocumentType {
+    /// Records the document type `D`, as the type of every document in its collection.
+    pub fn of<D: Document>() -> Self {
+        Self {
+            type_id: TypeId::of::<D>(),
+            type_name: type_name::<D>(),
+            collection: D::collection_name(),
+            schema_version: D::schema_version(),
+            discriminator: None,
+            register: |registry, discriminator| match discriminator {
+                Some((field, value)) => registry.with_variant::<D>(field, value),
+                None => registry.with_document::<D>(),
+            },
+        }
+    }
+
+    /// Records the document type `D` as a variant of its collection, for the documents whose
+    /// `field` equals `value`, like [`DocumentRegistry::with_variant`].
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The discriminator field
+    /// * `value` - The discriminator value identifying `D`
+    pub fn variant<D: Document>(field: &'static str, value: impl Into<Bson>) -> Self {
+        Self {
+            discriminator: Some((field, value.into())),
+            ..Self::of::<D>()
+        }
+    }
+
+    /// Returns the Rust type name of the document.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// Returns the collection the document is stored in.
+    pub fn collection(&self) -> &'static str {
+        self.collection
+    }
+
+    /// Returns the document's current schema version.
+    pub fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+
+    /// Returns the discriminator field and value of a variant type.
+    pub fn discriminator(&self) -> Option<(&'static str, &Bson)> {
+        self.discriminator
+            .as_ref()
+            .map(|(field, value)| (*field, value))
+    }
+}
+
+/// The document types of an application, built with [`register_documents!`](crate::register_documents).
+///
+/// Listing every document type in one place makes it possible to check that no two types
+/// share a collection by accident, which otherwise goes unnoticed until documents of one type
+/// fail to deserialize as the other. Types sharing a collection on purpose are declared as
+/// variants, told apart by a discriminator field.
+#[derive(Debug, Clone, Default)]
+pub struct DocumentTypes {
+    types: Vec<DocumentType>,
+}
+
+/// Several document types declaring the same collection without telling their documents
+/// apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectionConflict {
+    /// The shared collection.
+    pub collection: &'static str,
+    /// The type names of the conflicting document types.
+    pub types: Vec<&'static str>,
+}
+
+impl fmt::Display for CollectionConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "collection `{}` is declared by {}", self.collection, self.types.join(", "))
+    }
+}
+
+impl DocumentTypes {
+    /// Creates a set from the given types, ignoring repeated registrations of the same type.
+    pub fn new(types: impl IntoIterator<Item = DocumentType>) -> Self {
+        let mut unique: Vec<DocumentType> = Vec::new();
+        for document in types {
+            if !unique
+                .iter()
+                .any(|known| known.type_id == document.type_id)
+            {
+                unique.push(document);
+            }
+        }
+
+        Self { types: unique }
+    }
+
+    /// Returns the registered types in registration order.
+    pub fn types(&self) -> &[DocumentType] {
+        &self.types
+    }
+
+    /// Returns the registered type stored in `collection`, if exactly one is.
+    pub fn get(&self, collection: &str) -> Option<&DocumentType> {
+        let mut matching = self
+            .types
+            .iter()
+            .filter(|document| document.collection == collection);
+
+        match (matching.next(), matching.next()) {
+            (Some(document), None) => Some(document),
+            _ => None,
+        }
+    }
+
+    /// Returns the types whose documents can't be told apart, ordered by collection name.
+    ///
+    /// A collection may hold any number of variants with distinct discriminators, and one
+    /// type without a discriminator for the documents matching none of them. Several types
+    /// without a discriminator conflict, as do variants sharing a discriminator.
+    pub fn conflicts(&self) -> Vec<CollectionConflict> {
+        let mut collections: BTreeMap<&'static str, Vec<&DocumentType>> = BTreeMap::new();
+        for document in &self.types {
+            collections
+                .entry(document.collection)
+                .or_default()
+                .push(document);
+        }
+
+        let mut conflicts = Vec::new();
+        for (collection, documents) in collections {
+            // Types conflict when they'd be registered under the same discriminator, or none
+            let mut groups = Vec::<(_, Vec<&'static str>)>::new();
+            for document in documents {
+                let discriminator = document.discriminator();
+                match groups
+                    .iter_mut()
+                    .find(|(known, _)| *known == discriminator)
+                {
+                    Some((_, types)) => types.push(document.type_name),
+                    None => groups.push((discriminator, vec![document.type_name])),
+                }
+            }
+
+            conflicts.extend(
+                groups
+                    .into_iter()
+                    .filter(|(_, types)| types.len() > 1)
+                    .map(|(_, types)| CollectionConflict { collection, types }),
+            );
+        }
+
+        conflicts
+    }
+
+    /// Checks that the documents of every collection can be told apart by type.
+    ///
+    /// # Errors
+    ///
+    /// Returns every [`CollectionConflict`] found.
+    pub fn validate(&self) -> Result<(), Vec<CollectionConflict>> {
+        let conflicts = self.conflicts();
+
+        match conflicts.is_empty() {
+            true => Ok(()),
+            false => Err(conflicts),
+        }
+    }
+
+    /// Builds a [`DocumentRegistry`] registering each type for its collection, and each
+    /// variant for its discriminator.
+    ///
+    /// When types conflict, the one registered last wins; call [`validate`](Self::validate) first.
+    pub fn registry(&self) -> DocumentRegistry {
+        self.types
+            .iter()
+            .fold(DocumentRegistry::new(), |registry, document| {
+                (document.register)(registry, document.discriminator.clone())
+            })
+    }
+}
+
+/// Lists an application's document types as [`DocumentTypes`].
+///
+/// Types sharing a collection are listed with their discriminator field and value, as in
+/// `Circle { "kind" => "circle" }`.
+///
+/// # Example
+///
+/// ```ignore
+/// use doclayer::register_documents;
+///
+/// let documents = register_documents!(
+///     User,
+///     Order,
+///     Circle { "kind" => "circle" },
+///     Square { "kind" => "square" },
+/// );
+///
+/// if let Err(conflicts) = documents.validate() {
+///     for conflict in conflicts {
+///         eprintln!("{conflict}");
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! register_documents {
+    (@type $document:ty) => {
+        $crate::document::DocumentType::of::<$document>()
+    };
+    (@type $document:ty, $field:expr, $value:expr) => {
+        $crate::document::DocumentType::variant::<$document>($field, $value)
+    };
+    ($($document:ty $({ $field:expr => $value:expr })?),* $(,)?) => {
+        $crate::document::DocumentTypes::new([
+            $($crate::register_documents!(@type $document $(, $field, $value)?)),*
+        ])
+    };
+}
+
 impl Clone for Box<dyn AnyDocument> {
     fn clone(&self) -> Box<dyn AnyDocument> {
         self.clone_box()
@@ -0,0 +1,1116 @@
+//! Store-level configuration of how documents are represented in BSON.
+//!
+//! By default documents are stored the way `bson` serializes them: UUIDs as binary values,
+//! enums externally tagged (`{ "Variant": { ... } }`, or a string for unit variants) and
+//! datetimes with millisecond precision. An [`Encoding`] changes these representations for
+//! every typed collection of a store, for example to share a database with applications that
+//! store UUIDs as strings:
+//!
+//! ```ignore
+//! use doclayer::encoding::{Encoding, EnumRepresentation, UuidRepresentation};
+//!
+//! let store = InMemoryStore::builder()
+//!     .with_encoding(
+//!         Encoding::new()
+//!             .with_uuid_representation(UuidRepresentation::String)
+//!             .with_enum_representation(EnumRepresentation::adjacent("type", "value")),
+//!     )
+//!     .build()
+//!     .await?;
+//! ```
+//!
+//! The encoding is applied when typed collections convert documents with
+//! [`DocumentExt::to_bson_encoded`](crate::document::DocumentExt::to_bson_encoded) and
+//! [`DocumentExt::from_bson_encoded`](crate::document::DocumentExt::from_bson_encoded), to the
+//! UUIDs in their filters and updates, and by backends to the document IDs they use as keys.
+//! Untyped collections read and write BSON as given.
+//!
+//! # Changing representations
+//!
+//! Documents written before an encoding change keep their old representation. During the
+//! transition, [`Encoding::with_compatibility`] reads a document that doesn't decode with the
+//! new encoding using the previous one, and matches UUIDs in either representation. Each
+//! document is decoded with a single encoding, which holds as long as documents are always
+//! written whole.
+
+use bson::{Binary, Bson, Uuid, spec::BinarySubtype};
+use serde::{
+    Serialize, Serializer,
+    de::{
+        self, DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess, IntoDeserializer,
+        MapAccess, SeqAccess, VariantAccess, Visitor,
+    },
+    ser::{
+        SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+        SerializeTupleStruct, SerializeTupleVariant,
+    },
+};
+
+use crate::{
+    aggregate::Aggregate,
+    error::DocumentStoreResult,
+    query::{Expr, FieldOp, Query},
+    update::{Update, UpdateOp},
+};
+
+/// The encoding of backends that weren't configured with one.
+pub(crate) static DEFAULT_ENCODING: Encoding = Encoding::new();
+
+/// How UUIDs are stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum UuidRepresentation {
+    /// As BSON binary values of the UUID subtype.
+    #[default]
+    Binary,
+    /// As hyphenated lowercase strings.
+    String,
+}
+
+/// How enums are stored.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum EnumRepresentation {
+    /// As serde derives them: unit variants as strings, other variants as a document with the
+    /// variant name as its only key.
+    #[default]
+    External,
+    /// As a document holding the variant name in the `tag` field and its data, if any, in the
+    /// `content` field.
+    Adjacent {
+        /// The field holding the variant name.
+        tag: String,
+        /// The field holding the variant data.
+        content: String,
+    },
+}
+
+impl EnumRepresentation {
+    /// Stores enums adjacently tagged, with the variant name in `tag` and its data in `content`.
+    pub fn adjacent(tag: impl Into<String>, content: impl Into<String>) -> Self {
+        EnumRepresentation::Adjacent { tag: tag.into(), content: content.into() }
+    }
+}
+
+/// The precision datetimes are stored with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum DateTimePrecision {
+    /// Milliseconds, the precision of BSON datetimes.
+    #[default]
+    Milliseconds,
+    /// Whole seconds, truncating the milliseconds.
+    Seconds,
+}
+
+/// The representation of UUIDs, enums and datetimes in stored documents.
+///
+/// The default encoding stores documents exactly as `bson` serializes them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Encoding {
+    uuid: UuidRepresentation,
+    enums: EnumRepresentation,
+    datetime: DateTimePrecision,
+    compatibility: Option<Box<Encoding>>,
+}
+
+impl Encoding {
+    /// Creates the default encoding.
+    pub const fn new() -> Self {
+        Self {
+            uuid: UuidRepresentation::Binary,
+            enums: EnumRepresentation::External,
+            datetime: DateTimePrecision::Milliseconds,
+            compatibility: None,
+        }
+    }
+
+    /// Sets how UUIDs are stored, as binary values by default.
+    pub fn with_uuid_representation(mut self, uuid: UuidRepresentation) -> Self {
+        self.uuid = uuid;
+        self
+    }
+
+    /// Sets how enums are stored, externally tagged by default.
+    pub fn with_enum_representation(mut self, enums: EnumRepresentation) -> Self {
+        self.enums = enums;
+        self
+    }
+
+    /// Sets the precision datetimes are stored with, milliseconds by default.
+    pub fn with_datetime_precision(mut self, datetime: DateTimePrecision) -> Self {
+        self.datetime = datetime;
+        self
+    }
+
+    /// Also reads documents and matches UUIDs written with a previous encoding.
+    ///
+    /// Documents are still written with this encoding only. A document that fails to decode
+    /// is decoded again with `previous`, and UUIDs in filters and document keys match both
+    /// representations.
+    ///
+    /// # Arguments
+    ///
+    /// * `previous` - The encoding documents were written with before this one
+    pub fn with_compatibility(mut self, previous: Encoding) -> Self {
+        self.compatibility = Some(Box::new(previous));
+        self
+    }
+
+    /// Returns how UUIDs are stored.
+    pub fn uuid_representation(&self) -> UuidRepresentation {
+        self.uuid
+    }
+
+    /// Returns how enums are stored.
+    pub fn enum_representation(&self) -> &EnumRepresentation {
+        &self.enums
+    }
+
+    /// Returns the precision datetimes are stored with.
+    pub fn datetime_precision(&self) -> DateTimePrecision {
+        self.datetime
+    }
+
+    /// Returns the previous encoding documents are also read with, if any.
+    pub fn compatibility(&self) -> Option<&Encoding> {
+        self.compatibility.as_deref()
+    }
+
+    /// Serializes a value to BSON in this encoding.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Serialization`](crate::error::DocumentStoreError::Serialization)
+    /// if the value can't be serialized.
+    pub fn serialize<T: Serialize + ?Sized>(&self, value: &T) -> DocumentStoreResult<Bson> {
+        let bson = match &self.enums {
+            EnumRepresentation::External => bson::serialize_to_bson(value)?,
+            EnumRepresentation::Adjacent { tag, content } => {
+                bson::serialize_to_bson(&Tagged { value, tag, content })?
+            }
+        };
+
+        Ok(self.encode_value(bson))
+    }
+
+    /// Deserializes a value from BSON in this encoding, or in the previous encoding if it
+    /// doesn't decode with this one.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Serialization`](crate::error::DocumentStoreError::Serialization)
+    /// with the error of this encoding if the value decodes with neither.
+    pub fn deserialize<T: DeserializeOwned>(&self, bson: Bson) -> DocumentStoreResult<T> {
+        let Some(previous) = &self.compatibility else {
+            return self.deserialize_strict(bson);
+        };
+
+        self.deserialize_strict(bson.clone())
+            .or_else(|e| {
+                previous
+                    .deserialize(bson)
+                    .map_err(|_| e)
+            })
+    }
+
+    /// Converts the UUIDs and datetimes of a BSON value to this encoding.
+    ///
+    /// Embedded documents and arrays are converted recursively. Enums can't be recognized in
+    /// serialized BSON, so they are left as they are.
+    pub fn encode_value(&self, value: Bson) -> Bson {
+        if self.uuid == UuidRepresentation::Binary
+            && self.datetime == DateTimePrecision::Milliseconds
+        {
+            return value;
+        }
+
+        match value {
+            Bson::Binary(binary)
+                if binary.subtype == BinarySubtype::Uuid
+                    && self.uuid == UuidRepresentation::String =>
+            {
+                match binary.to_uuid() {
+                    Ok(uuid) => Bson::String(uuid.to_string()),
+                    Err(_) => Bson::Binary(binary),
+                }
+            }
+            Bson::DateTime(datetime) if self.datetime == DateTimePrecision::Seconds => {
+                let millis = datetime.timestamp_millis();
+                Bson::DateTime(bson::DateTime::from_millis(millis - millis.rem_euclid(1000)))
+            }
+            Bson::Document(document) => Bson::Document(
+                document
+                    .into_iter()
+                    .map(|(key, value)| (key, self.encode_value(value)))
+                    .collect(),
+            ),
+            Bson::Array(values) => Bson::Array(
+                values
+                    .into_iter()
+                    .map(|value| self.encode_value(value))
+                    .collect(),
+            ),
+            value => value,
+        }
+    }
+
+    /// Returns a document ID as it is stored in this encoding.
+    pub fn encode_id(&self, id: Uuid) -> Bson {
+        encode_uuid(id, self.uuid)
+    }
+
+    /// Returns every stored form of a document ID that should match it.
+    ///
+    /// This is the ID in this encoding, followed by the ID in the previous encoding when it
+    /// uses another UUID representation.
+    pub fn id_candidates(&self, id: Uuid) -> Vec<Bson> {
+        self.uuid_representations()
+            .into_iter()
+            .map(|representation| encode_uuid(id, representation))
+            .collect()
+    }
+
+    /// Converts the UUIDs compared against in a filter to this encoding.
+    ///
+    /// With a previous encoding storing UUIDs differently, equality comparisons match both
+    /// representations. Other values are compared as given.
+    pub fn encode_filter(&self, filter: Expr) -> Expr {
+        if self.uuid_representations() == [UuidRepresentation::Binary] {
+            return filter;
+        }
+
+        match filter {
+            Expr::And(exprs) => Expr::And(
+                exprs
+                    .into_iter()
+                    .map(|expr| self.encode_filter(expr))
+                    .collect(),
+            ),
+            Expr::Or(exprs) => Expr::Or(
+                exprs
+                    .into_iter()
+                    .map(|expr| self.encode_filter(expr))
+                    .collect(),
+            ),
+            Expr::Not(expr) => Expr::Not(Box::new(self.encode_filter(*expr))),
+            Expr::CaseInsensitive(expr) => {
+                Expr::CaseInsensitive(Box::new(self.encode_filter(*expr)))
+            }
+            Expr::ElemMatch { field, expr } => Expr::ElemMatch {
+                field,
+                expr: Box::new(self.encode_filter(*expr)),
+            },
+            Expr::Range { field, low, high } => Expr::Range {
+                field,
+                low: low.map(|value| self.encode_uuids(value)),
+                high: high.map(|value| self.encode_uuids(value)),
+            },
+            Expr::Field { field, op, value } => self.encode_comparison(field, op, value),
+            expr @ (Expr::Exists(..) | Expr::Custom { .. }) => expr,
+        }
+    }
+
+    /// Converts the UUIDs and datetimes written by an update to this encoding.
+    pub fn encode_update(&self, update: Update) -> Update {
+        Update {
+            ops: update
+                .ops
+                .into_iter()
+                .map(|op| match op {
+                    UpdateOp::Set(field, value) => UpdateOp::Set(field, self.encode_value(value)),
+                    UpdateOp::Inc(field, value) => UpdateOp::Inc(field, self.encode_value(value)),
+                    UpdateOp::Push(field, value) => UpdateOp::Push(field, self.encode_value(value)),
+                    UpdateOp::Pull(field, value) => UpdateOp::Pull(field, self.encode_value(value)),
+                    op @ UpdateOp::Unset(_) => op,
+                })
+                .collect(),
+        }
+    }
+
+    /// Converts the UUIDs compared against in a query's filter to this encoding.
+    pub fn encode_query(&self, query: Query) -> Query {
+        Query {
+            filter: query
+                .filter
+                .map(|filter| self.encode_filter(filter)),
+            ..query
+        }
+    }
+
+    /// Converts the UUIDs compared against in an aggregation's filter to this encoding.
+    pub fn encode_aggregate(&self, aggregate: Aggregate) -> Aggregate {
+        Aggregate {
+            filter: aggregate
+                .filter
+                .map(|filter| self.encode_filter(filter)),
+            ..aggregate
+        }
+    }
+
+    /// Deserializes a value in this encoding only.
+    fn deserialize_strict<T: DeserializeOwned>(&self, bson: Bson) -> DocumentStoreResult<T> {
+        Ok(match &self.enums {
+            EnumRepresentation::External => bson::deserialize_from_bson(bson)?,
+            EnumRepresentation::Adjacent { tag, content } => {
+                T::deserialize(TaggedDeserializer { value: bson, tag, content })?
+            }
+        })
+    }
+
+    /// Deserializes a value through a seed in this encoding, without falling back to the
+    /// previous one.
+    pub(crate) fn deserialize_seed<'de, S: DeserializeSeed<'de>>(
+        &self,
+        seed: S,
+        bson: Bson,
+    ) -> Result<S::Value, bson::error::Error> {
+        match &self.enums {
+            EnumRepresentation::External => seed.deserialize(bson::Deserializer::new(bson)),
+            EnumRepresentation::Adjacent { tag, content } => {
+                seed.deserialize(TaggedDeserializer { value: bson, tag, content })
+            }
+        }
+    }
+
+    /// The UUID representations matched when reading, this encoding's first.
+    fn uuid_representations(&self) -> Vec<UuidRepresentation> {
+        let mut representations = vec![self.uuid];
+
+        if let Some(previous) = &self.compatibility {
+            for representation in previous.uuid_representations() {
+                if !representations.contains(&representation) {
+                    representations.push(representation);
+                }
+            }
+        }
+
+        representations
+    }
+
+    /// Converts UUIDs, and only UUIDs, to this encoding.
+    fn encode_uuids(&self, value: Bson) -> Bson {
+        match value {
+            Bson::Binary(binary) if binary.subtype == BinarySubtype::Uuid => match binary.to_uuid()
+            {
+                Ok(uuid) => encode_uuid(uuid, self.uuid),
+                Err(_) => Bson::Binary(binary),
+            },
+            Bson::Document(document) => Bson::Document(
+                document
+                    .into_iter()
+                    .map(|(key, value)| (key, self.encode_uuids(value)))
+                    .collect(),
+            ),
+            Bson::Array(values) => Bson::Array(
+                values
+                    .into_iter()
+                    .map(|value| self.encode_uuids(value))
+                    .collect(),
+            ),
+            value => value,
+        }
+    }
+
+    /// Converts a field comparison, matching a UUID in every readable representation.
+    fn encode_comparison(&self, field: String, op: FieldOp, value: Bson) -> Expr {
+        let candidates = |value: &Bson| match uuid_of(value) {
+            Some(uuid) => self.id_candidates(uuid),
+            None => vec![self.encode_uuids(value.clone())],
+        };
+
+        match op {
+            FieldOp::Eq | FieldOp::Ne if uuid_of(&value).is_some() => {
+                let exprs = candidates(&value)
+                    .into_iter()
+                    .map(|candidate| Expr::field(field.clone(), op.clone(), candidate))
+                    .collect::<Vec<_>>();
+
+                match (exprs.len(), op) {
+                    (1, _) => exprs
+                        .into_iter()
+                        .next()
+                        .unwrap_or(Expr::And(Vec::new())),
+                    (_, FieldOp::Eq) => Expr::Or(exprs),
+                    _ => Expr::And(exprs),
+                }
+            }
+            FieldOp::AnyOf | FieldOp::NoneOf => {
+                let values = match value {
+                    Bson::Array(values) => values,
+                    value => vec![value],
+                };
+
+                Expr::field(
+                    field,
+                    op,
+                    Bson::Array(
+                        values
+                            .iter()
+                            .flat_map(candidates)
+                            .collect(),
+                    ),
+                )
+            }
+            op => Expr::field(field, op, self.encode_uuids(value)),
+        }
+    }
+}
+
+/// Returns a UUID stored in the given representation.
+fn encode_uuid(uuid: Uuid, representation: UuidRepresentation) -> Bson {
+    match representation {
+        UuidRepresentation::Binary => Bson::Binary(Binary::from(uuid)),
+        UuidRepresentation::String => Bson::String(uuid.to_string()),
+    }
+}
+
+/// Reads the UUID held by a binary value.
+fn uuid_of(value: &Bson) -> Option<Uuid> {
+    match value {
+        Bson::Binary(binary) if binary.subtype == BinarySubtype::Uuid => binary.to_uuid().ok(),
+        _ => None,
+    }
+}
+
+/// Whether a newtype is one of the types `bson` serializes specially, such as UUIDs and
+/// datetimes, which are passed through untouched.
+fn is_bson_newtype(name: &str) -> bool {
+    name.starts_with("$__")
+}
+
+/// A value serialized with adjacently tagged enums.
+struct Tagged<'a, T: ?Sized> {
+    value: &'a T,
+    tag: &'a str,
+    content: &'a str,
+}
+
+impl<T: Serialize + ?Sized> Serialize for Tagged<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(TaggedSerializer {
+            inner: serializer,
+            tag: self.tag,
+            content: self.content,
+        })
+    }
+}
+
+/// A serializer writing enums adjacently tagged and forwarding everything else.
+struct TaggedSerializer<'a, S> {
+    inner: S,
+    tag: &'a str,
+    content: &'a str,
+}
+
+impl<'a, S> TaggedSerializer<'a, S> {
+    fn wrap<'v, T: ?Sized>(&self, value: &'v T) -> Tagged<'v, T>
+    where
+        'a: 'v,
+    {
+        Tagged {
+            value,
+            tag: self.tag,
+            content: self.content,
+        }
+    }
+}
+
+/// Wraps the compound serializers of the inner serializer, tagging the values written to them.
+struct Compound<'a, C> {
+    inner: C,
+    tag: &'a str,
+    content: &'a str,
+}
+
+impl<'a, C> Compound<'a, C> {
+    fn wrap<'v, T: ?Sized>(&self, value: &'v T) -> Tagged<'v, T>
+    where
+        'a: 'v,
+    {
+        Tagged {
+            value,
+            tag: self.tag,
+            content: self.content,
+        }
+    }
+}
+
+/// Buffers the data of a tuple or struct variant, which is written once it is complete.
+struct VariantBuffer<'a, S> {
+    inner: S,
+    tag: &'a str,
+    content: &'a str,
+    variant: &'static str,
+    data: Bson,
+}
+
+impl<S: Serializer> VariantBuffer<'_, S> {
+    fn push<T: Serialize + ?Sized>(
+        &mut self,
+        key: Option<&str>,
+        value: &T,
+    ) -> Result<(), S::Error> {
+        let value = bson::serialize_to_bson(&Tagged {
+            value,
+            tag: self.tag,
+            content: self.content,
+        })
+        .map_err(serde::ser::Error::custom)?;
+
+        match (&mut self.data, key) {
+            (Bson::Document(document), Some(key)) => {
+                document.insert(key, value);
+            }
+            (Bson::Array(values), _) => values.push(value),
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<S::Ok, S::Error> {
+        let mut map = self.inner.serialize_map(Some(2))?;
+        map.serialize_entry(self.tag, self.variant)?;
+        map.serialize_entry(self.content, &self.data)?;
+        map.end()
+    }
+}
+
+impl<'a, S: Serializer> Serializer for TaggedSerializer<'a, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Compound<'a, S::SerializeSeq>;
+    type SerializeTuple = Compound<'a, S::SerializeTuple>;
+    type SerializeTupleStruct = Compound<'a, S::SerializeTupleStruct>;
+    type SerializeTupleVariant = VariantBuffer<'a, S>;
+    type SerializeMap = Compound<'a, S::SerializeMap>;
+    type SerializeStruct = Compound<'a, S::SerializeStruct>;
+    type SerializeStructVariant = VariantBuffer<'a, S>;
+
+    fn serialize_bool(self, v: bool) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_bool(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i8(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i16(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i32(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i64(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u8(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u16(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u32(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u64(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_f32(v)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_f64(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_char(v)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_str(v)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_bytes(v)
+    }
+
+    fn serialize_none(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_none()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<S::Ok, S::Error> {
+        let value = self.wrap(value);
+        self.inner.serialize_some(&value)
+    }
+
+    fn serialize_unit(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit()
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit_struct(name)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<S::Ok, S::Error> {
+        let mut map = self.inner.serialize_map(Some(1))?;
+        map.serialize_entry(self.tag, variant)?;
+        map.end()
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        if is_bson_newtype(name) {
+            return self
+                .inner
+                .serialize_newtype_struct(name, value);
+        }
+
+        let value = self.wrap(value);
+        self.inner
+            .serialize_newtype_struct(name, &value)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        let value = self.wrap(value);
+        let mut map = self.inner.serialize_map(Some(2))?;
+        map.serialize_entry(self.tag, variant)?;
+        map.serialize_entry(self.content, &value)?;
+        map.end()
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        Ok(Compound {
+            inner: self.inner.serialize_seq(len)?,
+            tag: self.tag,
+            content: self.content,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+        Ok(Compound {
+            inner: self.inner.serialize_tuple(len)?,
+            tag: self.tag,
+            content: self.content,
+        })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, S::Error> {
+        Ok(Compound {
+            inner: self
+                .inner
+                .serialize_tuple_struct(name, len)?,
+            tag: self.tag,
+            content: self.content,
+        })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, S::Error> {
+        Ok(VariantBuffer {
+            inner: self.inner,
+            tag: self.tag,
+            content: self.content,
+            variant,
+            data: Bson::Array(Vec::with_capacity(len)),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        Ok(Compound {
+            inner: self.inner.serialize_map(len)?,
+            tag: self.tag,
+            content: self.content,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, S::Error> {
+        Ok(Compound {
+            inner: self.inner.serialize_struct(name, len)?,
+            tag: self.tag,
+            content: self.content,
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, S::Error> {
+        Ok(VariantBuffer {
+            inner: self.inner,
+            tag: self.tag,
+            content: self.content,
+            variant,
+            data: Bson::Document(bson::Document::new()),
+        })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+impl<C: SerializeSeq> SerializeSeq for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = self.wrap(value);
+        self.inner.serialize_element(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeTuple> SerializeTuple for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = self.wrap(value);
+        self.inner.serialize_element(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeTupleStruct> SerializeTupleStruct for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = self.wrap(value);
+        self.inner.serialize_field(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeMap> SerializeMap for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    // Keys are written as they are, since a tagged enum can't be a document key
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), C::Error> {
+        self.inner.serialize_key(key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = self.wrap(value);
+        self.inner.serialize_value(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeStruct> SerializeStruct for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), C::Error> {
+        let value = self.wrap(value);
+        self.inner.serialize_field(key, &value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), C::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: Serializer> SerializeTupleVariant for VariantBuffer<'_, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.push(None, value)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.finish()
+    }
+}
+
+impl<S: Serializer> SerializeStructVariant for VariantBuffer<'_, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), S::Error> {
+        self.push(Some(key), value)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.finish()
+    }
+}
+
+/// A deserializer reading adjacently tagged enums from a BSON value.
+///
+/// Documents and arrays are visited here so enums nested in them are read the same way;
+/// every other value is handed to the `bson` deserializer.
+struct TaggedDeserializer<'a> {
+    value: Bson,
+    tag: &'a str,
+    content: &'a str,
+}
+
+impl<'de> Deserializer<'de> for TaggedDeserializer<'_> {
+    type Error = bson::error::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Bson::Document(document) => visitor.visit_map(TaggedMap {
+                entries: document.into_iter(),
+                value: None,
+                tag: self.tag,
+                content: self.content,
+            }),
+            Bson::Array(values) => visitor.visit_seq(TaggedSeq {
+                values: values.into_iter(),
+                tag: self.tag,
+                content: self.content,
+            }),
+            value => bson::Deserializer::new(value).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Bson::Null | Bson::Undefined => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if is_bson_newtype(name) {
+            return bson::Deserializer::new(self.value).deserialize_newtype_struct(name, visitor);
+        }
+
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Bson::Document(mut document)
+                if matches!(document.get(self.tag), Some(Bson::String(_))) =>
+            {
+                let variant = match document.remove(self.tag) {
+                    Some(Bson::String(variant)) => variant,
+                    _ => unreachable!("the tag was checked to be a string"),
+                };
+                let content = document
+                    .remove(self.content)
+                    .unwrap_or(Bson::Null);
+
+                visitor.visit_enum(TaggedEnum {
+                    variant,
+                    content: TaggedDeserializer {
+                        value: content,
+                        tag: self.tag,
+                        content: self.content,
+                    },
+                })
+            }
+            // Unit variants are also accepted as plain strings, as their name is unambiguous
+            Bson::String(variant) if variants.contains(&variant.as_str()) => {
+                visitor.visit_enum(variant.into_deserializer())
+            }
+            value => Err(de::Error::custom(format!(
+                "expected a document with a string {:?} field for enum {}, found {:?}",
+                self.tag,
+                name,
+                value.element_type()
+            ))),
+        }
+    }
+
+    fn is_human_readable(&self) -> bool {
+        true
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct TaggedMap<'a> {
+    entries: bson::document::IntoIter,
+    value: Option<Bson>,
+    tag: &'a str,
+    content: &'a str,
+}
+
+impl<'de> MapAccess<'de> for TaggedMap<'_> {
+    type Error = bson::error::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(key.into_deserializer())
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        seed.deserialize(TaggedDeserializer {
+            value: self.value.take().unwrap_or(Bson::Null),
+            tag: self.tag,
+            content: self.content,
+        })
+    }
+}
+
+struct TaggedSeq<'a> {
+    values: std::vec::IntoIter<Bson>,
+    tag: &'a str,
+    content: &'a str,
+}
+
+impl<'de> SeqAccess<'de> for TaggedSeq<'_> {
+    type Error = bson::error::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        self.values
+            .next()
+            .map(|value| {
+                seed.deserialize(TaggedDeserializer {
+                    value,
+                    tag: self.tag,
+                    content: self.content,
+                })
+            })
+            .transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.values.len())
+    }
+}
+
+struct TaggedEnum<'a> {
+    variant: String,
+    content: TaggedDeserializer<'a>,
+}
+
+impl<'de, 'a> EnumAccess<'de> for TaggedEnum<'a> {
+    type Error = bson::error::Error;
+    type Variant = TaggedDeserializer<'a>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Self::Error> {
+        let variant =
+            seed.deserialize(IntoDeserializer::<Self::Error>::into_deserializer(self.variant))?;
+
+        Ok((variant, self.content))
+    }
+}
+
+impl<'de> VariantAccess<'de> for TaggedDeserializer<'_> {
+    type Error = bson::error::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_any(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_any(visitor)
+    }
+}
@@ -7,6 +7,8 @@ use bson::error::Error as BsonError;
 use serde_json::Error as SerdeJsonError;
 use thiserror::Error;
 
+use crate::validate::FieldError;
+
 /// Represents all possible errors that can occur when interacting with a document store.
 ///
 /// This enum covers serialization errors, document lifecycle issues, collection management,
@@ -30,15 +32,55 @@ pub enum DocumentStoreError {
     /// The requested collection does not exist in the store.
     #[error("Collection not found: {0}")]
     CollectionNotFound(String),
+    /// A collection with the given name already exists in the store.
+    #[error("Collection already exists: {0}")]
+    CollectionAlreadyExists(String),
+    /// The document was changed by another writer since it was read.
+    /// The first argument is the document ID, the second is the collection name.
+    #[error("Document {0} in collection {1} was modified concurrently")]
+    Conflict(String, String),
+    /// The stored document is no longer at the version the writer expected, because another
+    /// writer updated it since it was read.
+    /// The arguments are the document ID, the collection name and the expected version.
+    #[error("Document {0} in collection {1} is no longer at version {2}")]
+    VersionConflict(String, String, u64),
+    /// The stored document doesn't exist or isn't at the revision a conditional write expected.
+    /// The first argument is the document ID, the second is the collection name.
+    #[error("Document {0} in collection {1} doesn't match the expected revision")]
+    PreconditionFailed(String, String),
+    /// A write would give two documents the same value of a field with a unique index.
+    /// The first argument is the indexed field, the second is the collection name.
+    #[error("Duplicate value of unique field {0} in collection {1}")]
+    UniqueViolation(String, String),
     /// The document violates schema constraints or has invalid structure.
     #[error("Invalid document: {0}")]
     InvalidDocument(String),
+    /// Documents were rejected by the validators of their collection, with every violation
+    /// found. See the [`validate`](crate::validate) module.
+    #[error("Validation failed: {}", join_field_errors(.0))]
+    Validation(Vec<FieldError>),
     /// An error occurred in the underlying storage backend.
     #[error("Backend error: {0}")]
     Backend(String),
+    /// The backend couldn't be reached or was temporarily unable to serve the operation,
+    /// for example during a failover. Retrying the operation later may succeed.
+    #[error("Backend unavailable: {0}")]
+    Unavailable(String),
+    /// The operation didn't finish within its timeout.
+    #[error("Operation timed out: {0}")]
+    Timeout(String),
+    /// The backend doesn't support the operation, such as a transaction on a backend
+    /// without them.
+    #[error("Operation not supported: {0}")]
+    Unsupported(String),
     /// An error occurred during schema migration.
     #[error("Migration error: {0}")]
     Migration(String),
+    /// A downgrade would revert a migration that can't be reverted.
+    /// The first argument is the ID of the migration, the second the IDs of the migrations
+    /// the downgrade would revert, in order, or nothing if the migration refused on its own.
+    #[error("Migration {0} is irreversible{path}", path = describe_downgrade(.1))]
+    IrreversibleMigration(String, Vec<String>),
     /// An unknown error occurred.
     #[error("Unknown error: {0}")]
     Unknown(String),
@@ -50,6 +92,95 @@ pub enum DocumentStoreError {
 /// with a [`DocumentStoreError`].
 pub type DocumentStoreResult<T> = Result<T, DocumentStoreError>;
 
+impl DocumentStoreError {
+    /// Returns the HTTP status code that best describes the error.
+    ///
+    /// Missing documents and collections map to `404`, failed validations to `422`, duplicates
+    /// and concurrent modifications to `409`, failed revision checks to `412`, unsupported
+    /// operations to `501`, an unavailable backend to `503` and timeouts to `504`. Every other
+    /// error is a server error, `500`, including invalid documents, whose messages describe
+    /// stored data rather than the client's request. With the `web` feature, this is the
+    /// status of the error's HTTP response.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            DocumentStoreError::DocumentNotFound(..)
+            | DocumentStoreError::CollectionNotFound(_) => 404,
+            DocumentStoreError::Validation(_) => 422,
+            DocumentStoreError::DocumentAlreadyExists(..)
+            | DocumentStoreError::CollectionAlreadyExists(_)
+            | DocumentStoreError::Conflict(..)
+            | DocumentStoreError::VersionConflict(..)
+            | DocumentStoreError::UniqueViolation(..) => 409,
+            DocumentStoreError::PreconditionFailed(..) => 412,
+            DocumentStoreError::Unsupported(_) => 501,
+            DocumentStoreError::Unavailable(_) => 503,
+            DocumentStoreError::Timeout(_) => 504,
+            DocumentStoreError::Serialization(_)
+            | DocumentStoreError::Initialization(_)
+            | DocumentStoreError::InvalidDocument(_)
+            | DocumentStoreError::Backend(_)
+            | DocumentStoreError::Migration(_)
+            | DocumentStoreError::IrreversibleMigration(..)
+            | DocumentStoreError::Unknown(_) => 500,
+        }
+    }
+
+    /// Returns whether the error is transient, so retrying the operation may succeed.
+    ///
+    /// Only [`Unavailable`](DocumentStoreError::Unavailable) and
+    /// [`Timeout`](DocumentStoreError::Timeout) errors are retryable. Other errors would
+    /// recur on a retry, such as a missing document, or need the caller to decide how to
+    /// proceed, such as a conflict.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, DocumentStoreError::Unavailable(_) | DocumentStoreError::Timeout(_))
+    }
+
+    /// Returns the name of the error variant in snake case, such as `document_not_found`.
+    ///
+    /// Unlike the message, the kind doesn't include IDs or collection names, so it suits
+    /// metric labels and log fields grouping errors.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DocumentStoreError::Serialization(_) => "serialization",
+            DocumentStoreError::Initialization(_) => "initialization",
+            DocumentStoreError::DocumentAlreadyExists(..) => "document_already_exists",
+            DocumentStoreError::DocumentNotFound(..) => "document_not_found",
+            DocumentStoreError::CollectionNotFound(_) => "collection_not_found",
+            DocumentStoreError::CollectionAlreadyExists(_) => "collection_already_exists",
+            DocumentStoreError::Conflict(..) => "conflict",
+            DocumentStoreError::VersionConflict(..) => "version_conflict",
+            DocumentStoreError::PreconditionFailed(..) => "precondition_failed",
+            DocumentStoreError::UniqueViolation(..) => "unique_violation",
+            DocumentStoreError::InvalidDocument(_) => "invalid_document",
+            DocumentStoreError::Validation(_) => "validation",
+            DocumentStoreError::Backend(_) => "backend",
+            DocumentStoreError::Unavailable(_) => "unavailable",
+            DocumentStoreError::Timeout(_) => "timeout",
+            DocumentStoreError::Unsupported(_) => "unsupported",
+            DocumentStoreError::Migration(_) => "migration",
+            DocumentStoreError::IrreversibleMigration(..) => "irreversible_migration",
+            DocumentStoreError::Unknown(_) => "unknown",
+        }
+    }
+}
+
+/// Lists the migrations of a refused downgrade in its message.
+fn describe_downgrade(path: &[String]) -> String {
+    match path.is_empty() {
+        true => String::new(),
+        false => format!(", so the downgrade reverting {} was refused", path.join(" -> ")),
+    }
+}
+
+/// Lists the violations of a validation error in its message.
+fn join_field_errors(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
 impl From<BsonError> for DocumentStoreError {
     fn from(err: BsonError) -> Self {
         DocumentStoreError::Serialization(err.to_string())
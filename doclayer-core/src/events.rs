@@ -0,0 +1,984 @@
+//! Subscribing to the documents inserted, updated and deleted through a store.
+//!
+//! [`EventedStore`] wraps a backend and publishes an event on its [`EventBus`] for every
+//! document a write inserts, updates or deletes. Subscribers receive the events of one
+//! collection as a stream, with the documents decoded into their type. Events are published
+//! in-process by the wrapper itself, so this works for every backend, whether or not the
+//! database has change streams of its own.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::events::DocumentEvent;
+//! use futures::StreamExt;
+//!
+//! let store = DocumentStore::new(backend).with_events();
+//! let mut events = store.events().subscribe::<User>();
+//!
+//! tokio::spawn(async move {
+//!     while let Some(event) = events.next().await {
+//!         match event? {
+//!             DocumentEvent::Inserted(user) => println!("{} signed up", user.name),
+//!             DocumentEvent::Updated(user) => println!("{} changed", user.name),
+//!             DocumentEvent::Deleted(id) => println!("{id} left"),
+//!         }
+//!     }
+//!     Ok::<_, DocumentStoreError>(())
+//! });
+//! ```
+//!
+//! # Delivery
+//!
+//! - Events are published once the write succeeds, in the order of the documents in the
+//!   write. Writes made in a transaction are published when it commits, and never if it rolls
+//!   back.
+//! - Each subscription buffers up to the bus's [capacity](EventBus::with_capacity) of events.
+//!   A subscriber that falls further behind misses the events published meanwhile, counted by
+//!   [`Subscription::missed`], so a slow subscriber never blocks writers.
+//! - Telling inserts from updates, and finding the documents written by query, takes extra
+//!   reads. They are only made while a collection has subscribers, and can be inexact when
+//!   another writer changes the same documents concurrently.
+//! - Only writes made through the wrapper are seen, not those of other processes sharing the
+//!   database. Collection-level operations, such as dropping a collection, publish no events.
+
+use async_trait::async_trait;
+use bson::{Bson, RawDocumentBuf, Uuid};
+use futures::{
+    Stream, StreamExt,
+    channel::mpsc::{self, Receiver, Sender},
+};
+use serde::de::DeserializeOwned;
+use std::{
+    collections::HashSet,
+    fmt,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll},
+};
+
+use crate::{
+    aggregate::Aggregate,
+    backend::{
+        DocumentStream, DynStoreBackend, InsertPolicy, MissingDocumentPolicy, QueryPlan,
+        ReturnDocument, StoreBackend, WriteReport,
+    },
+    cache::DEFAULT_ID_FIELD,
+    document::{Document, DocumentExt, stored_id},
+    encoding::Encoding,
+    error::DocumentStoreResult,
+    query::{Expr, Query, Sort},
+    transaction::{Transaction, TransactionBackend},
+    update::Update,
+};
+
+/// The number of events a subscription buffers by default.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// What a write did to a document, with the document decoded into `T`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DocumentEvent<T> {
+    /// The document was inserted.
+    Inserted(T),
+    /// The document was replaced or partially updated, and is now as given.
+    Updated(T),
+    /// The document with the given ID was deleted.
+    Deleted(Uuid),
+}
+
+impl<T> DocumentEvent<T> {
+    /// Returns the inserted or updated document, or `None` for a deletion.
+    pub fn document(&self) -> Option<&T> {
+        match self {
+            DocumentEvent::Inserted(document) | DocumentEvent::Updated(document) => Some(document),
+            DocumentEvent::Deleted(_) => None,
+        }
+    }
+
+    /// Consumes the event, returning the inserted or updated document.
+    pub fn into_document(self) -> Option<T> {
+        match self {
+            DocumentEvent::Inserted(document) | DocumentEvent::Updated(document) => Some(document),
+            DocumentEvent::Deleted(_) => None,
+        }
+    }
+}
+
+/// An event as published, with the document as stored.
+#[derive(Debug)]
+struct Published {
+    collection: String,
+    event: DocumentEvent<Bson>,
+    encoding: Arc<Encoding>,
+}
+
+/// A subscription registered on the bus.
+struct Subscriber {
+    collection: String,
+    sender: Sender<Arc<Published>>,
+    missed: Arc<AtomicU64>,
+}
+
+struct Subscribers {
+    capacity: usize,
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+/// Delivers the events published by [`EventedStore`]s to their subscribers.
+///
+/// Clones share the same subscribers, so a bus can be kept after the store is wrapped
+/// further, or shared by several stores.
+#[derive(Clone)]
+pub struct EventBus {
+    inner: Arc<Subscribers>,
+}
+
+impl EventBus {
+    /// Creates a bus buffering [`DEFAULT_CAPACITY`] events per subscription.
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// Creates a bus buffering `capacity` events per subscription.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Subscribers { capacity, subscribers: Mutex::default() }),
+        }
+    }
+
+    /// Subscribes to the events of a document type's collection.
+    pub fn subscribe<D: Document>(&self) -> Subscription<D> {
+        self.register(D::collection_name(), D::from_bson_encoded)
+    }
+
+    /// Subscribes to the events of a collection, decoding its documents into `T`.
+    ///
+    /// Use [`Bson`] to receive the documents as stored.
+    pub fn subscribe_to<T: DeserializeOwned>(&self, collection: &str) -> Subscription<T> {
+        self.register(collection, |bson, encoding| encoding.deserialize(bson))
+    }
+
+    /// Returns `true` if the events of a collection have subscribers.
+    pub fn has_subscribers(&self, collection: &str) -> bool {
+        self.subscribers()
+            .iter()
+            .any(|subscriber| subscriber.collection == collection && !subscriber.sender.is_closed())
+    }
+
+    fn subscribers(&self) -> MutexGuard<'_, Vec<Subscriber>> {
+        // A panic while publishing leaves the subscribers consistent, so poisoning is ignored
+        self.inner
+            .subscribers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn register<T>(&self, collection: &str, decode: DecodeFn<T>) -> Subscription<T> {
+        // The channel holds one more event than its buffer for its single sender
+        let (sender, receiver) = mpsc::channel(self.inner.capacity.saturating_sub(1));
+        let missed = Arc::new(AtomicU64::new(0));
+
+        self.subscribers().push(Subscriber {
+            collection: collection.to_string(),
+            sender,
+            missed: missed.clone(),
+        });
+
+        Subscription {
+            receiver,
+            missed,
+            decode,
+            document: PhantomData,
+        }
+    }
+
+    /// Delivers events to the subscribers of their collection, forgetting the subscriptions
+    /// that were dropped.
+    fn publish(&self, events: Vec<Published>) {
+        if events.is_empty() {
+            return;
+        }
+
+        let mut subscribers = self.subscribers();
+
+        for event in events.into_iter().map(Arc::new) {
+            subscribers.retain_mut(|subscriber| {
+                if subscriber.collection != event.collection {
+                    return !subscriber.sender.is_closed();
+                }
+
+                match subscriber
+                    .sender
+                    .try_send(event.clone())
+                {
+                    Ok(()) => true,
+                    Err(error) if error.is_full() => {
+                        subscriber
+                            .missed
+                            .fetch_add(1, Ordering::Relaxed);
+                        true
+                    }
+                    Err(_) => false,
+                }
+            });
+        }
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventBus")
+            .field("capacity", &self.inner.capacity)
+            .field("subscribers", &self.subscribers().len())
+            .finish()
+    }
+}
+
+type DecodeFn<T> = fn(Bson, &Encoding) -> DocumentStoreResult<T>;
+
+/// A stream of the events of one collection, created by [`EventBus::subscribe`].
+///
+/// Each item is the event, or the error decoding its document. The stream ends once the bus
+/// and every store publishing on it are dropped; dropping the subscription unsubscribes.
+pub struct Subscription<T> {
+    receiver: Receiver<Arc<Published>>,
+    missed: Arc<AtomicU64>,
+    decode: DecodeFn<T>,
+    document: PhantomData<fn() -> T>,
+}
+
+impl<T> Subscription<T> {
+    /// Returns the number of events missed so far because the subscription's buffer was full.
+    pub fn missed(&self) -> u64 {
+        self.missed.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Stream for Subscription<T> {
+    type Item = DocumentStoreResult<DocumentEvent<T>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let decode = self.decode;
+
+        self.receiver
+            .poll_next_unpin(cx)
+            .map(|published| {
+                published.map(|published| {
+                    let encoding = &published.encoding;
+
+                    Ok(match &published.event {
+                        DocumentEvent::Inserted(document) => {
+                            DocumentEvent::Inserted(decode(document.clone(), encoding)?)
+                        }
+                        DocumentEvent::Updated(document) => {
+                            DocumentEvent::Updated(decode(document.clone(), encoding)?)
+                        }
+                        DocumentEvent::Deleted(id) => DocumentEvent::Deleted(*id),
+                    })
+                })
+            })
+    }
+}
+
+impl<T> fmt::Debug for Subscription<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subscription")
+            .field("missed", &self.missed())
+            .finish()
+    }
+}
+
+/// A backend publishing the documents changed by every write on an [`EventBus`].
+///
+/// See the [module documentation](self) for how events are delivered.
+pub struct EventedStore<B> {
+    backend: B,
+    bus: EventBus,
+    id_field: String,
+    encoding: Arc<Encoding>,
+    /// The events of a transaction, published when it commits
+    pending: Option<Mutex<Vec<Published>>>,
+}
+
+impl<B: StoreBackend> EventedStore<B> {
+    /// Wraps a backend so its writes are published on a new bus.
+    pub fn new(backend: B) -> Self {
+        let encoding = Arc::new(backend.encoding().clone());
+
+        Self {
+            backend,
+            bus: EventBus::new(),
+            id_field: DEFAULT_ID_FIELD.to_string(),
+            encoding,
+            pending: None,
+        }
+    }
+
+    /// Publishes the events on an existing bus instead, such as one shared with another store.
+    pub fn with_bus(mut self, bus: EventBus) -> Self {
+        self.bus = bus;
+        self
+    }
+
+    /// Sets the field holding the ID of stored documents, `id` by default.
+    ///
+    /// Deletions, and writes by query, publish no event for documents without an ID there.
+    pub fn with_id_field(mut self, field: impl Into<String>) -> Self {
+        self.id_field = field.into();
+        self
+    }
+
+    /// Returns the bus the events are published on.
+    pub fn events(&self) -> &EventBus {
+        &self.bus
+    }
+
+    /// Returns the wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.backend
+    }
+
+    /// Unwraps the backend.
+    pub fn into_inner(self) -> B {
+        self.backend
+    }
+
+    /// Wraps the backend of a transaction, holding its events until it commits.
+    fn wrap(&self, transaction: Transaction) -> EventedStore<Transaction> {
+        EventedStore {
+            backend: transaction,
+            bus: self.bus.clone(),
+            id_field: self.id_field.clone(),
+            encoding: self.encoding.clone(),
+            pending: Some(Mutex::default()),
+        }
+    }
+
+    /// Publishes the events of a write, or holds them until its transaction commits.
+    fn emit(&self, collection: &str, events: Vec<DocumentEvent<Bson>>) {
+        let events = events
+            .into_iter()
+            .map(|event| Published {
+                collection: collection.to_string(),
+                event,
+                encoding: self.encoding.clone(),
+            })
+            .collect();
+
+        match &self.pending {
+            Some(pending) => pending
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .extend(events),
+            None => self.bus.publish(events),
+        }
+    }
+
+    /// Returns the IDs among `ids` of the documents stored in a collection.
+    async fn existing(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<HashSet<Uuid>> {
+        Ok(self
+            .backend
+            .get_documents(ids, collection)
+            .await?
+            .iter()
+            .filter_map(|document| stored_id(document, &self.id_field))
+            .collect())
+    }
+
+    /// Returns the IDs of the documents matching a filter.
+    async fn matching(&self, filter: &Expr, collection: &str) -> DocumentStoreResult<Vec<Uuid>> {
+        Ok(self
+            .backend
+            .query_documents(
+                Query {
+                    filter: Some(filter.clone()),
+                    ..Query::default()
+                },
+                collection,
+            )
+            .await?
+            .iter()
+            .filter_map(|document| stored_id(document, &self.id_field))
+            .collect())
+    }
+
+    /// Returns the IDs of the documents of a write, if the collection has subscribers.
+    fn subscribed_ids<T>(&self, documents: &[(Uuid, T)], collection: &str) -> Option<Vec<Uuid>> {
+        self.bus
+            .has_subscribers(collection)
+            .then(|| {
+                documents
+                    .iter()
+                    .map(|(id, _)| *id)
+                    .collect()
+            })
+    }
+
+    /// Publishes an insert for the documents that didn't exist and an update for those that
+    /// did, leaving out existing documents if `updates` is `false` and new ones if `inserts`
+    /// is `false`.
+    fn emit_writes(
+        &self,
+        collection: &str,
+        documents: Vec<(Uuid, Bson)>,
+        existing: &HashSet<Uuid>,
+        inserts: bool,
+        updates: bool,
+    ) {
+        self.emit(
+            collection,
+            documents
+                .into_iter()
+                .filter_map(|(id, document)| match existing.contains(&id) {
+                    true if updates => Some(DocumentEvent::Updated(document)),
+                    false if inserts => Some(DocumentEvent::Inserted(document)),
+                    _ => None,
+                })
+                .collect(),
+        );
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for EventedStore<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventedStore")
+            .field("backend", &self.backend)
+            .field("bus", &self.bus)
+            .field("id_field", &self.id_field)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<B: StoreBackend + 'static> StoreBackend for EventedStore<B> {
+    async fn insert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        let Some(ids) = self.subscribed_ids(&documents, collection) else {
+            return self
+                .backend
+                .insert_documents(documents, collection, policy)
+                .await;
+        };
+        // Inserts that fail on conflict never replace a document
+        let existing = match policy {
+            InsertPolicy::ErrorOnConflict => HashSet::new(),
+            InsertPolicy::Replace | InsertPolicy::Skip => self.existing(ids, collection).await?,
+        };
+        let report = self
+            .backend
+            .insert_documents(documents.clone(), collection, policy)
+            .await?;
+
+        self.emit_writes(collection, documents, &existing, true, policy == InsertPolicy::Replace);
+
+        Ok(report)
+    }
+
+    async fn update_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        let Some(ids) = self.subscribed_ids(&documents, collection) else {
+            return self
+                .backend
+                .update_documents(documents, collection, policy)
+                .await;
+        };
+        // Updates that fail on missing documents update them all
+        let existing = match policy {
+            MissingDocumentPolicy::Error => ids.into_iter().collect(),
+            MissingDocumentPolicy::Skip | MissingDocumentPolicy::Upsert => {
+                self.existing(ids, collection).await?
+            }
+        };
+        let report = self
+            .backend
+            .update_documents(documents.clone(), collection, policy)
+            .await?;
+
+        self.emit_writes(
+            collection,
+            documents,
+            &existing,
+            policy == MissingDocumentPolicy::Upsert,
+            true,
+        );
+
+        Ok(report)
+    }
+
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        let written = self
+            .bus
+            .has_subscribers(collection)
+            .then(|| {
+                documents
+                    .iter()
+                    .map(|(_, document, _)| DocumentEvent::Updated(document.clone()))
+                    .collect()
+            });
+        let report = self
+            .backend
+            .update_documents_if_version(documents, version_field, collection)
+            .await?;
+
+        // The write fails unless every document is updated
+        if let Some(events) = written {
+            self.emit(collection, events);
+        }
+
+        Ok(report)
+    }
+
+    async fn update_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, Bson, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        let written = self
+            .bus
+            .has_subscribers(collection)
+            .then(|| {
+                documents
+                    .iter()
+                    .map(|(_, document, _)| DocumentEvent::Updated(document.clone()))
+                    .collect()
+            });
+        let report = self
+            .backend
+            .update_documents_if_match(documents, collection)
+            .await?;
+
+        // The write fails unless every document is updated
+        if let Some(events) = written {
+            self.emit(collection, events);
+        }
+
+        Ok(report)
+    }
+
+    async fn upsert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+    ) -> DocumentStoreResult<()> {
+        let Some(ids) = self.subscribed_ids(&documents, collection) else {
+            return self
+                .backend
+                .upsert_documents(documents, collection)
+                .await;
+        };
+        let existing = self.existing(ids, collection).await?;
+        self.backend
+            .upsert_documents(documents.clone(), collection)
+            .await?;
+
+        self.emit_writes(collection, documents, &existing, true, true);
+
+        Ok(())
+    }
+
+    async fn delete_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        if !self.bus.has_subscribers(collection) {
+            return self
+                .backend
+                .delete_documents(ids, collection)
+                .await;
+        }
+
+        let existing = self
+            .existing(ids.clone(), collection)
+            .await?;
+        let deleted = self
+            .backend
+            .delete_documents(ids.clone(), collection)
+            .await?;
+
+        self.emit(
+            collection,
+            ids.into_iter()
+                .filter(|id| existing.contains(id))
+                .map(DocumentEvent::Deleted)
+                .collect(),
+        );
+
+        Ok(deleted)
+    }
+
+    async fn delete_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        let ids = self.subscribed_ids(&documents, collection);
+        let deleted = self
+            .backend
+            .delete_documents_if_match(documents, collection)
+            .await?;
+
+        // The write fails unless every document is deleted
+        if let Some(ids) = ids {
+            self.emit(
+                collection,
+                ids.into_iter()
+                    .map(DocumentEvent::Deleted)
+                    .collect(),
+            );
+        }
+
+        Ok(deleted)
+    }
+
+    async fn update_by_query(
+        &self,
+        filter: Expr,
+        update: Update,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        if !self.bus.has_subscribers(collection) {
+            return self
+                .backend
+                .update_by_query(filter, update, collection)
+                .await;
+        }
+
+        let ids = self
+            .matching(&filter, collection)
+            .await?;
+        let report = self
+            .backend
+            .update_by_query(filter, update, collection)
+            .await?;
+        let updated = self
+            .backend
+            .get_documents(ids, collection)
+            .await?;
+
+        self.emit(
+            collection,
+            updated
+                .into_iter()
+                .map(DocumentEvent::Updated)
+                .collect(),
+        );
+
+        Ok(report)
+    }
+
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize> {
+        if !self.bus.has_subscribers(collection) {
+            return self
+                .backend
+                .delete_by_query(filter, collection)
+                .await;
+        }
+
+        let ids = self
+            .matching(&filter, collection)
+            .await?;
+        let deleted = self
+            .backend
+            .delete_by_query(filter, collection)
+            .await?;
+
+        self.emit(
+            collection,
+            ids.into_iter()
+                .map(DocumentEvent::Deleted)
+                .collect(),
+        );
+
+        Ok(deleted)
+    }
+
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        let Some(document) = self
+            .backend
+            .find_one_and_update(filter, sort, update, returned, collection)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        if self.bus.has_subscribers(collection) {
+            let updated = match returned {
+                ReturnDocument::After => Some(document.clone()),
+                ReturnDocument::Before => match stored_id(&document, &self.id_field) {
+                    Some(id) => self
+                        .backend
+                        .get_documents(vec![id], collection)
+                        .await?
+                        .pop(),
+                    None => None,
+                },
+            };
+
+            if let Some(updated) = updated {
+                self.emit(collection, vec![DocumentEvent::Updated(updated)]);
+            }
+        }
+
+        Ok(Some(document))
+    }
+
+    async fn find_one_and_delete(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        let deleted = self
+            .backend
+            .find_one_and_delete(filter, sort, collection)
+            .await?;
+
+        if let Some(id) = deleted
+            .as_ref()
+            .and_then(|document| stored_id(document, &self.id_field))
+        {
+            self.emit(collection, vec![DocumentEvent::Deleted(id)]);
+        }
+
+        Ok(deleted)
+    }
+
+    async fn get_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .get_documents(ids, collection)
+            .await
+    }
+
+    async fn query_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .query_documents(query, collection)
+            .await
+    }
+
+    async fn query_stream(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<DocumentStream> {
+        self.backend
+            .query_stream(query, collection)
+            .await
+    }
+
+    async fn query_raw_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<RawDocumentBuf>> {
+        self.backend
+            .query_raw_documents(query, collection)
+            .await
+    }
+
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize> {
+        self.backend
+            .count_documents(query, collection)
+            .await
+    }
+
+    async fn distinct(
+        &self,
+        field: &str,
+        filter: Option<Expr>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .distinct(field, filter, collection)
+            .await
+    }
+
+    async fn aggregate(
+        &self,
+        aggregate: Aggregate,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .aggregate(aggregate, collection)
+            .await
+    }
+
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan> {
+        self.backend
+            .explain(query, collection)
+            .await
+    }
+
+    async fn current_revision_id(&self) -> DocumentStoreResult<Option<String>> {
+        self.backend.current_revision_id().await
+    }
+
+    async fn set_revision_id(&self, revision_id: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .set_revision_id(revision_id)
+            .await
+    }
+
+    async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .create_collection(name)
+            .await
+    }
+
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        self.backend
+            .collection_exists(name)
+            .await
+    }
+
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .ensure_collection(name)
+            .await
+    }
+
+    async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.backend.drop_collection(name).await
+    }
+
+    async fn list_collections(&self) -> DocumentStoreResult<Vec<String>> {
+        self.backend.list_collections().await
+    }
+
+    async fn add_field(
+        &self,
+        collection: &str,
+        field: &str,
+        default: Bson,
+    ) -> DocumentStoreResult<()> {
+        self.backend
+            .add_field(collection, field, default)
+            .await
+    }
+
+    async fn drop_field(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .drop_field(collection, field)
+            .await
+    }
+
+    async fn rename_field(
+        &self,
+        collection: &str,
+        field: &str,
+        new: &str,
+    ) -> DocumentStoreResult<()> {
+        self.backend
+            .rename_field(collection, field, new)
+            .await
+    }
+
+    async fn add_index(
+        &self,
+        collection: &str,
+        field: &str,
+        unique: bool,
+    ) -> DocumentStoreResult<()> {
+        self.backend
+            .add_index(collection, field, unique)
+            .await
+    }
+
+    async fn drop_index(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .drop_index(collection, field)
+            .await
+    }
+
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .expire_documents(collection, field)
+            .await
+    }
+
+    async fn begin_transaction(&self) -> DocumentStoreResult<Transaction> {
+        let transaction = self.backend.begin_transaction().await?;
+
+        Ok(Transaction::new(self.wrap(transaction)))
+    }
+
+    fn inner_backend(&self) -> Option<&dyn DynStoreBackend> {
+        Some(&self.backend)
+    }
+
+    async fn close(&self) -> DocumentStoreResult<()> {
+        self.backend.close().await
+    }
+
+    async fn shutdown(self) -> DocumentStoreResult<()> {
+        self.backend.shutdown().await
+    }
+}
+
+// The events of a transaction are published once it commits
+#[async_trait]
+impl TransactionBackend for EventedStore<Transaction> {
+    fn backend(&self) -> &dyn DynStoreBackend {
+        self
+    }
+
+    async fn commit(self: Box<Self>) -> DocumentStoreResult<()> {
+        let EventedStore { backend, bus, pending, .. } = *self;
+        backend.commit().await?;
+
+        if let Some(pending) = pending {
+            bus.publish(
+                pending
+                    .into_inner()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()),
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) -> DocumentStoreResult<()> {
+        self.backend.rollback().await
+    }
+}
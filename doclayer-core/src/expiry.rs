@@ -0,0 +1,181 @@
+//! Expiring documents once a datetime has passed.
+//!
+//! A collection is made to expire its documents with
+//! [`StoreBackend::expire_documents`], naming the field holding each document's expiry. For a
+//! document type, `#[document(expires = "expires_at")]` names the field and
+//! [`DocumentStore::ensure_indexes`](crate::store::DocumentStore::ensure_indexes) enables the
+//! expiry. The field must hold a BSON datetime, such as a `bson::DateTime`; `insert_with_ttl`
+//! sets it to the current time plus a time-to-live.
+//!
+//! MongoDB deletes expired documents itself, through a TTL index. The other backends record
+//! the expiring collections in an [`ExpiringCollections`] and delete expired documents when
+//! swept: call their `sweep_expired` method, or spawn their `run_sweeper` as a background task.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::prelude::*;
+//! use std::time::Duration;
+//!
+//! #[derive(Debug, Clone, Serialize, Deserialize, Document)]
+//! #[document(collection = "sessions", expires = "expires_at")]
+//! pub struct Session {
+//!     pub id: Uuid,
+//!     pub user: Uuid,
+//!     pub expires_at: bson::DateTime,
+//! }
+//!
+//! let backend = InMemoryStore::new();
+//! let sweeper = backend.clone();
+//! tokio::spawn(async move {
+//!     sweeper
+//!         .run_sweeper(Duration::from_secs(60), tokio::time::sleep, |swept| {
+//!             if let Err(error) = swept {
+//!                 eprintln!("sweeping expired sessions failed: {error}");
+//!             }
+//!         })
+//!         .await
+//! });
+//!
+//! let store = DocumentStore::new(backend);
+//! store.ensure_indexes::<Session>().await?;
+//!
+//! // Sets `expires_at` to an hour from now
+//! store
+//!     .typed_collection::<Session>()
+//!     .insert_with_ttl(vec![session], Duration::from_secs(3600))
+//!     .await?;
+//! ```
+
+use bson::Bson;
+use chrono::{TimeDelta, Utc};
+use mea::rwlock::RwLock;
+use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
+
+use crate::{
+    backend::StoreBackend,
+    error::{DocumentStoreError, DocumentStoreResult},
+    query::{Expr, Filter},
+};
+
+/// Returns the expiry of a document written now with the given time-to-live.
+///
+/// A time-to-live too long to represent gives the latest representable datetime.
+pub fn expires_at(ttl: Duration) -> bson::DateTime {
+    TimeDelta::from_std(ttl)
+        .ok()
+        .and_then(|ttl| Utc::now().checked_add_signed(ttl))
+        .map_or(bson::DateTime::MAX, bson::DateTime::from_chrono)
+}
+
+/// Returns a filter matching the documents whose expiry in `field` has passed.
+pub fn expired(field: &str) -> Expr {
+    Filter::lte(field, Bson::DateTime(bson::DateTime::from_chrono(Utc::now())))
+}
+
+/// Returns whether a document's expiry in `field` has passed, like [`expired`] matches it.
+pub fn has_expired(document: &Bson, field: &str) -> bool {
+    let now = bson::DateTime::from_chrono(Utc::now());
+
+    document
+        .as_document()
+        .and_then(|document| document.get(field))
+        .is_some_and(|expiry| matches!(expiry, Bson::DateTime(expiry) if *expiry <= now))
+}
+
+/// The collections whose documents expire, for backends deleting expired documents themselves.
+///
+/// Clones share the same collections.
+#[derive(Debug, Clone, Default)]
+pub struct ExpiringCollections {
+    /// The field holding the expiry of documents, by collection
+    fields: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl ExpiringCollections {
+    /// Creates an empty set of expiring collections.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that the documents of a collection expire at the datetime in `field`.
+    pub async fn insert(&self, collection: &str, field: &str) {
+        self.fields
+            .write()
+            .await
+            .insert(collection.to_string(), field.to_string());
+    }
+
+    /// Returns the field holding the expiry of a collection's documents, if they expire.
+    pub async fn field(&self, collection: &str) -> Option<String> {
+        self.fields
+            .read()
+            .await
+            .get(collection)
+            .cloned()
+    }
+
+    /// Forgets a collection, such as when it's dropped.
+    pub async fn remove(&self, collection: &str) {
+        self.fields
+            .write()
+            .await
+            .remove(collection);
+    }
+
+    /// Deletes the expired documents of every expiring collection from a backend.
+    ///
+    /// # Returns
+    ///
+    /// The number of documents deleted.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error deleting from a collection; the collections after it aren't swept.
+    pub async fn sweep<B: StoreBackend>(&self, backend: &B) -> DocumentStoreResult<usize> {
+        let fields = self.fields.read().await.clone();
+        let mut deleted = 0;
+
+        for (collection, field) in fields {
+            match backend
+                .delete_by_query(expired(&field), &collection)
+                .await
+            {
+                Ok(count) => deleted += count,
+                // The collection was dropped since the fields were read
+                Err(DocumentStoreError::CollectionNotFound(_)) => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(deleted)
+    }
+
+    /// Sweeps a backend repeatedly, waiting `interval` between sweeps.
+    ///
+    /// This never returns; cancel it by dropping the future or aborting its task.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - The backend to delete expired documents from
+    /// * `interval` - The time to wait after each sweep
+    /// * `sleep` - The async runtime's sleep function, such as `tokio::time::sleep`
+    /// * `on_sweep` - Called with the outcome of every sweep
+    pub async fn run<B, F, Fut, R>(
+        &self,
+        backend: &B,
+        interval: Duration,
+        sleep: F,
+        mut on_sweep: R,
+    ) where
+        B: StoreBackend,
+        F: Fn(Duration) -> Fut,
+        Fut: Future<Output = ()>,
+        R: FnMut(DocumentStoreResult<usize>),
+    {
+        loop {
+            on_sweep(self.sweep(backend).await);
+            sleep(interval).await;
+        }
+    }
+}
@@ -0,0 +1,332 @@
+//! Bulk imports of typed documents.
+//!
+//! [`TypedCollection::import`](crate::collection::TypedCollection::import) loads a stream of
+//! records into a collection in batches. [`ImportOptions`] configures the load: transformations
+//! applied to every record, duplicate detection by a natural key, the batch size and what
+//! happens when a record fails. The outcome is summarized in an [`ImportReport`].
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::import::{ImportErrorPolicy, ImportOptions};
+//!
+//! let options = ImportOptions::new()
+//!     .transform(|mut user: User| {
+//!         user.email = user.email.to_lowercase();
+//!         Ok(Some(user))
+//!     })
+//!     .dedupe_by("email", |user| user.email.clone())
+//!     .batch_size(1000)
+//!     .on_error(ImportErrorPolicy::Collect);
+//!
+//! let report = users.import(futures::stream::iter(rows.map(parse_user)), options).await?;
+//! println!("{} imported, {} duplicates, {} failed", report.inserted, report.duplicates, report.failed);
+//! ```
+
+use bson::Bson;
+use futures::{Stream, StreamExt, future::BoxFuture};
+use std::{collections::HashSet, fmt, pin::pin, sync::Arc};
+
+use crate::{
+    document::Document,
+    error::{DocumentStoreError, DocumentStoreResult},
+    query::{Filter, Query},
+};
+
+/// The default number of documents inserted per batch.
+pub const DEFAULT_IMPORT_BATCH_SIZE: usize = 500;
+
+type TransformFn<D> = Arc<dyn Fn(D) -> DocumentStoreResult<Option<D>> + Send + Sync>;
+type KeyFn<D> = Arc<dyn Fn(&D) -> Bson + Send + Sync>;
+
+/// What an import does when a record fails to read, transform or insert.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImportErrorPolicy {
+    /// Stop at the first failure and return its error. Earlier batches stay inserted.
+    #[default]
+    Abort,
+    /// Skip failed records, only counting them.
+    Skip,
+    /// Skip failed records, keeping their errors in the report.
+    Collect,
+}
+
+/// Configures [`TypedCollection::import`](crate::collection::TypedCollection::import).
+pub struct ImportOptions<D> {
+    transforms: Vec<TransformFn<D>>,
+    dedupe: Option<(String, KeyFn<D>)>,
+    batch_size: usize,
+    on_error: ImportErrorPolicy,
+}
+
+impl<D: Document> ImportOptions<D> {
+    /// Creates options importing every record as is, aborting on the first failure.
+    pub fn new() -> Self {
+        ImportOptions {
+            transforms: Vec::new(),
+            dedupe: None,
+            batch_size: DEFAULT_IMPORT_BATCH_SIZE,
+            on_error: ImportErrorPolicy::Abort,
+        }
+    }
+
+    /// Adds a transformation applied to every record, after those added before it.
+    ///
+    /// Returning `Ok(None)` drops the record; returning an error fails it.
+    pub fn transform(
+        mut self,
+        transform: impl Fn(D) -> DocumentStoreResult<Option<D>> + Send + Sync + 'static,
+    ) -> Self {
+        self.transforms
+            .push(Arc::new(transform));
+        self
+    }
+
+    /// Skips records whose natural key was already imported or is already stored.
+    ///
+    /// Keys are compared within the import and against the stored documents whose `field`
+    /// holds one of the keys of each batch. Keys seen during the import are kept in memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The stored field holding the key
+    /// * `key` - Reads the key of a document, matching the stored value of `field`
+    pub fn dedupe_by<K: Into<Bson>>(
+        mut self,
+        field: impl Into<String>,
+        key: impl Fn(&D) -> K + Send + Sync + 'static,
+    ) -> Self {
+        self.dedupe = Some((field.into(), Arc::new(move |document| key(document).into())));
+        self
+    }
+
+    /// Sets the number of documents inserted per batch.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch_size` - The batch size, at least 1
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Sets what happens when a record fails.
+    pub fn on_error(mut self, policy: ImportErrorPolicy) -> Self {
+        self.on_error = policy;
+        self
+    }
+
+    fn apply_transforms(&self, document: D) -> DocumentStoreResult<Option<D>> {
+        self.transforms
+            .iter()
+            .try_fold(Some(document), |document, transform| match document {
+                Some(document) => transform(document),
+                None => Ok(None),
+            })
+    }
+}
+
+impl<D: Document> Default for ImportOptions<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D> fmt::Debug for ImportOptions<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImportOptions")
+            .field("transforms", &self.transforms.len())
+            .field(
+                "dedupe_by",
+                &self
+                    .dedupe
+                    .as_ref()
+                    .map(|(field, _)| field),
+            )
+            .field("batch_size", &self.batch_size)
+            .field("on_error", &self.on_error)
+            .finish()
+    }
+}
+
+/// A record that failed during an import.
+#[derive(Debug)]
+pub struct ImportError {
+    /// The position of the record in the imported stream, starting at 0.
+    pub record: usize,
+    /// Why the record failed.
+    pub error: DocumentStoreError,
+}
+
+/// The outcome of an import.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    /// The number of records read from the stream.
+    pub read: usize,
+    /// The number of documents inserted.
+    pub inserted: usize,
+    /// The number of records skipped as duplicates.
+    pub duplicates: usize,
+    /// The number of records dropped by a transformation.
+    pub filtered: usize,
+    /// The number of records that failed.
+    pub failed: usize,
+    /// The failures, when imported with [`ImportErrorPolicy::Collect`].
+    pub errors: Vec<ImportError>,
+}
+
+impl ImportReport {
+    fn fail(
+        &mut self,
+        policy: ImportErrorPolicy,
+        record: usize,
+        error: DocumentStoreError,
+    ) -> DocumentStoreResult<()> {
+        match policy {
+            ImportErrorPolicy::Abort => return Err(error),
+            ImportErrorPolicy::Skip => {}
+            ImportErrorPolicy::Collect => self
+                .errors
+                .push(ImportError { record, error }),
+        }
+
+        self.failed += 1;
+        Ok(())
+    }
+}
+
+/// The collection operations an import needs.
+pub(crate) trait ImportTarget<D>: Sync {
+    fn insert_batch(&self, documents: Vec<D>) -> BoxFuture<'_, DocumentStoreResult<()>>;
+
+    fn find(&self, query: Query) -> BoxFuture<'_, DocumentStoreResult<Vec<D>>>;
+}
+
+/// Runs an import into a collection.
+pub(crate) async fn run<D, S, T>(
+    records: S,
+    options: &ImportOptions<D>,
+    target: &T,
+) -> DocumentStoreResult<ImportReport>
+where
+    D: Document,
+    S: Stream<Item = DocumentStoreResult<D>>,
+    T: ImportTarget<D>,
+{
+    let mut records = pin!(records);
+    let mut report = ImportReport::default();
+    let mut seen = HashSet::new();
+    let mut batch = Vec::with_capacity(options.batch_size);
+
+    while let Some(record) = records.next().await {
+        let index = report.read;
+        report.read += 1;
+
+        let document = match record.and_then(|document| options.apply_transforms(document)) {
+            Ok(Some(document)) => document,
+            Ok(None) => {
+                report.filtered += 1;
+                continue;
+            }
+            Err(e) => {
+                report.fail(options.on_error, index, e)?;
+                continue;
+            }
+        };
+
+        if let Some((_, key)) = &options.dedupe
+            && !seen.insert(key(&document).to_string())
+        {
+            report.duplicates += 1;
+            continue;
+        }
+
+        batch.push((index, document));
+        if batch.len() >= options.batch_size {
+            flush(std::mem::take(&mut batch), options, &mut report, target).await?;
+        }
+    }
+
+    if !batch.is_empty() {
+        flush(batch, options, &mut report, target).await?;
+    }
+
+    Ok(report)
+}
+
+/// Inserts a batch, dropping documents whose key is already stored.
+async fn flush<D, T>(
+    mut batch: Vec<(usize, D)>,
+    options: &ImportOptions<D>,
+    report: &mut ImportReport,
+    target: &T,
+) -> DocumentStoreResult<()>
+where
+    D: Document,
+    T: ImportTarget<D>,
+{
+    if let Some((field, key)) = &options.dedupe {
+        let keys = batch
+            .iter()
+            .map(|(_, document)| key(document))
+            .collect::<Vec<_>>();
+        let stored = target
+            .find(Query {
+                filter: Some(Filter::any_of(field.clone(), keys)),
+                ..Query::default()
+            })
+            .await?
+            .iter()
+            .map(|document| key(document).to_string())
+            .collect::<HashSet<_>>();
+
+        let before = batch.len();
+        batch.retain(|(_, document)| !stored.contains(&key(document).to_string()));
+        report.duplicates += before - batch.len();
+    }
+
+    if batch.is_empty() {
+        return Ok(());
+    }
+
+    let count = batch.len();
+    if options.on_error == ImportErrorPolicy::Abort {
+        target
+            .insert_batch(
+                batch
+                    .into_iter()
+                    .map(|(_, document)| document)
+                    .collect(),
+            )
+            .await?;
+        report.inserted += count;
+        return Ok(());
+    }
+
+    let documents = batch
+        .iter()
+        .map(|(_, document)| document.clone())
+        .collect::<Vec<_>>();
+
+    if target
+        .insert_batch(documents)
+        .await
+        .is_ok()
+    {
+        report.inserted += count;
+        return Ok(());
+    }
+
+    // Retry one by one to tell the failing records apart from the rest of the batch
+    for (index, document) in batch {
+        match target
+            .insert_batch(vec![document])
+            .await
+        {
+            Ok(()) => report.inserted += 1,
+            Err(e) => report.fail(options.on_error, index, e)?,
+        }
+    }
+
+    Ok(())
+}
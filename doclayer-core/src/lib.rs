@@ -3,13 +3,42 @@
 //! This crate is the core of the doclayer project and provides:
 //!
 //! - **Document traits** ([`document`]) - Core traits for defining and serializing documents
+//! - **Encodings** ([`encoding`]) - Store-level representation of UUIDs, enums and datetimes
 //! - **Store backend abstraction** ([`backend`]) - Traits for implementing different storage backends
 //! - **Query and filtering API** ([`query`]) - Type-safe query construction and filtering
+//! - **Partial updates** ([`update`]) - Field-level update operations applied by the backend
+//! - **Validation** ([`validate`]) - Field-level rules checked before documents are written, on every backend
+//! - **JSON Schemas** ([`schema`]) - Validating the documents written to a collection against its JSON Schema, or one derived from a document type
+//! - **Aggregation** ([`aggregate`]) - Group-by computations evaluated by the backend
 //! - **Collections interface** ([`collection`]) - High-level API for interacting with document collections
 //! - **Document store** ([`store`]) - Main interface for working with typed or untyped documents
-//! - **Error handling** ([`error`]) - Comprehensive error types and result types
+//! - **Error handling** ([`error`]) - Comprehensive error types and result types, convertible
+//!   into axum and actix-web responses with the `web` feature
+//! - **Imports** ([`import`]) - Batched loads with transformation, deduplication and error policies
 //! - **Type utilities** ([`types`]) - Common types like pagination and page results
-//! - **Schema migrations** ([`migrate`]) - Tools for versioning and migrating document schemas
+//! - **Schema migrations** ([`migrate`]) - Tools for versioning and migrating document schemas, and generating migrations from schema snapshots
+//! - **Seeding** ([`seed`]) - Idempotent fixture documents for development, tests and demos, tagged by environment
+//! - **Linting** ([`lint`]) - Finding stored fields whose type the document type rejects
+//! - **Plugins** ([`plugin`]) - Assembling the document layer from independent modules
+//! - **Archival** ([`archive`]) - Declarative policies moving old documents out of hot collections
+//! - **Concurrency limits** ([`limit`]) - Capping in-flight operations against a backend
+//! - **Rollups** ([`rollup`]) - Counter and sum documents maintained as source documents change
+//! - **Prefixes** ([`prefix`]) - Namespacing collection names so environments can share a database
+//! - **Caching** ([`cache`]) - Serving lookups by ID from a fast backend in front of a persistent one
+//! - **Mirroring** ([`mirror`]) - Writing to two backends at once to migrate between them
+//! - **Read/write splitting** ([`split`]) - Writing to a primary backend and reading from its replicas
+//! - **Audit trail** ([`audit`]) - Recording who changed which document and when, with the changed values
+//! - **Events** ([`events`]) - Subscribing to the documents inserted, updated and deleted, for every backend
+//! - **Shadow mode** ([`shadow`]) - Recording writes instead of applying them, to replay traffic safely
+//! - **Retries** ([`retry`]) - Retrying operations that fail with transient errors, with exponential backoff
+//! - **Slow query log** ([`slowlog`]) - Reporting queries slower than a threshold, for every backend
+//! - **Timeouts** ([`timeout`]) - Failing operations and queries that run longer than their timeout
+//! - **Transactions** ([`transaction`]) - Applying writes to several collections atomically
+//! - **Revisions** ([`versioned`]) - Per-document revision tokens for `If-Match` conditional writes
+//! - **Expiry** ([`expiry`]) - Deleting documents once the datetime in a field has passed
+//! - **Time series** ([`timeseries`]) - Routing documents into per-day, per-month or per-year collections
+//! - **Tracing** (`trace`) - Spans around every backend operation, with the `tracing` feature
+//! - **Metrics** (`metrics`) - Operation counts, errors and latency, with the `metrics` feature
 //!
 //! # Example
 //!
@@ -38,11 +67,45 @@
 #[allow(unused_extern_crates)]
 extern crate self as doclayer_core;
 
+pub mod aggregate;
+pub mod archive;
+pub mod audit;
+pub mod limit;
 pub mod backend;
+pub mod cache;
 pub mod collection;
 pub mod document;
+pub mod encoding;
 pub mod error;
+pub mod events;
+pub mod expiry;
+pub mod import;
 pub mod migrate;
+pub mod mirror;
+pub mod plugin;
+pub mod prefix;
 pub mod query;
+pub mod retry;
+pub mod schema;
+pub mod seed;
+pub mod rollup;
+pub mod lint;
+pub mod shadow;
+pub mod slowlog;
+pub mod split;
 pub mod store;
+pub mod timeseries;
+pub mod timeout;
+mod time;
+pub mod transaction;
+pub mod update;
+pub mod validate;
+pub mod versioned;
 pub mod page;
+
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "tracing")]
+pub mod trace;
+#[cfg(feature = "web")]
+mod web;
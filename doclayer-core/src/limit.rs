@@ -0,0 +1,699 @@
+//! Limits on concurrent backend operations.
+//!
+//! A [`ConcurrencyLimiter`] caps how many operations may be in flight against a backend at
+//! once. Callers beyond the cap wait in a first-come queue, which can itself be bounded so a
+//! burst fails fast instead of piling up. Sharing one limiter between services that use the
+//! same cluster keeps a burst in one of them from exhausting the driver's connection pool.
+//!
+//! Any backend can be limited by wrapping it in [`ConcurrencyLimited`]. Backends that manage
+//! their own connections may also take a limiter directly.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::limit::{ConcurrencyLimited, ConcurrencyLimiter};
+//!
+//! let limiter = ConcurrencyLimiter::new(32).with_max_queued(256);
+//! let store = DocumentStore::new(ConcurrencyLimited::new(backend, limiter.clone()));
+//!
+//! let metrics = limiter.metrics();
+//! println!("{} in flight, {} queued", metrics.in_flight, metrics.queued);
+//! ```
+
+use async_trait::async_trait;
+use bson::{Bson, RawDocumentBuf, Uuid};
+use futures::StreamExt;
+use mea::semaphore::{OwnedSemaphorePermit, Semaphore};
+use std::{
+    future::Future,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::{
+    aggregate::Aggregate,
+    backend::{
+        DocumentStream, DynStoreBackend, InsertPolicy, MissingDocumentPolicy, QueryPlan,
+        ReturnDocument, StoreBackend, WriteReport,
+    },
+    error::{DocumentStoreError, DocumentStoreResult},
+    query::{Expr, Query, Sort},
+    time::Instant,
+    transaction::{Transaction, TransactionBackend},
+    update::Update,
+};
+
+/// Caps the number of operations in flight at once, queueing the rest.
+///
+/// Cloning a limiter shares its permits and metrics, so one limiter can guard several
+/// backends or stores.
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLimiter {
+    state: Arc<LimiterState>,
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    semaphore: Arc<Semaphore>,
+    max_in_flight: usize,
+    max_queued: Option<usize>,
+    in_flight: AtomicUsize,
+    queued: AtomicUsize,
+    peak_queued: AtomicUsize,
+    acquired: AtomicU64,
+    rejected: AtomicU64,
+    wait_micros: AtomicU64,
+}
+
+/// A point-in-time snapshot of a [`ConcurrencyLimiter`]'s activity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LimiterMetrics {
+    /// The maximum number of operations allowed in flight.
+    pub max_in_flight: usize,
+    /// Operations currently holding a permit.
+    pub in_flight: usize,
+    /// Operations currently waiting for a permit.
+    pub queued: usize,
+    /// The longest the queue has been.
+    pub peak_queued: usize,
+    /// Permits handed out so far.
+    pub acquired: u64,
+    /// Operations rejected because the queue was full.
+    pub rejected: u64,
+    /// Total time operations spent waiting for a permit.
+    pub total_wait: Duration,
+}
+
+impl LimiterMetrics {
+    /// Returns the average time an operation waited for its permit.
+    pub fn average_wait(&self) -> Duration {
+        match self.acquired {
+            0 => Duration::ZERO,
+            acquired => self.total_wait / acquired.min(u32::MAX as u64) as u32,
+        }
+    }
+}
+
+/// A permit to run one operation, released when dropped.
+#[derive(Debug)]
+pub struct LimiterPermit {
+    _permit: OwnedSemaphorePermit,
+    state: Arc<LimiterState>,
+}
+
+impl Drop for LimiterPermit {
+    fn drop(&mut self) {
+        self.state
+            .in_flight
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Keeps the queue length accurate when a waiting operation is cancelled.
+struct QueueSlot<'a>(&'a LimiterState);
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.0
+            .queued
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl ConcurrencyLimiter {
+    /// Creates a limiter allowing up to `max_in_flight` concurrent operations and an
+    /// unbounded queue.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_in_flight` is zero.
+    pub fn new(max_in_flight: usize) -> Self {
+        assert!(max_in_flight > 0, "max_in_flight must be at least 1");
+
+        Self {
+            state: Arc::new(LimiterState {
+                semaphore: Arc::new(Semaphore::new(max_in_flight)),
+                max_in_flight,
+                max_queued: None,
+                in_flight: AtomicUsize::new(0),
+                queued: AtomicUsize::new(0),
+                peak_queued: AtomicUsize::new(0),
+                acquired: AtomicU64::new(0),
+                rejected: AtomicU64::new(0),
+                wait_micros: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Bounds the queue, rejecting operations that arrive while `max_queued` are already waiting.
+    ///
+    /// Must be called before the limiter is cloned or used.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the limiter has already been cloned.
+    pub fn with_max_queued(mut self, max_queued: usize) -> Self {
+        Arc::get_mut(&mut self.state)
+            .expect("with_max_queued must be called before the limiter is shared")
+            .max_queued = Some(max_queued);
+        self
+    }
+
+    /// Waits for a permit to run one operation.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Backend`] if the queue is bounded and full.
+    pub async fn acquire(&self) -> DocumentStoreResult<LimiterPermit> {
+        let state = &self.state;
+
+        let permit = match state
+            .semaphore
+            .clone()
+            .try_acquire_owned(1)
+        {
+            Some(permit) => permit,
+            None => {
+                let queued = state
+                    .queued
+                    .fetch_add(1, Ordering::Relaxed)
+                    + 1;
+                let slot = QueueSlot(state);
+
+                if let Some(max_queued) = state.max_queued
+                    && queued > max_queued
+                {
+                    state
+                        .rejected
+                        .fetch_add(1, Ordering::Relaxed);
+                    return Err(DocumentStoreError::Backend(format!(
+                        "Concurrency limit reached: {} operations in flight and {} queued",
+                        state.max_in_flight, max_queued,
+                    )));
+                }
+                state
+                    .peak_queued
+                    .fetch_max(queued, Ordering::Relaxed);
+
+                let started = Instant::now();
+                let permit = state
+                    .semaphore
+                    .clone()
+                    .acquire_owned(1)
+                    .await;
+                drop(slot);
+
+                state.wait_micros.fetch_add(
+                    started
+                        .elapsed()
+                        .as_micros()
+                        .min(u64::MAX as u128) as u64,
+                    Ordering::Relaxed,
+                );
+                permit
+            }
+        };
+
+        state
+            .in_flight
+            .fetch_add(1, Ordering::Relaxed);
+        state
+            .acquired
+            .fetch_add(1, Ordering::Relaxed);
+
+        Ok(LimiterPermit { _permit: permit, state: state.clone() })
+    }
+
+    /// Runs an operation once a permit is available.
+    ///
+    /// # Errors
+    ///
+    /// Returns the operation's error, or [`DocumentStoreError::Backend`] if the queue is full.
+    pub async fn run<T>(
+        &self,
+        operation: impl Future<Output = DocumentStoreResult<T>>,
+    ) -> DocumentStoreResult<T> {
+        let _permit = self.acquire().await?;
+
+        operation.await
+    }
+
+    /// Returns a snapshot of the limiter's activity.
+    pub fn metrics(&self) -> LimiterMetrics {
+        let state = &self.state;
+
+        LimiterMetrics {
+            max_in_flight: state.max_in_flight,
+            in_flight: state.in_flight.load(Ordering::Relaxed),
+            queued: state.queued.load(Ordering::Relaxed),
+            peak_queued: state
+                .peak_queued
+                .load(Ordering::Relaxed),
+            acquired: state.acquired.load(Ordering::Relaxed),
+            rejected: state.rejected.load(Ordering::Relaxed),
+            total_wait: Duration::from_micros(
+                state
+                    .wait_micros
+                    .load(Ordering::Relaxed),
+            ),
+        }
+    }
+}
+
+/// A backend whose operations are limited by a [`ConcurrencyLimiter`].
+///
+/// Streams returned by [`query_stream`](StoreBackend::query_stream) hold their permit until
+/// they are dropped.
+#[derive(Debug)]
+pub struct ConcurrencyLimited<B> {
+    backend: B,
+    limiter: ConcurrencyLimiter,
+}
+
+impl<B: StoreBackend> ConcurrencyLimited<B> {
+    /// Wraps a backend so its operations go through `limiter`.
+    pub fn new(backend: B, limiter: ConcurrencyLimiter) -> Self {
+        Self { backend, limiter }
+    }
+
+    /// Returns the limiter guarding this backend.
+    pub fn limiter(&self) -> &ConcurrencyLimiter {
+        &self.limiter
+    }
+
+    /// Returns the wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.backend
+    }
+
+    /// Unwraps the backend, discarding the limiter.
+    pub fn into_inner(self) -> B {
+        self.backend
+    }
+}
+
+#[async_trait]
+impl<B: StoreBackend + 'static> StoreBackend for ConcurrencyLimited<B> {
+    async fn insert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.limiter
+            .run(
+                self.backend
+                    .insert_documents(documents, collection, policy),
+            )
+            .await
+    }
+
+    async fn update_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.limiter
+            .run(
+                self.backend
+                    .update_documents(documents, collection, policy),
+            )
+            .await
+    }
+
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.limiter
+            .run(
+                self.backend
+                    .update_documents_if_version(documents, version_field, collection),
+            )
+            .await
+    }
+
+    async fn update_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, Bson, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.limiter
+            .run(
+                self.backend
+                    .update_documents_if_match(documents, collection),
+            )
+            .await
+    }
+
+    async fn upsert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+    ) -> DocumentStoreResult<()> {
+        self.limiter
+            .run(
+                self.backend
+                    .upsert_documents(documents, collection),
+            )
+            .await
+    }
+
+    async fn delete_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        self.limiter
+            .run(
+                self.backend
+                    .delete_documents(ids, collection),
+            )
+            .await
+    }
+
+    async fn delete_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        self.limiter
+            .run(
+                self.backend
+                    .delete_documents_if_match(documents, collection),
+            )
+            .await
+    }
+
+    async fn update_by_query(
+        &self,
+        filter: Expr,
+        update: Update,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.limiter
+            .run(
+                self.backend
+                    .update_by_query(filter, update, collection),
+            )
+            .await
+    }
+
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize> {
+        self.limiter
+            .run(
+                self.backend
+                    .delete_by_query(filter, collection),
+            )
+            .await
+    }
+
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        self.limiter
+            .run(
+                self.backend
+                    .find_one_and_update(filter, sort, update, returned, collection),
+            )
+            .await
+    }
+
+    async fn find_one_and_delete(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        self.limiter
+            .run(
+                self.backend
+                    .find_one_and_delete(filter, sort, collection),
+            )
+            .await
+    }
+
+    async fn get_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.limiter
+            .run(
+                self.backend
+                    .get_documents(ids, collection),
+            )
+            .await
+    }
+
+    async fn query_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.limiter
+            .run(
+                self.backend
+                    .query_documents(query, collection),
+            )
+            .await
+    }
+
+    async fn query_stream(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<DocumentStream> {
+        let permit = self.limiter.acquire().await?;
+        let stream = self
+            .backend
+            .query_stream(query, collection)
+            .await?;
+
+        Ok(stream
+            .map(move |item| {
+                let _ = &permit;
+                item
+            })
+            .boxed())
+    }
+
+    async fn query_raw_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<RawDocumentBuf>> {
+        self.limiter
+            .run(
+                self.backend
+                    .query_raw_documents(query, collection),
+            )
+            .await
+    }
+
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize> {
+        self.limiter
+            .run(
+                self.backend
+                    .count_documents(query, collection),
+            )
+            .await
+    }
+
+    async fn distinct(
+        &self,
+        field: &str,
+        filter: Option<Expr>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.limiter
+            .run(
+                self.backend
+                    .distinct(field, filter, collection),
+            )
+            .await
+    }
+
+    async fn aggregate(
+        &self,
+        aggregate: Aggregate,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.limiter
+            .run(
+                self.backend
+                    .aggregate(aggregate, collection),
+            )
+            .await
+    }
+
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan> {
+        self.limiter
+            .run(self.backend.explain(query, collection))
+            .await
+    }
+
+    async fn current_revision_id(&self) -> DocumentStoreResult<Option<String>> {
+        self.limiter
+            .run(self.backend.current_revision_id())
+            .await
+    }
+
+    async fn set_revision_id(&self, revision_id: &str) -> DocumentStoreResult<()> {
+        self.limiter
+            .run(
+                self.backend
+                    .set_revision_id(revision_id),
+            )
+            .await
+    }
+
+    async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.limiter
+            .run(self.backend.create_collection(name))
+            .await
+    }
+
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        self.limiter
+            .run(self.backend.collection_exists(name))
+            .await
+    }
+
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.limiter
+            .run(self.backend.ensure_collection(name))
+            .await
+    }
+
+    async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.limiter
+            .run(self.backend.drop_collection(name))
+            .await
+    }
+
+    async fn list_collections(&self) -> DocumentStoreResult<Vec<String>> {
+        self.limiter
+            .run(self.backend.list_collections())
+            .await
+    }
+
+    async fn add_field(
+        &self,
+        collection: &str,
+        field: &str,
+        default: Bson,
+    ) -> DocumentStoreResult<()> {
+        self.limiter
+            .run(
+                self.backend
+                    .add_field(collection, field, default),
+            )
+            .await
+    }
+
+    async fn drop_field(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.limiter
+            .run(
+                self.backend
+                    .drop_field(collection, field),
+            )
+            .await
+    }
+
+    async fn rename_field(
+        &self,
+        collection: &str,
+        field: &str,
+        new: &str,
+    ) -> DocumentStoreResult<()> {
+        self.limiter
+            .run(
+                self.backend
+                    .rename_field(collection, field, new),
+            )
+            .await
+    }
+
+    async fn add_index(
+        &self,
+        collection: &str,
+        field: &str,
+        unique: bool,
+    ) -> DocumentStoreResult<()> {
+        self.limiter
+            .run(
+                self.backend
+                    .add_index(collection, field, unique),
+            )
+            .await
+    }
+
+    async fn drop_index(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.limiter
+            .run(
+                self.backend
+                    .drop_index(collection, field),
+            )
+            .await
+    }
+
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.limiter
+            .run(
+                self.backend
+                    .expire_documents(collection, field),
+            )
+            .await
+    }
+
+    async fn begin_transaction(&self) -> DocumentStoreResult<Transaction> {
+        let transaction = self
+            .limiter
+            .run(self.backend.begin_transaction())
+            .await?;
+
+        Ok(Transaction::new(ConcurrencyLimited::new(transaction, self.limiter.clone())))
+    }
+
+    fn inner_backend(&self) -> Option<&dyn DynStoreBackend> {
+        Some(&self.backend)
+    }
+
+    async fn close(&self) -> DocumentStoreResult<()> {
+        self.backend.close().await
+    }
+
+    async fn shutdown(self) -> DocumentStoreResult<()> {
+        self.backend.shutdown().await
+    }
+}
+
+// Transactions of a limited backend are limited by the same limiter
+#[async_trait]
+impl TransactionBackend for ConcurrencyLimited<Transaction> {
+    fn backend(&self) -> &dyn DynStoreBackend {
+        self
+    }
+
+    async fn commit(self: Box<Self>) -> DocumentStoreResult<()> {
+        let limiter = self.limiter.clone();
+
+        limiter.run(self.backend.commit()).await
+    }
+
+    async fn rollback(self: Box<Self>) -> DocumentStoreResult<()> {
+        self.backend.rollback().await
+    }
+}
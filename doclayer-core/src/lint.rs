@@ -0,0 +1,453 @@
+//! Checking stored documents against the type that reads them.
+//!
+//! Documents written by older code, other services or manual edits may hold a field in a
+//! different BSON type than the document type now expects, such as a number stored as a
+//! string. Such documents fail to deserialize, usually long after they were written.
+//! [`lint_collection`](crate::store::DocumentStore::lint_collection) scans a collection and
+//! reports every field whose stored type the document type rejects, with how many documents
+//! are affected and a few of their IDs, so the data can be fixed before strict validation
+//! is enabled.
+//!
+//! Where the stored values can be converted to a type the document type accepts, the report
+//! suggests a [`LintFix`], which can be applied from a migration:
+//!
+//! ```ignore
+//! let report = store.lint_collection::<User>("users").await?;
+//!
+//! for mismatch in &report.mismatches {
+//!     println!("{}", mismatch);
+//!     if let Some(fix) = &mismatch.fix {
+//!         println!("  fix: {}", fix);
+//!     }
+//! }
+//! ```
+//!
+//! Fields are checked one at a time against the document type, at the top level of each
+//! document. A field is reported when the document type fails to read it on its own, so
+//! types with custom deserialization are checked as they actually read documents.
+
+use bson::{Binary, Bson, Uuid, spec::ElementType};
+use futures::TryStreamExt;
+use serde::de::{self, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, Visitor};
+use std::{cell::Cell, fmt};
+
+use crate::{
+    backend::DynStoreBackend,
+    document::{Document, upgrade_document},
+    encoding::Encoding,
+    error::DocumentStoreResult,
+    migrate::MigrateOp,
+    query::Query,
+};
+
+/// The number of example IDs kept for each mismatch.
+pub const MAX_EXAMPLE_IDS: usize = 5;
+
+/// The types stored values are tried in, in order, to find one the document type accepts.
+const CANDIDATE_TYPES: [ElementType; 7] = [
+    ElementType::Int32,
+    ElementType::Int64,
+    ElementType::Double,
+    ElementType::Boolean,
+    ElementType::String,
+    ElementType::DateTime,
+    ElementType::Binary,
+];
+
+/// The result of linting a collection against a document type.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LintReport {
+    /// The collection scanned.
+    pub collection: String,
+    /// The number of documents scanned.
+    pub scanned: usize,
+    /// The number of documents the document type fails to read.
+    pub invalid: usize,
+    /// The number of invalid documents whose fields all read on their own, typically
+    /// because a required field is missing.
+    pub unexplained: usize,
+    /// The fields holding a type the document type rejects, grouped by field and type.
+    pub mismatches: Vec<FieldMismatch>,
+}
+
+impl LintReport {
+    /// Returns `true` if every scanned document can be read by the document type.
+    pub fn is_clean(&self) -> bool {
+        self.invalid == 0
+    }
+
+    /// Returns the suggested fixes of every mismatch that has one.
+    pub fn fixes(&self) -> Vec<&LintFix> {
+        self.mismatches
+            .iter()
+            .filter_map(|mismatch| mismatch.fix.as_ref())
+            .collect()
+    }
+}
+
+/// A field holding a type the document type rejects, in some documents of a collection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldMismatch {
+    /// The field name.
+    pub field: String,
+    /// The type stored in the field.
+    pub found: ElementType,
+    /// A type the stored values convert to and the document type accepts, if one was found.
+    ///
+    /// `None` for fields the document type doesn't know, and for values that don't convert
+    /// to an accepted type, such as a null in a required field.
+    pub expected: Option<ElementType>,
+    /// Whether the document type rejects the field name itself, as types denying unknown
+    /// fields do.
+    pub unknown_field: bool,
+    /// The number of documents with this mismatch.
+    pub count: usize,
+    /// The IDs of up to [`MAX_EXAMPLE_IDS`] documents with this mismatch.
+    pub example_ids: Vec<String>,
+    /// A suggested migration step fixing the stored values.
+    pub fix: Option<LintFix>,
+}
+
+impl fmt::Display for FieldMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.unknown_field, self.expected) {
+            (true, _) => write!(f, "unknown field '{}'", self.field)?,
+            (false, Some(expected)) => write!(
+                f,
+                "field '{}' holds {:?} where {:?} is expected",
+                self.field, self.found, expected
+            )?,
+            (false, None) => write!(
+                f,
+                "field '{}' holds {:?}, which the document type rejects",
+                self.field, self.found
+            )?,
+        }
+
+        write!(f, " in {} documents (e.g. {})", self.count, self.example_ids.join(", "))
+    }
+}
+
+/// A migration step suggested by a [`LintReport`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum LintFix {
+    /// Convert the field's values to another type, with [`MigrateOp::convert_field`].
+    ConvertField {
+        collection: String,
+        field: String,
+        to: ElementType,
+    },
+    /// Remove the field from every document, with [`MigrateOp::drop_field`].
+    DropField { collection: String, field: String },
+}
+
+impl LintFix {
+    /// Applies the fix from within a migration.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying migration operation fails.
+    pub async fn apply(&self, op: &MigrateOp<'_>) -> DocumentStoreResult<()> {
+        match self {
+            LintFix::ConvertField { collection, field, to } => op
+                .convert_field(collection, field, *to)
+                .await
+                .map(|_| ()),
+            LintFix::DropField { collection, field } => op.drop_field(collection, field).await,
+        }
+    }
+}
+
+/// Renders the fix as the migration code applying it.
+impl fmt::Display for LintFix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LintFix::ConvertField { collection, field, to } => write!(
+                f,
+                "op.convert_field({:?}, {:?}, ElementType::{:?}).await?;",
+                collection, field, to
+            ),
+            LintFix::DropField { collection, field } => {
+                write!(f, "op.drop_field({:?}, {:?}).await?;", collection, field)
+            }
+        }
+    }
+}
+
+/// Scans a collection and reports the fields `D` rejects.
+pub(crate) async fn lint_collection<D: Document>(
+    backend: &dyn DynStoreBackend,
+    collection: &str,
+) -> DocumentStoreResult<LintReport> {
+    let encoding = backend.encoding().clone();
+    let mut report = LintReport {
+        collection: collection.to_string(),
+        ..LintReport::default()
+    };
+    let mut documents = backend
+        .query_stream(Query::default(), collection)
+        .await?;
+
+    while let Some(document) = documents.try_next().await? {
+        report.scanned += 1;
+
+        let id = document_id(&document);
+        let Ok(Bson::Document(fields)) = upgrade_document::<D>(document) else {
+            report.invalid += 1;
+            report.unexplained += 1;
+            continue;
+        };
+
+        if encoding
+            .deserialize::<D>(Bson::Document(fields.clone()))
+            .is_ok()
+        {
+            continue;
+        }
+
+        report.invalid += 1;
+        let mut explained = false;
+
+        for (field, value) in fields {
+            let (found, expected, unknown_field) =
+                match probe::<D>(&encoding, &field, value.clone()) {
+                    Probe::Accepted => continue,
+                    Probe::UnknownField => (value.element_type(), None, true),
+                    Probe::Rejected => (
+                        value.element_type(),
+                        CANDIDATE_TYPES
+                            .into_iter()
+                            .filter(|candidate| *candidate != value.element_type())
+                            .find(|candidate| {
+                                convert_value(&value, *candidate).is_some_and(|converted| {
+                                    probe::<D>(&encoding, &field, converted) == Probe::Accepted
+                                })
+                            }),
+                        false,
+                    ),
+                };
+
+            explained = true;
+            report.record(field, found, expected, unknown_field, id.as_deref());
+        }
+
+        if !explained {
+            report.unexplained += 1;
+        }
+    }
+
+    Ok(report)
+}
+
+impl LintReport {
+    fn record(
+        &mut self,
+        field: String,
+        found: ElementType,
+        expected: Option<ElementType>,
+        unknown_field: bool,
+        id: Option<&str>,
+    ) {
+        let index = match self
+            .mismatches
+            .iter()
+            .position(|mismatch| {
+                mismatch.field == field
+                    && mismatch.found == found
+                    && mismatch.expected == expected
+                    && mismatch.unknown_field == unknown_field
+            }) {
+            Some(index) => index,
+            None => {
+                let fix = match (unknown_field, expected) {
+                    (true, _) => Some(LintFix::DropField {
+                        collection: self.collection.clone(),
+                        field: field.clone(),
+                    }),
+                    (false, Some(to)) => Some(LintFix::ConvertField {
+                        collection: self.collection.clone(),
+                        field: field.clone(),
+                        to,
+                    }),
+                    (false, None) => None,
+                };
+
+                self.mismatches.push(FieldMismatch {
+                    field,
+                    found,
+                    expected,
+                    unknown_field,
+                    count: 0,
+                    example_ids: Vec::new(),
+                    fix,
+                });
+                self.mismatches.len() - 1
+            }
+        };
+
+        let mismatch = &mut self.mismatches[index];
+        mismatch.count += 1;
+        if let Some(id) = id
+            && mismatch.example_ids.len() < MAX_EXAMPLE_IDS
+        {
+            mismatch
+                .example_ids
+                .push(id.to_string());
+        }
+    }
+}
+
+/// Reads the ID of a stored document from its `id` or `_id` field.
+pub(crate) fn document_id(document: &Bson) -> Option<String> {
+    let document = document.as_document()?;
+
+    match document
+        .get("id")
+        .or_else(|| document.get("_id"))?
+    {
+        Bson::Binary(binary) => binary
+            .to_uuid()
+            .ok()
+            .map(|id| id.to_string()),
+        Bson::String(id) => Some(id.clone()),
+        id => Some(id.to_string()),
+    }
+}
+
+/// Converts a value to another BSON type, if it has a faithful representation in it.
+///
+/// Numbers convert between each other when no precision is lost, strings parse into
+/// numbers, booleans, RFC 3339 datetimes and UUIDs, and every scalar converts to a string.
+pub(crate) fn convert_value(value: &Bson, to: ElementType) -> Option<Bson> {
+    if value.element_type() == to {
+        return Some(value.clone());
+    }
+
+    match (value, to) {
+        (Bson::Int32(n), ElementType::Int64) => Some(Bson::Int64(i64::from(*n))),
+        (Bson::Int32(n), ElementType::Double) => Some(Bson::Double(f64::from(*n))),
+        (Bson::Int64(n), ElementType::Int32) => i32::try_from(*n).ok().map(Bson::Int32),
+        (Bson::Int64(n), ElementType::Double) => {
+            let converted = *n as f64;
+            (converted as i64 == *n).then_some(Bson::Double(converted))
+        }
+        (Bson::Double(n), ElementType::Int32) => {
+            (n.fract() == 0.0 && *n >= f64::from(i32::MIN) && *n <= f64::from(i32::MAX))
+                .then_some(Bson::Int32(*n as i32))
+        }
+        (Bson::Double(n), ElementType::Int64) => {
+            (n.fract() == 0.0 && *n >= i64::MIN as f64 && *n < i64::MAX as f64)
+                .then_some(Bson::Int64(*n as i64))
+        }
+        (Bson::String(s), ElementType::Int32) => s.trim().parse().ok().map(Bson::Int32),
+        (Bson::String(s), ElementType::Int64) => s.trim().parse().ok().map(Bson::Int64),
+        (Bson::String(s), ElementType::Double) => s
+            .trim()
+            .parse()
+            .ok()
+            .filter(|n: &f64| n.is_finite())
+            .map(Bson::Double),
+        (Bson::String(s), ElementType::Boolean) => match s.trim() {
+            "true" => Some(Bson::Boolean(true)),
+            "false" => Some(Bson::Boolean(false)),
+            _ => None,
+        },
+        (Bson::String(s), ElementType::DateTime) => bson::DateTime::parse_rfc3339_str(s.trim())
+            .ok()
+            .map(Bson::DateTime),
+        (Bson::String(s), ElementType::Binary) => Uuid::parse_str(s.trim())
+            .ok()
+            .map(|id| Bson::Binary(Binary::from(id))),
+        (Bson::Binary(binary), ElementType::String) => binary
+            .to_uuid()
+            .ok()
+            .map(|id| Bson::String(id.to_string())),
+        (Bson::DateTime(datetime), ElementType::String) => datetime
+            .try_to_rfc3339_string()
+            .ok()
+            .map(Bson::String),
+        (
+            Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_) | Bson::Boolean(_),
+            ElementType::String,
+        ) => Some(Bson::String(value.to_string())),
+        _ => None,
+    }
+}
+
+/// How a document type read a single field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Probe {
+    Accepted,
+    Rejected,
+    UnknownField,
+}
+
+/// Reads `D` from a document holding only the given field, and reports how the field fared.
+///
+/// Deserialization still fails afterwards when `D` has other required fields, but only once
+/// every present field was read, so the outcome of the field is known either way.
+fn probe<D: Document>(encoding: &Encoding, field: &str, value: Bson) -> Probe {
+    let outcome = Cell::new(Probe::Accepted);
+    let _ = D::deserialize(FieldProbe {
+        field,
+        value: Some(value),
+        key_read: false,
+        encoding,
+        outcome: &outcome,
+    });
+
+    outcome.get()
+}
+
+/// A deserializer presenting a single field as a map.
+struct FieldProbe<'a> {
+    field: &'a str,
+    value: Option<Bson>,
+    key_read: bool,
+    encoding: &'a Encoding,
+    outcome: &'a Cell<Probe>,
+}
+
+impl<'de> Deserializer<'de> for FieldProbe<'_> {
+    type Error = bson::error::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map struct enum
+        identifier ignored_any
+    }
+}
+
+impl<'de> MapAccess<'de> for FieldProbe<'_> {
+    type Error = bson::error::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        if self.key_read {
+            return Ok(None);
+        }
+        self.key_read = true;
+
+        let key: de::value::StrDeserializer<'_, Self::Error> = self.field.into_deserializer();
+        seed.deserialize(key)
+            .map(Some)
+            .inspect_err(|_| self.outcome.set(Probe::UnknownField))
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let value = self.value.take().unwrap_or(Bson::Null);
+
+        self.encoding
+            .deserialize_seed(seed, value)
+            .inspect_err(|_| self.outcome.set(Probe::Rejected))
+    }
+}
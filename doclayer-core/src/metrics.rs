@@ -0,0 +1,166 @@
+//! Metrics for store operations.
+//!
+//! Requires the `metrics` feature. A [`MetricsStore`] reports every [`StoreBackend`] call
+//! through the [`metrics`](::metrics) crate facade, so any installed recorder, such as a
+//! Prometheus exporter, receives them. With no recorder installed the calls do nothing.
+//!
+//! Three metrics are emitted, labelled with `operation`, the [`StoreBackend`] method, and
+//! `collection`, empty for operations without one:
+//!
+//! - `doclayer_operations_total` - a counter of operations, also labelled with `status`,
+//!   either `ok` or `error`
+//! - `doclayer_errors_total` - a counter of failed operations, also labelled with `kind`,
+//!   the [`DocumentStoreError::kind`]
+//! - `doclayer_operation_duration_seconds` - a histogram of operation latency
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::metrics::Metrics;
+//!
+//! PrometheusBuilder::new().install()?;
+//!
+//! let metrics = Metrics::new();
+//! metrics.describe();
+//! let store = DocumentStore::new(metrics.wrap(backend));
+//! ```
+//!
+//! The `doclayer` prefix can be changed with [`Metrics::with_prefix`], for example to tell
+//! several stores apart.
+
+use async_trait::async_trait;
+use std::sync::Arc;
+
+use crate::{
+    backend::{Layered, Next, Operation, Outcome, StoreBackend, StoreLayer},
+    error::{DocumentStoreError, DocumentStoreResult},
+    time::Instant,
+};
+
+/// A backend whose operations are measured by a [`Metrics`] layer.
+pub type MetricsStore<B> = Layered<Metrics, B>;
+
+/// The default prefix of metric names.
+pub const DEFAULT_PREFIX: &str = "doclayer";
+
+#[derive(Debug)]
+struct MetricNames {
+    operations: String,
+    errors: String,
+    duration: String,
+}
+
+impl MetricNames {
+    fn new(prefix: &str) -> Self {
+        Self {
+            operations: format!("{}_operations_total", prefix),
+            errors: format!("{}_errors_total", prefix),
+            duration: format!("{}_operation_duration_seconds", prefix),
+        }
+    }
+}
+
+/// A [`StoreLayer`] counting operations and errors and measuring their latency.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    names: Arc<MetricNames>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    /// Creates a layer emitting metrics prefixed with `doclayer`.
+    pub fn new() -> Self {
+        Self {
+            names: Arc::new(MetricNames::new(DEFAULT_PREFIX)),
+        }
+    }
+
+    /// Prefixes the metric names with `prefix` instead, giving e.g.
+    /// `{prefix}_operations_total`.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.names = Arc::new(MetricNames::new(prefix));
+        self
+    }
+
+    /// Wraps a backend so its operations are measured.
+    pub fn wrap<B: StoreBackend + 'static>(self, backend: B) -> MetricsStore<B> {
+        Layered::new(self, backend)
+    }
+
+    /// Describes the metrics to the installed recorder, which exporters such as Prometheus
+    /// publish as help text. Call it once after installing the recorder.
+    pub fn describe(&self) {
+        ::metrics::describe_counter!(
+            self.names.operations.clone(),
+            "Operations issued to the document store backend"
+        );
+        ::metrics::describe_counter!(
+            self.names.errors.clone(),
+            "Document store backend operations that failed"
+        );
+        ::metrics::describe_histogram!(
+            self.names.duration.clone(),
+            ::metrics::Unit::Seconds,
+            "Latency of document store backend operations"
+        );
+    }
+
+    fn record(
+        &self,
+        operation: &'static str,
+        collection: String,
+        seconds: f64,
+        error: Option<&DocumentStoreError>,
+    ) {
+        let status = match error {
+            Some(_) => "error",
+            None => "ok",
+        };
+
+        ::metrics::counter!(
+            self.names.operations.clone(),
+            "operation" => operation,
+            "collection" => collection.clone(),
+            "status" => status,
+        )
+        .increment(1);
+        ::metrics::histogram!(
+            self.names.duration.clone(),
+            "operation" => operation,
+            "collection" => collection.clone(),
+        )
+        .record(seconds);
+
+        if let Some(error) = error {
+            ::metrics::counter!(
+                self.names.errors.clone(),
+                "operation" => operation,
+                "collection" => collection,
+                "kind" => error.kind(),
+            )
+            .increment(1);
+        }
+    }
+}
+
+#[async_trait]
+impl StoreLayer for Metrics {
+    async fn call(&self, operation: Operation, next: Next<'_>) -> DocumentStoreResult<Outcome> {
+        let name = operation.name();
+        let collection = operation
+            .collection()
+            .unwrap_or_default()
+            .to_string();
+        let started = Instant::now();
+        let outcome = next.run(operation).await;
+
+        self.record(name, collection, started.elapsed().as_secs_f64(), outcome.as_ref().err());
+
+        outcome
+    }
+}
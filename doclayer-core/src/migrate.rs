@@ -6,9 +6,12 @@
 //! # Migration Traits
 //!
 //! - [`Migration`] - Individual migration step (upgrade/downgrade)
+//! - [`IrreversibleMigration`] - Migration step that can only be applied
 //! - [`Migrations`] - Registry of all available migrations
 //! - [`Migrator`] - Auto-implemented trait for running migrations
 //!
+//! The [`autogen`] module generates migrations from the changes between schema snapshots.
+//!
 //! # Example
 //!
 //! ```ignore
@@ -55,22 +58,94 @@
 //!
 //! store.downgrade_to::<MyMigrations>("001_initial").await?;
 //! ```
+//!
+//! [`Migrator::migration_status`] reports the pending migrations without running them, for
+//! health checks:
+//!
+//! ```ignore
+//! let status = store.migration_status::<MyMigrations>().await?;
+//! println!("{} pending migrations", status.pending.len());
+//! ```
+//!
+//! Besides the current revision, every run of a migration is appended to the
+//! [`MIGRATIONS_COLLECTION`] as a [`MigrationRecord`], with its direction, timing and outcome,
+//! and returned by [`Migrator::migration_history`]:
+//!
+//! ```ignore
+//! for record in store.migration_history().await? {
+//!     println!("{} {:?} {:?} {}", record.migration, record.direction, record.duration(), record.success);
+//! }
+//! ```
+//!
+//! # Irreversible migrations
+//!
+//! A migration that can't be reverted implements [`IrreversibleMigration`], which has no
+//! `down`, instead of [`Migration`]. Downgrades that would revert it fail with
+//! [`DocumentStoreError::IrreversibleMigration`] before reverting anything.
+//!
+//! # Merging branches
+//!
+//! Migrations form a graph through [`Migration::previous_ids`], not just a chain. Branches
+//! that each added migrations after the same revision are joined by a [`MergeMigration`]
+//! following both heads; [`MigrationRunner::upgrade`] refuses to run while a chain has more
+//! than one head. A store that has applied only some branches records their heads as its
+//! revision, joined by [`REVISION_SEPARATOR`], so migration IDs must not contain it.
+//!
+//! # Concurrent runners
+//!
+//! A [`MigrationRunner`] holds the store's migration lock while it applies migrations, so of
+//! several application instances upgrading the same store at startup only one applies them.
+//! The others wait for the lock and find the store already upgraded, or return at once with
+//! [`LockPolicy::Skip`]:
+//!
+//! ```ignore
+//! MigrationRunner::<MyMigrations>::new()
+//!     .with_lock_policy(LockPolicy::Skip)
+//!     .upgrade(store.as_dyn())
+//!     .await?;
+//! ```
+
+pub mod autogen;
 
 use async_trait::async_trait;
-use bson::{Bson, Uuid};
+use bson::{Bson, Uuid, spec::ElementType};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     marker::PhantomData,
+    time::Duration,
 };
 
 use crate::{
-    document::Document,
+    backend::{InsertPolicy, StoreBackend, WriteReport},
+    document::{Document, DocumentExt, stored_id},
     error::{DocumentStoreError, DocumentStoreResult},
-    query::Query,
+    lint::convert_value,
+    query::{Expr, Filter, Query, SortDirection},
+    retry::sleep,
     store::{AsDynDocumentStore, DynDocumentStoreRef},
+    update::Update,
+    versioned::document_revision,
 };
 
+/// The collection recording every migration applied to a store, successful or not.
+pub const MIGRATIONS_COLLECTION: &str = "_migrations";
+
+/// How many documents [`MigrateOp::transform`] and [`MigrateOp::transform_bson`] write at a
+/// time.
+pub const TRANSFORM_BATCH_SIZE: usize = 500;
+
+/// The collection holding the [`MigrationLock`] of a store, while a runner holds it.
+pub const MIGRATION_LOCK_COLLECTION: &str = "_migration_lock";
+
+/// How long a [`MigrationRunner`] holds the migration lock before another runner may take it
+/// over, unless it's released or extended first.
+pub const DEFAULT_LOCK_TTL: Duration = Duration::from_secs(600);
+
 /// Direction of schema migration (upgrade or downgrade to different version).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum MigrationDirection {
     /// Upgrade to a newer schema version.
     Up,
@@ -90,7 +165,18 @@ pub trait Migration: Send + Sync {
 
     /// Returns the ID of the migration this one follows (for ordering).
     /// Should return `None` for the initial migration.
-    fn previous_id(&self) -> Option<&'static str>;
+    fn previous_id(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Returns the IDs of every migration this one follows.
+    ///
+    /// Defaults to the [`previous_id`](Migration::previous_id). Merge migrations, which join
+    /// branches of migrations added in parallel, return the head of each branch; see
+    /// [`MergeMigration`].
+    fn previous_ids(&self) -> Vec<&'static str> {
+        self.previous_id().into_iter().collect()
+    }
 
     /// Executes this migration in the upgrade direction.
     ///
@@ -105,6 +191,9 @@ pub trait Migration: Send + Sync {
 
     /// Executes this migration in the downgrade direction (reverses the changes from `up`).
     ///
+    /// Migrations that can't be reverted, such as those dropping data, implement
+    /// [`IrreversibleMigration`] instead.
+    ///
     /// # Arguments
     ///
     /// * `op` - Operation context providing access to the document store for this migration
@@ -113,10 +202,424 @@ pub trait Migration: Send + Sync {
     ///
     /// Returns a [`DocumentStoreError`] if migration fails.
     async fn down(&self, op: &MigrateOp<'_>) -> DocumentStoreResult<()>;
+
+    /// Returns whether [`down`](Migration::down) reverts this migration.
+    ///
+    /// `false` for every [`IrreversibleMigration`]. A [`MigrationRunner`] refuses downgrades
+    /// that would revert an irreversible migration before reverting anything, unless
+    /// [`refuse_irreversible`](MigrationRunner::refuse_irreversible) is turned off.
+    fn is_reversible(&self) -> bool {
+        true
+    }
+}
+
+/// A migration step that can't be reverted, such as one dropping data.
+///
+/// Every `IrreversibleMigration` is a [`Migration`] whose [`down`](Migration::down) fails with
+/// [`DocumentStoreError::IrreversibleMigration`] and whose
+/// [`is_reversible`](Migration::is_reversible) is `false`, so downgrades that would revert it
+/// are refused before anything is reverted:
+///
+/// ```ignore
+/// #[async_trait::async_trait]
+/// impl IrreversibleMigration for DropLegacyFields {
+///     fn id(&self) -> &'static str { "003_drop_legacy_fields" }
+///     fn previous_id(&self) -> Option<&'static str> { Some("002_add_name") }
+///
+///     async fn up(&self, op: &MigrateOp<'_>) -> DocumentStoreResult<()> {
+///         op.drop_field("users", "legacy_id").await
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait IrreversibleMigration: Send + Sync {
+    /// Returns a unique identifier for this migration.
+    fn id(&self) -> &'static str;
+
+    /// Returns the ID of the migration this one follows, or `None` for the initial migration.
+    fn previous_id(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Returns the IDs of every migration this one follows.
+    ///
+    /// Defaults to the [`previous_id`](IrreversibleMigration::previous_id).
+    fn previous_ids(&self) -> Vec<&'static str> {
+        self.previous_id().into_iter().collect()
+    }
+
+    /// Executes this migration.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`DocumentStoreError`] if migration fails.
+    async fn up(&self, op: &MigrateOp<'_>) -> DocumentStoreResult<()>;
+}
+
+#[async_trait]
+impl<T: IrreversibleMigration> Migration for T {
+    fn id(&self) -> &'static str {
+        IrreversibleMigration::id(self)
+    }
+
+    fn previous_id(&self) -> Option<&'static str> {
+        IrreversibleMigration::previous_id(self)
+    }
+
+    fn previous_ids(&self) -> Vec<&'static str> {
+        IrreversibleMigration::previous_ids(self)
+    }
+
+    async fn up(&self, op: &MigrateOp<'_>) -> DocumentStoreResult<()> {
+        IrreversibleMigration::up(self, op).await
+    }
+
+    async fn down(&self, _op: &MigrateOp<'_>) -> DocumentStoreResult<()> {
+        Err(DocumentStoreError::IrreversibleMigration(IrreversibleMigration::id(self).to_string(), Vec::new()))
+    }
+
+    fn is_reversible(&self) -> bool {
+        false
+    }
 }
 
 pub type MigrationRef = Box<dyn Migration>;
 
+/// A migration joining branches of migrations, with no changes of its own.
+///
+/// When two branches each add migrations after the same revision, the chain has two heads
+/// and [`upgrade`](MigrationRunner::upgrade) refuses to pick one. A merge migration following
+/// both heads joins them, so neither branch needs renumbering:
+///
+/// ```ignore
+/// Box::new(MergeMigration::new("005_merge", ["003_add_orders", "004_add_tags"]))
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeMigration {
+    id: &'static str,
+    parents: Vec<&'static str>,
+}
+
+impl MergeMigration {
+    /// Creates a migration following every migration in `parents`.
+    pub fn new(id: &'static str, parents: impl IntoIterator<Item = &'static str>) -> Self {
+        Self {
+            id,
+            parents: parents.into_iter().collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl Migration for MergeMigration {
+    fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn previous_ids(&self) -> Vec<&'static str> {
+        self.parents.clone()
+    }
+
+    async fn up(&self, _op: &MigrateOp<'_>) -> DocumentStoreResult<()> {
+        Ok(())
+    }
+
+    async fn down(&self, _op: &MigrateOp<'_>) -> DocumentStoreResult<()> {
+        Ok(())
+    }
+}
+
+/// A run of a migration, recorded in the [`MIGRATIONS_COLLECTION`].
+///
+/// Records are only ever appended, so the collection holds the full history of a store's
+/// migrations, including those of plugins and those that failed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MigrationRecord {
+    /// The ID of the record.
+    pub id: Uuid,
+    /// The position of the run in the history, starting at 1.
+    pub sequence: u64,
+    /// The ID of the migration that ran.
+    pub migration: String,
+    /// Whether the migration was applied or reverted.
+    pub direction: MigrationDirection,
+    /// The plugin the migration belongs to, if it was applied by
+    /// [`register_plugin`](crate::plugin::PluginHost::register_plugin).
+    pub plugin: Option<String>,
+    /// When the migration started.
+    pub started_at: bson::DateTime,
+    /// When the migration finished or failed.
+    pub finished_at: bson::DateTime,
+    /// How long the migration ran, in milliseconds.
+    pub duration_ms: i64,
+    /// Whether the migration completed.
+    pub success: bool,
+    /// The error the migration failed with.
+    pub error: Option<String>,
+}
+
+impl MigrationRecord {
+    /// Returns how long the migration ran.
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms.max(0) as u64)
+    }
+}
+
+impl Document for MigrationRecord {
+    fn id(&self) -> &Uuid {
+        &self.id
+    }
+
+    fn collection_name() -> &'static str {
+        MIGRATIONS_COLLECTION
+    }
+}
+
+/// Runs a migration and appends the run to the [`MIGRATIONS_COLLECTION`].
+///
+/// The error of a failed migration is returned even if recording it fails too.
+pub(crate) async fn run_migration(
+    migration: &dyn Migration,
+    direction: MigrationDirection,
+    plugin: Option<&str>,
+    op: &MigrateOp<'_>,
+) -> DocumentStoreResult<()> {
+    op.ensure_collection(MIGRATIONS_COLLECTION)
+        .await?;
+
+    let records = op
+        .store
+        .typed_collection::<MigrationRecord>();
+    let sequence = records.count(Query::default()).await? as u64 + 1;
+
+    let started_at = bson::DateTime::now();
+    let result = match direction {
+        MigrationDirection::Up => migration.up(op).await,
+        MigrationDirection::Down => migration.down(op).await,
+    };
+    let finished_at = bson::DateTime::now();
+
+    let record = MigrationRecord {
+        id: Uuid::new(),
+        sequence,
+        migration: migration.id().to_string(),
+        direction,
+        plugin: plugin.map(str::to_string),
+        started_at,
+        finished_at,
+        duration_ms: finished_at.timestamp_millis() - started_at.timestamp_millis(),
+        success: result.is_ok(),
+        error: result
+            .as_ref()
+            .err()
+            .map(ToString::to_string),
+    };
+    let recorded = records.insert(vec![record]).await;
+
+    result?;
+    recorded
+}
+
+/// Where a store stands in a chain of migrations, returned by
+/// [`Migrator::migration_status`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MigrationStatus {
+    /// The revision the store is at, or `None` if no migration has been applied.
+    pub current: Option<String>,
+    /// The latest revision of the chain, or `None` if it has no migrations.
+    pub head: Option<String>,
+    /// The IDs of the migrations an upgrade would apply, in order.
+    pub pending: Vec<String>,
+}
+
+impl MigrationStatus {
+    /// Returns whether every migration has been applied.
+    pub fn is_up_to_date(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// The advisory lock taken by a [`MigrationRunner`] while it applies migrations.
+///
+/// Stored by the default implementation of
+/// [`acquire_migration_lock`](StoreBackend::acquire_migration_lock) as the only document of
+/// the [`MIGRATION_LOCK_COLLECTION`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MigrationLock {
+    /// The ID of the lock, the same for every owner.
+    pub id: Uuid,
+    /// The name of the runner holding the lock.
+    pub owner: String,
+    /// When the owner acquired the lock.
+    pub acquired_at: bson::DateTime,
+    /// When the lock may be taken over by another owner.
+    pub expires_at: bson::DateTime,
+}
+
+impl MigrationLock {
+    fn id() -> Uuid {
+        Uuid::from_bytes([0; 16])
+    }
+}
+
+impl Document for MigrationLock {
+    fn id(&self) -> &Uuid {
+        &self.id
+    }
+
+    fn collection_name() -> &'static str {
+        MIGRATION_LOCK_COLLECTION
+    }
+}
+
+/// Returns the lock currently stored, along with its stored form.
+async fn stored_lock<B: StoreBackend + ?Sized>(
+    backend: &B,
+) -> DocumentStoreResult<Option<(MigrationLock, Bson)>> {
+    let Some(stored) = backend
+        .get_documents(vec![MigrationLock::id()], MIGRATION_LOCK_COLLECTION)
+        .await?
+        .into_iter()
+        .next()
+    else {
+        return Ok(None);
+    };
+
+    Ok(Some((
+        MigrationLock::from_bson_encoded(stored.clone(), backend.encoding())?,
+        stored,
+    )))
+}
+
+/// The default implementation of [`StoreBackend::acquire_migration_lock`].
+///
+/// The lock is created with [`InsertPolicy::ErrorOnConflict`], so of two runners creating it
+/// only one succeeds, and extended or taken over with
+/// [`update_documents_if_match`](StoreBackend::update_documents_if_match), so of two runners
+/// taking over an expired lock only one succeeds.
+pub(crate) async fn acquire_lock<B: StoreBackend + ?Sized>(
+    backend: &B,
+    owner: &str,
+    ttl: Duration,
+) -> DocumentStoreResult<bool> {
+    backend
+        .ensure_collection(MIGRATION_LOCK_COLLECTION)
+        .await?;
+
+    loop {
+        let now = bson::DateTime::now();
+        let mut lock = MigrationLock {
+            id: MigrationLock::id(),
+            owner: owner.to_string(),
+            acquired_at: now,
+            expires_at: bson::DateTime::from_millis(
+                now.timestamp_millis()
+                    .saturating_add(ttl.as_millis().min(i64::MAX as u128) as i64),
+            ),
+        };
+
+        match backend
+            .insert_documents(
+                vec![(lock.id, lock.to_bson_encoded(backend.encoding())?)],
+                MIGRATION_LOCK_COLLECTION,
+                InsertPolicy::ErrorOnConflict,
+            )
+            .await
+        {
+            Ok(_) => return Ok(true),
+            Err(DocumentStoreError::DocumentAlreadyExists(..)) => {}
+            Err(error) => return Err(error),
+        }
+
+        // Released since the insert failed, so try creating it again
+        let Some((held, stored)) = stored_lock(backend).await? else {
+            continue;
+        };
+
+        if held.owner == owner {
+            lock.acquired_at = held.acquired_at;
+        } else if held.expires_at > now {
+            return Ok(false);
+        }
+
+        match backend
+            .update_documents_if_match(
+                vec![(
+                    lock.id,
+                    lock.to_bson_encoded(backend.encoding())?,
+                    document_revision(&stored)?,
+                )],
+                MIGRATION_LOCK_COLLECTION,
+            )
+            .await
+        {
+            Ok(_) => return Ok(true),
+            // Changed since it was read, so look at it again
+            Err(DocumentStoreError::PreconditionFailed(..)) => {}
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// The default implementation of [`StoreBackend::release_migration_lock`].
+pub(crate) async fn release_lock<B: StoreBackend + ?Sized>(
+    backend: &B,
+    owner: &str,
+) -> DocumentStoreResult<()> {
+    if !backend
+        .collection_exists(MIGRATION_LOCK_COLLECTION)
+        .await?
+    {
+        return Ok(());
+    }
+
+    let Some((held, stored)) = stored_lock(backend).await? else {
+        return Ok(());
+    };
+
+    if held.owner != owner {
+        return Ok(());
+    }
+
+    match backend
+        .delete_documents_if_match(
+            vec![(held.id, document_revision(&stored)?)],
+            MIGRATION_LOCK_COLLECTION,
+        )
+        .await
+    {
+        // Taken over by another owner after it expired
+        Ok(_) | Err(DocumentStoreError::PreconditionFailed(..)) => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+/// What a [`MigrationRunner`] does when another runner holds the migration lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockPolicy {
+    /// Wait for the lock, checking it every `poll_interval`, and fail with
+    /// [`DocumentStoreError::Migration`] if it isn't acquired within `timeout`.
+    ///
+    /// Once acquired, the runner only applies the migrations the previous owner left.
+    Wait {
+        /// How long to wait for the lock.
+        timeout: Duration,
+        /// How long to wait between attempts to acquire the lock.
+        poll_interval: Duration,
+    },
+    /// Return without applying any migration, leaving them to the owner of the lock.
+    Skip,
+}
+
+impl Default for LockPolicy {
+    fn default() -> Self {
+        LockPolicy::Wait {
+            timeout: DEFAULT_LOCK_TTL,
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+}
+
 pub trait Migrations: Send + Sync {
     fn migrations() -> Vec<MigrationRef>;
 }
@@ -134,6 +637,14 @@ impl<'a> MigrateOp<'a> {
         self.store.create_collection(name).await
     }
 
+    pub async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.store.ensure_collection(name).await
+    }
+
+    pub async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        self.store.collection_exists(name).await
+    }
+
     pub async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
         self.store.drop_collection(name).await
     }
@@ -170,6 +681,178 @@ impl<'a> MigrateOp<'a> {
             .await
     }
 
+    /// Converts the values of a field to another BSON type, as suggested by
+    /// [`LintFix::ConvertField`](crate::lint::LintFix::ConvertField).
+    ///
+    /// Values that already have the type, or that don't convert faithfully, are left as they
+    /// are. One update is issued per distinct value to convert.
+    ///
+    /// # Returns
+    ///
+    /// The number of documents converted.
+    pub async fn convert_field(
+        &self,
+        collection: &str,
+        field: &str,
+        to: ElementType,
+    ) -> DocumentStoreResult<usize> {
+        let values = self
+            .store
+            .collection(collection)
+            .distinct(field, None)
+            .await?;
+        let mut converted = 0;
+
+        for value in values {
+            if value.element_type() == to {
+                continue;
+            }
+
+            let Some(new) = convert_value(&value, to) else {
+                continue;
+            };
+
+            converted += self
+                .update_where(
+                    collection,
+                    Filter::eq(field, value),
+                    Update::builder()
+                        .set(field, new)
+                        .build(),
+                )
+                .await?
+                .matched;
+        }
+
+        Ok(converted)
+    }
+
+    /// Rewrites every document of `Old`'s collection as a `New` document.
+    ///
+    /// Documents are read as they're stored, including soft-deleted ones, and the results are
+    /// upserted into `New`'s collection [`TRANSFORM_BATCH_SIZE`] at a time. When both types
+    /// share a collection, each document is replaced in place as long as `transform` keeps its
+    /// ID; otherwise the old documents are left for the migration to drop.
+    ///
+    /// # Returns
+    ///
+    /// The number of documents written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a stored document isn't a valid `Old`, or reading or writing fails.
+    /// Batches written before the error are kept.
+    pub async fn transform<Old, New, F>(&self, mut transform: F) -> DocumentStoreResult<usize>
+    where
+        Old: Document,
+        New: Document,
+        F: FnMut(Old) -> New + Send,
+    {
+        if !self
+            .collection_exists(Old::collection_name())
+            .await?
+        {
+            return Ok(0);
+        }
+
+        let encoding = self.store.backend().encoding().clone();
+        let collection = self.store.typed_collection::<New>();
+        let mut documents = self
+            .store
+            .collection(Old::collection_name())
+            .query_stream(Query::default())
+            .await?
+            .try_chunks(TRANSFORM_BATCH_SIZE);
+        let mut written = 0;
+
+        while let Some(batch) = documents
+            .try_next()
+            .await
+            .map_err(|error| error.1)?
+        {
+            let batch = batch
+                .into_iter()
+                .map(|document| Ok(transform(Old::from_bson_encoded(document, &encoding)?)))
+                .collect::<DocumentStoreResult<Vec<_>>>()?;
+
+            written += batch.len();
+            collection.upsert(batch).await?;
+        }
+
+        Ok(written)
+    }
+
+    /// Passes every document of a collection through `transform`, replacing the documents it
+    /// changes.
+    ///
+    /// Documents are read as they're stored and written [`TRANSFORM_BATCH_SIZE`] at a time,
+    /// under the ID they were read with, so `transform` can't move a document to another ID.
+    ///
+    /// # Returns
+    ///
+    /// The number of documents replaced.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::InvalidDocument`] if a stored document has no UUID `id`,
+    /// or an error if reading or writing fails. Batches written before the error are kept.
+    pub async fn transform_bson<F>(
+        &self,
+        collection: &str,
+        mut transform: F,
+    ) -> DocumentStoreResult<usize>
+    where
+        F: FnMut(Bson) -> Bson + Send,
+    {
+        if !self
+            .collection_exists(collection)
+            .await?
+        {
+            return Ok(0);
+        }
+
+        let mut documents = self
+            .store
+            .collection(collection)
+            .query_stream(Query::default())
+            .await?
+            .try_chunks(TRANSFORM_BATCH_SIZE);
+        let mut replaced = 0;
+
+        while let Some(batch) = documents
+            .try_next()
+            .await
+            .map_err(|error| error.1)?
+        {
+            let mut changed = Vec::new();
+
+            for document in batch {
+                let id = stored_id(&document, "id")
+                    .or_else(|| stored_id(&document, "_id"))
+                    .ok_or_else(|| {
+                        DocumentStoreError::InvalidDocument(format!(
+                            "Document without a UUID id in collection {}",
+                            collection
+                        ))
+                    })?;
+                let transformed = transform(document.clone());
+
+                if transformed != document {
+                    changed.push((id, transformed));
+                }
+            }
+
+            if changed.is_empty() {
+                continue;
+            }
+
+            replaced += changed.len();
+            self.update(collection, changed).await?;
+        }
+
+        Ok(replaced)
+    }
+
     pub async fn add_index(
         &self,
         collection: &str,
@@ -201,7 +884,14 @@ impl<'a> MigrateOp<'a> {
             .await
     }
 
-    pub async fn delete_typed<U, D>(&self, ids: Vec<U>) -> DocumentStoreResult<()>
+    pub async fn upsert_typed<D: Document>(&self, docs: Vec<D>) -> DocumentStoreResult<()> {
+        self.store
+            .typed_collection::<D>()
+            .upsert(docs)
+            .await
+    }
+
+    pub async fn delete_typed<U, D>(&self, ids: Vec<U>) -> DocumentStoreResult<usize>
     where
         U: Into<Uuid> + Send + Sync + 'static,
         D: Document,
@@ -252,7 +942,18 @@ impl<'a> MigrateOp<'a> {
             .await
     }
 
-    pub async fn delete<U>(&self, collection: &str, ids: Vec<U>) -> DocumentStoreResult<()>
+    pub async fn upsert(
+        &self,
+        collection: &str,
+        docs: Vec<(Uuid, Bson)>,
+    ) -> DocumentStoreResult<()> {
+        self.store
+            .collection(collection)
+            .upsert(docs)
+            .await
+    }
+
+    pub async fn delete<U>(&self, collection: &str, ids: Vec<U>) -> DocumentStoreResult<usize>
     where
         U: Into<Uuid> + Send + Sync + 'static,
     {
@@ -262,6 +963,25 @@ impl<'a> MigrateOp<'a> {
             .await
     }
 
+    pub async fn update_where(
+        &self,
+        collection: &str,
+        filter: Expr,
+        update: Update,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.store
+            .collection(collection)
+            .update_where(filter, update)
+            .await
+    }
+
+    pub async fn delete_where(&self, collection: &str, filter: Expr) -> DocumentStoreResult<usize> {
+        self.store
+            .collection(collection)
+            .delete_where(filter)
+            .await
+    }
+
     pub async fn get<U>(&self, collection: &str, ids: Vec<U>) -> DocumentStoreResult<Vec<Bson>>
     where
         U: Into<Uuid> + Send + Sync + 'static,
@@ -280,145 +1000,274 @@ impl<'a> MigrateOp<'a> {
     }
 }
 
-struct RevisionGraph {
-    children: HashMap<String, Vec<String>>,
-    parents: HashMap<String, String>,
-}
+/// Separates the heads of a store's revision while it's on several unmerged branches.
+pub const REVISION_SEPARATOR: char = ',';
 
-impl RevisionGraph {
-    fn new(migrations: &[&MigrationRef]) -> Self {
-        let (children, parents) = migrations.iter().fold(
-            (HashMap::new(), HashMap::new()),
-            |(mut children, mut parents), migration| {
-                migration.previous_id().map(|prev_id| {
-                    children
-                        .entry(prev_id.to_string())
-                        .or_insert_with(Vec::new)
-                        .push(migration.id().to_string());
+/// The revision before every migration, which [`MigrationRunner::downgrade_to`] reverts
+/// every migration to, and a store records once they're all reverted. No migration may
+/// have it as its ID.
+pub const BASE_REVISION: &str = "base";
 
-                    parents.insert(migration.id().to_string(), prev_id.to_string());
-                });
+/// The migrations of a chain, which may branch and merge.
+struct RevisionChain {
+    revisions: HashMap<String, MigrationRef>,
+    children: HashMap<&'static str, Vec<&'static str>>,
+}
 
-                (children, parents)
-            },
-        );
+impl RevisionChain {
+    fn new(migrations: Vec<MigrationRef>) -> Self {
+        let revisions = migrations
+            .into_iter()
+            .map(|migration| (migration.id().to_string(), migration))
+            .collect::<HashMap<_, _>>();
+        let mut children = HashMap::<_, Vec<_>>::new();
+
+        for migration in revisions.values() {
+            for parent in migration.previous_ids() {
+                children
+                    .entry(parent)
+                    .or_default()
+                    .push(migration.id());
+            }
+        }
 
-        Self { children, parents }
+        Self { revisions, children }
     }
 
-    fn find_path<F>(&self, from: &str, to: &str, next: F) -> Option<Vec<String>>
-    where
-        F: Fn(&RevisionGraph, &str) -> Vec<String>,
-    {
-        if from == to {
-            return Some(vec![from.to_string()]);
-        }
-
-        let mut visited = HashSet::new();
-        let mut queue = vec![(from.to_string(), vec![from.to_string()])];
+    fn get(&self, id: &str) -> Option<&MigrationRef> {
+        self.revisions.get(id)
+    }
 
-        while let Some((cur, path)) = queue.pop() {
-            if !visited.insert(cur.clone()) {
-                continue;
-            }
+    /// Returns the IDs of the migrations no other migration follows, sorted.
+    fn heads(&self) -> Vec<&'static str> {
+        self.sorted_ids(|migration| {
+            !self
+                .children
+                .contains_key(migration.id())
+        })
+    }
 
-            for neighbor in next(self, &cur) {
-                if neighbor == to {
-                    return Some([path.clone(), vec![to.to_string()]].concat());
-                }
+    /// Returns the IDs of the migrations that follow no other migration, sorted.
+    fn tails(&self) -> Vec<&'static str> {
+        self.sorted_ids(|migration| migration.previous_ids().is_empty())
+    }
 
-                queue.push((neighbor.clone(), [path.clone(), vec![neighbor]].concat()));
-            }
+    fn head(&self) -> Option<&'static str> {
+        match self.heads()[..] {
+            [head] => Some(head),
+            _ => None,
         }
-
-        None
     }
 
-    fn find_up_path(&self, from: &str, to: &str) -> Option<Vec<String>> {
-        self.find_path(from, to, |graph, node| {
-            graph
-                .children
-                .get(node)
-                .cloned()
-                .unwrap_or_default()
-        })
+    fn tail(&self) -> Option<&'static str> {
+        match self.tails()[..] {
+            [tail] => Some(tail),
+            _ => None,
+        }
     }
 
-    fn find_down_path(&self, from: &str, to: &str) -> Option<Vec<String>> {
-        self.find_path(from, to, |graph, node| {
-            graph
-                .parents
-                .get(node)
-                .map(|parent| vec![parent.clone()])
-                .unwrap_or_default()
-        })
+    fn sorted_ids(&self, include: impl Fn(&MigrationRef) -> bool) -> Vec<&'static str> {
+        let mut ids = self
+            .revisions
+            .values()
+            .filter(|migration| include(migration))
+            .map(|migration| migration.id())
+            .collect::<Vec<_>>();
+
+        ids.sort_unstable();
+        ids
     }
-}
 
-struct RevisionChain {
-    revisions: HashMap<String, MigrationRef>,
-    graph: RevisionGraph,
-    head: Option<String>,
-    tail: Option<String>,
-}
+    /// Returns the migrations applied to a store at `revision`, which are its heads and all
+    /// their ancestors, or `None` if one of them isn't part of the chain.
+    fn applied(&self, revision: Option<&str>) -> Option<HashSet<&'static str>> {
+        let mut applied = HashSet::new();
+        let mut queue = revision
+            .filter(|revision| *revision != BASE_REVISION)
+            .map(|revision| {
+                revision
+                    .split(REVISION_SEPARATOR)
+                    .map(|id| {
+                        self.get(id)
+                            .map(|migration| migration.id())
+                    })
+                    .collect::<Option<Vec<_>>>()
+            })
+            .unwrap_or(Some(Vec::new()))?;
 
-impl RevisionChain {
-    fn new(migrations: Vec<MigrationRef>) -> Self {
-        let revisions = migrations
-            .into_iter()
-            .map(|migration| (migration.id().to_string(), migration))
-            .collect::<HashMap<_, _>>();
+        while let Some(id) = queue.pop() {
+            let migration = self.get(id)?;
 
-        let graph = RevisionGraph::new(&revisions.values().collect::<Vec<_>>());
+            if applied.insert(migration.id()) {
+                queue.extend(migration.previous_ids());
+            }
+        }
 
-        let head = revisions
-            .keys()
-            .find(|id| !graph.children.contains_key(*id))
-            .cloned();
+        Some(applied)
+    }
 
-        let tail = revisions
-            .keys()
-            .find(|id| !graph.parents.contains_key(*id))
-            .cloned();
+    /// Returns the revision of a store with the `applied` migrations: the applied migrations
+    /// no other applied migration follows, or [`BASE_REVISION`] if there are none.
+    fn revision_of(&self, applied: &HashSet<&'static str>) -> String {
+        if applied.is_empty() {
+            return BASE_REVISION.to_string();
+        }
 
-        Self { revisions, graph, head, tail }
-    }
+        let mut heads = applied
+            .iter()
+            .copied()
+            .filter(|id| {
+                !self
+                    .children
+                    .get(id)
+                    .is_some_and(|children| {
+                        children
+                            .iter()
+                            .any(|child| applied.contains(child))
+                    })
+            })
+            .collect::<Vec<_>>();
 
-    fn get(&self, id: &str) -> Option<&MigrationRef> {
-        self.revisions.get(id)
+        heads.sort_unstable();
+        heads.join(&REVISION_SEPARATOR.to_string())
     }
 
-    fn head(&self) -> Option<&str> {
-        self.head.as_deref()
-    }
+    /// Orders migrations so that each comes after the migrations it follows, breaking ties by
+    /// ID, or returns `None` if they follow each other in a cycle.
+    fn topological(&self, ids: &HashSet<&'static str>) -> Option<Vec<&'static str>> {
+        let mut remaining = ids
+            .iter()
+            .copied()
+            .collect::<BTreeSet<_>>();
+        let mut ordered = Vec::with_capacity(remaining.len());
+
+        while !remaining.is_empty() {
+            let next = remaining.iter().copied().find(|id| {
+                self.get(id).is_some_and(|migration| {
+                    migration
+                        .previous_ids()
+                        .iter()
+                        .all(|parent| !remaining.contains(parent))
+                })
+            })?;
+
+            remaining.remove(next);
+            ordered.push(next);
+        }
 
-    fn tail(&self) -> Option<&str> {
-        self.tail.as_deref()
+        Some(ordered)
     }
 
-    fn get_upgrade_path(&self, from: &str, to: &str) -> Option<Vec<&MigrationRef>> {
-        self.graph
-            .find_up_path(from, to)
-            .map(|ids| {
-                ids.iter()
-                    .filter_map(|id| self.get(id))
-                    .collect()
-            })
-    }
+    /// Returns the migrations that take a store at `current` to `target`, in the order they
+    /// run, each with the revision of the store once it has run.
+    ///
+    /// Upgrades apply `target` and every migration it follows that isn't applied yet.
+    /// Downgrades revert every applied migration `target` doesn't follow, newest first, and
+    /// leave `target` applied, so only [`BASE_REVISION`] reverts them all. Returns `None` if
+    /// a revision isn't part of the chain, or a downgrade's target isn't applied.
+    fn steps(
+        &self,
+        current: Option<&str>,
+        target: &str,
+        direction: MigrationDirection,
+    ) -> Option<Vec<(&MigrationRef, String)>> {
+        let mut applied = self.applied(current)?;
+        let wanted = self.applied(Some(target))?;
+
+        let ids = match direction {
+            MigrationDirection::Up => self.topological(
+                &wanted
+                    .difference(&applied)
+                    .copied()
+                    .collect(),
+            )?,
+            // Nothing has been applied, so there's nothing to revert
+            MigrationDirection::Down if current.is_none() => Vec::new(),
+            MigrationDirection::Down if !wanted.is_subset(&applied) => return None,
+            MigrationDirection::Down => {
+                let mut ids = self.topological(
+                    &applied
+                        .difference(&wanted)
+                        .copied()
+                        .collect(),
+                )?;
+
+                ids.reverse();
+                ids
+            }
+        };
+
+        ids.into_iter()
+            .map(|id| {
+                match direction {
+                    MigrationDirection::Up => applied.insert(id),
+                    MigrationDirection::Down => applied.remove(id),
+                };
 
-    fn get_downgrade_path(&self, from: &str, to: &str) -> Option<Vec<&MigrationRef>> {
-        self.graph
-            .find_down_path(from, to)
-            .map(|ids| {
-                ids.iter()
-                    .filter_map(|id| self.get(id))
-                    .collect()
+                Some((self.get(id)?, self.revision_of(&applied)))
             })
+            .collect()
     }
 }
 
+/// Returns the migrations that still need to run to bring a self-contained chain, such as a
+/// plugin's, from `current` to its head revision, in the order they must be applied, each
+/// with the revision to record once it has run.
+///
+/// `current` is the last recorded revision; with `None` the whole chain is pending.
+pub(crate) fn pending_upgrades(
+    migrations: Vec<MigrationRef>,
+    current: Option<&str>,
+) -> DocumentStoreResult<Vec<(MigrationRef, String)>> {
+    let mut chain = RevisionChain::new(migrations);
+
+    let head = match chain.heads()[..] {
+        [] => return Ok(Vec::new()),
+        [head] => head,
+        ref heads => return Err(multiple_heads(heads)),
+    };
+    let steps = chain
+        .steps(current, head, MigrationDirection::Up)
+        .ok_or(DocumentStoreError::Migration(format!(
+            "No upgrade path from revision '{}' to '{}'",
+            current.unwrap_or_default(),
+            head
+        )))?
+        .into_iter()
+        .map(|(migration, revision)| (migration.id(), revision))
+        .collect::<Vec<_>>();
+
+    Ok(steps
+        .into_iter()
+        .filter_map(|(id, revision)| {
+            chain
+                .revisions
+                .remove(id)
+                .map(|migration| (migration, revision))
+        })
+        .collect())
+}
+
+/// The error of an upgrade to the head of a chain whose branches haven't been merged.
+fn multiple_heads(heads: &[&str]) -> DocumentStoreError {
+    DocumentStoreError::Migration(format!(
+        "Multiple head revisions {}; add a MergeMigration following all of them",
+        heads.join(", ")
+    ))
+}
+
+/// Applies the migrations of `M` to a store.
+///
+/// Runners take the store's migration lock (see
+/// [`acquire_migration_lock`](StoreBackend::acquire_migration_lock)) before reading the
+/// current revision and hold it until they're done, so when several instances of an
+/// application upgrade the same store at startup only one of them applies the migrations.
+/// The others wait for it or skip, depending on the runner's [`LockPolicy`].
 pub struct MigrationRunner<M: Migrations> {
     chain: RevisionChain,
+    lock_policy: LockPolicy,
+    lock_ttl: Duration,
+    refuse_irreversible: bool,
     _marker: PhantomData<M>,
 }
 
@@ -426,32 +1275,64 @@ impl<M: Migrations> MigrationRunner<M> {
     pub fn new() -> Self {
         Self {
             chain: RevisionChain::new(M::migrations()),
+            lock_policy: LockPolicy::default(),
+            lock_ttl: DEFAULT_LOCK_TTL,
+            refuse_irreversible: true,
             _marker: PhantomData,
         }
     }
 
+    /// Sets what the runner does when another runner holds the migration lock.
+    pub fn with_lock_policy(mut self, policy: LockPolicy) -> Self {
+        self.lock_policy = policy;
+        self
+    }
+
+    /// Sets whether downgrades that would revert an irreversible migration are refused
+    /// before anything is reverted, which they are by default.
+    ///
+    /// Refused downgrades fail with [`DocumentStoreError::IrreversibleMigration`], naming the
+    /// migration and every migration the downgrade would revert. Without the guard, the
+    /// migrations after the irreversible one are reverted before its
+    /// [`down`](Migration::down) fails, leaving the store at its revision.
+    pub fn refuse_irreversible(mut self, refuse: bool) -> Self {
+        self.refuse_irreversible = refuse;
+        self
+    }
+
+    /// Sets how long the migration lock is held before another runner may take it over.
+    ///
+    /// The lock is extended before every migration, so the TTL only needs to exceed the
+    /// duration of the longest migration. Defaults to [`DEFAULT_LOCK_TTL`].
+    pub fn with_lock_ttl(mut self, ttl: Duration) -> Self {
+        self.lock_ttl = ttl;
+        self
+    }
+
+    /// Applies every migration that isn't applied yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Migration`] if there are no migrations, or branches of
+    /// them that no [`MergeMigration`] joins.
     pub async fn upgrade<'a>(&self, store: DynDocumentStoreRef<'a>) -> DocumentStoreResult<()> {
-        self.upgrade_to(
-            store,
-            self.chain
-                .head()
-                .ok_or(DocumentStoreError::Migration(
+        let head = match self.chain.heads()[..] {
+            [] => {
+                return Err(DocumentStoreError::Migration(
                     "No head revision found for upgrade".to_string(),
-                ))?,
-        )
-        .await
+                ));
+            }
+            [head] => head,
+            ref heads => return Err(multiple_heads(heads)),
+        };
+
+        self.upgrade_to(store, head).await
     }
 
+    /// Reverts every applied migration, leaving the store at [`BASE_REVISION`].
     pub async fn downgrade<'a>(&self, store: DynDocumentStoreRef<'a>) -> DocumentStoreResult<()> {
-        self.downgrade_to(
-            store,
-            self.chain
-                .tail()
-                .ok_or(DocumentStoreError::Migration(
-                    "No tail revision found for downgrade".to_string(),
-                ))?,
-        )
-        .await
+        self.downgrade_to(store, BASE_REVISION)
+            .await
     }
 
     pub async fn upgrade_to<'a>(
@@ -463,6 +1344,9 @@ impl<M: Migrations> MigrationRunner<M> {
             .await
     }
 
+    /// Reverts the migrations applied after `target_revision`, newest first, leaving the
+    /// store at `target_revision`. [`BASE_REVISION`] reverts every migration, including the
+    /// first.
     pub async fn downgrade_to<'a>(
         &self,
         store: DynDocumentStoreRef<'a>,
@@ -472,49 +1356,216 @@ impl<M: Migrations> MigrationRunner<M> {
             .await
     }
 
+    /// Returns the ID of the latest migration, the revision [`upgrade`](Self::upgrade) brings
+    /// a store to, or `None` if there are no migrations or several unmerged branches.
+    pub fn head_revision(&self) -> Option<&str> {
+        self.chain.head()
+    }
+
+    /// Returns the IDs of the migrations no other migration follows, which are the heads of
+    /// the branches a [`MergeMigration`] has yet to join.
+    pub fn head_revisions(&self) -> Vec<&str> {
+        self.chain.heads()
+    }
+
+    /// Returns the ID of the first migration, or `None` if there are no migrations or several
+    /// first ones.
+    pub fn tail_revision(&self) -> Option<&str> {
+        self.chain.tail()
+    }
+
+    /// Returns the store's current revision, the head revision and the migrations an
+    /// [`upgrade`](Self::upgrade) would apply, without running them or taking the migration
+    /// lock.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Migration`] if the current revision isn't part of the
+    /// chain, or an error if it cannot be read.
+    pub async fn status<'a>(
+        &self,
+        store: DynDocumentStoreRef<'a>,
+    ) -> DocumentStoreResult<MigrationStatus> {
+        let current = store.current_revision_id().await?;
+        let head = match self.chain.heads()[..] {
+            [] => None,
+            [head] => Some(head),
+            ref heads => return Err(multiple_heads(heads)),
+        };
+        let pending = match head {
+            Some(head) => self
+                .path(current.as_deref(), head, MigrationDirection::Up)?
+                .into_iter()
+                .map(|(migration, _)| migration.id().to_string())
+                .collect(),
+            None => Vec::new(),
+        };
+
+        Ok(MigrationStatus {
+            current,
+            head: head.map(str::to_string),
+            pending,
+        })
+    }
+
+    /// Returns the IDs of the migrations [`apply`](Self::apply) would run, in order, without
+    /// running them or taking the migration lock.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Migration`] if there's no path to `target_revision`, or
+    /// an error if the current revision cannot be read.
+    pub async fn plan<'a>(
+        &self,
+        store: DynDocumentStoreRef<'a>,
+        target_revision: &str,
+        direction: MigrationDirection,
+    ) -> DocumentStoreResult<Vec<&'static str>> {
+        let current_revision = store.current_revision_id().await?;
+
+        Ok(self
+            .path(current_revision.as_deref(), target_revision, direction)?
+            .into_iter()
+            .map(|(migration, _)| migration.id())
+            .collect())
+    }
+
+    /// Applies the migrations between the store's current revision and `target_revision`
+    /// while holding the migration lock.
+    ///
+    /// Returns without applying anything if another runner holds the lock and the runner's
+    /// [`LockPolicy`] is [`Skip`](LockPolicy::Skip).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Migration`] if there's no path to `target_revision`, the
+    /// lock isn't acquired in time or is lost to another runner, or the error of the first
+    /// migration that fails.
     pub async fn apply<'a>(
         &self,
         store: DynDocumentStoreRef<'a>,
         target_revision: &str,
         direction: MigrationDirection,
     ) -> DocumentStoreResult<()> {
-        let current_revision = store.current_revision_id().await?;
-        let path = match direction {
-            MigrationDirection::Up => {
-                let from = current_revision
-                    .as_deref()
-                    .unwrap_or_else(|| self.chain.tail().unwrap_or(""));
-
-                self.chain
-                    .get_upgrade_path(from, target_revision)
-                    .ok_or(DocumentStoreError::Migration(format!(
-                        "No upgrade path from revision '{}' to '{}'",
-                        from, target_revision
-                    )))?
-            }
-            MigrationDirection::Down => {
-                let from = current_revision
-                    .as_deref()
-                    .unwrap_or_else(|| self.chain.head().unwrap_or(""));
-
-                self.chain
-                    .get_downgrade_path(from, target_revision)
-                    .ok_or(DocumentStoreError::Migration(format!(
-                        "No downgrade path from revision '{}' to '{}'",
-                        from, target_revision
-                    )))?
+        let owner = Uuid::new().to_string();
+
+        if !self.lock(&store, &owner).await? {
+            return Ok(());
+        }
+
+        let result = self
+            .apply_locked(&store, &owner, target_revision, direction)
+            .await;
+        let released = store
+            .release_migration_lock(&owner)
+            .await;
+
+        result?;
+        released
+    }
+
+    /// Acquires the migration lock according to the lock policy, returning whether it's held.
+    async fn lock(
+        &self,
+        store: &DynDocumentStoreRef<'_>,
+        owner: &str,
+    ) -> DocumentStoreResult<bool> {
+        let (timeout, poll_interval) = match self.lock_policy {
+            LockPolicy::Wait { timeout, poll_interval } => (timeout, poll_interval),
+            LockPolicy::Skip => {
+                return store
+                    .acquire_migration_lock(owner, self.lock_ttl)
+                    .await;
             }
         };
+        let deadline = bson::DateTime::now()
+            .timestamp_millis()
+            .saturating_add(
+                timeout
+                    .as_millis()
+                    .min(i64::MAX as u128) as i64,
+            );
+
+        while !store
+            .acquire_migration_lock(owner, self.lock_ttl)
+            .await?
+        {
+            if bson::DateTime::now().timestamp_millis() >= deadline {
+                return Err(DocumentStoreError::Migration(format!(
+                    "Timed out after {:?} waiting for the migration lock",
+                    timeout
+                )));
+            }
 
-        let op = MigrateOp::new(&store);
-        for migration in path {
-            match direction {
-                MigrationDirection::Up => migration.up(&op).await?,
-                MigrationDirection::Down => migration.down(&op).await?,
-            };
-            store
-                .set_revision_id(migration.id())
-                .await?;
+            sleep(poll_interval).await;
+        }
+
+        Ok(true)
+    }
+
+    /// Returns the migrations that take a store at `current_revision` to `target_revision`,
+    /// in the order they run, each with the revision of the store once it has run.
+    fn path(
+        &self,
+        current_revision: Option<&str>,
+        target_revision: &str,
+        direction: MigrationDirection,
+    ) -> DocumentStoreResult<Vec<(&MigrationRef, String)>> {
+        let path = self
+            .chain
+            .steps(current_revision, target_revision, direction)
+            .ok_or(DocumentStoreError::Migration(format!(
+                "No {} path from revision '{}' to '{}'",
+                match direction {
+                    MigrationDirection::Up => "upgrade",
+                    MigrationDirection::Down => "downgrade",
+                },
+                current_revision.unwrap_or_default(),
+                target_revision
+            )))?;
+
+        if direction == MigrationDirection::Down
+            && self.refuse_irreversible
+            && let Some((irreversible, _)) = path
+                .iter()
+                .find(|(migration, _)| !migration.is_reversible())
+        {
+            return Err(DocumentStoreError::IrreversibleMigration(
+                irreversible.id().to_string(),
+                path.iter()
+                    .map(|(migration, _)| migration.id().to_string())
+                    .collect(),
+            ));
+        }
+
+        Ok(path)
+    }
+
+    async fn apply_locked(
+        &self,
+        store: &DynDocumentStoreRef<'_>,
+        owner: &str,
+        target_revision: &str,
+        direction: MigrationDirection,
+    ) -> DocumentStoreResult<()> {
+        let current_revision = store.current_revision_id().await?;
+        let path = self.path(current_revision.as_deref(), target_revision, direction)?;
+
+        let op = MigrateOp::new(store);
+        for (migration, revision) in path {
+            // Extends the lock, so that only the longest migration has to fit in its TTL
+            if !store
+                .acquire_migration_lock(owner, self.lock_ttl)
+                .await?
+            {
+                return Err(DocumentStoreError::Migration(format!(
+                    "Lost the migration lock before applying '{}'",
+                    migration.id()
+                )));
+            }
+
+            run_migration(migration.as_ref(), direction, None, &op).await?;
+            store.set_revision_id(&revision).await?;
         }
 
         Ok(())
@@ -527,6 +1578,26 @@ pub trait Migrator: Send + Sync {
     async fn downgrade_to<M: Migrations>(&self, target_revision: &str) -> DocumentStoreResult<()>;
     async fn upgrade<M: Migrations>(&self) -> DocumentStoreResult<()>;
     async fn downgrade<M: Migrations>(&self) -> DocumentStoreResult<()>;
+
+    /// Returns the store's current revision, the head revision of `M` and the migrations
+    /// [`upgrade`](Migrator::upgrade) would apply, without running anything.
+    ///
+    /// See [`MigrationStatus`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Migration`] if the current revision isn't one of `M`'s,
+    /// or an error if it cannot be read.
+    async fn migration_status<M: Migrations>(&self) -> DocumentStoreResult<MigrationStatus>;
+
+    /// Returns every recorded run of a migration on the store, oldest first.
+    ///
+    /// See [`MigrationRecord`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the [`MIGRATIONS_COLLECTION`] cannot be read.
+    async fn migration_history(&self) -> DocumentStoreResult<Vec<MigrationRecord>>;
 }
 
 #[async_trait]
@@ -557,4 +1628,30 @@ where
             .downgrade(self.as_dyn())
             .await
     }
+
+    async fn migration_status<M: Migrations>(&self) -> DocumentStoreResult<MigrationStatus> {
+        MigrationRunner::<M>::new()
+            .status(self.as_dyn())
+            .await
+    }
+
+    async fn migration_history(&self) -> DocumentStoreResult<Vec<MigrationRecord>> {
+        let store = self.as_dyn();
+
+        if !store
+            .collection_exists(MIGRATIONS_COLLECTION)
+            .await?
+        {
+            return Ok(Vec::new());
+        }
+
+        store
+            .typed_collection::<MigrationRecord>()
+            .query(
+                Query::builder()
+                    .sort("sequence", SortDirection::Asc)
+                    .build(),
+            )
+            .await
+    }
 }
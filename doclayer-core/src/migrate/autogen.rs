@@ -0,0 +1,493 @@
+//! Generating migrations from the changes between two schema snapshots.
+//!
+//! A [`SchemaSnapshot`] records the JSON Schema of every collection, typically derived from
+//! the document types with [`SchemaType`]. Saving a snapshot next to each migration and
+//! comparing it with the snapshot of the current types produces an [`AutoMigration`] adding
+//! the new fields, dropping the removed ones and renaming the fields that only changed name,
+//! so the mechanical steps of a schema change don't have to be written by hand:
+//!
+//! ```ignore
+//! use doclayer::migrate::autogen::{self, SchemaSnapshot};
+//!
+//! let previous = SchemaSnapshot::load("migrations/001_initial.json")?;
+//! let current = SchemaSnapshot::new()
+//!     .with_document::<User>()?
+//!     .with_document::<Order>()?;
+//!
+//! let migration = autogen::generate("002_user_age", Some("001_initial"), &previous, &current)
+//!     .rename("users", "mail", "email");
+//!
+//! // Keep the generated code as a regular migration, and the snapshot for the next one
+//! println!("{}", migration.render("UserAge"));
+//! migration.snapshot().save("migrations/002_user_age.json")?;
+//! ```
+//!
+//! An [`AutoMigration`] is also a [`Migration`] itself, so it can be registered directly
+//! from the two snapshots.
+//!
+//! Only the top-level fields of documents are compared. A removed and an added field with
+//! the same schema are taken as a rename when no other removed or added field of the
+//! collection has that schema; [`AutoMigration::rename`] declares the others. New collections
+//! are created, but collections missing from the current snapshot are left in place, since
+//! dropping them deletes their documents. Changes to the type of a field need a hand-written
+//! step, such as [`MigrateOp::convert_field`].
+
+use async_trait::async_trait;
+use bson::Bson;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt, path::Path};
+
+use crate::{
+    document::Document,
+    error::{DocumentStoreError, DocumentStoreResult},
+    migrate::{MigrateOp, Migration},
+    schema::{JsonSchema, SchemaRegistry, SchemaType},
+};
+
+/// The JSON Schemas of a set of collections at one revision.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SchemaSnapshot {
+    /// The schema of each collection, by collection name.
+    pub collections: BTreeMap<String, Value>,
+}
+
+impl SchemaSnapshot {
+    /// Creates a snapshot without collections.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a snapshot of the schemas registered in a registry.
+    pub fn from_registry(schemas: &SchemaRegistry) -> Self {
+        let mut snapshot = Self::new();
+
+        for (collection, schema) in schemas.schemas() {
+            snapshot.insert(collection, &schema);
+        }
+
+        snapshot
+    }
+
+    /// Records the schema of the document type `D` for its collection.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::InvalidDocument`] if the schema of `D` is neither an
+    /// object nor a boolean.
+    pub fn with_document<D: Document + SchemaType>(mut self) -> DocumentStoreResult<Self> {
+        self.insert(D::collection_name(), &JsonSchema::of::<D>()?);
+        Ok(self)
+    }
+
+    /// Records the schema of a collection, replacing its previous schema.
+    pub fn insert(&mut self, collection: impl Into<String>, schema: &JsonSchema) {
+        self.collections
+            .insert(collection.into(), schema.as_json().clone());
+    }
+
+    /// Serializes the snapshot as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// Parses a snapshot serialized by [`to_json`](Self::to_json).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Serialization`] if the JSON isn't a snapshot.
+    pub fn from_json(json: &str) -> DocumentStoreResult<Self> {
+        serde_json::from_str(json).map_err(|err| DocumentStoreError::Serialization(err.to_string()))
+    }
+
+    /// Writes the snapshot to a JSON file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Migration`] if the file cannot be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> DocumentStoreResult<()> {
+        let path = path.as_ref();
+
+        std::fs::write(path, self.to_json() + "\n").map_err(|err| {
+            DocumentStoreError::Migration(format!(
+                "Failed to write schema snapshot {}: {}",
+                path.display(),
+                err
+            ))
+        })
+    }
+
+    /// Reads a snapshot from a JSON file written by [`save`](Self::save).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Migration`] if the file cannot be read, or
+    /// [`DocumentStoreError::Serialization`] if it doesn't hold a snapshot.
+    pub fn load(path: impl AsRef<Path>) -> DocumentStoreResult<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).map_err(|err| {
+            DocumentStoreError::Migration(format!(
+                "Failed to read schema snapshot {}: {}",
+                path.display(),
+                err
+            ))
+        })?;
+
+        Self::from_json(&json)
+    }
+
+    /// Returns the top-level fields of a collection, with their schemas and whether they're
+    /// required.
+    fn fields(&self, collection: &str) -> BTreeMap<String, (Value, bool)> {
+        self.collections
+            .get(collection)
+            .and_then(|schema| JsonSchema::new(schema.clone()).ok())
+            .map(|schema| schema.top_level_fields())
+            .unwrap_or_default()
+    }
+}
+
+/// A step of an [`AutoMigration`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum AutoOp {
+    /// Create a collection that's new in the current snapshot.
+    CreateCollection { collection: String },
+    /// Add a new field to every document, set to `default`.
+    AddField {
+        collection: String,
+        field: String,
+        default: Bson,
+    },
+    /// Remove a field from every document. Downgrading adds it back, set to `default`.
+    DropField {
+        collection: String,
+        field: String,
+        default: Bson,
+    },
+    /// Rename a field in every document.
+    RenameField {
+        collection: String,
+        from: String,
+        to: String,
+    },
+}
+
+impl AutoOp {
+    /// Returns the step undoing this one, if any.
+    fn inverse(&self) -> Option<AutoOp> {
+        match self {
+            // Dropping the collection would also delete the documents written since
+            AutoOp::CreateCollection { .. } => None,
+            AutoOp::AddField { collection, field, default } => Some(AutoOp::DropField {
+                collection: collection.clone(),
+                field: field.clone(),
+                default: default.clone(),
+            }),
+            AutoOp::DropField { collection, field, default } => Some(AutoOp::AddField {
+                collection: collection.clone(),
+                field: field.clone(),
+                default: default.clone(),
+            }),
+            AutoOp::RenameField { collection, from, to } => Some(AutoOp::RenameField {
+                collection: collection.clone(),
+                from: to.clone(),
+                to: from.clone(),
+            }),
+        }
+    }
+
+    async fn apply(&self, op: &MigrateOp<'_>) -> DocumentStoreResult<()> {
+        match self {
+            AutoOp::CreateCollection { collection } => op.ensure_collection(collection).await,
+            AutoOp::AddField { collection, field, default } => {
+                op.add_field(collection, field, default.clone())
+                    .await
+            }
+            AutoOp::DropField { collection, field, .. } => op.drop_field(collection, field).await,
+            AutoOp::RenameField { collection, from, to } => {
+                op.rename_field(collection, from, to)
+                    .await
+            }
+        }
+    }
+}
+
+/// Renders the step as the migration code applying it.
+impl fmt::Display for AutoOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AutoOp::CreateCollection { collection } => {
+                write!(f, "op.ensure_collection({:?}).await?;", collection)
+            }
+            AutoOp::AddField { collection, field, default } => write!(
+                f,
+                "op.add_field({:?}, {:?}, bson!({})).await?;",
+                collection,
+                field,
+                serde_json::to_value(default).unwrap_or(Value::Null)
+            ),
+            AutoOp::DropField { collection, field, .. } => {
+                write!(f, "op.drop_field({:?}, {:?}).await?;", collection, field)
+            }
+            AutoOp::RenameField { collection, from, to } => {
+                write!(f, "op.rename_field({:?}, {:?}, {:?}).await?;", collection, from, to)
+            }
+        }
+    }
+}
+
+/// A migration generated from the changes between two schema snapshots.
+///
+/// Upgrading applies its [`ops`](Self::ops) in order, and downgrading undoes them in reverse.
+#[derive(Debug, Clone)]
+pub struct AutoMigration {
+    id: &'static str,
+    previous_id: Option<&'static str>,
+    ops: Vec<AutoOp>,
+    snapshot: SchemaSnapshot,
+}
+
+impl AutoMigration {
+    /// Returns the steps of the migration.
+    pub fn ops(&self) -> &[AutoOp] {
+        &self.ops
+    }
+
+    /// Returns `true` if the snapshots have no differences the migration handles.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Returns the current snapshot, to save as the previous snapshot of the next migration.
+    pub fn snapshot(&self) -> &SchemaSnapshot {
+        &self.snapshot
+    }
+
+    /// Declares that a field of a collection was renamed, replacing the steps dropping `from`
+    /// and adding `to` with a step renaming it.
+    ///
+    /// Does nothing if the migration doesn't drop `from` and add `to`.
+    pub fn rename(mut self, collection: &str, from: &str, to: &str) -> Self {
+        let dropped = self.ops.iter().position(|op| {
+            matches!(op, AutoOp::DropField { collection: c, field, .. } if c == collection && field == from)
+        });
+        let added = self.ops.iter().position(|op| {
+            matches!(op, AutoOp::AddField { collection: c, field, .. } if c == collection && field == to)
+        });
+
+        if let (Some(dropped), Some(added)) = (dropped, added) {
+            self.ops[dropped] = AutoOp::RenameField {
+                collection: collection.to_string(),
+                from: from.to_string(),
+                to: to.to_string(),
+            };
+            self.ops.remove(added);
+        }
+
+        self
+    }
+
+    /// Renders the migration as the code of a hand-written [`Migration`] named `name`.
+    pub fn render(&self, name: &str) -> String {
+        let previous_id = match self.previous_id {
+            Some(id) => format!("Some({:?})", id),
+            None => "None".to_string(),
+        };
+        let body = |ops: Vec<AutoOp>| {
+            ops.iter()
+                .map(|op| format!("        {}\n", op))
+                .collect::<String>()
+        };
+
+        format!(
+            "pub struct {name};\n\
+             \n\
+             #[async_trait::async_trait]\n\
+             impl Migration for {name} {{\n\
+             \x20   fn id(&self) -> &'static str {{\n\
+             \x20       {id:?}\n\
+             \x20   }}\n\
+             \n\
+             \x20   fn previous_id(&self) -> Option<&'static str> {{\n\
+             \x20       {previous_id}\n\
+             \x20   }}\n\
+             \n\
+             \x20   async fn up(&self, op: &MigrateOp<'_>) -> DocumentStoreResult<()> {{\n\
+             {up}\
+             \x20       Ok(())\n\
+             \x20   }}\n\
+             \n\
+             \x20   async fn down(&self, op: &MigrateOp<'_>) -> DocumentStoreResult<()> {{\n\
+             {down}\
+             \x20       Ok(())\n\
+             \x20   }}\n\
+             }}\n",
+            id = self.id,
+            up = body(self.ops.clone()),
+            down = body(self.inverse_ops()),
+        )
+    }
+
+    /// Returns the steps undoing the migration, in the order they run.
+    fn inverse_ops(&self) -> Vec<AutoOp> {
+        self.ops
+            .iter()
+            .rev()
+            .filter_map(AutoOp::inverse)
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Migration for AutoMigration {
+    fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn previous_id(&self) -> Option<&'static str> {
+        self.previous_id
+    }
+
+    async fn up(&self, op: &MigrateOp<'_>) -> DocumentStoreResult<()> {
+        for step in &self.ops {
+            step.apply(op).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, op: &MigrateOp<'_>) -> DocumentStoreResult<()> {
+        for step in self.inverse_ops() {
+            step.apply(op).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Generates the migration from the `previous` snapshot to the `current` one.
+///
+/// # Arguments
+///
+/// * `id` - The ID of the generated migration
+/// * `previous_id` - The ID of the migration the `previous` snapshot was saved with
+/// * `previous` - The snapshot of the schemas before the migration
+/// * `current` - The snapshot of the schemas after the migration
+pub fn generate(
+    id: &'static str,
+    previous_id: Option<&'static str>,
+    previous: &SchemaSnapshot,
+    current: &SchemaSnapshot,
+) -> AutoMigration {
+    let mut ops = Vec::new();
+
+    for collection in current.collections.keys() {
+        // New collections hold no documents to change
+        if !previous
+            .collections
+            .contains_key(collection)
+        {
+            ops.push(AutoOp::CreateCollection { collection: collection.clone() });
+            continue;
+        }
+
+        let before = previous.fields(collection);
+        let after = current.fields(collection);
+
+        let mut dropped = before
+            .iter()
+            .filter(|(field, _)| !after.contains_key(*field))
+            .collect::<Vec<_>>();
+        let mut added = after
+            .iter()
+            .filter(|(field, _)| !before.contains_key(*field))
+            .collect::<Vec<_>>();
+
+        // A field is taken as renamed when its schema identifies it among the changed fields
+        let mut renamed = Vec::new();
+        for (from, (schema, _)) in &dropped {
+            let candidates = added
+                .iter()
+                .filter(|(_, (other, _))| other == schema)
+                .collect::<Vec<_>>();
+            let rivals = dropped
+                .iter()
+                .filter(|(_, (other, _))| other == schema)
+                .count();
+
+            if let [(to, _)] = candidates.as_slice()
+                && rivals == 1
+            {
+                renamed.push(((*from).clone(), (*to).clone()));
+            }
+        }
+        dropped.retain(|(field, _)| {
+            !renamed
+                .iter()
+                .any(|(from, _)| from == *field)
+        });
+        added.retain(|(field, _)| {
+            !renamed
+                .iter()
+                .any(|(_, to)| to == *field)
+        });
+
+        for (from, to) in renamed {
+            ops.push(AutoOp::RenameField { collection: collection.clone(), from, to });
+        }
+        for (field, (schema, required)) in dropped {
+            ops.push(AutoOp::DropField {
+                collection: collection.clone(),
+                field: field.clone(),
+                default: default_value(schema, *required),
+            });
+        }
+        for (field, (schema, required)) in added {
+            ops.push(AutoOp::AddField {
+                collection: collection.clone(),
+                field: field.clone(),
+                default: default_value(schema, *required),
+            });
+        }
+    }
+
+    AutoMigration {
+        id,
+        previous_id,
+        ops,
+        snapshot: current.clone(),
+    }
+}
+
+/// Returns the value a field is set to when it's added to existing documents.
+///
+/// The schema's `default` if it has one, otherwise null for optional and nullable fields and
+/// the empty value of the field's type for required ones.
+fn default_value(schema: &Value, required: bool) -> Bson {
+    if let Some(default) = schema.get("default") {
+        return bson::serialize_to_bson(default).unwrap_or(Bson::Null);
+    }
+
+    let types = match schema.get("type") {
+        Some(Value::String(name)) => vec![name.as_str()],
+        Some(Value::Array(names)) => names
+            .iter()
+            .filter_map(Value::as_str)
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    if !required || types.contains(&"null") {
+        return Bson::Null;
+    }
+
+    match types.first() {
+        Some(&"string") => Bson::String(String::new()),
+        Some(&"integer") => Bson::Int64(0),
+        Some(&"number") => Bson::Double(0.0),
+        Some(&"boolean") => Bson::Boolean(false),
+        Some(&"array") => Bson::Array(Vec::new()),
+        Some(&"object") => Bson::Document(bson::Document::new()),
+        _ => Bson::Null,
+    }
+}
@@ -0,0 +1,779 @@
+//! Writing to two backends at once, for migrating between them without downtime.
+//!
+//! [`MirroredStore`] applies every write to both of its backends and serves reads from the
+//! one chosen as primary. The usual migration to a new backend is:
+//!
+//! 1. Mirror the current backend as primary to the new one, so new writes reach both
+//! 2. Copy the existing documents over, for instance with an import
+//! 3. Make the new backend primary once it has every document
+//! 4. Drop the mirror and use the new backend alone
+//!
+//! Writes whose outcome differs between the backends are reported as a [`Divergence`], such
+//! as a write failing on the secondary only or updating a different number of documents.
+//! Divergences never fail the write: the primary's result is always returned.
+//!
+//! `find_one_and_update` and `find_one_and_delete` run on the primary first. The document
+//! it found is then updated or deleted on the secondary by its
+//! [ID](MirroredStore::with_id_field), so both backends modify the same document even when
+//! the filter and sort leave the choice open.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::mirror::{MirroredStore, MirrorPrimary};
+//!
+//! let store = DocumentStore::new(
+//!     MirroredStore::new(file_store, mongo_store)
+//!         .with_primary(MirrorPrimary::A)
+//!         .with_divergence_handler(|divergence| eprintln!("{}", divergence)),
+//! );
+//! ```
+
+use async_trait::async_trait;
+use bson::{Bson, RawDocumentBuf, Uuid};
+use std::{
+    fmt,
+    future::Future,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use crate::{
+    aggregate::Aggregate,
+    backend::{
+        DocumentStream, DynStoreBackend, InsertPolicy, MissingDocumentPolicy, QueryPlan,
+        ReturnDocument, StoreBackend, WriteReport,
+    },
+    cache::DEFAULT_ID_FIELD,
+    error::DocumentStoreResult,
+    query::{Expr, Filter, Query, Sort},
+    update::Update,
+};
+
+type DivergenceFn = Arc<dyn Fn(&Divergence) + Send + Sync>;
+
+/// Which backend of a [`MirroredStore`] serves reads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MirrorPrimary {
+    /// The backend passed first.
+    #[default]
+    A,
+    /// The backend passed second.
+    B,
+}
+
+/// A write whose outcome differed between the backends of a [`MirroredStore`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The backend operation, such as `update_documents`.
+    pub operation: &'static str,
+    /// The collection written to, if the operation targets one.
+    pub collection: Option<String>,
+    /// How the outcomes differed.
+    pub kind: DivergenceKind,
+}
+
+/// How the outcomes of a mirrored write differed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DivergenceKind {
+    /// The write succeeded on the primary and failed on the secondary, with this error.
+    SecondaryFailed(String),
+    /// The write failed on the primary, with this error, and succeeded on the secondary.
+    PrimaryFailed(String),
+    /// The write succeeded on both, but affected a different number of documents.
+    CountMismatch {
+        /// The documents affected on the primary.
+        primary: usize,
+        /// The documents affected on the secondary.
+        secondary: usize,
+    },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.operation)?;
+        if let Some(collection) = &self.collection {
+            write!(f, " on {}", collection)?;
+        }
+
+        match &self.kind {
+            DivergenceKind::SecondaryFailed(error) => {
+                write!(f, " failed on the secondary only: {}", error)
+            }
+            DivergenceKind::PrimaryFailed(error) => {
+                write!(f, " failed on the primary only: {}", error)
+            }
+            DivergenceKind::CountMismatch { primary, secondary } => write!(
+                f,
+                " affected {} documents on the primary and {} on the secondary",
+                primary, secondary
+            ),
+        }
+    }
+}
+
+/// A backend writing to two backends and reading from one of them.
+///
+/// See the [module documentation](self) for how it's used to migrate between backends.
+pub struct MirroredStore<A, B> {
+    a: A,
+    b: B,
+    primary: MirrorPrimary,
+    id_field: String,
+    on_divergence: Option<DivergenceFn>,
+    divergences: AtomicU64,
+}
+
+impl<A: StoreBackend, B: StoreBackend> MirroredStore<A, B> {
+    /// Mirrors writes to both backends, reading from `a` until another primary is chosen.
+    pub fn new(a: A, b: B) -> Self {
+        Self {
+            a,
+            b,
+            primary: MirrorPrimary::A,
+            id_field: DEFAULT_ID_FIELD.to_string(),
+            on_divergence: None,
+            divergences: AtomicU64::new(0),
+        }
+    }
+
+    /// Sets the backend serving reads, whose results are returned for writes.
+    pub fn with_primary(mut self, primary: MirrorPrimary) -> Self {
+        self.primary = primary;
+        self
+    }
+
+    /// Sets the field documents hold their ID in, `id` by default.
+    ///
+    /// Documents found by `find_one_and_update` and `find_one_and_delete` on the primary are
+    /// found on the secondary by this field.
+    pub fn with_id_field(mut self, field: impl Into<String>) -> Self {
+        self.id_field = field.into();
+        self
+    }
+
+    /// Calls `handler` with every divergence between the backends.
+    ///
+    /// The handler runs within the write, so it should only record the divergence, such as
+    /// logging it or incrementing a metric.
+    pub fn with_divergence_handler(
+        mut self,
+        handler: impl Fn(&Divergence) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_divergence = Some(Arc::new(handler));
+        self
+    }
+
+    /// Returns the backend serving reads.
+    pub fn primary(&self) -> MirrorPrimary {
+        self.primary
+    }
+
+    /// Returns the backend passed first.
+    pub fn a(&self) -> &A {
+        &self.a
+    }
+
+    /// Returns the backend passed second.
+    pub fn b(&self) -> &B {
+        &self.b
+    }
+
+    /// Unwraps both backends.
+    pub fn into_inner(self) -> (A, B) {
+        (self.a, self.b)
+    }
+
+    /// Returns the number of divergences reported since the store was created.
+    pub fn divergences(&self) -> u64 {
+        self.divergences.load(Ordering::Relaxed)
+    }
+
+    /// Runs a write on both backends, primary first, and reports how their outcomes differ.
+    ///
+    /// `count` returns the number of documents a successful write affected, if it reports one.
+    async fn mirror<T>(
+        &self,
+        operation: &'static str,
+        collection: Option<&str>,
+        a: impl Future<Output = DocumentStoreResult<T>>,
+        b: impl Future<Output = DocumentStoreResult<T>>,
+        count: impl Fn(&T) -> Option<usize>,
+    ) -> DocumentStoreResult<T> {
+        let (primary, secondary) = match self.primary {
+            MirrorPrimary::A => {
+                let primary = a.await;
+                (primary, b.await)
+            }
+            MirrorPrimary::B => {
+                let primary = b.await;
+                (primary, a.await)
+            }
+        };
+
+        let kind = match (&primary, &secondary) {
+            (Ok(_), Err(error)) => Some(DivergenceKind::SecondaryFailed(error.to_string())),
+            (Err(error), Ok(_)) => Some(DivergenceKind::PrimaryFailed(error.to_string())),
+            (Ok(primary), Ok(secondary)) => match (count(primary), count(secondary)) {
+                (Some(primary), Some(secondary)) if primary != secondary => {
+                    Some(DivergenceKind::CountMismatch { primary, secondary })
+                }
+                _ => None,
+            },
+            (Err(_), Err(_)) => None,
+        };
+
+        if let Some(kind) = kind {
+            self.report(Divergence {
+                operation,
+                collection: collection.map(str::to_string),
+                kind,
+            });
+        }
+
+        primary
+    }
+
+    fn report(&self, divergence: Divergence) {
+        self.divergences
+            .fetch_add(1, Ordering::Relaxed);
+
+        if let Some(handler) = &self.on_divergence {
+            handler(&divergence);
+        }
+    }
+
+    /// Reports how the secondary's outcome of a find-and-modify differs from the primary's,
+    /// which found a document.
+    fn compare_found(
+        &self,
+        operation: &'static str,
+        collection: &str,
+        secondary: DocumentStoreResult<Option<Bson>>,
+    ) {
+        let kind = match secondary {
+            Ok(Some(_)) => return,
+            Ok(None) => DivergenceKind::CountMismatch { primary: 1, secondary: 0 },
+            Err(error) => DivergenceKind::SecondaryFailed(error.to_string()),
+        };
+
+        self.report(Divergence {
+            operation,
+            collection: Some(collection.to_string()),
+            kind,
+        });
+    }
+
+    /// Returns a filter matching a document by the value of its ID field, or reports the
+    /// document as missing on the secondary if it has none.
+    fn id_filter(
+        &self,
+        operation: &'static str,
+        collection: &str,
+        document: &Bson,
+    ) -> Option<Expr> {
+        match document
+            .as_document()
+            .and_then(|document| document.get(&self.id_field))
+        {
+            Some(id) => Some(Filter::eq(self.id_field.clone(), id.clone())),
+            None => {
+                self.report(Divergence {
+                    operation,
+                    collection: Some(collection.to_string()),
+                    kind: DivergenceKind::SecondaryFailed(format!(
+                        "the document has no {} field to find it by",
+                        self.id_field
+                    )),
+                });
+                None
+            }
+        }
+    }
+
+    fn read(&self) -> &dyn DynStoreBackend
+    where
+        A: 'static,
+        B: 'static,
+    {
+        match self.primary {
+            MirrorPrimary::A => &self.a,
+            MirrorPrimary::B => &self.b,
+        }
+    }
+
+    fn secondary(&self) -> &dyn DynStoreBackend
+    where
+        A: 'static,
+        B: 'static,
+    {
+        match self.primary {
+            MirrorPrimary::A => &self.b,
+            MirrorPrimary::B => &self.a,
+        }
+    }
+}
+
+impl<A: fmt::Debug, B: fmt::Debug> fmt::Debug for MirroredStore<A, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MirroredStore")
+            .field("a", &self.a)
+            .field("b", &self.b)
+            .field("primary", &self.primary)
+            .field("id_field", &self.id_field)
+            .field("divergences", &self.divergences.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+/// The number of documents a write report counts as affected.
+fn affected(report: &WriteReport) -> Option<usize> {
+    Some(report.matched + report.upserted)
+}
+
+#[async_trait]
+impl<A: StoreBackend + 'static, B: StoreBackend + 'static> StoreBackend for MirroredStore<A, B> {
+    async fn insert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.mirror(
+            "insert_documents",
+            Some(collection),
+            self.a
+                .insert_documents(documents.clone(), collection, policy),
+            self.b
+                .insert_documents(documents, collection, policy),
+            affected,
+        )
+        .await
+    }
+
+    async fn update_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.mirror(
+            "update_documents",
+            Some(collection),
+            self.a
+                .update_documents(documents.clone(), collection, policy),
+            self.b
+                .update_documents(documents, collection, policy),
+            affected,
+        )
+        .await
+    }
+
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.mirror(
+            "update_documents_if_version",
+            Some(collection),
+            self.a
+                .update_documents_if_version(documents.clone(), version_field, collection),
+            self.b
+                .update_documents_if_version(documents, version_field, collection),
+            affected,
+        )
+        .await
+    }
+
+    async fn update_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, Bson, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.mirror(
+            "update_documents_if_match",
+            Some(collection),
+            self.a
+                .update_documents_if_match(documents.clone(), collection),
+            self.b
+                .update_documents_if_match(documents, collection),
+            affected,
+        )
+        .await
+    }
+
+    async fn upsert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+    ) -> DocumentStoreResult<()> {
+        self.mirror(
+            "upsert_documents",
+            Some(collection),
+            self.a
+                .upsert_documents(documents.clone(), collection),
+            self.b
+                .upsert_documents(documents, collection),
+            |_| None,
+        )
+        .await
+    }
+
+    async fn delete_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        self.mirror(
+            "delete_documents",
+            Some(collection),
+            self.a
+                .delete_documents(ids.clone(), collection),
+            self.b.delete_documents(ids, collection),
+            |deleted| Some(*deleted),
+        )
+        .await
+    }
+
+    async fn delete_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        self.mirror(
+            "delete_documents_if_match",
+            Some(collection),
+            self.a
+                .delete_documents_if_match(documents.clone(), collection),
+            self.b
+                .delete_documents_if_match(documents, collection),
+            |deleted| Some(*deleted),
+        )
+        .await
+    }
+
+    async fn update_by_query(
+        &self,
+        filter: Expr,
+        update: Update,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.mirror(
+            "update_by_query",
+            Some(collection),
+            self.a
+                .update_by_query(filter.clone(), update.clone(), collection),
+            self.b
+                .update_by_query(filter, update, collection),
+            affected,
+        )
+        .await
+    }
+
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize> {
+        self.mirror(
+            "delete_by_query",
+            Some(collection),
+            self.a
+                .delete_by_query(filter.clone(), collection),
+            self.b
+                .delete_by_query(filter, collection),
+            |deleted| Some(*deleted),
+        )
+        .await
+    }
+
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        let found = self
+            .read()
+            .find_one_and_update(filter, sort, update.clone(), returned, collection)
+            .await?;
+
+        if let Some(filter) = found
+            .as_ref()
+            .and_then(|document| self.id_filter("find_one_and_update", collection, document))
+        {
+            let secondary = self
+                .secondary()
+                .find_one_and_update(filter, Vec::new(), update, returned, collection)
+                .await;
+            self.compare_found("find_one_and_update", collection, secondary);
+        }
+
+        Ok(found)
+    }
+
+    async fn find_one_and_delete(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        let found = self
+            .read()
+            .find_one_and_delete(filter, sort, collection)
+            .await?;
+
+        if let Some(filter) = found
+            .as_ref()
+            .and_then(|document| self.id_filter("find_one_and_delete", collection, document))
+        {
+            let secondary = self
+                .secondary()
+                .find_one_and_delete(filter, Vec::new(), collection)
+                .await;
+            self.compare_found("find_one_and_delete", collection, secondary);
+        }
+
+        Ok(found)
+    }
+
+    async fn get_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.read()
+            .get_documents(ids, collection)
+            .await
+    }
+
+    async fn query_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.read()
+            .query_documents(query, collection)
+            .await
+    }
+
+    async fn query_stream(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<DocumentStream> {
+        self.read()
+            .query_stream(query, collection)
+            .await
+    }
+
+    async fn query_raw_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<RawDocumentBuf>> {
+        self.read()
+            .query_raw_documents(query, collection)
+            .await
+    }
+
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize> {
+        self.read()
+            .count_documents(query, collection)
+            .await
+    }
+
+    async fn distinct(
+        &self,
+        field: &str,
+        filter: Option<Expr>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.read()
+            .distinct(field, filter, collection)
+            .await
+    }
+
+    async fn aggregate(
+        &self,
+        aggregate: Aggregate,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.read()
+            .aggregate(aggregate, collection)
+            .await
+    }
+
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan> {
+        self.read()
+            .explain(query, collection)
+            .await
+    }
+
+    async fn current_revision_id(&self) -> DocumentStoreResult<Option<String>> {
+        self.read().current_revision_id().await
+    }
+
+    async fn set_revision_id(&self, revision_id: &str) -> DocumentStoreResult<()> {
+        self.mirror(
+            "set_revision_id",
+            None,
+            self.a.set_revision_id(revision_id),
+            self.b.set_revision_id(revision_id),
+            |_| None,
+        )
+        .await
+    }
+
+    async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.mirror(
+            "create_collection",
+            Some(name),
+            self.a.create_collection(name),
+            self.b.create_collection(name),
+            |_| None,
+        )
+        .await
+    }
+
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        self.read()
+            .collection_exists(name)
+            .await
+    }
+
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.mirror(
+            "ensure_collection",
+            Some(name),
+            self.a.ensure_collection(name),
+            self.b.ensure_collection(name),
+            |_| None,
+        )
+        .await
+    }
+
+    async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.mirror(
+            "drop_collection",
+            Some(name),
+            self.a.drop_collection(name),
+            self.b.drop_collection(name),
+            |_| None,
+        )
+        .await
+    }
+
+    async fn list_collections(&self) -> DocumentStoreResult<Vec<String>> {
+        self.read().list_collections().await
+    }
+
+    async fn add_field(
+        &self,
+        collection: &str,
+        field: &str,
+        default: Bson,
+    ) -> DocumentStoreResult<()> {
+        self.mirror(
+            "add_field",
+            Some(collection),
+            self.a
+                .add_field(collection, field, default.clone()),
+            self.b
+                .add_field(collection, field, default),
+            |_| None,
+        )
+        .await
+    }
+
+    async fn drop_field(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.mirror(
+            "drop_field",
+            Some(collection),
+            self.a.drop_field(collection, field),
+            self.b.drop_field(collection, field),
+            |_| None,
+        )
+        .await
+    }
+
+    async fn rename_field(
+        &self,
+        collection: &str,
+        field: &str,
+        new: &str,
+    ) -> DocumentStoreResult<()> {
+        self.mirror(
+            "rename_field",
+            Some(collection),
+            self.a
+                .rename_field(collection, field, new),
+            self.b
+                .rename_field(collection, field, new),
+            |_| None,
+        )
+        .await
+    }
+
+    async fn add_index(
+        &self,
+        collection: &str,
+        field: &str,
+        unique: bool,
+    ) -> DocumentStoreResult<()> {
+        self.mirror(
+            "add_index",
+            Some(collection),
+            self.a
+                .add_index(collection, field, unique),
+            self.b
+                .add_index(collection, field, unique),
+            |_| None,
+        )
+        .await
+    }
+
+    async fn drop_index(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.mirror(
+            "drop_index",
+            Some(collection),
+            self.a.drop_index(collection, field),
+            self.b.drop_index(collection, field),
+            |_| None,
+        )
+        .await
+    }
+
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.mirror(
+            "expire_documents",
+            Some(collection),
+            self.a
+                .expire_documents(collection, field),
+            self.b
+                .expire_documents(collection, field),
+            |_| None,
+        )
+        .await
+    }
+
+    fn inner_backend(&self) -> Option<&dyn DynStoreBackend> {
+        Some(self.read())
+    }
+
+    async fn close(&self) -> DocumentStoreResult<()> {
+        let a = self.a.close().await;
+        let b = self.b.close().await;
+
+        a.and(b)
+    }
+
+    async fn shutdown(self) -> DocumentStoreResult<()> {
+        let a = self.a.shutdown().await;
+        let b = self.b.shutdown().await;
+
+        a.and(b)
+    }
+}
@@ -3,9 +3,31 @@
 //! This module provides pagination support for large result sets,
 //! including the [`Page`] struct for result pages and [`PaginationParams`]
 //! for specifying pagination parameters.
+//!
+//! [`QueryPage`] is the result of `query_paged` on typed collections. It combines a page of
+//! items with the optional total, the cursor of the next page and facet counts, giving REST
+//! handlers a single response shape:
+//!
+//! ```ignore
+//! use doclayer::page::PageRequest;
+//!
+//! let request = PageRequest::new(20)
+//!     .after(cursor)          // from the previous page's `next_cursor`
+//!     .with_total()
+//!     .facet("status");
+//!
+//! let page = orders.query_paged(query, request).await?;
+//! // { "items": [...], "total": 132, "next_cursor": "o28", "facets": { "status": [...] } }
+//! ```
 
+use bson::Bson;
 use serde::{Deserialize, Serialize};
-use std::cmp::min;
+use std::{cmp::min, collections::BTreeMap, fmt, str::FromStr};
+
+use crate::{aggregate::Aggregate, error::DocumentStoreError, query::Query};
+
+/// The name of the count accumulator in facet aggregations.
+const FACET_COUNT_FIELD: &str = "count";
 
 /// A single page of paginated results.
 ///
@@ -169,7 +191,44 @@ impl PaginationParams {
     /// assert_eq!(params.offset(), 40);  // Skip 40 items for page 3
     /// ```
     pub fn offset(&self) -> usize {
-        (self.page - 1) * self.per_page
+        self.page.saturating_sub(1) * self.per_page
+    }
+
+    /// Wraps items already fetched for this page into a [`Page`].
+    ///
+    /// Unlike [`paginate`](Self::paginate), the items are expected to be only this page's
+    /// items, with the total across all pages given separately.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - The items of this page
+    /// * `count` - Total count of items across all pages
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let params = PaginationParams::new(2, 10);
+    /// let page = params.to_page((11..=20).collect(), 25);
+    ///
+    /// assert_eq!(page.next_page, Some(3));
+    /// assert_eq!(page.previous_page, Some(1));
+    /// ```
+    pub fn to_page<T>(&self, items: Vec<T>, count: usize) -> Page<T> {
+        let end = self.offset() + items.len();
+
+        Page::builder(items)
+            .with_count(count)
+            .with_next_page(if end < count {
+                Some(self.page + 1)
+            } else {
+                None
+            })
+            .with_previous_page(if self.page > 1 {
+                Some(self.page - 1)
+            } else {
+                None
+            })
+            .build()
     }
 
     /// Paginates a vec of items according to these parameters.
@@ -275,3 +334,234 @@ impl Default for PaginationParamsBuilder {
         Self::new()
     }
 }
+
+/// A page of query results with its total, next cursor and facet counts.
+///
+/// Returned by `query_paged` on typed collections. See the [module documentation](self).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QueryPage<T> {
+    /// The items of this page.
+    pub items: Vec<T>,
+    /// The number of items matching the query across all pages, if requested.
+    pub total: Option<usize>,
+    /// The cursor of the next page, `None` on the last page.
+    pub next_cursor: Option<PageCursor>,
+    /// The counts of the values of each requested facet field, by field.
+    pub facets: BTreeMap<String, Vec<FacetCount>>,
+}
+
+/// The number of matching items holding a value in a facet field.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FacetCount {
+    /// The value of the field, `null` for items missing the field.
+    pub value: Bson,
+    /// The number of matching items holding the value.
+    pub count: usize,
+}
+
+/// An opaque token selecting where a page starts.
+///
+/// Cursors are produced by [`QueryPage::next_cursor`] and only valid for the query that
+/// produced them. They round-trip through strings, e.g. as a query parameter:
+///
+/// ```ignore
+/// let cursor = params.cursor.map(|cursor| cursor.parse::<PageCursor>()).transpose()?;
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct PageCursor {
+    offset: usize,
+}
+
+impl PageCursor {
+    fn new(offset: usize) -> Self {
+        Self { offset }
+    }
+}
+
+impl fmt::Display for PageCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "o{:x}", self.offset)
+    }
+}
+
+impl FromStr for PageCursor {
+    type Err = DocumentStoreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.strip_prefix('o')
+            .and_then(|offset| usize::from_str_radix(offset, 16).ok())
+            .map(PageCursor::new)
+            .ok_or_else(|| {
+                DocumentStoreError::Serialization(format!("Invalid page cursor {:?}", s))
+            })
+    }
+}
+
+impl TryFrom<String> for PageCursor {
+    type Error = DocumentStoreError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<PageCursor> for String {
+    fn from(cursor: PageCursor) -> Self {
+        cursor.to_string()
+    }
+}
+
+/// Selects a page of a `query_paged` call and the metadata returned with it.
+///
+/// # Example
+///
+/// ```ignore
+/// let request = PageRequest::new(50).with_total().facet("category").facet("brand");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageRequest {
+    limit: usize,
+    cursor: Option<PageCursor>,
+    with_total: bool,
+    facets: Vec<String>,
+}
+
+impl PageRequest {
+    /// Creates a request for the first page.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The maximum number of items in the page
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            cursor: None,
+            with_total: false,
+            facets: Vec::new(),
+        }
+    }
+
+    /// Starts the page at a cursor returned with a previous page. `None` selects the first
+    /// page.
+    pub fn after(mut self, cursor: impl Into<Option<PageCursor>>) -> Self {
+        self.cursor = cursor.into();
+        self
+    }
+
+    /// Counts the items matching the query across all pages.
+    pub fn with_total(mut self) -> Self {
+        self.with_total = true;
+        self
+    }
+
+    /// Counts the items matching the query by the values of a field.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field to count values of, in dot notation for embedded fields
+    pub fn facet(mut self, field: impl Into<String>) -> Self {
+        self.facets.push(field.into());
+        self
+    }
+
+    /// Returns whether the total is requested.
+    pub fn includes_total(&self) -> bool {
+        self.with_total
+    }
+
+    /// Returns the requested facet fields.
+    pub fn facets(&self) -> &[String] {
+        &self.facets
+    }
+
+    fn offset(&self) -> usize {
+        self.cursor
+            .as_ref()
+            .map_or(0, |cursor| cursor.offset)
+    }
+
+    /// Returns the query reading this page, plus one item telling whether a next page exists.
+    pub(crate) fn page_query(&self, query: &Query) -> Query {
+        Query {
+            limit: Some(self.limit.saturating_add(1)),
+            offset: Some(self.offset()),
+            ..query.clone()
+        }
+    }
+
+    /// Returns the query counting every matching item.
+    pub(crate) fn total_query(&self, query: &Query) -> Query {
+        Query {
+            limit: None,
+            offset: None,
+            ..query.clone()
+        }
+    }
+
+    /// Returns the aggregation counting the matching items by the values of a field.
+    pub(crate) fn facet_aggregate(&self, query: &Query, field: &str) -> Aggregate {
+        Aggregate {
+            filter: query.filter.clone(),
+            ..Aggregate::builder()
+                .group_by(field)
+                .count(FACET_COUNT_FIELD)
+                .build()
+        }
+    }
+
+    /// Assembles the page from the results of the queries above.
+    pub(crate) fn to_page<T>(
+        &self,
+        mut items: Vec<T>,
+        total: Option<usize>,
+        facets: Vec<(String, Vec<Bson>)>,
+    ) -> QueryPage<T> {
+        let next_cursor = (items.len() > self.limit).then(|| {
+            items.truncate(self.limit);
+            PageCursor::new(self.offset() + self.limit)
+        });
+
+        QueryPage {
+            items,
+            total,
+            next_cursor,
+            facets: facets
+                .into_iter()
+                .map(|(field, rows)| {
+                    let counts = facet_counts(&field, rows);
+                    (field, counts)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Reads the counts of a facet aggregation, most frequent values first.
+fn facet_counts(field: &str, rows: Vec<Bson>) -> Vec<FacetCount> {
+    let mut counts = rows
+        .into_iter()
+        .filter_map(|row| match row {
+            Bson::Document(mut row) => Some(FacetCount {
+                value: row.remove(field).unwrap_or(Bson::Null),
+                count: match row.get(FACET_COUNT_FIELD) {
+                    Some(Bson::Int32(count)) => *count as usize,
+                    Some(Bson::Int64(count)) => *count as usize,
+                    Some(Bson::Double(count)) => *count as usize,
+                    _ => 0,
+                },
+            }),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    // Groups come back in no particular order; ties are ordered by value for stable output
+    counts.sort_by(|a, b| {
+        b.count.cmp(&a.count).then_with(|| {
+            a.value
+                .to_string()
+                .cmp(&b.value.to_string())
+        })
+    });
+    counts
+}
@@ -0,0 +1,204 @@
+//! Plugin registration for assembling a document layer from independent crates.
+//!
+//! A [`DoclayerPlugin`] bundles everything a module contributes to the document layer: its
+//! document types, the collections it needs, its migrations and a startup hook. Applications
+//! register each plugin once at startup through [`PluginHost::register_plugin`].
+//!
+//! Each plugin's migrations form their own revision chain. The last applied revision of every
+//! plugin is tracked in the [`PLUGIN_REVISIONS_COLLECTION`] collection, independently of the
+//! store revision used by [`Migrator`](crate::migrate::Migrator), so plugins can evolve
+//! their schema without coordinating migration ids with each other.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::plugin::{DoclayerPlugin, PluginHost};
+//!
+//! struct BillingPlugin;
+//!
+//! #[async_trait::async_trait]
+//! impl DoclayerPlugin for BillingPlugin {
+//!     fn name(&self) -> &'static str { "billing" }
+//!
+//!     fn register_documents(&self, registry: DocumentRegistry) -> DocumentRegistry {
+//!         registry.with_document::<Invoice>()
+//!     }
+//!
+//!     fn collections(&self) -> Vec<&'static str> {
+//!         vec![Invoice::collection_name()]
+//!     }
+//!
+//!     fn migrations(&self) -> Vec<MigrationRef> {
+//!         vec![Box::new(AddInvoiceIndexes)]
+//!     }
+//! }
+//!
+//! let registry = store.register_plugin(&BillingPlugin).await?
+//!     .merge(store.register_plugin(&ShippingPlugin).await?);
+//! ```
+
+use async_trait::async_trait;
+use bson::Uuid;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    document::{Document, DocumentRegistry},
+    error::DocumentStoreResult,
+    migrate::{MigrateOp, MigrationDirection, MigrationRef, pending_upgrades, run_migration},
+    query::{Filter, Query},
+    store::{AsDynDocumentStore, DynDocumentStoreRef},
+};
+
+/// The collection recording the last applied migration of every registered plugin.
+pub const PLUGIN_REVISIONS_COLLECTION: &str = "_plugin_revisions";
+
+/// A self-contained part of an application's document layer.
+///
+/// Every method except [`name`](DoclayerPlugin::name) has a default that contributes nothing,
+/// so plugins only implement what they need.
+#[async_trait]
+pub trait DoclayerPlugin: Send + Sync {
+    /// Returns a unique name for this plugin.
+    ///
+    /// The name keys the plugin's migration revision, so it must not change once the plugin
+    /// has been registered with a store.
+    fn name(&self) -> &'static str;
+
+    /// Registers the plugin's document types.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - The registry to add the plugin's document types to
+    fn register_documents(&self, registry: DocumentRegistry) -> DocumentRegistry {
+        registry
+    }
+
+    /// Returns the collections the plugin needs, created on registration if missing.
+    fn collections(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+
+    /// Returns the plugin's migrations.
+    ///
+    /// They form a revision chain of their own, applied up to its head on registration.
+    fn migrations(&self) -> Vec<MigrationRef> {
+        Vec::new()
+    }
+
+    /// Called once the plugin's collections exist and its migrations have been applied.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store the plugin is registered with
+    ///
+    /// # Errors
+    ///
+    /// Returns an error to abort the registration.
+    async fn on_register(&self, store: &DynDocumentStoreRef<'_>) -> DocumentStoreResult<()> {
+        let _ = store;
+        Ok(())
+    }
+}
+
+/// Extension trait for registering plugins with a store.
+///
+/// This trait is automatically implemented for every store type.
+#[async_trait]
+pub trait PluginHost: Send + Sync {
+    /// Registers a plugin with this store.
+    ///
+    /// Creates the plugin's collections, applies its pending migrations and runs its
+    /// [`on_register`](DoclayerPlugin::on_register) hook, in that order. Registering the same
+    /// plugin again only applies migrations added since.
+    ///
+    /// # Arguments
+    ///
+    /// * `plugin` - The plugin to register
+    ///
+    /// # Returns
+    ///
+    /// A registry containing the plugin's document types, to be merged with those of the
+    /// other plugins using [`DocumentRegistry::merge`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a collection cannot be created, a migration fails or the hook
+    /// returns an error. Migrations applied before the failure stay recorded.
+    async fn register_plugin<P>(&self, plugin: &P) -> DocumentStoreResult<DocumentRegistry>
+    where
+        P: DoclayerPlugin + ?Sized;
+}
+
+#[async_trait]
+impl<T> PluginHost for T
+where
+    T: AsDynDocumentStore + Send + Sync,
+{
+    async fn register_plugin<P>(&self, plugin: &P) -> DocumentStoreResult<DocumentRegistry>
+    where
+        P: DoclayerPlugin + ?Sized,
+    {
+        let store = self.as_dyn();
+
+        for collection in plugin.collections() {
+            store
+                .ensure_collection(collection)
+                .await?;
+        }
+
+        store
+            .ensure_collection(PLUGIN_REVISIONS_COLLECTION)
+            .await?;
+
+        let revisions = store.typed_collection::<PluginRevision>();
+        let mut record = revisions
+            .query(
+                Query::builder()
+                    .filter(Filter::eq("plugin", plugin.name()))
+                    .build(),
+            )
+            .await?
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| PluginRevision {
+                id: Uuid::new(),
+                plugin: plugin.name().to_string(),
+                revision: None,
+            });
+
+        let op = MigrateOp::new(&store);
+        for (migration, revision) in
+            pending_upgrades(plugin.migrations(), record.revision.as_deref())?
+        {
+            run_migration(migration.as_ref(), MigrationDirection::Up, Some(plugin.name()), &op)
+                .await?;
+
+            record.revision = Some(revision);
+            revisions
+                .upsert(vec![record.clone()])
+                .await?;
+        }
+
+        plugin.on_register(&store).await?;
+
+        Ok(plugin.register_documents(DocumentRegistry::new()))
+    }
+}
+
+/// The last applied migration of a plugin.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PluginRevision {
+    id: Uuid,
+    plugin: String,
+    revision: Option<String>,
+}
+
+impl Document for PluginRevision {
+    fn id(&self) -> &Uuid {
+        &self.id
+    }
+
+    fn collection_name() -> &'static str {
+        PLUGIN_REVISIONS_COLLECTION
+    }
+}
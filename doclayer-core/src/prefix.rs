@@ -0,0 +1,443 @@
+//! Namespacing collections by a name prefix.
+//!
+//! [`Prefixed`] prepends a fixed prefix to the name of every collection a backend is asked
+//! about, so several environments (`staging_`, `qa_`, ...) can share one database without
+//! seeing each other's documents. It is a lightweight alternative to full multi-tenancy:
+//! there is no isolation beyond the collection names.
+//!
+//! Migration revisions are namespaced too. Instead of the backend's own revision storage, a
+//! prefixed backend keeps its revision in the `_revisions` collection under its prefix, so
+//! each environment migrates independently.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let store = DocumentStore::new(backend).with_prefix("staging_");
+//!
+//! // Stored in the `staging_users` collection
+//! store.typed_collection::<User>().insert(vec![user]).await?;
+//! ```
+
+use async_trait::async_trait;
+use bson::{Bson, RawDocumentBuf, Uuid, doc};
+
+use crate::{
+    aggregate::Aggregate,
+    backend::{
+        DocumentStream, DynStoreBackend, InsertPolicy, MissingDocumentPolicy, QueryPlan,
+        ReturnDocument, StoreBackend, WriteReport,
+    },
+    error::DocumentStoreResult,
+    query::{Expr, Query, Sort},
+    transaction::{Transaction, TransactionBackend},
+    update::Update,
+};
+
+/// The collection, under the prefix, holding a prefixed backend's migration revision.
+pub const PREFIXED_REVISIONS_COLLECTION: &str = "_revisions";
+
+/// The id of the document holding the revision.
+const REVISION_DOCUMENT_ID: Uuid = Uuid::from_bytes([0; 16]);
+
+/// A backend whose collection names are prefixed, namespacing them within a shared database.
+///
+/// Collection names passed in are unprefixed and [`list_collections`](StoreBackend::list_collections)
+/// only returns this prefix's collections, with the prefix stripped.
+#[derive(Debug)]
+pub struct Prefixed<B> {
+    backend: B,
+    prefix: String,
+}
+
+impl<B: StoreBackend> Prefixed<B> {
+    /// Wraps a backend so every collection name is prefixed with `prefix`.
+    pub fn new(backend: B, prefix: impl Into<String>) -> Self {
+        Self { backend, prefix: prefix.into() }
+    }
+
+    /// Returns the prefix of the collection names.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Returns the wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.backend
+    }
+
+    /// Unwraps the backend, discarding the prefix.
+    pub fn into_inner(self) -> B {
+        self.backend
+    }
+
+    /// Returns the name of a collection in the wrapped backend.
+    pub fn collection_name(&self, collection: &str) -> String {
+        format!("{}{}", self.prefix, collection)
+    }
+}
+
+#[async_trait]
+impl<B: StoreBackend + 'static> StoreBackend for Prefixed<B> {
+    async fn insert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.backend
+            .insert_documents(documents, &self.collection_name(collection), policy)
+            .await
+    }
+
+    async fn update_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.backend
+            .update_documents(documents, &self.collection_name(collection), policy)
+            .await
+    }
+
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.backend
+            .update_documents_if_version(
+                documents,
+                version_field,
+                &self.collection_name(collection),
+            )
+            .await
+    }
+
+    async fn update_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, Bson, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.backend
+            .update_documents_if_match(documents, &self.collection_name(collection))
+            .await
+    }
+
+    async fn upsert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+    ) -> DocumentStoreResult<()> {
+        self.backend
+            .upsert_documents(documents, &self.collection_name(collection))
+            .await
+    }
+
+    async fn delete_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        self.backend
+            .delete_documents(ids, &self.collection_name(collection))
+            .await
+    }
+
+    async fn delete_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        self.backend
+            .delete_documents_if_match(documents, &self.collection_name(collection))
+            .await
+    }
+
+    async fn update_by_query(
+        &self,
+        filter: Expr,
+        update: Update,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.backend
+            .update_by_query(filter, update, &self.collection_name(collection))
+            .await
+    }
+
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize> {
+        self.backend
+            .delete_by_query(filter, &self.collection_name(collection))
+            .await
+    }
+
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        self.backend
+            .find_one_and_update(filter, sort, update, returned, &self.collection_name(collection))
+            .await
+    }
+
+    async fn find_one_and_delete(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        self.backend
+            .find_one_and_delete(filter, sort, &self.collection_name(collection))
+            .await
+    }
+
+    async fn get_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .get_documents(ids, &self.collection_name(collection))
+            .await
+    }
+
+    async fn query_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .query_documents(query, &self.collection_name(collection))
+            .await
+    }
+
+    async fn query_stream(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<DocumentStream> {
+        self.backend
+            .query_stream(query, &self.collection_name(collection))
+            .await
+    }
+
+    async fn query_raw_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<RawDocumentBuf>> {
+        self.backend
+            .query_raw_documents(query, &self.collection_name(collection))
+            .await
+    }
+
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize> {
+        self.backend
+            .count_documents(query, &self.collection_name(collection))
+            .await
+    }
+
+    async fn distinct(
+        &self,
+        field: &str,
+        filter: Option<Expr>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .distinct(field, filter, &self.collection_name(collection))
+            .await
+    }
+
+    async fn aggregate(
+        &self,
+        aggregate: Aggregate,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .aggregate(aggregate, &self.collection_name(collection))
+            .await
+    }
+
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan> {
+        self.backend
+            .explain(query, &self.collection_name(collection))
+            .await
+    }
+
+    async fn current_revision_id(&self) -> DocumentStoreResult<Option<String>> {
+        let collection = self.collection_name(PREFIXED_REVISIONS_COLLECTION);
+        if !self
+            .backend
+            .collection_exists(&collection)
+            .await?
+        {
+            return Ok(None);
+        }
+
+        let revision = self
+            .backend
+            .get_documents(vec![REVISION_DOCUMENT_ID], &collection)
+            .await?
+            .into_iter()
+            .next()
+            .and_then(|document| match document {
+                Bson::Document(document) => document
+                    .get_str("revision_id")
+                    .ok()
+                    .map(str::to_string),
+                _ => None,
+            });
+
+        Ok(revision)
+    }
+
+    async fn set_revision_id(&self, revision_id: &str) -> DocumentStoreResult<()> {
+        let collection = self.collection_name(PREFIXED_REVISIONS_COLLECTION);
+        self.backend
+            .ensure_collection(&collection)
+            .await?;
+
+        self.backend
+            .upsert_documents(
+                vec![(
+                    REVISION_DOCUMENT_ID,
+                    Bson::Document(doc! {
+                        "id": REVISION_DOCUMENT_ID,
+                        "revision_id": revision_id,
+                    }),
+                )],
+                &collection,
+            )
+            .await
+    }
+
+    async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .create_collection(&self.collection_name(name))
+            .await
+    }
+
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        self.backend
+            .collection_exists(&self.collection_name(name))
+            .await
+    }
+
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .ensure_collection(&self.collection_name(name))
+            .await
+    }
+
+    async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .drop_collection(&self.collection_name(name))
+            .await
+    }
+
+    async fn list_collections(&self) -> DocumentStoreResult<Vec<String>> {
+        let collections = self
+            .backend
+            .list_collections()
+            .await?
+            .into_iter()
+            .filter_map(|name| {
+                name.strip_prefix(&self.prefix)
+                    .map(str::to_string)
+            })
+            .filter(|name| name != PREFIXED_REVISIONS_COLLECTION)
+            .collect();
+
+        Ok(collections)
+    }
+
+    async fn add_field(
+        &self,
+        collection: &str,
+        field: &str,
+        default: Bson,
+    ) -> DocumentStoreResult<()> {
+        self.backend
+            .add_field(&self.collection_name(collection), field, default)
+            .await
+    }
+
+    async fn drop_field(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .drop_field(&self.collection_name(collection), field)
+            .await
+    }
+
+    async fn rename_field(
+        &self,
+        collection: &str,
+        field: &str,
+        new: &str,
+    ) -> DocumentStoreResult<()> {
+        self.backend
+            .rename_field(&self.collection_name(collection), field, new)
+            .await
+    }
+
+    async fn add_index(
+        &self,
+        collection: &str,
+        field: &str,
+        unique: bool,
+    ) -> DocumentStoreResult<()> {
+        self.backend
+            .add_index(&self.collection_name(collection), field, unique)
+            .await
+    }
+
+    async fn drop_index(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .drop_index(&self.collection_name(collection), field)
+            .await
+    }
+
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .expire_documents(&self.collection_name(collection), field)
+            .await
+    }
+
+    async fn begin_transaction(&self) -> DocumentStoreResult<Transaction> {
+        let transaction = self.backend.begin_transaction().await?;
+
+        Ok(Transaction::new(Prefixed::new(transaction, self.prefix.clone())))
+    }
+
+    fn inner_backend(&self) -> Option<&dyn DynStoreBackend> {
+        Some(&self.backend)
+    }
+
+    async fn close(&self) -> DocumentStoreResult<()> {
+        self.backend.close().await
+    }
+
+    async fn shutdown(self) -> DocumentStoreResult<()> {
+        self.backend.shutdown().await
+    }
+}
+
+// Transactions of a prefixed backend keep the prefix
+#[async_trait]
+impl TransactionBackend for Prefixed<Transaction> {
+    fn backend(&self) -> &dyn DynStoreBackend {
+        self
+    }
+
+    async fn commit(self: Box<Self>) -> DocumentStoreResult<()> {
+        self.backend.commit().await
+    }
+
+    async fn rollback(self: Box<Self>) -> DocumentStoreResult<()> {
+        self.backend.rollback().await
+    }
+}
@@ -23,16 +23,50 @@
 //! The [`Filter`] struct provides a collection of static methods for building filter expressions:
 //!
 //! - Comparison: `eq`, `ne`, `gt`, `gte`, `lt`, `lte`
-//! - String: `starts_with`, `ends_with`, `contains`, `not_contains`
+//! - Range: `between`, `between_exclusive`, `range`
+//! - String: `starts_with`, `ends_with`, `contains`, `not_contains`, `matches`,
+//!   `case_insensitive`
 //! - Existence: `exists`, `not_exists`
-//! - Array: `any_of`, `none_of`
+//! - Array: `any_of`, `none_of`, `contains_matching`
+//! - Custom: `custom`, for operators registered on the backend
 //! - Logical: `and`, `or`
 //!
 //! Expressions can be combined using chainable methods for more complex queries.
+//!
+//! # Typed Fields
+//!
+//! `#[derive(Document)]` also generates a [`Field`] constant for every field, checking field
+//! names and value types at compile time. Filters on typed fields return a
+//! [`DocumentStoreResult`], combined with the [`IntoFilter`] methods:
+//!
+//! ```ignore
+//! let expr = UserFields::NAME.eq("Alice").and_filter(UserFields::AGE.gt(18))?;
+//!
+//! // Or on a typed collection, through the document's fields
+//! let users = users
+//!     .find()
+//!     .filter_by(|f| f.name().eq("Alice").and_filter(f.age().gt(18)))
+//!     .sort_desc_by(|f| f.created_at())
+//!     .await?;
+//! ```
+//!
+//! # Rendering
+//!
+//! [`Expr`] and [`Query`] implement [`Display`](fmt::Display) as a compact single line, such as
+//! `name == "Alice" AND age > 18`, for logs and error messages. `to_pretty_string` renders the
+//! same text indented over several lines, which is easier to read for deeply nested filters.
 
-use bson::Bson;
+use bson::{Bson, Document, ser::serialize_to_bson};
+use serde::Serialize;
+use std::{
+    cmp::Ordering, collections::HashMap, fmt, marker::PhantomData, ops::Bound, sync::Arc,
+    time::Duration,
+};
 
-use crate::error::DocumentStoreError;
+use crate::{
+    error::{DocumentStoreError, DocumentStoreResult},
+    time::Instant,
+};
 
 /// Sort direction for query results.
 #[derive(Debug, Clone)]
@@ -54,6 +88,300 @@ pub struct Sort {
     pub direction: SortDirection,
 }
 
+type CompareFn = Arc<dyn Fn(&Bson, &Bson) -> Ordering + Send + Sync>;
+
+/// A custom ordering for the values of a sort field.
+///
+/// Comparators are registered per collection and field on a backend builder, in a
+/// [`SortComparators`] registry, and apply whenever a query sorts by that field.
+///
+/// # Example
+///
+/// ```ignore
+/// let store = InMemoryStore::builder()
+///     .with_comparator("releases", "version", SortComparator::Numeric)
+///     .with_comparator("users", "name", SortComparator::CaseInsensitive)
+///     .build()
+///     .await?;
+/// ```
+#[derive(Clone)]
+pub enum SortComparator {
+    /// Compares strings without regard to case.
+    CaseInsensitive,
+    /// Compares strings with runs of digits compared as numbers, so `"1.10.0"` sorts after
+    /// `"1.9.2"`. Suits semantic versions and numbered names.
+    Numeric,
+    /// Compares values with a function.
+    ///
+    /// Only backends sorting in process, such as the in-memory store, can use a custom
+    /// comparator; other backends reject queries sorting by its field.
+    Custom(CompareFn),
+}
+
+impl SortComparator {
+    /// Creates a comparator ordering values with a function.
+    ///
+    /// The function receives the field values, `Bson::Null` when the field is missing.
+    pub fn custom(compare: impl Fn(&Bson, &Bson) -> Ordering + Send + Sync + 'static) -> Self {
+        SortComparator::Custom(Arc::new(compare))
+    }
+
+    /// Compares two field values.
+    ///
+    /// # Returns
+    ///
+    /// The ordering of the values, or `None` if the comparator doesn't apply to them, such as
+    /// the built-in string comparators given a non-string value. Backends then fall back to
+    /// their default ordering.
+    pub fn compare(&self, a: &Bson, b: &Bson) -> Option<Ordering> {
+        match (self, a, b) {
+            (SortComparator::Custom(compare), a, b) => Some(compare(a, b)),
+            (SortComparator::CaseInsensitive, Bson::String(a), Bson::String(b)) => {
+                Some(a.to_lowercase().cmp(&b.to_lowercase()))
+            }
+            (SortComparator::Numeric, Bson::String(a), Bson::String(b)) => {
+                Some(compare_numeric(a, b))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for SortComparator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SortComparator::CaseInsensitive => f.write_str("CaseInsensitive"),
+            SortComparator::Numeric => f.write_str("Numeric"),
+            SortComparator::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+/// Compares strings, comparing runs of ASCII digits by their numeric value.
+fn compare_numeric(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+
+    loop {
+        let (Some(x), Some(y)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+
+        let ordering = if x.is_ascii_digit() && y.is_ascii_digit() {
+            let split = |s: &str| {
+                s.find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(s.len())
+            };
+            let (x, rest_a) = a.split_at(split(a));
+            let (y, rest_b) = b.split_at(split(b));
+            (a, b) = (rest_a, rest_b);
+
+            // Without leading zeros, the longer run is the larger number
+            let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+            x.len()
+                .cmp(&y.len())
+                .then_with(|| x.cmp(y))
+        } else {
+            (a, b) = (&a[x.len_utf8()..], &b[y.len_utf8()..]);
+            x.cmp(&y)
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// The sort comparators registered on a backend, by collection and field.
+#[derive(Debug, Clone, Default)]
+pub struct SortComparators {
+    comparators: HashMap<String, HashMap<String, SortComparator>>,
+}
+
+impl SortComparators {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the comparator of a field, replacing any previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `collection` - The collection holding the field
+    /// * `field` - The field, in dot notation for embedded fields
+    /// * `comparator` - The ordering of the field's values
+    pub fn insert(
+        &mut self,
+        collection: impl Into<String>,
+        field: impl Into<String>,
+        comparator: SortComparator,
+    ) {
+        self.comparators
+            .entry(collection.into())
+            .or_default()
+            .insert(field.into(), comparator);
+    }
+
+    /// Returns the comparator of a field, if one is registered.
+    pub fn get(&self, collection: &str, field: &str) -> Option<&SortComparator> {
+        self.comparators
+            .get(collection)
+            .and_then(|fields| fields.get(field))
+    }
+
+    /// Returns `true` if no comparator is registered.
+    pub fn is_empty(&self) -> bool {
+        self.comparators.is_empty()
+    }
+}
+
+type EvaluateFn = Arc<dyn Fn(&Bson, &Bson) -> DocumentStoreResult<bool> + Send + Sync>;
+type TranslateFn = Arc<dyn Fn(&Bson, &str) -> DocumentStoreResult<Document> + Send + Sync>;
+
+/// The handler of an application-defined filter operator, used by [`Expr::Custom`].
+///
+/// Handlers are registered by name on a backend builder, in a [`CustomOperators`] registry.
+/// Backends filtering in process, such as the in-memory store, evaluate operators on each
+/// document, while backends with a query language translate them into a native filter.
+/// Register the kind of handler the backend supports; queries using an operator registered
+/// with the other kind fail.
+///
+/// # Example
+///
+/// ```ignore
+/// let store = InMemoryStore::builder()
+///     .with_operator("ip_in_cidr", CustomOperator::evaluate(|document, payload| {
+///         Ok(in_cidr(lookup(document, payload)?, cidr(payload)?))
+///     }))
+///     .build()
+///     .await?;
+///
+/// let store = MongoDbStore::builder(uri, database)
+///     .with_operator("ip_in_cidr", CustomOperator::translate(|payload, prefix| {
+///         let (field, low, high) = cidr_range(payload)?;
+///         Ok(doc! { format!("{}{}", prefix, field): { "$gte": low, "$lte": high } })
+///     }))
+///     .build()
+///     .await?;
+/// ```
+#[derive(Clone)]
+pub enum CustomOperator {
+    /// Decides whether a document matches, given the document and the operator's payload.
+    ///
+    /// Within [`Filter::contains_matching`], the document is the array element.
+    Evaluate(EvaluateFn),
+    /// Translates the operator's payload into a filter of the backend's query language.
+    ///
+    /// The second argument is the prefix of stored field paths, which the filter must put in
+    /// front of every field it names. It is empty for MongoDB.
+    Translate(TranslateFn),
+}
+
+impl CustomOperator {
+    /// Creates an operator evaluated on each document.
+    pub fn evaluate(
+        evaluate: impl Fn(&Bson, &Bson) -> DocumentStoreResult<bool> + Send + Sync + 'static,
+    ) -> Self {
+        CustomOperator::Evaluate(Arc::new(evaluate))
+    }
+
+    /// Creates an operator translated into a native filter.
+    pub fn translate(
+        translate: impl Fn(&Bson, &str) -> DocumentStoreResult<Document> + Send + Sync + 'static,
+    ) -> Self {
+        CustomOperator::Translate(Arc::new(translate))
+    }
+}
+
+impl fmt::Debug for CustomOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CustomOperator::Evaluate(_) => f.write_str("Evaluate(..)"),
+            CustomOperator::Translate(_) => f.write_str("Translate(..)"),
+        }
+    }
+}
+
+/// The custom operators registered on a backend, by name.
+#[derive(Debug, Clone, Default)]
+pub struct CustomOperators {
+    operators: HashMap<String, CustomOperator>,
+}
+
+impl CustomOperators {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the handler of an operator, replacing any previous one.
+    pub fn insert(&mut self, name: impl Into<String>, operator: CustomOperator) {
+        self.operators
+            .insert(name.into(), operator);
+    }
+
+    /// Returns the handler of an operator, if one is registered.
+    pub fn get(&self, name: &str) -> Option<&CustomOperator> {
+        self.operators.get(name)
+    }
+
+    /// Evaluates an operator on a document.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Backend`] if the operator isn't registered or is only
+    /// translatable, or the error of its handler.
+    pub fn evaluate(
+        &self,
+        name: &str,
+        document: &Bson,
+        payload: &Bson,
+    ) -> DocumentStoreResult<bool> {
+        match self.get(name) {
+            Some(CustomOperator::Evaluate(evaluate)) => evaluate(document, payload),
+            Some(CustomOperator::Translate(_)) => Err(DocumentStoreError::Backend(format!(
+                "The custom operator {} can only be translated, not evaluated by this backend",
+                name
+            ))),
+            None => Err(unknown_operator(name)),
+        }
+    }
+
+    /// Translates an operator into a native filter, with field paths prefixed by `prefix`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Backend`] if the operator isn't registered or is only
+    /// evaluable, or the error of its handler.
+    pub fn translate(
+        &self,
+        name: &str,
+        payload: &Bson,
+        prefix: &str,
+    ) -> DocumentStoreResult<Document> {
+        match self.get(name) {
+            Some(CustomOperator::Translate(translate)) => translate(payload, prefix),
+            Some(CustomOperator::Evaluate(_)) => Err(DocumentStoreError::Backend(format!(
+                "The custom operator {} can only be evaluated in process, not translated by this backend",
+                name
+            ))),
+            None => Err(unknown_operator(name)),
+        }
+    }
+
+    /// Returns `true` if no operator is registered.
+    pub fn is_empty(&self) -> bool {
+        self.operators.is_empty()
+    }
+}
+
+fn unknown_operator(name: &str) -> DocumentStoreError {
+    DocumentStoreError::Backend(format!(
+        "No handler is registered for the custom operator {}",
+        name
+    ))
+}
+
 /// Field comparison operators for filter expressions.
 #[derive(Debug, Clone)]
 pub enum FieldOp {
@@ -77,10 +405,46 @@ pub enum FieldOp {
     StartsWith,
     /// String ends with value.
     EndsWith,
-    /// Array contains any of the values.
+    /// Field equals, or array field contains, any of the values.
+    ///
+    /// The value is an array of candidates; a scalar value is treated as a single candidate
+    /// (see [`FieldOp::set_values`]). A `null` candidate also matches missing fields.
     AnyOf,
-    /// Array contains none of the values.
+    /// Field equals, and array field contains, none of the values.
+    ///
+    /// The inverse of [`FieldOp::AnyOf`], so missing fields match unless a candidate is `null`.
     NoneOf,
+    /// String matches a regular expression.
+    Regex,
+}
+
+impl FieldOp {
+    /// Returns the candidates of an `AnyOf` or `NoneOf` comparison.
+    ///
+    /// An array value holds the candidates, while any other value is a single candidate.
+    /// Backends use this so both forms behave the same everywhere.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Backend`] if a candidate is a regular expression, which
+    /// backends would either match as a pattern or never match. Use [`Filter::matches`] instead.
+    pub fn set_values(value: &Bson) -> Result<&[Bson], DocumentStoreError> {
+        let values = match value {
+            Bson::Array(values) => values.as_slice(),
+            value => std::slice::from_ref(value),
+        };
+
+        if values
+            .iter()
+            .any(|value| matches!(value, Bson::RegularExpression(_)))
+        {
+            return Err(DocumentStoreError::Backend(
+                "AnyOf and NoneOf values can't be regular expressions".to_string(),
+            ));
+        }
+
+        Ok(values)
+    }
 }
 
 /// A filter expression for querying documents.
@@ -121,6 +485,45 @@ pub enum Expr {
         /// The value to compare against.
         value: Bson,
     },
+    /// Range check on a single field.
+    ///
+    /// Equivalent to the conjunction of the bound comparisons (see [`Expr::expand_range`]), but
+    /// kept as one node so backends can recognize it and use range indexes.
+    Range {
+        /// The field name to check.
+        field: String,
+        /// The lower bound of the range.
+        low: Bound<Bson>,
+        /// The upper bound of the range.
+        high: Bound<Bson>,
+    },
+    /// Evaluates the wrapped expression with case-insensitive string operators.
+    ///
+    /// Applies to `Contains`, `NotContains`, `StartsWith`, `EndsWith` and `Regex` comparisons
+    /// on string values anywhere in the wrapped expression. Equality and ordering comparisons
+    /// stay exact.
+    CaseInsensitive(Box<Expr>),
+    /// Checks that an array field holds an embedded document matching an expression.
+    ///
+    /// Field names in the wrapped expression are relative to the array element, and all of its
+    /// conditions must hold for the same element. Elements that aren't documents never match.
+    ElemMatch {
+        /// The array field to check.
+        field: String,
+        /// The expression an element must match.
+        expr: Box<Expr>,
+    },
+    /// An application-defined operator, evaluated by the handler registered under `name` on
+    /// the backend.
+    ///
+    /// The payload carries the operator's arguments, typically the field to check and the
+    /// values to check it against. See [`CustomOperator`].
+    Custom {
+        /// The name the operator's handler is registered under.
+        name: String,
+        /// The arguments of the operator.
+        payload: Bson,
+    },
 }
 
 impl Expr {
@@ -161,12 +564,326 @@ impl Expr {
     pub fn not(self) -> Self {
         Expr::Not(Box::new(self))
     }
+
+    /// Makes the string operators in this expression case-insensitive.
+    ///
+    /// See [`Expr::CaseInsensitive`] for the operators affected.
+    pub fn case_insensitive(self) -> Self {
+        Expr::CaseInsensitive(Box::new(self))
+    }
+
+    /// Expands a range check into the equivalent `And` of field comparisons.
+    ///
+    /// Included bounds become `Gte`/`Lte`, excluded bounds `Gt`/`Lt`, and unbounded ends are
+    /// dropped. A range without bounds expands to an empty `And`, which matches everything.
+    pub fn expand_range(field: &str, low: &Bound<Bson>, high: &Bound<Bson>) -> Self {
+        let low = match low {
+            Bound::Included(value) => Some((FieldOp::Gte, value)),
+            Bound::Excluded(value) => Some((FieldOp::Gt, value)),
+            Bound::Unbounded => None,
+        };
+        let high = match high {
+            Bound::Included(value) => Some((FieldOp::Lte, value)),
+            Bound::Excluded(value) => Some((FieldOp::Lt, value)),
+            Bound::Unbounded => None,
+        };
+
+        Expr::And(
+            low.into_iter()
+                .chain(high)
+                .map(|(op, value)| Expr::field(field.to_string(), op, value.clone()))
+                .collect(),
+        )
+    }
+
+    /// Returns a normalized form of this expression.
+    ///
+    /// Normalization rewrites logically equivalent expressions into the same shape:
+    ///
+    /// - nested `And`/`Or` nodes of the same kind are flattened
+    /// - children of `And`/`Or` are sorted and duplicates are removed
+    /// - single-child `And`/`Or` nodes are replaced by their child
+    /// - double negations are removed
+    pub fn normalize(self) -> Self {
+        match self {
+            Expr::And(exprs) => Self::normalize_list(exprs, true),
+            Expr::Or(exprs) => Self::normalize_list(exprs, false),
+            Expr::Not(expr) => match expr.normalize() {
+                Expr::Not(inner) => *inner,
+                other => Expr::Not(Box::new(other)),
+            },
+            Expr::CaseInsensitive(expr) => match expr.normalize() {
+                Expr::CaseInsensitive(inner) => Expr::CaseInsensitive(inner),
+                other => Expr::CaseInsensitive(Box::new(other)),
+            },
+            Expr::ElemMatch { field, expr } => {
+                Expr::ElemMatch { field, expr: Box::new(expr.normalize()) }
+            }
+            Expr::Field {
+                field,
+                op: op @ (FieldOp::AnyOf | FieldOp::NoneOf),
+                value,
+            } => Expr::Field { field, op, value: wrap_scalar(value) },
+            other => other,
+        }
+    }
+
+    fn normalize_list(exprs: Vec<Expr>, is_and: bool) -> Self {
+        let mut flattened = Vec::with_capacity(exprs.len());
+
+        for expr in exprs.into_iter().map(Expr::normalize) {
+            match (expr, is_and) {
+                (Expr::And(children), true) | (Expr::Or(children), false) => {
+                    flattened.extend(children)
+                }
+                (expr, _) => flattened.push(expr),
+            }
+        }
+
+        // Sorting by the canonical form makes the result independent of the original order
+        let mut keyed = flattened
+            .into_iter()
+            .map(|expr| (expr.canonical(), expr))
+            .collect::<Vec<_>>();
+        keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+        keyed.dedup_by(|(a, _), (b, _)| a == b);
+
+        let mut exprs = keyed
+            .into_iter()
+            .map(|(_, expr)| expr)
+            .collect::<Vec<_>>();
+
+        match (exprs.len(), is_and) {
+            (1, _) => exprs.remove(0),
+            (_, true) => Expr::And(exprs),
+            (_, false) => Expr::Or(exprs),
+        }
+    }
+
+    /// Renders this expression into a canonical, unambiguous string.
+    fn canonical(&self) -> String {
+        match self {
+            Expr::And(exprs) => format!(
+                "and({})",
+                exprs
+                    .iter()
+                    .map(Expr::canonical)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            Expr::Or(exprs) => format!(
+                "or({})",
+                exprs
+                    .iter()
+                    .map(Expr::canonical)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            Expr::Not(expr) => format!("not({})", expr.canonical()),
+            Expr::CaseInsensitive(expr) => format!("ci({})", expr.canonical()),
+            Expr::Exists(field, should_exist) => {
+                format!("exists({:?},{})", field, should_exist)
+            }
+            Expr::Field { field, op, value } => {
+                format!("{:?}({:?},{})", op, field, value)
+            }
+            Expr::Range { field, low, high } => {
+                format!("range({:?},{:?},{:?})", field, low, high)
+            }
+            Expr::ElemMatch { field, expr } => {
+                format!("elem({:?},{})", field, expr.canonical())
+            }
+            Expr::Custom { name, payload } => format!("custom({:?},{})", name, payload),
+        }
+    }
+
+    /// Renders this expression as indented text, one condition per line.
+    ///
+    /// The [`Display`](fmt::Display) implementation renders the same text on a single line.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let expr = Filter::eq("name", "Alice").and(Filter::gt("age", 18).or(Filter::eq("vip", true)));
+    ///
+    /// assert_eq!(expr.to_string(), r#"name == "Alice" AND (age > 18 OR vip == true)"#);
+    /// assert_eq!(
+    ///     expr.to_pretty_string(),
+    ///     "name == \"Alice\"\nAND (\n  age > 18\n  OR vip == true\n)"
+    /// );
+    /// ```
+    pub fn to_pretty_string(&self) -> String {
+        let mut output = String::new();
+        self.write_pretty(&mut output, 0);
+        output
+    }
+
+    fn write_pretty(&self, output: &mut String, depth: usize) {
+        let (exprs, keyword) = match self {
+            Expr::And(exprs) if exprs.len() > 1 => (exprs, "AND"),
+            Expr::Or(exprs) if exprs.len() > 1 => (exprs, "OR"),
+            Expr::Not(expr) | Expr::CaseInsensitive(expr) | Expr::ElemMatch { expr, .. }
+                if expr.is_compound() =>
+            {
+                match self {
+                    Expr::Not(_) => output.push_str("NOT"),
+                    Expr::CaseInsensitive(_) => output.push_str("CASE INSENSITIVE"),
+                    Expr::ElemMatch { field, .. } => {
+                        output.push_str(field);
+                        output.push_str(" CONTAINS MATCHING");
+                    }
+                    _ => unreachable!(),
+                }
+                output.push_str(" (\n");
+                push_indent(output, depth + 1);
+                expr.write_pretty(output, depth + 1);
+                output.push('\n');
+                push_indent(output, depth);
+                output.push(')');
+                return;
+            }
+            other => {
+                output.push_str(&other.to_string());
+                return;
+            }
+        };
+
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                output.push('\n');
+                push_indent(output, depth);
+                output.push_str(keyword);
+                output.push(' ');
+            }
+
+            if expr.is_compound() {
+                output.push_str("(\n");
+                push_indent(output, depth + 1);
+                expr.write_pretty(output, depth + 1);
+                output.push('\n');
+                push_indent(output, depth);
+                output.push(')');
+            } else {
+                expr.write_pretty(output, depth);
+            }
+        }
+    }
+
+    /// Returns whether this expression renders as several conditions joined by `AND`/`OR`.
+    fn is_compound(&self) -> bool {
+        matches!(self, Expr::And(exprs) | Expr::Or(exprs) if exprs.len() > 1)
+    }
+}
+
+/// Wraps a scalar value into a one element array, leaving arrays untouched.
+fn wrap_scalar(value: Bson) -> Bson {
+    match value {
+        Bson::Array(values) => Bson::Array(values),
+        value => Bson::Array(vec![value]),
+    }
+}
+
+fn push_indent(output: &mut String, depth: usize) {
+    output.extend(std::iter::repeat_n("  ", depth));
+}
+
+impl fmt::Display for SortDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SortDirection::Asc => f.write_str("ASC"),
+            SortDirection::Desc => f.write_str("DESC"),
+        }
+    }
+}
+
+impl fmt::Display for Sort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.field, self.direction)
+    }
+}
+
+impl fmt::Display for FieldOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FieldOp::Eq => "==",
+            FieldOp::Ne => "!=",
+            FieldOp::Gt => ">",
+            FieldOp::Gte => ">=",
+            FieldOp::Lt => "<",
+            FieldOp::Lte => "<=",
+            FieldOp::Contains => "CONTAINS",
+            FieldOp::NotContains => "NOT CONTAINS",
+            FieldOp::StartsWith => "STARTS WITH",
+            FieldOp::EndsWith => "ENDS WITH",
+            FieldOp::AnyOf => "IN",
+            FieldOp::NoneOf => "NOT IN",
+            FieldOp::Regex => "MATCHES",
+        })
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // An empty `And` matches everything and an empty `Or` matches nothing
+            Expr::And(exprs) if exprs.is_empty() => f.write_str("TRUE"),
+            Expr::Or(exprs) if exprs.is_empty() => f.write_str("FALSE"),
+            Expr::And(exprs) => write_joined(f, exprs, "AND"),
+            Expr::Or(exprs) => write_joined(f, exprs, "OR"),
+            Expr::Not(expr) => write!(f, "NOT ({})", expr),
+            Expr::CaseInsensitive(expr) => write!(f, "CASE INSENSITIVE ({})", expr),
+            Expr::ElemMatch { field, expr } => write!(f, "{} CONTAINS MATCHING ({})", field, expr),
+            Expr::Custom { name, payload } => write!(f, "{}({})", name, payload),
+            Expr::Exists(field, true) => write!(f, "{} EXISTS", field),
+            Expr::Exists(field, false) => write!(f, "{} NOT EXISTS", field),
+            Expr::Field { field, op, value } => write!(f, "{} {} {}", field, op, value),
+            Expr::Range { field, low, high } => {
+                let low = match low {
+                    Bound::Included(value) => Some(format!("{} <= ", value)),
+                    Bound::Excluded(value) => Some(format!("{} < ", value)),
+                    Bound::Unbounded => None,
+                };
+                let high = match high {
+                    Bound::Included(value) => Some(format!(" <= {}", value)),
+                    Bound::Excluded(value) => Some(format!(" < {}", value)),
+                    Bound::Unbounded => None,
+                };
+
+                match (low, high) {
+                    (None, None) => f.write_str("TRUE"),
+                    (low, high) => write!(
+                        f,
+                        "{}{}{}",
+                        low.unwrap_or_default(),
+                        field,
+                        high.unwrap_or_default()
+                    ),
+                }
+            }
+        }
+    }
+}
+
+fn write_joined(f: &mut fmt::Formatter<'_>, exprs: &[Expr], keyword: &str) -> fmt::Result {
+    for (i, expr) in exprs.iter().enumerate() {
+        if i > 0 {
+            write!(f, " {} ", keyword)?;
+        }
+
+        if expr.is_compound() {
+            write!(f, "({})", expr)?;
+        } else {
+            write!(f, "{}", expr)?;
+        }
+    }
+
+    Ok(())
 }
 
 /// A structured query for retrieving and filtering documents.
 ///
-/// This struct encapsulates filters, limits, offsets, and sort specifications
-/// for document queries. Use [`QueryBuilder`] for ergonomic construction.
+/// This struct encapsulates filters, limits, offsets, sort specifications and
+/// projections for document queries. Use [`QueryBuilder`] for ergonomic construction.
 ///
 /// # Example
 ///
@@ -188,8 +905,16 @@ pub struct Query {
     pub limit: Option<usize>,
     /// Number of documents to skip (for pagination).
     pub offset: Option<usize>,
-    /// Sort specification for results.
-    pub sort: Option<Sort>,
+    /// Sort keys for results, applied in order. Later keys break ties of earlier ones.
+    pub sort: Vec<Sort>,
+    /// Fields to include in returned documents. `None` returns whole documents.
+    pub projection: Option<Vec<String>>,
+    /// The longest the backend may take to run the query. `None` waits indefinitely.
+    ///
+    /// The [`Timeout`](crate::timeout::Timeout) layer fails queries running longer with
+    /// [`DocumentStoreError::Timeout`], and backends able to limit a query server-side,
+    /// such as MongoDB, are passed the timeout as well.
+    pub timeout: Option<Duration>,
 }
 
 impl Query {
@@ -199,7 +924,9 @@ impl Query {
             filter: None,
             limit: None,
             offset: None,
-            sort: None,
+            sort: Vec::new(),
+            projection: None,
+            timeout: None,
         }
     }
 
@@ -207,8 +934,102 @@ impl Query {
     pub fn builder() -> QueryBuilder {
         QueryBuilder::new()
     }
+
+    /// Returns a deterministic key identifying this query, suitable for keying result caches.
+    ///
+    /// The key is a hash of the [normalized](Expr::normalize) filter plus the sort, limit,
+    /// and offset, so queries that differ only in the order of `And`/`Or` operands share a
+    /// key. The hash is computed with a fixed algorithm (64-bit FNV-1a) and is stable across
+    /// processes and releases, so keys may be shared between replicas or persisted.
+    pub fn cache_key(&self) -> u64 {
+        let canonical = format!(
+            "filter={};sort={};limit={:?};offset={:?};projection={:?}",
+            self.filter
+                .clone()
+                .map(|filter| filter.normalize().canonical())
+                .unwrap_or_default(),
+            self.sort
+                .iter()
+                .map(|sort| format!("{:?}:{:?}", sort.field, sort.direction))
+                .collect::<Vec<_>>()
+                .join(","),
+            self.limit,
+            self.offset,
+            self.projection,
+        );
+
+        canonical
+            .bytes()
+            .fold(FNV_OFFSET_BASIS, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME))
+    }
+
+    /// Renders this query as indented text, one clause per line.
+    ///
+    /// The filter is rendered with [`Expr::to_pretty_string`]. The [`Display`](fmt::Display)
+    /// implementation renders the same clauses on a single line.
+    pub fn to_pretty_string(&self) -> String {
+        self.clauses(true).join("\n")
+    }
+
+    /// Renders the clauses of this query, in `SELECT`, `WHERE`, `ORDER BY`, `LIMIT`, `OFFSET`
+    /// order.
+    fn clauses(&self, pretty: bool) -> Vec<String> {
+        let mut clauses = Vec::new();
+
+        if let Some(projection) = &self.projection {
+            clauses.push(format!("SELECT {}", projection.join(", ")));
+        }
+
+        if let Some(filter) = &self.filter {
+            let filter = if pretty && filter.is_compound() {
+                format!(
+                    "(\n  {}\n)",
+                    filter
+                        .to_pretty_string()
+                        .replace('\n', "\n  ")
+                )
+            } else {
+                filter.to_string()
+            };
+            clauses.push(format!("WHERE {}", filter));
+        }
+
+        if !self.sort.is_empty() {
+            clauses.push(format!(
+                "ORDER BY {}",
+                self.sort
+                    .iter()
+                    .map(Sort::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+
+        if let Some(limit) = self.limit {
+            clauses.push(format!("LIMIT {}", limit));
+        }
+
+        if let Some(offset) = self.offset {
+            clauses.push(format!("OFFSET {}", offset));
+        }
+
+        if clauses.is_empty() {
+            clauses.push("ALL".to_string());
+        }
+
+        clauses
+    }
 }
 
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.clauses(false).join(" "))
+    }
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
 /// Helper struct for constructing filter expressions.
 ///
 /// Provides static methods to construct common filter expressions in a type-safe manner.
@@ -267,9 +1088,42 @@ impl Filter {
         Expr::field(field.into(), FieldOp::Lte, value.into())
     }
 
+    /// Creates an inclusive range filter expression.
+    ///
+    /// Matches documents where `low <= field <= high`.
+    pub fn between(field: impl Into<String>, low: impl Into<Bson>, high: impl Into<Bson>) -> Expr {
+        Filter::range(field, Bound::Included(low.into()), Bound::Included(high.into()))
+    }
+
+    /// Creates an exclusive range filter expression.
+    ///
+    /// Matches documents where `low < field < high`.
+    pub fn between_exclusive(
+        field: impl Into<String>,
+        low: impl Into<Bson>,
+        high: impl Into<Bson>,
+    ) -> Expr {
+        Filter::range(field, Bound::Excluded(low.into()), Bound::Excluded(high.into()))
+    }
+
+    /// Creates a range filter expression with arbitrary bounds.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// use std::ops::Bound;
+    ///
+    /// // 18 <= age < 65
+    /// let expr = Filter::range("age", Bound::Included(18.into()), Bound::Excluded(65.into()));
+    /// ```
+    pub fn range(field: impl Into<String>, low: Bound<Bson>, high: Bound<Bson>) -> Expr {
+        Expr::Range { field: field.into(), low, high }
+    }
+
     /// Creates a string prefix filter expression.
     ///
     /// Matches documents where the string field starts with the specified value.
+    /// String values are matched literally; use [`Filter::matches`] for patterns.
     pub fn starts_with(field: impl Into<String>, value: impl Into<Bson>) -> Expr {
         Expr::field(field.into(), FieldOp::StartsWith, value.into())
     }
@@ -277,6 +1131,7 @@ impl Filter {
     /// Creates a string suffix filter expression.
     ///
     /// Matches documents where the string field ends with the specified value.
+    /// String values are matched literally; use [`Filter::matches`] for patterns.
     pub fn ends_with(field: impl Into<String>, value: impl Into<Bson>) -> Expr {
         Expr::field(field.into(), FieldOp::EndsWith, value.into())
     }
@@ -284,6 +1139,7 @@ impl Filter {
     /// Creates a contains filter expression.
     ///
     /// Matches documents where the field (string or array) contains the specified value.
+    /// String values are matched literally; use [`Filter::matches`] for patterns.
     pub fn contains(field: impl Into<String>, value: impl Into<Bson>) -> Expr {
         Expr::field(field.into(), FieldOp::Contains, value.into())
     }
@@ -295,6 +1151,41 @@ impl Filter {
         Expr::field(field.into(), FieldOp::NotContains, value.into())
     }
 
+    /// Creates a regular expression filter expression.
+    ///
+    /// Matches documents where the string field matches the pattern anywhere in the value;
+    /// anchor it with `^` and `$` to match the whole value. Use the inline `(?i)` flag for
+    /// case-insensitive matching. Stick to syntax shared by MongoDB and the `regex` crate
+    /// (no lookaround or backreferences) so the pattern behaves the same on every backend.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let expr = Filter::matches("username", "^[a-z][a-z0-9_]{2,15}$");
+    /// let expr = Filter::matches("name", "(?i)smith");
+    /// ```
+    pub fn matches(field: impl Into<String>, pattern: impl Into<String>) -> Expr {
+        Expr::field(field.into(), FieldOp::Regex, Bson::String(pattern.into()))
+    }
+
+    /// Wraps an expression so its string operators ignore case.
+    ///
+    /// String operators are case-sensitive by default on every backend. Inside the wrapper,
+    /// `contains`, `not_contains`, `starts_with`, `ends_with` and `matches` compare without
+    /// regard to case; `eq` and the ordering comparisons are unaffected.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let expr = Filter::case_insensitive(Filter::or([
+    ///     Filter::starts_with("name", "al"),
+    ///     Filter::contains("email", "@example"),
+    /// ]));
+    /// ```
+    pub fn case_insensitive(expr: Expr) -> Expr {
+        expr.case_insensitive()
+    }
+
     /// Creates an existence filter expression.
     ///
     /// Matches documents where the field exists (is not null or missing).
@@ -325,16 +1216,321 @@ impl Filter {
 
     /// Creates an array membership filter expression.
     ///
-    /// Matches documents where the array field contains any of the specified values.
+    /// Matches documents where the field equals, or the array field contains, any of the
+    /// specified values. A single value is treated as a one element array.
     pub fn any_of(field: impl Into<String>, value: impl Into<Bson>) -> Expr {
-        Expr::field(field.into(), FieldOp::AnyOf, value.into())
+        Expr::field(field.into(), FieldOp::AnyOf, wrap_scalar(value.into()))
     }
 
     /// Creates an array exclusion filter expression.
     ///
-    /// Matches documents where the array field contains none of the specified values.
+    /// Matches documents where the field equals, and the array field contains, none of the
+    /// specified values. Documents missing the field match. A single value is treated as a one
+    /// element array.
     pub fn none_of(field: impl Into<String>, value: impl Into<Bson>) -> Expr {
-        Expr::field(field.into(), FieldOp::NoneOf, value.into())
+        Expr::field(field.into(), FieldOp::NoneOf, wrap_scalar(value.into()))
+    }
+
+    /// Creates an array element filter expression.
+    ///
+    /// Matches documents where the array field holds an embedded document matching `expr`,
+    /// whose field names are relative to the element. Unlike separate conditions on
+    /// `items.sku` and `items.qty`, all conditions must hold for the same element. A partial
+    /// document is matched by an `and` of equalities on its fields.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// // An order line for two or more of "A-100"
+    /// let expr = Filter::contains_matching(
+    ///     "items",
+    ///     Filter::eq("sku", "A-100").and(Filter::gte("qty", 2)),
+    /// );
+    /// ```
+    pub fn contains_matching(field: impl Into<String>, expr: Expr) -> Expr {
+        Expr::ElemMatch {
+            field: field.into(),
+            expr: Box::new(expr),
+        }
+    }
+
+    /// Creates an application-defined operator expression.
+    ///
+    /// Matches documents for which the [`CustomOperator`] registered under `name` on the
+    /// backend holds. Queries using an operator the backend has no handler for fail.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let expr = Filter::custom("ip_in_cidr", doc! { "field": "ip", "cidr": "10.0.0.0/8" });
+    /// ```
+    pub fn custom(name: impl Into<String>, payload: impl Into<Bson>) -> Expr {
+        Expr::Custom {
+            name: name.into(),
+            payload: payload.into(),
+        }
+    }
+}
+
+/// A typed reference to a document field, used to build filters checked at compile time.
+///
+/// `#[derive(Document)]` generates one constant per field on a companion `<Name>Fields` type,
+/// so a misspelled field name fails to compile instead of silently matching nothing, and
+/// comparison values must convert into the field's type. Values are serialized the same way
+/// as the document itself, so they always match the stored representation.
+///
+/// Optional fields are typed by their inner type. Fields convert into their name wherever a
+/// field name is expected, such as sort keys.
+///
+/// # Example
+///
+/// ```ignore
+/// let expr = UserFields::NAME.eq("Alice")?.and(UserFields::AGE.gt(18)?);
+/// let users = users.find().filter(expr).sort_desc(UserFields::CREATED_AT).await?;
+///
+/// // The same query through the fields of the collection's document type
+/// let users = users
+///     .find()
+///     .filter_by(|f| f.name().eq("Alice").and_filter(f.age().gt(18)))
+///     .sort_desc_by(|f| f.created_at())
+///     .await?;
+/// ```
+///
+/// # Errors
+///
+/// Filter methods taking values return [`DocumentStoreError::Serialization`] if a value
+/// can't be serialized to BSON, which only happens for values a document couldn't be stored
+/// with either (such as integers above `i64::MAX`).
+pub struct Field<T> {
+    name: &'static str,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Field<T> {
+    /// Creates a reference to the field stored under `name`.
+    pub const fn new(name: &'static str) -> Self {
+        Field { name, _marker: PhantomData }
+    }
+
+    /// Returns the name the field is stored under.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Matches documents where the field is present, including when it is null.
+    pub fn exists(&self) -> Expr {
+        Filter::exists(self.name)
+    }
+
+    /// Matches documents where the field is missing.
+    pub fn not_exists(&self) -> Expr {
+        Filter::not_exists(self.name)
+    }
+
+    fn value<V: Serialize>(value: V) -> DocumentStoreResult<Bson> {
+        Ok(serialize_to_bson(&value)?)
+    }
+}
+
+impl<T: Serialize> Field<T> {
+    /// Matches documents where the field equals the value.
+    pub fn eq(&self, value: impl Into<T>) -> DocumentStoreResult<Expr> {
+        Ok(Filter::eq(self.name, Self::value(value.into())?))
+    }
+
+    /// Matches documents where the field doesn't equal the value.
+    pub fn ne(&self, value: impl Into<T>) -> DocumentStoreResult<Expr> {
+        Ok(Filter::ne(self.name, Self::value(value.into())?))
+    }
+
+    /// Matches documents where the field is greater than the value.
+    pub fn gt(&self, value: impl Into<T>) -> DocumentStoreResult<Expr> {
+        Ok(Filter::gt(self.name, Self::value(value.into())?))
+    }
+
+    /// Matches documents where the field is greater than or equal to the value.
+    pub fn gte(&self, value: impl Into<T>) -> DocumentStoreResult<Expr> {
+        Ok(Filter::gte(self.name, Self::value(value.into())?))
+    }
+
+    /// Matches documents where the field is less than the value.
+    pub fn lt(&self, value: impl Into<T>) -> DocumentStoreResult<Expr> {
+        Ok(Filter::lt(self.name, Self::value(value.into())?))
+    }
+
+    /// Matches documents where the field is less than or equal to the value.
+    pub fn lte(&self, value: impl Into<T>) -> DocumentStoreResult<Expr> {
+        Ok(Filter::lte(self.name, Self::value(value.into())?))
+    }
+
+    /// Matches documents where `low <= field <= high`.
+    pub fn between(&self, low: impl Into<T>, high: impl Into<T>) -> DocumentStoreResult<Expr> {
+        Ok(Filter::between(self.name, Self::value(low.into())?, Self::value(high.into())?))
+    }
+
+    /// Matches documents where the field equals any of the values.
+    pub fn any_of<V: Into<T>>(
+        &self,
+        values: impl IntoIterator<Item = V>,
+    ) -> DocumentStoreResult<Expr> {
+        Ok(Filter::any_of(self.name, Self::values(values)?))
+    }
+
+    /// Matches documents where the field equals none of the values.
+    pub fn none_of<V: Into<T>>(
+        &self,
+        values: impl IntoIterator<Item = V>,
+    ) -> DocumentStoreResult<Expr> {
+        Ok(Filter::none_of(self.name, Self::values(values)?))
+    }
+
+    fn values<V: Into<T>>(values: impl IntoIterator<Item = V>) -> DocumentStoreResult<Bson> {
+        Ok(Bson::Array(
+            values
+                .into_iter()
+                .map(|value| Self::value(value.into()))
+                .collect::<DocumentStoreResult<_>>()?,
+        ))
+    }
+}
+
+impl Field<String> {
+    /// Matches documents where the string field starts with the value.
+    pub fn starts_with(&self, value: impl Into<String>) -> Expr {
+        Filter::starts_with(self.name, value.into())
+    }
+
+    /// Matches documents where the string field ends with the value.
+    pub fn ends_with(&self, value: impl Into<String>) -> Expr {
+        Filter::ends_with(self.name, value.into())
+    }
+
+    /// Matches documents where the string field contains the value.
+    pub fn contains(&self, value: impl Into<String>) -> Expr {
+        Filter::contains(self.name, value.into())
+    }
+
+    /// Matches documents where the string field doesn't contain the value.
+    pub fn not_contains(&self, value: impl Into<String>) -> Expr {
+        Filter::not_contains(self.name, value.into())
+    }
+
+    /// Matches documents where the string field matches a regular expression.
+    ///
+    /// See [`Filter::matches`] for the supported syntax.
+    pub fn matches(&self, pattern: impl Into<String>) -> Expr {
+        Filter::matches(self.name, pattern)
+    }
+}
+
+impl<T: Serialize> Field<Vec<T>> {
+    /// Matches documents where the array field contains the element.
+    pub fn contains(&self, element: impl Into<T>) -> DocumentStoreResult<Expr> {
+        Ok(Filter::contains(self.name, Self::value(element.into())?))
+    }
+
+    /// Matches documents where the array field doesn't contain the element.
+    pub fn not_contains(&self, element: impl Into<T>) -> DocumentStoreResult<Expr> {
+        Ok(Filter::not_contains(self.name, Self::value(element.into())?))
+    }
+
+    /// Matches documents where the array field contains any of the elements.
+    pub fn contains_any<V: Into<T>>(
+        &self,
+        elements: impl IntoIterator<Item = V>,
+    ) -> DocumentStoreResult<Expr> {
+        Ok(Filter::any_of(self.name, Field::<T>::values(elements)?))
+    }
+
+    /// Matches documents where the array field contains none of the elements.
+    pub fn contains_none<V: Into<T>>(
+        &self,
+        elements: impl IntoIterator<Item = V>,
+    ) -> DocumentStoreResult<Expr> {
+        Ok(Filter::none_of(self.name, Field::<T>::values(elements)?))
+    }
+
+    /// Matches documents where the array field holds an embedded document matching `expr`.
+    ///
+    /// See [`Filter::contains_matching`].
+    pub fn contains_matching(&self, expr: Expr) -> Expr {
+        Filter::contains_matching(self.name, expr)
+    }
+}
+
+impl<T> Clone for Field<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+/// A document type with typed field references, implemented by `#[derive(Document)]` for
+/// its `<Name>Fields` type.
+///
+/// The fields are passed to the closures of
+/// [`Find::filter_by`](crate::collection::Find::filter_by) and the sorts taking one.
+pub trait TypedFields {
+    /// The type holding a [`Field`] per stored field, as a constant and a method.
+    type Fields: Copy;
+
+    /// The field references.
+    const FIELDS: Self::Fields;
+}
+
+/// A filter expression, or the result of building one from typed [`Field`]s.
+///
+/// Filters on typed fields return a [`DocumentStoreResult`], whose own `and` and `or` keep
+/// only one of two results, so they're combined with [`and_filter`](Self::and_filter) and
+/// [`or_filter`](Self::or_filter) instead, which keep the first error.
+///
+/// # Example
+///
+/// ```ignore
+/// let expr = UserFields::NAME
+///     .eq("Alice")
+///     .and_filter(UserFields::AGE.gt(18))
+///     .or_filter(UserFields::ROLE.eq(Role::Admin))?;
+/// ```
+pub trait IntoFilter: Sized {
+    /// Returns the expression, or the error building it.
+    fn into_filter(self) -> DocumentStoreResult<Expr>;
+
+    /// Matches documents matching both filters.
+    fn and_filter(self, other: impl IntoFilter) -> DocumentStoreResult<Expr> {
+        Ok(self.into_filter()?.and(other.into_filter()?))
+    }
+
+    /// Matches documents matching either filter.
+    fn or_filter(self, other: impl IntoFilter) -> DocumentStoreResult<Expr> {
+        Ok(self.into_filter()?.or(other.into_filter()?))
+    }
+}
+
+impl IntoFilter for Expr {
+    fn into_filter(self) -> DocumentStoreResult<Expr> {
+        Ok(self)
+    }
+}
+
+impl IntoFilter for DocumentStoreResult<Expr> {
+    fn into_filter(self) -> DocumentStoreResult<Expr> {
+        self
+    }
+}
+
+impl<T> Copy for Field<T> {}
+
+impl<T> fmt::Debug for Field<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Field")
+            .field(&self.name)
+            .finish()
+    }
+}
+
+impl<T> From<Field<T>> for String {
+    fn from(field: Field<T>) -> Self {
+        field.name.to_string()
     }
 }
 
@@ -381,15 +1577,84 @@ impl QueryBuilder {
 
     /// Sets the sort specification for the query results.
     ///
+    /// This replaces any previously configured sort keys. Use [`then_sort`](Self::then_sort)
+    /// to add secondary keys.
+    ///
     /// # Arguments
     ///
     /// * `field` - The field name to sort by
     /// * `direction` - The sort direction (ascending or descending)
     pub fn sort(mut self, field: impl Into<String>, direction: SortDirection) -> Self {
-        self.query.sort = Some(Sort { field: field.into(), direction });
+        self.query.sort = vec![Sort { field: field.into(), direction }];
+        self
+    }
+
+    /// Adds a secondary sort key, used to order documents that compare equal on all
+    /// previous keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field name to sort by
+    /// * `direction` - The sort direction (ascending or descending)
+    pub fn then_sort(mut self, field: impl Into<String>, direction: SortDirection) -> Self {
+        self.query
+            .sort
+            .push(Sort { field: field.into(), direction });
+        self
+    }
+
+    /// Restricts returned documents to the given fields.
+    ///
+    /// Backends only fetch the listed fields, which reduces I/O for wide documents.
+    /// Field names may use dot notation to select fields of embedded documents.
+    /// Projected documents usually can't be deserialized into the full document type;
+    /// use `query_projected` on a collection to read them into a smaller struct.
+    ///
+    /// # Arguments
+    ///
+    /// * `fields` - The field names to include
+    pub fn project<I, S>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.query.projection = Some(
+            fields
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+        );
         self
     }
 
+    /// Limits how long the backend may take to run the query.
+    ///
+    /// The timeout is enforced by a store built [`with_timeout`](crate::store::DocumentStore::with_timeout)
+    /// and by backends limiting queries themselves, such as MongoDB; other stores ignore it.
+    /// See the [`timeout`](crate::timeout) module.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The longest the query may run
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.query.timeout = Some(timeout);
+        self
+    }
+
+    /// Limits the query to run before `deadline`, such as the deadline of the request it
+    /// serves.
+    ///
+    /// The deadline is turned into a [`timeout`](Self::timeout) of the time remaining when
+    /// this is called, so build the query just before running it. A deadline already passed
+    /// gives a zero timeout, which fails the query at once where the timeout is enforced.
+    ///
+    /// # Arguments
+    ///
+    /// * `deadline` - The instant the query must finish by
+    pub fn deadline(self, deadline: Instant) -> Self {
+        self.timeout(deadline.saturating_duration_since(Instant::now()))
+    }
+
     /// Builds and returns the final query.
     pub fn build(self) -> Query {
         self.query
@@ -403,6 +1668,24 @@ pub trait QueryVisitor {
     fn visit_and(&mut self, exprs: &[Expr]) -> Result<Self::Output, Self::Error>;
     fn visit_or(&mut self, exprs: &[Expr]) -> Result<Self::Output, Self::Error>;
     fn visit_not(&mut self, expr: &Expr) -> Result<Self::Output, Self::Error>;
+
+    /// Visits an expression whose string operators must ignore case.
+    ///
+    /// There's no default implementation: silently falling back to case-sensitive matching
+    /// would make results differ between backends.
+    fn visit_case_insensitive(&mut self, expr: &Expr) -> Result<Self::Output, Self::Error>;
+
+    /// Visits a check for an array element matching an expression.
+    ///
+    /// The field names in `expr` are relative to the array element.
+    fn visit_elem_match(&mut self, field: &str, expr: &Expr) -> Result<Self::Output, Self::Error>;
+
+    /// Visits an application-defined operator.
+    ///
+    /// There's no default implementation: backends look the operator up in their
+    /// [`CustomOperators`] and must fail when it isn't registered, rather than match
+    /// everything or nothing.
+    fn visit_custom(&mut self, name: &str, payload: &Bson) -> Result<Self::Output, Self::Error>;
     fn visit_exists(
         &mut self,
         field: &str,
@@ -415,13 +1698,66 @@ pub trait QueryVisitor {
         value: &Bson,
     ) -> Result<Self::Output, Self::Error>;
 
+    /// Visits a range check.
+    ///
+    /// The default implementation visits the expanded comparisons from
+    /// [`Expr::expand_range`]. Backends that can evaluate ranges natively should override it.
+    fn visit_range(
+        &mut self,
+        field: &str,
+        low: &Bound<Bson>,
+        high: &Bound<Bson>,
+    ) -> Result<Self::Output, Self::Error> {
+        self.visit_expr(&Expr::expand_range(field, low, high))
+    }
+
     fn visit_expr(&mut self, expr: &Expr) -> Result<Self::Output, Self::Error> {
         match expr {
             Expr::And(exprs) => self.visit_and(exprs),
             Expr::Or(exprs) => self.visit_or(exprs),
             Expr::Not(expr) => self.visit_not(expr),
+            Expr::CaseInsensitive(expr) => self.visit_case_insensitive(expr),
             Expr::Exists(field, should_exist) => self.visit_exists(field, *should_exist),
             Expr::Field { field, op, value } => self.visit_field(field, op, value),
+            Expr::Range { field, low, high } => self.visit_range(field, low, high),
+            Expr::ElemMatch { field, expr } => self.visit_elem_match(field, expr),
+            Expr::Custom { name, payload } => self.visit_custom(name, payload),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::Bson;
+
+    use super::{Expr, Field, FieldOp};
+    use crate::error::DocumentStoreError;
+
+    const COUNT: Field<u64> = Field::new("count");
+    const COUNTS: Field<Vec<u64>> = Field::new("counts");
+
+    #[test]
+    fn values_bson_can_represent_build_filters() {
+        let expr = COUNT.eq(5u64).unwrap();
+
+        assert!(
+            matches!(&expr, Expr::Field { field, op: FieldOp::Eq, value: Bson::Int64(5) } if field == "count"),
+            "{expr:?}"
+        );
+    }
+
+    #[test]
+    fn values_bson_cant_represent_fail_instead_of_panicking() {
+        let results = [
+            COUNT.eq(u64::MAX),
+            COUNT.between(0u64, u64::MAX),
+            COUNT.any_of([1u64, u64::MAX]),
+            COUNTS.contains(u64::MAX),
+            COUNTS.contains_none([u64::MAX]),
+        ];
+
+        for result in results {
+            assert!(matches!(result, Err(DocumentStoreError::Serialization(_))), "{result:?}");
         }
     }
 }
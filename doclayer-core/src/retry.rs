@@ -0,0 +1,179 @@
+//! Retrying operations that fail with transient errors.
+//!
+//! A [`RetryStore`] retries backend calls failing with a
+//! [retryable](crate::error::DocumentStoreError::is_retryable) error, such as a dropped connection or a
+//! failover, waiting longer before each attempt. Other errors are returned at once.
+//!
+//! The wait doubles from the initial backoff up to a maximum. With jitter, which is on by
+//! default, each wait is randomized between half and all of its nominal length, so clients
+//! failing together don't retry in lockstep.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::retry::Retry;
+//! use std::time::Duration;
+//!
+//! let store = DocumentStore::new(backend).with_retry(3);
+//!
+//! // Or with a custom policy
+//! let retry = Retry::new(5)
+//!     .with_backoff(Duration::from_millis(50))
+//!     .with_max_backoff(Duration::from_secs(2));
+//! let store = DocumentStore::new(retry.wrap(backend));
+//! ```
+//!
+//! `update_by_query` is never retried: its increments and pushes would apply twice if the
+//! failed attempt had reached the backend. Neither are `find_one_and_update` and
+//! `find_one_and_delete`, which would move on to another document, nor the conditional
+//! writes, such as `update_documents_if_version`, which would fail on the version or revision
+//! changed by their own earlier attempt, or inserts with `InsertPolicy::ErrorOnConflict`, which
+//! would fail on the documents inserted by it. Every other operation reads, or writes whole
+//! documents, so repeating it leaves the same stored state, although a repeated delete may
+//! count fewer documents deleted.
+//!
+//! Backoffs wait with `tokio::time::sleep`, so the store must run on a tokio runtime. In the
+//! browser, where there is no tokio timer, attempts follow each other without waiting.
+//!
+//! Stacked outside a [`Timeout`](crate::timeout::Timeout) layer, each attempt gets the
+//! full timeout; stacked inside it, the timeout covers every attempt together.
+
+use async_trait::async_trait;
+use std::{
+    hash::{BuildHasher, RandomState},
+    time::Duration,
+};
+
+use crate::{
+    backend::{InsertPolicy, Layered, Next, Operation, Outcome, StoreBackend, StoreLayer},
+    error::DocumentStoreResult,
+};
+
+/// A backend whose failed operations are retried by a [`Retry`] layer.
+pub type RetryStore<B> = Layered<Retry, B>;
+
+/// A [`StoreLayer`] retrying operations that fail with a retryable error, with exponential
+/// backoff.
+#[derive(Debug, Clone, Copy)]
+pub struct Retry {
+    max_attempts: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+impl Retry {
+    /// Creates a layer making at most `max_attempts` attempts per operation, the first
+    /// included, waiting 100ms before the first retry and at most 5s before any.
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            jitter: true,
+        }
+    }
+
+    /// Waits `backoff` before the first retry, doubling it for each retry after.
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Caps the wait before any retry at `max_backoff`.
+    pub fn with_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Turns randomizing the waits on or off. Without jitter, every wait is exactly its
+    /// nominal length.
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Returns the maximum number of attempts per operation.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Wraps a backend so its failed operations are retried.
+    pub fn wrap<B: StoreBackend + 'static>(self, backend: B) -> RetryStore<B> {
+        Layered::new(self, backend)
+    }
+
+    /// Returns the nominal wait before the given retry, the first being `1`.
+    fn delay(&self, retry: u32) -> Duration {
+        let delay = self
+            .backoff
+            .saturating_mul(2u32.saturating_pow(retry - 1))
+            .min(self.max_backoff);
+
+        match self.jitter {
+            true => delay.mul_f64(0.5 + random_fraction() / 2.0),
+            false => delay,
+        }
+    }
+}
+
+/// Returns whether an operation leaves the same result when applied twice.
+fn is_idempotent(operation: &Operation) -> bool {
+    !matches!(
+        operation,
+        Operation::InsertDocuments {
+            policy: InsertPolicy::ErrorOnConflict,
+            ..
+        } | Operation::UpdateByQuery { .. }
+            | Operation::FindOneAndUpdate { .. }
+            | Operation::FindOneAndDelete { .. }
+            | Operation::UpdateDocumentsIfVersion { .. }
+            | Operation::UpdateDocumentsIfMatch { .. }
+            | Operation::DeleteDocumentsIfMatch { .. }
+    )
+}
+
+/// Returns a random number in `0.0..1.0`.
+fn random_fraction() -> f64 {
+    // Each `RandomState` is seeded differently, which is all the randomness jitter needs
+    let bits = RandomState::new().hash_one(0u8);
+
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[async_trait]
+impl StoreLayer for Retry {
+    async fn call(&self, operation: Operation, next: Next<'_>) -> DocumentStoreResult<Outcome> {
+        if !is_idempotent(&operation) {
+            return next.run(operation).await;
+        }
+
+        // Every attempt but the last runs a copy, so the last can take the operation itself
+        let mut attempt = 1;
+        while attempt < self.max_attempts {
+            match next.run(operation.clone()).await {
+                Err(error) if error.is_retryable() => {
+                    sleep(self.delay(attempt)).await;
+                    attempt += 1;
+                }
+                outcome => return outcome,
+            }
+        }
+
+        next.run(operation).await
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await;
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn sleep(_duration: Duration) {}
@@ -0,0 +1,566 @@
+//! Pre-aggregated rollups of a collection.
+//!
+//! A [`Rollup`] maintains counter and sum documents (for example per-day order totals) in a
+//! separate collection, one document per group. Rather than aggregating the source collection
+//! on every read, the rollup is updated incrementally through [`RollupHost::apply_rollup`] as
+//! source documents change, and can be recomputed from scratch with
+//! [`RollupHost::rebuild_rollup`] to correct drift.
+//!
+//! Each group document stores the group key under [`ROLLUP_KEY_FIELD`] and one field per
+//! measure. Changes are applied as increments, so concurrent changes to the same group don't
+//! overwrite each other. A change touching two groups (a document moving from one day to
+//! another) updates them one after the other, not atomically.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::rollup::{Rollup, RollupChange, RollupHost};
+//!
+//! let daily_totals = Rollup::<Order>::builder()
+//!     .group_by(|order| order.created_at.format("%Y-%m-%d").to_string())
+//!     .filter(|order| order.status == "paid")
+//!     .count("orders")
+//!     .sum("revenue_cents", |order| order.total_cents)
+//!     .to_collection("daily_order_totals")
+//!     .build();
+//!
+//! orders.insert(vec![order.clone()]).await?;
+//! store.apply_rollup(&daily_totals, RollupChange::Inserted(&order)).await?;
+//!
+//! let day = store.rollup_group(&daily_totals, "2024-05-01").await?;
+//! ```
+
+use async_trait::async_trait;
+use bson::{Bson, Uuid};
+use futures::TryStreamExt;
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use crate::{
+    document::Document,
+    error::{DocumentStoreError, DocumentStoreResult},
+    query::{Filter, Query},
+    store::{AsDynDocumentStore, DynDocumentStoreRef},
+    update::Update,
+};
+
+/// The field of a rollup group document holding the group key.
+pub const ROLLUP_KEY_FIELD: &str = "key";
+
+/// A numeric contribution of a document to a rollup measure.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RollupValue {
+    /// An integer contribution, stored as a 64-bit integer.
+    Int(i64),
+    /// A floating point contribution, stored as a double.
+    Float(f64),
+}
+
+impl RollupValue {
+    fn add(self, other: RollupValue) -> RollupValue {
+        match (self, other) {
+            (RollupValue::Int(a), RollupValue::Int(b)) => RollupValue::Int(a.saturating_add(b)),
+            (a, b) => RollupValue::Float(a.as_f64() + b.as_f64()),
+        }
+    }
+
+    fn negate(self) -> RollupValue {
+        match self {
+            RollupValue::Int(value) => RollupValue::Int(value.saturating_neg()),
+            RollupValue::Float(value) => RollupValue::Float(-value),
+        }
+    }
+
+    fn is_zero(self) -> bool {
+        match self {
+            RollupValue::Int(value) => value == 0,
+            RollupValue::Float(value) => value == 0.0,
+        }
+    }
+
+    fn as_f64(self) -> f64 {
+        match self {
+            RollupValue::Int(value) => value as f64,
+            RollupValue::Float(value) => value,
+        }
+    }
+}
+
+impl From<i32> for RollupValue {
+    fn from(value: i32) -> Self {
+        RollupValue::Int(value.into())
+    }
+}
+
+impl From<u32> for RollupValue {
+    fn from(value: u32) -> Self {
+        RollupValue::Int(value.into())
+    }
+}
+
+impl From<i64> for RollupValue {
+    fn from(value: i64) -> Self {
+        RollupValue::Int(value)
+    }
+}
+
+impl From<f32> for RollupValue {
+    fn from(value: f32) -> Self {
+        RollupValue::Float(value.into())
+    }
+}
+
+impl From<f64> for RollupValue {
+    fn from(value: f64) -> Self {
+        RollupValue::Float(value)
+    }
+}
+
+impl From<RollupValue> for Bson {
+    fn from(value: RollupValue) -> Self {
+        match value {
+            RollupValue::Int(value) => Bson::Int64(value),
+            RollupValue::Float(value) => Bson::Double(value),
+        }
+    }
+}
+
+type GroupFn<D> = Arc<dyn Fn(&D) -> Bson + Send + Sync>;
+type PredicateFn<D> = Arc<dyn Fn(&D) -> bool + Send + Sync>;
+type MeasureFn<D> = Arc<dyn Fn(&D) -> RollupValue + Send + Sync>;
+
+/// The per-measure deltas of one group.
+type GroupDelta = (Bson, Vec<RollupValue>);
+
+/// A change of a source document, applied to a rollup with [`RollupHost::apply_rollup`].
+#[derive(Debug)]
+pub enum RollupChange<'a, D> {
+    /// The document was inserted.
+    Inserted(&'a D),
+    /// The document was replaced.
+    Updated {
+        /// The document before the change.
+        before: &'a D,
+        /// The document after the change.
+        after: &'a D,
+    },
+    /// The document was deleted.
+    Deleted(&'a D),
+}
+
+impl<D> Clone for RollupChange<'_, D> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<D> Copy for RollupChange<'_, D> {}
+
+/// Counter and sum documents maintained for the documents of `D`'s collection.
+///
+/// Use [`RollupBuilder`] for ergonomic construction.
+pub struct Rollup<D> {
+    collection: String,
+    group_by: GroupFn<D>,
+    filter: Option<PredicateFn<D>>,
+    measures: Vec<(String, MeasureFn<D>)>,
+}
+
+impl<D: Document> Rollup<D> {
+    /// Creates a new rollup builder for the document type `D`.
+    ///
+    /// Without further configuration, the rollup has a single group covering every document
+    /// and is stored in a collection of the same name as `D`'s suffixed with `_rollup`.
+    pub fn builder() -> RollupBuilder<D> {
+        RollupBuilder::new()
+    }
+
+    /// Returns the name of the collection holding the group documents.
+    pub fn collection(&self) -> &str {
+        &self.collection
+    }
+
+    /// Returns the names of the measure fields, in the order they were added.
+    pub fn measures(&self) -> impl Iterator<Item = &str> {
+        self.measures
+            .iter()
+            .map(|(field, _)| field.as_str())
+    }
+
+    /// Returns the ID of the document holding a group.
+    ///
+    /// IDs are derived from the rollup's collection and the group key, so every writer
+    /// addresses the same document without looking it up first.
+    pub fn group_id(&self, key: &Bson) -> Uuid {
+        let mut bytes = fnv1a_128(format!("{}\0{}", self.collection, key).as_bytes()).to_be_bytes();
+        // Mark the ID as a custom (version 8) UUID of the RFC 4122 variant
+        bytes[6] = (bytes[6] & 0x0f) | 0x80;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+        Uuid::from_bytes(bytes)
+    }
+
+    /// Returns the group and measure values a document contributes, if it passes the filter.
+    fn contribution(&self, document: &D) -> Option<GroupDelta> {
+        if let Some(filter) = &self.filter
+            && !filter(document)
+        {
+            return None;
+        }
+
+        Some((
+            (self.group_by)(document),
+            self.measures
+                .iter()
+                .map(|(_, measure)| measure(document))
+                .collect(),
+        ))
+    }
+
+    /// Computes the per-group deltas of a change, merging those that hit the same group.
+    fn deltas(&self, change: RollupChange<'_, D>) -> Vec<GroupDelta> {
+        let (removed, added) = match change {
+            RollupChange::Inserted(document) => (None, self.contribution(document)),
+            RollupChange::Updated { before, after } => {
+                (self.contribution(before), self.contribution(after))
+            }
+            RollupChange::Deleted(document) => (self.contribution(document), None),
+        };
+
+        let mut deltas: Vec<GroupDelta> = Vec::with_capacity(2);
+        let removed = removed.map(|(key, values)| {
+            (
+                key,
+                values
+                    .into_iter()
+                    .map(RollupValue::negate)
+                    .collect(),
+            )
+        });
+
+        for (key, values) in removed.into_iter().chain(added) {
+            match deltas
+                .iter_mut()
+                .find(|(existing, _)| *existing == key)
+            {
+                Some((_, existing)) => {
+                    for (total, value) in existing.iter_mut().zip(values) {
+                        *total = total.add(value);
+                    }
+                }
+                None => deltas.push((key, values)),
+            }
+        }
+
+        deltas.retain(|(_, values)| {
+            values
+                .iter()
+                .any(|value| !value.is_zero())
+        });
+        deltas
+    }
+
+    /// Builds the stored document of a group.
+    fn group_document(&self, key: Bson, values: &[RollupValue]) -> Bson {
+        let mut document = bson::Document::new();
+        document.insert(ROLLUP_KEY_FIELD, key);
+        for ((field, _), value) in self.measures.iter().zip(values) {
+            document.insert(field.clone(), Bson::from(*value));
+        }
+
+        Bson::Document(document)
+    }
+}
+
+impl<D> Clone for Rollup<D> {
+    fn clone(&self) -> Self {
+        Rollup {
+            collection: self.collection.clone(),
+            group_by: self.group_by.clone(),
+            filter: self.filter.clone(),
+            measures: self.measures.clone(),
+        }
+    }
+}
+
+impl<D> fmt::Debug for Rollup<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Rollup")
+            .field("collection", &self.collection)
+            .field("filtered", &self.filter.is_some())
+            .field(
+                "measures",
+                &self
+                    .measures
+                    .iter()
+                    .map(|(field, _)| field)
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+/// Builder for constructing [`Rollup`] instances with a fluent API.
+pub struct RollupBuilder<D> {
+    rollup: Rollup<D>,
+}
+
+impl<D: Document> RollupBuilder<D> {
+    /// Creates a new rollup builder for the document type `D`.
+    pub fn new() -> Self {
+        RollupBuilder {
+            rollup: Rollup {
+                collection: format!("{}_rollup", D::collection_name()),
+                group_by: Arc::new(|_| Bson::Null),
+                filter: None,
+                measures: Vec::new(),
+            },
+        }
+    }
+
+    /// Groups documents by the key computed from each document.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Computes a document's group key, such as its creation day
+    pub fn group_by<K: Into<Bson>>(
+        mut self,
+        key: impl Fn(&D) -> K + Send + Sync + 'static,
+    ) -> Self {
+        self.rollup.group_by = Arc::new(move |document| key(document).into());
+        self
+    }
+
+    /// Only counts documents matching a predicate.
+    ///
+    /// A document updated so that it no longer matches is removed from its group.
+    pub fn filter(mut self, predicate: impl Fn(&D) -> bool + Send + Sync + 'static) -> Self {
+        self.rollup.filter = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Counts the documents of each group into `field`.
+    pub fn count(self, field: impl Into<String>) -> Self {
+        self.sum(field, |_| 1i64)
+    }
+
+    /// Sums a value computed from each document into `field`.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field of the group document holding the sum
+    /// * `value` - Computes a document's contribution
+    pub fn sum<N: Into<RollupValue>>(
+        mut self,
+        field: impl Into<String>,
+        value: impl Fn(&D) -> N + Send + Sync + 'static,
+    ) -> Self {
+        self.rollup
+            .measures
+            .push((field.into(), Arc::new(move |document| value(document).into())));
+        self
+    }
+
+    /// Stores the group documents in the given collection.
+    pub fn to_collection(mut self, collection: impl Into<String>) -> Self {
+        self.rollup.collection = collection.into();
+        self
+    }
+
+    /// Builds and returns the final rollup.
+    pub fn build(self) -> Rollup<D> {
+        self.rollup
+    }
+}
+
+impl<D: Document> Default for RollupBuilder<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Extension trait for maintaining rollups in a store.
+///
+/// This trait is automatically implemented for every store type.
+#[async_trait]
+pub trait RollupHost: Send + Sync {
+    /// Updates a rollup's groups for a change of a source document.
+    ///
+    /// Call this after the change is written. Group documents are created on first use.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a group document cannot be written. Groups updated before the
+    /// failure keep their new values; [`rebuild_rollup`](Self::rebuild_rollup) corrects them.
+    async fn apply_rollup<D: Document>(
+        &self,
+        rollup: &Rollup<D>,
+        change: RollupChange<'_, D>,
+    ) -> DocumentStoreResult<()>;
+
+    /// Recomputes every group of a rollup from the source collection.
+    ///
+    /// Groups that no longer have documents are removed.
+    ///
+    /// # Returns
+    ///
+    /// The number of groups.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the source cannot be read or the groups cannot be written.
+    async fn rebuild_rollup<D: Document>(&self, rollup: &Rollup<D>) -> DocumentStoreResult<usize>;
+
+    /// Returns the stored document of a group, if it exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the group document cannot be read.
+    async fn rollup_group<D: Document, K: Into<Bson> + Send>(
+        &self,
+        rollup: &Rollup<D>,
+        key: K,
+    ) -> DocumentStoreResult<Option<Bson>>;
+}
+
+#[async_trait]
+impl<T> RollupHost for T
+where
+    T: AsDynDocumentStore + Send + Sync,
+{
+    async fn apply_rollup<D: Document>(
+        &self,
+        rollup: &Rollup<D>,
+        change: RollupChange<'_, D>,
+    ) -> DocumentStoreResult<()> {
+        let store = self.as_dyn();
+
+        for (key, values) in rollup.deltas(change) {
+            apply_group(&store, rollup, key, values).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn rebuild_rollup<D: Document>(&self, rollup: &Rollup<D>) -> DocumentStoreResult<usize> {
+        let store = self.as_dyn();
+        let groups = store
+            .typed_collection::<D>()
+            .query_stream(Query::default())
+            .await?
+            .try_fold(HashMap::<Uuid, GroupDelta>::new(), async |mut groups, document| {
+                if let Some((key, values)) = rollup.contribution(&document) {
+                    match groups.get_mut(&rollup.group_id(&key)) {
+                        Some((_, totals)) => {
+                            for (total, value) in totals.iter_mut().zip(values) {
+                                *total = total.add(value);
+                            }
+                        }
+                        None => {
+                            groups.insert(rollup.group_id(&key), (key, values));
+                        }
+                    }
+                }
+                Ok(groups)
+            })
+            .await?;
+
+        store
+            .ensure_collection(&rollup.collection)
+            .await?;
+
+        let target = store.collection(&rollup.collection);
+        let stale = target
+            .distinct(ROLLUP_KEY_FIELD, None)
+            .await?
+            .into_iter()
+            .map(|key| rollup.group_id(&key))
+            .filter(|id| !groups.contains_key(id))
+            .collect::<Vec<_>>();
+        let count = groups.len();
+
+        target
+            .upsert(
+                groups
+                    .into_iter()
+                    .map(|(id, (key, values))| (id, rollup.group_document(key, &values)))
+                    .collect(),
+            )
+            .await?;
+
+        if !stale.is_empty() {
+            target.delete(stale).await?;
+        }
+
+        Ok(count)
+    }
+
+    async fn rollup_group<D: Document, K: Into<Bson> + Send>(
+        &self,
+        rollup: &Rollup<D>,
+        key: K,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        self.as_dyn()
+            .collection(&rollup.collection)
+            .get_one(rollup.group_id(&key.into()))
+            .await
+    }
+}
+
+/// Adds the deltas of one group to its document, creating the document if needed.
+async fn apply_group<D: Document>(
+    store: &DynDocumentStoreRef<'_>,
+    rollup: &Rollup<D>,
+    key: Bson,
+    values: Vec<RollupValue>,
+) -> DocumentStoreResult<()> {
+    let target = store.collection(&rollup.collection);
+    let filter = Filter::eq(ROLLUP_KEY_FIELD, key.clone());
+    let update = rollup
+        .measures
+        .iter()
+        .zip(&values)
+        .fold(Update::builder(), |update, ((field, _), value)| {
+            update.inc(field.clone(), Bson::from(*value))
+        })
+        .build();
+
+    let matched = match target
+        .update_where(filter.clone(), update.clone())
+        .await
+    {
+        Ok(report) => report.matched,
+        Err(DocumentStoreError::CollectionNotFound(_)) => 0,
+        Err(e) => return Err(e),
+    };
+    if matched > 0 {
+        return Ok(());
+    }
+
+    store
+        .ensure_collection(&rollup.collection)
+        .await?;
+
+    match target
+        .insert(vec![(rollup.group_id(&key), rollup.group_document(key, &values))])
+        .await
+    {
+        // Another writer created the group in the meantime
+        Err(DocumentStoreError::DocumentAlreadyExists(..)) => {
+            target
+                .update_where(filter, update)
+                .await?;
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Hashes bytes with the 128-bit FNV-1a function, which is stable across platforms and releases.
+fn fnv1a_128(bytes: &[u8]) -> u128 {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    bytes
+        .iter()
+        .fold(OFFSET_BASIS, |hash, byte| (hash ^ u128::from(*byte)).wrapping_mul(PRIME))
+}
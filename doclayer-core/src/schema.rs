@@ -0,0 +1,1392 @@
+//! Validating the documents written to a collection against a JSON Schema.
+//!
+//! A [`SchemaRegistry`] maps collection names to [`JsonSchema`]s. Wrapping a backend in a
+//! [`SchemaStore`], or a store with
+//! [`DocumentStore::with_schemas`](crate::store::DocumentStore::with_schemas), validates every
+//! document inserted, updated or upserted into a collection with a schema before it's written,
+//! whether through an untyped [`Collection`](crate::collection::Collection) or a typed one.
+//! Violations fail the write with a [`DocumentStoreError::Validation`] listing the invalid
+//! fields of every document.
+//!
+//! Schemas describe documents as JSON, so UUIDs, datetimes and other BSON values without a
+//! JSON counterpart are strings to the `type` keyword, whatever the encoding of the store.
+//! The MongoDB `bsonType` keyword checks the stored BSON type instead.
+//!
+//! # Supported keywords
+//!
+//! `type`, `bsonType`, `enum`, `const`, `properties`, `required`, `additionalProperties`,
+//! `minProperties`, `maxProperties`, `items`, `minItems`, `maxItems`, `uniqueItems`,
+//! `minimum`, `maximum`, `exclusiveMinimum`, `exclusiveMaximum`, `multipleOf`, `minLength`,
+//! `maxLength`, `allOf`, `anyOf`, `oneOf`, `not` and `$ref` to a location in the same schema,
+//! such as `#/definitions/Address`. Other keywords, such as `format` and `pattern`, are
+//! accepted and ignored.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::schema::{JsonSchema, SchemaRegistry};
+//! use serde_json::json;
+//!
+//! let schemas = SchemaRegistry::new();
+//! schemas.register(
+//!     "users",
+//!     JsonSchema::new(json!({
+//!         "type": "object",
+//!         "required": ["name", "email"],
+//!         "properties": {
+//!             "name": { "type": "string", "minLength": 1 },
+//!             "email": { "type": "string" },
+//!             "age": { "type": "integer", "minimum": 0 }
+//!         }
+//!     }))?,
+//! );
+//!
+//! let store = DocumentStore::new(backend).with_schemas(schemas);
+//!
+//! // Fails with DocumentStoreError::Validation: name is required
+//! store
+//!     .collection("users")
+//!     .insert(vec![(Uuid::new(), bson!({ "email": "alice@example.com" }))])
+//!     .await?;
+//! ```
+//!
+//! Partial updates, such as [`StoreBackend::update_by_query`], are applied by the backend to
+//! the stored documents and aren't validated. MongoDB can validate them too, with the schemas
+//! pushed as collection validators by `MongoDbStore::apply_schemas`.
+//!
+//! # Schemas of Rust types
+//!
+//! Instead of writing a schema by hand, a document type can derive [`SchemaType`] and have
+//! its schema registered for its collection, so untyped writes to the collection are held
+//! to the shape of the type:
+//!
+//! ```ignore
+//! #[derive(Debug, Clone, Serialize, Deserialize, Document, SchemaType)]
+//! #[document(collection = "users")]
+//! pub struct User {
+//!     pub id: Uuid,
+//!     pub name: String,
+//!     pub age: Option<u32>,
+//! }
+//!
+//! let store = DocumentStore::new(backend).with_schemas(SchemaRegistry::new());
+//! store.register_schema::<User>()?;
+//!
+//! let diff = store.diff_schema("users").await?;
+//! for difference in &diff.differences {
+//!     println!("{}", difference);
+//! }
+//! ```
+//!
+//! The derived schema requires every field that isn't an `Option` or `#[serde(default)]`,
+//! follows serde's renames, skipped and flattened fields and enum representations, and leaves
+//! fields with a `#[serde(with = "..")]` unchecked unless they're given their own schema with
+//! `#[schema(with = path)]`, a `fn() -> serde_json::Value`. Fields it doesn't list are allowed.
+//! Enums are described as serde derives them, not as an adjacent
+//! [`EnumRepresentation`](crate::encoding::EnumRepresentation) stores them, and recursive types
+//! aren't supported.
+//!
+//! [`diff_schema`](crate::store::DocumentStore::diff_schema) compares the documents already
+//! stored in a collection with its schema, reporting the fields violating it and those it
+//! doesn't declare, with how many documents are affected and a few of their IDs.
+
+use async_trait::async_trait;
+use bson::{Binary, Bson, RawDocumentBuf, Uuid, oid::ObjectId};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone};
+use futures::TryStreamExt;
+use serde_json::{Map, Value, json};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+use crate::{
+    aggregate::Aggregate,
+    backend::{
+        DocumentStream, DynStoreBackend, InsertPolicy, MissingDocumentPolicy, QueryPlan,
+        ReturnDocument, StoreBackend, WriteReport,
+    },
+    document::SCHEMA_VERSION_FIELD,
+    error::{DocumentStoreError, DocumentStoreResult},
+    lint::{MAX_EXAMPLE_IDS, document_id},
+    query::{Expr, Query, Sort},
+    transaction::{Transaction, TransactionBackend},
+    update::Update,
+    validate::FieldError,
+};
+
+/// How deeply `$ref`s may be followed while validating one value, so a schema referring to
+/// itself fails instead of recursing forever.
+const MAX_REF_DEPTH: usize = 64;
+
+/// A JSON Schema documents are validated against.
+///
+/// See the [module documentation](self) for the supported keywords.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonSchema {
+    schema: Value,
+}
+
+impl JsonSchema {
+    /// Creates a schema from its JSON.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::InvalidDocument`] if the schema is neither an object nor
+    /// a boolean.
+    pub fn new(schema: Value) -> DocumentStoreResult<Self> {
+        match schema {
+            Value::Object(_) | Value::Bool(_) => Ok(Self { schema }),
+            other => Err(DocumentStoreError::InvalidDocument(format!(
+                "A JSON Schema must be an object or a boolean, not {}",
+                other
+            ))),
+        }
+    }
+
+    /// Creates the schema of a Rust type, such as a document type deriving [`SchemaType`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::InvalidDocument`] if the type's schema is neither an
+    /// object nor a boolean.
+    pub fn of<T: SchemaType + ?Sized>() -> DocumentStoreResult<Self> {
+        Self::new(T::json_schema())
+    }
+
+    /// Returns the JSON of the schema.
+    pub fn as_json(&self) -> &Value {
+        &self.schema
+    }
+
+    /// Returns the violations of a document, or an empty vector if it's valid.
+    ///
+    /// The violations have no document ID; the field of each is the dotted path of the
+    /// invalid value, empty for the document itself.
+    pub fn validate(&self, document: &Bson) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        self.check(&self.schema, document, "", 0, &mut errors);
+        errors
+    }
+
+    /// Returns `true` if a value matches a schema, without collecting its violations.
+    fn matches(&self, schema: &Value, value: &Bson, depth: usize) -> bool {
+        let mut errors = Vec::new();
+        self.check(schema, value, "", depth, &mut errors);
+        errors.is_empty()
+    }
+
+    fn check(
+        &self,
+        schema: &Value,
+        value: &Bson,
+        path: &str,
+        depth: usize,
+        errors: &mut Vec<FieldError>,
+    ) {
+        let keywords = match schema {
+            Value::Object(keywords) => keywords,
+            Value::Bool(false) => return errors.push(FieldError::new(path, "is not allowed")),
+            _ => return,
+        };
+        let mut fail = |message: String| errors.push(FieldError::new(path, message));
+
+        if let Some(Value::String(reference)) = keywords.get("$ref") {
+            match reference
+                .strip_prefix('#')
+                .and_then(|pointer| self.schema.pointer(pointer))
+            {
+                Some(_) if depth >= MAX_REF_DEPTH => fail(format!(
+                    "refers to {} more than {} times in a row",
+                    reference, MAX_REF_DEPTH
+                )),
+                Some(referenced) => self.check(referenced, value, path, depth + 1, errors),
+                None => fail(format!("refers to {}, which isn't in the schema", reference)),
+            }
+            return;
+        }
+
+        if let Some(types) = keywords.get("type")
+            && !names(types).any(|name| json_type_matches(name, value))
+        {
+            fail(format!("must be of type {}", describe(types)));
+        }
+        if let Some(types) = keywords.get("bsonType")
+            && !names(types).any(|name| bson_type_matches(name, value))
+        {
+            fail(format!("must be of BSON type {}", describe(types)));
+        }
+        if let Some(Value::Array(allowed)) = keywords.get("enum")
+            && !allowed
+                .iter()
+                .any(|allowed| equals(value, allowed))
+        {
+            fail(format!("must be one of {}", Value::Array(allowed.clone())));
+        }
+        if let Some(expected) = keywords.get("const")
+            && !equals(value, expected)
+        {
+            fail(format!("must be {}", expected));
+        }
+
+        if let Some(number) = as_number(value) {
+            let bound = |keyword: &str| {
+                keywords
+                    .get(keyword)
+                    .and_then(Value::as_f64)
+            };
+
+            if let Some(minimum) = bound("minimum")
+                && number < minimum
+            {
+                fail(format!("must be at least {}", minimum));
+            }
+            if let Some(maximum) = bound("maximum")
+                && number > maximum
+            {
+                fail(format!("must be at most {}", maximum));
+            }
+            if let Some(minimum) = bound("exclusiveMinimum")
+                && number <= minimum
+            {
+                fail(format!("must be greater than {}", minimum));
+            }
+            if let Some(maximum) = bound("exclusiveMaximum")
+                && number >= maximum
+            {
+                fail(format!("must be less than {}", maximum));
+            }
+            if let Some(divisor) = bound("multipleOf")
+                && divisor > 0.0
+                && (number / divisor).fract() != 0.0
+            {
+                fail(format!("must be a multiple of {}", divisor));
+            }
+        }
+
+        if let Bson::String(string) = value {
+            let length = string.chars().count() as u64;
+
+            if let Some(minimum) = keywords
+                .get("minLength")
+                .and_then(Value::as_u64)
+                && length < minimum
+            {
+                fail(format!("must be at least {} characters long", minimum));
+            }
+            if let Some(maximum) = keywords
+                .get("maxLength")
+                .and_then(Value::as_u64)
+                && length > maximum
+            {
+                fail(format!("must be at most {} characters long", maximum));
+            }
+        }
+
+        if let Bson::Array(items) = value {
+            let count = items.len() as u64;
+
+            if let Some(minimum) = keywords
+                .get("minItems")
+                .and_then(Value::as_u64)
+                && count < minimum
+            {
+                fail(format!("must have at least {} items", minimum));
+            }
+            if let Some(maximum) = keywords
+                .get("maxItems")
+                .and_then(Value::as_u64)
+                && count > maximum
+            {
+                fail(format!("must have at most {} items", maximum));
+            }
+            if keywords.get("uniqueItems") == Some(&Value::Bool(true))
+                && items
+                    .iter()
+                    .enumerate()
+                    .any(|(i, item)| items[..i].contains(item))
+            {
+                fail("must not have duplicate items".to_string());
+            }
+
+            match keywords.get("items") {
+                // The items of a tuple each have their own schema
+                Some(Value::Array(schemas)) => {
+                    for (i, (item, schema)) in items.iter().zip(schemas).enumerate() {
+                        self.check(schema, item, &join(path, &i.to_string()), depth, errors);
+                    }
+                }
+                Some(schema) => {
+                    for (i, item) in items.iter().enumerate() {
+                        self.check(schema, item, &join(path, &i.to_string()), depth, errors);
+                    }
+                }
+                None => {}
+            }
+        }
+
+        if let Bson::Document(fields) = value {
+            self.check_object(keywords, fields, path, depth, errors);
+        }
+
+        if let Some(Value::Array(schemas)) = keywords.get("allOf") {
+            for schema in schemas {
+                self.check(schema, value, path, depth, errors);
+            }
+        }
+        if let Some(Value::Array(schemas)) = keywords.get("anyOf")
+            && !schemas
+                .iter()
+                .any(|schema| self.matches(schema, value, depth))
+        {
+            errors.push(FieldError::new(path, "must match at least one of the allowed schemas"));
+        }
+        if let Some(Value::Array(schemas)) = keywords.get("oneOf")
+            && schemas
+                .iter()
+                .filter(|schema| self.matches(schema, value, depth))
+                .count()
+                != 1
+        {
+            errors.push(FieldError::new(path, "must match exactly one of the allowed schemas"));
+        }
+        if let Some(schema) = keywords.get("not")
+            && self.matches(schema, value, depth)
+        {
+            errors.push(FieldError::new(path, "must not match the disallowed schema"));
+        }
+    }
+
+    fn check_object(
+        &self,
+        keywords: &Map<String, Value>,
+        fields: &bson::Document,
+        path: &str,
+        depth: usize,
+        errors: &mut Vec<FieldError>,
+    ) {
+        let count = fields.len() as u64;
+
+        if let Some(minimum) = keywords
+            .get("minProperties")
+            .and_then(Value::as_u64)
+            && count < minimum
+        {
+            errors.push(FieldError::new(path, format!("must have at least {} fields", minimum)));
+        }
+        if let Some(maximum) = keywords
+            .get("maxProperties")
+            .and_then(Value::as_u64)
+            && count > maximum
+        {
+            errors.push(FieldError::new(path, format!("must have at most {} fields", maximum)));
+        }
+
+        if let Some(Value::Array(required)) = keywords.get("required") {
+            for field in required
+                .iter()
+                .filter_map(Value::as_str)
+            {
+                if !fields.contains_key(field) {
+                    errors.push(FieldError::new(join(path, field), "is required"));
+                }
+            }
+        }
+
+        let properties = keywords
+            .get("properties")
+            .and_then(Value::as_object);
+
+        for (field, value) in fields {
+            match (
+                properties.and_then(|properties| properties.get(field)),
+                keywords.get("additionalProperties"),
+            ) {
+                (Some(schema), _) | (None, Some(schema)) => {
+                    self.check(schema, value, &join(path, field), depth, errors)
+                }
+                (None, None) => {}
+            }
+        }
+    }
+
+    /// Collects the paths of the fields of a value that its schema doesn't declare.
+    ///
+    /// Only objects whose schema lists their `properties` are checked, through nested
+    /// objects. Objects whose schema also has `additionalProperties` or `patternProperties`
+    /// are left to [`validate`](Self::validate).
+    fn undeclared(
+        &self,
+        schema: &Value,
+        value: &Bson,
+        path: &str,
+        depth: usize,
+        fields: &mut Vec<String>,
+    ) {
+        let Bson::Document(document) = value else {
+            return;
+        };
+        let mut branches = Vec::new();
+        self.branches(schema, depth, &mut branches);
+
+        if !branches
+            .iter()
+            .any(|keywords| keywords.contains_key("properties"))
+            || branches.iter().any(|keywords| {
+                keywords.contains_key("additionalProperties")
+                    || keywords.contains_key("patternProperties")
+            })
+        {
+            return;
+        }
+
+        for (field, value) in document {
+            let declared = branches.iter().find_map(|keywords| {
+                keywords
+                    .get("properties")?
+                    .as_object()?
+                    .get(field)
+            });
+
+            match declared {
+                Some(schema) => self.undeclared(schema, value, &join(path, field), depth, fields),
+                None if path.is_empty() && field == SCHEMA_VERSION_FIELD => {}
+                None => fields.push(join(path, field)),
+            }
+        }
+    }
+
+    /// Returns the fields declared at the top level of a document, with their schemas and
+    /// whether they're required, following `$ref`s and combined schemas.
+    pub(crate) fn top_level_fields(&self) -> BTreeMap<String, (Value, bool)> {
+        let mut branches = Vec::new();
+        self.branches(&self.schema, 0, &mut branches);
+
+        let mut fields = BTreeMap::new();
+
+        for keywords in &branches {
+            if let Some(Value::Object(properties)) = keywords.get("properties") {
+                for (field, schema) in properties {
+                    fields
+                        .entry(field.clone())
+                        .or_insert_with(|| (schema.clone(), false));
+                }
+            }
+        }
+        for keywords in &branches {
+            if let Some(Value::Array(required)) = keywords.get("required") {
+                for field in required
+                    .iter()
+                    .filter_map(Value::as_str)
+                {
+                    if let Some((_, required)) = fields.get_mut(field) {
+                        *required = true;
+                    }
+                }
+            }
+        }
+
+        fields
+    }
+
+    /// Collects the object schemas a value is checked against: the schema itself, the schemas
+    /// it refers to and those it combines with `allOf`, `anyOf` and `oneOf`.
+    fn branches<'s>(
+        &'s self,
+        schema: &'s Value,
+        depth: usize,
+        branches: &mut Vec<&'s Map<String, Value>>,
+    ) {
+        let Value::Object(keywords) = schema else {
+            return;
+        };
+
+        if let Some(Value::String(reference)) = keywords.get("$ref") {
+            if depth < MAX_REF_DEPTH
+                && let Some(referenced) = reference
+                    .strip_prefix('#')
+                    .and_then(|pointer| self.schema.pointer(pointer))
+            {
+                self.branches(referenced, depth + 1, branches);
+            }
+            return;
+        }
+
+        branches.push(keywords);
+
+        for combinator in ["allOf", "anyOf", "oneOf"] {
+            if let Some(Value::Array(schemas)) = keywords.get(combinator) {
+                for schema in schemas {
+                    self.branches(schema, depth, branches);
+                }
+            }
+        }
+    }
+}
+
+/// The names of a `type` or `bsonType` keyword, given as a name or an array of names.
+fn names(types: &Value) -> impl Iterator<Item = &str> {
+    let names: Vec<&str> = match types {
+        Value::String(name) => vec![name],
+        Value::Array(names) => names
+            .iter()
+            .filter_map(Value::as_str)
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    names.into_iter()
+}
+
+/// Describes the names of a `type` or `bsonType` keyword in a message.
+fn describe(types: &Value) -> String {
+    names(types)
+        .collect::<Vec<_>>()
+        .join(" or ")
+}
+
+/// Returns the path of a field nested in the value at `path`.
+fn join(path: &str, field: &str) -> String {
+    match path {
+        "" => field.to_string(),
+        path => format!("{}.{}", path, field),
+    }
+}
+
+/// Returns `true` if a value has a JSON Schema type, as it would be written as JSON.
+fn json_type_matches(name: &str, value: &Bson) -> bool {
+    match name {
+        "object" => matches!(value, Bson::Document(_)),
+        "array" => matches!(value, Bson::Array(_)),
+        "boolean" => matches!(value, Bson::Boolean(_)),
+        "null" => matches!(value, Bson::Null | Bson::Undefined),
+        "number" => as_number(value).is_some(),
+        "integer" => match value {
+            Bson::Int32(_) | Bson::Int64(_) => true,
+            Bson::Double(number) => number.is_finite() && number.fract() == 0.0,
+            _ => false,
+        },
+        "string" => matches!(
+            value,
+            Bson::String(_)
+                | Bson::Symbol(_)
+                | Bson::Binary(_)
+                | Bson::ObjectId(_)
+                | Bson::DateTime(_)
+                | Bson::Timestamp(_)
+        ),
+        _ => false,
+    }
+}
+
+/// Returns `true` if a value has a MongoDB `bsonType`.
+fn bson_type_matches(name: &str, value: &Bson) -> bool {
+    match (name, value) {
+        ("double", Bson::Double(_))
+        | ("string", Bson::String(_))
+        | ("object", Bson::Document(_))
+        | ("array", Bson::Array(_))
+        | ("binData", Bson::Binary(_))
+        | ("undefined", Bson::Undefined)
+        | ("objectId", Bson::ObjectId(_))
+        | ("bool", Bson::Boolean(_))
+        | ("date", Bson::DateTime(_))
+        | ("null", Bson::Null)
+        | ("regex", Bson::RegularExpression(_))
+        | ("javascript", Bson::JavaScriptCode(_) | Bson::JavaScriptCodeWithScope(_))
+        | ("int", Bson::Int32(_))
+        | ("timestamp", Bson::Timestamp(_))
+        | ("long", Bson::Int64(_))
+        | ("decimal", Bson::Decimal128(_))
+        | ("minKey", Bson::MinKey)
+        | ("maxKey", Bson::MaxKey) => true,
+        ("number", value) => as_number(value).is_some(),
+        _ => false,
+    }
+}
+
+/// Returns a numeric value as a float.
+fn as_number(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Double(number) => Some(*number),
+        Bson::Int32(number) => Some(*number as f64),
+        Bson::Int64(number) => Some(*number as f64),
+        Bson::Decimal128(number) => number.to_string().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Returns `true` if a value equals a value of an `enum` or `const` keyword.
+fn equals(value: &Bson, expected: &Value) -> bool {
+    match (value, expected) {
+        (Bson::Null | Bson::Undefined, Value::Null) => true,
+        (Bson::Boolean(value), Value::Bool(expected)) => value == expected,
+        (Bson::String(value), Value::String(expected)) => value == expected,
+        (Bson::Array(values), Value::Array(expected)) => {
+            values.len() == expected.len()
+                && values
+                    .iter()
+                    .zip(expected)
+                    .all(|(value, expected)| equals(value, expected))
+        }
+        (Bson::Document(fields), Value::Object(expected)) => {
+            fields.len() == expected.len()
+                && fields.iter().all(|(field, value)| {
+                    expected
+                        .get(field)
+                        .is_some_and(|expected| equals(value, expected))
+                })
+        }
+        (value, Value::Number(expected)) => as_number(value) == expected.as_f64(),
+        _ => false,
+    }
+}
+
+/// A Rust type whose values a JSON Schema describes, as serde serializes them.
+///
+/// Implemented for booleans, numbers, strings, UUIDs, datetimes, options, sequences, tuples,
+/// sets and maps with string keys, and derived for structs and enums with `#[derive(SchemaType)]`,
+/// which follows their serde attributes. [`DocumentStore::register_schema`] registers the
+/// schema of a document type for its collection.
+///
+/// [`DocumentStore::register_schema`]: crate::store::DocumentStore::register_schema
+pub trait SchemaType {
+    /// Returns the JSON Schema of the type's values.
+    fn json_schema() -> Value;
+}
+
+macro_rules! schema_type {
+    ($schema:tt => $($ty:ty),+) => {
+        $(
+            impl SchemaType for $ty {
+                fn json_schema() -> Value {
+                    json!($schema)
+                }
+            }
+        )+
+    };
+}
+
+schema_type!({ "type": "boolean" } => bool);
+schema_type!({ "type": "integer" } => i8, i16, i32, i64, isize);
+schema_type!({ "type": "integer", "minimum": 0 } => u8, u16, u32, u64, usize);
+schema_type!({ "type": "number" } => f32, f64);
+schema_type!({ "type": "string" } => str, String, ObjectId, Binary);
+schema_type!({ "type": "string", "minLength": 1, "maxLength": 1 } => char);
+schema_type!({ "type": "string", "format": "uuid" } => Uuid, uuid::Uuid);
+schema_type!({ "type": "string", "format": "date-time" } => bson::DateTime, NaiveDateTime);
+schema_type!({ "type": "string", "format": "date" } => NaiveDate);
+schema_type!({ "type": "object" } => bson::Document, Map<String, Value>);
+schema_type!({ "type": "null" } => ());
+schema_type!({} => Bson, Value);
+
+impl<Tz: TimeZone> SchemaType for DateTime<Tz> {
+    fn json_schema() -> Value {
+        json!({ "type": "string", "format": "date-time" })
+    }
+}
+
+impl<T: SchemaType> SchemaType for Option<T> {
+    fn json_schema() -> Value {
+        nullable(T::json_schema())
+    }
+}
+
+impl<T: SchemaType + ?Sized> SchemaType for Box<T> {
+    fn json_schema() -> Value {
+        T::json_schema()
+    }
+}
+
+impl<T: SchemaType + ?Sized> SchemaType for Arc<T> {
+    fn json_schema() -> Value {
+        T::json_schema()
+    }
+}
+
+impl<T: SchemaType> SchemaType for [T] {
+    fn json_schema() -> Value {
+        json!({ "type": "array", "items": T::json_schema() })
+    }
+}
+
+impl<T: SchemaType> SchemaType for Vec<T> {
+    fn json_schema() -> Value {
+        <[T]>::json_schema()
+    }
+}
+
+impl<T: SchemaType> SchemaType for VecDeque<T> {
+    fn json_schema() -> Value {
+        <[T]>::json_schema()
+    }
+}
+
+impl<T: SchemaType, const N: usize> SchemaType for [T; N] {
+    fn json_schema() -> Value {
+        json!({ "type": "array", "items": T::json_schema(), "minItems": N, "maxItems": N })
+    }
+}
+
+impl<T: SchemaType, S> SchemaType for HashSet<T, S> {
+    fn json_schema() -> Value {
+        json!({ "type": "array", "items": T::json_schema(), "uniqueItems": true })
+    }
+}
+
+impl<T: SchemaType> SchemaType for BTreeSet<T> {
+    fn json_schema() -> Value {
+        HashSet::<T>::json_schema()
+    }
+}
+
+impl<K, V: SchemaType, S> SchemaType for HashMap<K, V, S> {
+    fn json_schema() -> Value {
+        json!({ "type": "object", "additionalProperties": V::json_schema() })
+    }
+}
+
+impl<K, V: SchemaType> SchemaType for BTreeMap<K, V> {
+    fn json_schema() -> Value {
+        HashMap::<K, V>::json_schema()
+    }
+}
+
+macro_rules! tuple_schema_type {
+    ($($name:ident)+) => {
+        impl<$($name: SchemaType),+> SchemaType for ($($name,)+) {
+            fn json_schema() -> Value {
+                let items = vec![$($name::json_schema()),+];
+                let count = items.len();
+
+                json!({ "type": "array", "items": items, "minItems": count, "maxItems": count })
+            }
+        }
+    };
+}
+
+tuple_schema_type!(A);
+tuple_schema_type!(A B);
+tuple_schema_type!(A B C);
+tuple_schema_type!(A B C D);
+tuple_schema_type!(A B C D E);
+tuple_schema_type!(A B C D E F);
+
+/// Keywords rejecting null values that a schema can't simply be widened past.
+const NULL_REJECTING_KEYWORDS: [&str; 8] = [
+    "$ref", "bsonType", "enum", "const", "allOf", "anyOf", "oneOf", "not",
+];
+
+/// Widens a schema to also accept null, adding `null` to its `type` where that's enough.
+fn nullable(schema: Value) -> Value {
+    match schema {
+        Value::Object(mut keywords)
+            if !NULL_REJECTING_KEYWORDS
+                .iter()
+                .any(|keyword| keywords.contains_key(*keyword)) =>
+        {
+            match keywords.get_mut("type") {
+                Some(Value::String(name)) => {
+                    let name = std::mem::take(name);
+                    keywords.insert("type".to_string(), json!([name, "null"]));
+                }
+                Some(Value::Array(names)) if !names.contains(&json!("null")) => {
+                    names.push(json!("null"));
+                }
+                _ => {}
+            }
+            Value::Object(keywords)
+        }
+        Value::Bool(true) => Value::Bool(true),
+        schema => json!({ "anyOf": [schema, { "type": "null" }] }),
+    }
+}
+
+/// The differences between the documents stored in a collection and its schema.
+///
+/// Returned by [`DocumentStore::diff_schema`](crate::store::DocumentStore::diff_schema).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SchemaDiff {
+    /// The collection scanned.
+    pub collection: String,
+    /// The number of documents scanned.
+    pub scanned: usize,
+    /// The number of documents violating the schema.
+    pub invalid: usize,
+    /// The violations and undeclared fields found, grouped by field and message.
+    pub differences: Vec<SchemaDifference>,
+}
+
+impl SchemaDiff {
+    /// Returns `true` if every scanned document matches the schema and has no undeclared
+    /// fields.
+    pub fn is_clean(&self) -> bool {
+        self.differences.is_empty()
+    }
+
+    fn record(&mut self, field: String, message: String, undeclared: bool, id: Option<&str>) {
+        let index = match self
+            .differences
+            .iter()
+            .position(|difference| difference.field == field && difference.message == message)
+        {
+            Some(index) => index,
+            None => {
+                self.differences.push(SchemaDifference {
+                    field,
+                    message,
+                    undeclared,
+                    count: 0,
+                    example_ids: Vec::new(),
+                });
+                self.differences.len() - 1
+            }
+        };
+
+        let difference = &mut self.differences[index];
+        difference.count += 1;
+        if let Some(id) = id
+            && difference.example_ids.len() < MAX_EXAMPLE_IDS
+        {
+            difference
+                .example_ids
+                .push(id.to_string());
+        }
+    }
+}
+
+/// A field of some stored documents that violates the schema or that the schema doesn't
+/// declare.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaDifference {
+    /// The dotted path of the field, empty for the document itself.
+    pub field: String,
+    /// What is wrong with the field, as in the [`FieldError`]s of a rejected write.
+    pub message: String,
+    /// Whether the field is missing from the schema rather than violating it.
+    pub undeclared: bool,
+    /// The number of documents with this difference.
+    pub count: usize,
+    /// The IDs of up to [`MAX_EXAMPLE_IDS`] documents with this difference.
+    pub example_ids: Vec<String>,
+}
+
+impl fmt::Display for SchemaDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.field.as_str() {
+            "" => write!(f, "document {}", self.message)?,
+            field => write!(f, "field '{}' {}", field, self.message)?,
+        }
+
+        write!(f, " in {} documents", self.count)?;
+        if !self.example_ids.is_empty() {
+            write!(f, " (e.g. {})", self.example_ids.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Scans a collection and compares its documents with a schema.
+pub(crate) async fn diff_schema(
+    backend: &dyn DynStoreBackend,
+    collection: &str,
+    schema: &JsonSchema,
+) -> DocumentStoreResult<SchemaDiff> {
+    let mut diff = SchemaDiff {
+        collection: collection.to_string(),
+        ..SchemaDiff::default()
+    };
+    let mut documents = backend
+        .query_stream(Query::default(), collection)
+        .await?;
+
+    while let Some(document) = documents.try_next().await? {
+        diff.scanned += 1;
+
+        let id = document_id(&document);
+        let errors = schema.validate(&document);
+        if !errors.is_empty() {
+            diff.invalid += 1;
+        }
+        for error in errors {
+            diff.record(error.field, error.message, false, id.as_deref());
+        }
+
+        let mut undeclared = Vec::new();
+        schema.undeclared(schema.as_json(), &document, "", 0, &mut undeclared);
+        for field in undeclared {
+            diff.record(field, "is not declared in the schema".to_string(), true, id.as_deref());
+        }
+    }
+
+    Ok(diff)
+}
+
+/// The JSON Schemas of collections, validating the documents written to them.
+///
+/// Clones share the same schemas, so schemas registered after the registry is handed to a
+/// [`SchemaStore`] apply to it.
+#[derive(Debug, Clone, Default)]
+pub struct SchemaRegistry {
+    schemas: Arc<RwLock<HashMap<String, Arc<JsonSchema>>>>,
+}
+
+impl SchemaRegistry {
+    /// Creates a registry without schemas.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attaches a schema to a collection, replacing its previous schema.
+    pub fn register(&self, collection: impl Into<String>, schema: JsonSchema) {
+        self.schemas
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(collection.into(), Arc::new(schema));
+    }
+
+    /// Detaches the schema of a collection, returning it.
+    pub fn remove(&self, collection: &str) -> Option<Arc<JsonSchema>> {
+        self.schemas
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(collection)
+    }
+
+    /// Returns the schema of a collection.
+    pub fn get(&self, collection: &str) -> Option<Arc<JsonSchema>> {
+        self.schemas
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(collection)
+            .cloned()
+    }
+
+    /// Returns the collections with a schema, and their schemas.
+    pub fn schemas(&self) -> Vec<(String, Arc<JsonSchema>)> {
+        self.schemas
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .map(|(collection, schema)| (collection.clone(), schema.clone()))
+            .collect()
+    }
+
+    /// Validates documents written to a collection against its schema, if it has one.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Validation`] with the violations of all the documents, if
+    /// there are any.
+    pub fn validate<'d>(
+        &self,
+        collection: &str,
+        documents: impl IntoIterator<Item = (&'d Uuid, &'d Bson)>,
+    ) -> DocumentStoreResult<()> {
+        let Some(schema) = self.get(collection) else {
+            return Ok(());
+        };
+
+        let errors = documents
+            .into_iter()
+            .flat_map(|(id, document)| {
+                schema
+                    .validate(document)
+                    .into_iter()
+                    .map(|error| FieldError { document: Some(*id), ..error })
+            })
+            .collect::<Vec<_>>();
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(DocumentStoreError::Validation(errors)),
+        }
+    }
+}
+
+/// A backend validating the documents written to each collection against its schema.
+///
+/// See the [module documentation](self) for what is validated.
+pub struct SchemaStore<B> {
+    backend: B,
+    schemas: SchemaRegistry,
+}
+
+impl<B: StoreBackend> SchemaStore<B> {
+    /// Wraps a backend so the documents written through it are validated against `schemas`.
+    pub fn new(backend: B, schemas: SchemaRegistry) -> Self {
+        Self { backend, schemas }
+    }
+
+    /// Returns the schemas documents are validated against.
+    pub fn schemas(&self) -> &SchemaRegistry {
+        &self.schemas
+    }
+
+    /// Returns the wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.backend
+    }
+
+    /// Unwraps the backend.
+    pub fn into_inner(self) -> B {
+        self.backend
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for SchemaStore<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SchemaStore")
+            .field("backend", &self.backend)
+            .field("schemas", &self.schemas)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<B: StoreBackend + 'static> StoreBackend for SchemaStore<B> {
+    async fn insert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.schemas.validate(
+            collection,
+            documents
+                .iter()
+                .map(|(id, document)| (id, document)),
+        )?;
+
+        self.backend
+            .insert_documents(documents, collection, policy)
+            .await
+    }
+
+    async fn update_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.schemas.validate(
+            collection,
+            documents
+                .iter()
+                .map(|(id, document)| (id, document)),
+        )?;
+
+        self.backend
+            .update_documents(documents, collection, policy)
+            .await
+    }
+
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.schemas.validate(
+            collection,
+            documents
+                .iter()
+                .map(|(id, document, _)| (id, document)),
+        )?;
+
+        self.backend
+            .update_documents_if_version(documents, version_field, collection)
+            .await
+    }
+
+    async fn update_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, Bson, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.schemas.validate(
+            collection,
+            documents
+                .iter()
+                .map(|(id, document, _)| (id, document)),
+        )?;
+
+        self.backend
+            .update_documents_if_match(documents, collection)
+            .await
+    }
+
+    async fn upsert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+    ) -> DocumentStoreResult<()> {
+        self.schemas.validate(
+            collection,
+            documents
+                .iter()
+                .map(|(id, document)| (id, document)),
+        )?;
+
+        self.backend
+            .upsert_documents(documents, collection)
+            .await
+    }
+
+    async fn delete_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        self.backend
+            .delete_documents(ids, collection)
+            .await
+    }
+
+    async fn delete_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        self.backend
+            .delete_documents_if_match(documents, collection)
+            .await
+    }
+
+    async fn update_by_query(
+        &self,
+        filter: Expr,
+        update: Update,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.backend
+            .update_by_query(filter, update, collection)
+            .await
+    }
+
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize> {
+        self.backend
+            .delete_by_query(filter, collection)
+            .await
+    }
+
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        self.backend
+            .find_one_and_update(filter, sort, update, returned, collection)
+            .await
+    }
+
+    async fn find_one_and_delete(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        self.backend
+            .find_one_and_delete(filter, sort, collection)
+            .await
+    }
+
+    async fn get_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .get_documents(ids, collection)
+            .await
+    }
+
+    async fn query_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .query_documents(query, collection)
+            .await
+    }
+
+    async fn query_stream(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<DocumentStream> {
+        self.backend
+            .query_stream(query, collection)
+            .await
+    }
+
+    async fn query_raw_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<RawDocumentBuf>> {
+        self.backend
+            .query_raw_documents(query, collection)
+            .await
+    }
+
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize> {
+        self.backend
+            .count_documents(query, collection)
+            .await
+    }
+
+    async fn distinct(
+        &self,
+        field: &str,
+        filter: Option<Expr>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .distinct(field, filter, collection)
+            .await
+    }
+
+    async fn aggregate(
+        &self,
+        aggregate: Aggregate,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.backend
+            .aggregate(aggregate, collection)
+            .await
+    }
+
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan> {
+        self.backend
+            .explain(query, collection)
+            .await
+    }
+
+    async fn current_revision_id(&self) -> DocumentStoreResult<Option<String>> {
+        self.backend.current_revision_id().await
+    }
+
+    async fn set_revision_id(&self, revision_id: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .set_revision_id(revision_id)
+            .await
+    }
+
+    async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .create_collection(name)
+            .await
+    }
+
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        self.backend
+            .collection_exists(name)
+            .await
+    }
+
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .ensure_collection(name)
+            .await
+    }
+
+    async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.backend.drop_collection(name).await
+    }
+
+    async fn list_collections(&self) -> DocumentStoreResult<Vec<String>> {
+        self.backend.list_collections().await
+    }
+
+    async fn add_field(
+        &self,
+        collection: &str,
+        field: &str,
+        default: Bson,
+    ) -> DocumentStoreResult<()> {
+        self.backend
+            .add_field(collection, field, default)
+            .await
+    }
+
+    async fn drop_field(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .drop_field(collection, field)
+            .await
+    }
+
+    async fn rename_field(
+        &self,
+        collection: &str,
+        field: &str,
+        new: &str,
+    ) -> DocumentStoreResult<()> {
+        self.backend
+            .rename_field(collection, field, new)
+            .await
+    }
+
+    async fn add_index(
+        &self,
+        collection: &str,
+        field: &str,
+        unique: bool,
+    ) -> DocumentStoreResult<()> {
+        self.backend
+            .add_index(collection, field, unique)
+            .await
+    }
+
+    async fn drop_index(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .drop_index(collection, field)
+            .await
+    }
+
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .expire_documents(collection, field)
+            .await
+    }
+
+    async fn begin_transaction(&self) -> DocumentStoreResult<Transaction> {
+        let transaction = self.backend.begin_transaction().await?;
+
+        Ok(Transaction::new(SchemaStore::new(transaction, self.schemas.clone())))
+    }
+
+    fn inner_backend(&self) -> Option<&dyn DynStoreBackend> {
+        Some(&self.backend)
+    }
+
+    async fn close(&self) -> DocumentStoreResult<()> {
+        self.backend.close().await
+    }
+
+    async fn shutdown(self) -> DocumentStoreResult<()> {
+        self.backend.shutdown().await
+    }
+}
+
+// Writes made in a transaction are validated too
+#[async_trait]
+impl TransactionBackend for SchemaStore<Transaction> {
+    fn backend(&self) -> &dyn DynStoreBackend {
+        self
+    }
+
+    async fn commit(self: Box<Self>) -> DocumentStoreResult<()> {
+        self.backend.commit().await
+    }
+
+    async fn rollback(self: Box<Self>) -> DocumentStoreResult<()> {
+        self.backend.rollback().await
+    }
+}
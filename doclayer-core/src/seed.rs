@@ -0,0 +1,302 @@
+//! Fixture documents for development, tests and demos.
+//!
+//! A [`Seed`] inserts a set of fixture documents through a [`SeedOp`], and a [`Seeder`] lists
+//! the seeds of an application, like [`Migrations`](crate::migrate::Migrations) lists its
+//! migrations. Seeds are idempotent: documents whose ID already exists are left as they are, so
+//! seeding a store twice, or seeding one that has been used since, inserts only what's missing.
+//!
+//! Each seed is tagged with the [`Environment`]s it belongs to. [`SeedHost::seed`] runs the
+//! seeds of the current environment, read from the [`ENVIRONMENT_VARIABLE`], and
+//! [`SeedHost::seed_for`] those of an explicit one. Seeds leave production out unless they
+//! list it.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::seed::{Environment, Seed, SeedHost, SeedOp, SeedRef, Seeder};
+//!
+//! struct DemoUsers;
+//!
+//! #[async_trait::async_trait]
+//! impl Seed for DemoUsers {
+//!     fn id(&self) -> &'static str { "demo_users" }
+//!
+//!     async fn seed(&self, op: &SeedOp<'_>) -> DocumentStoreResult<()> {
+//!         op.insert(vec![
+//!             User { id: Uuid::parse_str("6f1c...")?, name: "Alice".to_string() },
+//!             User { id: Uuid::parse_str("0b9e...")?, name: "Bob".to_string() },
+//!         ])
+//!         .await
+//!     }
+//! }
+//!
+//! struct MySeeds;
+//!
+//! impl Seeder for MySeeds {
+//!     fn seeds() -> Vec<SeedRef> {
+//!         vec![Box::new(DemoUsers)]
+//!     }
+//! }
+//!
+//! let report = store.seed_for::<MySeeds>(Environment::Test).await?;
+//! println!("{} fixture documents inserted", report.inserted);
+//! ```
+
+use async_trait::async_trait;
+use bson::{Bson, Uuid};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    backend::InsertPolicy,
+    document::Document,
+    error::{DocumentStoreError, DocumentStoreResult},
+    store::{AsDynDocumentStore, DynDocumentStoreRef},
+};
+
+/// The environment variable [`Environment::current`] reads.
+pub const ENVIRONMENT_VARIABLE: &str = "DOCLAYER_ENV";
+
+/// An environment seeds are tagged with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Environment {
+    /// Local development.
+    #[default]
+    Development,
+    /// Automated tests.
+    Test,
+    /// Pre-production deployments.
+    Staging,
+    /// Production deployments, which seeds leave out unless they list it.
+    Production,
+}
+
+impl Environment {
+    /// The environments seeds belong to by default, every one but production.
+    pub const NON_PRODUCTION: &'static [Environment] = &[
+        Environment::Development,
+        Environment::Test,
+        Environment::Staging,
+    ];
+
+    /// Returns the environment named by the [`ENVIRONMENT_VARIABLE`], or
+    /// [`Environment::Development`] if it isn't set.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Serialization`] if the variable names no environment.
+    pub fn current() -> DocumentStoreResult<Self> {
+        match std::env::var(ENVIRONMENT_VARIABLE) {
+            Ok(name) => name.parse(),
+            Err(_) => Ok(Environment::default()),
+        }
+    }
+
+    /// Returns the environment's name, as accepted by [`FromStr`].
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Environment::Development => "development",
+            Environment::Test => "test",
+            Environment::Staging => "staging",
+            Environment::Production => "production",
+        }
+    }
+}
+
+impl fmt::Display for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Environment {
+    type Err = DocumentStoreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "development" | "dev" => Ok(Environment::Development),
+            "test" => Ok(Environment::Test),
+            "staging" => Ok(Environment::Staging),
+            "production" | "prod" => Ok(Environment::Production),
+            _ => Err(DocumentStoreError::Serialization(format!("Invalid environment {:?}", s))),
+        }
+    }
+}
+
+/// A set of fixture documents.
+#[async_trait]
+pub trait Seed: Send + Sync {
+    /// Returns a unique identifier for this seed, reported in [`SeedReport`].
+    fn id(&self) -> &'static str;
+
+    /// Returns the environments this seed runs in.
+    ///
+    /// Defaults to [`Environment::NON_PRODUCTION`].
+    fn environments(&self) -> &'static [Environment] {
+        Environment::NON_PRODUCTION
+    }
+
+    /// Inserts the seed's documents.
+    async fn seed(&self, op: &SeedOp<'_>) -> DocumentStoreResult<()>;
+}
+
+pub type SeedRef = Box<dyn Seed>;
+
+/// The seeds of an application, in the order they run.
+pub trait Seeder: Send + Sync {
+    fn seeds() -> Vec<SeedRef>;
+}
+
+/// Inserts the documents of a [`Seed`], skipping those that already exist.
+pub struct SeedOp<'a> {
+    store: DynDocumentStoreRef<'a>,
+    inserted: AtomicUsize,
+    existing: AtomicUsize,
+}
+
+impl<'a> SeedOp<'a> {
+    pub fn new(store: DynDocumentStoreRef<'a>) -> Self {
+        Self {
+            store,
+            inserted: AtomicUsize::new(0),
+            existing: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the store being seeded, for fixtures that need more than inserts.
+    pub fn store(&self) -> &DynDocumentStoreRef<'a> {
+        &self.store
+    }
+
+    /// Inserts documents into their collection, leaving those whose ID already exists as
+    /// they are.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a document fails validation or the insert fails.
+    pub async fn insert<D: Document>(&self, documents: Vec<D>) -> DocumentStoreResult<()> {
+        let count = documents.len();
+        let report = self
+            .store
+            .typed_collection::<D>()
+            .insert_with_policy(documents, InsertPolicy::Skip)
+            .await?;
+
+        self.record(count, report.matched);
+        Ok(())
+    }
+
+    /// Inserts raw documents into a collection, leaving those whose ID already exists as
+    /// they are.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert fails.
+    pub async fn insert_raw(
+        &self,
+        collection: &str,
+        documents: Vec<(Uuid, Bson)>,
+    ) -> DocumentStoreResult<()> {
+        let count = documents.len();
+        let report = self
+            .store
+            .collection(collection)
+            .insert_with_policy(documents, InsertPolicy::Skip)
+            .await?;
+
+        self.record(count, report.matched);
+        Ok(())
+    }
+
+    fn record(&self, count: usize, existing: usize) {
+        self.inserted
+            .fetch_add(count.saturating_sub(existing), Ordering::Relaxed);
+        self.existing
+            .fetch_add(existing, Ordering::Relaxed);
+    }
+}
+
+/// What seeding a store did.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeedReport {
+    /// The environment the store was seeded for.
+    pub environment: Environment,
+    /// The seeds that ran, in order.
+    pub applied: Vec<String>,
+    /// The seeds left out because they don't belong to the environment.
+    pub skipped: Vec<String>,
+    /// Number of documents inserted.
+    pub inserted: usize,
+    /// Number of documents left as they were because their ID already existed.
+    pub existing: usize,
+}
+
+/// Extension trait for seeding a store with fixture documents.
+///
+/// This trait is automatically implemented for every store type.
+#[async_trait]
+pub trait SeedHost: Send + Sync {
+    /// Runs the seeds of `S` belonging to the current environment (see
+    /// [`Environment::current`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the [`ENVIRONMENT_VARIABLE`] names no environment, or a seed
+    /// fails. Documents inserted by earlier seeds stay.
+    async fn seed<S: Seeder>(&self) -> DocumentStoreResult<SeedReport>;
+
+    /// Runs the seeds of `S` belonging to `environment`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a seed fails. Documents inserted by earlier seeds stay.
+    async fn seed_for<S: Seeder>(
+        &self,
+        environment: Environment,
+    ) -> DocumentStoreResult<SeedReport>;
+}
+
+#[async_trait]
+impl<T> SeedHost for T
+where
+    T: AsDynDocumentStore + Send + Sync,
+{
+    async fn seed<S: Seeder>(&self) -> DocumentStoreResult<SeedReport> {
+        self.seed_for::<S>(Environment::current()?)
+            .await
+    }
+
+    async fn seed_for<S: Seeder>(
+        &self,
+        environment: Environment,
+    ) -> DocumentStoreResult<SeedReport> {
+        let op = SeedOp::new(self.as_dyn());
+        let mut report = SeedReport { environment, ..SeedReport::default() };
+
+        for seed in S::seeds() {
+            if !seed
+                .environments()
+                .contains(&environment)
+            {
+                report
+                    .skipped
+                    .push(seed.id().to_string());
+                continue;
+            }
+
+            seed.seed(&op).await?;
+            report
+                .applied
+                .push(seed.id().to_string());
+        }
+
+        report.inserted = op.inserted.into_inner();
+        report.existing = op.existing.into_inner();
+        Ok(report)
+    }
+}
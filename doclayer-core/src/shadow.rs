@@ -0,0 +1,415 @@
+//! Dry-running writes against a backend without applying them.
+//!
+//! A [`ShadowStore`] passes reads through to its backend but only records writes, answering
+//! them with the result they would have had: counts are derived from the documents the
+//! backend holds, so callers see the same numbers as if the write had been applied. Each
+//! write can also be applied to a scratch backend, for example one holding a new schema or a
+//! different database, so production traffic can be replayed against it and the outcomes
+//! compared without touching the live data.
+//!
+//! The shadow is a [`StoreLayer`], so it stacks with other layers. The [`Shadow`] handle is
+//! cheap to clone and shares the recorded writes, so a copy kept outside the store can
+//! inspect them.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::{backend::Layered, shadow::Shadow};
+//!
+//! let shadow = Shadow::new().with_scratch(candidate_backend);
+//! let store = DocumentStore::new(Layered::new(shadow.clone(), production_backend));
+//!
+//! replay(&store).await?;
+//!
+//! for write in shadow.take_writes() {
+//!     println!("{} on {:?}: {:?}", write.operation.name(), write.operation.collection(), write.scratch);
+//! }
+//! ```
+//!
+//! Reads don't see recorded writes: a document inserted through the shadow store can't be
+//! read back from it, and `find_one_and_update` returns the matching document as stored,
+//! without the update. Read from the scratch backend to follow the effect of the writes.
+
+use async_trait::async_trait;
+use bson::Uuid;
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    backend::{
+        DynStoreBackend, InsertPolicy, Layered, MissingDocumentPolicy, Next, Operation, Outcome,
+        StoreBackend, StoreLayer, WriteReport,
+    },
+    document::stored_version,
+    error::{DocumentStoreError, DocumentStoreResult},
+    query::Query,
+    versioned::document_revision,
+};
+
+/// A backend whose writes are recorded by a [`Shadow`] instead of being applied.
+pub type ShadowStore<B> = Layered<Shadow, B>;
+
+/// A write recorded by a [`Shadow`].
+#[derive(Debug)]
+pub struct ShadowWrite {
+    /// The write with its arguments.
+    pub operation: Operation,
+    /// The documents the write would have affected on the backend, for writes reporting a
+    /// count, or `None` if it reports none or would have failed.
+    pub affected: Option<usize>,
+    /// The outcome of the write on the scratch backend, if one is configured.
+    pub scratch: Option<DocumentStoreResult<Outcome>>,
+}
+
+#[derive(Debug, Default)]
+struct ShadowState {
+    scratch: Option<Arc<dyn DynStoreBackend>>,
+    max_recorded: Option<usize>,
+    writes: Mutex<VecDeque<ShadowWrite>>,
+}
+
+/// A [`StoreLayer`] recording writes instead of applying them.
+///
+/// Clones share the recorded writes and the scratch backend.
+#[derive(Debug, Clone, Default)]
+pub struct Shadow {
+    state: Arc<ShadowState>,
+}
+
+impl Shadow {
+    /// Creates a shadow keeping every write, without a scratch backend.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies every recorded write to `scratch` as well, recording its outcome.
+    ///
+    /// Configure the shadow before cloning it, since clones made earlier keep their own
+    /// settings.
+    pub fn with_scratch(mut self, scratch: impl StoreBackend + 'static) -> Self {
+        self.state_mut().scratch = Some(Arc::new(scratch));
+        self
+    }
+
+    /// Keeps only the most recent `max` writes, so replaying a long stream of traffic
+    /// doesn't grow without bound.
+    pub fn with_max_recorded(mut self, max: usize) -> Self {
+        self.state_mut().max_recorded = Some(max);
+        self
+    }
+
+    /// Wraps a backend so its writes are recorded by this shadow.
+    pub fn wrap<B: StoreBackend + 'static>(self, backend: B) -> ShadowStore<B> {
+        Layered::new(self, backend)
+    }
+
+    /// Returns the number of writes currently recorded.
+    pub fn recorded(&self) -> usize {
+        self.writes().len()
+    }
+
+    /// Removes and returns the recorded writes, oldest first.
+    pub fn take_writes(&self) -> Vec<ShadowWrite> {
+        self.writes().drain(..).collect()
+    }
+
+    fn state_mut(&mut self) -> &mut ShadowState {
+        // Only clones share the state, so a fresh configuration is made for a shared one
+        if Arc::get_mut(&mut self.state).is_none() {
+            self.state = Arc::new(ShadowState {
+                scratch: self.state.scratch.clone(),
+                max_recorded: self.state.max_recorded,
+                writes: Mutex::default(),
+            });
+        }
+
+        Arc::get_mut(&mut self.state).expect("the state was just made unique")
+    }
+
+    fn writes(&self) -> std::sync::MutexGuard<'_, VecDeque<ShadowWrite>> {
+        // A panic while recording leaves the writes consistent, so poisoning is ignored
+        self.state
+            .writes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn record(&self, write: ShadowWrite) {
+        let mut writes = self.writes();
+
+        writes.push_back(write);
+        if let Some(max) = self.state.max_recorded {
+            while writes.len() > max {
+                writes.pop_front();
+            }
+        }
+    }
+
+    /// Works out the outcome a write would have had on the backend, without applying it.
+    async fn synthesize(
+        operation: &Operation,
+        backend: &dyn DynStoreBackend,
+    ) -> DocumentStoreResult<Outcome> {
+        Ok(match operation {
+            Operation::InsertDocuments { documents, collection, policy } => {
+                let mut seen = HashSet::new();
+                let mut report = WriteReport::default();
+
+                for (id, _) in documents {
+                    let exists = !seen.insert(*id)
+                        || !backend
+                            .get_documents(vec![*id], collection)
+                            .await?
+                            .is_empty();
+
+                    match (exists, policy) {
+                        (false, _) => report.upserted += 1,
+                        (true, InsertPolicy::ErrorOnConflict) => {
+                            return Err(DocumentStoreError::DocumentAlreadyExists(
+                                id.to_string(),
+                                collection.clone(),
+                            ));
+                        }
+                        (true, InsertPolicy::Replace) => {
+                            report.matched += 1;
+                            report.modified += 1;
+                        }
+                        (true, InsertPolicy::Skip) => report.matched += 1,
+                    }
+                }
+
+                Outcome::Report(report)
+            }
+            Operation::UpdateDocuments { documents, collection, policy } => {
+                let ids = documents
+                    .iter()
+                    .map(|(id, _)| *id)
+                    .collect::<HashSet<_>>()
+                    .into_iter()
+                    .collect::<Vec<_>>();
+                let existing = backend
+                    .get_documents(ids.clone(), collection)
+                    .await?
+                    .len();
+
+                if *policy == MissingDocumentPolicy::Error && existing < ids.len() {
+                    return Err(Self::missing(&ids, collection, backend).await?);
+                }
+
+                Outcome::Report(WriteReport {
+                    matched: existing,
+                    modified: existing,
+                    upserted: match policy {
+                        MissingDocumentPolicy::Upsert => ids.len() - existing,
+                        _ => 0,
+                    },
+                })
+            }
+            Operation::UpdateDocumentsIfVersion { documents, version_field, collection } => {
+                for (id, _, expected) in documents {
+                    let Some(stored) = backend
+                        .get_documents(vec![*id], collection)
+                        .await?
+                        .pop()
+                    else {
+                        return Err(Self::missing(&[*id], collection, backend).await?);
+                    };
+
+                    if stored_version(&stored, version_field) != Some(*expected) {
+                        return Err(DocumentStoreError::VersionConflict(
+                            id.to_string(),
+                            collection.clone(),
+                            *expected,
+                        ));
+                    }
+                }
+
+                Outcome::Report(WriteReport {
+                    matched: documents.len(),
+                    modified: documents.len(),
+                    upserted: 0,
+                })
+            }
+            Operation::UpdateDocumentsIfMatch { documents, collection } => {
+                Self::check_revisions(
+                    documents
+                        .iter()
+                        .map(|(id, _, revision)| (*id, revision.as_str())),
+                    collection,
+                    backend,
+                )
+                .await?;
+
+                Outcome::Report(WriteReport {
+                    matched: documents.len(),
+                    modified: documents.len(),
+                    upserted: 0,
+                })
+            }
+            Operation::DeleteDocumentsIfMatch { documents, collection } => {
+                Self::check_revisions(
+                    documents
+                        .iter()
+                        .map(|(id, revision)| (*id, revision.as_str())),
+                    collection,
+                    backend,
+                )
+                .await?;
+
+                Outcome::Count(documents.len())
+            }
+            Operation::DeleteDocuments { ids, collection } => Outcome::Count(
+                backend
+                    .get_documents(
+                        ids.iter()
+                            .copied()
+                            .collect::<HashSet<_>>()
+                            .into_iter()
+                            .collect(),
+                        collection,
+                    )
+                    .await?
+                    .len(),
+            ),
+            Operation::UpdateByQuery { filter, collection, .. } => {
+                let matched = backend
+                    .count_documents(
+                        Query::builder()
+                            .filter(filter.clone())
+                            .build(),
+                        collection,
+                    )
+                    .await?;
+
+                Outcome::Report(WriteReport { matched, modified: matched, upserted: 0 })
+            }
+            Operation::DeleteByQuery { filter, collection } => Outcome::Count(
+                backend
+                    .count_documents(
+                        Query::builder()
+                            .filter(filter.clone())
+                            .build(),
+                        collection,
+                    )
+                    .await?,
+            ),
+            // The update isn't applied, so the document is returned as stored either way
+            Operation::FindOneAndUpdate { filter, sort, collection, .. }
+            | Operation::FindOneAndDelete { filter, sort, collection } => Outcome::Document(
+                backend
+                    .query_documents(
+                        Query {
+                            filter: Some(filter.clone()),
+                            sort: sort.clone(),
+                            limit: Some(1),
+                            ..Query::default()
+                        },
+                        collection,
+                    )
+                    .await?
+                    .pop(),
+            ),
+            _ => Outcome::Done,
+        })
+    }
+
+    /// Checks that the stored documents are at the revisions of a conditional write, to fail
+    /// it like the backend.
+    async fn check_revisions(
+        revisions: impl Iterator<Item = (Uuid, &str)>,
+        collection: &str,
+        backend: &dyn DynStoreBackend,
+    ) -> DocumentStoreResult<()> {
+        for (id, revision) in revisions {
+            let stored = backend
+                .get_documents(vec![id], collection)
+                .await?
+                .pop();
+
+            if stored
+                .map(|stored| document_revision(&stored))
+                .transpose()?
+                .as_deref()
+                != Some(revision)
+            {
+                return Err(DocumentStoreError::PreconditionFailed(
+                    id.to_string(),
+                    collection.to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds a document of an update that doesn't exist, to report it like the backend.
+    async fn missing(
+        ids: &[Uuid],
+        collection: &str,
+        backend: &dyn DynStoreBackend,
+    ) -> DocumentStoreResult<DocumentStoreError> {
+        if !backend
+            .collection_exists(collection)
+            .await?
+        {
+            return Ok(DocumentStoreError::CollectionNotFound(collection.to_string()));
+        }
+
+        let mut missing = ids[0];
+        for id in ids {
+            if backend
+                .get_documents(vec![*id], collection)
+                .await?
+                .is_empty()
+            {
+                missing = *id;
+                break;
+            }
+        }
+
+        Ok(DocumentStoreError::DocumentNotFound(
+            missing.to_string(),
+            collection.to_string(),
+        ))
+    }
+}
+
+/// The number of documents an outcome counts as affected.
+fn affected(outcome: &Outcome) -> Option<usize> {
+    match outcome {
+        Outcome::Report(report) => Some(report.matched + report.upserted),
+        Outcome::Count(count) => Some(*count),
+        Outcome::Document(document) => Some(usize::from(document.is_some())),
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl StoreLayer for Shadow {
+    async fn call(&self, operation: Operation, next: Next<'_>) -> DocumentStoreResult<Outcome> {
+        if !operation.is_write() {
+            return next.run(operation).await;
+        }
+
+        let scratch = match &self.state.scratch {
+            Some(scratch) => Some(
+                operation
+                    .clone()
+                    .apply(scratch.as_ref())
+                    .await,
+            ),
+            None => None,
+        };
+        let outcome = Self::synthesize(&operation, next.backend()).await;
+
+        self.record(ShadowWrite {
+            affected: outcome.as_ref().ok().and_then(affected),
+            operation,
+            scratch,
+        });
+
+        outcome
+    }
+}
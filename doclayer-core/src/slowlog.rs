@@ -0,0 +1,139 @@
+//! Reporting queries slower than a threshold.
+//!
+//! A [`SlowQueryStore`] times every query its backend runs and hands those taking longer
+//! than the threshold to a callback, along with the [`Query`], the collection and the number
+//! of documents returned. Timing happens above the backend, so the same threshold works for
+//! every backend and a slow query shows up whichever one ran it.
+//!
+//! The queries reported are those of `query_documents`, `query_raw_documents`,
+//! `count_documents` and `query_stream`. A stream is timed until it is opened; the time
+//! spent consuming it belongs to the caller.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use std::time::Duration;
+//!
+//! let store = DocumentStore::new(backend).with_slow_query_log(Duration::from_millis(200), |slow| {
+//!     eprintln!("slow query on {} took {:?}: {}", slow.collection, slow.duration, slow.query);
+//! });
+//! ```
+
+use async_trait::async_trait;
+use std::{fmt, sync::Arc, time::Duration};
+
+use crate::{
+    backend::{Layered, Next, Operation, Outcome, StoreBackend, StoreLayer},
+    error::DocumentStoreResult,
+    query::Query,
+    time::Instant,
+};
+
+/// A backend whose slow queries are reported by a [`SlowQueryLog`] layer.
+pub type SlowQueryStore<B> = Layered<SlowQueryLog, B>;
+
+/// A query that took longer than the threshold of a [`SlowQueryLog`].
+#[derive(Debug, Clone)]
+pub struct SlowQuery {
+    /// The [`StoreBackend`] method that ran the query, such as `query_documents`.
+    pub operation: &'static str,
+    /// The collection queried.
+    pub collection: String,
+    /// The query as passed to the backend.
+    pub query: Query,
+    /// The time the backend took.
+    pub duration: Duration,
+    /// The number of documents returned or counted, or `None` if the query failed or
+    /// returned a stream.
+    pub returned: Option<usize>,
+}
+
+type SlowQueryCallback = Arc<dyn Fn(&SlowQuery) + Send + Sync>;
+
+/// A [`StoreLayer`] passing queries slower than a threshold to a callback.
+#[derive(Clone)]
+pub struct SlowQueryLog {
+    threshold: Duration,
+    callback: SlowQueryCallback,
+}
+
+impl fmt::Debug for SlowQueryLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SlowQueryLog")
+            .field("threshold", &self.threshold)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SlowQueryLog {
+    /// Creates a layer calling `callback` for every query taking longer than `threshold`.
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold` - The duration a query must exceed to be reported
+    /// * `callback` - Called with each slow query, after the backend returns and before the
+    ///   caller receives the result, so it should hand slow work off elsewhere
+    pub fn new(threshold: Duration, callback: impl Fn(&SlowQuery) + Send + Sync + 'static) -> Self {
+        Self { threshold, callback: Arc::new(callback) }
+    }
+
+    /// Returns the duration a query must exceed to be reported.
+    pub fn threshold(&self) -> Duration {
+        self.threshold
+    }
+
+    /// Wraps a backend so its slow queries are reported.
+    pub fn wrap<B: StoreBackend + 'static>(self, backend: B) -> SlowQueryStore<B> {
+        Layered::new(self, backend)
+    }
+}
+
+/// The query and collection of an operation running one.
+fn query_of(operation: &Operation) -> Option<(&Query, &str)> {
+    match operation {
+        Operation::QueryDocuments { query, collection }
+        | Operation::QueryStream { query, collection }
+        | Operation::QueryRawDocuments { query, collection }
+        | Operation::CountDocuments { query, collection } => Some((query, collection)),
+        _ => None,
+    }
+}
+
+/// The number of documents an outcome returns or counts.
+fn returned(outcome: &Outcome) -> Option<usize> {
+    match outcome {
+        Outcome::Count(count) => Some(*count),
+        Outcome::Documents(documents) => Some(documents.len()),
+        Outcome::RawDocuments(documents) => Some(documents.len()),
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl StoreLayer for SlowQueryLog {
+    async fn call(&self, operation: Operation, next: Next<'_>) -> DocumentStoreResult<Outcome> {
+        // The operation is moved into the backend, so the query is cloned to report it
+        let Some((query, collection)) =
+            query_of(&operation).map(|(query, collection)| (query.clone(), collection.to_string()))
+        else {
+            return next.run(operation).await;
+        };
+
+        let name = operation.name();
+        let started = Instant::now();
+        let outcome = next.run(operation).await;
+        let duration = started.elapsed();
+
+        if duration > self.threshold {
+            (self.callback)(&SlowQuery {
+                operation: name,
+                collection,
+                query,
+                duration,
+                returned: outcome.as_ref().ok().and_then(returned),
+            });
+        }
+
+        outcome
+    }
+}
@@ -0,0 +1,652 @@
+//! Splitting reads from writes across a primary backend and its replicas.
+//!
+//! [`SplitStore`] sends every write to a primary backend and serves document reads from one
+//! of several replicas, such as the secondaries of a replicated database. A
+//! [`ReplicaPolicy`] chooses the replica for each read: [`RoundRobin`] spreads reads evenly
+//! and [`LowestLatency`] prefers the replica that has been answering fastest.
+//!
+//! Replicas usually lag behind the primary, so a document may not be readable right after it
+//! was written. Reads that decide how to write rather than what to return, namely
+//! [`collection_exists`](StoreBackend::collection_exists) and
+//! [`current_revision_id`](StoreBackend::current_revision_id), always go to the primary so
+//! migrations and collection setup see their own writes, as does the migration lock.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::split::{LowestLatency, SplitStore};
+//!
+//! let store = DocumentStore::new(
+//!     SplitStore::new(primary, vec![replica_a, replica_b])
+//!         .with_policy(LowestLatency::new()),
+//! );
+//! ```
+//!
+//! The latency and failures each replica has seen are available from
+//! [`SplitStore::replica_stats`].
+
+use async_trait::async_trait;
+use bson::{Bson, RawDocumentBuf, Uuid};
+use std::{
+    fmt,
+    future::Future,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::{
+    aggregate::Aggregate,
+    backend::{
+        DocumentStream, DynStoreBackend, InsertPolicy, MissingDocumentPolicy, QueryPlan,
+        ReturnDocument, StoreBackend, WriteReport,
+    },
+    error::DocumentStoreResult,
+    query::{Expr, Query, Sort},
+    time::Instant,
+    update::Update,
+};
+
+/// Chooses the replica serving a read of a [`SplitStore`].
+pub trait ReplicaPolicy: Send + Sync {
+    /// Returns the index of the replica serving the next read.
+    ///
+    /// # Arguments
+    ///
+    /// * `replicas` - The current statistics of every replica, never empty
+    fn select(&self, replicas: &[ReplicaStats]) -> usize;
+}
+
+/// How a replica of a [`SplitStore`] has been answering reads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplicaStats {
+    /// The moving average of the latency of successful reads, `None` before the first one.
+    pub latency: Option<Duration>,
+    /// The reads served, successful or not.
+    pub reads: u64,
+    /// The reads that failed.
+    pub failures: u64,
+    /// The reads that failed since the last successful one.
+    pub consecutive_failures: u64,
+}
+
+/// Sends reads to each replica in turn.
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    next: AtomicUsize,
+}
+
+impl RoundRobin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ReplicaPolicy for RoundRobin {
+    fn select(&self, replicas: &[ReplicaStats]) -> usize {
+        self.next
+            .fetch_add(1, Ordering::Relaxed)
+            % replicas.len()
+    }
+}
+
+/// Sends reads to the replica with the lowest average latency.
+///
+/// Replicas that haven't answered yet are tried first, and replicas whose last read failed
+/// are avoided while another one is healthy. Since a replica that was slow once would
+/// otherwise never be measured again, every [probe interval](LowestLatency::with_probe_interval)
+/// reads one read goes to the replicas in turn.
+#[derive(Debug)]
+pub struct LowestLatency {
+    probe_interval: u64,
+    reads: AtomicU64,
+}
+
+impl LowestLatency {
+    /// The default number of reads between probes of every replica.
+    pub const DEFAULT_PROBE_INTERVAL: u64 = 100;
+
+    pub fn new() -> Self {
+        Self {
+            probe_interval: Self::DEFAULT_PROBE_INTERVAL,
+            reads: AtomicU64::new(0),
+        }
+    }
+
+    /// Sets how many reads are made between reads sent to the replicas in turn.
+    ///
+    /// An interval of 0 disables probing.
+    pub fn with_probe_interval(mut self, interval: u64) -> Self {
+        self.probe_interval = interval;
+        self
+    }
+}
+
+impl Default for LowestLatency {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReplicaPolicy for LowestLatency {
+    fn select(&self, replicas: &[ReplicaStats]) -> usize {
+        let reads = self
+            .reads
+            .fetch_add(1, Ordering::Relaxed);
+
+        if self.probe_interval > 0 && reads % self.probe_interval == self.probe_interval - 1 {
+            return (reads / self.probe_interval) as usize % replicas.len();
+        }
+
+        if let Some(unmeasured) = replicas
+            .iter()
+            .position(|stats| stats.latency.is_none())
+        {
+            return unmeasured;
+        }
+
+        let healthy = replicas
+            .iter()
+            .any(|stats| stats.consecutive_failures == 0);
+
+        replicas
+            .iter()
+            .enumerate()
+            .filter(|(_, stats)| !healthy || stats.consecutive_failures == 0)
+            .min_by_key(|(_, stats)| stats.latency)
+            .map_or(0, |(index, _)| index)
+    }
+}
+
+/// The statistics of a replica, updated as its reads complete.
+#[derive(Debug, Default)]
+struct ReplicaState {
+    /// The moving average latency in nanoseconds, 0 before the first successful read
+    latency: AtomicU64,
+    reads: AtomicU64,
+    failures: AtomicU64,
+    consecutive_failures: AtomicU64,
+}
+
+impl ReplicaState {
+    /// The weight of a new latency sample in the moving average, in eighths.
+    const SAMPLE_WEIGHT: u64 = 2;
+
+    fn record(&self, latency: Duration, success: bool) {
+        self.reads
+            .fetch_add(1, Ordering::Relaxed);
+
+        if !success {
+            self.failures
+                .fetch_add(1, Ordering::Relaxed);
+            self.consecutive_failures
+                .fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        let sample = u64::try_from(latency.as_nanos())
+            .unwrap_or(u64::MAX)
+            .max(1);
+        let _ = self
+            .latency
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |average| {
+                Some(match average {
+                    0 => sample,
+                    // Kept above 0, which marks a replica that was never measured
+                    average => ((average / 8) * (8 - Self::SAMPLE_WEIGHT)
+                        + (sample / 8) * Self::SAMPLE_WEIGHT)
+                        .max(1),
+                })
+            });
+        self.consecutive_failures
+            .store(0, Ordering::Relaxed);
+    }
+
+    fn stats(&self) -> ReplicaStats {
+        ReplicaStats {
+            latency: match self.latency.load(Ordering::Relaxed) {
+                0 => None,
+                nanos => Some(Duration::from_nanos(nanos)),
+            },
+            reads: self.reads.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            consecutive_failures: self
+                .consecutive_failures
+                .load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A backend writing to a primary backend and reading from its replicas.
+///
+/// See the [module documentation](self) for which operations go where. Without replicas,
+/// every read goes to the primary.
+pub struct SplitStore<P, R> {
+    primary: P,
+    replicas: Vec<R>,
+    states: Vec<ReplicaState>,
+    policy: Arc<dyn ReplicaPolicy>,
+}
+
+impl<P: StoreBackend, R: StoreBackend> SplitStore<P, R> {
+    /// Writes to `primary` and reads from `replicas` in turn.
+    ///
+    /// # Arguments
+    ///
+    /// * `primary` - The backend receiving every write
+    /// * `replicas` - The backends serving reads, holding copies of the primary's documents
+    pub fn new(primary: P, replicas: Vec<R>) -> Self {
+        Self {
+            primary,
+            states: replicas
+                .iter()
+                .map(|_| ReplicaState::default())
+                .collect(),
+            replicas,
+            policy: Arc::new(RoundRobin::new()),
+        }
+    }
+
+    /// Sets the policy choosing the replica serving each read, [`RoundRobin`] by default.
+    pub fn with_policy(mut self, policy: impl ReplicaPolicy + 'static) -> Self {
+        self.policy = Arc::new(policy);
+        self
+    }
+
+    /// Returns the backend receiving every write.
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    /// Returns the backends serving reads.
+    pub fn replicas(&self) -> &[R] {
+        &self.replicas
+    }
+
+    /// Returns the statistics of every replica, in the order they were given.
+    pub fn replica_stats(&self) -> Vec<ReplicaStats> {
+        self.states
+            .iter()
+            .map(ReplicaState::stats)
+            .collect()
+    }
+
+    /// Unwraps the primary and the replicas.
+    pub fn into_inner(self) -> (P, Vec<R>) {
+        (self.primary, self.replicas)
+    }
+
+    /// Chooses the backend serving a read, along with the index of the replica if it is one.
+    fn read(&self) -> (Option<usize>, &dyn DynStoreBackend)
+    where
+        P: 'static,
+        R: 'static,
+    {
+        if self.replicas.is_empty() {
+            return (None, &self.primary);
+        }
+
+        let index = self
+            .policy
+            .select(&self.replica_stats())
+            % self.replicas.len();
+
+        (Some(index), &self.replicas[index])
+    }
+
+    /// Runs a read, recording its latency and outcome if a replica serves it.
+    async fn timed<T>(
+        &self,
+        replica: Option<usize>,
+        read: impl Future<Output = DocumentStoreResult<T>>,
+    ) -> DocumentStoreResult<T> {
+        let Some(index) = replica else {
+            return read.await;
+        };
+
+        let started = Instant::now();
+        let result = read.await;
+        self.states[index].record(started.elapsed(), result.is_ok());
+
+        result
+    }
+}
+
+impl<P: fmt::Debug, R: fmt::Debug> fmt::Debug for SplitStore<P, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SplitStore")
+            .field("primary", &self.primary)
+            .field("replicas", &self.replicas)
+            .field("states", &self.states)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<P: StoreBackend + 'static, R: StoreBackend + 'static> StoreBackend for SplitStore<P, R> {
+    async fn insert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.primary
+            .insert_documents(documents, collection, policy)
+            .await
+    }
+
+    async fn update_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.primary
+            .update_documents(documents, collection, policy)
+            .await
+    }
+
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.primary
+            .update_documents_if_version(documents, version_field, collection)
+            .await
+    }
+
+    async fn update_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, Bson, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.primary
+            .update_documents_if_match(documents, collection)
+            .await
+    }
+
+    async fn upsert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+    ) -> DocumentStoreResult<()> {
+        self.primary
+            .upsert_documents(documents, collection)
+            .await
+    }
+
+    async fn delete_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        self.primary
+            .delete_documents(ids, collection)
+            .await
+    }
+
+    async fn delete_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        self.primary
+            .delete_documents_if_match(documents, collection)
+            .await
+    }
+
+    async fn update_by_query(
+        &self,
+        filter: Expr,
+        update: Update,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.primary
+            .update_by_query(filter, update, collection)
+            .await
+    }
+
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize> {
+        self.primary
+            .delete_by_query(filter, collection)
+            .await
+    }
+
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        self.primary
+            .find_one_and_update(filter, sort, update, returned, collection)
+            .await
+    }
+
+    async fn find_one_and_delete(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        self.primary
+            .find_one_and_delete(filter, sort, collection)
+            .await
+    }
+
+    async fn get_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        let (replica, backend) = self.read();
+
+        self.timed(replica, backend.get_documents(ids, collection))
+            .await
+    }
+
+    async fn query_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        let (replica, backend) = self.read();
+
+        self.timed(replica, backend.query_documents(query, collection))
+            .await
+    }
+
+    async fn query_stream(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<DocumentStream> {
+        let (replica, backend) = self.read();
+
+        // Only opening the stream is timed, since the caller decides how fast it is read
+        self.timed(replica, backend.query_stream(query, collection))
+            .await
+    }
+
+    async fn query_raw_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<RawDocumentBuf>> {
+        let (replica, backend) = self.read();
+
+        self.timed(replica, backend.query_raw_documents(query, collection))
+            .await
+    }
+
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize> {
+        let (replica, backend) = self.read();
+
+        self.timed(replica, backend.count_documents(query, collection))
+            .await
+    }
+
+    async fn distinct(
+        &self,
+        field: &str,
+        filter: Option<Expr>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        let (replica, backend) = self.read();
+
+        self.timed(replica, backend.distinct(field, filter, collection))
+            .await
+    }
+
+    async fn aggregate(
+        &self,
+        aggregate: Aggregate,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        let (replica, backend) = self.read();
+
+        self.timed(replica, backend.aggregate(aggregate, collection))
+            .await
+    }
+
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan> {
+        let (replica, backend) = self.read();
+
+        self.timed(replica, backend.explain(query, collection))
+            .await
+    }
+
+    async fn current_revision_id(&self) -> DocumentStoreResult<Option<String>> {
+        self.primary.current_revision_id().await
+    }
+
+    async fn set_revision_id(&self, revision_id: &str) -> DocumentStoreResult<()> {
+        self.primary
+            .set_revision_id(revision_id)
+            .await
+    }
+
+    async fn acquire_migration_lock(
+        &self,
+        owner: &str,
+        ttl: Duration,
+    ) -> DocumentStoreResult<bool> {
+        self.primary
+            .acquire_migration_lock(owner, ttl)
+            .await
+    }
+
+    async fn release_migration_lock(&self, owner: &str) -> DocumentStoreResult<()> {
+        self.primary
+            .release_migration_lock(owner)
+            .await
+    }
+
+    async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.primary
+            .create_collection(name)
+            .await
+    }
+
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        self.primary
+            .collection_exists(name)
+            .await
+    }
+
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.primary
+            .ensure_collection(name)
+            .await
+    }
+
+    async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.primary.drop_collection(name).await
+    }
+
+    async fn list_collections(&self) -> DocumentStoreResult<Vec<String>> {
+        let (replica, backend) = self.read();
+
+        self.timed(replica, backend.list_collections())
+            .await
+    }
+
+    async fn add_field(
+        &self,
+        collection: &str,
+        field: &str,
+        default: Bson,
+    ) -> DocumentStoreResult<()> {
+        self.primary
+            .add_field(collection, field, default)
+            .await
+    }
+
+    async fn drop_field(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.primary
+            .drop_field(collection, field)
+            .await
+    }
+
+    async fn rename_field(
+        &self,
+        collection: &str,
+        field: &str,
+        new: &str,
+    ) -> DocumentStoreResult<()> {
+        self.primary
+            .rename_field(collection, field, new)
+            .await
+    }
+
+    async fn add_index(
+        &self,
+        collection: &str,
+        field: &str,
+        unique: bool,
+    ) -> DocumentStoreResult<()> {
+        self.primary
+            .add_index(collection, field, unique)
+            .await
+    }
+
+    async fn drop_index(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.primary
+            .drop_index(collection, field)
+            .await
+    }
+
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.primary
+            .expire_documents(collection, field)
+            .await
+    }
+
+    fn inner_backend(&self) -> Option<&dyn DynStoreBackend> {
+        Some(&self.primary)
+    }
+
+    async fn close(&self) -> DocumentStoreResult<()> {
+        let mut result = self.primary.close().await;
+        for replica in &self.replicas {
+            result = result.and(replica.close().await);
+        }
+
+        result
+    }
+
+    async fn shutdown(self) -> DocumentStoreResult<()> {
+        let mut result = self.primary.shutdown().await;
+        for replica in self.replicas {
+            result = result.and(replica.shutdown().await);
+        }
+
+        result
+    }
+}
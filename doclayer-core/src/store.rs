@@ -19,14 +19,28 @@
 //! ```
 
 use bson::Bson;
+use std::time::Duration;
 
 use crate::{
     backend::{DynStoreBackend, StoreBackend},
     collection::{Collection, DynCollection, DynTypedCollection, TypedCollection},
     document::Document,
-    error::DocumentStoreResult,
+    error::{DocumentStoreError, DocumentStoreResult},
+    events::{EventBus, EventedStore},
+    lint::{self, LintReport},
+    prefix::Prefixed,
+    retry::{Retry, RetryStore},
+    schema::{self, JsonSchema, SchemaDiff, SchemaRegistry, SchemaStore, SchemaType},
+    slowlog::{SlowQuery, SlowQueryLog, SlowQueryStore},
+    timeout::{Timeout, TimeoutStore},
+    transaction::Transaction,
 };
 
+#[cfg(feature = "metrics")]
+use crate::metrics::{Metrics, MetricsStore};
+#[cfg(feature = "tracing")]
+use crate::trace::{Traced, TracedStore};
+
 /// A strongly-typed document store bound to a specific backend implementation.
 ///
 /// This struct provides access to a document store with compile-time knowledge of the backend type.
@@ -53,6 +67,125 @@ impl<B: StoreBackend> DocumentStore<B> {
         Self { backend }
     }
 
+    /// Namespaces the store by prefixing every collection name with `prefix`.
+    ///
+    /// Every operation goes through the prefix, including migrations, whose revision is
+    /// tracked per prefix. Several environments can then share one database, e.g.
+    /// `with_prefix("staging_")` stores users in `staging_users`.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The prefix prepended to collection names
+    pub fn with_prefix(self, prefix: impl Into<String>) -> DocumentStore<Prefixed<B>>
+    where
+        B: 'static,
+    {
+        DocumentStore::new(Prefixed::new(self.backend, prefix))
+    }
+
+    /// Publishes the documents inserted, updated and deleted by every write, for
+    /// [`events`](DocumentStore::events) subscribers.
+    ///
+    /// See the [`events`](crate::events) module.
+    pub fn with_events(self) -> DocumentStore<EventedStore<B>>
+    where
+        B: 'static,
+    {
+        DocumentStore::new(EventedStore::new(self.backend))
+    }
+
+    /// Validates the documents written to each collection against its JSON Schema in
+    /// `schemas`, failing writes with violations.
+    ///
+    /// See the [`schema`](crate::schema) module.
+    pub fn with_schemas(self, schemas: SchemaRegistry) -> DocumentStore<SchemaStore<B>>
+    where
+        B: 'static,
+    {
+        DocumentStore::new(SchemaStore::new(self.backend, schemas))
+    }
+
+    /// Calls `callback` for every query the backend takes longer than `threshold` to run,
+    /// with the query, the collection, the duration and the number of documents returned.
+    ///
+    /// See the [`slowlog`](crate::slowlog) module.
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold` - The duration a query must exceed to be reported
+    /// * `callback` - Called with each slow query
+    pub fn with_slow_query_log(
+        self,
+        threshold: Duration,
+        callback: impl Fn(&SlowQuery) + Send + Sync + 'static,
+    ) -> DocumentStore<SlowQueryStore<B>>
+    where
+        B: 'static,
+    {
+        DocumentStore::new(SlowQueryLog::new(threshold, callback).wrap(self.backend))
+    }
+
+    /// Fails every backend operation that runs longer than `timeout` with
+    /// [`DocumentStoreError::Timeout`](crate::error::DocumentStoreError::Timeout). Queries
+    /// built with a [`timeout`](crate::query::QueryBuilder::timeout) of their own use it
+    /// instead.
+    ///
+    /// See the [`timeout`](crate::timeout) module.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The longest an operation may run
+    pub fn with_timeout(self, timeout: Duration) -> DocumentStore<TimeoutStore<B>>
+    where
+        B: 'static,
+    {
+        DocumentStore::new(
+            Timeout::new()
+                .with_default(timeout)
+                .wrap(self.backend),
+        )
+    }
+
+    /// Retries backend operations failing with a
+    /// [retryable](crate::error::DocumentStoreError::is_retryable) error, with exponential
+    /// backoff and jitter starting at 100ms.
+    ///
+    /// See the [`retry`](crate::retry) module.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_attempts` - The most attempts made per operation, the first included
+    pub fn with_retry(self, max_attempts: u32) -> DocumentStore<RetryStore<B>>
+    where
+        B: 'static,
+    {
+        DocumentStore::new(Retry::new(max_attempts).wrap(self.backend))
+    }
+
+    /// Runs every backend operation inside a tracing span, recording the collection, the
+    /// document counts and the duration. Requires the `tracing` feature.
+    ///
+    /// See the [`trace`](crate::trace) module.
+    #[cfg(feature = "tracing")]
+    pub fn with_tracing(self) -> DocumentStore<TracedStore<B>>
+    where
+        B: 'static,
+    {
+        DocumentStore::new(Traced::new().wrap(self.backend))
+    }
+
+    /// Reports every backend operation through the `metrics` crate, counting operations
+    /// and errors and measuring their latency. Requires the `metrics` feature.
+    ///
+    /// See the [`metrics`](crate::metrics) module.
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(self) -> DocumentStore<MetricsStore<B>>
+    where
+        B: 'static,
+    {
+        DocumentStore::new(Metrics::new().wrap(self.backend))
+    }
+
     /// Gets a typed collection for the specified document type.
     ///
     /// The collection name is determined by the document type's `collection_name()` method.
@@ -84,6 +217,36 @@ impl<B: StoreBackend> DocumentStore<B> {
             .await
     }
 
+    /// Checks whether a collection with the given name exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the collection
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the operation fails.
+    pub async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        self.backend
+            .collection_exists(name)
+            .await
+    }
+
+    /// Creates a collection with the given name if it does not already exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the collection to ensure
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if creation fails. An existing collection is not an error.
+    pub async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .ensure_collection(name)
+            .await
+    }
+
     /// Drops (deletes) a collection with the given name.
     ///
     /// # Arguments
@@ -208,6 +371,77 @@ impl<B: StoreBackend> DocumentStore<B> {
             .await
     }
 
+    /// Makes a collection delete its documents once the datetime in a field has passed.
+    ///
+    /// See the [`expiry`](crate::expiry) module.
+    ///
+    /// # Arguments
+    ///
+    /// * `collection` - The name of the collection
+    /// * `field` - The field holding each document's expiry
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the operation fails, e.g. because MongoDB already has an index on
+    /// the field.
+    pub async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .expire_documents(collection, field)
+            .await
+    }
+
+    /// Creates every index declared by a document type's [`Document::index_definitions`],
+    /// and enables the expiry of its [`Document::expires_field`].
+    ///
+    /// Meant to run on startup; creating an index that already exists with the same
+    /// options is a no-op on backends that support indexes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any index cannot be created, e.g. because existing documents
+    /// violate a uniqueness constraint.
+    pub async fn ensure_indexes<D: Document>(&self) -> DocumentStoreResult<()> {
+        for index in D::index_definitions() {
+            self.backend
+                .add_index(D::collection_name(), index.field, index.unique)
+                .await?;
+        }
+
+        if let Some(field) = D::expires_field() {
+            self.backend
+                .expire_documents(D::collection_name(), field)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Begins a transaction, whose writes to any collection are applied together when it is
+    /// committed.
+    ///
+    /// See the [`transaction`](crate::transaction) module.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Unsupported`](crate::error::DocumentStoreError::Unsupported)
+    /// if the backend doesn't support transactions.
+    pub async fn begin_transaction(&self) -> DocumentStoreResult<Transaction> {
+        self.backend.begin_transaction().await
+    }
+
+    /// Closes the store's backend without consuming the store.
+    ///
+    /// Works on every store, including borrowed ones such as those returned by
+    /// [`AsStaticDocumentStore::as_static`], and reaches the innermost backend through
+    /// wrappers. Operations issued after closing may fail.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backend fails to close.
+    pub async fn close(&self) -> DocumentStoreResult<()> {
+        self.backend.close().await
+    }
+
     /// Shuts down the store and releases backend resources.
     ///
     /// This consumes the store and should be called when no longer needed.
@@ -222,6 +456,97 @@ impl<B: StoreBackend> DocumentStore<B> {
     }
 }
 
+impl<B: StoreBackend + 'static> DocumentStore<EventedStore<B>> {
+    /// Returns the bus the store's writes are published on, to subscribe to them.
+    ///
+    /// Clone the bus to keep it after wrapping the store further.
+    pub fn events(&self) -> &EventBus {
+        self.backend.events()
+    }
+}
+
+impl<B: StoreBackend + 'static> DocumentStore<SchemaStore<B>> {
+    /// Returns the schemas the store's writes are validated against, to register more.
+    pub fn schemas(&self) -> &SchemaRegistry {
+        self.backend.schemas()
+    }
+
+    /// Registers the schema of the document type `D` for its collection, so every write to
+    /// the collection, typed or not, is validated against the shape of `D`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::InvalidDocument`] if the schema of `D` is neither an
+    /// object nor a boolean.
+    pub fn register_schema<D: Document + SchemaType>(&self) -> DocumentStoreResult<()> {
+        self.schemas()
+            .register(D::collection_name(), JsonSchema::of::<D>()?);
+
+        Ok(())
+    }
+
+    /// Compares the documents stored in a collection with its registered schema.
+    ///
+    /// Scans every document of the collection and reports the fields violating the schema
+    /// and those it doesn't declare, grouped by field and message with a few example document
+    /// IDs. See the [`schema`](crate::schema) module.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Unsupported`] if the collection has no schema, or an
+    /// error if the collection cannot be read.
+    pub async fn diff_schema(&self, collection: &str) -> DocumentStoreResult<SchemaDiff> {
+        let schema = self
+            .schemas()
+            .get(collection)
+            .ok_or_else(|| {
+                DocumentStoreError::Unsupported(format!(
+                    "Collection {} has no registered schema",
+                    collection
+                ))
+            })?;
+
+        schema::diff_schema(&self.backend, collection, &schema).await
+    }
+}
+
+impl<B: StoreBackend + 'static> DocumentStore<B> {
+    /// Returns the store's backend followed by every backend it wraps, outermost first.
+    ///
+    /// See [`unwrap_layers`](DynStoreBackend::unwrap_layers).
+    pub fn unwrap_layers(&self) -> Vec<&dyn DynStoreBackend> {
+        (&self.backend as &dyn DynStoreBackend).unwrap_layers()
+    }
+
+    /// Finds a backend of the concrete type `B2` anywhere in the store's wrapper chain.
+    ///
+    /// See [`find_backend`](DynStoreBackend::find_backend).
+    pub fn find_backend<B2: StoreBackend + 'static>(&self) -> Option<&B2> {
+        (&self.backend as &dyn DynStoreBackend).find_backend::<B2>()
+    }
+
+    /// Reports the fields of stored documents whose type `D` rejects.
+    ///
+    /// Scans every document of the collection, checking each field of the documents `D`
+    /// fails to read against `D`, and groups the mismatches by field and stored type with a
+    /// few example document IDs and, where possible, a [`LintFix`](crate::lint::LintFix)
+    /// converting the stored values. See the [`lint`](crate::lint) module.
+    ///
+    /// # Arguments
+    ///
+    /// * `collection` - The collection to scan, typically `D::collection_name()`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collection cannot be read.
+    pub async fn lint_collection<D: Document>(
+        &self,
+        collection: &str,
+    ) -> DocumentStoreResult<LintReport> {
+        lint::lint_collection::<D>(&self.backend, collection).await
+    }
+}
+
 #[derive(Debug)]
 pub struct DynDocumentStore {
     backend: Box<dyn DynStoreBackend>,
@@ -250,6 +575,20 @@ impl DynDocumentStore {
             .await
     }
 
+    /// Checks whether a collection with the given name exists.
+    pub async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        self.backend
+            .collection_exists(name)
+            .await
+    }
+
+    /// Creates a collection with the given name if it does not already exist.
+    pub async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .ensure_collection(name)
+            .await
+    }
+
     /// Drops (deletes) a collection with the given name.
     pub async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
         self.backend.drop_collection(name).await
@@ -310,10 +649,75 @@ impl DynDocumentStore {
             .await
     }
 
+    /// Makes a collection delete its documents once the datetime in a field has passed.
+    pub async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .expire_documents(collection, field)
+            .await
+    }
+
+    /// Creates every index declared by a document type's [`Document::index_definitions`],
+    /// and enables the expiry of its [`Document::expires_field`].
+    pub async fn ensure_indexes<D: Document>(&self) -> DocumentStoreResult<()> {
+        for index in D::index_definitions() {
+            self.backend
+                .add_index(D::collection_name(), index.field, index.unique)
+                .await?;
+        }
+
+        if let Some(field) = D::expires_field() {
+            self.backend
+                .expire_documents(D::collection_name(), field)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Reports the fields of stored documents whose type `D` rejects.
+    ///
+    /// See [`DocumentStore::lint_collection`].
+    pub async fn lint_collection<D: Document>(
+        &self,
+        collection: &str,
+    ) -> DocumentStoreResult<LintReport> {
+        lint::lint_collection::<D>(self.backend.as_ref(), collection).await
+    }
+
+    /// Begins a transaction.
+    ///
+    /// See [`DocumentStore::begin_transaction`].
+    pub async fn begin_transaction(&self) -> DocumentStoreResult<Transaction> {
+        self.backend.begin_transaction().await
+    }
+
+    /// Closes the store's backend without consuming the store.
+    ///
+    /// See [`DocumentStore::close`].
+    pub async fn close(&self) -> DocumentStoreResult<()> {
+        self.backend.close().await
+    }
+
     /// Shuts down the store and releases backend resources.
     pub async fn shutdown(self) -> DocumentStoreResult<()> {
         self.backend.shutdown_boxed().await
     }
+
+    /// Returns the store's backend followed by every backend it wraps, outermost first.
+    ///
+    /// See [`unwrap_layers`](DynStoreBackend::unwrap_layers).
+    pub fn unwrap_layers(&self) -> Vec<&dyn DynStoreBackend> {
+        self.backend.unwrap_layers()
+    }
+
+    /// Finds a backend of the concrete type `B` anywhere in the store's wrapper chain.
+    ///
+    /// Unlike [`AsStaticDocumentStore::as_static`], this also succeeds when the backend is
+    /// wrapped by other backends that expose their inner backend, which keeps
+    /// backend-specific APIs reachable through middleware stacks.
+    pub fn find_backend<B: StoreBackend + 'static>(&self) -> Option<&B> {
+        self.backend.find_backend::<B>()
+    }
 }
 
 #[derive(Debug)]
@@ -337,6 +741,11 @@ impl<'a> DynDocumentStoreRef<'a> {
         DynCollection::new(name.to_string(), self.backend)
     }
 
+    /// Returns the backend this reference points to.
+    pub(crate) fn backend(&self) -> &'a dyn DynStoreBackend {
+        self.backend
+    }
+
     /// Gets the current revision ID of the store.
     ///
     /// # Returns
@@ -357,6 +766,33 @@ impl<'a> DynDocumentStoreRef<'a> {
             .await
     }
 
+    /// Tries to take the migration lock of the store.
+    ///
+    /// See [`StoreBackend::acquire_migration_lock`].
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - A unique name of the runner taking the lock
+    /// * `ttl` - How long the lock is held unless released
+    pub async fn acquire_migration_lock(
+        &self,
+        owner: &str,
+        ttl: Duration,
+    ) -> DocumentStoreResult<bool> {
+        self.backend
+            .acquire_migration_lock(owner, ttl)
+            .await
+    }
+
+    /// Releases the migration lock of the store, if it's held by `owner`.
+    ///
+    /// See [`StoreBackend::release_migration_lock`].
+    pub async fn release_migration_lock(&self, owner: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .release_migration_lock(owner)
+            .await
+    }
+
     /// Creates a new collection with the given name.
     pub async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
         self.backend
@@ -364,6 +800,20 @@ impl<'a> DynDocumentStoreRef<'a> {
             .await
     }
 
+    /// Checks whether a collection with the given name exists.
+    pub async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        self.backend
+            .collection_exists(name)
+            .await
+    }
+
+    /// Creates a collection with the given name if it does not already exist.
+    pub async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .ensure_collection(name)
+            .await
+    }
+
     /// Drops (deletes) a collection with the given name.
     pub async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
         self.backend.drop_collection(name).await
@@ -423,6 +873,64 @@ impl<'a> DynDocumentStoreRef<'a> {
             .drop_index(collection, field)
             .await
     }
+
+    /// Makes a collection delete its documents once the datetime in a field has passed.
+    pub async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.backend
+            .expire_documents(collection, field)
+            .await
+    }
+
+    /// Creates every index declared by a document type's [`Document::index_definitions`],
+    /// and enables the expiry of its [`Document::expires_field`].
+    pub async fn ensure_indexes<D: Document>(&self) -> DocumentStoreResult<()> {
+        for index in D::index_definitions() {
+            self.backend
+                .add_index(D::collection_name(), index.field, index.unique)
+                .await?;
+        }
+
+        if let Some(field) = D::expires_field() {
+            self.backend
+                .expire_documents(D::collection_name(), field)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Reports the fields of stored documents whose type `D` rejects.
+    ///
+    /// See [`DocumentStore::lint_collection`].
+    pub async fn lint_collection<D: Document>(
+        &self,
+        collection: &str,
+    ) -> DocumentStoreResult<LintReport> {
+        lint::lint_collection::<D>(self.backend, collection).await
+    }
+
+    /// Closes the referenced backend.
+    ///
+    /// See [`DocumentStore::close`].
+    pub async fn close(&self) -> DocumentStoreResult<()> {
+        self.backend.close().await
+    }
+
+    /// Returns the store's backend followed by every backend it wraps, outermost first.
+    ///
+    /// See [`unwrap_layers`](DynStoreBackend::unwrap_layers).
+    pub fn unwrap_layers(&self) -> Vec<&'a dyn DynStoreBackend> {
+        self.backend.unwrap_layers()
+    }
+
+    /// Finds a backend of the concrete type `B` anywhere in the store's wrapper chain.
+    ///
+    /// Unlike [`AsStaticDocumentStore::as_static`], this also succeeds when the backend is
+    /// wrapped by other backends that expose their inner backend, which keeps
+    /// backend-specific APIs reachable through middleware stacks.
+    pub fn find_backend<B: StoreBackend + 'static>(&self) -> Option<&'a B> {
+        self.backend.find_backend::<B>()
+    }
 }
 
 /// Conversion trait for converting a document store to a dynamic reference.
@@ -0,0 +1,10 @@
+//! The clock timings are measured with.
+//!
+//! `std::time::Instant` panics in the browser, where the clock comes from
+//! `performance.now()`, so WebAssembly builds use `web_time`'s instead. Modules timing
+//! operations import [`Instant`] from here rather than from `std`.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+pub(crate) use web_time::Instant;
@@ -0,0 +1,135 @@
+//! Timeouts for store operations.
+//!
+//! A [`TimeoutStore`] fails backend calls running longer than their timeout with
+//! [`DocumentStoreError::Timeout`], so a hung backend can't stall a request handler
+//! indefinitely. Queries use the [`timeout`](Query::timeout) they were built with, and
+//! every other operation, as well as queries built without one, uses the layer's default,
+//! if it has one.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use std::time::Duration;
+//!
+//! let store = DocumentStore::new(backend).with_timeout(Duration::from_secs(5));
+//!
+//! // Queries can shorten or extend the default
+//! let users = store
+//!     .typed_collection::<User>()
+//!     .find()
+//!     .filter(Filter::eq("active", true))
+//!     .timeout(Duration::from_millis(500))
+//!     .await?;
+//! ```
+//!
+//! Query timeouts are only enforced by this layer and by backends limiting queries
+//! themselves, such as MongoDB's `maxTimeMS`; a store without the layer passes them to the
+//! backend and other backends ignore them.
+//!
+//! Timeouts are enforced with `tokio::time::timeout`, so the store must be used from a tokio
+//! runtime with the time driver enabled. The backend call is dropped when it times out,
+//! which cancels it on the client side; a write the server already received may still be
+//! applied. An operation with a zero timeout, such as a query built from a deadline already
+//! passed, fails before the backend is called. In the browser the layer enforces nothing
+//! else, since there is no tokio timer to wait on, but query timeouts are still passed to the
+//! backend.
+
+use async_trait::async_trait;
+use std::time::Duration;
+
+use crate::{
+    backend::{Layered, Next, Operation, Outcome, StoreBackend, StoreLayer},
+    error::{DocumentStoreError, DocumentStoreResult},
+    query::Query,
+};
+
+/// A backend whose operations are limited by a [`Timeout`] layer.
+pub type TimeoutStore<B> = Layered<Timeout, B>;
+
+/// A [`StoreLayer`] failing operations that run longer than their timeout.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timeout {
+    default: Option<Duration>,
+}
+
+impl Timeout {
+    /// Creates a layer enforcing only the timeouts set on queries.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits every operation without a timeout of its own to `timeout`.
+    pub fn with_default(mut self, timeout: Duration) -> Self {
+        self.default = Some(timeout);
+        self
+    }
+
+    /// Wraps a backend so its operations are limited by this layer.
+    pub fn wrap<B: StoreBackend + 'static>(self, backend: B) -> TimeoutStore<B> {
+        Layered::new(self, backend)
+    }
+
+    /// Returns the timeout applying to an operation, if any.
+    pub fn timeout_of(&self, operation: &Operation) -> Option<Duration> {
+        query_of(operation)
+            .and_then(|query| query.timeout)
+            .or(self.default)
+    }
+}
+
+/// The query of an operation running one.
+fn query_of(operation: &Operation) -> Option<&Query> {
+    match operation {
+        Operation::QueryDocuments { query, .. }
+        | Operation::QueryStream { query, .. }
+        | Operation::QueryRawDocuments { query, .. }
+        | Operation::CountDocuments { query, .. }
+        | Operation::Explain { query, .. } => Some(query),
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl StoreLayer for Timeout {
+    async fn call(&self, operation: Operation, next: Next<'_>) -> DocumentStoreResult<Outcome> {
+        match self.timeout_of(&operation) {
+            // `tokio::time::timeout` polls the operation once before checking the timer
+            Some(timeout) if timeout.is_zero() => {
+                Err(DocumentStoreError::Timeout(describe(&operation, timeout)))
+            },
+            Some(timeout) => run_with_timeout(timeout, operation, next).await,
+            None => next.run(operation).await,
+        }
+    }
+}
+
+/// Describes an operation that ran out of time, for its [`DocumentStoreError::Timeout`].
+fn describe(operation: &Operation, timeout: Duration) -> String {
+    match operation.collection() {
+        Some(collection) => format!("{} on {} after {:?}", operation.name(), collection, timeout),
+        None => format!("{} after {:?}", operation.name(), timeout),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn run_with_timeout(
+    timeout: Duration,
+    operation: Operation,
+    next: Next<'_>,
+) -> DocumentStoreResult<Outcome> {
+    // The operation moves into the backend, so it's described before it runs
+    let description = describe(&operation, timeout);
+
+    tokio::time::timeout(timeout, next.run(operation))
+        .await
+        .map_err(|_| DocumentStoreError::Timeout(description))?
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn run_with_timeout(
+    _timeout: Duration,
+    operation: Operation,
+    next: Next<'_>,
+) -> DocumentStoreResult<Outcome> {
+    next.run(operation).await
+}
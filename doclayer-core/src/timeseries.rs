@@ -0,0 +1,550 @@
+//! Time-bucketed collections for time series documents.
+//!
+//! A [`TimeSeriesCollection`] spreads the documents of one type over several physical
+//! collections, one per day, month or year of a timestamp field (`metrics_2024_06` holds the
+//! metrics recorded in June 2024). Inserts are routed into the bucket of each document, range
+//! queries only read the buckets overlapping the range, and old data is removed or archived a
+//! whole bucket at a time instead of document by document.
+//!
+//! The timestamp field must be stored as a BSON datetime, for example by declaring it as a
+//! `bson::DateTime`. Buckets are cut in UTC.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use chrono::{TimeDelta, Utc};
+//! use doclayer::timeseries::{BucketSpan, TimeSeriesCollection};
+//!
+//! let metrics = TimeSeriesCollection::<Metric>::new(&store, "recorded_at", BucketSpan::Month);
+//!
+//! // Written to `metrics_2024_06`, `metrics_2024_07`, ... depending on `recorded_at`
+//! metrics.insert(samples).await?;
+//!
+//! // Reads only the buckets of the last week
+//! let recent = metrics
+//!     .query_range(Utc::now() - TimeDelta::days(7), Utc::now(), Query::default())
+//!     .await?;
+//!
+//! // Move every bucket older than a year to cold storage
+//! metrics.archive_before(Utc::now() - TimeDelta::days(365), &cold_store).await?;
+//! ```
+
+use bson::{Bson, Uuid};
+use chrono::{DateTime, Datelike, NaiveDate, TimeDelta, Utc};
+use futures::{StreamExt, TryStreamExt};
+use std::{collections::BTreeMap, marker::PhantomData};
+
+use crate::{
+    backend::DynStoreBackend,
+    collection::DynTypedCollection,
+    document::{Document, DocumentExt},
+    error::{DocumentStoreError, DocumentStoreResult},
+    query::{Expr, Filter, Query, SortDirection},
+    store::{AsDynDocumentStore, DynDocumentStoreRef},
+};
+
+/// The number of documents moved per write when archiving a bucket.
+const ARCHIVE_BATCH_SIZE: usize = 1_000;
+
+/// The period of time covered by one bucket collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BucketSpan {
+    /// One bucket per day, named `{name}_{YYYY}_{MM}_{DD}`.
+    Day,
+    /// One bucket per month, named `{name}_{YYYY}_{MM}`.
+    Month,
+    /// One bucket per year, named `{name}_{YYYY}`.
+    Year,
+}
+
+impl BucketSpan {
+    /// Returns the start of the bucket containing `at`.
+    pub fn bucket_start(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        let date = match self {
+            BucketSpan::Day => at.date_naive(),
+            BucketSpan::Month => at
+                .date_naive()
+                .with_day(1)
+                .unwrap_or_default(),
+            BucketSpan::Year => NaiveDate::from_ymd_opt(at.year(), 1, 1).unwrap_or_default(),
+        };
+
+        date.and_time(Default::default())
+            .and_utc()
+    }
+
+    /// Returns the start of the bucket following the one starting at `start`.
+    pub fn next_bucket_start(&self, start: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            BucketSpan::Day => start + TimeDelta::days(1),
+            BucketSpan::Month => {
+                let (year, month) = match start.month() {
+                    12 => (start.year() + 1, 1),
+                    month => (start.year(), month + 1),
+                };
+                NaiveDate::from_ymd_opt(year, month, 1)
+                    .unwrap_or_default()
+                    .and_time(Default::default())
+                    .and_utc()
+            }
+            BucketSpan::Year => NaiveDate::from_ymd_opt(start.year() + 1, 1, 1)
+                .unwrap_or_default()
+                .and_time(Default::default())
+                .and_utc(),
+        }
+    }
+
+    /// Formats the name suffix of the bucket starting at `start`.
+    fn suffix(&self, start: DateTime<Utc>) -> String {
+        match self {
+            BucketSpan::Day => start.format("%Y_%m_%d").to_string(),
+            BucketSpan::Month => start.format("%Y_%m").to_string(),
+            BucketSpan::Year => start.format("%Y").to_string(),
+        }
+    }
+
+    /// Parses a name suffix back into the start of its bucket.
+    fn parse_suffix(&self, suffix: &str) -> Option<DateTime<Utc>> {
+        let parts = suffix
+            .split('_')
+            .map(|part| part.parse::<u32>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        let date = match (self, parts.as_slice()) {
+            (BucketSpan::Day, [year, month, day]) => {
+                NaiveDate::from_ymd_opt(*year as i32, *month, *day)?
+            }
+            (BucketSpan::Month, [year, month]) => NaiveDate::from_ymd_opt(*year as i32, *month, 1)?,
+            (BucketSpan::Year, [year]) => NaiveDate::from_ymd_opt(*year as i32, 1, 1)?,
+            _ => return None,
+        };
+        let start = date
+            .and_time(Default::default())
+            .and_utc();
+
+        // Reject names that parse but aren't in canonical form, like `metrics_2024_6`
+        (self.suffix(start) == suffix).then_some(start)
+    }
+}
+
+/// One physical collection of a time series.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bucket {
+    /// The name of the collection.
+    pub name: String,
+    /// The earliest timestamp the bucket holds.
+    pub start: DateTime<Utc>,
+    /// The start of the next bucket, which this bucket holds no documents from.
+    pub end: DateTime<Utc>,
+}
+
+/// A collection of documents split into time-bucketed physical collections.
+///
+/// Bucket collections are named after the document's collection name unless renamed with
+/// [`with_name`](TimeSeriesCollection::with_name), and are created on the first insert into
+/// them.
+#[derive(Debug)]
+pub struct TimeSeriesCollection<'a, D> {
+    backend: &'a dyn DynStoreBackend,
+    name: String,
+    time_field: String,
+    span: BucketSpan,
+    _marker: PhantomData<D>,
+}
+
+impl<'a, D: Document> TimeSeriesCollection<'a, D> {
+    /// Creates a time series over a store.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store holding the bucket collections
+    /// * `time_field` - The top-level BSON datetime field documents are bucketed by
+    /// * `span` - The period of time covered by each bucket
+    pub fn new<S: AsDynDocumentStore>(
+        store: &'a S,
+        time_field: impl Into<String>,
+        span: BucketSpan,
+    ) -> Self {
+        Self {
+            backend: store.as_dyn().backend(),
+            name: D::collection_name().to_string(),
+            time_field: time_field.into(),
+            span,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sets the name bucket collections are prefixed with, the document's collection name by
+    /// default.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Returns the name bucket collections are prefixed with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the period of time covered by each bucket.
+    pub fn span(&self) -> BucketSpan {
+        self.span
+    }
+
+    /// Returns the bucket a timestamp falls into, whether or not its collection exists.
+    pub fn bucket_for(&self, at: DateTime<Utc>) -> Bucket {
+        let start = self.span.bucket_start(at);
+
+        Bucket {
+            name: format!("{}_{}", self.name, self.span.suffix(start)),
+            start,
+            end: self.span.next_bucket_start(start),
+        }
+    }
+
+    /// Returns a typed collection reading and writing a single bucket.
+    pub fn bucket_collection(&self, bucket: &Bucket) -> DynTypedCollection<'a, D> {
+        DynTypedCollection::new(bucket.name.clone(), self.backend)
+    }
+
+    /// Lists the existing buckets, earliest first.
+    ///
+    /// Collections are recognized by their name, so other collections named like a bucket of
+    /// this series are listed as well.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collections of the store cannot be listed.
+    pub async fn buckets(&self) -> DocumentStoreResult<Vec<Bucket>> {
+        let prefix = format!("{}_", self.name);
+        let mut buckets = self
+            .backend
+            .list_collections()
+            .await?
+            .into_iter()
+            .filter_map(|name| {
+                let start = self
+                    .span
+                    .parse_suffix(name.strip_prefix(&prefix)?)?;
+
+                Some(Bucket {
+                    name,
+                    start,
+                    end: self.span.next_bucket_start(start),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        buckets.sort_by_key(|bucket| bucket.start);
+
+        Ok(buckets)
+    }
+
+    /// Lists the existing buckets holding timestamps in `[start, end)`, earliest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collections of the store cannot be listed.
+    pub async fn buckets_between(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> DocumentStoreResult<Vec<Bucket>> {
+        Ok(self
+            .buckets()
+            .await?
+            .into_iter()
+            .filter(|bucket| bucket.start < end && bucket.end > start)
+            .collect())
+    }
+
+    /// Creates the bucket a timestamp falls into, if it doesn't exist yet.
+    ///
+    /// Buckets are created by inserts as needed; this creates them ahead of time, for example
+    /// to add indexes before data arrives.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collection cannot be created.
+    pub async fn ensure_bucket(&self, at: DateTime<Utc>) -> DocumentStoreResult<Bucket> {
+        let bucket = self.bucket_for(at);
+
+        self.backend
+            .ensure_collection(&bucket.name)
+            .await?;
+
+        Ok(bucket)
+    }
+
+    /// Inserts documents into the buckets of their timestamps.
+    ///
+    /// Documents are inserted one bucket at a time, earliest first, so a failure leaves the
+    /// documents of earlier buckets inserted.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::InvalidDocument`] if a document's timestamp field is
+    /// missing or not a BSON datetime, before anything is written, or an error if a bucket
+    /// cannot be written.
+    pub async fn insert(&self, documents: Vec<D>) -> DocumentStoreResult<()> {
+        for (bucket, documents) in self.group_by_bucket(documents)? {
+            self.backend
+                .ensure_collection(&bucket.name)
+                .await?;
+            self.bucket_collection(&bucket)
+                .insert(documents)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Inserts or replaces documents in the buckets of their timestamps.
+    ///
+    /// A document whose timestamp moved to another bucket is written to the new bucket
+    /// without being removed from the old one.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::InvalidDocument`] if a document's timestamp field is
+    /// missing or not a BSON datetime, before anything is written, or an error if a bucket
+    /// cannot be written.
+    pub async fn upsert(&self, documents: Vec<D>) -> DocumentStoreResult<()> {
+        for (bucket, documents) in self.group_by_bucket(documents)? {
+            self.backend
+                .ensure_collection(&bucket.name)
+                .await?;
+            self.bucket_collection(&bucket)
+                .upsert(documents)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Queries the documents with a timestamp in `[start, end)`.
+    ///
+    /// Every bucket overlapping the range is queried with the query's filter, and the results
+    /// are concatenated bucket by bucket: earliest first, or latest first when the query's
+    /// first sort is on the timestamp field in descending order. Other sorts order the
+    /// documents within each bucket. The query's offset and limit apply to the combined
+    /// results, and buckets past the limit are not read.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a bucket cannot be queried.
+    pub async fn query_range(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        query: Query,
+    ) -> DocumentStoreResult<Vec<D>> {
+        let mut buckets = self.buckets_between(start, end).await?;
+        let descending = query.sort.first().is_some_and(|sort| {
+            sort.field == self.time_field && matches!(sort.direction, SortDirection::Desc)
+        });
+
+        if descending {
+            buckets.reverse();
+        }
+
+        let mut skip = query.offset.unwrap_or(0);
+        let mut remaining = query.limit;
+        let mut documents = Vec::new();
+        let filter = self.range_filter(start, end, query.filter.clone());
+
+        for bucket in buckets {
+            if remaining == Some(0) {
+                break;
+            }
+
+            // Skipped documents may span several buckets, so each one is asked for enough
+            // documents to cover the rest of the offset and the limit
+            let mut found = self
+                .bucket_collection(&bucket)
+                .query(Query {
+                    filter: Some(filter.clone()),
+                    limit: remaining.map(|limit| skip.saturating_add(limit)),
+                    offset: None,
+                    ..query.clone()
+                })
+                .await?;
+            let skipped = skip.min(found.len());
+
+            found.drain(..skipped);
+            skip -= skipped;
+
+            if let Some(limit) = remaining.as_mut() {
+                found.truncate(*limit);
+                *limit -= found.len();
+            }
+
+            documents.extend(found);
+        }
+
+        Ok(documents)
+    }
+
+    /// Counts the documents with a timestamp in `[start, end)` matching a filter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a bucket cannot be counted.
+    pub async fn count_range(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        filter: Option<Expr>,
+    ) -> DocumentStoreResult<usize> {
+        let filter = self.range_filter(start, end, filter);
+        let mut count = 0;
+
+        for bucket in self.buckets_between(start, end).await? {
+            count += self
+                .bucket_collection(&bucket)
+                .count(Query {
+                    filter: Some(filter.clone()),
+                    ..Query::default()
+                })
+                .await?;
+        }
+
+        Ok(count)
+    }
+
+    /// Drops every bucket ending at or before `cutoff`.
+    ///
+    /// Only whole buckets are dropped, so documents older than the cutoff remain in the bucket
+    /// the cutoff falls into.
+    ///
+    /// # Returns
+    ///
+    /// The dropped buckets.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a bucket cannot be dropped. Buckets dropped before the failure stay
+    /// dropped.
+    pub async fn drop_before(&self, cutoff: DateTime<Utc>) -> DocumentStoreResult<Vec<Bucket>> {
+        let buckets = self.buckets_ending_by(cutoff).await?;
+
+        for bucket in &buckets {
+            self.backend
+                .drop_collection(&bucket.name)
+                .await?;
+        }
+
+        Ok(buckets)
+    }
+
+    /// Moves every bucket ending at or before `cutoff` to another store.
+    ///
+    /// Each bucket is copied into a collection of the same name in `target`, in batches, and
+    /// then dropped. A run interrupted while copying leaves the bucket in place and can be
+    /// repeated, as documents are upserted into the target.
+    ///
+    /// # Returns
+    ///
+    /// The archived buckets.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a bucket cannot be read, written to the target or dropped. Buckets
+    /// archived before the failure stay archived.
+    pub async fn archive_before<S: AsDynDocumentStore>(
+        &self,
+        cutoff: DateTime<Utc>,
+        target: &S,
+    ) -> DocumentStoreResult<Vec<Bucket>> {
+        let target = target.as_dyn();
+        let buckets = self.buckets_ending_by(cutoff).await?;
+
+        for bucket in &buckets {
+            target
+                .ensure_collection(&bucket.name)
+                .await?;
+
+            let source = DynDocumentStoreRef::new(self.backend);
+            let destination = target.collection(&bucket.name);
+            let mut batches = source
+                .collection(&bucket.name)
+                .query_stream(Query::default())
+                .await?
+                .map(|document| {
+                    let document = document?;
+                    Ok::<(Uuid, Bson), DocumentStoreError>((
+                        *D::from_bson_encoded(document.clone(), self.backend.encoding())?.id(),
+                        document,
+                    ))
+                })
+                .try_chunks(ARCHIVE_BATCH_SIZE)
+                .map_err(|e| e.1);
+
+            while let Some(batch) = batches.try_next().await? {
+                destination.upsert(batch).await?;
+            }
+
+            self.backend
+                .drop_collection(&bucket.name)
+                .await?;
+        }
+
+        Ok(buckets)
+    }
+
+    /// Lists the existing buckets ending at or before `cutoff`.
+    async fn buckets_ending_by(&self, cutoff: DateTime<Utc>) -> DocumentStoreResult<Vec<Bucket>> {
+        Ok(self
+            .buckets()
+            .await?
+            .into_iter()
+            .filter(|bucket| bucket.end <= cutoff)
+            .collect())
+    }
+
+    /// Restricts a filter to the timestamps in `[start, end)`.
+    fn range_filter(&self, start: DateTime<Utc>, end: DateTime<Utc>, filter: Option<Expr>) -> Expr {
+        Filter::and(
+            [
+                Filter::gte(&self.time_field, bson::DateTime::from_chrono(start)),
+                Filter::lt(&self.time_field, bson::DateTime::from_chrono(end)),
+            ]
+            .into_iter()
+            .chain(filter),
+        )
+    }
+
+    /// Groups documents by the bucket of their timestamp, earliest bucket first.
+    fn group_by_bucket(&self, documents: Vec<D>) -> DocumentStoreResult<Vec<(Bucket, Vec<D>)>> {
+        let mut groups = BTreeMap::<DateTime<Utc>, (Bucket, Vec<D>)>::new();
+
+        for document in documents {
+            let at = match document.to_bson_encoded(self.backend.encoding())? {
+                Bson::Document(fields) => match fields.get(&self.time_field) {
+                    Some(Bson::DateTime(at)) => at.to_chrono(),
+                    _ => {
+                        return Err(DocumentStoreError::InvalidDocument(format!(
+                            "Document {} has no BSON datetime in its {} field",
+                            document.id(),
+                            self.time_field
+                        )));
+                    }
+                },
+                _ => {
+                    return Err(DocumentStoreError::InvalidDocument(format!(
+                        "Document {} did not serialize to a BSON document",
+                        document.id()
+                    )));
+                }
+            };
+            let bucket = self.bucket_for(at);
+
+            groups
+                .entry(bucket.start)
+                .or_insert_with(|| (bucket, Vec::new()))
+                .1
+                .push(document);
+        }
+
+        Ok(groups.into_values().collect())
+    }
+}
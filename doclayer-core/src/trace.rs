@@ -0,0 +1,153 @@
+//! Tracing spans around store operations.
+//!
+//! Requires the `tracing` feature. A [`TracedStore`] opens a span for every [`StoreBackend`]
+//! call and runs the call inside it, so backend latency shows up in distributed traces
+//! alongside the request that caused it. Each span is named `doclayer.store` and carries:
+//!
+//! - `operation` - the [`StoreBackend`] method, such as `insert_documents`
+//! - `collection` - the collection operated on, if any
+//! - `documents` - the number of documents or IDs passed in, for operations taking them
+//! - `returned` - the number of documents returned, counted or affected
+//! - `duration_ms` - the time the backend took, in milliseconds
+//! - `error` - the error, if the operation failed
+//!
+//! The tracer is a [`StoreLayer`], so it stacks with other layers. Stacked outermost, its
+//! spans cover the whole stack and become the parents of spans opened further in.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::trace::Traced;
+//!
+//! let store = DocumentStore::new(Traced::new().wrap(backend));
+//! // Or
+//! let store = DocumentStore::new(backend).with_tracing();
+//! ```
+//!
+//! Streams returned by `query_stream` are traced until the stream is opened; the time spent
+//! consuming them belongs to the caller.
+
+use async_trait::async_trait;
+use tracing::{Instrument, Level, Span, field};
+
+use crate::{
+    backend::{Layered, Next, Operation, Outcome, StoreBackend, StoreLayer},
+    error::DocumentStoreResult,
+    time::Instant,
+};
+
+/// A backend whose operations are traced by a [`Traced`] layer.
+pub type TracedStore<B> = Layered<Traced, B>;
+
+/// A [`StoreLayer`] running every operation inside a `doclayer.store` span.
+#[derive(Debug, Clone, Copy)]
+pub struct Traced {
+    level: Level,
+}
+
+impl Default for Traced {
+    fn default() -> Self {
+        Self { level: Level::INFO }
+    }
+}
+
+impl Traced {
+    /// Creates a tracer opening its spans at the `INFO` level.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens the spans at the given level instead, e.g. `Level::DEBUG` to trace operations
+    /// only when debugging.
+    pub fn with_level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Wraps a backend so its operations are traced.
+    pub fn wrap<B: StoreBackend + 'static>(self, backend: B) -> TracedStore<B> {
+        Layered::new(self, backend)
+    }
+
+    fn span(&self, operation: &Operation) -> Span {
+        // The level of a span is part of its static metadata, so each level needs its own
+        // callsite
+        macro_rules! span {
+            ($level:expr) => {
+                tracing::span!(
+                    $level,
+                    "doclayer.store",
+                    operation = operation.name(),
+                    collection = operation.collection(),
+                    documents = documents(operation),
+                    returned = field::Empty,
+                    duration_ms = field::Empty,
+                    error = field::Empty,
+                )
+            };
+        }
+
+        match self.level {
+            Level::TRACE => span!(Level::TRACE),
+            Level::DEBUG => span!(Level::DEBUG),
+            Level::INFO => span!(Level::INFO),
+            Level::WARN => span!(Level::WARN),
+            _ => span!(Level::ERROR),
+        }
+    }
+}
+
+/// The number of documents or IDs an operation passes to the backend.
+fn documents(operation: &Operation) -> Option<usize> {
+    match operation {
+        Operation::InsertDocuments { documents, .. }
+        | Operation::UpdateDocuments { documents, .. }
+        | Operation::UpsertDocuments { documents, .. } => Some(documents.len()),
+        Operation::UpdateDocumentsIfVersion { documents, .. } => Some(documents.len()),
+        Operation::UpdateDocumentsIfMatch { documents, .. } => Some(documents.len()),
+        Operation::DeleteDocumentsIfMatch { documents, .. } => Some(documents.len()),
+        Operation::DeleteDocuments { ids, .. } | Operation::GetDocuments { ids, .. } => {
+            Some(ids.len())
+        }
+        _ => None,
+    }
+}
+
+/// The number of documents an outcome returns, counts or affects.
+fn returned(outcome: &Outcome) -> Option<usize> {
+    match outcome {
+        Outcome::Report(report) => Some(report.matched + report.upserted),
+        Outcome::Count(count) => Some(*count),
+        Outcome::Documents(documents) => Some(documents.len()),
+        Outcome::Document(document) => Some(usize::from(document.is_some())),
+        Outcome::RawDocuments(documents) => Some(documents.len()),
+        Outcome::Collections(collections) => Some(collections.len()),
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl StoreLayer for Traced {
+    async fn call(&self, operation: Operation, next: Next<'_>) -> DocumentStoreResult<Outcome> {
+        let span = self.span(&operation);
+        let started = Instant::now();
+        let outcome = next
+            .run(operation)
+            .instrument(span.clone())
+            .await;
+
+        span.record("duration_ms", started.elapsed().as_secs_f64() * 1000.0);
+        match &outcome {
+            Ok(outcome) => {
+                if let Some(returned) = returned(outcome) {
+                    span.record("returned", returned);
+                }
+            }
+            Err(error) => {
+                span.record("error", field::display(error));
+            }
+        }
+
+        outcome
+    }
+}
@@ -0,0 +1,445 @@
+//! Transactions applying several writes atomically.
+//!
+//! A [`Transaction`], begun with [`StoreBackend::begin_transaction`], runs operations on
+//! any number of collections and applies their writes together when it is committed. Until
+//! then, only operations run through the transaction see its writes, and a transaction that
+//! is rolled back or dropped leaves the store as it was. A write failing halfway through a
+//! sequence of collections then no longer leaves the ones before it changed.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let transaction = store.begin_transaction().await?;
+//!
+//! transaction
+//!     .typed_collection::<Account>()
+//!     .update(vec![from, to])
+//!     .await?;
+//! transaction
+//!     .typed_collection::<Transfer>()
+//!     .insert(vec![transfer])
+//!     .await?;
+//!
+//! transaction.commit().await?;
+//! ```
+//!
+//! Backends implement [`TransactionBackend`] to support transactions. The in-memory store
+//! buffers writes against a snapshot of the store, and MongoDB runs them in a multi-document
+//! transaction of a client session. Backends without transactions fail with
+//! [`DocumentStoreError::Unsupported`](crate::error::DocumentStoreError::Unsupported), as do
+//! wrappers spreading operations over several backends, such as a cache or a mirror.
+//! Operations run through a transaction don't pass through the [`StoreLayer`](crate::backend::StoreLayer)s
+//! of a [`Layered`](crate::backend::Layered) backend.
+
+use async_trait::async_trait;
+use bson::{Bson, RawDocumentBuf, Uuid};
+use std::fmt::Debug;
+
+use crate::{
+    aggregate::Aggregate,
+    backend::{
+        DocumentStream, DynStoreBackend, InsertPolicy, MissingDocumentPolicy, QueryPlan,
+        ReturnDocument, StoreBackend, WriteReport,
+    },
+    collection::{DynCollection, DynTypedCollection},
+    document::Document,
+    encoding::Encoding,
+    error::DocumentStoreResult,
+    query::{Expr, Query, Sort},
+    store::DynDocumentStoreRef,
+    update::Update,
+};
+
+/// A transaction of a backend, as returned inside a [`Transaction`].
+///
+/// The transaction's operations run on [`backend`](TransactionBackend::backend), which must
+/// keep its writes invisible to every other operation until they are committed.
+#[async_trait]
+pub trait TransactionBackend: Send + Sync + Debug {
+    /// Returns the backend running the transaction's operations.
+    fn backend(&self) -> &dyn DynStoreBackend;
+
+    /// Applies the transaction's writes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the writes couldn't be applied, in which case none of them are,
+    /// such as [`DocumentStoreError::Conflict`](crate::error::DocumentStoreError::Conflict)
+    /// when another writer changed a document the transaction wrote.
+    async fn commit(self: Box<Self>) -> DocumentStoreResult<()>;
+
+    /// Discards the transaction's writes.
+    async fn rollback(self: Box<Self>) -> DocumentStoreResult<()>;
+}
+
+/// A transaction begun with [`StoreBackend::begin_transaction`].
+///
+/// The transaction is itself a [`StoreBackend`], running every operation inside the
+/// transaction, so wrappers such as [`Prefixed`](crate::prefix::Prefixed) can wrap it.
+/// Closing it does nothing; it ends with [`commit`](Transaction::commit) or
+/// [`rollback`](Transaction::rollback).
+#[derive(Debug)]
+pub struct Transaction {
+    inner: Box<dyn TransactionBackend>,
+}
+
+impl Transaction {
+    /// Wraps a backend's transaction.
+    pub fn new(inner: impl TransactionBackend + 'static) -> Self {
+        Self { inner: Box::new(inner) }
+    }
+
+    /// Gets a typed collection whose operations run inside the transaction.
+    pub fn typed_collection<D: Document>(&self) -> DynTypedCollection<'_, D> {
+        DynTypedCollection::new(D::collection_name().to_string(), self.inner.backend())
+    }
+
+    /// Gets an untyped collection whose operations run inside the transaction.
+    pub fn collection(&self, name: &str) -> DynCollection<'_> {
+        DynCollection::new(name.to_string(), self.inner.backend())
+    }
+
+    /// Returns a store whose operations run inside the transaction, for the operations not
+    /// tied to a collection, such as creating one.
+    pub fn store(&self) -> DynDocumentStoreRef<'_> {
+        DynDocumentStoreRef::new(self.inner.backend())
+    }
+
+    /// Applies the transaction's writes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the writes couldn't be applied, in which case none of them are.
+    pub async fn commit(self) -> DocumentStoreResult<()> {
+        self.inner.commit().await
+    }
+
+    /// Discards the transaction's writes. Dropping the transaction discards them too, but
+    /// without reporting errors.
+    pub async fn rollback(self) -> DocumentStoreResult<()> {
+        self.inner.rollback().await
+    }
+}
+
+#[async_trait]
+impl StoreBackend for Transaction {
+    async fn insert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.inner
+            .backend()
+            .insert_documents(documents, collection, policy)
+            .await
+    }
+
+    async fn update_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.inner
+            .backend()
+            .update_documents(documents, collection, policy)
+            .await
+    }
+
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.inner
+            .backend()
+            .update_documents_if_version(documents, version_field, collection)
+            .await
+    }
+
+    async fn update_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, Bson, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.inner
+            .backend()
+            .update_documents_if_match(documents, collection)
+            .await
+    }
+
+    async fn upsert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+    ) -> DocumentStoreResult<()> {
+        self.inner
+            .backend()
+            .upsert_documents(documents, collection)
+            .await
+    }
+
+    async fn delete_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        self.inner
+            .backend()
+            .delete_documents(ids, collection)
+            .await
+    }
+
+    async fn delete_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<usize> {
+        self.inner
+            .backend()
+            .delete_documents_if_match(documents, collection)
+            .await
+    }
+
+    async fn update_by_query(
+        &self,
+        filter: Expr,
+        update: Update,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.inner
+            .backend()
+            .update_by_query(filter, update, collection)
+            .await
+    }
+
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize> {
+        self.inner
+            .backend()
+            .delete_by_query(filter, collection)
+            .await
+    }
+
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        self.inner
+            .backend()
+            .find_one_and_update(filter, sort, update, returned, collection)
+            .await
+    }
+
+    async fn find_one_and_delete(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        self.inner
+            .backend()
+            .find_one_and_delete(filter, sort, collection)
+            .await
+    }
+
+    async fn get_documents(
+        &self,
+        ids: Vec<Uuid>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.inner
+            .backend()
+            .get_documents(ids, collection)
+            .await
+    }
+
+    async fn query_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.inner
+            .backend()
+            .query_documents(query, collection)
+            .await
+    }
+
+    async fn query_stream(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<DocumentStream> {
+        self.inner
+            .backend()
+            .query_stream(query, collection)
+            .await
+    }
+
+    async fn query_raw_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<RawDocumentBuf>> {
+        self.inner
+            .backend()
+            .query_raw_documents(query, collection)
+            .await
+    }
+
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize> {
+        self.inner
+            .backend()
+            .count_documents(query, collection)
+            .await
+    }
+
+    async fn distinct(
+        &self,
+        field: &str,
+        filter: Option<Expr>,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.inner
+            .backend()
+            .distinct(field, filter, collection)
+            .await
+    }
+
+    async fn aggregate(
+        &self,
+        aggregate: Aggregate,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        self.inner
+            .backend()
+            .aggregate(aggregate, collection)
+            .await
+    }
+
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan> {
+        self.inner
+            .backend()
+            .explain(query, collection)
+            .await
+    }
+
+    async fn current_revision_id(&self) -> DocumentStoreResult<Option<String>> {
+        self.inner
+            .backend()
+            .current_revision_id()
+            .await
+    }
+
+    async fn set_revision_id(&self, revision_id: &str) -> DocumentStoreResult<()> {
+        self.inner
+            .backend()
+            .set_revision_id(revision_id)
+            .await
+    }
+
+    async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.inner
+            .backend()
+            .create_collection(name)
+            .await
+    }
+
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        self.inner
+            .backend()
+            .collection_exists(name)
+            .await
+    }
+
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.inner
+            .backend()
+            .ensure_collection(name)
+            .await
+    }
+
+    async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.inner
+            .backend()
+            .drop_collection(name)
+            .await
+    }
+
+    async fn list_collections(&self) -> DocumentStoreResult<Vec<String>> {
+        self.inner
+            .backend()
+            .list_collections()
+            .await
+    }
+
+    async fn add_field(
+        &self,
+        collection: &str,
+        field: &str,
+        default: Bson,
+    ) -> DocumentStoreResult<()> {
+        self.inner
+            .backend()
+            .add_field(collection, field, default)
+            .await
+    }
+
+    async fn drop_field(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.inner
+            .backend()
+            .drop_field(collection, field)
+            .await
+    }
+
+    async fn rename_field(
+        &self,
+        collection: &str,
+        field: &str,
+        new: &str,
+    ) -> DocumentStoreResult<()> {
+        self.inner
+            .backend()
+            .rename_field(collection, field, new)
+            .await
+    }
+
+    async fn add_index(
+        &self,
+        collection: &str,
+        field: &str,
+        unique: bool,
+    ) -> DocumentStoreResult<()> {
+        self.inner
+            .backend()
+            .add_index(collection, field, unique)
+            .await
+    }
+
+    async fn drop_index(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.inner
+            .backend()
+            .drop_index(collection, field)
+            .await
+    }
+
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.inner
+            .backend()
+            .expire_documents(collection, field)
+            .await
+    }
+
+    fn inner_backend(&self) -> Option<&dyn DynStoreBackend> {
+        Some(self.inner.backend())
+    }
+
+    fn encoding(&self) -> &Encoding {
+        self.inner.backend().encoding()
+    }
+}
@@ -0,0 +1,181 @@
+//! Partial update construction for document stores.
+//!
+//! This module provides the [`Update`] type describing field-level modifications that backends
+//! apply in place, avoiding read-modify-write cycles of whole documents.
+//!
+//! # Update Building
+//!
+//! Updates are constructed using the fluent builder API:
+//!
+//! ```ignore
+//! use doclayer::update::Update;
+//!
+//! let update = Update::builder()
+//!     .set("status", "active")
+//!     .inc("login_count", 1)
+//!     .push("tags", "verified")
+//!     .unset("activation_token")
+//!     .build();
+//!
+//! users.update_where(Filter::eq("email", "alice@example.com"), update).await?;
+//! ```
+//!
+//! # Operations
+//!
+//! - [`UpdateOp::Set`] - Sets a field to a value, creating it if missing
+//! - [`UpdateOp::Unset`] - Removes a field
+//! - [`UpdateOp::Inc`] - Increments a numeric field, treating a missing field as zero
+//! - [`UpdateOp::Push`] - Appends a value to an array field, creating the array if missing
+//! - [`UpdateOp::Pull`] - Removes all occurrences of a value from an array field
+//!
+//! Field names may use dot notation (`"address.city"`) to reach into embedded documents.
+
+use bson::Bson;
+
+/// A single field-level modification within an [`Update`].
+#[derive(Debug, Clone)]
+pub enum UpdateOp {
+    /// Sets the field to the value, creating it if it doesn't exist.
+    Set(String, Bson),
+    /// Removes the field from the document.
+    Unset(String),
+    /// Adds the value to a numeric field. A missing field is treated as zero.
+    Inc(String, Bson),
+    /// Appends the value to an array field, creating the array if it doesn't exist.
+    Push(String, Bson),
+    /// Removes every element equal to the value from an array field.
+    Pull(String, Bson),
+}
+
+impl UpdateOp {
+    /// Returns the name of the field this operation modifies.
+    pub fn field(&self) -> &str {
+        match self {
+            UpdateOp::Set(field, _)
+            | UpdateOp::Unset(field)
+            | UpdateOp::Inc(field, _)
+            | UpdateOp::Push(field, _)
+            | UpdateOp::Pull(field, _) => field,
+        }
+    }
+}
+
+/// A partial update applied to every document matched by a filter.
+///
+/// Operations are applied in the order they were added. Use [`UpdateBuilder`] for
+/// ergonomic construction.
+///
+/// # Example
+///
+/// ```ignore
+/// use doclayer::update::Update;
+///
+/// let update = Update::builder()
+///     .set("status", "archived")
+///     .inc("version", 1)
+///     .build();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Update {
+    /// The operations making up this update, in application order.
+    pub ops: Vec<UpdateOp>,
+}
+
+impl Update {
+    /// Creates a new update with no operations.
+    pub fn new() -> Self {
+        Update { ops: Vec::new() }
+    }
+
+    /// Creates a new update builder for fluent construction.
+    pub fn builder() -> UpdateBuilder {
+        UpdateBuilder::new()
+    }
+
+    /// Returns `true` if this update contains no operations.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+/// Builder for constructing [`Update`] instances with a fluent API.
+#[derive(Debug, Clone, Default)]
+pub struct UpdateBuilder {
+    update: Update,
+}
+
+impl UpdateBuilder {
+    /// Creates a new update builder.
+    pub fn new() -> Self {
+        UpdateBuilder { update: Update::new() }
+    }
+
+    /// Sets a field to a value, creating it if it doesn't exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field to set
+    /// * `value` - The new value
+    pub fn set(mut self, field: impl Into<String>, value: impl Into<Bson>) -> Self {
+        self.update
+            .ops
+            .push(UpdateOp::Set(field.into(), value.into()));
+        self
+    }
+
+    /// Removes a field from the document.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field to remove
+    pub fn unset(mut self, field: impl Into<String>) -> Self {
+        self.update
+            .ops
+            .push(UpdateOp::Unset(field.into()));
+        self
+    }
+
+    /// Increments a numeric field by the given amount (use a negative amount to decrement).
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The numeric field to increment
+    /// * `amount` - The amount to add
+    pub fn inc(mut self, field: impl Into<String>, amount: impl Into<Bson>) -> Self {
+        self.update
+            .ops
+            .push(UpdateOp::Inc(field.into(), amount.into()));
+        self
+    }
+
+    /// Appends a value to an array field, creating the array if it doesn't exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The array field to append to
+    /// * `value` - The value to append
+    pub fn push(mut self, field: impl Into<String>, value: impl Into<Bson>) -> Self {
+        self.update
+            .ops
+            .push(UpdateOp::Push(field.into(), value.into()));
+        self
+    }
+
+    /// Removes every occurrence of a value from an array field.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The array field to remove from
+    /// * `value` - The value to remove
+    pub fn pull(mut self, field: impl Into<String>, value: impl Into<Bson>) -> Self {
+        self.update
+            .ops
+            .push(UpdateOp::Pull(field.into(), value.into()));
+        self
+    }
+
+    /// Builds and returns the final update.
+    pub fn build(self) -> Update {
+        self.update
+    }
+}
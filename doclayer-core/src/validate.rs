@@ -0,0 +1,168 @@
+//! Validating documents before they're written.
+//!
+//! A [`Validator`] checks a document and returns the [`FieldError`]s it finds. Validators are
+//! attached to a typed collection with
+//! [`with_validator`](crate::collection::TypedCollection::with_validator), and run on every
+//! whole document the collection inserts, updates or upserts, before anything is written.
+//! The violations of every document of a write are collected into a single
+//! [`DocumentStoreError::Validation`], so a client learns about all of them at once.
+//!
+//! Partial updates are exempt: [`update_where`](crate::collection::TypedCollection::update_where),
+//! [`find_one_and_update`](crate::collection::TypedCollection::find_one_and_update) and
+//! [`Find::update_one`](crate::collection::Find::update_one) send the update to the backend,
+//! which applies it to the stored documents without the collection seeing the result. When a
+//! rule must hold, check the update before sending it, or read the document, change it and
+//! write it back with [`update`](crate::collection::TypedCollection::update).
+//!
+//! Validation runs in the collection rather than the backend, so every backend enforces the
+//! same rules.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::validate::FieldError;
+//!
+//! fn validate_user(user: &User) -> Vec<FieldError> {
+//!     let mut errors = Vec::new();
+//!
+//!     if user.name.trim().is_empty() {
+//!         errors.push(FieldError::new("name", "must not be empty"));
+//!     }
+//!     if !user.email.contains('@') {
+//!         errors.push(FieldError::new("email", "must be an email address"));
+//!     }
+//!
+//!     errors
+//! }
+//!
+//! let users = store
+//!     .typed_collection::<User>()
+//!     .with_validator(validate_user);
+//!
+//! match users.insert(vec![user]).await {
+//!     Err(DocumentStoreError::Validation(errors)) => {
+//!         for error in errors {
+//!             println!("{}: {}", error.field, error.message);
+//!         }
+//!     }
+//!     result => result?,
+//! }
+//! ```
+
+use bson::Uuid;
+use serde::Serialize;
+use std::{fmt, sync::Arc};
+
+use crate::{
+    document::Document,
+    error::{DocumentStoreError, DocumentStoreResult},
+};
+
+/// A rule violated by a field of a document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    /// The ID of the invalid document, set by the collection running the validator.
+    pub document: Option<Uuid>,
+    /// The path of the invalid field, such as `address.city`.
+    pub field: String,
+    /// What is wrong with the field's value.
+    pub message: String,
+}
+
+impl FieldError {
+    /// Creates a violation of a field.
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            document: None,
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.document {
+            Some(document) => {
+                write!(f, "{} of document {}: {}", self.field, document, self.message)
+            }
+            None => write!(f, "{}: {}", self.field, self.message),
+        }
+    }
+}
+
+/// Checks documents of type `D` before they're written.
+///
+/// Implemented by every `Fn(&D) -> Vec<FieldError>`.
+pub trait Validator<D>: Send + Sync {
+    /// Returns the violations of a document, or an empty vector if it's valid.
+    fn validate(&self, document: &D) -> Vec<FieldError>;
+}
+
+impl<D, F> Validator<D> for F
+where
+    F: Fn(&D) -> Vec<FieldError> + Send + Sync,
+{
+    fn validate(&self, document: &D) -> Vec<FieldError> {
+        self(document)
+    }
+}
+
+/// The validators attached to a typed collection.
+pub(crate) struct Validators<D> {
+    validators: Vec<Arc<dyn Validator<D>>>,
+}
+
+impl<D: Document> Validators<D> {
+    pub(crate) fn push(&mut self, validator: impl Validator<D> + 'static) {
+        self.validators
+            .push(Arc::new(validator));
+    }
+
+    /// Runs every validator on every document.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Validation`] with the violations of all the documents, if
+    /// there are any.
+    pub(crate) fn check<'d>(
+        &self,
+        documents: impl IntoIterator<Item = &'d D>,
+    ) -> DocumentStoreResult<()>
+    where
+        D: 'd,
+    {
+        if self.validators.is_empty() {
+            return Ok(());
+        }
+
+        let errors = documents
+            .into_iter()
+            .flat_map(|document| {
+                self.validators
+                    .iter()
+                    .flat_map(|validator| validator.validate(document))
+                    .map(|error| FieldError { document: Some(*document.id()), ..error })
+            })
+            .collect::<Vec<_>>();
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(DocumentStoreError::Validation(errors)),
+        }
+    }
+}
+
+impl<D> Default for Validators<D> {
+    fn default() -> Self {
+        Self { validators: Vec::new() }
+    }
+}
+
+impl<D> fmt::Debug for Validators<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Validators")
+            .field("count", &self.validators.len())
+            .finish()
+    }
+}
@@ -0,0 +1,118 @@
+//! Per-document revision tokens for conditional writes.
+//!
+//! Every stored document has a revision, a token that changes whenever the document does.
+//! [`TypedCollection::get_versioned`](crate::collection::TypedCollection::get_versioned)
+//! returns documents along with their revisions as [`Versioned`] values, and
+//! [`update_if_match`](crate::collection::TypedCollection::update_if_match) and
+//! [`delete_if_match`](crate::collection::TypedCollection::delete_if_match) only write
+//! documents still at the given revision, failing with
+//! [`DocumentStoreError::PreconditionFailed`] otherwise.
+//!
+//! These are the `If-Match` semantics of HTTP: a handler sends the revision as the `ETag` of
+//! its response and passes the `If-Match` header of the next request back, so a client
+//! writing from a stale copy gets a `412 Precondition Failed` instead of overwriting someone
+//! else's changes.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::versioned::Versioned;
+//!
+//! // GET /users/{id}: respond with the document and its ETag
+//! let user = users.get_one_versioned(id).await?;
+//!
+//! // PUT /users/{id}: write only if the client had the current revision
+//! users
+//!     .update_if_match(vec![Versioned::from_etag(user, if_match)])
+//!     .await?;
+//! ```
+//!
+//! Revisions are derived from the stored content by [`document_revision`], so backends keep
+//! no state for them and every stored document has one, however it was written. Backends
+//! compare the revision and write each document atomically, so of two writers holding the
+//! same revision only one succeeds.
+
+use bson::{Bson, RawDocumentBuf};
+use sha2::{Digest, Sha256};
+use std::ops::{Deref, DerefMut};
+
+use crate::error::{DocumentStoreError, DocumentStoreResult};
+
+/// A document along with the revision of the stored document it was read from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Versioned<D> {
+    /// The document.
+    pub document: D,
+    /// The revision of the stored document, as computed by [`document_revision`].
+    pub revision: String,
+}
+
+impl<D> Versioned<D> {
+    /// Pairs a document with a revision.
+    pub fn new(document: D, revision: impl Into<String>) -> Self {
+        Self { document, revision: revision.into() }
+    }
+
+    /// Pairs a document with the revision of an `ETag`, such as the value of an `If-Match`
+    /// header. The quotes and weak prefix of the tag are removed.
+    pub fn from_etag(document: D, etag: &str) -> Self {
+        Self::new(document, revision_from_etag(etag))
+    }
+
+    /// Returns the revision as a quoted `ETag` header value.
+    pub fn etag(&self) -> String {
+        format!("\"{}\"", self.revision)
+    }
+
+    /// Returns the document, discarding the revision.
+    pub fn into_inner(self) -> D {
+        self.document
+    }
+}
+
+impl<D> Deref for Versioned<D> {
+    type Target = D;
+
+    fn deref(&self) -> &D {
+        &self.document
+    }
+}
+
+impl<D> DerefMut for Versioned<D> {
+    fn deref_mut(&mut self) -> &mut D {
+        &mut self.document
+    }
+}
+
+/// Computes the revision of a stored document.
+///
+/// The revision is a hash of the document's BSON bytes, so it is the same for every reader
+/// and changes whenever any field does. Backends compute it from the documents as
+/// [`get_documents`](crate::backend::StoreBackend::get_documents) returns them.
+///
+/// # Errors
+///
+/// Returns an error if the value isn't a document or can't be encoded.
+pub fn document_revision(document: &Bson) -> DocumentStoreResult<String> {
+    let Bson::Document(document) = document else {
+        return Err(DocumentStoreError::InvalidDocument("Expected document".into()));
+    };
+
+    let digest = Sha256::digest(RawDocumentBuf::try_from(document)?.as_bytes());
+
+    // Half of the digest keeps tags short while leaving collisions out of reach
+    Ok(digest[..16]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+/// Returns the revision of an `ETag`, removing its quotes and weak prefix.
+pub fn revision_from_etag(etag: &str) -> &str {
+    let etag = etag.trim();
+    let etag = etag.strip_prefix("W/").unwrap_or(etag);
+
+    etag.strip_prefix('"')
+        .and_then(|etag| etag.strip_suffix('"'))
+        .unwrap_or(etag)
+}
@@ -0,0 +1,128 @@
+//! HTTP responses for document store errors and versioned documents.
+//!
+//! With the `web` feature, [`DocumentStoreError`] converts into an axum response and
+//! implements actix-web's `ResponseError`, so handlers can apply `?` to doclayer results
+//! directly. Both respond with [`DocumentStoreError::status_code`] and a JSON body of the
+//! form `{ "error": "..." }`. Validation errors also list their violations, as
+//! `{ "error": "...", "fields": [{ "document": ..., "field": "...", "message": "..." }] }`.
+//!
+//! Server errors only report the status reason, keeping backend details such as connection
+//! strings out of responses. Log the error before returning it to keep them.
+//!
+//! A [`Versioned`] document responds with the document as JSON and its revision as the
+//! `ETag` header, for clients to send back as `If-Match`.
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{error::DocumentStoreError, versioned::Versioned};
+
+/// Returns the JSON body of an error response.
+fn response_body(error: &DocumentStoreError) -> String {
+    if let DocumentStoreError::Validation(errors) = error {
+        return json!({ "error": error.to_string(), "fields": errors }).to_string();
+    }
+
+    let status = error.status_code();
+    let message = match status {
+        500.. => http::StatusCode::from_u16(status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or("Internal Server Error")
+            .to_string(),
+        _ => error.to_string(),
+    };
+
+    json!({ "error": message }).to_string()
+}
+
+impl axum_core::response::IntoResponse for DocumentStoreError {
+    fn into_response(self) -> axum_core::response::Response {
+        let status = http::StatusCode::from_u16(self.status_code())
+            .unwrap_or(http::StatusCode::INTERNAL_SERVER_ERROR);
+
+        (status, [(http::header::CONTENT_TYPE, "application/json")], response_body(&self))
+            .into_response()
+    }
+}
+
+impl actix_web::ResponseError for DocumentStoreError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        actix_web::http::StatusCode::from_u16(DocumentStoreError::status_code(self))
+            .unwrap_or(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    fn error_response(&self) -> actix_web::HttpResponse {
+        actix_web::HttpResponse::build(actix_web::ResponseError::status_code(self))
+            .content_type("application/json")
+            .body(response_body(self))
+    }
+}
+
+impl<D: Serialize> axum_core::response::IntoResponse for Versioned<D> {
+    fn into_response(self) -> axum_core::response::Response {
+        match serde_json::to_string(&self.document) {
+            Ok(body) => (
+                [
+                    (http::header::CONTENT_TYPE, "application/json"),
+                    (http::header::ETAG, self.etag().as_str()),
+                ],
+                body,
+            )
+                .into_response(),
+            Err(err) => DocumentStoreError::from(err).into_response(),
+        }
+    }
+}
+
+impl<D: Serialize> actix_web::Responder for Versioned<D> {
+    type Body = actix_web::body::BoxBody;
+
+    fn respond_to(self, _req: &actix_web::HttpRequest) -> actix_web::HttpResponse {
+        match serde_json::to_string(&self.document) {
+            Ok(body) => actix_web::HttpResponse::Ok()
+                .content_type("application/json")
+                .insert_header((actix_web::http::header::ETAG, self.etag()))
+                .body(body),
+            Err(err) => actix_web::ResponseError::error_response(&DocumentStoreError::from(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::response_body;
+    use crate::{error::DocumentStoreError, validate::FieldError};
+
+    fn body(error: &DocumentStoreError) -> Value {
+        serde_json::from_str(&response_body(error)).unwrap()
+    }
+
+    #[test]
+    fn invalid_documents_are_server_errors_without_details() {
+        let error =
+            DocumentStoreError::InvalidDocument("users/42 has no ID in its id field".to_string());
+
+        assert_eq!(error.status_code(), 500);
+        assert_eq!(body(&error), json!({ "error": "Internal Server Error" }));
+    }
+
+    #[test]
+    fn client_errors_keep_their_message() {
+        let error = DocumentStoreError::CollectionNotFound("users".to_string());
+
+        assert_eq!(error.status_code(), 404);
+        assert_eq!(body(&error), json!({ "error": error.to_string() }));
+    }
+
+    #[test]
+    fn validation_errors_list_their_fields() {
+        let error =
+            DocumentStoreError::Validation(vec![FieldError::new("email", "must not be empty")]);
+
+        assert_eq!(error.status_code(), 422);
+        assert_eq!(body(&error)["fields"][0]["field"], "email");
+    }
+}
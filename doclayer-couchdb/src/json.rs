@@ -0,0 +1,45 @@
+//! Conversion between BSON values and the JSON stored in CouchDB.
+//!
+//! Documents are stored as relaxed [extended JSON](https://www.mongodb.com/docs/manual/reference/mongodb-extended-json/),
+//! with UUIDs rendered as `{ "$uuid": "..." }`. Filter values go through the same conversion,
+//! so Mango compares them against the stored form.
+
+use bson::{Bson, spec::BinarySubtype};
+use serde_json::{Value, json};
+
+use doclayer_core::error::{DocumentStoreError, DocumentStoreResult};
+
+
+/// Converts a value into relaxed extended JSON, rendering UUIDs in their readable form.
+pub(crate) fn to_json(value: &Bson) -> Value {
+    match value {
+        Bson::Document(doc) => Value::Object(
+            doc
+                .iter()
+                .map(|(key, value)| (key.clone(), to_json(value)))
+                .collect()
+        ),
+        Bson::Array(values) => Value::Array(values.iter().map(to_json).collect()),
+        Bson::Binary(binary) if binary.subtype == BinarySubtype::Uuid => match binary.to_uuid() {
+            Ok(uuid) => json!({ "$uuid": uuid.to_string() }),
+            Err(_) => value.clone().into_relaxed_extjson(),
+        },
+        other => other.clone().into_relaxed_extjson(),
+    }
+}
+
+/// Parses extended JSON back into a BSON document.
+///
+/// # Errors
+///
+/// Returns [`DocumentStoreError::Serialization`] if the value is not valid extended JSON,
+/// or [`DocumentStoreError::InvalidDocument`] if it isn't an object.
+pub(crate) fn from_json(value: Value) -> DocumentStoreResult<Bson> {
+    match Bson::try_from(value)? {
+        document @ Bson::Document(_) => Ok(document),
+        other => Err(DocumentStoreError::InvalidDocument(format!(
+            "Expected a JSON object, found {}",
+            other
+        ))),
+    }
+}
@@ -0,0 +1,60 @@
+//! CouchDB backend implementation for doclayer.
+//!
+//! This crate provides a [CouchDB](https://couchdb.apache.org/) implementation of the
+//! `StoreBackend` trait over CouchDB's HTTP API.
+//!
+//! To use this backend, include the `couchdb` feature in your `Cargo.toml`:
+//!
+//! ```toml
+//! [dependencies]
+//! doclayer = { version = "x.y.z", features = ["couchdb"] }
+//! ```
+//!
+//! # Storage
+//!
+//! - **Collections** - Every collection is a database named after it, behind a configurable
+//!   prefix (`doclayer_` by default), so `users` is stored in `doclayer_users`
+//! - **Documents** - Each document is a CouchDB document whose `_id` is the document ID. The
+//!   document itself is stored as relaxed extended JSON under a `data` field, since CouchDB
+//!   reserves top-level fields starting with `_`
+//! - **Revisions** - Writes replace the `_rev` the store last read. A document changed by
+//!   another writer in between fails with [`DocumentStoreError::Conflict`](doclayer_core::error::DocumentStoreError::Conflict)
+//!   instead of being overwritten
+//! - **Migrations** - The migration revision is kept in the `_revisions` collection
+//!
+//! # Queries
+//!
+//! Filters are translated into [Mango](https://docs.couchdb.org/en/stable/api/database/find.html)
+//! selectors and evaluated by CouchDB, which doesn't match missing fields against comparisons.
+//! Sorting, projections, distinct values, aggregations and partial updates are applied by the
+//! store to the documents CouchDB returns, with the same semantics as the in-memory backend.
+//!
+//! CouchDB has no transactions, so a batch write interrupted by a conflict keeps the
+//! documents written before it, and it doesn't support unique indexes.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::{backend::StoreBackendBuilder, couchdb::CouchDbStore};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let store = CouchDbStore::builder("http://localhost:5984")
+//!         .with_credentials("admin", "secret")
+//!         .build()
+//!         .await?;
+//!
+//!     store.ping().await?;
+//!
+//!     Ok(())
+//! }
+//! ```
+
+#[allow(unused_extern_crates)]
+extern crate self as doclayer_couchdb;
+
+pub mod store;
+pub mod query;
+mod json;
+
+pub use store::{CouchDbStore, CouchDbStoreBuilder, DEFAULT_DATABASE_PREFIX};
@@ -0,0 +1,218 @@
+//! Query translation from doclayer AST to CouchDB Mango selectors.
+//!
+//! This module translates doclayer's abstract query expressions into
+//! [Mango](https://docs.couchdb.org/en/stable/api/database/find.html#selector-syntax)
+//! selectors evaluated by CouchDB's `_find` endpoint.
+
+use std::ops::Bound;
+use bson::Bson;
+use serde_json::{Map, Value, json};
+
+use doclayer_core::{
+    query::{QueryVisitor, CustomOperators, Expr, FieldOp},
+    error::DocumentStoreError,
+};
+
+use crate::{json::to_json, store::DATA_FIELD};
+
+
+/// Translates doclayer query expressions into Mango selectors.
+///
+/// Documents are stored under [`DATA_FIELD`], so field names are prefixed with it, except
+/// within `$elemMatch` where they address the fields of array elements. Custom operators
+/// are translated by the handlers registered on the store.
+pub(crate) struct MangoQueryTranslator<'a> {
+    case_insensitive: bool,
+    in_element: bool,
+    operators: &'a CustomOperators,
+}
+
+impl<'a> MangoQueryTranslator<'a> {
+    /// Translates an optional filter, matching every document when there is none.
+    pub fn selector(filter: Option<&Expr>, operators: &'a CustomOperators) -> Result<Value, DocumentStoreError> {
+        match filter {
+            Some(expr) => Self { case_insensitive: false, in_element: false, operators }.visit_expr(expr),
+            None => Ok(json!({})),
+        }
+    }
+
+    /// Returns the path of a field in the stored document.
+    fn path(&self, field: &str) -> String {
+        match self.in_element {
+            true => field.to_string(),
+            false => format!("{}.{}", DATA_FIELD, field),
+        }
+    }
+
+    /// Builds a `$regex` condition, honoring the current case sensitivity.
+    fn regex(&self, pattern: String) -> Value {
+        match self.case_insensitive {
+            true => json!({ "$regex": format!("(?i){}", pattern) }),
+            false => json!({ "$regex": pattern }),
+        }
+    }
+
+    fn condition(&self, field: &str, condition: Value) -> Value {
+        let mut selector = Map::new();
+        selector.insert(self.path(field), condition);
+
+        Value::Object(selector)
+    }
+}
+
+impl QueryVisitor for MangoQueryTranslator<'_> {
+    type Output = Value;
+    type Error = DocumentStoreError;
+
+    fn visit_and(&mut self, exprs: &[Expr]) -> Result<Self::Output, Self::Error> {
+        // An empty conjunction matches everything
+        if exprs.is_empty() {
+            return Ok(json!({}));
+        }
+
+        Ok(json!({
+            "$and": exprs
+                .iter()
+                .map(|expr| self.visit_expr(expr))
+                .collect::<Result<Vec<_>, _>>()?,
+        }))
+    }
+
+    fn visit_or(&mut self, exprs: &[Expr]) -> Result<Self::Output, Self::Error> {
+        // Every document has an `_id`, so an empty disjunction matches nothing
+        if exprs.is_empty() {
+            return Ok(json!({ "_id": { "$exists": false } }));
+        }
+
+        Ok(json!({
+            "$or": exprs
+                .iter()
+                .map(|expr| self.visit_expr(expr))
+                .collect::<Result<Vec<_>, _>>()?,
+        }))
+    }
+
+    fn visit_not(&mut self, expr: &Expr) -> Result<Self::Output, Self::Error> {
+        Ok(json!({
+            "$not": self.visit_expr(expr)?,
+        }))
+    }
+
+    fn visit_case_insensitive(&mut self, expr: &Expr) -> Result<Self::Output, Self::Error> {
+        let previous = std::mem::replace(&mut self.case_insensitive, true);
+        let result = self.visit_expr(expr);
+        self.case_insensitive = previous;
+
+        result
+    }
+
+    fn visit_elem_match(&mut self, field: &str, expr: &Expr) -> Result<Self::Output, Self::Error> {
+        let path = self.path(field);
+
+        let previous = std::mem::replace(&mut self.in_element, true);
+        let result = self.visit_expr(expr);
+        self.in_element = previous;
+
+        let mut selector = Map::new();
+        selector.insert(path, json!({ "$elemMatch": result? }));
+
+        Ok(Value::Object(selector))
+    }
+
+    fn visit_custom(&mut self, name: &str, payload: &Bson) -> Result<Self::Output, Self::Error> {
+        let prefix = match self.in_element {
+            true => String::new(),
+            false => format!("{}.", DATA_FIELD),
+        };
+
+        Ok(to_json(&Bson::Document(self.operators.translate(name, payload, &prefix)?)))
+    }
+
+    fn visit_exists(&mut self, field: &str, should_exist: bool) -> Result<Self::Output, Self::Error> {
+        Ok(self.condition(field, json!({ "$exists": should_exist })))
+    }
+
+    fn visit_range(&mut self, field: &str, low: &Bound<Bson>, high: &Bound<Bson>) -> Result<Self::Output, Self::Error> {
+        let mut range = Map::new();
+
+        match low {
+            Bound::Included(value) => { range.insert("$gte".to_string(), to_json(value)); },
+            Bound::Excluded(value) => { range.insert("$gt".to_string(), to_json(value)); },
+            Bound::Unbounded => {},
+        }
+        match high {
+            Bound::Included(value) => { range.insert("$lte".to_string(), to_json(value)); },
+            Bound::Excluded(value) => { range.insert("$lt".to_string(), to_json(value)); },
+            Bound::Unbounded => {},
+        }
+
+        if range.is_empty() {
+            return Ok(json!({}));
+        }
+
+        Ok(self.condition(field, Value::Object(range)))
+    }
+
+    fn visit_field(&mut self, field: &str, op: &FieldOp, value: &Bson) -> Result<Self::Output, Self::Error> {
+        let condition = match op {
+            FieldOp::Eq => json!({ "$eq": to_json(value) }),
+            FieldOp::Ne => json!({ "$ne": to_json(value) }),
+            FieldOp::Gt => json!({ "$gt": to_json(value) }),
+            FieldOp::Gte => json!({ "$gte": to_json(value) }),
+            FieldOp::Lt => json!({ "$lt": to_json(value) }),
+            FieldOp::Lte => json!({ "$lte": to_json(value) }),
+            FieldOp::Contains => match value {
+                Bson::String(s) => self.regex(escape_regex(s)),
+                Bson::Array(arr) => json!({ "$all": arr.iter().map(to_json).collect::<Vec<_>>() }),
+                _ => return Err(DocumentStoreError::Backend("Contains operator requires a string or array value".to_string())),
+            },
+            // Mango only negates whole selectors, so the negated condition is built first
+            FieldOp::NotContains => return Ok(json!({
+                "$not": match value {
+                    Bson::String(s) => self.condition(field, self.regex(escape_regex(s))),
+                    Bson::Array(arr) => json!({
+                        "$or": arr
+                            .iter()
+                            .map(|item| self.condition(field, json!({ "$elemMatch": { "$eq": to_json(item) } })))
+                            .collect::<Vec<_>>(),
+                    }),
+                    _ => return Err(DocumentStoreError::Backend("NotContains operator requires a string or array value".to_string())),
+                },
+            })),
+            FieldOp::StartsWith => match value {
+                Bson::String(s) => self.regex(format!("^{}", escape_regex(s))),
+                _ => return Err(DocumentStoreError::Backend("StartsWith operator requires a string value".to_string())),
+            },
+            FieldOp::EndsWith => match value {
+                Bson::String(s) => self.regex(format!("{}$", escape_regex(s))),
+                _ => return Err(DocumentStoreError::Backend("EndsWith operator requires a string value".to_string())),
+            },
+            FieldOp::AnyOf => json!({ "$in": FieldOp::set_values(value)?.iter().map(to_json).collect::<Vec<_>>() }),
+            FieldOp::NoneOf => json!({ "$nin": FieldOp::set_values(value)?.iter().map(to_json).collect::<Vec<_>>() }),
+            FieldOp::Regex => match value {
+                Bson::String(s) => self.regex(s.clone()),
+                _ => return Err(DocumentStoreError::Backend("Regex operator requires a string pattern".to_string())),
+            },
+        };
+
+        Ok(self.condition(field, condition))
+    }
+}
+
+
+/// Escapes the regular expression metacharacters in a string so it matches literally.
+///
+/// String operators are implemented with `$regex`, so the compared value must not be
+/// interpreted as a pattern. Intentional patterns go through [`FieldOp::Regex`] unescaped.
+fn escape_regex(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        if matches!(c, '\\' | '^' | '$' | '.' | '|' | '?' | '*' | '+' | '(' | ')' | '[' | ']' | '{' | '}' | '-' | '#') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
@@ -0,0 +1,1184 @@
+use std::{collections::{HashMap, HashSet}, future::Future, time::Duration};
+use async_trait::async_trait;
+use bson::{Bson, Document, Uuid};
+use reqwest::{Client, Method, RequestBuilder, StatusCode, Url};
+use serde_json::{Value, json};
+use doclayer_core::{
+    aggregate::Aggregate,
+    backend::{InsertPolicy, MissingDocumentPolicy, QueryPlan, ReturnDocument, ScanStrategy, StoreBackend, StoreBackendBuilder, WriteReport},
+    document::stored_version,
+    encoding::Encoding,
+    error::{DocumentStoreError, DocumentStoreResult},
+    expiry::ExpiringCollections,
+    query::{CustomOperator, CustomOperators, Expr, Query, Sort, SortComparator, SortComparators},
+    update::Update,
+    versioned::document_revision,
+};
+use doclayer_memory::{
+    aggregator::DocumentAggregator,
+    evaluator::{compare_documents, distinct_values, project_document, sort_documents},
+    updater::DocumentUpdater,
+};
+
+use crate::{
+    json::{from_json, to_json},
+    query::MangoQueryTranslator,
+};
+
+
+/// The field of a CouchDB document holding the stored document.
+///
+/// CouchDB reserves top-level fields starting with `_`, so documents are nested under a
+/// field of their own instead of being stored at the top level.
+pub(crate) const DATA_FIELD: &str = "data";
+
+/// The default prefix of the CouchDB database names.
+pub const DEFAULT_DATABASE_PREFIX: &str = "doclayer_";
+
+/// The collection, under the database prefix, holding the migration revision.
+const REVISIONS_COLLECTION: &str = "_revisions";
+
+/// The ID of the CouchDB document holding the migration revision.
+const REVISION_DOCUMENT_ID: &str = "revision";
+
+/// The number of documents requested per `_find` page.
+const FIND_BATCH_SIZE: usize = 1000;
+
+
+/// A stored CouchDB document: its ID, its current `_rev` and the document under [`DATA_FIELD`].
+struct StoredDocument {
+    id: String,
+    rev: String,
+    data: Bson,
+}
+
+impl StoredDocument {
+    fn from_json(mut value: Value) -> DocumentStoreResult<Self> {
+        let field = |value: &mut Value, name: &str| match value.get_mut(name).map(Value::take) {
+            Some(Value::String(s)) => Ok(s),
+            _ => Err(DocumentStoreError::InvalidDocument(format!("CouchDB document without {}", name))),
+        };
+
+        Ok(Self {
+            id: field(&mut value, "_id")?,
+            rev: field(&mut value, "_rev")?,
+            data: from_json(
+                value
+                    .get_mut(DATA_FIELD)
+                    .map(Value::take)
+                    .unwrap_or_else(|| json!({}))
+            )?,
+        })
+    }
+}
+
+
+/// Storage backend over CouchDB's HTTP API.
+///
+/// Every collection is a CouchDB database named after the collection, prefixed with the
+/// store's database prefix. See the [crate documentation](crate) for how documents and
+/// queries map onto CouchDB.
+///
+/// # Thread Safety
+///
+/// `CouchDbStore` is cloneable, and clones share the same HTTP connection pool. CouchDB has
+/// no transactions; conflicting concurrent writes to the same document fail with
+/// [`DocumentStoreError::Conflict`] instead of overwriting each other.
+#[derive(Clone, Debug)]
+pub struct CouchDbStore {
+    client: Client,
+    url: Url,
+    credentials: Option<(String, String)>,
+    database_prefix: String,
+    comparators: SortComparators,
+    operators: CustomOperators,
+    encoding: Encoding,
+    /// Collections whose documents expire, with the field holding their expiry
+    expiring: ExpiringCollections,
+}
+
+impl CouchDbStore {
+    /// Creates a builder for a store on the CouchDB server at `url`.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The server URL, e.g. `http://localhost:5984`
+    pub fn builder(url: &str) -> CouchDbStoreBuilder {
+        CouchDbStoreBuilder::new(url)
+    }
+
+    /// Returns the prefix of the database names of the collections.
+    pub fn database_prefix(&self) -> &str {
+        &self.database_prefix
+    }
+
+    /// Checks that the server is reachable and accepts the configured credentials.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Backend`] if the server cannot be reached or rejects
+    /// the credentials.
+    pub async fn ping(&self) -> DocumentStoreResult<()> {
+        let (status, body) = self.send(self.request(Method::GET, &["_session"])).await?;
+
+        match status.is_success() {
+            true => Ok(()),
+            false => Err(Self::error(status, &body)),
+        }
+    }
+
+    /// Deletes the documents whose expiry has passed, in every collection made to expire them
+    /// with [`StoreBackend::expire_documents`].
+    ///
+    /// CouchDB has no expiry of its own, so expired documents stay until swept.
+    ///
+    /// # Returns
+    ///
+    /// The number of documents deleted.
+    pub async fn sweep_expired(&self) -> DocumentStoreResult<usize> {
+        self.expiring.sweep(self).await
+    }
+
+    /// Sweeps expired documents repeatedly, waiting `interval` between sweeps.
+    ///
+    /// Meant to be spawned as a background task; this never returns. See
+    /// [`ExpiringCollections::run`] for the arguments.
+    pub async fn run_sweeper<F, Fut, R>(&self, interval: Duration, sleep: F, on_sweep: R)
+    where
+        F: Fn(Duration) -> Fut,
+        Fut: Future<Output = ()>,
+        R: FnMut(DocumentStoreResult<usize>),
+    {
+        self.expiring.run(self, interval, sleep, on_sweep).await
+    }
+
+    /// Returns the name of a collection's database, validated against CouchDB's naming rules.
+    fn database(&self, collection: &str) -> DocumentStoreResult<String> {
+        let name = format!("{}{}", self.database_prefix, collection);
+
+        if !is_valid_database_name(&name) {
+            return Err(DocumentStoreError::Backend(format!(
+                "Collection '{}' maps to the invalid CouchDB database name '{}'",
+                collection, name
+            )));
+        }
+
+        Ok(name)
+    }
+
+    fn request(&self, method: Method, segments: &[&str]) -> RequestBuilder {
+        let mut url = self.url.clone();
+        url.path_segments_mut()
+            .expect("the server URL is validated by the builder")
+            .pop_if_empty()
+            .extend(segments);
+
+        let request = self.client.request(method, url);
+        match &self.credentials {
+            Some((username, password)) => request.basic_auth(username, Some(password)),
+            None => request,
+        }
+    }
+
+    /// Sends a request, returning the status and the JSON body (`null` when empty).
+    async fn send(&self, request: RequestBuilder) -> DocumentStoreResult<(StatusCode, Value)> {
+        let response = request
+            .send()
+            .await
+            .map_err(Self::request_error)?;
+        let status = response.status();
+        let body = response
+            .bytes()
+            .await
+            .map_err(Self::request_error)?;
+
+        Ok((
+            status,
+            match body.is_empty() {
+                true => Value::Null,
+                false => serde_json::from_slice(&body)?,
+            },
+        ))
+    }
+
+    /// Converts an HTTP error, reporting connection failures and timeouts as
+    /// [`DocumentStoreError::Unavailable`].
+    fn request_error(error: reqwest::Error) -> DocumentStoreError {
+        match error.is_connect() || error.is_timeout() {
+            true => DocumentStoreError::Unavailable(error.to_string()),
+            false => DocumentStoreError::Backend(error.to_string()),
+        }
+    }
+
+    /// Converts an error response, reporting rate limiting and an overloaded or unreachable
+    /// server as [`DocumentStoreError::Unavailable`].
+    fn error(status: StatusCode, body: &Value) -> DocumentStoreError {
+        let message = format!(
+            "CouchDB returned {}: {} ({})",
+            status,
+            body.get("error").and_then(Value::as_str).unwrap_or("unknown error"),
+            body.get("reason").and_then(Value::as_str).unwrap_or("no reason given"),
+        );
+
+        match status {
+            StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT => DocumentStoreError::Unavailable(message),
+            _ => DocumentStoreError::Backend(message),
+        }
+    }
+
+    /// Reads the stored documents with the given IDs, or `None` if the database doesn't exist.
+    async fn stored_documents(&self, database: &str, ids: &[Uuid]) -> DocumentStoreResult<Option<HashMap<String, StoredDocument>>> {
+        let (status, body) = self.send(
+            self.request(Method::POST, &[database, "_all_docs"])
+                .query(&[("include_docs", "true")])
+                .json(&json!({ "keys": ids.iter().map(Uuid::to_string).collect::<Vec<_>>() }))
+        ).await?;
+
+        match status {
+            StatusCode::NOT_FOUND => return Ok(None),
+            status if !status.is_success() => return Err(Self::error(status, &body)),
+            _ => {},
+        }
+
+        // Missing and deleted documents come back as rows without a document
+        body.get("rows")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|row| row.get("doc").filter(|doc| doc.is_object()).cloned())
+            .map(|doc| StoredDocument::from_json(doc).map(|doc| (doc.id.clone(), doc)))
+            .collect::<DocumentStoreResult<HashMap<_, _>>>()
+            .map(Some)
+    }
+
+    /// Returns the stored document with the given ID if it's at the given revision.
+    fn matching_revision<'s>(
+        stored: &'s HashMap<String, StoredDocument>,
+        id: &str,
+        revision: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<&'s StoredDocument> {
+        match stored.get(id) {
+            Some(current) if document_revision(&current.data)? == revision => Ok(current),
+            _ => Err(DocumentStoreError::PreconditionFailed(id.to_string(), collection.to_string())),
+        }
+    }
+
+    /// Runs a Mango query, following bookmarks until `limit` documents are read.
+    ///
+    /// Returns no documents if the database doesn't exist.
+    async fn find(&self, database: &str, selector: Value, fields: Option<&[&str]>, skip: usize, limit: Option<usize>) -> DocumentStoreResult<Vec<Value>> {
+        let mut documents = Vec::new();
+        let mut bookmark: Option<String> = None;
+
+        loop {
+            let batch = FIND_BATCH_SIZE.min(limit.map_or(usize::MAX, |limit| limit - documents.len()));
+            if batch == 0 {
+                break;
+            }
+
+            let mut request = json!({ "selector": selector, "limit": batch });
+            match &bookmark {
+                Some(bookmark) => { request["bookmark"] = json!(bookmark); },
+                None => { request["skip"] = json!(skip); },
+            }
+            if let Some(fields) = fields {
+                request["fields"] = json!(fields);
+            }
+
+            let (status, mut body) = self.send(self.request(Method::POST, &[database, "_find"]).json(&request)).await?;
+
+            match status {
+                StatusCode::NOT_FOUND => return Ok(Vec::new()),
+                status if !status.is_success() => return Err(Self::error(status, &body)),
+                _ => {},
+            }
+
+            let page = match body.get_mut("docs").map(Value::take) {
+                Some(Value::Array(page)) => page,
+                _ => Vec::new(),
+            };
+            let read = page.len();
+            documents.extend(page);
+
+            if read < batch {
+                break;
+            }
+            bookmark = body.get("bookmark").and_then(Value::as_str).map(str::to_string);
+        }
+
+        Ok(documents)
+    }
+
+    /// Reads every stored document of a collection matching a filter.
+    async fn find_documents(&self, database: &str, filter: Option<&Expr>) -> DocumentStoreResult<Vec<StoredDocument>> {
+        self.find(database, MangoQueryTranslator::selector(filter, &self.operators)?, None, 0, None)
+            .await?
+            .into_iter()
+            .map(StoredDocument::from_json)
+            .collect()
+    }
+
+    /// Reads the first stored document of a collection matching a filter, in sort order.
+    async fn first_document(&self, database: &str, filter: &Expr, sort: &[Sort], collection: &str) -> DocumentStoreResult<Option<StoredDocument>> {
+        let selector = MangoQueryTranslator::selector(Some(filter), &self.operators)?;
+
+        // Sorted like in `query_documents`, by the store
+        match sort.is_empty() {
+            true => self.find(database, selector, None, 0, Some(1))
+                .await?
+                .into_iter()
+                .next()
+                .map(StoredDocument::from_json)
+                .transpose(),
+            false => Ok(
+                self.find(database, selector, None, 0, None)
+                    .await?
+                    .into_iter()
+                    .map(StoredDocument::from_json)
+                    .collect::<DocumentStoreResult<Vec<_>>>()?
+                    .into_iter()
+                    .min_by(|a, b| compare_documents(&a.data, &b.data, sort, |field| self.comparators.get(collection, field)))
+            ),
+        }
+    }
+
+    /// Writes documents with `_bulk_docs`, returning the number written.
+    ///
+    /// A document CouchDB reports as conflicting fails the write with the error built by
+    /// `conflict`; CouchDB has already written the other documents of the batch by then.
+    async fn bulk_write(
+        &self,
+        database: &str,
+        collection: &str,
+        documents: Vec<Value>,
+        conflict: impl Fn(String) -> DocumentStoreError,
+    ) -> DocumentStoreResult<usize> {
+        if documents.is_empty() {
+            return Ok(0);
+        }
+
+        let (status, body) = self.send(
+            self.request(Method::POST, &[database, "_bulk_docs"])
+                .json(&json!({ "docs": documents }))
+        ).await?;
+
+        match status {
+            StatusCode::NOT_FOUND => return Err(DocumentStoreError::CollectionNotFound(collection.to_string())),
+            status if !status.is_success() => return Err(Self::error(status, &body)),
+            _ => {},
+        }
+
+        let mut written = 0;
+        for result in body.as_array().into_iter().flatten() {
+            let id = result.get("id").and_then(Value::as_str).unwrap_or_default().to_string();
+
+            match result.get("error").and_then(Value::as_str) {
+                None => written += 1,
+                Some("conflict") => return Err(conflict(id)),
+                Some(_) => return Err(Self::error(status, result)),
+            }
+        }
+
+        Ok(written)
+    }
+
+    /// Builds the CouchDB document storing `data`, replacing revision `rev` when given.
+    fn couch_document(id: &str, rev: Option<&str>, data: &Bson) -> DocumentStoreResult<Value> {
+        if !matches!(data, Bson::Document(_)) {
+            return Err(DocumentStoreError::InvalidDocument("Expected document".into()));
+        }
+
+        let mut document = json!({ "_id": id, DATA_FIELD: to_json(data) });
+        if let Some(rev) = rev {
+            document["_rev"] = json!(rev);
+        }
+
+        Ok(document)
+    }
+
+    /// Rewrites the documents matching a filter, writing back the ones `rewrite` changed.
+    ///
+    /// Every document is rewritten before any is written, so a failing rewrite changes nothing.
+    async fn rewrite_documents(
+        &self,
+        collection: &str,
+        filter: Option<&Expr>,
+        rewrite: impl Fn(&mut Document) -> DocumentStoreResult<()>,
+    ) -> DocumentStoreResult<WriteReport> {
+        let database = self.database(collection)?;
+        let mut report = WriteReport::default();
+        let mut changed = Vec::new();
+
+        for stored in self.find_documents(&database, filter).await? {
+            let Bson::Document(original) = &stored.data else {
+                continue;
+            };
+
+            let mut document = original.clone();
+            rewrite(&mut document)?;
+            report.matched += 1;
+
+            if &document != original {
+                changed.push(Self::couch_document(&stored.id, Some(&stored.rev), &Bson::Document(document))?);
+            }
+        }
+
+        report.modified = self.bulk_write(&database, collection, changed, |id| {
+            DocumentStoreError::Conflict(id, collection.to_string())
+        }).await?;
+
+        Ok(report)
+    }
+
+    /// Writes documents, replacing the stored revisions, according to `policy`.
+    async fn write_documents(&self, documents: Vec<(Uuid, Bson)>, collection: &str, policy: MissingDocumentPolicy) -> DocumentStoreResult<WriteReport> {
+        let database = self.database(collection)?;
+        let ids = documents.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+
+        let stored = match self.stored_documents(&database, &ids).await? {
+            Some(stored) => stored,
+            None if policy == MissingDocumentPolicy::Error && !documents.is_empty() => {
+                return Err(DocumentStoreError::CollectionNotFound(collection.to_string()));
+            },
+            None => HashMap::new(),
+        };
+
+        // Check every ID up front so an error leaves the collection untouched
+        if policy == MissingDocumentPolicy::Error
+            && let Some(id) = ids.iter().find(|id| !stored.contains_key(&id.to_string()))
+        {
+            return Err(DocumentStoreError::DocumentNotFound(id.to_string(), collection.to_string()));
+        }
+
+        let mut report = WriteReport::default();
+        let mut writes = Vec::new();
+
+        for (id, document) in documents {
+            let id = id.to_string();
+
+            match stored.get(&id) {
+                Some(current) => {
+                    report.matched += 1;
+                    if current.data != document {
+                        report.modified += 1;
+                        writes.push(Self::couch_document(&id, Some(&current.rev), &document)?);
+                    }
+                },
+                None if policy == MissingDocumentPolicy::Upsert => {
+                    report.upserted += 1;
+                    writes.push(Self::couch_document(&id, None, &document)?);
+                },
+                None => {},
+            }
+        }
+
+        if report.upserted > 0 {
+            self.ensure_collection(collection).await?;
+        }
+
+        self.bulk_write(&database, collection, writes, |id| {
+            DocumentStoreError::Conflict(id, collection.to_string())
+        }).await?;
+
+        Ok(report)
+    }
+}
+
+#[async_trait]
+impl StoreBackend for CouchDbStore {
+    async fn insert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        if policy == InsertPolicy::Replace {
+            return self.write_documents(documents, collection, MissingDocumentPolicy::Upsert).await;
+        }
+
+        let database = self.database(collection)?;
+        self.ensure_collection(collection).await?;
+
+        let ids = documents.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        let stored = self.stored_documents(&database, &ids).await?.unwrap_or_default();
+
+        let mut report = WriteReport::default();
+        let mut seen = HashSet::new();
+        let mut writes = Vec::new();
+
+        // Check every ID up front, including repeats within the batch, so a conflict leaves
+        // the collection untouched
+        for (id, document) in &documents {
+            if seen.insert(*id) && !stored.contains_key(&id.to_string()) {
+                report.upserted += 1;
+                writes.push(Self::couch_document(&id.to_string(), None, document)?);
+            } else if policy == InsertPolicy::Skip {
+                report.matched += 1;
+            } else {
+                return Err(DocumentStoreError::DocumentAlreadyExists(id.to_string(), collection.to_string()));
+            }
+        }
+
+        // A document created concurrently conflicts with the new one's missing revision
+        self.bulk_write(&database, collection, writes, |id| {
+            DocumentStoreError::DocumentAlreadyExists(id, collection.to_string())
+        }).await?;
+
+        Ok(report)
+    }
+
+    async fn update_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        self.write_documents(documents, collection, policy).await
+    }
+
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        let database = self.database(collection)?;
+        let ids = documents.iter().map(|(id, ..)| *id).collect::<Vec<_>>();
+
+        let stored = match self.stored_documents(&database, &ids).await? {
+            Some(stored) => stored,
+            None if documents.is_empty() => return Ok(WriteReport::default()),
+            None => return Err(DocumentStoreError::CollectionNotFound(collection.to_string())),
+        };
+
+        let mut report = WriteReport::default();
+        let mut writes = Vec::new();
+        let mut versions = HashMap::new();
+
+        // Check every version up front so a conflict leaves the collection untouched
+        for (id, document, version) in &documents {
+            let id = id.to_string();
+
+            match stored.get(&id) {
+                None => return Err(DocumentStoreError::DocumentNotFound(id, collection.to_string())),
+                Some(current) if stored_version(&current.data, version_field) != Some(*version) => {
+                    return Err(DocumentStoreError::VersionConflict(id, collection.to_string(), *version));
+                },
+                Some(current) => {
+                    report.matched += 1;
+                    if current.data != *document {
+                        report.modified += 1;
+                        writes.push(Self::couch_document(&id, Some(&current.rev), document)?);
+                    }
+                    versions.insert(id, *version);
+                },
+            }
+        }
+
+        // A document written since it was read fails on its revision instead
+        self.bulk_write(&database, collection, writes, |id| {
+            let version = versions.get(&id).copied().unwrap_or_default();
+            DocumentStoreError::VersionConflict(id, collection.to_string(), version)
+        }).await?;
+
+        Ok(report)
+    }
+
+    async fn update_documents_if_match(&self, documents: Vec<(Uuid, Bson, String)>, collection: &str) -> DocumentStoreResult<WriteReport> {
+        let database = self.database(collection)?;
+        let ids = documents.iter().map(|(id, ..)| *id).collect::<Vec<_>>();
+        let stored = self.stored_documents(&database, &ids).await?.unwrap_or_default();
+
+        let mut report = WriteReport::default();
+        let mut writes = Vec::new();
+
+        // Check every revision up front so a mismatch leaves the collection untouched
+        for (id, document, revision) in &documents {
+            let id = id.to_string();
+            let current = Self::matching_revision(&stored, &id, revision, collection)?;
+
+            report.matched += 1;
+            if current.data != *document {
+                report.modified += 1;
+                writes.push(Self::couch_document(&id, Some(&current.rev), document)?);
+            }
+        }
+
+        // A document written since it was read fails on its CouchDB revision instead
+        self.bulk_write(&database, collection, writes, |id| {
+            DocumentStoreError::PreconditionFailed(id, collection.to_string())
+        }).await?;
+
+        Ok(report)
+    }
+
+    async fn upsert_documents(&self, documents: Vec<(Uuid, Bson)>, collection: &str) -> DocumentStoreResult<()> {
+        self.write_documents(documents, collection, MissingDocumentPolicy::Upsert).await?;
+
+        Ok(())
+    }
+
+    async fn delete_documents(&self, ids: Vec<Uuid>, collection: &str) -> DocumentStoreResult<usize> {
+        let database = self.database(collection)?;
+        let Some(stored) = self.stored_documents(&database, &ids).await? else {
+            return Ok(0);
+        };
+
+        let deletions = stored
+            .values()
+            .map(|document| json!({ "_id": document.id, "_rev": document.rev, "_deleted": true }))
+            .collect();
+
+        self.bulk_write(&database, collection, deletions, |id| {
+            DocumentStoreError::Conflict(id, collection.to_string())
+        }).await
+    }
+
+    async fn delete_documents_if_match(&self, documents: Vec<(Uuid, String)>, collection: &str) -> DocumentStoreResult<usize> {
+        let database = self.database(collection)?;
+        let ids = documents.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        let stored = self.stored_documents(&database, &ids).await?.unwrap_or_default();
+
+        let deletions = documents
+            .iter()
+            .map(|(id, revision)| {
+                Self::matching_revision(&stored, &id.to_string(), revision, collection)
+                    .map(|document| json!({ "_id": document.id, "_rev": document.rev, "_deleted": true }))
+            })
+            .collect::<DocumentStoreResult<Vec<_>>>()?;
+
+        self.bulk_write(&database, collection, deletions, |id| {
+            DocumentStoreError::PreconditionFailed(id, collection.to_string())
+        }).await
+    }
+
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        let database = self.database(collection)?;
+
+        // A document changed by another writer since it was read fails on its revision, and
+        // the first match is looked up again
+        loop {
+            let Some(stored) = self.first_document(&database, &filter, &sort, collection).await? else {
+                return Ok(None);
+            };
+            let Bson::Document(original) = &stored.data else {
+                return Err(DocumentStoreError::InvalidDocument(format!("Document {} is not a BSON document", stored.id)));
+            };
+
+            let mut document = original.clone();
+            DocumentUpdater::new(&mut document).apply(&update)?;
+
+            if &document != original {
+                let write = Self::couch_document(&stored.id, Some(&stored.rev), &Bson::Document(document.clone()))?;
+                let written = self.bulk_write(&database, collection, vec![write], |id| {
+                    DocumentStoreError::Conflict(id, collection.to_string())
+                }).await;
+
+                match written {
+                    Err(DocumentStoreError::Conflict(..)) => continue,
+                    written => written?,
+                };
+            }
+
+            return Ok(Some(match returned {
+                ReturnDocument::Before => stored.data,
+                ReturnDocument::After => Bson::Document(document),
+            }));
+        }
+    }
+
+    async fn find_one_and_delete(&self, filter: Expr, sort: Vec<Sort>, collection: &str) -> DocumentStoreResult<Option<Bson>> {
+        let database = self.database(collection)?;
+
+        loop {
+            let Some(stored) = self.first_document(&database, &filter, &sort, collection).await? else {
+                return Ok(None);
+            };
+
+            let deletion = json!({ "_id": stored.id, "_rev": stored.rev, "_deleted": true });
+            let deleted = self.bulk_write(&database, collection, vec![deletion], |id| {
+                DocumentStoreError::Conflict(id, collection.to_string())
+            }).await;
+
+            match deleted {
+                Err(DocumentStoreError::Conflict(..)) => continue,
+                deleted => deleted?,
+            };
+
+            return Ok(Some(stored.data));
+        }
+    }
+
+    async fn update_by_query(&self, filter: Expr, update: Update, collection: &str) -> DocumentStoreResult<WriteReport> {
+        self.rewrite_documents(collection, Some(&filter), |document| {
+            DocumentUpdater::new(document).apply(&update)
+        }).await
+    }
+
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize> {
+        let database = self.database(collection)?;
+
+        let deletions = self.find(&database, MangoQueryTranslator::selector(Some(&filter), &self.operators)?, Some(&["_id", "_rev"]), 0, None)
+            .await?
+            .into_iter()
+            .map(|document| json!({ "_id": document["_id"], "_rev": document["_rev"], "_deleted": true }))
+            .collect();
+
+        self.bulk_write(&database, collection, deletions, |id| {
+            DocumentStoreError::Conflict(id, collection.to_string())
+        }).await
+    }
+
+    async fn get_documents(&self, ids: Vec<Uuid>, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+        let database = self.database(collection)?;
+
+        Ok(
+            self.stored_documents(&database, &ids)
+                .await?
+                .unwrap_or_default()
+                .into_values()
+                .map(|document| document.data)
+                .collect()
+        )
+    }
+
+    async fn query_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+        let database = self.database(collection)?;
+        let selector = MangoQueryTranslator::selector(query.filter.as_ref(), &self.operators)?;
+        let (skip, limit) = (query.offset.unwrap_or(0), query.limit);
+
+        // Mango only sorts through an index covering fields the selector requires, which
+        // would drop documents missing them, so sorted queries are ordered here instead
+        let documents = match query.sort.is_empty() {
+            true => self.find(&database, selector, None, skip, limit)
+                .await?
+                .into_iter()
+                .map(|document| StoredDocument::from_json(document).map(|document| document.data))
+                .collect::<DocumentStoreResult<Vec<_>>>()?,
+            false => {
+                let mut documents = self.find(&database, selector, None, 0, None)
+                    .await?
+                    .into_iter()
+                    .map(|document| StoredDocument::from_json(document).map(|document| document.data))
+                    .collect::<DocumentStoreResult<Vec<_>>>()?;
+
+                sort_documents(&mut documents, &query.sort, |field| self.comparators.get(collection, field));
+
+                documents
+                    .into_iter()
+                    .skip(skip)
+                    .take(limit.unwrap_or(usize::MAX))
+                    .collect()
+            },
+        };
+
+        Ok(match &query.projection {
+            Some(fields) => documents
+                .iter()
+                .map(|document| project_document(document, fields))
+                .collect(),
+            None => documents,
+        })
+    }
+
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize> {
+        let database = self.database(collection)?;
+
+        Ok(
+            self.find(
+                &database,
+                MangoQueryTranslator::selector(query.filter.as_ref(), &self.operators)?,
+                Some(&["_id"]),
+                query.offset.unwrap_or(0),
+                query.limit,
+            )
+            .await?
+            .len()
+        )
+    }
+
+    async fn distinct(&self, field: &str, filter: Option<Expr>, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+        let database = self.database(collection)?;
+        let documents = self.find_documents(&database, filter.as_ref()).await?;
+
+        Ok(distinct_values(documents.iter().map(|document| &document.data), field))
+    }
+
+    async fn aggregate(&self, aggregate: Aggregate, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+        let database = self.database(collection)?;
+        let documents = self.find_documents(&database, aggregate.filter.as_ref()).await?;
+
+        Ok(DocumentAggregator::aggregate(&aggregate, documents.iter().map(|document| &document.data)))
+    }
+
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan> {
+        let database = self.database(collection)?;
+        let (status, body) = self.send(
+            self.request(Method::POST, &[&database, "_explain"])
+                .json(&json!({ "selector": MangoQueryTranslator::selector(query.filter.as_ref(), &self.operators)? }))
+        ).await?;
+
+        if !status.is_success() {
+            return Err(Self::error(status, &body));
+        }
+
+        // `_all_docs` is reported as a "special" index and reads every document
+        let index = &body["index"];
+        let fields = match index["type"].as_str() {
+            Some("json") => index["def"]["fields"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_object)
+                .flat_map(|field| field.keys())
+                .map(|field| field.strip_prefix(&format!("{}.", DATA_FIELD)).unwrap_or(field).to_string())
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Ok(QueryPlan {
+            strategy: match fields.is_empty() {
+                true => ScanStrategy::FullScan,
+                false => ScanStrategy::IndexScan(fields),
+            },
+            details: Bson::try_from(body)?,
+        })
+    }
+
+    async fn current_revision_id(&self) -> DocumentStoreResult<Option<String>> {
+        let database = self.database(REVISIONS_COLLECTION)?;
+        let (status, body) = self.send(self.request(Method::GET, &[&database, REVISION_DOCUMENT_ID])).await?;
+
+        match status {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(body["revision_id"].as_str().map(str::to_string)),
+            status => Err(Self::error(status, &body)),
+        }
+    }
+
+    async fn set_revision_id(&self, revision_id: &str) -> DocumentStoreResult<()> {
+        let database = self.database(REVISIONS_COLLECTION)?;
+        self.ensure_collection(REVISIONS_COLLECTION).await?;
+
+        let (status, current) = self.send(self.request(Method::GET, &[&database, REVISION_DOCUMENT_ID])).await?;
+        let mut document = json!({ "revision_id": revision_id });
+        if status.is_success() {
+            document["_rev"] = current["_rev"].clone();
+        }
+
+        let (status, body) = self.send(
+            self.request(Method::PUT, &[&database, REVISION_DOCUMENT_ID]).json(&document)
+        ).await?;
+
+        match status {
+            StatusCode::CONFLICT => Err(DocumentStoreError::Conflict(REVISION_DOCUMENT_ID.to_string(), REVISIONS_COLLECTION.to_string())),
+            status if status.is_success() => Ok(()),
+            status => Err(Self::error(status, &body)),
+        }
+    }
+
+    async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        let database = self.database(name)?;
+        let (status, body) = self.send(self.request(Method::PUT, &[&database])).await?;
+
+        match status {
+            StatusCode::PRECONDITION_FAILED => Err(DocumentStoreError::CollectionAlreadyExists(name.to_string())),
+            status if status.is_success() => Ok(()),
+            status => Err(Self::error(status, &body)),
+        }
+    }
+
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        // Creating directly and ignoring an existing database avoids the check-then-create race
+        match self.create_collection(name).await {
+            Err(DocumentStoreError::CollectionAlreadyExists(_)) => Ok(()),
+            result => result,
+        }
+    }
+
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        let database = self.database(name)?;
+        let (status, body) = self.send(self.request(Method::HEAD, &[&database])).await?;
+
+        match status {
+            StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(true),
+            status => Err(Self::error(status, &body)),
+        }
+    }
+
+    async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        let database = self.database(name)?;
+        let (status, body) = self.send(self.request(Method::DELETE, &[&database])).await?;
+
+        if status.is_success() || status == StatusCode::NOT_FOUND {
+            self.expiring.remove(name).await;
+        }
+
+        match status {
+            StatusCode::NOT_FOUND => Ok(()),
+            status if status.is_success() => Ok(()),
+            status => Err(Self::error(status, &body)),
+        }
+    }
+
+    async fn list_collections(&self) -> DocumentStoreResult<Vec<String>> {
+        let (status, body) = self.send(self.request(Method::GET, &["_all_dbs"])).await?;
+
+        if !status.is_success() {
+            return Err(Self::error(status, &body));
+        }
+
+        Ok(
+            body.as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .filter_map(|name| name.strip_prefix(&self.database_prefix))
+                .filter(|name| *name != REVISIONS_COLLECTION)
+                .map(str::to_string)
+                .collect()
+        )
+    }
+
+    async fn add_field(&self, collection: &str, field: &str, default: Bson) -> DocumentStoreResult<()> {
+        if !self.collection_exists(collection).await? {
+            return Err(DocumentStoreError::CollectionNotFound(collection.to_string()));
+        }
+
+        self.rewrite_documents(collection, Some(&Expr::Exists(field.to_string(), false)), |document| {
+            document.insert(field, default.clone());
+            Ok(())
+        }).await?;
+
+        Ok(())
+    }
+
+    async fn drop_field(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        if !self.collection_exists(collection).await? {
+            return Err(DocumentStoreError::CollectionNotFound(collection.to_string()));
+        }
+
+        self.rewrite_documents(collection, Some(&Expr::Exists(field.to_string(), true)), |document| {
+            document.remove(field);
+            Ok(())
+        }).await?;
+
+        Ok(())
+    }
+
+    async fn rename_field(&self, collection: &str, field: &str, new: &str) -> DocumentStoreResult<()> {
+        if !self.collection_exists(collection).await? {
+            return Err(DocumentStoreError::CollectionNotFound(collection.to_string()));
+        }
+
+        self.rewrite_documents(collection, Some(&Expr::Exists(field.to_string(), true)), |document| {
+            if let Some(value) = document.remove(field) {
+                document.insert(new, value);
+            }
+            Ok(())
+        }).await?;
+
+        Ok(())
+    }
+
+    async fn add_index(&self, collection: &str, field: &str, unique: bool) -> DocumentStoreResult<()> {
+        if unique {
+            return Err(DocumentStoreError::Unsupported(format!(
+                "CouchDB doesn't support unique indexes, can't index {}.{}",
+                collection, field
+            )));
+        }
+
+        let database = self.database(collection)?;
+        let (status, body) = self.send(
+            self.request(Method::POST, &[&database, "_index"])
+                .json(&json!({
+                    "index": { "fields": [format!("{}.{}", DATA_FIELD, field)] },
+                    "ddoc": index_design_document(field),
+                    "name": field,
+                    "type": "json",
+                }))
+        ).await?;
+
+        match status {
+            StatusCode::NOT_FOUND => Err(DocumentStoreError::CollectionNotFound(collection.to_string())),
+            status if status.is_success() => Ok(()),
+            status => Err(Self::error(status, &body)),
+        }
+    }
+
+    async fn drop_index(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        let database = self.database(collection)?;
+        let (status, body) = self.send(
+            self.request(Method::DELETE, &[&database, "_index", &index_design_document(field), "json", field])
+        ).await?;
+
+        match status {
+            StatusCode::NOT_FOUND => Ok(()),
+            status if status.is_success() => Ok(()),
+            status => Err(Self::error(status, &body)),
+        }
+    }
+
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        // Expired documents are deleted by `sweep_expired`
+        self.database(collection)?;
+        self.expiring.insert(collection, field).await;
+        Ok(())
+    }
+
+    fn encoding(&self) -> &Encoding {
+        &self.encoding
+    }
+}
+
+
+/// Builder for constructing [`CouchDbStore`] instances.
+///
+/// # Example
+///
+/// ```ignore
+/// use doclayer::{backend::StoreBackendBuilder, couchdb::CouchDbStore};
+///
+/// let store = CouchDbStore::builder("http://localhost:5984")
+///     .with_credentials("admin", "secret")
+///     .with_database_prefix("app_")
+///     .build()
+///     .await?;
+/// ```
+pub struct CouchDbStoreBuilder {
+    url: String,
+    credentials: Option<(String, String)>,
+    database_prefix: String,
+    timeout: Option<Duration>,
+    comparators: SortComparators,
+    operators: CustomOperators,
+    encoding: Encoding,
+}
+
+impl CouchDbStoreBuilder {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            credentials: None,
+            database_prefix: DEFAULT_DATABASE_PREFIX.to_string(),
+            timeout: None,
+            comparators: SortComparators::new(),
+            operators: CustomOperators::new(),
+            encoding: Encoding::new(),
+        }
+    }
+
+    /// Authenticates every request with HTTP basic authentication.
+    pub fn with_credentials(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.credentials = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Sets the prefix of the database names, [`DEFAULT_DATABASE_PREFIX`] by default.
+    ///
+    /// The prefix must start with a lowercase letter, since CouchDB reserves database names
+    /// starting with `_` and collection names such as `_revisions` may start with one.
+    pub fn with_database_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.database_prefix = prefix.into();
+        self
+    }
+
+    /// Sets the timeout of every request to the server.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Orders a field with a custom comparator when queries sort by it.
+    ///
+    /// Sorted queries are ordered by the store rather than by CouchDB, so every kind of
+    /// comparator is supported.
+    pub fn with_comparator(mut self, collection: impl Into<String>, field: impl Into<String>, comparator: SortComparator) -> Self {
+        self.comparators.insert(collection, field, comparator);
+        self
+    }
+
+    /// Registers the handler of a custom filter operator, used by `Expr::Custom` filters
+    /// naming it.
+    ///
+    /// Filters are translated into Mango selectors evaluated by CouchDB, so the handler must
+    /// be a [`CustomOperator::Translate`] building a selector. Its field paths must start
+    /// with the prefix it is given, since documents are stored under a data field.
+    pub fn with_operator(mut self, name: impl Into<String>, operator: CustomOperator) -> Self {
+        self.operators.insert(name, operator);
+        self
+    }
+
+    /// Sets the representation of UUIDs, enums and datetimes in stored documents.
+    ///
+    /// Document IDs are always stored as strings in `_id`, since CouchDB requires them to be.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+}
+
+#[async_trait]
+impl StoreBackendBuilder for CouchDbStoreBuilder {
+    type Backend = CouchDbStore;
+
+    async fn build(self) -> DocumentStoreResult<Self::Backend> {
+        let url = Url::parse(&self.url)
+            .map_err(|e| DocumentStoreError::Initialization(format!("Invalid CouchDB URL '{}': {}", self.url, e)))?;
+        if url.cannot_be_a_base() {
+            return Err(DocumentStoreError::Initialization(format!("Invalid CouchDB URL '{}'", self.url)));
+        }
+
+        if !self.database_prefix.starts_with(|c: char| c.is_ascii_lowercase())
+            || !is_valid_database_name(&self.database_prefix)
+        {
+            return Err(DocumentStoreError::Initialization(format!(
+                "Invalid CouchDB database prefix '{}': must start with a lowercase letter and only hold a-z, 0-9 and _$()+-/",
+                self.database_prefix
+            )));
+        }
+
+        let mut client = Client::builder();
+        if let Some(timeout) = self.timeout {
+            client = client.timeout(timeout);
+        }
+
+        Ok(CouchDbStore {
+            client: client
+                .build()
+                .map_err(|e| DocumentStoreError::Initialization(e.to_string()))?,
+            url,
+            credentials: self.credentials,
+            database_prefix: self.database_prefix,
+            comparators: self.comparators,
+            operators: self.operators,
+            encoding: self.encoding,
+            expiring: ExpiringCollections::new(),
+        })
+    }
+}
+
+
+/// Whether a name follows CouchDB's database naming rules.
+fn is_valid_database_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "_$()+-/".contains(c))
+}
+
+/// Returns the design document holding the index of a field.
+fn index_design_document(field: &str) -> String {
+    format!("doclayer-index-{}", field)
+}
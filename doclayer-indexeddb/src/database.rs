@@ -0,0 +1,284 @@
+//! The IndexedDB database holding the persisted store.
+//!
+//! The database has a fixed schema, so collections are created and dropped without the
+//! version changes IndexedDB requires to add object stores:
+//!
+//! - `documents` holds every document as `{ collection, id, document }`, keyed by
+//!   `[collection, id]`, with the document as canonical extended JSON
+//! - `collections` holds `{ name }` for every collection, including empty ones
+//! - `metadata` holds the current migration revision under the `revision` key
+//!
+//! IndexedDB handles can't leave the thread they were created on, so nothing here is
+//! `Send`. [`IndexedDbStore`](crate::IndexedDbStore) wraps the database and the futures
+//! returned here in a `SendWrapper`, which is sound because browsers run wasm on one thread.
+
+use js_sys::{Array, Function, Object, Promise, Reflect};
+use wasm_bindgen::{JsCast, JsValue, closure::Closure};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{DomException, IdbDatabase, IdbFactory, IdbKeyRange, IdbObjectStoreParameters, IdbRequest, IdbTransaction, IdbTransactionMode};
+
+use doclayer_core::error::{DocumentStoreError, DocumentStoreResult};
+
+/// The version of the database schema.
+const SCHEMA_VERSION: u32 = 1;
+
+const DOCUMENTS_STORE: &str = "documents";
+const COLLECTIONS_STORE: &str = "collections";
+const METADATA_STORE: &str = "metadata";
+
+/// The key of the revision in the metadata store.
+const REVISION_KEY: &str = "revision";
+
+
+/// The contents of the database, read when the store is built.
+pub(crate) struct Contents {
+    /// Every document, as its collection, ID and extended JSON
+    pub documents: Vec<(String, String, String)>,
+    /// The name of every collection
+    pub collections: Vec<String>,
+    /// The current migration revision
+    pub revision: Option<String>,
+}
+
+/// An open connection to the database.
+#[derive(Debug)]
+pub(crate) struct Database {
+    database: IdbDatabase,
+}
+
+impl Database {
+    /// Opens the database, creating its object stores if it doesn't exist.
+    pub async fn open(name: &str) -> DocumentStoreResult<Self> {
+        let factory = Reflect::get(&js_sys::global(), &JsValue::from_str("indexedDB"))
+            .ok()
+            .and_then(|factory| factory.dyn_into::<IdbFactory>().ok())
+            .ok_or_else(|| DocumentStoreError::Initialization("IndexedDB is not available in this environment".to_string()))?;
+
+        let request = factory
+            .open_with_u32(name, SCHEMA_VERSION)
+            .map_err(|e| js_error("open database", e))?;
+
+        let upgrade_request = request.clone();
+        let on_upgrade = Closure::once_into_js(move || {
+            if let Ok(database) = upgrade_request.result().and_then(|database| database.dyn_into::<IdbDatabase>()) {
+                let _ = create_object_stores(&database);
+            }
+        });
+        request.set_onupgradeneeded(Some(on_upgrade.unchecked_ref()));
+
+        let database = complete_request(&request)
+            .await
+            .and_then(|database| database.dyn_into::<IdbDatabase>())
+            .map_err(|e| DocumentStoreError::Initialization(js_error("open database", e).to_string()))?;
+
+        Ok(Self { database })
+    }
+
+    /// Reads every document, collection and the revision.
+    pub async fn load(&self) -> DocumentStoreResult<Contents> {
+        let transaction = self.transaction(&[DOCUMENTS_STORE, COLLECTIONS_STORE, METADATA_STORE], IdbTransactionMode::Readonly)?;
+
+        // Requests are issued up front, as the transaction commits once none are pending
+        let documents = object_store(&transaction, DOCUMENTS_STORE)?.get_all().map_err(|e| js_error("read documents", e))?;
+        let collections = object_store(&transaction, COLLECTIONS_STORE)?.get_all().map_err(|e| js_error("read collections", e))?;
+        let revision = object_store(&transaction, METADATA_STORE)?
+            .get(&JsValue::from_str(REVISION_KEY))
+            .map_err(|e| js_error("read revision", e))?;
+
+        let documents = Array::from(&complete_request(&documents).await.map_err(|e| js_error("read documents", e))?)
+            .iter()
+            .map(|record| Ok((string_field(&record, "collection")?, string_field(&record, "id")?, string_field(&record, "document")?)))
+            .collect::<DocumentStoreResult<Vec<_>>>()?;
+        let collections = Array::from(&complete_request(&collections).await.map_err(|e| js_error("read collections", e))?)
+            .iter()
+            .map(|record| string_field(&record, "name"))
+            .collect::<DocumentStoreResult<Vec<_>>>()?;
+        let revision = complete_request(&revision)
+            .await
+            .map_err(|e| js_error("read revision", e))?
+            .as_string();
+
+        Ok(Contents { documents, collections, revision })
+    }
+
+    /// Writes documents of a collection, removing the ones without a document.
+    ///
+    /// The collection is recorded in the same transaction, so it exists once anything was
+    /// written to it.
+    pub async fn write_documents(&self, collection: &str, documents: Vec<(String, Option<String>)>) -> DocumentStoreResult<()> {
+        let transaction = self.transaction(&[DOCUMENTS_STORE, COLLECTIONS_STORE], IdbTransactionMode::Readwrite)?;
+        let store = object_store(&transaction, DOCUMENTS_STORE)?;
+
+        object_store(&transaction, COLLECTIONS_STORE)?
+            .put(&collection_record(collection))
+            .map_err(|e| js_error("write collection", e))?;
+
+        for (id, document) in documents {
+            match document {
+                Some(document) => store.put(&document_record(collection, &id, &document)),
+                None => store.delete(&Array::of2(&JsValue::from_str(collection), &JsValue::from_str(&id))),
+            }
+            .map_err(|e| js_error("write document", e))?;
+        }
+
+        complete_transaction(&transaction).await
+    }
+
+    /// Records an empty collection.
+    pub async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        self.write_documents(name, Vec::new()).await
+    }
+
+    /// Removes a collection and all its documents.
+    pub async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        let transaction = self.transaction(&[DOCUMENTS_STORE, COLLECTIONS_STORE], IdbTransactionMode::Readwrite)?;
+
+        // `[name]` sorts before every key of the collection, and `[name, []]` after, as
+        // arrays sort after strings
+        let documents = IdbKeyRange::bound(
+            &Array::of1(&JsValue::from_str(name)),
+            &Array::of2(&JsValue::from_str(name), &Array::new()),
+        )
+        .map_err(|e| js_error("drop collection", e))?;
+
+        object_store(&transaction, DOCUMENTS_STORE)?
+            .delete(&documents)
+            .map_err(|e| js_error("drop collection", e))?;
+        object_store(&transaction, COLLECTIONS_STORE)?
+            .delete(&JsValue::from_str(name))
+            .map_err(|e| js_error("drop collection", e))?;
+
+        complete_transaction(&transaction).await
+    }
+
+    /// Records the current migration revision.
+    pub async fn set_revision(&self, revision_id: &str) -> DocumentStoreResult<()> {
+        let transaction = self.transaction(&[METADATA_STORE], IdbTransactionMode::Readwrite)?;
+
+        object_store(&transaction, METADATA_STORE)?
+            .put_with_key(&JsValue::from_str(revision_id), &JsValue::from_str(REVISION_KEY))
+            .map_err(|e| js_error("write revision", e))?;
+
+        complete_transaction(&transaction).await
+    }
+
+    /// Closes the connection once its pending transactions complete.
+    pub fn close(&self) {
+        self.database.close();
+    }
+
+    fn transaction(&self, stores: &[&str], mode: IdbTransactionMode) -> DocumentStoreResult<IdbTransaction> {
+        let stores = stores.iter().map(|store| JsValue::from_str(store)).collect::<Array>();
+
+        self.database
+            .transaction_with_str_sequence_and_mode(&stores, mode)
+            .map_err(|e| js_error("start transaction", e))
+    }
+}
+
+
+/// Creates the object stores of a new database.
+fn create_object_stores(database: &IdbDatabase) -> Result<(), JsValue> {
+    let documents = IdbObjectStoreParameters::new();
+    documents.set_key_path(&Array::of2(&JsValue::from_str("collection"), &JsValue::from_str("id")));
+    database.create_object_store_with_optional_parameters(DOCUMENTS_STORE, &documents)?;
+
+    let collections = IdbObjectStoreParameters::new();
+    collections.set_key_path(&JsValue::from_str("name"));
+    database.create_object_store_with_optional_parameters(COLLECTIONS_STORE, &collections)?;
+
+    database.create_object_store(METADATA_STORE)?;
+
+    Ok(())
+}
+
+fn object_store(transaction: &IdbTransaction, name: &str) -> DocumentStoreResult<web_sys::IdbObjectStore> {
+    transaction
+        .object_store(name)
+        .map_err(|e| js_error("open object store", e))
+}
+
+fn document_record(collection: &str, id: &str, document: &str) -> Object {
+    let record = Object::new();
+
+    let _ = Reflect::set(&record, &JsValue::from_str("collection"), &JsValue::from_str(collection));
+    let _ = Reflect::set(&record, &JsValue::from_str("id"), &JsValue::from_str(id));
+    let _ = Reflect::set(&record, &JsValue::from_str("document"), &JsValue::from_str(document));
+
+    record
+}
+
+fn collection_record(name: &str) -> Object {
+    let record = Object::new();
+    let _ = Reflect::set(&record, &JsValue::from_str("name"), &JsValue::from_str(name));
+
+    record
+}
+
+/// Reads a string field of a stored record.
+fn string_field(record: &JsValue, field: &str) -> DocumentStoreResult<String> {
+    Reflect::get(record, &JsValue::from_str(field))
+        .ok()
+        .and_then(|value| value.as_string())
+        .ok_or_else(|| DocumentStoreError::Initialization(format!("Stored record is missing the {:?} field", field)))
+}
+
+/// Waits for a request to succeed, returning its result.
+async fn complete_request(request: &IdbRequest) -> Result<JsValue, JsValue> {
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        let success_request = request.clone();
+        let on_success = Closure::once_into_js(move || {
+            let _ = resolve.call1(&JsValue::UNDEFINED, &success_request.result().unwrap_or(JsValue::UNDEFINED));
+        });
+
+        let error_request = request.clone();
+        let on_error = Closure::once_into_js(move || {
+            let error = error_request.error().ok().flatten().map(JsValue::from).unwrap_or(JsValue::UNDEFINED);
+            let _ = reject.call1(&JsValue::UNDEFINED, &error);
+        });
+
+        request.set_onsuccess(Some(on_success.unchecked_ref()));
+        request.set_onerror(Some(on_error.unchecked_ref()));
+    });
+
+    JsFuture::from(promise).await
+}
+
+/// Waits for a transaction to commit.
+///
+/// # Errors
+///
+/// Returns a backend error if the transaction failed or was aborted, in which case none of
+/// its writes were applied.
+async fn complete_transaction(transaction: &IdbTransaction) -> DocumentStoreResult<()> {
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        let on_complete = Closure::once_into_js(move || {
+            let _ = resolve.call0(&JsValue::UNDEFINED);
+        });
+
+        let error_transaction = transaction.clone();
+        let on_abort = Closure::once_into_js(move || {
+            let error = error_transaction.error().map(JsValue::from).unwrap_or(JsValue::UNDEFINED);
+            let _ = reject.call1(&JsValue::UNDEFINED, &error);
+        });
+
+        // A failed request aborts the transaction, so waiting for the abort covers both
+        transaction.set_oncomplete(Some(on_complete.unchecked_ref()));
+        transaction.set_onabort(Some(on_abort.unchecked_ref()));
+    });
+
+    JsFuture::from(promise)
+        .await
+        .map(|_| ())
+        .map_err(|e| js_error("commit transaction", e))
+}
+
+/// Converts an IndexedDB failure into a backend error.
+fn js_error(action: &str, error: JsValue) -> DocumentStoreError {
+    let message = match error.dyn_ref::<DomException>() {
+        Some(exception) => format!("{}: {}", exception.name(), exception.message()),
+        None => format!("{:?}", error),
+    };
+
+    DocumentStoreError::Backend(format!("Failed to {} in IndexedDB: {}", action, message))
+}
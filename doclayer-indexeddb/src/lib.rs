@@ -0,0 +1,41 @@
+//! IndexedDB storage backend for doclayer, for browser applications compiled to WebAssembly.
+//!
+//! This crate provides an implementation of the `StoreBackend` trait persisting documents
+//! in the browser's IndexedDB, so the same `Document` and `Query` code can run against a
+//! local database in the browser and against MongoDB on the server.
+//!
+//! # Storage
+//!
+//! Like the file backend, [`IndexedDbStore`] loads the whole database into memory when it's
+//! built and serves every query from that copy, using the same evaluation as the in-memory
+//! backend. Every write is applied in memory and then persisted in a single IndexedDB
+//! transaction. Documents are stored as canonical extended JSON, keyed by collection and ID.
+//!
+//! The store is meant for data sets that fit in memory. Changes made to the database by
+//! another tab while the store is open are not picked up until it is built again.
+//!
+//! # Targets
+//!
+//! The backend is only available on `wasm32` targets, in a window or a worker. On other
+//! targets this crate is empty.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::{prelude::*, indexeddb::IndexedDbStore};
+//!
+//! let store = DocumentStore::new(IndexedDbStore::builder("my-app").build().await?);
+//!
+//! let users = store
+//!     .typed_collection::<User>()
+//!     .query(Query::builder().filter(Field::new("active").eq(true)).build())
+//!     .await?;
+//! ```
+
+#[cfg(target_arch = "wasm32")]
+mod database;
+#[cfg(target_arch = "wasm32")]
+pub mod store;
+
+#[cfg(target_arch = "wasm32")]
+pub use store::{IndexedDbStore, IndexedDbStoreBuilder};
@@ -0,0 +1,501 @@
+//! IndexedDB storage backend implementation.
+
+use std::{future::Future, sync::Arc, time::Duration};
+use async_trait::async_trait;
+use mea::mutex::Mutex;
+use bson::{Bson, Uuid};
+use send_wrapper::SendWrapper;
+use serde_json::Value;
+
+use doclayer_core::{
+    aggregate::Aggregate,
+    encoding::Encoding,
+    query::{CustomOperator, Expr, Query, Sort, SortComparator},
+    update::Update,
+    error::{DocumentStoreError, DocumentStoreResult},
+    backend::{DocumentStream, InsertPolicy, MissingDocumentPolicy, QueryPlan, ReturnDocument, StoreBackend, StoreBackendBuilder, WriteReport},
+};
+use doclayer_memory::{InMemoryStore, InMemoryStoreBuilder};
+
+use crate::database::Database;
+
+
+/// Storage backend persisting collections in the browser's IndexedDB.
+///
+/// See the [crate documentation](crate) for how documents are stored.
+///
+/// # Thread Safety
+///
+/// `IndexedDbStore` is cloneable, and clones share the same data. Writes are serialized so
+/// the database always reflects the order in which they were applied. IndexedDB handles are
+/// tied to the thread that opened them, so the store must be used on that thread, which in
+/// the browser is the only one.
+///
+/// # Example
+///
+/// ```ignore
+/// use doclayer::{prelude::*, indexeddb::IndexedDbStore};
+///
+/// let store = DocumentStore::new(IndexedDbStore::builder("my-app").build().await?);
+///
+/// store
+///     .typed_collection::<User>()
+///     .insert(vec![user])
+///     .await?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct IndexedDbStore {
+    /// The name of the IndexedDB database
+    name: String,
+    /// The connection to the database
+    database: Arc<SendWrapper<Database>>,
+    /// The loaded documents, which every read is served from
+    memory: InMemoryStore,
+    /// Serializes writes, so they are persisted in the order they were applied
+    writes: Arc<Mutex<()>>,
+}
+
+impl IndexedDbStore {
+    /// Creates a builder for a store in the given IndexedDB database.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the database, created if it doesn't exist
+    pub fn builder(name: impl Into<String>) -> IndexedDbStoreBuilder {
+        IndexedDbStoreBuilder::new(name)
+    }
+
+    /// Returns the name of the IndexedDB database.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Deletes the documents whose expiry has passed, in every collection made to expire them
+    /// with [`StoreBackend::expire_documents`], and from the database.
+    ///
+    /// # Returns
+    ///
+    /// The number of documents deleted.
+    pub async fn sweep_expired(&self) -> DocumentStoreResult<usize> {
+        self.memory.expiring_collections().sweep(self).await
+    }
+
+    /// Sweeps expired documents repeatedly, waiting `interval` between sweeps.
+    ///
+    /// Meant to be spawned as a background task; this never returns. See
+    /// [`ExpiringCollections::run`](doclayer_core::expiry::ExpiringCollections::run) for the arguments.
+    pub async fn run_sweeper<F, Fut, R>(&self, interval: Duration, sleep: F, on_sweep: R)
+    where
+        F: Fn(Duration) -> Fut,
+        Fut: Future<Output = ()>,
+        R: FnMut(DocumentStoreResult<usize>),
+    {
+        self.memory.expiring_collections().run(self, interval, sleep, on_sweep).await
+    }
+
+    /// Writes the given documents of a collection to the database as they are in memory.
+    ///
+    /// Documents that are no longer in memory are removed.
+    async fn persist(&self, collection: &str, keys: impl IntoIterator<Item = String>) -> DocumentStoreResult<()> {
+        let documents = self.memory
+            .entries(collection, keys)
+            .await
+            .into_iter()
+            .map(|(key, document)| Ok((key, document.map(|document| to_extended_json(&document)).transpose()?)))
+            .collect::<DocumentStoreResult<Vec<_>>>()?;
+
+        SendWrapper::new(self.database.write_documents(collection, documents)).await
+    }
+}
+
+
+#[async_trait]
+impl StoreBackend for IndexedDbStore {
+    async fn insert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        let _guard = self.writes.lock().await;
+        let keys = documents.iter().map(|(id, _)| id.to_string()).collect::<Vec<_>>();
+
+        let report = self.memory.insert_documents(documents, collection, policy).await?;
+        self.persist(collection, keys).await?;
+
+        Ok(report)
+    }
+
+    async fn update_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        let _guard = self.writes.lock().await;
+        let keys = documents.iter().map(|(id, _)| id.to_string()).collect::<Vec<_>>();
+
+        let report = self.memory.update_documents(documents, collection, policy).await?;
+
+        // Nothing was written when a missing collection was skipped
+        if self.memory.collection_exists(collection).await? {
+            self.persist(collection, keys).await?;
+        }
+
+        Ok(report)
+    }
+
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        let _guard = self.writes.lock().await;
+        let keys = documents.iter().map(|(id, ..)| id.to_string()).collect::<Vec<_>>();
+
+        let report = self.memory.update_documents_if_version(documents, version_field, collection).await?;
+        self.persist(collection, keys).await?;
+
+        Ok(report)
+    }
+
+    async fn update_documents_if_match(&self, documents: Vec<(Uuid, Bson, String)>, collection: &str) -> DocumentStoreResult<WriteReport> {
+        let _guard = self.writes.lock().await;
+        let keys = documents.iter().map(|(id, ..)| id.to_string()).collect::<Vec<_>>();
+
+        let report = self.memory.update_documents_if_match(documents, collection).await?;
+        self.persist(collection, keys).await?;
+
+        Ok(report)
+    }
+
+    async fn upsert_documents(&self, documents: Vec<(Uuid, Bson)>, collection: &str) -> DocumentStoreResult<()> {
+        let _guard = self.writes.lock().await;
+        let keys = documents.iter().map(|(id, _)| id.to_string()).collect::<Vec<_>>();
+
+        self.memory.upsert_documents(documents, collection).await?;
+        self.persist(collection, keys).await
+    }
+
+    async fn delete_documents(&self, ids: Vec<Uuid>, collection: &str) -> DocumentStoreResult<usize> {
+        let _guard = self.writes.lock().await;
+
+        if !self.memory.collection_exists(collection).await? {
+            return Ok(0);
+        }
+
+        let keys = ids.iter().map(Uuid::to_string).collect::<Vec<_>>();
+        let deleted = self.memory.delete_documents(ids, collection).await?;
+
+        self.persist(collection, keys).await?;
+
+        Ok(deleted)
+    }
+
+    async fn delete_documents_if_match(&self, documents: Vec<(Uuid, String)>, collection: &str) -> DocumentStoreResult<usize> {
+        let _guard = self.writes.lock().await;
+        let keys = documents.iter().map(|(id, _)| id.to_string()).collect::<Vec<_>>();
+
+        let deleted = self.memory.delete_documents_if_match(documents, collection).await?;
+        self.persist(collection, keys).await?;
+
+        Ok(deleted)
+    }
+
+    async fn update_by_query(&self, filter: Expr, update: Update, collection: &str) -> DocumentStoreResult<WriteReport> {
+        let _guard = self.writes.lock().await;
+
+        // Writes are serialized, so the matched documents are exactly the ones updated
+        let keys = self.memory.matching_keys(&filter, collection).await?;
+        let report = self.memory.update_by_query(filter, update, collection).await?;
+
+        if !keys.is_empty() {
+            self.persist(collection, keys).await?;
+        }
+
+        Ok(report)
+    }
+
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize> {
+        let _guard = self.writes.lock().await;
+
+        let keys = self.memory.matching_keys(&filter, collection).await?;
+        let deleted = self.memory.delete_by_query(filter, collection).await?;
+
+        if !keys.is_empty() {
+            self.persist(collection, keys).await?;
+        }
+
+        Ok(deleted)
+    }
+
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        let _guard = self.writes.lock().await;
+
+        // Writes are serialized, so the first match is the document updated
+        let key = self.memory.first_matching_key(&filter, &sort, collection).await?;
+        let document = self.memory.find_one_and_update(filter, sort, update, returned, collection).await?;
+
+        if let Some(key) = key {
+            self.persist(collection, vec![key]).await?;
+        }
+
+        Ok(document)
+    }
+
+    async fn find_one_and_delete(&self, filter: Expr, sort: Vec<Sort>, collection: &str) -> DocumentStoreResult<Option<Bson>> {
+        let _guard = self.writes.lock().await;
+
+        let key = self.memory.first_matching_key(&filter, &sort, collection).await?;
+        let document = self.memory.find_one_and_delete(filter, sort, collection).await?;
+
+        if let Some(key) = key {
+            self.persist(collection, vec![key]).await?;
+        }
+
+        Ok(document)
+    }
+
+    async fn get_documents(&self, ids: Vec<Uuid>, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+        self.memory.get_documents(ids, collection).await
+    }
+
+    async fn query_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+        self.memory.query_documents(query, collection).await
+    }
+
+    async fn query_stream(&self, query: Query, collection: &str) -> DocumentStoreResult<DocumentStream> {
+        self.memory.query_stream(query, collection).await
+    }
+
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize> {
+        self.memory.count_documents(query, collection).await
+    }
+
+    async fn distinct(&self, field: &str, filter: Option<Expr>, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+        self.memory.distinct(field, filter, collection).await
+    }
+
+    async fn aggregate(&self, aggregate: Aggregate, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+        self.memory.aggregate(aggregate, collection).await
+    }
+
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan> {
+        self.memory.explain(query, collection).await
+    }
+
+    async fn current_revision_id(&self) -> DocumentStoreResult<Option<String>> {
+        self.memory.current_revision_id().await
+    }
+
+    async fn set_revision_id(&self, revision_id: &str) -> DocumentStoreResult<()> {
+        let _guard = self.writes.lock().await;
+
+        SendWrapper::new(self.database.set_revision(revision_id)).await?;
+        self.memory.set_revision_id(revision_id).await
+    }
+
+    async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        let _guard = self.writes.lock().await;
+
+        if self.memory.collection_exists(name).await? {
+            return Err(DocumentStoreError::CollectionAlreadyExists(name.to_string()));
+        }
+
+        SendWrapper::new(self.database.create_collection(name)).await?;
+        self.memory.create_collection(name).await
+    }
+
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        self.memory.collection_exists(name).await
+    }
+
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        let _guard = self.writes.lock().await;
+
+        // Recording a collection in the database keeps the documents it already has
+        SendWrapper::new(self.database.create_collection(name)).await?;
+        self.memory.ensure_collection(name).await
+    }
+
+    async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        let _guard = self.writes.lock().await;
+
+        self.memory.drop_collection(name).await?;
+        SendWrapper::new(self.database.drop_collection(name)).await
+    }
+
+    async fn list_collections(&self) -> DocumentStoreResult<Vec<String>> {
+        self.memory.list_collections().await
+    }
+
+    async fn add_field(&self, collection: &str, field: &str, default: Bson) -> DocumentStoreResult<()> {
+        let _guard = self.writes.lock().await;
+
+        self.memory.add_field(collection, field, default).await?;
+        self.persist(collection, self.memory.keys(collection).await).await
+    }
+
+    async fn drop_field(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        let _guard = self.writes.lock().await;
+
+        self.memory.drop_field(collection, field).await?;
+        self.persist(collection, self.memory.keys(collection).await).await
+    }
+
+    async fn rename_field(&self, collection: &str, field: &str, new: &str) -> DocumentStoreResult<()> {
+        let _guard = self.writes.lock().await;
+
+        self.memory.rename_field(collection, field, new).await?;
+        self.persist(collection, self.memory.keys(collection).await).await
+    }
+
+    async fn add_index(&self, collection: &str, field: &str, unique: bool) -> DocumentStoreResult<()> {
+        self.memory.add_index(collection, field, unique).await
+    }
+
+    async fn drop_index(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.memory.drop_index(collection, field).await
+    }
+
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.memory.expire_documents(collection, field).await
+    }
+
+    fn encoding(&self) -> &Encoding {
+        self.memory.encoding()
+    }
+
+    async fn close(&self) -> DocumentStoreResult<()> {
+        // Every write is persisted before releasing the lock, so the connection is idle
+        let _guard = self.writes.lock().await;
+        self.database.close();
+
+        Ok(())
+    }
+}
+
+
+/// Builder for constructing [`IndexedDbStore`] instances.
+///
+/// # Example
+///
+/// ```ignore
+/// use doclayer::indexeddb::IndexedDbStore;
+/// use doclayer::backend::StoreBackendBuilder;
+///
+/// let store = IndexedDbStore::builder("my-app").build().await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct IndexedDbStoreBuilder {
+    name: String,
+    memory: InMemoryStoreBuilder,
+}
+
+impl IndexedDbStoreBuilder {
+    /// Creates a builder for a store in the given IndexedDB database.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the database, created if it doesn't exist
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into(), memory: InMemoryStoreBuilder::default() }
+    }
+
+    /// Orders a field with a custom comparator when queries sort by it.
+    ///
+    /// See [`InMemoryStoreBuilder::with_comparator`].
+    pub fn with_comparator(mut self, collection: impl Into<String>, field: impl Into<String>, comparator: SortComparator) -> Self {
+        self.memory = self.memory.with_comparator(collection, field, comparator);
+        self
+    }
+
+    /// Registers the handler of a custom filter operator.
+    ///
+    /// See [`InMemoryStoreBuilder::with_operator`].
+    pub fn with_operator(mut self, name: impl Into<String>, operator: CustomOperator) -> Self {
+        self.memory = self.memory.with_operator(name, operator);
+        self
+    }
+
+    /// Sets the representation of UUIDs, enums and datetimes in stored documents.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.memory = self.memory.with_encoding(encoding);
+        self
+    }
+}
+
+#[async_trait]
+impl StoreBackendBuilder for IndexedDbStoreBuilder {
+    type Backend = IndexedDbStore;
+
+    /// Opens the database and loads every collection into memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Initialization`] if IndexedDB isn't available or the
+    /// database can't be opened, or if a stored document isn't keyed by a UUID or doesn't
+    /// hold a JSON object.
+    async fn build(self) -> DocumentStoreResult<Self::Backend> {
+        let name = self.name;
+        let (database, contents) = SendWrapper::new(async {
+            let database = Database::open(&name).await?;
+            let contents = database.load().await?;
+
+            DocumentStoreResult::Ok((database, contents))
+        })
+        .await?;
+
+        let memory = self.memory.build().await?;
+
+        for collection in contents.collections {
+            memory.create_collection(&collection).await?;
+        }
+
+        for (collection, id, document) in contents.documents {
+            let id = Uuid::parse_str(&id)
+                .map_err(|_| DocumentStoreError::Initialization(format!(
+                    "Document {} in collection {} is not keyed by a UUID",
+                    id,
+                    collection
+                )))?;
+
+            memory
+                .insert_documents(vec![(id, from_extended_json(&document)?)], &collection, InsertPolicy::ErrorOnConflict)
+                .await?;
+        }
+
+        if let Some(revision) = contents.revision {
+            memory.set_revision_id(&revision).await?;
+        }
+
+        Ok(IndexedDbStore {
+            name,
+            database: Arc::new(SendWrapper::new(database)),
+            memory,
+            writes: Arc::new(Mutex::new(())),
+        })
+    }
+}
+
+
+/// Serializes a document as canonical extended JSON, which preserves every BSON type.
+fn to_extended_json(document: &Bson) -> DocumentStoreResult<String> {
+    Ok(serde_json::to_string(&document.clone().into_canonical_extjson())?)
+}
+
+/// Parses a stored document.
+fn from_extended_json(document: &str) -> DocumentStoreResult<Bson> {
+    serde_json::from_str::<Value>(document)
+        .map_err(DocumentStoreError::from)
+        .and_then(|value| Bson::try_from(value).map_err(DocumentStoreError::from))
+        .map_err(|e| DocumentStoreError::Initialization(format!("Invalid stored document: {}", e)))
+}
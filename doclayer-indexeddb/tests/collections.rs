@@ -0,0 +1,46 @@
+//! Creates the same collection concurrently, as tabs opening the same database at once do:
+//! exactly one creator succeeds, the other is told the collection exists, and ensuring it
+//! succeeds for both.
+//!
+//! Run in a browser with `wasm-pack test --headless --firefox doclayer-indexeddb`.
+
+#![cfg(target_arch = "wasm32")]
+
+use doclayer_core::{
+    backend::{StoreBackend, StoreBackendBuilder},
+    error::DocumentStoreError,
+};
+use doclayer_indexeddb::IndexedDbStore;
+use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+#[wasm_bindgen_test]
+async fn indexeddb_creates_a_collection_once() {
+    let backend = IndexedDbStore::builder(format!("doclayer-test-{}", bson::Uuid::new()))
+        .build()
+        .await
+        .unwrap();
+
+    let (first, second) = futures::join!(backend.create_collection("things"), backend.create_collection("things"));
+
+    let created = [&first, &second]
+        .iter()
+        .filter(|result| result.is_ok())
+        .count();
+    assert_eq!(created, 1, "{first:?}, {second:?}");
+    assert!(
+        [&first, &second]
+            .iter()
+            .any(|result| matches!(result, Err(DocumentStoreError::CollectionAlreadyExists(name)) if name == "things")),
+        "{first:?}, {second:?}"
+    );
+    assert!(backend.collection_exists("things").await.unwrap());
+
+    let (first, second) = futures::join!(backend.ensure_collection("others"), backend.ensure_collection("others"));
+    assert!(first.is_ok() && second.is_ok(), "{first:?}, {second:?}");
+
+    // Ensuring a collection that was already created
+    backend.ensure_collection("things").await.unwrap();
+    assert!(backend.collection_exists("things").await.unwrap());
+}
@@ -0,0 +1,497 @@
+//! Expansion of `#[derive(Document)]`.
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    Data, DeriveInput, Error, Fields, GenericArgument, Ident, LitInt, LitStr, Path, PathArguments,
+    Result, Type, ext::IdentExt, meta::ParseNestedMeta,
+};
+
+/// Options parsed from the container-level `#[document(...)]` attribute.
+struct DocumentOptions {
+    collection: Option<LitStr>,
+    version: Option<LitInt>,
+    upgrade_with: Option<Path>,
+    indexes: Vec<IndexOptions>,
+    expires: Option<LitStr>,
+    soft_delete: Option<LitStr>,
+}
+
+/// A `#[document(index(field = "..", unique))]` declaration.
+struct IndexOptions {
+    field: LitStr,
+    unique: bool,
+}
+
+impl IndexOptions {
+    fn parse(meta: &ParseNestedMeta) -> Result<Self> {
+        let mut field = None;
+        let mut unique = false;
+
+        meta.parse_nested_meta(|inner| {
+            if inner.path.is_ident("field") {
+                let name: LitStr = inner.value()?.parse()?;
+                if name.value().is_empty() {
+                    return Err(Error::new_spanned(&name, "index field must not be empty"));
+                }
+                field = Some(name);
+            } else if inner.path.is_ident("unique") {
+                unique = true;
+            } else {
+                return Err(inner.error("unsupported index option"));
+            }
+            Ok(())
+        })?;
+
+        let field = field.ok_or_else(|| meta.error("index requires a `field = \"...\"` option"))?;
+
+        Ok(IndexOptions { field, unique })
+    }
+}
+
+impl DocumentOptions {
+    fn parse(input: &DeriveInput) -> Result<Self> {
+        let mut options = DocumentOptions {
+            collection: None,
+            version: None,
+            upgrade_with: None,
+            indexes: Vec::new(),
+            expires: None,
+            soft_delete: None,
+        };
+
+        for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("document")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("collection") {
+                    options.collection = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("version") {
+                    let version: LitInt = meta.value()?.parse()?;
+                    if version.base10_parse::<u32>()? == 0 {
+                        return Err(Error::new_spanned(&version, "version must be at least 1"));
+                    }
+                    options.version = Some(version);
+                } else if meta.path.is_ident("upgrade_with") {
+                    options.upgrade_with = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("index") {
+                    options.indexes.push(IndexOptions::parse(&meta)?);
+                } else if meta.path.is_ident("expires") {
+                    let field: LitStr = meta.value()?.parse()?;
+                    if field.value().is_empty() {
+                        return Err(Error::new_spanned(&field, "expires field must not be empty"));
+                    }
+                    options.expires = Some(field);
+                } else if meta.path.is_ident("soft_delete") {
+                    let field: LitStr = meta.value()?.parse()?;
+                    if field.value().is_empty() {
+                        return Err(Error::new_spanned(&field, "soft_delete field must not be empty"));
+                    }
+                    options.soft_delete = Some(field);
+                } else {
+                    return Err(meta.error("unsupported document attribute"));
+                }
+                Ok(())
+            })?;
+        }
+
+        Ok(options)
+    }
+}
+
+/// The fields of a document the derive reads.
+struct DocumentFields {
+    /// The identifier field: the one marked `#[document(id)]`, otherwise the one named `id`
+    id: Ident,
+    /// The field marked `#[document(version)]` and its stored name
+    version: Option<(Ident, String)>,
+}
+
+/// Finds the identifier field and the version field, if any.
+fn document_fields(input: &DeriveInput) -> Result<DocumentFields> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &input.ident,
+                    "Document can only be derived for structs with named fields",
+                ));
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "Document can only be derived for structs",
+            ));
+        }
+    };
+
+    let rename_all = serde_rename_all(input)?;
+    let mut marked = None;
+    let mut version = None;
+    for field in fields {
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("document")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("id") {
+                    marked = field.ident.clone();
+                    Ok(())
+                } else if meta.path.is_ident("version") {
+                    let options = serde_field(field)?;
+                    if options.skipped {
+                        return Err(meta.error("the version field must be stored"));
+                    }
+                    if let Some(ident) = &field.ident {
+                        let name = stored_name(ident, options, &rename_all)?;
+                        version = Some((ident.clone(), name));
+                    }
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported document field attribute"))
+                }
+            })?;
+        }
+    }
+
+    let id = marked
+        .or_else(|| {
+            fields
+                .iter()
+                .filter_map(|field| field.ident.clone())
+                .find(|ident| ident == "id")
+        })
+        .ok_or_else(|| {
+            Error::new_spanned(
+                &input.ident,
+                "Document requires an `id` field or a field marked with #[document(id)]",
+            )
+        })?;
+
+    Ok(DocumentFields { id, version })
+}
+
+/// Serde options affecting the stored name of a field.
+#[derive(Default)]
+struct SerdeField {
+    rename: Option<String>,
+    skipped: bool,
+}
+
+/// Reads a `rename`-style serde value, taking the serialized name from `rename(serialize = "..")`.
+pub(crate) fn serde_name(meta: &ParseNestedMeta) -> Result<Option<String>> {
+    if meta.input.peek(syn::Token![=]) {
+        let name: LitStr = meta.value()?.parse()?;
+        return Ok(Some(name.value()));
+    }
+
+    let mut name = None;
+    meta.parse_nested_meta(|inner| {
+        let value: LitStr = inner.value()?.parse()?;
+        if inner.path.is_ident("serialize") {
+            name = Some(value.value());
+        }
+        Ok(())
+    })?;
+
+    Ok(name)
+}
+
+/// Consumes the value of a serde option the derive doesn't care about.
+pub(crate) fn skip_serde_value(meta: &ParseNestedMeta) -> Result<()> {
+    if meta.input.peek(syn::Token![=]) {
+        meta.value()?.parse::<syn::Expr>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        let content;
+        syn::parenthesized!(content in meta.input);
+        content.parse::<TokenStream>()?;
+    }
+    Ok(())
+}
+
+/// Reads the container-level `#[serde(rename_all = "..")]` rule.
+fn serde_rename_all(input: &DeriveInput) -> Result<Option<String>> {
+    let mut rule = None;
+
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename_all") {
+                rule = serde_name(&meta)?;
+                Ok(())
+            } else {
+                skip_serde_value(&meta)
+            }
+        })?;
+    }
+
+    Ok(rule)
+}
+
+/// Reads the field-level serde options.
+fn serde_field(field: &syn::Field) -> Result<SerdeField> {
+    let mut options = SerdeField::default();
+
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                options.rename = serde_name(&meta)?;
+                Ok(())
+            } else if ["skip", "skip_serializing", "flatten"]
+                .iter()
+                .any(|name| meta.path.is_ident(name))
+            {
+                // Skipped fields aren't stored, and flattened fields are stored under their own names
+                options.skipped = true;
+                Ok(())
+            } else {
+                skip_serde_value(&meta)
+            }
+        })?;
+    }
+
+    Ok(options)
+}
+
+/// Applies a serde `rename_all` rule to a snake_case field name.
+pub(crate) fn apply_rename_all(rule: &str, name: &str) -> Result<String> {
+    let pascal = || {
+        name.split('_')
+            .map(|word| {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                    None => String::new(),
+                }
+            })
+            .collect::<String>()
+    };
+
+    Ok(match rule {
+        "lowercase" | "snake_case" => name.to_string(),
+        "UPPERCASE" | "SCREAMING_SNAKE_CASE" => name.to_uppercase(),
+        "PascalCase" => pascal(),
+        "camelCase" => {
+            let pascal = pascal();
+            let mut chars = pascal.chars();
+            match chars.next() {
+                Some(first) => first.to_lowercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        }
+        "kebab-case" => name.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => name.replace('_', "-").to_uppercase(),
+        _ => {
+            return Err(Error::new(
+                proc_macro2::Span::call_site(),
+                format!("unsupported serde rename_all rule `{rule}`"),
+            ));
+        }
+    })
+}
+
+/// Returns the name a field is stored under, after serde renames.
+fn stored_name(ident: &Ident, options: SerdeField, rename_all: &Option<String>) -> Result<String> {
+    let rust_name = ident.unraw().to_string();
+
+    match (options.rename, rename_all) {
+        (Some(rename), _) => Ok(rename),
+        (None, Some(rule)) => apply_rename_all(rule, &rust_name),
+        (None, None) => Ok(rust_name),
+    }
+}
+
+/// Returns `T` for `Option<T>`, so optional fields are compared by their inner type.
+pub(crate) fn unwrap_option(ty: &Type) -> &Type {
+    if let Type::Path(path) = ty
+        && path.qself.is_none()
+        && let Some(segment) = path.path.segments.last()
+        && segment.ident == "Option"
+        && let PathArguments::AngleBracketed(args) = &segment.arguments
+        && args.args.len() == 1
+        && let Some(GenericArgument::Type(inner)) = args.args.first()
+    {
+        return inner;
+    }
+
+    ty
+}
+
+/// Generates the `<Name>Fields` type holding a typed `Field` constant and method per stored
+/// field, and its `TypedFields` implementation.
+///
+/// Generic structs are skipped, since field types may refer to the type parameters.
+fn field_constants(input: &DeriveInput) -> Result<TokenStream> {
+    let Data::Struct(data) = &input.data else {
+        return Ok(TokenStream::new());
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Ok(TokenStream::new());
+    };
+    if !input.generics.params.is_empty() {
+        return Ok(TokenStream::new());
+    }
+
+    let rename_all = serde_rename_all(input)?;
+    let mut constants = Vec::new();
+    let mut methods = Vec::new();
+
+    for field in &fields.named {
+        let Some(ident) = &field.ident else {
+            continue;
+        };
+        let options = serde_field(field)?;
+        if options.skipped {
+            continue;
+        }
+
+        let rust_name = ident.unraw().to_string();
+        let stored = stored_name(ident, options, &rename_all)?;
+        let constant = format_ident!("{}", rust_name.to_uppercase());
+        let ty = unwrap_option(&field.ty);
+        let doc = format!("The `{stored}` field.");
+
+        constants.push(quote! {
+            #[doc = #doc]
+            pub const #constant: ::doclayer::query::Field<#ty> = ::doclayer::query::Field::new(#stored);
+        });
+        methods.push(quote! {
+            #[doc = #doc]
+            pub const fn #ident(&self) -> ::doclayer::query::Field<#ty> {
+                Self::#constant
+            }
+        });
+    }
+
+    let vis = &input.vis;
+    let name = &input.ident;
+    let fields_name = format_ident!("{}Fields", name);
+    let doc = format!("Typed field references for [`{name}`], generated by `#[derive(Document)]`.");
+
+    Ok(quote! {
+        #[doc = #doc]
+        #[derive(Debug, Clone, Copy)]
+        #[allow(dead_code)]
+        #vis struct #fields_name;
+
+        #[allow(dead_code)]
+        impl #fields_name {
+            #(#constants)*
+
+            #(#methods)*
+        }
+
+        impl ::doclayer::query::TypedFields for #name {
+            type Fields = #fields_name;
+
+            const FIELDS: #fields_name = #fields_name;
+        }
+    })
+}
+
+pub(crate) fn expand(input: DeriveInput) -> Result<TokenStream> {
+    let options = DocumentOptions::parse(&input)?;
+    let DocumentFields { id, version: version_field } = document_fields(&input)?;
+    let collection = options.collection.ok_or_else(|| {
+        Error::new_spanned(
+            &input.ident,
+            "missing #[document(collection = \"...\")] attribute",
+        )
+    })?;
+
+    let fields = field_constants(&input)?;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let version = options.version.map(|version| {
+        quote! {
+            fn schema_version() -> u32 {
+                #version
+            }
+        }
+    });
+
+    let upgrade = options.upgrade_with.map(|path| {
+        quote! {
+            fn upgrade_from(
+                version: u32,
+                document: ::doclayer::bson::Bson,
+            ) -> ::doclayer::error::DocumentStoreResult<::doclayer::bson::Bson> {
+                #path(version, document)
+            }
+        }
+    });
+
+    let versioned = version_field.map(|(field, stored)| {
+        quote! {
+            fn version(&self) -> ::std::option::Option<u64> {
+                ::std::option::Option::Some(self.#field as u64)
+            }
+
+            fn set_version(&mut self, version: u64) {
+                self.#field = version as _;
+            }
+
+            fn version_field() -> ::std::option::Option<&'static str> {
+                ::std::option::Option::Some(#stored)
+            }
+        }
+    });
+
+    let indexes = (!options.indexes.is_empty()).then(|| {
+        let definitions = options.indexes.iter().map(|index| {
+            let field = &index.field;
+            let unique = index.unique;
+            quote! {
+                ::doclayer::document::IndexDefinition {
+                    field: #field,
+                    unique: #unique,
+                }
+            }
+        });
+
+        quote! {
+            fn index_definitions() -> ::std::vec::Vec<::doclayer::document::IndexDefinition> {
+                ::std::vec![#(#definitions),*]
+            }
+        }
+    });
+
+    let expires = options.expires.map(|field| {
+        quote! {
+            fn expires_field() -> ::std::option::Option<&'static str> {
+                ::std::option::Option::Some(#field)
+            }
+        }
+    });
+
+    let soft_delete = options.soft_delete.map(|field| {
+        quote! {
+            fn deleted_field() -> ::std::option::Option<&'static str> {
+                ::std::option::Option::Some(#field)
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::doclayer::document::Document for #name #ty_generics #where_clause {
+            fn id(&self) -> &::doclayer::bson::Uuid {
+                &self.#id
+            }
+
+            fn collection_name() -> &'static str {
+                #collection
+            }
+
+            #version
+
+            #upgrade
+
+            #indexes
+
+            #expires
+
+            #soft_delete
+
+            #versioned
+        }
+
+        #fields
+    })
+}
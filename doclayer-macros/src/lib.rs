@@ -7,5 +7,101 @@
 extern crate self as doclayer_macros;
 
 use proc_macro::TokenStream;
-use quote::quote;
 use syn::{parse_macro_input, DeriveInput};
+
+mod document;
+mod schema;
+
+/// Derives the `Document` trait for a struct.
+///
+/// The struct's identifier is read from the field named `id`, or from the field marked
+/// with `#[document(id)]`. The container attribute configures the rest of the trait:
+///
+/// - `collection = "name"` - The collection the document belongs to (required)
+/// - `version = N` - The current schema version (defaults to `1`)
+/// - `upgrade_with = path` - A `fn(u32, Bson) -> DocumentStoreResult<Bson>` used to upgrade
+///   documents stored with an older schema version
+/// - `index(field = "name", unique)` - An index to create with `ensure_indexes`; repeat for
+///   several indexes and omit `unique` for a non-unique index
+/// - `expires = "name"` - The stored field holding the datetime at which a document expires;
+///   `ensure_indexes` makes the collection delete documents once it has passed
+/// - `soft_delete = "name"` - The stored field marking a document as soft-deleted, set by
+///   `soft_delete` and cleared by `restore`; reads leave out documents where it's set
+///
+/// Marking an integer field with `#[document(version)]` makes it the document's version for
+/// optimistic concurrency control, read and incremented by `update_if_version`. It is unrelated
+/// to the schema `version = N` of the container attribute.
+///
+/// For non-generic structs, the derive also generates a `<Name>Fields` type with a typed
+/// `Field` constant per stored field (`UserFields::EMAIL`), named after the field in upper
+/// case, and a method named after the field (`f.email()`), which `Find::filter_by` and the
+/// sorts taking a closure pass the fields to. Stored names follow serde's `rename` and
+/// `rename_all`; skipped and flattened fields get no constant.
+///
+/// # Example
+///
+/// ```ignore
+/// use doclayer::prelude::*;
+///
+/// #[derive(Debug, Clone, Serialize, Deserialize, Document)]
+/// #[document(collection = "users", version = 2, upgrade_with = upgrade_user)]
+/// #[document(index(field = "email", unique))]
+/// pub struct User {
+///     pub id: Uuid,
+///     pub email: String,
+/// }
+///
+/// let expr = UserFields::EMAIL.ends_with("@example.com");
+/// let users = users.find().filter_by(|f| f.email().ends_with("@example.com")).await?;
+/// ```
+#[proc_macro_derive(Document, attributes(document))]
+pub fn derive_document(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    document::expand(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+/// Derives the `SchemaType` trait for a struct or an enum.
+///
+/// The generated JSON Schema describes values as serde serializes them: it follows `rename`,
+/// `rename_all`, `skip`, `flatten`, `transparent` and the `tag`, `content` and `untagged` enum
+/// representations. Every field is required unless it's an `Option`, has `#[serde(default)]`
+/// or `#[serde(skip_serializing_if = "..")]`, or its container has `#[serde(default)]`.
+///
+/// Every field type must implement `SchemaType`. Fields serialized with
+/// `#[serde(with = "..")]` accept any value, unless they're given a schema with
+/// `#[schema(with = path)]`, where `path` is a `fn() -> serde_json::Value`.
+///
+/// # Example
+///
+/// ```ignore
+/// use doclayer::prelude::*;
+///
+/// #[derive(Debug, Clone, Serialize, Deserialize, Document, SchemaType)]
+/// #[document(collection = "users")]
+/// pub struct User {
+///     pub id: Uuid,
+///     pub email: String,
+///     #[serde(default)]
+///     pub roles: Vec<Role>,
+/// }
+///
+/// #[derive(Debug, Clone, Serialize, Deserialize, SchemaType)]
+/// #[serde(rename_all = "lowercase")]
+/// pub enum Role {
+///     Admin,
+///     Member,
+/// }
+///
+/// store.register_schema::<User>()?;
+/// ```
+#[proc_macro_derive(SchemaType, attributes(schema))]
+pub fn derive_schema_type(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    schema::expand(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
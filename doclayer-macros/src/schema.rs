@@ -0,0 +1,401 @@
+//! Expansion of `#[derive(SchemaType)]`.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{
+    Attribute, Data, DeriveInput, Error, Field, Fields, FieldsNamed, Path, Result, Type,
+    ext::IdentExt, parse_quote,
+};
+
+use crate::document::{apply_rename_all, serde_name, skip_serde_value, unwrap_option};
+
+/// Container-level serde options, of a struct, an enum or an enum variant.
+#[derive(Default)]
+struct SerdeContainer {
+    rename: Option<String>,
+    rename_all: Option<String>,
+    skipped: bool,
+    default: bool,
+    transparent: bool,
+    tag: Option<String>,
+    content: Option<String>,
+    untagged: bool,
+}
+
+impl SerdeContainer {
+    fn parse(attrs: &[Attribute]) -> Result<Self> {
+        let mut options = SerdeContainer::default();
+
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    options.rename = serde_name(&meta)?;
+                    return Ok(());
+                } else if meta.path.is_ident("rename_all") {
+                    options.rename_all = serde_name(&meta)?;
+                    return Ok(());
+                } else if meta.path.is_ident("tag") {
+                    options.tag = Some(meta.value()?.parse::<syn::LitStr>()?.value());
+                    return Ok(());
+                } else if meta.path.is_ident("content") {
+                    options.content = Some(meta.value()?.parse::<syn::LitStr>()?.value());
+                    return Ok(());
+                } else if meta.path.is_ident("skip") || meta.path.is_ident("skip_serializing") {
+                    options.skipped = true;
+                } else if meta.path.is_ident("default") {
+                    options.default = true;
+                } else if meta.path.is_ident("transparent") {
+                    options.transparent = true;
+                } else if meta.path.is_ident("untagged") {
+                    options.untagged = true;
+                }
+                skip_serde_value(&meta)
+            })?;
+        }
+
+        Ok(options)
+    }
+}
+
+/// Serde and `#[schema(...)]` options of a field.
+#[derive(Default)]
+struct FieldOptions {
+    rename: Option<String>,
+    skipped: bool,
+    flatten: bool,
+    /// Whether the field may be left out, with `default` or `skip_serializing_if`
+    optional: bool,
+    /// Whether the field is serialized by a custom function, so its type doesn't describe it
+    custom: bool,
+    schema_with: Option<Path>,
+}
+
+impl FieldOptions {
+    fn parse(field: &Field) -> Result<Self> {
+        let mut options = FieldOptions::default();
+
+        for attr in &field.attrs {
+            if attr.path().is_ident("serde") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("rename") {
+                        options.rename = serde_name(&meta)?;
+                        return Ok(());
+                    } else if meta.path.is_ident("skip") || meta.path.is_ident("skip_serializing") {
+                        options.skipped = true;
+                    } else if meta.path.is_ident("flatten") {
+                        options.flatten = true;
+                    } else if meta.path.is_ident("default")
+                        || meta.path.is_ident("skip_serializing_if")
+                    {
+                        options.optional = true;
+                    } else if meta.path.is_ident("with") || meta.path.is_ident("serialize_with") {
+                        options.custom = true;
+                    }
+                    skip_serde_value(&meta)
+                })?;
+            } else if attr.path().is_ident("schema") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("with") {
+                        options.schema_with = Some(meta.value()?.parse()?);
+                        Ok(())
+                    } else {
+                        Err(meta.error("unsupported schema attribute"))
+                    }
+                })?;
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// Returns the expression building the schema of the field's values.
+    fn schema(&self, ty: &Type) -> TokenStream {
+        match (&self.schema_with, self.custom) {
+            (Some(path), _) => quote!(#path()),
+            (None, true) => quote!(::doclayer::serde_json::json!({})),
+            (None, false) => quote!(<#ty as ::doclayer::schema::SchemaType>::json_schema()),
+        }
+    }
+}
+
+/// Returns `true` for `Option<T>`, whose fields may hold null.
+fn is_option(ty: &Type) -> bool {
+    !std::ptr::eq(unwrap_option(ty), ty)
+}
+
+/// Returns `true` for map types, whose flattened fields can have any name.
+fn is_map(ty: &Type) -> bool {
+    matches!(
+        ty,
+        Type::Path(path) if path.path.segments.last().is_some_and(|segment| {
+            ["HashMap", "BTreeMap", "Map", "Document", "Value", "Bson"]
+                .iter()
+                .any(|name| segment.ident == name)
+        })
+    )
+}
+
+/// Applies a serde `rename_all` rule to a PascalCase variant name.
+fn rename_variant(rule: &str, name: &str) -> Result<String> {
+    let snake_case = name
+        .chars()
+        .enumerate()
+        .fold(String::new(), |mut snake_case, (i, c)| {
+            if i > 0 && c.is_uppercase() {
+                snake_case.push('_');
+            }
+            snake_case.extend(c.to_lowercase());
+            snake_case
+        });
+
+    match rule {
+        "lowercase" => Ok(name.to_lowercase()),
+        "UPPERCASE" => Ok(name.to_uppercase()),
+        "PascalCase" => Ok(name.to_string()),
+        rule => apply_rename_all(rule, &snake_case),
+    }
+}
+
+/// Builds the schema of an object with named fields.
+///
+/// `tag` is the field holding the variant name of an internally tagged enum, and the name.
+fn object_schema(
+    fields: &FieldsNamed,
+    rename_all: Option<&str>,
+    all_optional: bool,
+    tag: Option<(&str, &str)>,
+) -> Result<TokenStream> {
+    let mut names = Vec::new();
+    let mut schemas = Vec::new();
+    let mut required = Vec::new();
+    let mut flattened = Vec::new();
+    let mut open = false;
+
+    if let Some((field, name)) = tag {
+        names.push(field.to_string());
+        schemas.push(quote!(::doclayer::serde_json::json!({ "const": #name })));
+        required.push(field.to_string());
+    }
+
+    for field in &fields.named {
+        let Some(ident) = &field.ident else {
+            continue;
+        };
+        let options = FieldOptions::parse(field)?;
+        if options.skipped {
+            continue;
+        }
+        if options.flatten {
+            // The fields of a flattened struct are checked by its own schema, while a flattened
+            // map or option lets the object hold any field
+            match is_map(&field.ty) || is_option(&field.ty) {
+                true => open = true,
+                false => flattened.push(options.schema(&field.ty)),
+            }
+            continue;
+        }
+
+        let rust_name = ident.unraw().to_string();
+        let stored = match (&options.rename, rename_all) {
+            (Some(rename), _) => rename.clone(),
+            (None, Some(rule)) => apply_rename_all(rule, &rust_name)?,
+            (None, None) => rust_name,
+        };
+
+        if !options.optional && !all_optional && !is_option(&field.ty) {
+            required.push(stored.clone());
+        }
+        schemas.push(options.schema(&field.ty));
+        names.push(stored);
+    }
+
+    let mut keywords = vec![
+        quote!("type": "object"),
+        quote!("properties": { #(#names: (#schemas)),* }),
+    ];
+    if !required.is_empty() {
+        keywords.push(quote!("required": [#(#required),*]));
+    }
+    if !flattened.is_empty() {
+        keywords.push(quote!("allOf": [#((#flattened)),*]));
+    }
+    if open {
+        keywords.push(quote!("additionalProperties": true));
+    }
+
+    Ok(quote!(::doclayer::serde_json::json!({ #(#keywords),* })))
+}
+
+/// Builds the schema of the data of a tuple struct or variant, or `None` for a unit.
+fn data_schema(fields: &Fields, rename_all: Option<&str>) -> Result<Option<TokenStream>> {
+    match fields {
+        Fields::Named(fields) => object_schema(fields, rename_all, false, None).map(Some),
+        Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+            let field = &fields.unnamed[0];
+            Ok(Some(FieldOptions::parse(field)?.schema(&field.ty)))
+        }
+        Fields::Unnamed(fields) => {
+            let items = fields
+                .unnamed
+                .iter()
+                .map(|field| Ok(FieldOptions::parse(field)?.schema(&field.ty)))
+                .collect::<Result<Vec<_>>>()?;
+            let count = items.len();
+
+            Ok(Some(quote!(::doclayer::serde_json::json!({
+                "type": "array",
+                "items": [#((#items)),*],
+                "minItems": #count,
+                "maxItems": #count,
+            }))))
+        }
+        Fields::Unit => Ok(None),
+    }
+}
+
+fn struct_schema(
+    input: &DeriveInput,
+    fields: &Fields,
+    serde: &SerdeContainer,
+) -> Result<TokenStream> {
+    if serde.transparent {
+        for field in fields {
+            let options = FieldOptions::parse(field)?;
+            if !options.skipped {
+                return Ok(options.schema(&field.ty));
+            }
+        }
+
+        return Err(Error::new_spanned(&input.ident, "transparent structs need a field"));
+    }
+
+    match fields {
+        Fields::Named(fields) => {
+            object_schema(fields, serde.rename_all.as_deref(), serde.default, None)
+        }
+        fields => Ok(data_schema(fields, None)?
+            .unwrap_or_else(|| quote!(::doclayer::serde_json::json!({ "type": "null" })))),
+    }
+}
+
+fn enum_schema(data: &syn::DataEnum, serde: &SerdeContainer) -> Result<TokenStream> {
+    let mut units = Vec::new();
+    let mut branches = Vec::new();
+
+    for variant in &data.variants {
+        let options = SerdeContainer::parse(&variant.attrs)?;
+        if options.skipped {
+            continue;
+        }
+
+        let rust_name = variant.ident.unraw().to_string();
+        let name = match (&options.rename, &serde.rename_all) {
+            (Some(rename), _) => rename.clone(),
+            (None, Some(rule)) => rename_variant(rule, &rust_name)?,
+            (None, None) => rust_name,
+        };
+        let rename_all = options.rename_all.as_deref();
+
+        let branch = match (&serde.tag, &serde.content, serde.untagged) {
+            (_, _, true) => data_schema(&variant.fields, rename_all)?
+                .unwrap_or_else(|| quote!(::doclayer::serde_json::json!({ "type": "null" }))),
+            (Some(tag), Some(content), false) => match data_schema(&variant.fields, rename_all)? {
+                Some(data) => quote!(::doclayer::serde_json::json!({
+                    "type": "object",
+                    "properties": { #tag: { "const": #name }, #content: (#data) },
+                    "required": [#tag, #content],
+                })),
+                None => quote!(::doclayer::serde_json::json!({
+                    "type": "object",
+                    "properties": { #tag: { "const": #name } },
+                    "required": [#tag],
+                })),
+            },
+            (Some(tag), None, false) => match &variant.fields {
+                Fields::Named(fields) => {
+                    object_schema(fields, rename_all, false, Some((tag, &name)))?
+                }
+                Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                    let data = data_schema(&variant.fields, rename_all)?;
+                    quote!(::doclayer::serde_json::json!({
+                        "allOf": [
+                            { "type": "object", "properties": { #tag: { "const": #name } }, "required": [#tag] },
+                            (#data)
+                        ]
+                    }))
+                }
+                _ => quote!(::doclayer::serde_json::json!({
+                    "type": "object",
+                    "properties": { #tag: { "const": #name } },
+                    "required": [#tag],
+                })),
+            },
+            (None, _, false) => match data_schema(&variant.fields, rename_all)? {
+                Some(data) => quote!(::doclayer::serde_json::json!({
+                    "type": "object",
+                    "properties": { #name: (#data) },
+                    "required": [#name],
+                    "additionalProperties": false,
+                })),
+                None => {
+                    units.push(name);
+                    continue;
+                }
+            },
+        };
+
+        branches.push(branch);
+    }
+
+    // Unit variants of externally tagged enums are stored as their names
+    if !units.is_empty() {
+        branches.insert(
+            0,
+            quote!(::doclayer::serde_json::json!({ "type": "string", "enum": [#(#units),*] })),
+        );
+    }
+
+    Ok(match branches.len() {
+        0 => quote!(::doclayer::serde_json::Value::Bool(false)),
+        1 => branches.remove(0),
+        _ => quote!(::doclayer::serde_json::json!({ "anyOf": [#((#branches)),*] })),
+    })
+}
+
+pub(crate) fn expand(mut input: DeriveInput) -> Result<TokenStream> {
+    let serde = SerdeContainer::parse(&input.attrs)?;
+
+    let schema = match &input.data {
+        Data::Struct(data) => struct_schema(&input, &data.fields, &serde)?,
+        Data::Enum(data) => enum_schema(data, &serde)?,
+        Data::Union(_) => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "SchemaType can only be derived for structs and enums",
+            ));
+        }
+    };
+
+    let parameters = input
+        .generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect::<Vec<_>>();
+    let where_clause = input.generics.make_where_clause();
+    for parameter in parameters {
+        where_clause
+            .predicates
+            .push(parse_quote!(#parameter: ::doclayer::schema::SchemaType));
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::doclayer::schema::SchemaType for #name #ty_generics #where_clause {
+            fn json_schema() -> ::doclayer::serde_json::Value {
+                #schema
+            }
+        }
+    })
+}
@@ -0,0 +1,206 @@
+//! Grouped aggregation over in-memory documents.
+//!
+//! This module computes doclayer [`Aggregate`]s over BSON documents, following
+//! the semantics of MongoDB's `$group` stage so results match across backends.
+
+use std::{collections::HashMap, cmp::Ordering};
+use bson::{Bson, Document};
+
+use doclayer_core::aggregate::{Aggregate, Accumulator};
+
+use crate::evaluator::{Comparable, resolve_path, value_key};
+
+
+/// Running state of a single accumulator within a group.
+enum AccumulatorState {
+    Count(i64),
+    Sum {
+        integer: Option<i64>,
+        float: f64,
+        is_float: bool,
+        is_long: bool,
+    },
+    Avg {
+        total: f64,
+        count: usize,
+    },
+    Min(Option<Bson>),
+    Max(Option<Bson>),
+}
+
+impl AccumulatorState {
+    fn new(accumulator: &Accumulator) -> Self {
+        match accumulator {
+            Accumulator::Count => AccumulatorState::Count(0),
+            Accumulator::Sum(_) => AccumulatorState::Sum {
+                integer: Some(0),
+                float: 0.0,
+                is_float: false,
+                is_long: false,
+            },
+            Accumulator::Avg(_) => AccumulatorState::Avg { total: 0.0, count: 0 },
+            Accumulator::Min(_) => AccumulatorState::Min(None),
+            Accumulator::Max(_) => AccumulatorState::Max(None),
+        }
+    }
+
+    fn add(&mut self, value: &Bson) {
+        match self {
+            AccumulatorState::Count(count) => *count += 1,
+            AccumulatorState::Sum { integer, float, is_float, is_long } => match value {
+                Bson::Int32(number) => {
+                    *integer = integer.and_then(|total| total.checked_add(*number as i64));
+                    *float += *number as f64;
+                },
+                Bson::Int64(number) => {
+                    *integer = integer.and_then(|total| total.checked_add(*number));
+                    *float += *number as f64;
+                    *is_long = true;
+                },
+                Bson::Double(number) => {
+                    *float += number;
+                    *is_float = true;
+                },
+                _ => {},
+            },
+            AccumulatorState::Avg { total, count } => {
+                if let Some(number) = Self::as_number(value) {
+                    *total += number;
+                    *count += 1;
+                }
+            },
+            AccumulatorState::Min(current) => Self::keep(current, value, Ordering::Less),
+            AccumulatorState::Max(current) => Self::keep(current, value, Ordering::Greater),
+        }
+    }
+
+    fn finish(self) -> Bson {
+        match self {
+            AccumulatorState::Count(count) => Bson::Int64(count),
+            AccumulatorState::Sum { integer, float, is_float, is_long } => match integer {
+                // Like MongoDB, the result widens to the widest input type and overflows into a double
+                Some(total) if !is_float && !is_long && i32::try_from(total).is_ok() => Bson::Int32(total as i32),
+                Some(total) if !is_float => Bson::Int64(total),
+                _ => Bson::Double(float),
+            },
+            AccumulatorState::Avg { total, count } => match count {
+                0 => Bson::Null,
+                _ => Bson::Double(total / count as f64),
+            },
+            AccumulatorState::Min(value) | AccumulatorState::Max(value) => value.unwrap_or(Bson::Null),
+        }
+    }
+
+    fn as_number(value: &Bson) -> Option<f64> {
+        match value {
+            Bson::Int32(number) => Some(*number as f64),
+            Bson::Int64(number) => Some(*number as f64),
+            Bson::Double(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    /// Replaces the current value if the new one compares as `wanted` against it.
+    fn keep(current: &mut Option<Bson>, value: &Bson, wanted: Ordering) {
+        if matches!(value, Bson::Null) {
+            return;
+        }
+
+        let replace = match current {
+            Some(existing) => Comparable::from(value).partial_cmp(&Comparable::from(&*existing)) == Some(wanted),
+            None => true,
+        };
+
+        if replace {
+            *current = Some(value.clone());
+        }
+    }
+}
+
+
+/// Computes [`Aggregate`]s over documents held in memory.
+pub struct DocumentAggregator;
+
+impl DocumentAggregator {
+    /// Groups documents and computes the aggregate's accumulators for every group.
+    ///
+    /// # Returns
+    ///
+    /// One document per group, holding the group-by fields followed by the accumulator results.
+    pub fn aggregate<'a>(aggregate: &Aggregate, documents: impl IntoIterator<Item = &'a Bson>) -> Vec<Bson> {
+        let mut groups: Vec<(Vec<Option<Bson>>, Vec<AccumulatorState>)> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+
+        for document in documents {
+            let key = aggregate.group_by
+                .iter()
+                .map(|field| Self::field_value(document, field))
+                .collect::<Vec<_>>();
+
+            let position = *positions
+                .entry(
+                    key.iter()
+                        .map(Self::group_key)
+                        .collect::<Vec<_>>()
+                        .join("\u{0}")
+                )
+                .or_insert_with(|| {
+                    groups.push((
+                        key,
+                        aggregate.accumulators
+                            .iter()
+                            .map(|(_, accumulator)| AccumulatorState::new(accumulator))
+                            .collect(),
+                    ));
+                    groups.len() - 1
+                });
+
+            for ((_, accumulator), state) in aggregate.accumulators.iter().zip(groups[position].1.iter_mut()) {
+                match accumulator.field() {
+                    Some(field) => {
+                        if let Some(value) = Self::field_value(document, field) {
+                            state.add(&value);
+                        }
+                    },
+                    None => state.add(&Bson::Null),
+                }
+            }
+        }
+
+        groups
+            .into_iter()
+            .map(|(key, states)| {
+                let mut result = Document::new();
+
+                for (field, value) in aggregate.group_by.iter().zip(key) {
+                    result.insert(field, value.unwrap_or(Bson::Null));
+                }
+                for ((name, _), state) in aggregate.accumulators.iter().zip(states) {
+                    result.insert(name, state.finish());
+                }
+
+                Bson::Document(result)
+            })
+            .collect()
+    }
+
+    /// Reads a field the way a MongoDB `$field` expression does: paths through arrays
+    /// collect every value into an array.
+    fn field_value(document: &Bson, field: &str) -> Option<Bson> {
+        let mut values = resolve_path(document, field);
+
+        match values.len() {
+            0 => None,
+            1 => Some(values.remove(0).clone()),
+            _ => Some(Bson::Array(values.into_iter().cloned().collect())),
+        }
+    }
+
+    /// Renders a group value into a string that is equal for values MongoDB groups together.
+    fn group_key(value: &Option<Bson>) -> String {
+        match value {
+            Some(value) => value_key(value),
+            None => "null".to_string(),
+        }
+    }
+}
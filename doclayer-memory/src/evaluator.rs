@@ -3,14 +3,25 @@
 //! This module provides the evaluation engine for query expressions,
 //! enabling filtering and comparison operations on BSON documents.
 
-use std::{collections::HashMap, cmp::Ordering};
+use std::{collections::{HashMap, HashSet}, cmp::Ordering};
 use bson::{Bson, datetime::DateTime};
+use regex::{Regex, RegexBuilder};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 use doclayer_core::{
-    query::{QueryVisitor, Expr, FieldOp},
+    query::{QueryVisitor, CustomOperators, Expr, FieldOp, Sort, SortComparator, SortDirection},
     error::{DocumentStoreError, DocumentStoreResult},
 };
 
+/// Number of documents from which [`DocumentEvaluator::filter_documents`] evaluates chunks of
+/// them in parallel.
+#[cfg(feature = "parallel")]
+const PARALLEL_THRESHOLD: usize = 8192;
+
+/// Number of documents evaluated by each parallel task.
+#[cfg(feature = "parallel")]
+const PARALLEL_CHUNK_SIZE: usize = 1024;
 
 /// Type-erased, comparable representation of BSON values.
 ///
@@ -36,6 +47,8 @@ pub(crate) enum Comparable<'a> {
     Array(Vec<Comparable<'a>>),
     /// Map/Object of comparable values
     Map(HashMap<&'a str, Comparable<'a>>),
+    /// Any other value, such as a binary UUID or an ObjectId, only equal to an identical value
+    Other(&'a Bson),
 }
 
 impl<'a> From<&'a Bson> for Comparable<'a> {
@@ -60,7 +73,7 @@ impl<'a> From<&'a Bson> for Comparable<'a> {
                     .map(|(k, v)| (k.as_str(), Comparable::from(v)))
                     .collect::<HashMap<_, _>>()
             ),
-            _ => Comparable::Null, // Other types are not comparable
+            other => Comparable::Other(other), // Other types are not ordered
         }
     }
 }
@@ -75,6 +88,7 @@ impl<'a> PartialEq for Comparable<'a> {
             (Comparable::String(a), Comparable::String(b)) => a == b,
             (Comparable::Array(a), Comparable::Array(b)) => a == b,
             (Comparable::Map(a), Comparable::Map(b)) => a == b,
+            (Comparable::Other(a), Comparable::Other(b)) => a == b,
             _ => false,
         }
     }
@@ -93,34 +107,190 @@ impl<'a> PartialOrd for Comparable<'a> {
 }
 
 
-pub(crate) struct DocumentEvaluator<'a> {
+/// Evaluates query expressions against a single BSON document.
+///
+/// Besides the in-memory store, backends whose servers can't evaluate an operation use
+/// it to filter fetched documents with the same semantics.
+pub struct DocumentEvaluator<'a> {
     document: &'a Bson,
+    case_insensitive: bool,
+    operators: Option<&'a CustomOperators>,
+    regexes: Option<&'a Regexes>,
 }
 
 impl<'a> DocumentEvaluator<'a> {
     pub fn new(document: &'a Bson) -> Self {
-        Self { document }
+        Self { document, case_insensitive: false, operators: None, regexes: None }
+    }
+
+    /// Evaluates [`Expr::Custom`] operators with the handlers of a registry.
+    ///
+    /// Without a registry, every custom operator fails to evaluate.
+    pub fn with_operators(mut self, operators: &'a CustomOperators) -> Self {
+        self.operators = Some(operators);
+        self
     }
 
+    /// Evaluates [`FieldOp::Regex`] comparisons with the patterns compiled from the expression.
+    ///
+    /// Without them, each comparison compiles its pattern again, so evaluating a filter
+    /// against many documents should compile its [`Regexes`] once and share them.
+    pub fn with_regexes(mut self, regexes: &'a Regexes) -> Self {
+        self.regexes = Some(regexes);
+        self
+    }
+
+    /// Evaluates an expression against the document.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::InvalidDocument`] if the stored value is not a document,
+    /// or a backend error if the expression is invalid.
     pub fn evaluate(&mut self, expr: &Expr) -> DocumentStoreResult<bool> {
+        if !matches!(self.document, Bson::Document(_)) {
+            return Err(DocumentStoreError::InvalidDocument(format!(
+                "Expected a document, found a stored value of type {:?}",
+                self.document.element_type()
+            )));
+        }
+
         self.visit_expr(expr)
     }
 
+    fn compare(&self, field_value: &Bson, op: &FieldOp, value: &Bson) -> DocumentStoreResult<bool> {
+        if self.case_insensitive
+            && matches!(op, FieldOp::Contains | FieldOp::NotContains | FieldOp::StartsWith | FieldOp::EndsWith)
+            && let (Bson::String(left), Bson::String(right)) = (field_value, value)
+        {
+            let (left, right) = (left.to_lowercase(), right.to_lowercase());
+
+            return Ok(match op {
+                FieldOp::Contains => left.contains(&right),
+                FieldOp::NotContains => !left.contains(&right),
+                FieldOp::StartsWith => left.starts_with(&right),
+                FieldOp::EndsWith => left.ends_with(&right),
+                _ => unreachable!(),
+            });
+        }
+
+        match op {
+            FieldOp::Eq => Ok(Comparable::from(field_value) == Comparable::from(value)),
+            FieldOp::Ne => Ok(Comparable::from(field_value) != Comparable::from(value)),
+            FieldOp::Gt | FieldOp::Gte | FieldOp::Lt | FieldOp::Lte => {
+                match Comparable::from(field_value).partial_cmp(&Comparable::from(value)) {
+                    Some(ordering) => Ok(match op {
+                        FieldOp::Gt => ordering == Ordering::Greater,
+                        FieldOp::Gte => ordering == Ordering::Greater || ordering == Ordering::Equal,
+                        FieldOp::Lt => ordering == Ordering::Less,
+                        FieldOp::Lte => ordering == Ordering::Less || ordering == Ordering::Equal,
+                        _ => unreachable!(),
+                    }),
+                    None => Ok(false),
+                }
+            },
+            FieldOp::Contains => match Comparable::from(field_value) {
+                Comparable::Array(array) => Ok(
+                    array
+                        .iter()
+                        .any(|item| item == &Comparable::from(value))
+                ),
+                Comparable::String(left) => match Comparable::from(value) {
+                    Comparable::String(right) => Ok(left.contains(right)),
+                    _ => Ok(false),
+                },
+                _ => Ok(false),
+            },
+            FieldOp::NotContains => match Comparable::from(field_value) {
+                Comparable::Array(array) => Ok(
+                    !array
+                        .iter()
+                        .any(|item| item == &Comparable::from(value))
+                ),
+                Comparable::String(left) => match Comparable::from(value) {
+                    Comparable::String(right) => Ok(!left.contains(right)),
+                    _ => Ok(true),
+                },
+                _ => Ok(true),
+            },
+            FieldOp::StartsWith => match (Comparable::from(field_value), Comparable::from(value)) {
+                (Comparable::String(left), Comparable::String(right)) => Ok(left.starts_with(right)),
+                _ => Ok(false),
+            },
+            FieldOp::EndsWith => match (Comparable::from(field_value), Comparable::from(value)) {
+                (Comparable::String(left), Comparable::String(right)) => Ok(left.ends_with(right)),
+                _ => Ok(false),
+            },
+            FieldOp::AnyOf => is_any_of(field_value, value),
+            FieldOp::NoneOf => Ok(!is_any_of(field_value, value)?),
+            FieldOp::Regex => match (Comparable::from(field_value), value) {
+                (Comparable::String(haystack), Bson::String(pattern)) => Ok(
+                    match self.regexes.and_then(|regexes| regexes.get(pattern, self.case_insensitive)) {
+                        Some(regex) => regex.is_match(haystack),
+                        None => compile_regex(pattern, self.case_insensitive)?.is_match(haystack),
+                    }
+                ),
+                (_, Bson::String(_)) => Ok(false),
+                _ => Err(regex_pattern_error()),
+            },
+        }
+    }
+
+    /// Returns copies of the documents matching an expression, in the order given.
+    ///
+    /// With the `parallel` feature, the documents of large collections are split into chunks
+    /// evaluated on rayon's thread pool, and the matches of each chunk are concatenated in
+    /// order. `regexes` are those [compiled](Regexes::compile) from `expr`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first evaluation error in the order given, such as a stored value that is
+    /// not a document, whether or not chunks were evaluated in parallel.
     pub fn filter_documents(
         documents: impl IntoIterator<Item = &'a Bson>,
         expr: &Expr,
+        operators: &'a CustomOperators,
+        regexes: &Regexes,
     ) -> DocumentStoreResult<Vec<Bson>> {
-        Ok(
-            documents
-                .into_iter()
-                .filter(|doc| {
-                    DocumentEvaluator::new(doc)
-                        .evaluate(expr)
-                        .unwrap_or(false)
-                })
-                .cloned()
-                .collect::<Vec<_>>()
-        )
+        #[cfg(feature = "parallel")]
+        {
+            let documents = documents.into_iter().collect::<Vec<_>>();
+
+            if documents.len() >= PARALLEL_THRESHOLD {
+                // Collecting every chunk's result before the first error keeps the error
+                // returned the same whichever chunk fails first
+                let chunks = documents
+                    .par_chunks(PARALLEL_CHUNK_SIZE)
+                    .map(|chunk| Self::filter_sequential(chunk.iter().copied(), expr, operators, regexes))
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .collect::<DocumentStoreResult<Vec<_>>>()?;
+
+                return Ok(chunks.into_iter().flatten().collect());
+            }
+
+            Self::filter_sequential(documents, expr, operators, regexes)
+        }
+
+        #[cfg(not(feature = "parallel"))]
+        Self::filter_sequential(documents, expr, operators, regexes)
+    }
+
+    /// Returns copies of the documents matching an expression, evaluating them one by one.
+    fn filter_sequential(
+        documents: impl IntoIterator<Item = &'a Bson>,
+        expr: &Expr,
+        operators: &'a CustomOperators,
+        regexes: &Regexes,
+    ) -> DocumentStoreResult<Vec<Bson>> {
+        let mut matched = Vec::new();
+
+        for doc in documents {
+            if DocumentEvaluator::new(doc).with_operators(operators).with_regexes(regexes).evaluate(expr)? {
+                matched.push(doc.clone());
+            }
+        }
+
+        Ok(matched)
     }
 }
 
@@ -152,125 +322,505 @@ impl<'a> QueryVisitor for DocumentEvaluator<'a> {
         Ok(!self.visit_expr(expr)?)
     }
 
+    fn visit_case_insensitive(&mut self, expr: &Expr) -> Result<Self::Output, Self::Error> {
+        let previous = std::mem::replace(&mut self.case_insensitive, true);
+        let result = self.visit_expr(expr);
+        self.case_insensitive = previous;
+
+        result
+    }
+
+    fn visit_elem_match(&mut self, field: &str, expr: &Expr) -> Result<Self::Output, Self::Error> {
+        // Like MongoDB's `$elemMatch`, only arrays are searched and only embedded documents
+        // can match, each evaluated on its own
+        for candidate in resolve_path(self.document, field) {
+            if let Bson::Array(elements) = candidate {
+                for element in elements.iter().filter(|element| matches!(element, Bson::Document(_))) {
+                    let mut evaluator = DocumentEvaluator { document: element, case_insensitive: self.case_insensitive, operators: self.operators, regexes: self.regexes };
+
+                    if evaluator.visit_expr(expr)? {
+                        return Ok(true);
+                    }
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn visit_custom(&mut self, name: &str, payload: &Bson) -> Result<Self::Output, Self::Error> {
+        match self.operators {
+            Some(operators) => operators.evaluate(name, self.document, payload),
+            None => Err(DocumentStoreError::Backend(format!("No handler is registered for the custom operator {}", name))),
+        }
+    }
+
     fn visit_exists(&mut self, field: &str, should_exist: bool) -> Result<Self::Output, Self::Error> {
-        Ok(
-            self.document
-                .as_document()
-                .expect("expected document")
-                .get(field)
-                .is_some() == should_exist
-        )
+        Ok(resolve_path(self.document, field).is_empty() != should_exist)
     }
 
     fn visit_field(&mut self, field: &str, op: &FieldOp, value: &Bson) -> Result<Self::Output, Self::Error> {
-        match self.document
-            .as_document()
-            .expect("expected document")
-            .get(field)
-        {
-            Some(field_value) => match op {
-                FieldOp::Eq => Ok(Comparable::from(field_value) == Comparable::from(value)),
-                FieldOp::Ne => Ok(Comparable::from(field_value) != Comparable::from(value)),
-                FieldOp::Gt | FieldOp::Gte | FieldOp::Lt | FieldOp::Lte => {
-                    match Comparable::from(field_value).partial_cmp(&Comparable::from(value)) {
-                        Some(ordering) => Ok(match op {
-                            FieldOp::Gt => ordering == Ordering::Greater,
-                            FieldOp::Gte => ordering == Ordering::Greater || ordering == Ordering::Equal,
-                            FieldOp::Lt => ordering == Ordering::Less,
-                            FieldOp::Lte => ordering == Ordering::Less || ordering == Ordering::Equal,
-                            _ => unreachable!(),
-                        }),
-                        None => Ok(false),
+        let candidates = resolve_path(self.document, field);
+
+        if candidates.is_empty() {
+            // Like MongoDB, a missing field compares as `null` for membership operators
+            return match op {
+                FieldOp::AnyOf => is_any_of(&Bson::Null, value),
+                FieldOp::NoneOf => Ok(!is_any_of(&Bson::Null, value)?),
+                _ => Ok(false),
+            };
+        }
+
+        // A path through an array yields one candidate per element. Like MongoDB, positive
+        // operators match if any candidate matches, negated operators only if all of them do.
+        match op {
+            FieldOp::Ne | FieldOp::NotContains | FieldOp::NoneOf => {
+                for candidate in candidates {
+                    if !self.compare(candidate, op, value)? {
+                        return Ok(false);
                     }
-                },
-                FieldOp::Contains => match Comparable::from(field_value) {
-                    Comparable::Array(array) => Ok(
-                        array
-                            .iter()
-                            .any(|item| item == &Comparable::from(value))
-                    ),
-                    Comparable::String(left) => match Comparable::from(value) {
-                        Comparable::String(right) => Ok(left.contains(right)),
-                        _ => Ok(false),
-                    },
-                    _ => Ok(false),
-                },
-                FieldOp::NotContains => match Comparable::from(field_value) {
-                    Comparable::Array(array) => Ok(
-                        !array
-                            .iter()
-                            .any(|item| item == &Comparable::from(value))
-                    ),
-                    Comparable::String(left) => match Comparable::from(value) {
-                        Comparable::String(right) => Ok(!left.contains(right)),
-                        _ => Ok(true),
-                    },
-                    _ => Ok(true),
-                },
-                FieldOp::StartsWith => match (Comparable::from(field_value), Comparable::from(value)) {
-                    (Comparable::String(left), Comparable::String(right)) => Ok(left.starts_with(right)),
-                    _ => Ok(false),
-                },
-                FieldOp::EndsWith => match (Comparable::from(field_value), Comparable::from(value)) {
-                    (Comparable::String(left), Comparable::String(right)) => Ok(left.ends_with(right)),
-                    _ => Ok(false),
-                },
-                FieldOp::AnyOf => match (Comparable::from(field_value), Comparable::from(value)) {
-                    (Comparable::Array(array), Comparable::Array(values)) => {
-                        for val in values {
-                            if array.iter().any(|item| item == &val) {
-                                return Ok(true);
-                            }
-                        }
-                        Ok(false)
-                    },
-                    (Comparable::Array(array), single_value) => {
-                        for item in array {
-                            if item == single_value {
-                                return Ok(true);
-                            }
-                        }
-                        Ok(false)
-                    },
-                    (single_value, Comparable::Array(values)) => {
-                        for val in values {
-                            if val == single_value {
-                                return Ok(true);
-                            }
-                        }
-                        Ok(false)
-                    },
-                    _ => Ok(false),
-                },
-                FieldOp::NoneOf => match (Comparable::from(field_value), Comparable::from(value)) {
-                    (Comparable::Array(array), Comparable::Array(values)) => {
-                        for val in values {
-                            if array.iter().any(|item| item == &val) {
-                                return Ok(false);
-                            }
-                        }
-                        Ok(true)
-                    },
-                    (Comparable::Array(array), single_value) => {
-                        for item in array {
-                            if item == single_value {
-                                return Ok(false);
-                            }
-                        }
-                        Ok(true)
-                    },
-                    (single_value, Comparable::Array(values)) => {
-                        for val in values {
-                            if val == single_value {
-                                return Ok(false);
-                            }
-                        }
-                        Ok(true)
-                    },
-                    _ => Ok(true),
-                },
+                }
+                Ok(true)
+            },
+            _ => {
+                for candidate in candidates {
+                    if self.compare(candidate, op, value)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
             },
-            None => Ok(false),
+        }
+    }
+}
+
+
+/// The regular expressions of a filter, compiled once to evaluate it against any number of
+/// documents.
+///
+/// Patterns are kept apart by whether they ignore case, since the same pattern may appear
+/// both inside and outside [`Expr::CaseInsensitive`].
+#[derive(Debug, Default)]
+pub struct Regexes {
+    sensitive: HashMap<String, Regex>,
+    insensitive: HashMap<String, Regex>,
+}
+
+impl Regexes {
+    /// Compiles the patterns of every [`FieldOp::Regex`] comparison in an expression.
+    ///
+    /// # Errors
+    ///
+    /// Returns a backend error if a pattern is invalid or not a string, whether or not any
+    /// document would reach the comparison.
+    pub fn compile(expr: &Expr) -> DocumentStoreResult<Self> {
+        let mut compiler = RegexCompiler { regexes: Regexes::default(), case_insensitive: false };
+        compiler.visit_expr(expr)?;
+
+        Ok(compiler.regexes)
+    }
+
+    fn get(&self, pattern: &str, case_insensitive: bool) -> Option<&Regex> {
+        match case_insensitive {
+            true => self.insensitive.get(pattern),
+            false => self.sensitive.get(pattern),
+        }
+    }
+}
+
+/// Collects the regular expressions of an expression into [`Regexes`].
+struct RegexCompiler {
+    regexes: Regexes,
+    case_insensitive: bool,
+}
+
+impl QueryVisitor for RegexCompiler {
+    type Output = ();
+    type Error = DocumentStoreError;
+
+    fn visit_and(&mut self, exprs: &[Expr]) -> Result<Self::Output, Self::Error> {
+        exprs.iter().try_for_each(|expr| self.visit_expr(expr))
+    }
+
+    fn visit_or(&mut self, exprs: &[Expr]) -> Result<Self::Output, Self::Error> {
+        exprs.iter().try_for_each(|expr| self.visit_expr(expr))
+    }
+
+    fn visit_not(&mut self, expr: &Expr) -> Result<Self::Output, Self::Error> {
+        self.visit_expr(expr)
+    }
+
+    fn visit_case_insensitive(&mut self, expr: &Expr) -> Result<Self::Output, Self::Error> {
+        let previous = std::mem::replace(&mut self.case_insensitive, true);
+        let result = self.visit_expr(expr);
+        self.case_insensitive = previous;
+
+        result
+    }
+
+    fn visit_elem_match(&mut self, _field: &str, expr: &Expr) -> Result<Self::Output, Self::Error> {
+        self.visit_expr(expr)
+    }
+
+    fn visit_custom(&mut self, _name: &str, _payload: &Bson) -> Result<Self::Output, Self::Error> {
+        Ok(())
+    }
+
+    fn visit_exists(&mut self, _field: &str, _should_exist: bool) -> Result<Self::Output, Self::Error> {
+        Ok(())
+    }
+
+    fn visit_field(&mut self, _field: &str, op: &FieldOp, value: &Bson) -> Result<Self::Output, Self::Error> {
+        if !matches!(op, FieldOp::Regex) {
+            return Ok(());
+        }
+
+        let Bson::String(pattern) = value else {
+            return Err(regex_pattern_error());
+        };
+        let compiled = match self.case_insensitive {
+            true => &mut self.regexes.insensitive,
+            false => &mut self.regexes.sensitive,
+        };
+
+        if !compiled.contains_key(pattern) {
+            compiled.insert(pattern.clone(), compile_regex(pattern, self.case_insensitive)?);
+        }
+
+        Ok(())
+    }
+}
+
+fn compile_regex(pattern: &str, case_insensitive: bool) -> DocumentStoreResult<Regex> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .build()
+        .map_err(|e| DocumentStoreError::Backend(format!("Invalid regex pattern: {}", e)))
+}
+
+fn regex_pattern_error() -> DocumentStoreError {
+    DocumentStoreError::Backend("Regex operator requires a string pattern".to_string())
+}
+
+
+/// Returns whether a value equals any candidate of an `AnyOf` comparison, or is an array
+/// holding one of them.
+fn is_any_of(field_value: &Bson, value: &Bson) -> DocumentStoreResult<bool> {
+    let field_value = Comparable::from(field_value);
+    let candidates = FieldOp::set_values(value)?
+        .iter()
+        .map(Comparable::from)
+        .collect::<Vec<_>>();
+
+    // An array matches as a whole or through any of its elements
+    let matches = |value: &Comparable| candidates.iter().any(|candidate| candidate == value);
+
+    Ok(match &field_value {
+        Comparable::Array(elements) => matches(&field_value) || elements.iter().any(matches),
+        value => matches(value),
+    })
+}
+
+
+/// Resolves a dotted field path against a document.
+///
+/// Each segment selects a field of an embedded document. On arrays, a numeric segment
+/// selects the element at that index, while any other segment is applied to every
+/// embedded document in the array, so a single path can resolve to several values.
+///
+/// # Returns
+///
+/// All values found at the path, empty if the path doesn't exist.
+pub(crate) fn resolve_path<'a>(document: &'a Bson, path: &str) -> Vec<&'a Bson> {
+    let mut current = vec![document];
+
+    for segment in path.split('.') {
+        current = current
+            .into_iter()
+            .flat_map(|value| match value {
+                Bson::Document(doc) => doc.get(segment).into_iter().collect::<Vec<_>>(),
+                Bson::Array(array) => match segment.parse::<usize>() {
+                    Ok(index) => array.get(index).into_iter().collect(),
+                    Err(_) => array
+                        .iter()
+                        .filter_map(|item| item.as_document())
+                        .filter_map(|doc| doc.get(segment))
+                        .collect(),
+                },
+                _ => Vec::new(),
+            })
+            .collect();
+    }
+
+    current
+}
+
+/// Renders a value into a string that is equal for values MongoDB treats as equal when
+/// grouping or deduplicating, such as numbers of different types but equal value.
+///
+/// Integers, and doubles holding one a long can hold, render as the exact integer, so longs
+/// too large for a double stay apart.
+pub(crate) fn value_key(value: &Bson) -> String {
+    // Doubles from -2^63 up to, but excluding, 2^63 convert to a long exactly
+    const LONG_RANGE: f64 = 9_223_372_036_854_775_808.0;
+
+    match value {
+        // Numbers of different types but equal value are equal
+        Bson::Int32(number) => format!("n:{}", number),
+        Bson::Int64(number) => format!("n:{}", number),
+        Bson::Double(number) if number.fract() == 0.0 && (-LONG_RANGE..LONG_RANGE).contains(number) => {
+            format!("n:{}", *number as i64)
+        },
+        Bson::Double(number) => format!("n:{}", number),
+        Bson::Null => "null".to_string(),
+        Bson::Array(values) => format!(
+            "[{}]",
+            values
+                .iter()
+                .map(value_key)
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        Bson::Document(document) => format!(
+            "{{{}}}",
+            document
+                .iter()
+                .map(|(key, value)| format!("{:?}:{}", key, value_key(value)))
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Sorts documents by the given keys, comparing each key in turn until one breaks the tie.
+///
+/// The sort is stable. Missing fields sort as null, and a comparator returned by
+/// `comparator` for a field takes precedence over the default ordering of values.
+pub fn sort_documents<'c>(
+    documents: &mut [Bson],
+    sort: &[Sort],
+    comparator: impl Fn(&str) -> Option<&'c SortComparator>,
+) {
+    documents.sort_by(|a, b| compare_documents(a, b, sort, &comparator));
+}
+
+/// Compares two documents by the given sort keys, like [`sort_documents`].
+pub fn compare_documents<'c>(
+    a: &Bson,
+    b: &Bson,
+    sort: &[Sort],
+    comparator: impl Fn(&str) -> Option<&'c SortComparator>,
+) -> Ordering {
+    sort
+        .iter()
+        .map(|sort| {
+            let left = resolve_path(a, &sort.field)
+                .first()
+                .copied()
+                .unwrap_or(&Bson::Null);
+            let right = resolve_path(b, &sort.field)
+                .first()
+                .copied()
+                .unwrap_or(&Bson::Null);
+
+            // A registered comparator takes precedence over the Comparable ordering
+            let ordering = comparator(&sort.field)
+                .and_then(|comparator| comparator.compare(left, right))
+                .unwrap_or_else(|| {
+                    Comparable::from(left)
+                        .partial_cmp(&Comparable::from(right))
+                        .unwrap_or(Ordering::Equal)
+                });
+
+            match sort.direction {
+                SortDirection::Asc => ordering,
+                SortDirection::Desc => ordering.reverse(),
+            }
+        })
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Collects the distinct values of a field across documents, in the order first seen.
+///
+/// Arrays are unwound like MongoDB's distinct, so each of their items counts as a value.
+pub fn distinct_values<'a>(documents: impl IntoIterator<Item = &'a Bson>, field: &str) -> Vec<Bson> {
+    let mut seen = HashSet::new();
+    let mut values = Vec::new();
+
+    for document in documents {
+        for value in resolve_path(document, field) {
+            let items = match value {
+                Bson::Array(items) => items.iter().collect::<Vec<_>>(),
+                other => vec![other],
+            };
+
+            for item in items {
+                if seen.insert(value_key(item)) {
+                    values.push(item.clone());
+                }
+            }
+        }
+    }
+
+    values
+}
+
+/// Builds a copy of a document containing only the given fields.
+///
+/// Dotted field names select fields of embedded documents, keeping the
+/// surrounding structure. Fields missing from the document are skipped.
+pub fn project_document(document: &Bson, fields: &[String]) -> Bson {
+    let source = match document.as_document() {
+        Some(doc) => doc,
+        None => return document.clone(),
+    };
+    let mut projected = bson::Document::new();
+
+    for field in fields {
+        let mut segments = field.split('.').peekable();
+        let mut from = source;
+        let mut to = &mut projected;
+
+        while let Some(segment) = segments.next() {
+            let Some(value) = from.get(segment) else {
+                break;
+            };
+
+            if segments.peek().is_none() {
+                to.insert(segment, value.clone());
+                break;
+            }
+
+            let Bson::Document(inner) = value else {
+                break;
+            };
+
+            if !matches!(to.get(segment), Some(Bson::Document(_))) {
+                to.insert(segment, bson::Document::new());
+            }
+
+            from = inner;
+            to = to.get_document_mut(segment).expect("embedded document was just inserted");
+        }
+    }
+
+    Bson::Document(projected)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use bson::{Bson, doc};
+
+    use super::{distinct_values, value_key};
+    #[cfg(feature = "parallel")]
+    use super::{DocumentEvaluator, PARALLEL_CHUNK_SIZE, PARALLEL_THRESHOLD, Regexes};
+    #[cfg(feature = "parallel")]
+    use doclayer_core::{error::DocumentStoreError, query::{CustomOperators, Filter}};
+
+    #[test]
+    fn numbers_of_different_types_but_equal_value_have_equal_keys() {
+        assert_eq!(value_key(&Bson::Int32(1)), value_key(&Bson::Int64(1)));
+        assert_eq!(value_key(&Bson::Int64(1)), value_key(&Bson::Double(1.0)));
+        assert_eq!(value_key(&Bson::Int32(-3)), value_key(&Bson::Double(-3.0)));
+        assert_ne!(value_key(&Bson::Int32(1)), value_key(&Bson::Double(1.5)));
+        assert_eq!(value_key(&Bson::Int32(0)), value_key(&Bson::Double(-0.0)));
+    }
+
+    #[test]
+    fn longs_too_large_for_a_double_have_different_keys() {
+        let large = 1_i64 << 53;
+
+        assert_ne!(value_key(&Bson::Int64(large)), value_key(&Bson::Int64(large + 1)));
+        assert_ne!(value_key(&Bson::Int64(i64::MAX)), value_key(&Bson::Int64(i64::MAX - 1)));
+        assert_eq!(value_key(&Bson::Int64(large)), value_key(&Bson::Double(large as f64)));
+        assert_ne!(value_key(&Bson::Int64(i64::MAX)), value_key(&Bson::Double(i64::MAX as f64)));
+        assert_eq!(value_key(&Bson::Int64(i64::MIN)), value_key(&Bson::Double(i64::MIN as f64)));
+    }
+
+    #[test]
+    fn scalars_of_different_types_have_different_keys() {
+        let keys = [
+            Bson::Int32(1),
+            Bson::String("1".to_string()),
+            Bson::Boolean(true),
+            Bson::Null,
+            Bson::String("null".to_string()),
+        ]
+        .iter()
+        .map(value_key)
+        .collect::<HashSet<_>>();
+
+        assert_eq!(keys.len(), 5);
+    }
+
+    #[test]
+    fn arrays_compare_item_by_item() {
+        let ints = Bson::Array(vec![Bson::Int32(1), Bson::Int64(2)]);
+        let doubles = Bson::Array(vec![Bson::Double(1.0), Bson::Double(2.0)]);
+        let reversed = Bson::Array(vec![Bson::Int32(2), Bson::Int32(1)]);
+
+        assert_eq!(value_key(&ints), value_key(&doubles));
+        assert_ne!(value_key(&ints), value_key(&reversed));
+        assert_ne!(value_key(&Bson::Array(vec![Bson::Int32(1)])), value_key(&Bson::Int32(1)));
+        assert_ne!(value_key(&Bson::Array(vec![])), value_key(&Bson::Null));
+    }
+
+    #[test]
+    fn distinct_values_unwind_arrays_and_merge_equal_numbers() {
+        let documents = [
+            Bson::Document(doc! { "value": 1 }),
+            Bson::Document(doc! { "value": 1.0 }),
+            Bson::Document(doc! { "value": [1_i64, 2, "2"] }),
+            Bson::Document(doc! { "value": [[1, 2]] }),
+            Bson::Document(doc! { "value": [[1.0, 2.0]] }),
+            Bson::Document(doc! { "value": Bson::Null }),
+            Bson::Document(doc! {}),
+        ];
+
+        assert_eq!(distinct_values(&documents, "value"), vec![
+            Bson::Int32(1),
+            Bson::Int32(2),
+            Bson::String("2".to_string()),
+            Bson::Array(vec![Bson::Int32(1), Bson::Int32(2)]),
+            Bson::Null,
+        ]);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_filtering_keeps_the_order_of_matches() {
+        let documents = (0..PARALLEL_THRESHOLD as i64 * 2)
+            .map(|index| Bson::Document(doc! { "index": index }))
+            .collect::<Vec<_>>();
+        let expr = Filter::gt("index", 10_i64);
+
+        let matched = DocumentEvaluator::filter_documents(&documents, &expr, &CustomOperators::default(), &Regexes::default())
+            .unwrap();
+
+        assert_eq!(matched, documents[11..]);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_filtering_returns_the_first_error_in_order() {
+        // The first error ends the first chunk, and another starts the second
+        let mut documents = (0..PARALLEL_THRESHOLD as i64)
+            .map(|index| Bson::Document(doc! { "index": index }))
+            .collect::<Vec<_>>();
+        documents[PARALLEL_CHUNK_SIZE - 1] = Bson::String("first".to_string());
+        documents[PARALLEL_CHUNK_SIZE] = Bson::Int32(2);
+        let expr = Filter::gt("index", 10_i64);
+
+        for _ in 0..10 {
+            let result = DocumentEvaluator::filter_documents(&documents, &expr, &CustomOperators::default(), &Regexes::default());
+
+            assert!(
+                matches!(&result, Err(DocumentStoreError::InvalidDocument(message)) if message.contains("String")),
+                "{result:?}"
+            );
         }
     }
 }
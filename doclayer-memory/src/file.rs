@@ -0,0 +1,650 @@
+//! Filesystem storage backend persisting documents as JSON files.
+//!
+//! [`FileStore`] keeps every collection in a directory holding one pretty-printed JSON file
+//! per document, named after the document ID:
+//!
+//! ```text
+//! data/
+//! ├── .revision
+//! └── users/
+//!     ├── 0b6a5f0e-3f1c-4c1e-9a57-1f4d1f0b9c2a.json
+//!     └── 5d9e8c4b-7a2f-4b8e-8f0d-6c3e2a1b4d5f.json
+//! ```
+//!
+//! Documents are written as relaxed [extended JSON](https://www.mongodb.com/docs/manual/reference/mongodb-extended-json/),
+//! with UUIDs rendered as `{ "$uuid": "..." }`, so files stay readable and diff cleanly in git.
+//! The whole store is loaded into memory when it's built and queries run against that copy,
+//! using the same evaluation as [`InMemoryStore`]. Every write is applied in memory and then
+//! persisted, replacing each changed file atomically.
+//!
+//! The store is meant for small, human-edited data sets such as configuration. Files are
+//! written synchronously, and changes made to the directory while the store is open are not
+//! picked up until it is built again.
+
+use std::{collections::HashMap, fs, future::Future, io, path::{Component, Path, PathBuf}, sync::Arc, time::Duration};
+use async_trait::async_trait;
+use mea::{mutex::Mutex, rwlock::RwLock};
+use bson::{Bson, Uuid, spec::BinarySubtype};
+use serde_json::{Value, json};
+
+use doclayer_core::{
+    aggregate::Aggregate,
+    encoding::Encoding,
+    expiry::ExpiringCollections,
+    query::{CustomOperator, CustomOperators, Expr, Query, Sort, SortComparator, SortComparators},
+    update::Update,
+    error::{DocumentStoreError, DocumentStoreResult},
+    backend::{DocumentStream, InsertPolicy, MissingDocumentPolicy, QueryPlan, ReturnDocument, StoreBackend, StoreBackendBuilder, WriteReport},
+};
+
+use crate::{
+    store::{CollectionMap, InMemoryStore, StoreMap},
+    index::Indexes,
+};
+
+/// The file holding the current revision ID, in the root directory.
+const REVISION_FILE: &str = ".revision";
+
+/// The extension of document files.
+const DOCUMENT_EXTENSION: &str = "json";
+
+
+/// Storage backend persisting each collection as a directory of JSON files.
+///
+/// See the [module documentation](self) for the layout of the directory.
+///
+/// # Thread Safety
+///
+/// `FileStore` is cloneable, and clones share the same data. Writes are serialized so the
+/// files always reflect the order in which they were applied. Only one store should have a
+/// directory open at a time.
+///
+/// # Example
+///
+/// ```ignore
+/// use doclayer::{prelude::*, file::FileStore};
+///
+/// let store = DocumentStore::new(FileStore::builder("data").build().await?);
+///
+/// store
+///     .typed_collection::<User>()
+///     .insert(vec![user])
+///     .await?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct FileStore {
+    /// The directory holding the collections
+    root: PathBuf,
+    /// The loaded documents, which every read is served from
+    memory: InMemoryStore,
+    /// Serializes writes, so the files are persisted in the order the writes were applied
+    writes: Arc<Mutex<()>>,
+}
+
+impl FileStore {
+    /// Creates a builder for a store in the given directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The directory holding the collections, created if it doesn't exist
+    pub fn builder(path: impl Into<PathBuf>) -> FileStoreBuilder {
+        FileStoreBuilder::new(path)
+    }
+
+    /// Returns the directory holding the collections.
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    /// Deletes the documents whose expiry has passed, in every collection made to expire them
+    /// with [`StoreBackend::expire_documents`], and removes their files.
+    ///
+    /// # Returns
+    ///
+    /// The number of documents deleted.
+    pub async fn sweep_expired(&self) -> DocumentStoreResult<usize> {
+        self.memory.expiring_collections().sweep(self).await
+    }
+
+    /// Sweeps expired documents repeatedly, waiting `interval` between sweeps.
+    ///
+    /// Meant to be spawned as a background task; this never returns. See
+    /// [`ExpiringCollections::run`] for the arguments.
+    pub async fn run_sweeper<F, Fut, R>(&self, interval: Duration, sleep: F, on_sweep: R)
+    where
+        F: Fn(Duration) -> Fut,
+        Fut: Future<Output = ()>,
+        R: FnMut(DocumentStoreResult<usize>),
+    {
+        self.memory.expiring_collections().run(self, interval, sleep, on_sweep).await
+    }
+
+    /// Writes the given documents of a collection to disk as they are in memory.
+    ///
+    /// Documents that are no longer in memory have their file removed.
+    async fn persist(&self, collection: &str, keys: impl IntoIterator<Item = String>) -> DocumentStoreResult<()> {
+        let store = self.memory.store.read().await;
+        let directory = self.collection_path(collection)?;
+        let empty = CollectionMap::new();
+        let collection_map = store.get(collection).unwrap_or(&empty);
+
+        create_directory(&directory)?;
+
+        for key in keys {
+            let path = directory.join(format!("{}.{}", key, DOCUMENT_EXTENSION));
+
+            match collection_map.get(&key) {
+                Some(doc) => write_document(&path, doc)?,
+                None => remove_file(&path)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the directory of a collection.
+    ///
+    /// # Errors
+    ///
+    /// Returns a backend error if the name can't be used as a directory name, such as a name
+    /// containing a path separator or starting with a dot.
+    fn collection_path(&self, collection: &str) -> DocumentStoreResult<PathBuf> {
+        let mut components = Path::new(collection).components();
+
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) if !collection.starts_with('.') => Ok(self.root.join(collection)),
+            _ => Err(DocumentStoreError::Backend(format!(
+                "Collection name {:?} can't be stored as a directory",
+                collection
+            ))),
+        }
+    }
+}
+
+
+#[async_trait]
+impl StoreBackend for FileStore {
+    async fn insert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        let _guard = self.writes.lock().await;
+        let keys = documents.iter().map(|(id, _)| id.to_string()).collect::<Vec<_>>();
+
+        self.collection_path(collection)?;
+
+        let report = self.memory.insert_documents(documents, collection, policy).await?;
+        self.persist(collection, keys).await?;
+
+        Ok(report)
+    }
+
+    async fn update_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        let _guard = self.writes.lock().await;
+        let keys = documents.iter().map(|(id, _)| id.to_string()).collect::<Vec<_>>();
+
+        self.collection_path(collection)?;
+        let report = self.memory.update_documents(documents, collection, policy).await?;
+
+        // Nothing was written when a missing collection was skipped
+        if self.memory.collection_exists(collection).await? {
+            self.persist(collection, keys).await?;
+        }
+
+        Ok(report)
+    }
+
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        let _guard = self.writes.lock().await;
+        let keys = documents.iter().map(|(id, ..)| id.to_string()).collect::<Vec<_>>();
+
+        self.collection_path(collection)?;
+        let report = self.memory.update_documents_if_version(documents, version_field, collection).await?;
+        self.persist(collection, keys).await?;
+
+        Ok(report)
+    }
+
+    async fn update_documents_if_match(&self, documents: Vec<(Uuid, Bson, String)>, collection: &str) -> DocumentStoreResult<WriteReport> {
+        let _guard = self.writes.lock().await;
+        let keys = documents.iter().map(|(id, ..)| id.to_string()).collect::<Vec<_>>();
+
+        self.collection_path(collection)?;
+        let report = self.memory.update_documents_if_match(documents, collection).await?;
+        self.persist(collection, keys).await?;
+
+        Ok(report)
+    }
+
+    async fn upsert_documents(&self, documents: Vec<(Uuid, Bson)>, collection: &str) -> DocumentStoreResult<()> {
+        let _guard = self.writes.lock().await;
+        let keys = documents.iter().map(|(id, _)| id.to_string()).collect::<Vec<_>>();
+
+        self.collection_path(collection)?;
+        self.memory.upsert_documents(documents, collection).await?;
+        self.persist(collection, keys).await
+    }
+
+    async fn delete_documents(&self, ids: Vec<Uuid>, collection: &str) -> DocumentStoreResult<usize> {
+        let _guard = self.writes.lock().await;
+
+        if !self.memory.collection_exists(collection).await? {
+            return Ok(0);
+        }
+
+        let keys = ids.iter().map(Uuid::to_string).collect::<Vec<_>>();
+        let deleted = self.memory.delete_documents(ids, collection).await?;
+
+        self.persist(collection, keys).await?;
+
+        Ok(deleted)
+    }
+
+    async fn delete_documents_if_match(&self, documents: Vec<(Uuid, String)>, collection: &str) -> DocumentStoreResult<usize> {
+        let _guard = self.writes.lock().await;
+        let keys = documents.iter().map(|(id, _)| id.to_string()).collect::<Vec<_>>();
+
+        self.collection_path(collection)?;
+        let deleted = self.memory.delete_documents_if_match(documents, collection).await?;
+        self.persist(collection, keys).await?;
+
+        Ok(deleted)
+    }
+
+    async fn update_by_query(&self, filter: Expr, update: Update, collection: &str) -> DocumentStoreResult<WriteReport> {
+        let _guard = self.writes.lock().await;
+
+        // Writes are serialized, so the matched documents are exactly the ones updated
+        let keys = self.memory.matching_keys(&filter, collection).await?;
+        let report = self.memory.update_by_query(filter, update, collection).await?;
+
+        if !keys.is_empty() {
+            self.persist(collection, keys).await?;
+        }
+
+        Ok(report)
+    }
+
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize> {
+        let _guard = self.writes.lock().await;
+
+        let keys = self.memory.matching_keys(&filter, collection).await?;
+        let deleted = self.memory.delete_by_query(filter, collection).await?;
+
+        if !keys.is_empty() {
+            self.persist(collection, keys).await?;
+        }
+
+        Ok(deleted)
+    }
+
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        let _guard = self.writes.lock().await;
+
+        // Writes are serialized, so the first match is the document updated
+        let key = self.memory.first_matching_key(&filter, &sort, collection).await?;
+        let document = self.memory.find_one_and_update(filter, sort, update, returned, collection).await?;
+
+        if let Some(key) = key {
+            self.persist(collection, vec![key]).await?;
+        }
+
+        Ok(document)
+    }
+
+    async fn find_one_and_delete(&self, filter: Expr, sort: Vec<Sort>, collection: &str) -> DocumentStoreResult<Option<Bson>> {
+        let _guard = self.writes.lock().await;
+
+        let key = self.memory.first_matching_key(&filter, &sort, collection).await?;
+        let document = self.memory.find_one_and_delete(filter, sort, collection).await?;
+
+        if let Some(key) = key {
+            self.persist(collection, vec![key]).await?;
+        }
+
+        Ok(document)
+    }
+
+    async fn get_documents(&self, ids: Vec<Uuid>, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+        self.memory.get_documents(ids, collection).await
+    }
+
+    async fn query_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+        self.memory.query_documents(query, collection).await
+    }
+
+    async fn query_stream(&self, query: Query, collection: &str) -> DocumentStoreResult<DocumentStream> {
+        self.memory.query_stream(query, collection).await
+    }
+
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize> {
+        self.memory.count_documents(query, collection).await
+    }
+
+    async fn distinct(&self, field: &str, filter: Option<Expr>, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+        self.memory.distinct(field, filter, collection).await
+    }
+
+    async fn aggregate(&self, aggregate: Aggregate, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+        self.memory.aggregate(aggregate, collection).await
+    }
+
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan> {
+        self.memory.explain(query, collection).await
+    }
+
+    async fn current_revision_id(&self) -> DocumentStoreResult<Option<String>> {
+        self.memory.current_revision_id().await
+    }
+
+    async fn set_revision_id(&self, revision_id: &str) -> DocumentStoreResult<()> {
+        let _guard = self.writes.lock().await;
+
+        write_file(&self.root.join(REVISION_FILE), format!("{}\n", revision_id).as_bytes())?;
+        self.memory.set_revision_id(revision_id).await
+    }
+
+    async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        let _guard = self.writes.lock().await;
+
+        if self.memory.collection_exists(name).await? {
+            return Err(DocumentStoreError::CollectionAlreadyExists(name.to_string()));
+        }
+
+        create_directory(&self.collection_path(name)?)?;
+        self.memory.create_collection(name).await
+    }
+
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        self.memory.collection_exists(name).await
+    }
+
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        let _guard = self.writes.lock().await;
+
+        create_directory(&self.collection_path(name)?)?;
+        self.memory.ensure_collection(name).await
+    }
+
+    async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        let _guard = self.writes.lock().await;
+
+        self.memory.drop_collection(name).await?;
+
+        let path = self.collection_path(name)?;
+        match fs::remove_dir_all(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(io_error("remove", &path, e)),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list_collections(&self) -> DocumentStoreResult<Vec<String>> {
+        self.memory.list_collections().await
+    }
+
+    async fn add_field(&self, collection: &str, field: &str, default: Bson) -> DocumentStoreResult<()> {
+        let _guard = self.writes.lock().await;
+
+        self.memory.add_field(collection, field, default).await?;
+        self.persist(collection, self.memory.keys(collection).await).await
+    }
+
+    async fn drop_field(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        let _guard = self.writes.lock().await;
+
+        self.memory.drop_field(collection, field).await?;
+        self.persist(collection, self.memory.keys(collection).await).await
+    }
+
+    async fn rename_field(&self, collection: &str, field: &str, new: &str) -> DocumentStoreResult<()> {
+        let _guard = self.writes.lock().await;
+
+        self.memory.rename_field(collection, field, new).await?;
+        self.persist(collection, self.memory.keys(collection).await).await
+    }
+
+    async fn add_index(&self, collection: &str, field: &str, unique: bool) -> DocumentStoreResult<()> {
+        self.memory.add_index(collection, field, unique).await
+    }
+
+    async fn drop_index(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.memory.drop_index(collection, field).await
+    }
+
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.memory.expire_documents(collection, field).await
+    }
+
+    fn encoding(&self) -> &Encoding {
+        self.memory.encoding()
+    }
+
+    async fn close(&self) -> DocumentStoreResult<()> {
+        // Every write is persisted before releasing the lock, so waiting for it is enough
+        let _guard = self.writes.lock().await;
+        Ok(())
+    }
+}
+
+
+/// Builder for constructing [`FileStore`] instances.
+///
+/// # Example
+///
+/// ```ignore
+/// use doclayer::file::FileStore;
+/// use doclayer::backend::StoreBackendBuilder;
+///
+/// let store = FileStore::builder("data").build().await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct FileStoreBuilder {
+    path: PathBuf,
+    comparators: SortComparators,
+    operators: CustomOperators,
+    encoding: Encoding,
+}
+
+impl FileStoreBuilder {
+    /// Creates a builder for a store in the given directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The directory holding the collections, created if it doesn't exist
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), comparators: SortComparators::new(), operators: CustomOperators::new(), encoding: Encoding::new() }
+    }
+
+    /// Orders a field with a custom comparator when queries sort by it.
+    ///
+    /// See [`InMemoryStoreBuilder::with_comparator`](crate::InMemoryStoreBuilder::with_comparator).
+    pub fn with_comparator(mut self, collection: impl Into<String>, field: impl Into<String>, comparator: SortComparator) -> Self {
+        self.comparators.insert(collection, field, comparator);
+        self
+    }
+
+    /// Registers the handler of a custom filter operator.
+    ///
+    /// See [`InMemoryStoreBuilder::with_operator`](crate::InMemoryStoreBuilder::with_operator).
+    pub fn with_operator(mut self, name: impl Into<String>, operator: CustomOperator) -> Self {
+        self.operators.insert(name, operator);
+        self
+    }
+
+    /// Sets the representation of UUIDs, enums and datetimes in stored documents.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+}
+
+#[async_trait]
+impl StoreBackendBuilder for FileStoreBuilder {
+    type Backend = FileStore;
+
+    /// Loads every collection in the directory and returns the store.
+    ///
+    /// Each subdirectory not starting with a dot is a collection, and each `.json` file in it
+    /// a document named after its ID. Other files are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Initialization`] if the directory can't be read, or if a
+    /// document file isn't named after a UUID or doesn't hold a JSON object.
+    async fn build(self) -> DocumentStoreResult<Self::Backend> {
+        let root = self.path;
+        let initialization = |e: DocumentStoreError| DocumentStoreError::Initialization(e.to_string());
+
+        create_directory(&root).map_err(initialization)?;
+
+        let mut store = StoreMap::new();
+
+        for entry in read_directory(&root).map_err(initialization)? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+
+            if name.starts_with('.') || !entry.path().is_dir() {
+                continue;
+            }
+
+            store.insert(name, load_collection(&entry.path())?);
+        }
+
+        let revision = match fs::read_to_string(root.join(REVISION_FILE)) {
+            Ok(revision) => Some(revision.trim().to_string()).filter(|revision| !revision.is_empty()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(initialization(io_error("read", &root.join(REVISION_FILE), e))),
+        };
+
+        Ok(FileStore {
+            root,
+            memory: InMemoryStore {
+                store: Arc::new(RwLock::new(store)),
+                current_revision: Arc::new(RwLock::new(revision)),
+                comparators: Arc::new(self.comparators),
+                operators: Arc::new(self.operators),
+                encoding: Arc::new(self.encoding),
+                expiring: ExpiringCollections::new(),
+                indexes: Indexes::default(),
+            },
+            writes: Arc::new(Mutex::new(())),
+        })
+    }
+}
+
+
+/// Loads the documents of a collection directory.
+fn load_collection(directory: &Path) -> DocumentStoreResult<CollectionMap> {
+    let mut collection_map = HashMap::new();
+
+    for entry in read_directory(directory).map_err(|e| DocumentStoreError::Initialization(e.to_string()))? {
+        let path = entry.path();
+
+        if path.extension().is_none_or(|extension| extension != DOCUMENT_EXTENSION) {
+            continue;
+        }
+
+        let key = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| Uuid::parse_str(stem).ok())
+            .ok_or_else(|| DocumentStoreError::Initialization(format!(
+                "Document file {} is not named after a UUID",
+                path.display()
+            )))?
+            .to_string();
+
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| DocumentStoreError::Initialization(io_error("read", &path, e).to_string()))?;
+        let document = serde_json::from_str::<Value>(&contents)
+            .map_err(DocumentStoreError::from)
+            .and_then(|value| Bson::try_from(value).map_err(DocumentStoreError::from))
+            .map_err(|e| DocumentStoreError::Initialization(format!("Invalid document file {}: {}", path.display(), e)))?;
+
+        if !matches!(document, Bson::Document(_)) {
+            return Err(DocumentStoreError::Initialization(format!(
+                "Document file {} doesn't hold a JSON object",
+                path.display()
+            )));
+        }
+
+        collection_map.insert(key, document);
+    }
+
+    Ok(collection_map)
+}
+
+/// Writes a document as pretty-printed extended JSON.
+fn write_document(path: &Path, document: &Bson) -> DocumentStoreResult<()> {
+    let mut contents = serde_json::to_string_pretty(&to_json(document))?;
+    contents.push('\n');
+
+    write_file(path, contents.as_bytes())
+}
+
+/// Converts a value into relaxed extended JSON, rendering UUIDs in their readable form.
+fn to_json(value: &Bson) -> Value {
+    match value {
+        Bson::Document(doc) => Value::Object(
+            doc
+                .iter()
+                .map(|(key, value)| (key.clone(), to_json(value)))
+                .collect()
+        ),
+        Bson::Array(values) => Value::Array(values.iter().map(to_json).collect()),
+        Bson::Binary(binary) if binary.subtype == BinarySubtype::Uuid => match binary.to_uuid() {
+            Ok(uuid) => json!({ "$uuid": uuid.to_string() }),
+            Err(_) => value.clone().into_relaxed_extjson(),
+        },
+        other => other.clone().into_relaxed_extjson(),
+    }
+}
+
+/// Replaces a file atomically, writing a temporary file and renaming it.
+fn write_file(path: &Path, contents: &[u8]) -> DocumentStoreResult<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    fs::write(&temporary, contents).map_err(|e| io_error("write", &temporary, e))?;
+    fs::rename(&temporary, path).map_err(|e| io_error("write", path, e))
+}
+
+fn remove_file(path: &Path) -> DocumentStoreResult<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(io_error("remove", path, e)),
+        _ => Ok(()),
+    }
+}
+
+fn create_directory(path: &Path) -> DocumentStoreResult<()> {
+    fs::create_dir_all(path).map_err(|e| io_error("create", path, e))
+}
+
+fn read_directory(path: &Path) -> DocumentStoreResult<Vec<fs::DirEntry>> {
+    fs::read_dir(path)
+        .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+        .map_err(|e| io_error("read", path, e))
+}
+
+fn io_error(action: &str, path: &Path, error: io::Error) -> DocumentStoreError {
+    DocumentStoreError::Backend(format!("Failed to {} {}: {}", action, path.display(), error))
+}
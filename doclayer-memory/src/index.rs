@@ -0,0 +1,354 @@
+//! Secondary indexes of the in-memory store.
+//!
+//! An index orders the documents of a collection by the scalar values of a field: booleans,
+//! numbers, datetimes and strings, compared like the evaluator compares them. Values of other
+//! types, arrays and missing fields aren't indexed, so an index only answers equality and
+//! range comparisons, which never match them. See [`planner`](crate::planner) for how
+//! queries use indexes.
+//!
+//! Indexes are kept up to date by every write to their collection, under the store's write
+//! lock, so a reader holding the store's read lock sees indexes matching the documents.
+//! Unique indexes also answer the [`unique`](crate::unique) checks of those writes.
+
+use std::{cmp::Ordering, collections::{BTreeMap, BTreeSet, HashMap, HashSet}, ops::Bound, sync::Arc};
+use mea::rwlock::RwLock;
+use bson::Bson;
+
+use doclayer_core::{error::DocumentStoreResult, query::Expr};
+
+use crate::{
+    evaluator::resolve_path,
+    planner::{self, IndexPlan},
+    store::CollectionMap,
+    unique,
+};
+
+
+/// A value an index orders documents by.
+///
+/// Values of different types never compare equal or ordered in filters, so each type has a
+/// range of keys of its own.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum IndexKey {
+    Bool(bool),
+    Number(IndexNumber),
+    DateTime(i64),
+    String(String),
+}
+
+impl IndexKey {
+    /// Returns the key of a value, or `None` if the value isn't indexed.
+    pub(crate) fn from_value(value: &Bson) -> Option<Self> {
+        match value {
+            Bson::Boolean(value) => Some(IndexKey::Bool(*value)),
+            Bson::Int32(value) => IndexNumber::new(*value as f64).map(IndexKey::Number),
+            Bson::Int64(value) => IndexNumber::new(*value as f64).map(IndexKey::Number),
+            Bson::Double(value) => IndexNumber::new(*value).map(IndexKey::Number),
+            Bson::DateTime(value) => Some(IndexKey::DateTime(value.timestamp_millis())),
+            Bson::String(value) => Some(IndexKey::String(value.clone())),
+            _ => None,
+        }
+    }
+
+    /// Returns the smallest key of the same type.
+    pub(crate) fn type_min(&self) -> Bound<IndexKey> {
+        Bound::Included(match self {
+            IndexKey::Bool(_) => IndexKey::Bool(false),
+            IndexKey::Number(_) => IndexKey::Number(IndexNumber(f64::NEG_INFINITY)),
+            IndexKey::DateTime(_) => IndexKey::DateTime(i64::MIN),
+            IndexKey::String(_) => IndexKey::String(String::new()),
+        })
+    }
+
+    /// Returns the largest key of the same type.
+    pub(crate) fn type_max(&self) -> Bound<IndexKey> {
+        match self {
+            IndexKey::Bool(_) => Bound::Included(IndexKey::Bool(true)),
+            IndexKey::Number(_) => Bound::Included(IndexKey::Number(IndexNumber(f64::INFINITY))),
+            IndexKey::DateTime(_) => Bound::Included(IndexKey::DateTime(i64::MAX)),
+            // Strings are the last type
+            IndexKey::String(_) => Bound::Unbounded,
+        }
+    }
+
+    /// Returns `true` if both keys are of the same type.
+    pub(crate) fn same_type(&self, other: &IndexKey) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+/// A number in an index, never NaN, with negative zero stored as zero so equal numbers have
+/// equal keys.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct IndexNumber(f64);
+
+impl IndexNumber {
+    fn new(value: f64) -> Option<Self> {
+        if value.is_nan() {
+            return None;
+        }
+
+        // Adding zero turns negative zero into zero
+        Some(IndexNumber(value + 0.0))
+    }
+}
+
+impl Eq for IndexNumber {}
+
+impl PartialOrd for IndexNumber {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for IndexNumber {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// A range of keys, as the bounds of a [`BTreeMap`] range.
+pub(crate) type KeyRange = (Bound<IndexKey>, Bound<IndexKey>);
+
+/// An index on a field of a collection.
+#[derive(Debug, Clone)]
+pub(crate) struct FieldIndex {
+    field: String,
+    unique: bool,
+    /// Whether a document ever held several keys, so two comparisons on the field may each
+    /// be met by a different key of the same document
+    multikey: bool,
+    /// The documents holding each key
+    entries: BTreeMap<IndexKey, BTreeSet<String>>,
+    /// The keys of each document, to unindex it when it changes
+    documents: HashMap<String, Vec<IndexKey>>,
+    /// The documents missing the field or holding a value that isn't indexed
+    unindexed: BTreeSet<String>,
+}
+
+impl FieldIndex {
+    fn build(field: &str, unique: bool, collection_map: Option<&CollectionMap>) -> Self {
+        let mut index = Self {
+            field: field.to_string(),
+            unique,
+            multikey: false,
+            entries: BTreeMap::new(),
+            documents: HashMap::new(),
+            unindexed: BTreeSet::new(),
+        };
+
+        for (key, document) in collection_map.into_iter().flatten() {
+            index.update(key, Some(document));
+        }
+
+        index
+    }
+
+    /// Reindexes a document, or unindexes it if it was deleted.
+    fn update(&mut self, key: &str, document: Option<&Bson>) {
+        for previous in self.documents.remove(key).unwrap_or_default() {
+            if let Some(keys) = self.entries.get_mut(&previous) {
+                keys.remove(key);
+
+                if keys.is_empty() {
+                    self.entries.remove(&previous);
+                }
+            }
+        }
+        self.unindexed.remove(key);
+
+        let Some(document) = document else {
+            return;
+        };
+
+        // A path through an array yields a value per element, each indexed once
+        let values = resolve_path(document, &self.field);
+        let mut unindexed = values.is_empty();
+        let values = values
+            .into_iter()
+            .filter_map(|value| {
+                let key = IndexKey::from_value(value);
+                unindexed |= key.is_none();
+                key
+            })
+            .collect::<BTreeSet<_>>();
+
+        if unindexed {
+            self.unindexed.insert(key.to_string());
+        }
+        if values.is_empty() {
+            return;
+        }
+        self.multikey |= values.len() > 1;
+
+        for value in &values {
+            self.entries
+                .entry(value.clone())
+                .or_default()
+                .insert(key.to_string());
+        }
+        self.documents.insert(key.to_string(), values.into_iter().collect());
+    }
+
+    /// Returns the documents that may hold a value: those holding its key, and those whose
+    /// values aren't all indexed.
+    pub(crate) fn holders(&self, value: &Bson) -> impl Iterator<Item = &String> {
+        IndexKey::from_value(value)
+            .and_then(|key| self.entries.get(&key))
+            .into_iter()
+            .flatten()
+            .chain(&self.unindexed)
+    }
+
+    /// Returns `true` if a document ever held several keys.
+    pub(crate) fn multikey(&self) -> bool {
+        self.multikey
+    }
+
+    /// Returns the number of entries in a range of keys, an upper bound of the documents
+    /// holding them.
+    pub(crate) fn count(&self, range: &KeyRange) -> usize {
+        if is_empty(range) {
+            return 0;
+        }
+
+        self.entries
+            .range((range.0.as_ref(), range.1.as_ref()))
+            .map(|(_, keys)| keys.len())
+            .sum()
+    }
+
+    /// Returns the documents holding a key in a range, each once.
+    pub(crate) fn lookup(&self, range: &KeyRange) -> Vec<String> {
+        if is_empty(range) {
+            return Vec::new();
+        }
+
+        let mut seen = HashSet::new();
+
+        self.entries
+            .range((range.0.as_ref(), range.1.as_ref()))
+            .flat_map(|(_, keys)| keys)
+            .filter(|key| seen.insert(*key))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Returns `true` if no key lies in a range, which [`BTreeMap::range`] panics on when its
+/// bounds are reversed.
+fn is_empty((low, high): &KeyRange) -> bool {
+    match (low, high) {
+        (Bound::Included(low), Bound::Included(high)) => low > high,
+        (Bound::Included(low) | Bound::Excluded(low), Bound::Included(high) | Bound::Excluded(high)) => low >= high,
+        _ => false,
+    }
+}
+
+/// The indexes of a store, by collection and field.
+///
+/// Clones share the same indexes.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Indexes {
+    collections: Arc<RwLock<HashMap<String, HashMap<String, FieldIndex>>>>,
+}
+
+impl Indexes {
+    /// Returns an independent copy of the indexes, such as for a transaction's copy of the
+    /// store.
+    pub(crate) async fn snapshot(&self) -> Self {
+        Self {
+            collections: Arc::new(RwLock::new(self.collections.read().await.clone())),
+        }
+    }
+
+    /// Indexes a field of a collection, replacing any index on it.
+    pub(crate) async fn add(&self, collection: &str, field: &str, unique: bool, collection_map: Option<&CollectionMap>) {
+        self.collections
+            .write()
+            .await
+            .entry(collection.to_string())
+            .or_default()
+            .insert(field.to_string(), FieldIndex::build(field, unique, collection_map));
+    }
+
+    /// Removes the index on a field.
+    pub(crate) async fn remove(&self, collection: &str, field: &str) {
+        if let Some(indexes) = self.collections.write().await.get_mut(collection) {
+            indexes.remove(field);
+        }
+    }
+
+    /// Removes every index of a collection, such as when it's dropped.
+    pub(crate) async fn remove_collection(&self, collection: &str) {
+        self.collections
+            .write()
+            .await
+            .remove(collection);
+    }
+
+    /// Checks that writing documents to a collection leaves no two of its documents with the
+    /// same value of a field with a unique index, looking the values up in the indexes.
+    ///
+    /// `collection_map` holds the documents as the indexes have them. See
+    /// [`check_unique`](unique::check_unique) for the other arguments.
+    pub(crate) async fn check_unique<'a>(
+        &self,
+        collection_map: Option<&CollectionMap>,
+        written: impl IntoIterator<Item = (String, Option<&'a Bson>)>,
+        collection: &str,
+    ) -> DocumentStoreResult<()> {
+        let collections = self.collections.read().await;
+        let unique = collections
+            .get(collection)
+            .into_iter()
+            .flat_map(|indexes| indexes.values())
+            .filter(|index| index.unique)
+            .map(|index| (index.field.as_str(), Some(index)))
+            .collect::<Vec<_>>();
+
+        unique::check_unique(&unique, collection_map, written, collection)
+    }
+
+    /// Reindexes the documents written to a collection, as they now are in `collection_map`.
+    ///
+    /// Keys missing from the collection are unindexed.
+    pub(crate) async fn update(&self, collection: &str, collection_map: Option<&CollectionMap>, keys: impl IntoIterator<Item = String>) {
+        let mut collections = self.collections.write().await;
+        let Some(indexes) = collections.get_mut(collection) else {
+            return;
+        };
+
+        for key in keys {
+            let document = collection_map.and_then(|collection_map| collection_map.get(&key));
+
+            for index in indexes.values_mut() {
+                index.update(&key, document);
+            }
+        }
+    }
+
+    /// Rebuilds the indexes of a collection whose documents were all rewritten.
+    pub(crate) async fn rebuild(&self, collection: &str, collection_map: Option<&CollectionMap>) {
+        if let Some(indexes) = self.collections.write().await.get_mut(collection) {
+            // Rebuilding also clears the multikey flags of fields no longer holding arrays
+            for index in indexes.values_mut() {
+                *index = FieldIndex::build(&index.field, index.unique, collection_map);
+            }
+        }
+    }
+
+    /// Plans a query of a collection with a filter, or returns `None` if no index applies.
+    pub(crate) async fn plan(&self, collection: &str, filter: &Expr) -> Option<IndexPlan> {
+        planner::plan(filter, self.collections.read().await.get(collection)?)
+    }
+
+    /// Returns the keys of the documents of a collection a filter may match, or `None` if no
+    /// index applies and every document may.
+    pub(crate) async fn lookup(&self, collection: &str, filter: &Expr) -> Option<Vec<String>> {
+        let collections = self.collections.read().await;
+        let indexes = collections.get(collection)?;
+
+        planner::plan(filter, indexes).map(|plan| plan.lookup(indexes))
+    }
+}
@@ -9,7 +9,11 @@
 //! - **Thread-safe access** - Concurrent reads and writes using async-aware RwLock
 //! - **Type-erased storage** - Stores documents as BSON for flexibility
 //! - **Full query support** - Supports filtering, sorting, and pagination
+//! - **Parallel filtering** - With the `parallel` feature, large collections are filtered on rayon's thread pool
+//! - **Aggregation** - Group-by aggregations with MongoDB-compatible results
+//! - **Transactions** - [`InMemoryTransaction`] buffers writes against a snapshot of the store
 //! - **Revision tracking** - Optional revision ID tracking for migrations
+//! - **File persistence** - [`FileStore`] keeps collections as directories of JSON files
 //!
 //! # Quick Start
 //!
@@ -51,5 +55,14 @@ extern crate self as doclayer_memory;
 
 pub mod store;
 pub mod evaluator;
+pub mod aggregator;
+pub mod updater;
+pub mod file;
+pub mod transaction;
+mod index;
+mod planner;
+mod unique;
 
-pub use store::{InMemoryStore, InMemoryStoreBuilder};
+pub use store::{InMemoryStore, InMemoryStoreBuilder, IntegrityReport, CorruptEntry, IntegrityProblem};
+pub use file::{FileStore, FileStoreBuilder};
+pub use transaction::InMemoryTransaction;
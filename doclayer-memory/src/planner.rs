@@ -0,0 +1,132 @@
+//! Query planning of the in-memory store.
+//!
+//! Without a usable index, a query scans its whole collection. The planner looks through the
+//! conjunction at the top of a filter for comparisons an index answers, equality and ordering
+//! comparisons and ranges of indexed values, and picks the one whose index finds the fewest
+//! documents. The documents found are only candidates: the whole filter is still evaluated
+//! against each of them, which applies the remaining conditions.
+
+use std::{collections::HashMap, ops::Bound};
+use bson::Bson;
+
+use doclayer_core::query::{Expr, FieldOp};
+
+use crate::index::{FieldIndex, IndexKey, KeyRange};
+
+
+/// A lookup of the documents a filter may match through an index.
+#[derive(Debug, Clone)]
+pub(crate) struct IndexPlan {
+    /// The indexed field
+    pub(crate) field: String,
+    /// The part of the filter the index answers
+    pub(crate) predicate: Expr,
+    /// The keys looked up
+    pub(crate) range: KeyRange,
+    /// The number of index entries in the range, an upper bound of the candidates
+    pub(crate) candidates: usize,
+}
+
+impl IndexPlan {
+    /// Returns the keys of the candidate documents.
+    pub(crate) fn lookup(&self, indexes: &HashMap<String, FieldIndex>) -> Vec<String> {
+        indexes
+            .get(&self.field)
+            .map(|index| index.lookup(&self.range))
+            .unwrap_or_default()
+    }
+}
+
+/// Picks the index finding the fewest candidates for a filter, or `None` if no index
+/// applies and the collection must be scanned.
+pub(crate) fn plan(filter: &Expr, indexes: &HashMap<String, FieldIndex>) -> Option<IndexPlan> {
+    let mut plans = Vec::new();
+    collect(filter, indexes, &mut plans);
+
+    plans
+        .into_iter()
+        .min_by_key(|plan| plan.candidates)
+}
+
+/// Collects a plan for each comparison of the filter an index answers, among those every
+/// match of the filter must meet.
+fn collect(expr: &Expr, indexes: &HashMap<String, FieldIndex>, plans: &mut Vec<IndexPlan>) {
+    match expr {
+        Expr::And(exprs) => {
+            for expr in exprs {
+                collect(expr, indexes, plans);
+            }
+        },
+        // Equality and ordering comparisons stay exact
+        Expr::CaseInsensitive(expr) => collect(expr, indexes, plans),
+        Expr::Field { field, op, value } => {
+            let Some(index) = indexes.get(field) else {
+                return;
+            };
+
+            if let Some(range) = IndexKey::from_value(value).and_then(|key| comparison_range(op, key)) {
+                plans.push(IndexPlan {
+                    field: field.clone(),
+                    predicate: expr.clone(),
+                    candidates: index.count(&range),
+                    range,
+                });
+            }
+        },
+        Expr::Range { field, low, high } => {
+            let Some(index) = indexes.get(field) else {
+                return;
+            };
+
+            // Each bound may be met by a different value of a field holding several, so only
+            // a field holding one value per document is looked up between both bounds
+            match range_keys(low, high).filter(|_| !index.multikey()) {
+                Some(range) => plans.push(IndexPlan {
+                    field: field.clone(),
+                    predicate: expr.clone(),
+                    candidates: index.count(&range),
+                    range,
+                }),
+                None => collect(&Expr::expand_range(field, low, high), indexes, plans),
+            }
+        },
+        _ => {},
+    }
+}
+
+/// Returns the keys a comparison with a key matches, or `None` if an index can't answer the
+/// operator.
+fn comparison_range(op: &FieldOp, key: IndexKey) -> Option<KeyRange> {
+    match op {
+        FieldOp::Eq => Some((Bound::Included(key.clone()), Bound::Included(key))),
+        FieldOp::Gt => Some((Bound::Excluded(key.clone()), key.type_max())),
+        FieldOp::Gte => Some((Bound::Included(key.clone()), key.type_max())),
+        FieldOp::Lt => Some((key.type_min(), Bound::Excluded(key))),
+        FieldOp::Lte => Some((key.type_min(), Bound::Included(key))),
+        _ => None,
+    }
+}
+
+/// Returns the keys between the bounds of a range, or `None` if they aren't indexed values
+/// of the same type.
+fn range_keys(low: &Bound<Bson>, high: &Bound<Bson>) -> Option<KeyRange> {
+    let (low, high) = (bound_key(low)?, bound_key(high)?);
+
+    match (&low, &high) {
+        (Bound::Unbounded, Bound::Unbounded) => None,
+        (Bound::Unbounded, Bound::Included(key) | Bound::Excluded(key)) => Some((key.type_min(), high.clone())),
+        (Bound::Included(key) | Bound::Excluded(key), Bound::Unbounded) => Some((low.clone(), key.type_max())),
+        (Bound::Included(low_key) | Bound::Excluded(low_key), Bound::Included(high_key) | Bound::Excluded(high_key)) => {
+            low_key.same_type(high_key).then(|| (low.clone(), high.clone()))
+        },
+    }
+}
+
+/// Returns the key of a bound, or `None` if its value isn't indexed.
+fn bound_key(bound: &Bound<Bson>) -> Option<Bound<IndexKey>> {
+    match bound {
+        Bound::Included(value) => IndexKey::from_value(value).map(Bound::Included),
+        Bound::Excluded(value) => IndexKey::from_value(value).map(Bound::Excluded),
+        Bound::Unbounded => Some(Bound::Unbounded),
+    }
+}
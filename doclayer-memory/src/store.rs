@@ -3,21 +3,39 @@
 //! This module provides a simple but powerful in-memory backend that stores
 //! documents as BSON values in HashMaps with async-safe read-write locks.
 
-use std::{collections::HashMap, sync::Arc, cmp::Ordering};
+use std::{cmp::Ordering, collections::{HashMap, HashSet, hash_map::Entry}, future::Future, sync::Arc, time::Duration};
 use async_trait::async_trait;
 use mea::rwlock::RwLock;
-use bson::{Uuid, Bson};
+use futures::stream::{self, StreamExt};
+use bson::{Uuid, Bson, doc, spec::ElementType};
 
 use doclayer_core::{
-    query::{Query, SortDirection},
+    aggregate::Aggregate,
+    document::stored_version,
+    versioned::document_revision,
+    encoding::Encoding,
+    expiry::ExpiringCollections,
+    query::{CustomOperator, CustomOperators, Expr, Query, Sort, SortComparator, SortComparators},
+    update::Update,
     error::{DocumentStoreError, DocumentStoreResult},
-    backend::{StoreBackend, StoreBackendBuilder},
+    backend::{DocumentStream, InsertPolicy, MissingDocumentPolicy, QueryPlan, ReturnDocument, ScanStrategy, StoreBackend, StoreBackendBuilder, WriteReport},
+    transaction::Transaction,
 };
 
-use crate::evaluator::{DocumentEvaluator, Comparable};
+use crate::{
+    aggregator::DocumentAggregator,
+    evaluator::{DocumentEvaluator, Regexes, compare_documents, distinct_values, project_document, sort_documents},
+    updater::DocumentUpdater,
+    transaction::InMemoryTransaction,
+    index::Indexes,
+    unique::check_unique,
+};
+
+pub(crate) type CollectionMap = HashMap<String, Bson>;
+pub(crate) type StoreMap = HashMap<String, CollectionMap>;
 
-type CollectionMap = HashMap<String, Bson>;
-type StoreMap = HashMap<String, CollectionMap>;
+/// Number of documents read per lock acquisition when streaming query results.
+const STREAM_CHUNK_SIZE: usize = 256;
 
 
 /// Thread-safe in-memory document storage backend.
@@ -34,15 +52,21 @@ type StoreMap = HashMap<String, CollectionMap>;
 ///
 /// # Performance
 ///
-/// Queries scan all documents in a collection (no indexing). For small to medium
-/// datasets (< 100k documents), this is typically acceptable. For larger datasets,
-/// consider using a persistent backend like MongoDB.
+/// Queries scan all documents in a collection unless a field they compare is indexed with
+/// [`StoreBackend::add_index`]. A query comparing indexed fields for equality or order,
+/// among the conditions all of its matches must meet, only evaluates the documents the most
+/// selective of those indexes finds; [`StoreBackend::explain`] reports the index chosen.
+/// Every write to a collection updates its indexes. For larger datasets, consider using a
+/// persistent backend like MongoDB.
+///
+/// Unique indexes are enforced, by checking every write to their collection against its
+/// documents.
 ///
 /// # Example
 ///
 /// ```ignore
 /// use doclayer_memory::InMemoryStore;
-/// use doclayer::backend::StoreBackend;
+/// use doclayer::backend::{InsertPolicy, StoreBackend};
 /// use bson::{Uuid, Bson, doc};
 ///
 /// #[tokio::main]
@@ -52,7 +76,7 @@ type StoreMap = HashMap<String, CollectionMap>;
 ///     // Insert documents
 ///     let id = Uuid::new();
 ///     let doc = Bson::Document(doc! { "name": "Alice", "age": 30 });
-///     store.insert_documents(vec![(id, doc)], "users").await?;
+///     store.insert_documents(vec![(id, doc)], "users", InsertPolicy::ErrorOnConflict).await?;
 ///     
 ///     // Retrieve documents
 ///     let docs = store.get_documents(vec![id], "users").await?;
@@ -64,9 +88,19 @@ type StoreMap = HashMap<String, CollectionMap>;
 #[derive(Default, Clone, Debug)]
 pub struct InMemoryStore {
     /// The main storage map: collection_name -> (document_id -> document)
-    store: Arc<RwLock<StoreMap>>,
+    pub(crate) store: Arc<RwLock<StoreMap>>,
     /// Optional current revision ID for tracking schema versions
-    current_revision: Arc<RwLock<Option<String>>>,
+    pub(crate) current_revision: Arc<RwLock<Option<String>>>,
+    /// Custom orderings of sort fields, by collection and field
+    pub(crate) comparators: Arc<SortComparators>,
+    /// Handlers of custom filter operators, by name
+    pub(crate) operators: Arc<CustomOperators>,
+    /// The representation of documents written through typed collections
+    pub(crate) encoding: Arc<Encoding>,
+    /// Collections whose documents expire, with the field holding their expiry
+    pub(crate) expiring: ExpiringCollections,
+    /// Indexes of fields, by collection
+    pub(crate) indexes: Indexes,
 }
 
 impl InMemoryStore {
@@ -86,13 +120,18 @@ impl InMemoryStore {
         Self {
             store: Arc::new(RwLock::new(StoreMap::new())),
             current_revision: Arc::new(RwLock::new(None)),
+            comparators: Arc::new(SortComparators::new()),
+            operators: Arc::new(CustomOperators::new()),
+            encoding: Arc::new(Encoding::new()),
+            expiring: ExpiringCollections::new(),
+            indexes: Indexes::default(),
         }
     }
 
     /// Creates a builder for constructing an `InMemoryStore` with custom options.
     ///
-    /// Currently, the builder simply creates a default store, but it can be extended
-    /// in future versions to support configuration options.
+    /// The builder registers custom sort comparators; see
+    /// [`InMemoryStoreBuilder::with_comparator`].
     ///
     /// # Example
     ///
@@ -104,66 +143,680 @@ impl InMemoryStore {
     pub fn builder() -> InMemoryStoreBuilder {
         InMemoryStoreBuilder::default()
     }
+
+    /// Creates an evaluator for a stored document, with the store's custom operators and the
+    /// regular expressions compiled from the filter it evaluates.
+    fn evaluator<'a>(&'a self, document: &'a Bson, regexes: &'a Regexes) -> DocumentEvaluator<'a> {
+        DocumentEvaluator::new(document).with_operators(&self.operators).with_regexes(regexes)
+    }
+
+    /// Checks the integrity of every stored entry.
+    ///
+    /// Each entry must be keyed by a valid UUID and hold a BSON document. Untyped collections
+    /// accept any BSON value, and a stored value that isn't a document fails evaluation in
+    /// every query, so this surfaces such entries up front.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let report = store.verify().await;
+    ///
+    /// for entry in &report.corrupt {
+    ///     eprintln!("{}/{}: {:?}", entry.collection, entry.key, entry.problem);
+    /// }
+    /// ```
+    pub async fn verify(&self) -> IntegrityReport {
+        let store = self.store.read().await;
+        let mut report = IntegrityReport::default();
+
+        for (collection, collection_map) in store.iter() {
+            for (key, document) in collection_map {
+                report.checked += 1;
+
+                let problem = if Uuid::parse_str(key).is_err() {
+                    Some(IntegrityProblem::InvalidId)
+                } else if !matches!(document, Bson::Document(_)) {
+                    Some(IntegrityProblem::NotADocument(document.element_type()))
+                } else {
+                    None
+                };
+
+                if let Some(problem) = problem {
+                    report.corrupt.push(CorruptEntry {
+                        collection: collection.clone(),
+                        key: key.clone(),
+                        problem,
+                    });
+                }
+            }
+        }
+
+        report
+    }
+
+    /// Returns the collections made to expire their documents with
+    /// [`StoreBackend::expire_documents`].
+    ///
+    /// Backends persisting the store, such as [`FileStore`](crate::FileStore), sweep them
+    /// through their own writes.
+    pub fn expiring_collections(&self) -> &ExpiringCollections {
+        &self.expiring
+    }
+
+    /// Deletes the documents whose expiry has passed, in every collection made to expire them
+    /// with [`StoreBackend::expire_documents`].
+    ///
+    /// # Returns
+    ///
+    /// The number of documents deleted.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// store.expire_documents("sessions", "expires_at").await?;
+    ///
+    /// let deleted = store.sweep_expired().await?;
+    /// ```
+    pub async fn sweep_expired(&self) -> DocumentStoreResult<usize> {
+        self.expiring.sweep(self).await
+    }
+
+    /// Sweeps expired documents repeatedly, waiting `interval` between sweeps.
+    ///
+    /// Meant to be spawned as a background task; this never returns. See
+    /// [`ExpiringCollections::run`] for the arguments.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let sweeper = store.clone();
+    /// tokio::spawn(async move {
+    ///     sweeper.run_sweeper(Duration::from_secs(60), tokio::time::sleep, |_| {}).await
+    /// });
+    /// ```
+    pub async fn run_sweeper<F, Fut, R>(&self, interval: Duration, sleep: F, on_sweep: R)
+    where
+        F: Fn(Duration) -> Fut,
+        Fut: Future<Output = ()>,
+        R: FnMut(DocumentStoreResult<usize>),
+    {
+        self.expiring.run(self, interval, sleep, on_sweep).await
+    }
+
+    /// Returns the keys of the documents in a collection matching a filter.
+    ///
+    /// Keys are the documents' IDs as strings. Backends persisting the store, such as
+    /// [`FileStore`](crate::FileStore), use them to find the documents a write will touch.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the filter can't be evaluated against a document.
+    pub async fn matching_keys(&self, filter: &Expr, collection: &str) -> DocumentStoreResult<Vec<String>> {
+        let regexes = Regexes::compile(filter)?;
+        let store = self.store.read().await;
+        let mut matched = Vec::new();
+
+        if let Some(collection_map) = store.get(collection) {
+            for (key, doc) in self.candidates(collection_map, Some(filter), collection).await {
+                if self.evaluator(doc, &regexes).evaluate(filter)? {
+                    matched.push(key.clone());
+                }
+            }
+        }
+
+        Ok(matched)
+    }
+
+    /// Returns the key of the first document in a collection matching a filter, in sort
+    /// order, which is the document [`StoreBackend::find_one_and_update`] and
+    /// [`StoreBackend::find_one_and_delete`] would write.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the filter can't be evaluated against a document.
+    pub async fn first_matching_key(&self, filter: &Expr, sort: &[Sort], collection: &str) -> DocumentStoreResult<Option<String>> {
+        match self.store.read().await.get(collection) {
+            Some(collection_map) => self.first_match(collection_map, filter, sort, collection).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the keys of every document in a collection.
+    pub async fn keys(&self, collection: &str) -> Vec<String> {
+        match self.store.read().await.get(collection) {
+            Some(collection_map) => collection_map.keys().cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the documents stored under the given keys of a collection.
+    ///
+    /// Each key is paired with its document, or `None` if the collection doesn't hold one,
+    /// so a persisting backend can write the documents and remove the deleted ones.
+    pub async fn entries(&self, collection: &str, keys: impl IntoIterator<Item = String>) -> Vec<(String, Option<Bson>)> {
+        let store = self.store.read().await;
+        let collection_map = store.get(collection);
+
+        keys
+            .into_iter()
+            .map(|key| {
+                let document = collection_map.and_then(|collection_map| collection_map.get(&key)).cloned();
+                (key, document)
+            })
+            .collect()
+    }
+
+    /// Checks that a collection holds documents at the given revisions, so a conditional write
+    /// fails before changing anything.
+    fn check_revisions<'r>(
+        collection_map: Option<&CollectionMap>,
+        revisions: impl IntoIterator<Item = (&'r Uuid, &'r String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<()> {
+        for (id, revision) in revisions {
+            let stored = collection_map.and_then(|collection_map| collection_map.get(&id.to_string()));
+
+            if stored.map(document_revision).transpose()?.as_ref() != Some(revision) {
+                return Err(DocumentStoreError::PreconditionFailed(id.to_string(), collection.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the documents of a collection a filter may match: those the index chosen for it
+    /// finds, or every document if no index applies. The filter still has to be evaluated
+    /// against each of them.
+    async fn candidates<'c>(&self, collection_map: &'c CollectionMap, filter: Option<&Expr>, collection: &str) -> Vec<(&'c String, &'c Bson)> {
+        let keys = match filter {
+            Some(filter) => self.indexes.lookup(collection, filter).await,
+            None => None,
+        };
+
+        match keys {
+            Some(keys) => keys
+                .iter()
+                .filter_map(|key| collection_map.get_key_value(key))
+                .collect(),
+            None => collection_map.iter().collect(),
+        }
+    }
+
+    /// Returns the key of the first document in a collection matching a filter, in sort order.
+    async fn first_match(
+        &self,
+        collection_map: &CollectionMap,
+        filter: &Expr,
+        sort: &[Sort],
+        collection: &str,
+    ) -> DocumentStoreResult<Option<String>> {
+        let regexes = Regexes::compile(filter)?;
+        let mut first: Option<(&String, &Bson)> = None;
+
+        for (key, doc) in self.candidates(collection_map, Some(filter), collection).await {
+            if !self.evaluator(doc, &regexes).evaluate(filter)? {
+                continue;
+            }
+
+            let earlier = first.is_none_or(|(_, current)| {
+                compare_documents(doc, current, sort, |field| self.comparators.get(collection, field)) == Ordering::Less
+            });
+
+            if earlier {
+                first = Some((key, doc));
+            }
+        }
+
+        Ok(first.map(|(key, _)| key.clone()))
+    }
+}
+
+
+/// The result of [`InMemoryStore::verify`].
+#[derive(Debug, Clone, Default)]
+pub struct IntegrityReport {
+    /// The number of entries checked.
+    pub checked: usize,
+    /// The entries that failed a check.
+    pub corrupt: Vec<CorruptEntry>,
+}
+
+impl IntegrityReport {
+    /// Returns `true` if no corrupt entry was found.
+    pub fn is_ok(&self) -> bool {
+        self.corrupt.is_empty()
+    }
+}
+
+/// A stored entry that failed an integrity check.
+#[derive(Debug, Clone)]
+pub struct CorruptEntry {
+    /// The collection holding the entry.
+    pub collection: String,
+    /// The key the entry is stored under.
+    pub key: String,
+    /// What is wrong with the entry.
+    pub problem: IntegrityProblem,
+}
+
+/// The ways a stored entry can be corrupt.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IntegrityProblem {
+    /// The entry's key is not a valid document ID.
+    InvalidId,
+    /// The entry holds a value of the given type instead of a document.
+    NotADocument(ElementType),
 }
 
 
 #[async_trait]
 impl StoreBackend for InMemoryStore {
-    async fn insert_documents(&self, documents: Vec<(Uuid, Bson)>, collection: &str) -> DocumentStoreResult<()> {
+    async fn insert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
         let mut store = self.store.write().await;
+
+        // Check every ID up front, including repeats within the batch, so a conflict leaves
+        // the collection untouched
+        if policy == InsertPolicy::ErrorOnConflict {
+            let existing = store.get(collection);
+            let mut seen = HashSet::new();
+
+            if let Some((id, _)) = documents.iter().find(|(id, _)| {
+                !seen.insert(*id) || existing.is_some_and(|col| col.contains_key(&id.to_string()))
+            }) {
+                return Err(DocumentStoreError::DocumentAlreadyExists(id.to_string(), collection.to_string()));
+            }
+        }
+
+        // Skipped documents keep the stored ones, and a repeated ID keeps the first unless
+        // replacing
+        let existing = store.get(collection);
+        let mut seen = HashSet::new();
+        self.indexes.check_unique(
+            existing,
+            documents
+                .iter()
+                .filter(|(id, _)| {
+                    policy == InsertPolicy::Replace
+                        || (seen.insert(*id) && !existing.is_some_and(|col| col.contains_key(&id.to_string())))
+                })
+                .map(|(id, doc)| (id.to_string(), Some(doc))),
+            collection,
+        )
+        .await?;
+
+        let keys = documents
+            .iter()
+            .map(|(id, _)| id.to_string())
+            .collect::<Vec<_>>();
         let collection_map = store
             .entry(collection.to_string())
             .or_default();
+        let mut report = WriteReport::default();
+
+        for (id, doc) in documents {
+            match collection_map.entry(id.to_string()) {
+                Entry::Vacant(entry) => {
+                    entry.insert(doc);
+                    report.upserted += 1;
+                },
+                Entry::Occupied(mut entry) => {
+                    report.matched += 1;
+                    if policy == InsertPolicy::Replace && *entry.get() != doc {
+                        entry.insert(doc);
+                        report.modified += 1;
+                    }
+                },
+            }
+        }
+
+        self.indexes.update(collection, Some(collection_map), keys).await;
+        Ok(report)
+    }
+
+    async fn update_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        if documents.is_empty() {
+            return Ok(WriteReport::default());
+        }
+
+        let mut store = self.store.write().await;
+        let collection_map = match (store.get_mut(collection), policy) {
+            (Some(col), _) => col,
+            (None, MissingDocumentPolicy::Error) => return Err(DocumentStoreError::CollectionNotFound(collection.to_string())),
+            (None, MissingDocumentPolicy::Skip) => return Ok(WriteReport::default()),
+            (None, MissingDocumentPolicy::Upsert) => store.entry(collection.to_string()).or_default(),
+        };
+
+        // Check every ID up front so an error leaves the collection untouched
+        if policy == MissingDocumentPolicy::Error
+            && let Some((id, _)) = documents.iter().find(|(id, _)| !collection_map.contains_key(&id.to_string()))
+        {
+            return Err(DocumentStoreError::DocumentNotFound(id.to_string(), collection.to_string()));
+        }
+
+        self.indexes.check_unique(
+            Some(collection_map),
+            documents
+                .iter()
+                .filter(|(id, _)| policy != MissingDocumentPolicy::Skip || collection_map.contains_key(&id.to_string()))
+                .map(|(id, doc)| (id.to_string(), Some(doc))),
+            collection,
+        )
+        .await?;
+
+        let keys = documents
+            .iter()
+            .map(|(id, _)| id.to_string())
+            .collect::<Vec<_>>();
+        let mut report = WriteReport::default();
 
         for (id, doc) in documents {
             let key = id.to_string();
 
-            if collection_map.contains_key(&key) {
-                return Err(DocumentStoreError::DocumentAlreadyExists(key, collection.to_string()));
+            match collection_map.get(&key) {
+                Some(existing) => {
+                    report.matched += 1;
+                    if *existing != doc {
+                        report.modified += 1;
+                    }
+                },
+                None if policy == MissingDocumentPolicy::Skip => continue,
+                None => report.upserted += 1,
             }
 
             collection_map.insert(key, doc);
         }
 
+        self.indexes.update(collection, Some(collection_map), keys).await;
+        Ok(report)
+    }
+
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        if documents.is_empty() {
+            return Ok(WriteReport::default());
+        }
+
+        let mut store = self.store.write().await;
+        let collection_map = store
+            .get_mut(collection)
+            .ok_or_else(|| DocumentStoreError::CollectionNotFound(collection.to_string()))?;
+
+        // Check every version up front so a conflict leaves the collection untouched
+        for (id, _, version) in &documents {
+            match collection_map.get(&id.to_string()) {
+                None => return Err(DocumentStoreError::DocumentNotFound(id.to_string(), collection.to_string())),
+                Some(existing) if stored_version(existing, version_field) != Some(*version) => {
+                    return Err(DocumentStoreError::VersionConflict(id.to_string(), collection.to_string(), *version));
+                },
+                Some(_) => {},
+            }
+        }
+
+        self.indexes.check_unique(
+            Some(collection_map),
+            documents.iter().map(|(id, doc, _)| (id.to_string(), Some(doc))),
+            collection,
+        )
+        .await?;
+
+        let mut report = WriteReport::default();
+
+        let mut keys = Vec::with_capacity(documents.len());
+
+        for (id, doc, _) in documents {
+            report.matched += 1;
+            if collection_map.insert(id.to_string(), doc.clone()).as_ref() != Some(&doc) {
+                report.modified += 1;
+            }
+            keys.push(id.to_string());
+        }
+
+        self.indexes.update(collection, Some(collection_map), keys).await;
+        Ok(report)
+    }
+
+    async fn update_documents_if_match(
+        &self,
+        documents: Vec<(Uuid, Bson, String)>,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        let mut store = self.store.write().await;
+        Self::check_revisions(store.get(collection), documents.iter().map(|(id, _, revision)| (id, revision)), collection)?;
+        self.indexes.check_unique(
+            store.get(collection),
+            documents.iter().map(|(id, doc, _)| (id.to_string(), Some(doc))),
+            collection,
+        )
+        .await?;
+
+        let Some(collection_map) = store.get_mut(collection) else {
+            return Ok(WriteReport::default());
+        };
+        let mut report = WriteReport::default();
+
+        let mut keys = Vec::with_capacity(documents.len());
+
+        for (id, doc, _) in documents {
+            report.matched += 1;
+            if collection_map.insert(id.to_string(), doc.clone()).as_ref() != Some(&doc) {
+                report.modified += 1;
+            }
+            keys.push(id.to_string());
+        }
+
+        self.indexes.update(collection, Some(collection_map), keys).await;
+        Ok(report)
+    }
+
+    async fn upsert_documents(&self, documents: Vec<(Uuid, Bson)>, collection: &str) -> DocumentStoreResult<()> {
+        let mut store = self.store.write().await;
+        self.indexes.check_unique(
+            store.get(collection),
+            documents.iter().map(|(id, doc)| (id.to_string(), Some(doc))),
+            collection,
+        )
+        .await?;
+
+        let collection_map = store
+            .entry(collection.to_string())
+            .or_default();
+
+        let mut keys = Vec::with_capacity(documents.len());
+
+        for (id, doc) in documents {
+            collection_map.insert(id.to_string(), doc);
+            keys.push(id.to_string());
+        }
+
+        self.indexes.update(collection, Some(collection_map), keys).await;
         Ok(())
     }
 
-    async fn update_documents(&self, documents: Vec<(Uuid, Bson)>, collection: &str) -> DocumentStoreResult<()> {
+    async fn delete_documents(&self, ids: Vec<Uuid>, collection: &str) -> DocumentStoreResult<usize> {
         let mut store = self.store.write().await;
         let collection_map = match store.get_mut(collection) {
             Some(col) => col,
-            None => return Err(DocumentStoreError::CollectionNotFound(collection.to_string())),
+            None => return Ok(0),
         };
 
-        for (id, doc) in documents {
-            let key = id.to_string();
+        // Missing IDs are skipped to match the other backends
+        let deleted = ids
+            .into_iter()
+            .map(|id| id.to_string())
+            .filter(|key| collection_map.remove(key).is_some())
+            .collect::<Vec<_>>();
+        let count = deleted.len();
 
-            if !collection_map.contains_key(&key) {
-                return Err(DocumentStoreError::DocumentNotFound(key, collection.to_string()));
+        self.indexes.update(collection, Some(collection_map), deleted).await;
+        Ok(count)
+    }
+
+    async fn delete_documents_if_match(&self, documents: Vec<(Uuid, String)>, collection: &str) -> DocumentStoreResult<usize> {
+        let mut store = self.store.write().await;
+        Self::check_revisions(store.get(collection), documents.iter().map(|(id, revision)| (id, revision)), collection)?;
+
+        let Some(collection_map) = store.get_mut(collection) else {
+            return Ok(0);
+        };
+
+        let deleted = documents
+            .into_iter()
+            .map(|(id, _)| id.to_string())
+            .filter(|key| collection_map.remove(key).is_some())
+            .collect::<Vec<_>>();
+        let count = deleted.len();
+
+        self.indexes.update(collection, Some(collection_map), deleted).await;
+        Ok(count)
+    }
+
+    async fn update_by_query(&self, filter: Expr, update: Update, collection: &str) -> DocumentStoreResult<WriteReport> {
+        let regexes = Regexes::compile(&filter)?;
+        let mut store = self.store.write().await;
+        let collection_map = match store.get_mut(collection) {
+            Some(col) => col,
+            None => return Ok(WriteReport::default()),
+        };
+
+        // Apply the update to copies first so a failing operation leaves the collection untouched
+        let mut updated = Vec::new();
+        let mut report = WriteReport::default();
+
+        for (id, doc) in self.candidates(collection_map, Some(&filter), collection).await {
+            if !self.evaluator(doc, &regexes).evaluate(&filter)? {
+                continue;
             }
 
-            collection_map.insert(key, doc);
+            let mut document = doc
+                .as_document()
+                .cloned()
+                .ok_or_else(|| DocumentStoreError::InvalidDocument(
+                    format!("Document {} is not a BSON document", id)
+                ))?;
+
+            DocumentUpdater::new(&mut document).apply(&update)?;
+            report.matched += 1;
+
+            if Some(&document) != doc.as_document() {
+                report.modified += 1;
+                updated.push((id.clone(), Bson::Document(document)));
+            }
         }
 
-        Ok(())
+        self.indexes.check_unique(
+            Some(collection_map),
+            updated.iter().map(|(id, doc)| (id.clone(), Some(doc))),
+            collection,
+        )
+        .await?;
+
+        let keys = updated
+            .iter()
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        collection_map.extend(updated);
+        self.indexes.update(collection, Some(collection_map), keys).await;
+
+        Ok(report)
     }
 
-    async fn delete_documents(&self, ids: Vec<Uuid>, collection: &str) -> DocumentStoreResult<()> {
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize> {
+        let regexes = Regexes::compile(&filter)?;
         let mut store = self.store.write().await;
         let collection_map = match store.get_mut(collection) {
             Some(col) => col,
-            None => return Err(DocumentStoreError::CollectionNotFound(collection.to_string())),
+            None => return Ok(0),
         };
 
-        for id in ids {
-            let key = id.to_string();
+        // Evaluate every document before removing any, so a failing evaluation deletes nothing
+        let mut matched = Vec::new();
 
-            if collection_map.remove(&key).is_none() {
-                return Err(DocumentStoreError::DocumentNotFound(key, collection.to_string()));
+        for (id, doc) in self.candidates(collection_map, Some(&filter), collection).await {
+            if self.evaluator(doc, &regexes).evaluate(&filter)? {
+                matched.push(id.clone());
             }
         }
 
-        Ok(())
+        for id in &matched {
+            collection_map.remove(id);
+        }
+
+        let count = matched.len();
+        self.indexes.update(collection, Some(collection_map), matched).await;
+
+        Ok(count)
+    }
+
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        // The write lock is held from matching to writing, so no other write can come between
+        let mut store = self.store.write().await;
+        let Some(collection_map) = store.get_mut(collection) else {
+            return Ok(None);
+        };
+        let Some(key) = self.first_match(collection_map, &filter, &sort, collection).await? else {
+            return Ok(None);
+        };
+
+        let previous = collection_map[&key].clone();
+        let mut document = previous
+            .as_document()
+            .cloned()
+            .ok_or_else(|| DocumentStoreError::InvalidDocument(
+                format!("Document {} is not a BSON document", key)
+            ))?;
+
+        DocumentUpdater::new(&mut document).apply(&update)?;
+
+        let updated = Bson::Document(document);
+        self.indexes.check_unique(
+            Some(collection_map),
+            [(key.clone(), Some(&updated))],
+            collection,
+        )
+        .await?;
+        collection_map.insert(key.clone(), updated.clone());
+        self.indexes.update(collection, Some(collection_map), [key]).await;
+
+        Ok(Some(match returned {
+            ReturnDocument::Before => previous,
+            ReturnDocument::After => updated,
+        }))
+    }
+
+    async fn find_one_and_delete(&self, filter: Expr, sort: Vec<Sort>, collection: &str) -> DocumentStoreResult<Option<Bson>> {
+        let mut store = self.store.write().await;
+        let Some(collection_map) = store.get_mut(collection) else {
+            return Ok(None);
+        };
+
+        let Some(key) = self.first_match(collection_map, &filter, &sort, collection).await? else {
+            return Ok(None);
+        };
+        let deleted = collection_map.remove(&key);
+
+        self.indexes.update(collection, Some(collection_map), [key]).await;
+        Ok(deleted)
     }
 
     async fn get_documents(&self, ids: Vec<Uuid>, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
@@ -187,6 +840,7 @@ impl StoreBackend for InMemoryStore {
     }
 
     async fn query_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+        let regexes = query.filter.as_ref().map(Regexes::compile).transpose()?.unwrap_or_default();
         let store = self.store.read().await;
         let collection_map = match store.get(collection) {
             Some(col) => col,
@@ -196,8 +850,13 @@ impl StoreBackend for InMemoryStore {
         // Apply filter expressions if present
         let filtered_docs = match &query.filter {
             Some(filter) => DocumentEvaluator::filter_documents(
-                collection_map.values(),
+                self.candidates(collection_map, Some(filter), collection)
+                    .await
+                    .into_iter()
+                    .map(|(_, doc)| doc),
                 filter,
+                &self.operators,
+                &regexes,
             )?,
             None => collection_map
                 .values()
@@ -206,50 +865,223 @@ impl StoreBackend for InMemoryStore {
         };
 
         // Apply sorting if specified
-        if let Some(sort) = &query.sort {
+        let documents = if !query.sort.is_empty() {
             let mut sorted_docs = filtered_docs;
 
-            sorted_docs.sort_by(|a, b| {
-                // Extract the field value and compare using Comparable wrapper
-                let left = a
-                    .as_document()
-                    .unwrap()
-                    .get(&sort.field)
-                    .map(Comparable::from)
-                    .unwrap_or(Comparable::Null);
-                let right = b
-                    .as_document()
-                    .unwrap()
-                    .get(&sort.field)
-                    .map(Comparable::from)
-                    .unwrap_or(Comparable::Null);
-
-                match sort.direction {
-                    SortDirection::Asc => left.partial_cmp(&right).unwrap_or(Ordering::Equal),
-                    SortDirection::Desc => right.partial_cmp(&left).unwrap_or(Ordering::Equal),
-                }
-            });
+            sort_documents(&mut sorted_docs, &query.sort, |field| self.comparators.get(collection, field));
 
             // Apply offset and limit
-            return Ok(
-                sorted_docs
-                    .into_iter()
-                    .skip(query.offset.unwrap_or(0))
-                    .take(query.limit.unwrap_or(usize::MAX))
-                    .collect()
-            );
-        }
-
-        // Apply offset and limit without sorting
-        Ok(
+            sorted_docs
+                .into_iter()
+                .skip(query.offset.unwrap_or(0))
+                .take(query.limit.unwrap_or(usize::MAX))
+                .collect::<Vec<_>>()
+        } else {
+            // Apply offset and limit without sorting
             filtered_docs
                 .into_iter()
                 .skip(query.offset.unwrap_or(0))
                 .take(query.limit.unwrap_or(usize::MAX))
-                .collect()
+                .collect::<Vec<_>>()
+        };
+
+        // Trim documents to the projected fields if requested
+        Ok(match &query.projection {
+            Some(fields) => documents
+                .iter()
+                .map(|doc| project_document(doc, fields))
+                .collect(),
+            None => documents,
+        })
+    }
+
+    async fn query_stream(&self, query: Query, collection: &str) -> DocumentStoreResult<DocumentStream> {
+        // Sorting needs every match up front, so only unsorted queries are read in chunks
+        if !query.sort.is_empty() {
+            let documents = self.query_documents(query, collection).await?;
+            return Ok(stream::iter(documents.into_iter().map(Ok)).boxed());
+        }
+
+        let regexes = Arc::new(query.filter.as_ref().map(Regexes::compile).transpose()?.unwrap_or_default());
+
+        // Snapshot the ids of the candidates, then read the documents a chunk at a time so the
+        // lock is only held briefly and writers aren't blocked while the stream is consumed
+        let ids = match self.store.read().await.get(collection) {
+            Some(col) => self.candidates(col, query.filter.as_ref(), collection)
+                .await
+                .into_iter()
+                .map(|(id, _)| id.clone())
+                .collect::<Vec<_>>(),
+            None => Vec::new(),
+        };
+        let chunks = ids
+            .chunks(STREAM_CHUNK_SIZE)
+            .map(<[String]>::to_vec)
+            .collect::<Vec<_>>();
+
+        let store = self.store.clone();
+        let collection = collection.to_string();
+        let filter = Arc::new(query.filter);
+        let operators = self.operators.clone();
+        let projection = query.projection;
+
+        Ok(stream::iter(chunks)
+            .then(move |chunk| {
+                let store = store.clone();
+                let collection = collection.clone();
+                let filter = filter.clone();
+                let operators = operators.clone();
+                let regexes = regexes.clone();
+
+                async move {
+                    let store = store.read().await;
+                    let Some(collection_map) = store.get(&collection) else {
+                        return Vec::new();
+                    };
+
+                    // Documents deleted since the snapshot are skipped
+                    chunk
+                        .iter()
+                        .filter_map(|id| collection_map.get(id))
+                        .filter_map(|doc| match filter.as_ref() {
+                            Some(expr) => match DocumentEvaluator::new(doc).with_operators(&operators).with_regexes(&regexes).evaluate(expr) {
+                                Ok(true) => Some(Ok(doc.clone())),
+                                Ok(false) => None,
+                                Err(e) => Some(Err(e)),
+                            },
+                            None => Some(Ok(doc.clone())),
+                        })
+                        .collect::<Vec<_>>()
+                }
+            })
+            .flat_map(stream::iter)
+            .skip(query.offset.unwrap_or(0))
+            .take(query.limit.unwrap_or(usize::MAX))
+            .map(move |doc| match &projection {
+                Some(fields) => doc.map(|doc| project_document(&doc, fields)),
+                None => doc,
+            })
+            .boxed())
+    }
+
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize> {
+        let regexes = query.filter.as_ref().map(Regexes::compile).transpose()?.unwrap_or_default();
+        let store = self.store.read().await;
+        let collection_map = match store.get(collection) {
+            Some(col) => col,
+            None => return Ok(0),
+        };
+
+        let matched = match &query.filter {
+            Some(filter) => self.candidates(collection_map, Some(filter), collection)
+                .await
+                .into_iter()
+                .try_fold(0, |count, (_, doc)| {
+                    self.evaluator(doc, &regexes)
+                        .evaluate(filter)
+                        .map(|matches| count + matches as usize)
+                })?,
+            None => collection_map.len(),
+        };
+
+        // Apply offset and limit the same way query_documents does
+        Ok(
+            matched
+                .saturating_sub(query.offset.unwrap_or(0))
+                .min(query.limit.unwrap_or(usize::MAX))
         )
     }
 
+    async fn distinct(&self, field: &str, filter: Option<Expr>, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+        let regexes = filter.as_ref().map(Regexes::compile).transpose()?.unwrap_or_default();
+        let store = self.store.read().await;
+        let collection_map = match store.get(collection) {
+            Some(col) => col,
+            None => return Ok(vec![]),
+        };
+
+        let mut documents = Vec::new();
+
+        for (_, document) in self.candidates(collection_map, filter.as_ref(), collection).await {
+            if let Some(filter) = &filter
+                && !self.evaluator(document, &regexes).evaluate(filter)?
+            {
+                continue;
+            }
+
+            documents.push(document);
+        }
+
+        Ok(distinct_values(documents, field))
+    }
+
+    async fn aggregate(&self, aggregate: Aggregate, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+        let regexes = aggregate.filter.as_ref().map(Regexes::compile).transpose()?.unwrap_or_default();
+        let store = self.store.read().await;
+        let collection_map = match store.get(collection) {
+            Some(col) => col,
+            None => return Ok(vec![]),
+        };
+
+        Ok(match &aggregate.filter {
+            Some(filter) => DocumentAggregator::aggregate(
+                &aggregate,
+                &DocumentEvaluator::filter_documents(
+                    self.candidates(collection_map, Some(filter), collection)
+                        .await
+                        .into_iter()
+                        .map(|(_, doc)| doc),
+                    filter,
+                    &self.operators,
+                    &regexes,
+                )?,
+            ),
+            None => DocumentAggregator::aggregate(&aggregate, collection_map.values()),
+        })
+    }
+
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan> {
+        let store = self.store.read().await;
+        let documents = match store.get(collection) {
+            Some(col) => col.len(),
+            None => 0,
+        };
+        let plan = match &query.filter {
+            Some(filter) => self.indexes.plan(collection, filter).await,
+            None => None,
+        };
+
+        // The whole filter is evaluated against the documents an index finds; sorting happens
+        // after filtering
+        if let Some(plan) = plan {
+            return Ok(QueryPlan {
+                strategy: ScanStrategy::IndexScan(vec![plan.field.clone()]),
+                details: Bson::Document(doc! {
+                    "stage": "INDEX_SCAN",
+                    "collection": collection,
+                    "index": plan.field,
+                    "predicate": plan.predicate.to_string(),
+                    "candidates": plan.candidates as i64,
+                    "documents": documents as i64,
+                    "filtered": true,
+                    "sorted": !query.sort.is_empty(),
+                }),
+            });
+        }
+
+        // Without a usable index the query reads the whole collection
+        Ok(QueryPlan {
+            strategy: ScanStrategy::FullScan,
+            details: Bson::Document(doc! {
+                "stage": "FULL_SCAN",
+                "collection": collection,
+                "documents": documents as i64,
+                "filtered": query.filter.is_some(),
+                "sorted": !query.sort.is_empty(),
+            }),
+        })
+    }
+
     async fn current_revision_id(&self) -> DocumentStoreResult<Option<String>> {
         Ok(
             self.current_revision
@@ -267,6 +1099,27 @@ impl StoreBackend for InMemoryStore {
     }
 
     async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        let mut store = self.store.write().await;
+
+        // Checked under the write lock, so of concurrent creators only one succeeds
+        if store.contains_key(name) {
+            return Err(DocumentStoreError::CollectionAlreadyExists(name.to_string()));
+        }
+        store.insert(name.to_string(), HashMap::new());
+
+        Ok(())
+    }
+
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        Ok(
+            self.store
+                .read()
+                .await
+                .contains_key(name)
+        )
+    }
+
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
         self.store
             .write()
             .await
@@ -283,6 +1136,8 @@ impl StoreBackend for InMemoryStore {
             return Err(DocumentStoreError::CollectionNotFound(name.to_string()));
         }
 
+        self.expiring.remove(name).await;
+        self.indexes.remove_collection(name).await;
         Ok(())
     }
 
@@ -305,13 +1160,25 @@ impl StoreBackend for InMemoryStore {
             None => return Err(DocumentStoreError::CollectionNotFound(collection.to_string())),
         };
 
-        // Add the field to every document in the collection
-        for doc in collection_map.values_mut() {
+        // Add the field to every document in a copy of the collection, so a unique index
+        // violation leaves it untouched
+        let mut updated = collection_map.clone();
+
+        for doc in updated.values_mut() {
             if let Some(doc_map) = doc.as_document_mut() {
                 doc_map.insert(field.to_string(), default.clone());
             }
         }
 
+        self.indexes.check_unique(
+            None,
+            updated.iter().map(|(key, doc)| (key.clone(), Some(doc))),
+            collection,
+        )
+        .await?;
+        *collection_map = updated;
+        self.indexes.rebuild(collection, Some(collection_map)).await;
+
         Ok(())
     }
 
@@ -323,13 +1190,25 @@ impl StoreBackend for InMemoryStore {
             None => return Err(DocumentStoreError::CollectionNotFound(collection.to_string())),
         };
 
-        // Remove the field from every document in the collection
-        for doc in collection_map.values_mut() {
+        // Remove the field from every document in a copy of the collection, so a unique index
+        // violation leaves it untouched
+        let mut updated = collection_map.clone();
+
+        for doc in updated.values_mut() {
             if let Some(doc_map) = doc.as_document_mut() {
                 doc_map.remove(field);
             }
         }
 
+        self.indexes.check_unique(
+            None,
+            updated.iter().map(|(key, doc)| (key.clone(), Some(doc))),
+            collection,
+        )
+        .await?;
+        *collection_map = updated;
+        self.indexes.rebuild(collection, Some(collection_map)).await;
+
         Ok(())
     }
 
@@ -341,8 +1220,11 @@ impl StoreBackend for InMemoryStore {
             None => return Err(DocumentStoreError::CollectionNotFound(collection.to_string())),
         };
 
-        // Rename the field in every document in the collection
-        for doc in collection_map.values_mut() {
+        // Rename the field in every document in a copy of the collection, so a unique index
+        // violation leaves it untouched
+        let mut updated = collection_map.clone();
+
+        for doc in updated.values_mut() {
             if let Some(doc_map) = doc.as_document_mut() {
                 if let Some(value) = doc_map.remove(field) {
                     doc_map.insert(new.to_string(), value);
@@ -350,26 +1232,53 @@ impl StoreBackend for InMemoryStore {
             }
         }
 
+        self.indexes.check_unique(
+            None,
+            updated.iter().map(|(key, doc)| (key.clone(), Some(doc))),
+            collection,
+        )
+        .await?;
+        *collection_map = updated;
+        self.indexes.rebuild(collection, Some(collection_map)).await;
+
         Ok(())
     }
 
-    async fn add_index(&self, _collection: &str, _field: &str, _unique: bool) -> DocumentStoreResult<()> {
-        // In-memory store does not support indexing (no-op)
+    async fn add_index(&self, collection: &str, field: &str, unique: bool) -> DocumentStoreResult<()> {
+        let store = self.store.read().await;
+
+        // Like MongoDB, a unique index can't be added to a collection already violating it
+        if unique {
+            check_unique(&[(field, None)], store.get(collection), [], collection)?;
+        }
+
+        self.indexes.add(collection, field, unique, store.get(collection)).await;
         Ok(())
     }
 
-    async fn drop_index(&self, _collection: &str, _field: &str) -> DocumentStoreResult<()> {
-        // In-memory store does not support indexing (no-op)
+    async fn drop_index(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        self.indexes.remove(collection, field).await;
         Ok(())
     }
+
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        // Expired documents are deleted by `sweep_expired`
+        self.expiring.insert(collection, field).await;
+        Ok(())
+    }
+
+    async fn begin_transaction(&self) -> DocumentStoreResult<Transaction> {
+        Ok(Transaction::new(InMemoryTransaction::begin(self).await))
+    }
+
+    fn encoding(&self) -> &Encoding {
+        &self.encoding
+    }
 }
 
 
 /// Builder for constructing [`InMemoryStore`] instances.
 ///
-/// Currently a no-op builder, but can be extended in future versions
-/// to support configuration options like capacity hints or concurrency settings.
-///
 /// # Example
 ///
 /// ```ignore
@@ -381,8 +1290,47 @@ impl StoreBackend for InMemoryStore {
 ///     let store = InMemoryStore::builder().build().await.unwrap();
 /// }
 /// ```
-#[derive(Default)]
-pub struct InMemoryStoreBuilder;
+#[derive(Default, Debug, Clone)]
+pub struct InMemoryStoreBuilder {
+    comparators: SortComparators,
+    operators: CustomOperators,
+    encoding: Encoding,
+}
+
+impl InMemoryStoreBuilder {
+    /// Orders a field with a custom comparator when queries sort by it.
+    ///
+    /// # Arguments
+    ///
+    /// * `collection` - The collection holding the field
+    /// * `field` - The field, in dot notation for embedded fields
+    /// * `comparator` - The ordering of the field's values
+    pub fn with_comparator(mut self, collection: impl Into<String>, field: impl Into<String>, comparator: SortComparator) -> Self {
+        self.comparators.insert(collection, field, comparator);
+        self
+    }
+
+    /// Registers the handler of a custom filter operator, used by `Expr::Custom` filters
+    /// naming it.
+    ///
+    /// The in-memory store evaluates operators on each document, so the handler must be a
+    /// [`CustomOperator::Evaluate`].
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name filters refer to the operator by
+    /// * `operator` - The handler deciding whether a document matches
+    pub fn with_operator(mut self, name: impl Into<String>, operator: CustomOperator) -> Self {
+        self.operators.insert(name, operator);
+        self
+    }
+
+    /// Sets the representation of UUIDs, enums and datetimes in stored documents.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+}
 
 #[async_trait]
 impl StoreBackendBuilder for InMemoryStoreBuilder {
@@ -392,6 +1340,11 @@ impl StoreBackendBuilder for InMemoryStoreBuilder {
     ///
     /// This always succeeds and returns a freshly initialized store.
     async fn build(self) -> DocumentStoreResult<Self::Backend> {
-        Ok(InMemoryStore::new())
+        Ok(InMemoryStore {
+            comparators: Arc::new(self.comparators),
+            operators: Arc::new(self.operators),
+            encoding: Arc::new(self.encoding),
+            ..InMemoryStore::new()
+        })
     }
 }
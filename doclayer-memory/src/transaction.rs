@@ -0,0 +1,179 @@
+//! Transactions of the in-memory store.
+//!
+//! An [`InMemoryTransaction`] runs its operations on a private copy of the store, taken when
+//! it begins. Committing compares the copy with the snapshot it started from and applies the
+//! documents that changed to the store, all under one write lock, so other readers see
+//! either none or all of the transaction's writes.
+//!
+//! Transactions are optimistic: a commit fails with [`DocumentStoreError::Conflict`],
+//! applying nothing, if another writer changed a document the transaction also changed
+//! since the transaction began, and with [`DocumentStoreError::UniqueViolation`] if another
+//! writer took a value of a unique field the transaction wrote.
+
+use std::{collections::{HashMap, HashSet}, sync::Arc};
+use async_trait::async_trait;
+use mea::rwlock::RwLock;
+use bson::Bson;
+
+use doclayer_core::{
+    backend::DynStoreBackend,
+    error::{DocumentStoreError, DocumentStoreResult},
+    transaction::TransactionBackend,
+};
+
+use crate::store::{CollectionMap, InMemoryStore, StoreMap};
+
+
+/// A transaction of an [`InMemoryStore`], begun with
+/// [`begin_transaction`](doclayer_core::backend::StoreBackend::begin_transaction).
+///
+/// Beginning a transaction copies the whole store, with its indexes, so transactions suit the
+/// small datasets the in-memory store is meant for. Indexes added or dropped in a transaction
+/// only apply to its copy.
+#[derive(Debug)]
+pub struct InMemoryTransaction {
+    /// The store the transaction commits to
+    store: InMemoryStore,
+    /// The store's documents when the transaction began
+    snapshot: StoreMap,
+    /// The store's revision when the transaction began
+    revision: Option<String>,
+    /// The copy of the store the transaction's operations run on
+    working: InMemoryStore,
+}
+
+/// The writes of a transaction to one collection.
+enum CollectionChange {
+    /// The collection was dropped
+    Dropped,
+    /// The given documents were written, or deleted where `None`
+    Written(Vec<(String, Option<Bson>)>),
+}
+
+impl InMemoryTransaction {
+    /// Begins a transaction on a snapshot of the store.
+    pub(crate) async fn begin(store: &InMemoryStore) -> Self {
+        // The indexes are copied under the same lock as the documents, so they match
+        let (snapshot, indexes) = {
+            let documents = store.store.read().await;
+            (documents.clone(), store.indexes.snapshot().await)
+        };
+        let revision = store.current_revision.read().await.clone();
+
+        let working = InMemoryStore {
+            store: Arc::new(RwLock::new(snapshot.clone())),
+            current_revision: Arc::new(RwLock::new(revision.clone())),
+            indexes,
+            ..store.clone()
+        };
+
+        Self { store: store.clone(), snapshot, revision, working }
+    }
+
+    /// Computes the writes of the transaction to a collection, checking that the store still
+    /// holds the documents they replace.
+    fn change(
+        &self,
+        name: &str,
+        before: Option<&CollectionMap>,
+        after: Option<&CollectionMap>,
+        current: Option<&CollectionMap>,
+    ) -> DocumentStoreResult<Option<CollectionChange>> {
+        let empty = CollectionMap::new();
+        let (before_map, after_map, current_map) = (before.unwrap_or(&empty), after.unwrap_or(&empty), current.unwrap_or(&empty));
+
+        let unchanged_since = |key: &String| current_map.get(key) == before_map.get(key);
+
+        match (before, after) {
+            (None, None) => Ok(None),
+            // Dropping a collection removes every document in it, so none may have changed
+            (Some(_), None) => match current_map.keys().chain(before_map.keys()).find(|key| !unchanged_since(key)) {
+                Some(key) => Err(DocumentStoreError::Conflict(key.clone(), name.to_string())),
+                None => Ok(Some(CollectionChange::Dropped)),
+            },
+            _ => {
+                let mut written = Vec::new();
+
+                for key in before_map.keys().chain(after_map.keys()).collect::<HashSet<_>>() {
+                    let document = after_map.get(key);
+
+                    if before_map.get(key) == document {
+                        continue;
+                    }
+                    if !unchanged_since(key) {
+                        return Err(DocumentStoreError::Conflict(key.clone(), name.to_string()));
+                    }
+
+                    written.push((key.clone(), document.cloned()));
+                }
+
+                // A collection created by the transaction is created even if left empty
+                Ok((before.is_none() || !written.is_empty()).then_some(CollectionChange::Written(written)))
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl TransactionBackend for InMemoryTransaction {
+    fn backend(&self) -> &dyn DynStoreBackend {
+        &self.working
+    }
+
+    async fn commit(self: Box<Self>) -> DocumentStoreResult<()> {
+        let working = self.working.store.read().await;
+        let mut store = self.store.store.write().await;
+
+        // Every change is checked before any is applied, so a conflict applies nothing
+        let mut changes = HashMap::new();
+        for name in self.snapshot.keys().chain(working.keys()).collect::<HashSet<_>>() {
+            if let Some(change) = self.change(name, self.snapshot.get(name), working.get(name), store.get(name))? {
+                // Writes committed since the transaction began may take the values it wrote
+                if let CollectionChange::Written(documents) = &change {
+                    self.store.indexes.check_unique(
+                        store.get(name),
+                        documents.iter().map(|(key, document)| (key.clone(), document.as_ref())),
+                        name,
+                    )
+                    .await?;
+                }
+
+                changes.insert(name.clone(), change);
+            }
+        }
+
+        for (name, change) in changes {
+            match change {
+                CollectionChange::Dropped => {
+                    store.remove(&name);
+                    self.store.indexes.remove_collection(&name).await;
+                },
+                CollectionChange::Written(documents) => {
+                    let collection_map = store.entry(name.clone()).or_default();
+                    let mut keys = Vec::with_capacity(documents.len());
+
+                    for (key, document) in documents {
+                        match document {
+                            Some(document) => collection_map.insert(key.clone(), document),
+                            None => collection_map.remove(&key),
+                        };
+                        keys.push(key);
+                    }
+
+                    self.store.indexes.update(&name, Some(collection_map), keys).await;
+                },
+            }
+        }
+
+        let revision = self.working.current_revision.read().await;
+        if *revision != self.revision {
+            *self.store.current_revision.write().await = revision.clone();
+        }
+
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) -> DocumentStoreResult<()> {
+        Ok(())
+    }
+}
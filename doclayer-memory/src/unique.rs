@@ -0,0 +1,142 @@
+//! Unique index constraints of the in-memory store.
+//!
+//! Every write to a collection with unique indexes is checked against its documents. The
+//! values written are looked up in the index on each field, and only the documents holding
+//! them, or holding values the index leaves out, are compared; without an index, the
+//! collection is scanned. Values compare like MongoDB's unique indexes: numbers of different
+//! types but equal value are equal, each element of an array is a value of its own, and a
+//! missing field counts as `null`, so two documents without the field violate the index.
+
+use std::collections::HashMap;
+use bson::Bson;
+
+use doclayer_core::error::{DocumentStoreError, DocumentStoreResult};
+
+use crate::{
+    evaluator::{resolve_path, value_key},
+    index::FieldIndex,
+    store::CollectionMap,
+};
+
+/// The value a missing field counts as.
+static NULL: Bson = Bson::Null;
+
+
+/// Checks that writing documents to a collection leaves no two of its documents with the same
+/// value of a unique field.
+///
+/// `fields` holds each unique field with the index on it, if it's to be used. `written` holds
+/// the documents being written by key, or `None` for those being deleted, and the later of two
+/// writes to a key wins. The other documents of `collection_map` stay as stored.
+///
+/// # Errors
+///
+/// Returns [`DocumentStoreError::UniqueViolation`] naming the first field the write would
+/// duplicate a value of.
+pub(crate) fn check_unique<'a>(
+    fields: &[(&str, Option<&FieldIndex>)],
+    collection_map: Option<&CollectionMap>,
+    written: impl IntoIterator<Item = (String, Option<&'a Bson>)>,
+    collection: &str,
+) -> DocumentStoreResult<()> {
+    if fields.is_empty() {
+        return Ok(());
+    }
+
+    let written = written
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+    for (field, index) in fields {
+        let unique = match (index, collection_map) {
+            (Some(index), Some(collection_map)) => check_indexed(field, index, collection_map, &written),
+            _ => check_scanned(field, collection_map, &written),
+        };
+
+        if !unique {
+            return Err(DocumentStoreError::UniqueViolation(field.to_string(), collection.to_string()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns `true` if no two documents hold the same value of a field, comparing the written
+/// documents with the stored ones the index finds holding their values.
+fn check_indexed(
+    field: &str,
+    index: &FieldIndex,
+    collection_map: &CollectionMap,
+    written: &HashMap<String, Option<&Bson>>,
+) -> bool {
+    let mut owners = HashMap::new();
+
+    for (key, document) in written {
+        let Some(document) = document else {
+            continue;
+        };
+
+        for (value_key, value) in index_values(document, field) {
+            let stored_owner = index
+                .holders(value)
+                .filter(|owner| !written.contains_key(*owner))
+                .filter_map(|owner| collection_map.get(owner))
+                .any(|stored| index_values(stored, field).contains_key(&value_key));
+
+            if stored_owner || owners.insert(value_key, key).is_some() {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+/// Returns `true` if no two documents hold the same value of a field, comparing the written
+/// documents with every stored one.
+fn check_scanned(
+    field: &str,
+    collection_map: Option<&CollectionMap>,
+    written: &HashMap<String, Option<&Bson>>,
+) -> bool {
+    let documents = written
+        .iter()
+        .filter_map(|(key, document)| document.map(|document| (key.as_str(), document)))
+        .chain(
+            collection_map
+                .into_iter()
+                .flatten()
+                .filter(|(key, _)| !written.contains_key(*key))
+                .map(|(key, document)| (key.as_str(), document))
+        );
+    let mut owners = HashMap::new();
+
+    for (key, document) in documents {
+        for value_key in index_values(document, field).into_keys() {
+            if owners.insert(value_key, key).is_some() {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+/// Returns the values a document holds for a unique field, each once, by their
+/// [`value_key`].
+fn index_values<'d>(document: &'d Bson, field: &str) -> HashMap<String, &'d Bson> {
+    let values = resolve_path(document, field);
+
+    if values.is_empty() {
+        return HashMap::from([(value_key(&NULL), &NULL)]);
+    }
+
+    values
+        .into_iter()
+        .flat_map(|value| match value {
+            Bson::Array(items) if !items.is_empty() => items.iter().collect::<Vec<_>>(),
+            other => vec![other],
+        })
+        .map(|value| (value_key(value), value))
+        .collect()
+}
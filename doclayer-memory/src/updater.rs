@@ -0,0 +1,152 @@
+//! Partial update application for in-memory documents.
+//!
+//! This module applies [`Update`] operations to BSON documents in place,
+//! mirroring the semantics of MongoDB's update operators.
+
+use bson::{Bson, Document};
+
+use doclayer_core::{
+    update::{Update, UpdateOp},
+    error::{DocumentStoreError, DocumentStoreResult},
+};
+
+use crate::evaluator::Comparable;
+
+
+/// Applies [`Update`] operations to a single BSON document.
+///
+/// Field names may use dot notation to reach into embedded documents. Intermediate
+/// documents are created as needed by `Set`, `Inc` and `Push`, while `Unset` and `Pull`
+/// leave the document untouched when the path doesn't exist.
+pub struct DocumentUpdater<'a> {
+    document: &'a mut Document,
+}
+
+impl<'a> DocumentUpdater<'a> {
+    pub fn new(document: &'a mut Document) -> Self {
+        Self { document }
+    }
+
+    /// Applies every operation of the update in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::InvalidDocument`] if an operation targets a field of
+    /// the wrong type (e.g. incrementing a string or pushing onto a non-array).
+    pub fn apply(&mut self, update: &Update) -> DocumentStoreResult<()> {
+        for op in &update.ops {
+            self.apply_op(op)?;
+        }
+        Ok(())
+    }
+
+    fn apply_op(&mut self, op: &UpdateOp) -> DocumentStoreResult<()> {
+        match op {
+            UpdateOp::Set(field, value) => {
+                let (parent, key) = Self::resolve(self.document, field, true)?
+                    .expect("parent is created when missing");
+                parent.insert(key, value.clone());
+            },
+            UpdateOp::Unset(field) => {
+                if let Some((parent, key)) = Self::resolve(self.document, field, false)? {
+                    parent.remove(key);
+                }
+            },
+            UpdateOp::Inc(field, amount) => {
+                let (parent, key) = Self::resolve(self.document, field, true)?
+                    .expect("parent is created when missing");
+                let current = parent.get(key).cloned().unwrap_or(Bson::Int32(0));
+                parent.insert(key, Self::add(field, &current, amount)?);
+            },
+            UpdateOp::Push(field, value) => {
+                let (parent, key) = Self::resolve(self.document, field, true)?
+                    .expect("parent is created when missing");
+                match parent.get_mut(key) {
+                    Some(Bson::Array(array)) => array.push(value.clone()),
+                    Some(_) => return Err(DocumentStoreError::InvalidDocument(
+                        format!("Cannot push to non-array field '{}'", field)
+                    )),
+                    None => {
+                        parent.insert(key, Bson::Array(vec![value.clone()]));
+                    },
+                }
+            },
+            UpdateOp::Pull(field, value) => {
+                if let Some((parent, key)) = Self::resolve(self.document, field, false)? {
+                    match parent.get_mut(key) {
+                        Some(Bson::Array(array)) => array.retain(|item| {
+                            Comparable::from(item) != Comparable::from(value)
+                        }),
+                        Some(_) => return Err(DocumentStoreError::InvalidDocument(
+                            format!("Cannot pull from non-array field '{}'", field)
+                        )),
+                        None => {},
+                    }
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Resolves a dotted field path to its parent document and final key.
+    ///
+    /// Returns `None` if an intermediate document is missing and `create` is `false`.
+    fn resolve<'d, 'f>(
+        document: &'d mut Document,
+        field: &'f str,
+        create: bool,
+    ) -> DocumentStoreResult<Option<(&'d mut Document, &'f str)>> {
+        let mut segments = field.split('.').collect::<Vec<_>>();
+        let key = segments.pop().expect("split yields at least one segment");
+        let mut current = document;
+
+        for segment in segments {
+            if !current.contains_key(segment) {
+                if !create {
+                    return Ok(None);
+                }
+                current.insert(segment, Document::new());
+            }
+
+            current = match current.get_mut(segment) {
+                Some(Bson::Document(inner)) => inner,
+                _ => return Err(DocumentStoreError::InvalidDocument(
+                    format!("Cannot traverse non-document field '{}' in path '{}'", segment, field)
+                )),
+            };
+        }
+
+        Ok(Some((current, key)))
+    }
+
+    fn add(field: &str, current: &Bson, amount: &Bson) -> DocumentStoreResult<Bson> {
+        match (current, amount) {
+            (Bson::Int32(a), Bson::Int32(b)) => Ok(
+                a.checked_add(*b)
+                    .map(Bson::Int32)
+                    .unwrap_or(Bson::Int64(*a as i64 + *b as i64))
+            ),
+            (Bson::Int32(a), Bson::Int64(b)) => Self::add_longs(field, *a as i64, *b),
+            (Bson::Int64(a), Bson::Int32(b)) => Self::add_longs(field, *a, *b as i64),
+            (Bson::Int64(a), Bson::Int64(b)) => Self::add_longs(field, *a, *b),
+            (Bson::Double(a), Bson::Int32(b)) => Ok(Bson::Double(a + *b as f64)),
+            (Bson::Double(a), Bson::Int64(b)) => Ok(Bson::Double(a + *b as f64)),
+            (Bson::Int32(a), Bson::Double(b)) => Ok(Bson::Double(*a as f64 + b)),
+            (Bson::Int64(a), Bson::Double(b)) => Ok(Bson::Double(*a as f64 + b)),
+            (Bson::Double(a), Bson::Double(b)) => Ok(Bson::Double(a + b)),
+            _ => Err(DocumentStoreError::InvalidDocument(
+                format!("Cannot increment non-numeric field '{}'", field)
+            )),
+        }
+    }
+
+    /// Adds two longs, failing like MongoDB's `$inc` when the sum overflows.
+    fn add_longs(field: &str, a: i64, b: i64) -> DocumentStoreResult<Bson> {
+        a.checked_add(b)
+            .map(Bson::Int64)
+            .ok_or_else(|| DocumentStoreError::InvalidDocument(
+                format!("Incrementing field '{}' overflows a 64-bit integer", field)
+            ))
+    }
+}
@@ -0,0 +1,67 @@
+//! Audits writes by ID to documents that don't hold their ID, which the audited store records
+//! by the IDs they were written by.
+
+use bson::{Bson, Uuid, doc};
+
+use doclayer_core::{
+    audit::{AUDIT_COLLECTION, AuditAction, AuditEntry, AuditedStore},
+    backend::{InsertPolicy, MissingDocumentPolicy, StoreBackend},
+    document::DocumentExt,
+    error::DocumentStoreError,
+    query::Query,
+};
+use doclayer_memory::InMemoryStore;
+
+/// Returns the recorded entries of a document.
+async fn entries(store: &AuditedStore<InMemoryStore>, id: Uuid) -> Vec<AuditEntry> {
+    store
+        .query_documents(Query::default(), AUDIT_COLLECTION)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|entry| AuditEntry::from_bson(entry).unwrap())
+        .filter(|entry| entry.document_id == id)
+        .collect()
+}
+
+#[tokio::test]
+async fn writes_by_id_are_recorded_without_an_id_field() {
+    let store = AuditedStore::new(InMemoryStore::new());
+    let id = Uuid::new();
+
+    store.insert_documents(vec![(id, Bson::Document(doc! { "name": "a" }))], "things", InsertPolicy::ErrorOnConflict).await.unwrap();
+    store.insert_documents(vec![(id, Bson::Document(doc! { "name": "b" }))], "things", InsertPolicy::Replace).await.unwrap();
+    store.insert_documents(vec![(id, Bson::Document(doc! { "name": "c" }))], "things", InsertPolicy::Skip).await.unwrap();
+    store.update_documents(vec![(id, Bson::Document(doc! { "name": "d" }))], "things", MissingDocumentPolicy::Error).await.unwrap();
+    store.upsert_documents(vec![(id, Bson::Document(doc! { "name": "e" }))], "things").await.unwrap();
+    store.delete_documents(vec![id], "things").await.unwrap();
+
+    // Entries of the same millisecond have no order, so they're compared by the name written
+    let mut actions = entries(&store, id)
+        .await
+        .into_iter()
+        .map(|entry| (entry.action, entry.after.get_str("name").ok().map(str::to_string)))
+        .collect::<Vec<_>>();
+    actions.sort_by_key(|(_, name)| name.clone());
+
+    assert_eq!(actions, vec![
+        (AuditAction::Delete, None),
+        (AuditAction::Insert, Some("a".to_string())),
+        (AuditAction::Update, Some("b".to_string())),
+        (AuditAction::Update, Some("d".to_string())),
+        (AuditAction::Update, Some("e".to_string())),
+    ]);
+}
+
+#[tokio::test]
+async fn updating_a_missing_document_is_not_found() {
+    let store = AuditedStore::new(InMemoryStore::new());
+    let id = Uuid::new();
+
+    store.insert_documents(vec![(Uuid::new(), Bson::Document(doc! { "name": "a" }))], "things", InsertPolicy::ErrorOnConflict).await.unwrap();
+
+    let result = store.update_documents(vec![(id, Bson::Document(doc! { "name": "b" }))], "things", MissingDocumentPolicy::Error).await;
+
+    assert!(matches!(result, Err(DocumentStoreError::DocumentNotFound(..))), "{result:?}");
+    assert!(entries(&store, id).await.is_empty());
+}
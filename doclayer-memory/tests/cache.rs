@@ -0,0 +1,68 @@
+//! Checks that a cached store stops serving documents once their expiry has passed, although
+//! the back deletes them without the cache seeing it.
+
+use std::time::Duration;
+
+use bson::{Bson, Uuid, doc};
+
+use doclayer_core::{
+    backend::{InsertPolicy, StoreBackend},
+    cache::CachedStore,
+    expiry::expires_at,
+};
+use doclayer_memory::InMemoryStore;
+
+fn session(id: Uuid, ttl: Duration) -> (Uuid, Bson) {
+    (id, Bson::Document(doc! { "id": id.to_string(), "expires_at": expires_at(ttl) }))
+}
+
+#[tokio::test]
+async fn cached_store_drops_documents_once_they_expire() {
+    let store = CachedStore::new(InMemoryStore::new(), InMemoryStore::new());
+    let (expiring, lasting) = (Uuid::new(), Uuid::new());
+
+    store
+        .expire_documents("sessions", "expires_at")
+        .await
+        .unwrap();
+    store
+        .insert_documents(
+            vec![session(expiring, Duration::from_millis(50)), session(lasting, Duration::from_secs(3600))],
+            "sessions",
+            InsertPolicy::ErrorOnConflict,
+        )
+        .await
+        .unwrap();
+
+    // Both are cached while they last
+    assert_eq!(store.get_documents(vec![expiring, lasting], "sessions").await.unwrap().len(), 2);
+    assert_eq!(store.front().get_documents(vec![expiring, lasting], "sessions").await.unwrap().len(), 2);
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(store.back().sweep_expired().await.unwrap(), 1);
+
+    let documents = store.get_documents(vec![expiring, lasting], "sessions").await.unwrap();
+    assert_eq!(documents.len(), 1);
+    assert_eq!(documents[0].as_document().unwrap().get_str("id").unwrap(), lasting.to_string());
+    assert_eq!(store.front().get_documents(vec![expiring], "sessions").await.unwrap(), Vec::<Bson>::new());
+}
+
+#[tokio::test]
+async fn cached_store_does_not_cache_expired_documents() {
+    let store = CachedStore::new(InMemoryStore::new(), InMemoryStore::new());
+    let id = Uuid::new();
+
+    store
+        .expire_documents("sessions", "expires_at")
+        .await
+        .unwrap();
+    store
+        .back()
+        .insert_documents(vec![session(id, Duration::ZERO)], "sessions", InsertPolicy::ErrorOnConflict)
+        .await
+        .unwrap();
+
+    // Until the back sweeps it, the back still returns the document
+    assert_eq!(store.get_documents(vec![id], "sessions").await.unwrap().len(), 1);
+    assert_eq!(store.front().get_documents(vec![id], "sessions").await.unwrap(), Vec::<Bson>::new());
+}
@@ -0,0 +1,43 @@
+//! Creates the same collection concurrently, as replicas starting at once do: exactly one
+//! creator succeeds, the other is told the collection exists, and ensuring it succeeds for
+//! both.
+
+mod common;
+
+use doclayer_core::{
+    backend::{StoreBackend, StoreBackendBuilder},
+    error::DocumentStoreError,
+};
+use doclayer_memory::{FileStore, InMemoryStore};
+
+async fn check_concurrent_creation(backend: &impl StoreBackend) {
+    let (first, second) = tokio::join!(backend.create_collection("things"), backend.create_collection("things"));
+
+    let created = [&first, &second]
+        .iter()
+        .filter(|result| result.is_ok())
+        .count();
+    assert_eq!(created, 1, "{first:?}, {second:?}");
+    assert!(
+        [&first, &second]
+            .iter()
+            .any(|result| matches!(result, Err(DocumentStoreError::CollectionAlreadyExists(name)) if name == "things")),
+        "{first:?}, {second:?}"
+    );
+    assert!(backend.collection_exists("things").await.unwrap());
+
+    let (first, second) = tokio::join!(backend.ensure_collection("others"), backend.ensure_collection("others"));
+    assert!(first.is_ok() && second.is_ok(), "{first:?}, {second:?}");
+}
+
+#[tokio::test]
+async fn memory_creates_a_collection_once() {
+    check_concurrent_creation(&InMemoryStore::new()).await;
+}
+
+#[tokio::test]
+async fn file_creates_a_collection_once() {
+    let dir = common::scratch_dir();
+
+    check_concurrent_creation(&FileStore::builder(dir.path()).build().await.unwrap()).await;
+}
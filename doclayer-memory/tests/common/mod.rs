@@ -0,0 +1,8 @@
+//! Helpers shared by the integration tests.
+
+use tempfile::TempDir;
+
+/// Returns a directory no other test uses, for a file store, deleted when dropped.
+pub fn scratch_dir() -> TempDir {
+    TempDir::with_prefix("doclayer-").unwrap()
+}
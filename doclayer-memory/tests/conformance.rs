@@ -0,0 +1,14 @@
+//! Runs the backend conformance suite against the in-memory and file stores, and against
+//! wrappers layered over the in-memory store.
+
+mod common;
+
+use doclayer_core::{audit::AuditedStore, backend::StoreBackendBuilder, cache::CachedStore, mirror::MirroredStore};
+use doclayer_memory::{FileStore, InMemoryStore};
+use doclayer_test::backend_conformance;
+
+backend_conformance!(memory, InMemoryStore::new());
+backend_conformance!(file, let dir = common::scratch_dir(); FileStore::builder(dir.path()).build().await.unwrap());
+backend_conformance!(cached, CachedStore::new(InMemoryStore::new(), InMemoryStore::new()));
+backend_conformance!(mirrored, MirroredStore::new(InMemoryStore::new(), InMemoryStore::new()));
+backend_conformance!(audited, AuditedStore::new(InMemoryStore::new()));
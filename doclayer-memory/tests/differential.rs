@@ -0,0 +1,37 @@
+//! Runs generated filters against the in-memory and file stores, which must match the same
+//! documents for every one of them.
+
+mod common;
+
+use doclayer_core::{backend::StoreBackendBuilder, query::FieldOp};
+use doclayer_memory::{FileStore, InMemoryStore};
+use doclayer_test::Differential;
+
+#[tokio::test]
+async fn file_filters_like_memory() {
+    let dir = common::scratch_dir();
+    let memory = InMemoryStore::new();
+    let file = FileStore::builder(dir.path()).build().await.unwrap();
+
+    for seed in [1, 7, 42] {
+        Differential::new(seed)
+            .run(&memory, &file)
+            .await
+            .unwrap()
+            .assert_consistent();
+    }
+}
+
+#[tokio::test]
+async fn file_filters_membership_like_memory() {
+    let dir = common::scratch_dir();
+    let memory = InMemoryStore::new();
+    let file = FileStore::builder(dir.path()).build().await.unwrap();
+
+    Differential::new(3)
+        .with_operators([FieldOp::AnyOf, FieldOp::NoneOf])
+        .run(&memory, &file)
+        .await
+        .unwrap()
+        .assert_consistent();
+}
@@ -0,0 +1,95 @@
+//! Checks `register_documents!`: types sharing a collection by accident conflict, while
+//! variants with distinct discriminators share one and are registered as variants.
+
+use bson::{Uuid, doc};
+use serde::{Deserialize, Serialize};
+
+use doclayer_core::{
+    document::{CollectionConflict, Document},
+    register_documents,
+};
+
+macro_rules! document {
+    ($name:ident, $collection:literal) => {
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        struct $name {
+            id: Uuid,
+        }
+
+        impl Document for $name {
+            fn id(&self) -> &Uuid {
+                &self.id
+            }
+
+            fn collection_name() -> &'static str {
+                $collection
+            }
+        }
+    };
+}
+
+document!(User, "users");
+document!(Account, "users");
+document!(Shape, "shapes");
+document!(Circle, "shapes");
+document!(Square, "shapes");
+document!(Triangle, "shapes");
+
+#[test]
+fn document_types_conflict_when_sharing_a_collection_by_accident() {
+    let documents = register_documents!(User, Account, Circle);
+
+    assert_eq!(
+        documents.validate(),
+        Err(vec![CollectionConflict {
+            collection: "users",
+            types: vec![std::any::type_name::<User>(), std::any::type_name::<Account>()],
+        }])
+    );
+}
+
+#[test]
+fn document_types_accept_variants_with_distinct_discriminators() {
+    let documents = register_documents!(
+        User,
+        Shape,
+        Circle { "kind" => "circle" },
+        Square { "kind" => "square" },
+    );
+    assert_eq!(documents.validate(), Ok(()));
+
+    // Variants are told apart by their discriminator, other shapes fall back to the plain type
+    let registry = documents.registry();
+    let id = Uuid::new();
+    let circle = registry
+        .deserialize("shapes", doc! { "id": id, "kind": "circle" }.into())
+        .unwrap();
+    let square = registry
+        .deserialize("shapes", doc! { "id": id, "kind": "square" }.into())
+        .unwrap();
+    let other = registry
+        .deserialize("shapes", doc! { "id": id, "kind": "hexagon" }.into())
+        .unwrap();
+
+    assert!(circle.downcast_ref::<Circle>().is_some());
+    assert!(square.downcast_ref::<Square>().is_some());
+    assert!(other.downcast_ref::<Shape>().is_some());
+}
+
+#[test]
+fn document_types_conflict_when_variants_share_a_discriminator() {
+    let documents = register_documents!(
+        Circle { "kind" => "round" },
+        Square { "kind" => "square" },
+        Triangle { "kind" => "round" },
+    );
+
+    assert_eq!(
+        documents.validate(),
+        Err(vec![CollectionConflict {
+            collection: "shapes",
+            types: vec![std::any::type_name::<Circle>(), std::any::type_name::<Triangle>()],
+        }])
+    );
+    assert_eq!(documents.types()[0].discriminator(), Some(("kind", &bson::Bson::from("round"))));
+}
@@ -0,0 +1,70 @@
+//! Gets documents by ID in several chunks: every document is returned once, even when its ID
+//! is repeated in another chunk.
+
+use bson::{Bson, Uuid, doc};
+
+use doclayer_core::store::DocumentStore;
+use doclayer_test::Mock;
+
+#[tokio::test]
+async fn get_splits_ids_into_chunks() {
+    let mock = Mock::new();
+    let store = DocumentStore::new(mock.clone().wrap_in_memory().await.unwrap());
+    let ids = (0..5).map(|_| Uuid::new()).collect::<Vec<_>>();
+
+    store
+        .collection("things")
+        .insert(
+            ids.iter()
+                .enumerate()
+                .map(|(index, id)| (*id, Bson::Document(doc! { "_id": *id, "index": index as i32 })))
+                .collect(),
+        )
+        .await
+        .unwrap();
+
+    // Five IDs and a missing one, in chunks of two
+    let mut requested = ids.clone();
+    requested.push(Uuid::new());
+    let documents = store
+        .collection("things")
+        .with_get_chunk_size(2)
+        .get(requested)
+        .await
+        .unwrap();
+
+    assert_eq!(documents.len(), 5);
+    mock.assert_called("get_documents", 3);
+}
+
+#[tokio::test]
+async fn get_returns_a_document_requested_in_two_chunks_once() {
+    let mock = Mock::new();
+    let store = DocumentStore::new(mock.clone().wrap_in_memory().await.unwrap());
+    let ids = (0..3).map(|_| Uuid::new()).collect::<Vec<_>>();
+
+    store
+        .collection("things")
+        .insert(
+            ids.iter()
+                .map(|id| (*id, Bson::Document(doc! { "_id": *id })))
+                .collect(),
+        )
+        .await
+        .unwrap();
+
+    // The first ID would be in the first and last chunks
+    let documents = store
+        .collection("things")
+        .with_get_chunk_size(2)
+        .get(vec![ids[0], ids[1], ids[2], ids[0]])
+        .await
+        .unwrap();
+
+    let returned = documents
+        .iter()
+        .map(|document| document.as_document().unwrap().get("_id").cloned().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(returned, ids.iter().map(|id| Bson::from(*id)).collect::<Vec<_>>());
+    mock.assert_called("get_documents", 2);
+}
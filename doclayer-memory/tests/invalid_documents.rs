@@ -0,0 +1,52 @@
+//! Filters collections holding values that aren't documents, which fails every operation
+//! evaluating a filter with `InvalidDocument` rather than skipping the value.
+
+use bson::{Bson, Uuid, doc};
+
+use doclayer_core::{
+    backend::{InsertPolicy, StoreBackend},
+    error::{DocumentStoreError, DocumentStoreResult},
+    query::{Filter, Query},
+    update::UpdateBuilder,
+};
+use doclayer_memory::InMemoryStore;
+
+/// Returns a store whose `things` collection holds a document and `value`.
+async fn store_with(value: Bson) -> InMemoryStore {
+    let store = InMemoryStore::new();
+
+    store.insert_documents(
+        vec![(Uuid::new(), Bson::Document(doc! { "name": "a" })), (Uuid::new(), value)],
+        "things",
+        InsertPolicy::ErrorOnConflict,
+    ).await.unwrap();
+
+    store
+}
+
+fn assert_invalid<T: std::fmt::Debug>(result: DocumentStoreResult<T>) {
+    assert!(matches!(result, Err(DocumentStoreError::InvalidDocument(_))), "{result:?}");
+}
+
+async fn check_filters_fail(value: Bson) {
+    let store = store_with(value).await;
+    let query = || Query::builder().filter(Filter::eq("name", "a")).build();
+
+    assert_invalid(store.query_documents(query(), "things").await);
+    assert_invalid(store.count_documents(query(), "things").await);
+    assert_invalid(store.update_by_query(Filter::eq("name", "a"), UpdateBuilder::new().set("name", "b").build(), "things").await);
+    assert_invalid(store.delete_by_query(Filter::eq("name", "a"), "things").await);
+
+    // The failed writes left both values in place
+    assert_eq!(store.query_documents(Query::default(), "things").await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn filtering_an_integer_value_fails() {
+    check_filters_fail(Bson::Int32(7)).await;
+}
+
+#[tokio::test]
+async fn filtering_a_string_value_fails() {
+    check_filters_fail(Bson::String("a".to_string())).await;
+}
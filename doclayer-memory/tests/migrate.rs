@@ -0,0 +1,187 @@
+//! Checks where downgrades stop: `downgrade_to` leaves its target applied, `downgrade`
+//! reverts every migration, including the first, and neither reverts anything when it would
+//! have to revert an irreversible migration.
+
+use async_trait::async_trait;
+
+use doclayer_core::{
+    error::{DocumentStoreError, DocumentStoreResult},
+    migrate::{BASE_REVISION, IrreversibleMigration, MigrateOp, Migration, MigrationRef, MigrationRunner, Migrations, Migrator},
+    store::{AsDynDocumentStore, DocumentStore},
+};
+use doclayer_memory::InMemoryStore;
+
+/// A migration creating a collection named after it.
+struct CreateCollection(&'static str, Option<&'static str>);
+
+#[async_trait]
+impl Migration for CreateCollection {
+    fn id(&self) -> &'static str {
+        self.0
+    }
+
+    fn previous_id(&self) -> Option<&'static str> {
+        self.1
+    }
+
+    async fn up(&self, op: &MigrateOp<'_>) -> DocumentStoreResult<()> {
+        op.create_collection(self.0).await
+    }
+
+    async fn down(&self, op: &MigrateOp<'_>) -> DocumentStoreResult<()> {
+        op.drop_collection(self.0).await
+    }
+}
+
+/// A migration creating a collection named after it, which it can't revert.
+struct CreateCollectionForGood(&'static str, Option<&'static str>);
+
+#[async_trait]
+impl IrreversibleMigration for CreateCollectionForGood {
+    fn id(&self) -> &'static str {
+        self.0
+    }
+
+    fn previous_id(&self) -> Option<&'static str> {
+        self.1
+    }
+
+    async fn up(&self, op: &MigrateOp<'_>) -> DocumentStoreResult<()> {
+        op.create_collection(self.0).await
+    }
+}
+
+struct AppMigrations;
+
+impl Migrations for AppMigrations {
+    fn migrations() -> Vec<MigrationRef> {
+        vec![
+            Box::new(CreateCollection("first", None)),
+            Box::new(CreateCollection("second", Some("first"))),
+            Box::new(CreateCollection("third", Some("second"))),
+        ]
+    }
+}
+
+struct IrreversibleMigrations;
+
+impl Migrations for IrreversibleMigrations {
+    fn migrations() -> Vec<MigrationRef> {
+        vec![
+            Box::new(CreateCollection("first", None)),
+            Box::new(CreateCollectionForGood("second", Some("first"))),
+            Box::new(CreateCollection("third", Some("second"))),
+        ]
+    }
+}
+
+/// Returns a store with every migration applied.
+async fn upgraded() -> DocumentStore<InMemoryStore> {
+    let store = DocumentStore::new(InMemoryStore::new());
+    store.upgrade::<AppMigrations>().await.unwrap();
+
+    store
+}
+
+/// Returns the store's revision and which of the migrations' collections exist.
+async fn state(store: &DocumentStore<InMemoryStore>) -> (Option<String>, Vec<&'static str>) {
+    let mut existing = Vec::new();
+
+    for name in ["first", "second", "third"] {
+        if store.collection_exists(name).await.unwrap() {
+            existing.push(name);
+        }
+    }
+
+    (store.as_dyn().current_revision_id().await.unwrap(), existing)
+}
+
+#[tokio::test]
+async fn downgrade_to_keeps_the_target_applied() {
+    let store = upgraded().await;
+
+    store.downgrade_to::<AppMigrations>("first").await.unwrap();
+
+    assert_eq!(state(&store).await, (Some("first".to_string()), vec!["first"]));
+}
+
+#[tokio::test]
+async fn downgrade_to_the_current_revision_reverts_nothing() {
+    let store = upgraded().await;
+
+    store.downgrade_to::<AppMigrations>("third").await.unwrap();
+
+    assert_eq!(state(&store).await, (Some("third".to_string()), vec!["first", "second", "third"]));
+}
+
+#[tokio::test]
+async fn downgrade_reverts_every_migration() {
+    let store = upgraded().await;
+
+    store.downgrade::<AppMigrations>().await.unwrap();
+
+    assert_eq!(state(&store).await, (Some(BASE_REVISION.to_string()), vec![]));
+
+    // A store at the base revision upgrades like a new one
+    store.upgrade::<AppMigrations>().await.unwrap();
+
+    assert_eq!(state(&store).await, (Some("third".to_string()), vec!["first", "second", "third"]));
+}
+
+#[tokio::test]
+async fn downgrade_to_base_reverts_the_first_migration() {
+    let store = upgraded().await;
+
+    store.downgrade_to::<AppMigrations>("first").await.unwrap();
+    store.downgrade_to::<AppMigrations>(BASE_REVISION).await.unwrap();
+
+    assert_eq!(state(&store).await, (Some(BASE_REVISION.to_string()), vec![]));
+}
+
+#[tokio::test]
+async fn downgrading_a_new_store_reverts_nothing() {
+    let store = DocumentStore::new(InMemoryStore::new());
+
+    store.downgrade::<AppMigrations>().await.unwrap();
+
+    assert_eq!(state(&store).await, (None, vec![]));
+}
+
+#[tokio::test]
+async fn downgrade_past_an_irreversible_migration_reverts_nothing() {
+    let store = DocumentStore::new(InMemoryStore::new());
+    store.upgrade::<IrreversibleMigrations>().await.unwrap();
+
+    let result = store.downgrade_to::<IrreversibleMigrations>("first").await;
+
+    assert!(
+        matches!(
+            &result,
+            Err(DocumentStoreError::IrreversibleMigration(id, path)) if id == "second" && path == &["third", "second"]
+        ),
+        "{result:?}"
+    );
+    assert_eq!(state(&store).await, (Some("third".to_string()), vec!["first", "second", "third"]));
+
+    // Reverting the migrations after it is still allowed
+    store.downgrade_to::<IrreversibleMigrations>("second").await.unwrap();
+
+    assert_eq!(state(&store).await, (Some("second".to_string()), vec!["first", "second"]));
+}
+
+#[tokio::test]
+async fn downgrade_without_the_guard_stops_at_an_irreversible_migration() {
+    let store = DocumentStore::new(InMemoryStore::new());
+    store.upgrade::<IrreversibleMigrations>().await.unwrap();
+
+    let result = MigrationRunner::<IrreversibleMigrations>::new()
+        .refuse_irreversible(false)
+        .downgrade_to(store.as_dyn(), "first")
+        .await;
+
+    assert!(
+        matches!(&result, Err(DocumentStoreError::IrreversibleMigration(id, path)) if id == "second" && path.is_empty()),
+        "{result:?}"
+    );
+    assert_eq!(state(&store).await, (Some("second".to_string()), vec!["first", "second"]));
+}
@@ -0,0 +1,105 @@
+//! Checks that mirrored find-and-modify operations change the same document on both
+//! backends, even when the filter matches different documents on each.
+
+use std::sync::{Arc, Mutex};
+
+use bson::{Bson, Uuid, doc};
+
+use doclayer_core::{
+    backend::{InsertPolicy, ReturnDocument, StoreBackend},
+    mirror::{Divergence, DivergenceKind, MirroredStore},
+    query::Filter,
+    update::Update,
+};
+use doclayer_memory::InMemoryStore;
+
+fn document(id: Uuid, status: &str) -> (Uuid, Bson) {
+    (id, Bson::Document(doc! { "id": id.to_string(), "status": status }))
+}
+
+/// Returns a mirror whose secondary holds a pending document the primary doesn't, along
+/// with the ID of the pending document both hold and the reported divergences.
+async fn drifted() -> (MirroredStore<InMemoryStore, InMemoryStore>, Uuid, Arc<Mutex<Vec<Divergence>>>) {
+    let (primary, secondary) = (InMemoryStore::new(), InMemoryStore::new());
+    let shared = Uuid::new();
+
+    primary
+        .insert_documents(vec![document(shared, "pending")], "jobs", InsertPolicy::ErrorOnConflict)
+        .await
+        .unwrap();
+    secondary
+        .insert_documents(
+            vec![document(shared, "pending"), document(Uuid::new(), "pending")],
+            "jobs",
+            InsertPolicy::ErrorOnConflict,
+        )
+        .await
+        .unwrap();
+
+    let divergences = Arc::new(Mutex::new(Vec::new()));
+    let recorded = divergences.clone();
+    let store = MirroredStore::new(primary, secondary)
+        .with_divergence_handler(move |divergence| recorded.lock().unwrap().push(divergence.clone()));
+
+    (store, shared, divergences)
+}
+
+#[tokio::test]
+async fn find_one_and_update_updates_the_primarys_document_on_the_secondary() {
+    let (store, shared, divergences) = drifted().await;
+
+    for _ in 0..2 {
+        store
+            .find_one_and_update(
+                Filter::eq("status", "pending"),
+                Vec::new(),
+                Update::builder().set("status", "running").build(),
+                ReturnDocument::After,
+                "jobs",
+            )
+            .await
+            .unwrap();
+    }
+
+    let running = store
+        .b()
+        .get_documents(vec![shared], "jobs")
+        .await
+        .unwrap();
+    assert_eq!(running[0].as_document().unwrap().get_str("status").unwrap(), "running");
+    // The second call found nothing on the primary, so it left the secondary alone
+    assert_eq!(store.b().delete_by_query(Filter::eq("status", "pending"), "jobs").await.unwrap(), 1);
+    assert!(divergences.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn find_one_and_delete_deletes_the_primarys_document_on_the_secondary() {
+    let (store, shared, divergences) = drifted().await;
+
+    let deleted = store
+        .find_one_and_delete(Filter::eq("status", "pending"), Vec::new(), "jobs")
+        .await
+        .unwrap();
+
+    assert!(deleted.is_some());
+    assert!(store.b().get_documents(vec![shared], "jobs").await.unwrap().is_empty());
+    assert_eq!(store.b().count_documents(Default::default(), "jobs").await.unwrap(), 1);
+    assert!(divergences.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn a_document_missing_on_the_secondary_is_a_divergence() {
+    let (store, shared, divergences) = drifted().await;
+    store.b().delete_documents(vec![shared], "jobs").await.unwrap();
+
+    store
+        .find_one_and_delete(Filter::eq("status", "pending"), Vec::new(), "jobs")
+        .await
+        .unwrap();
+
+    let divergences = divergences.lock().unwrap().clone();
+    assert_eq!(divergences.len(), 1);
+    assert_eq!(divergences[0].kind, DivergenceKind::CountMismatch { primary: 1, secondary: 0 });
+    // The secondary's other pending document was left alone
+    assert_eq!(store.b().count_documents(Default::default(), "jobs").await.unwrap(), 1);
+}
@@ -0,0 +1,73 @@
+//! Checks that regular expressions are compiled before any document is scanned: an invalid
+//! pattern fails even when no document would reach it.
+
+use bson::{Bson, Uuid, doc};
+
+use doclayer_core::{
+    aggregate::Aggregate,
+    backend::{InsertPolicy, StoreBackend},
+    error::{DocumentStoreError, DocumentStoreResult},
+    query::{Filter, Query},
+    update::UpdateBuilder,
+};
+use doclayer_memory::InMemoryStore;
+
+fn assert_invalid_pattern<T: std::fmt::Debug>(result: DocumentStoreResult<T>) {
+    assert!(
+        matches!(&result, Err(DocumentStoreError::Backend(message)) if message.starts_with("Invalid regex pattern")),
+        "{result:?}"
+    );
+}
+
+async fn check_invalid_pattern_fails(store: &InMemoryStore) {
+    let filter = || Filter::matches("name", "(unclosed");
+    let query = || Query::builder().filter(filter()).build();
+
+    assert_invalid_pattern(store.query_documents(query(), "things").await);
+    assert_invalid_pattern(store.query_stream(query(), "things").await.map(|_| ()));
+    assert_invalid_pattern(store.count_documents(query(), "things").await);
+    assert_invalid_pattern(store.distinct("name", Some(filter()), "things").await);
+    assert_invalid_pattern(store.aggregate(Aggregate { filter: Some(filter()), ..Aggregate::default() }, "things").await);
+    assert_invalid_pattern(store.update_by_query(filter(), UpdateBuilder::new().set("name", "b").build(), "things").await);
+    assert_invalid_pattern(store.delete_by_query(filter(), "things").await);
+}
+
+#[tokio::test]
+async fn invalid_patterns_fail_on_an_empty_collection() {
+    let store = InMemoryStore::new();
+    store.create_collection("things").await.unwrap();
+
+    check_invalid_pattern_fails(&store).await;
+}
+
+#[tokio::test]
+async fn invalid_patterns_fail_on_a_missing_collection() {
+    check_invalid_pattern_fails(&InMemoryStore::new()).await;
+}
+
+#[tokio::test]
+async fn a_pattern_can_both_ignore_and_match_case() {
+    let store = InMemoryStore::new();
+
+    store.insert_documents(
+        ["Ada", "ada", "Grace"]
+            .into_iter()
+            .map(|name| (Uuid::new(), Bson::Document(doc! { "name": name })))
+            .collect(),
+        "things",
+        InsertPolicy::ErrorOnConflict,
+    ).await.unwrap();
+
+    let count = |filter| store.count_documents(Query::builder().filter(filter).build(), "things");
+
+    assert_eq!(count(Filter::matches("name", "^ada$")).await.unwrap(), 1);
+    assert_eq!(count(Filter::case_insensitive(Filter::matches("name", "^ada$"))).await.unwrap(), 2);
+    assert_eq!(
+        count(Filter::or([Filter::matches("name", "^ada$"), Filter::case_insensitive(Filter::matches("name", "^ada$"))])).await.unwrap(),
+        2
+    );
+    assert_eq!(
+        count(Filter::and([Filter::matches("name", "^ada$"), Filter::case_insensitive(Filter::matches("name", "^ADA$"))])).await.unwrap(),
+        1
+    );
+}
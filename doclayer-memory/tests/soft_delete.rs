@@ -0,0 +1,94 @@
+//! Checks which writes by filter reach soft-deleted documents: `update_where` leaves them
+//! out like reads do, while `delete_where` purges them.
+
+use bson::Uuid;
+use serde::{Deserialize, Serialize};
+
+use doclayer_core::{
+    document::Document,
+    query::{Filter, Query},
+    store::{DocumentStore, DynDocumentStore},
+    update::UpdateBuilder,
+};
+use doclayer_memory::InMemoryStore;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Post {
+    id: Uuid,
+    title: String,
+    published: bool,
+    deleted_at: Option<bson::DateTime>,
+}
+
+impl Document for Post {
+    fn id(&self) -> &Uuid {
+        &self.id
+    }
+
+    fn collection_name() -> &'static str {
+        "posts"
+    }
+
+    fn deleted_field() -> Option<&'static str> {
+        Some("deleted_at")
+    }
+}
+
+fn post(title: &str) -> Post {
+    Post { id: Uuid::new(), title: title.to_string(), published: false, deleted_at: None }
+}
+
+/// Returns whether each post is published, soft-deleted ones included, by title.
+fn published(mut posts: Vec<Post>) -> Vec<(String, bool)> {
+    posts.sort_by(|a, b| a.title.cmp(&b.title));
+    posts.into_iter().map(|post| (post.title, post.published)).collect()
+}
+
+#[tokio::test]
+async fn update_where_leaves_soft_deleted_documents_untouched() {
+    let store = DocumentStore::new(InMemoryStore::new());
+    let posts = store.typed_collection::<Post>();
+    let (live, deleted) = (post("live"), post("deleted"));
+
+    posts.insert(vec![live, deleted.clone()]).await.unwrap();
+    posts.soft_delete(vec![deleted.id]).await.unwrap();
+
+    let report = posts.update_where(Filter::eq("published", false), UpdateBuilder::new().set("published", true).build()).await.unwrap();
+
+    assert_eq!(report.matched, 1);
+    assert_eq!(published(posts.query_with_deleted(Query::default()).await.unwrap()), vec![
+        ("deleted".to_string(), false),
+        ("live".to_string(), true),
+    ]);
+}
+
+#[tokio::test]
+async fn dyn_update_where_leaves_soft_deleted_documents_untouched() {
+    let store = DynDocumentStore::new(Box::new(InMemoryStore::new()));
+    let posts = store.typed_collection::<Post>();
+    let (live, deleted) = (post("live"), post("deleted"));
+
+    posts.insert(vec![live, deleted.clone()]).await.unwrap();
+    posts.soft_delete(vec![deleted.id]).await.unwrap();
+
+    let report = posts.update_where(Filter::eq("published", false), UpdateBuilder::new().set("published", true).build()).await.unwrap();
+
+    assert_eq!(report.matched, 1);
+    assert_eq!(published(posts.query_with_deleted(Query::default()).await.unwrap()), vec![
+        ("deleted".to_string(), false),
+        ("live".to_string(), true),
+    ]);
+}
+
+#[tokio::test]
+async fn delete_where_purges_soft_deleted_documents() {
+    let store = DocumentStore::new(InMemoryStore::new());
+    let posts = store.typed_collection::<Post>();
+    let (live, deleted) = (post("live"), post("deleted"));
+
+    posts.insert(vec![live, deleted.clone()]).await.unwrap();
+    posts.soft_delete(vec![deleted.id]).await.unwrap();
+
+    assert_eq!(posts.delete_where(Filter::eq("published", false)).await.unwrap(), 2);
+    assert!(posts.query_with_deleted(Query::default()).await.unwrap().is_empty());
+}
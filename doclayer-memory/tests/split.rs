@@ -0,0 +1,24 @@
+//! Checks that the migration lock of a split store is taken on the primary, so a runner never
+//! reads a stale lock from a replica.
+
+use std::time::Duration;
+
+use doclayer_core::{backend::StoreBackend, split::SplitStore};
+use doclayer_memory::InMemoryStore;
+use doclayer_test::Mock;
+
+#[tokio::test]
+async fn split_store_takes_the_migration_lock_on_the_primary() {
+    let replica = Mock::new();
+    let store = SplitStore::new(InMemoryStore::new(), vec![replica.clone().wrap(InMemoryStore::new())]);
+    let ttl = Duration::from_secs(60);
+
+    assert!(store.acquire_migration_lock("first", ttl).await.unwrap());
+    assert!(!store.acquire_migration_lock("second", ttl).await.unwrap());
+
+    store.release_migration_lock("first").await.unwrap();
+    assert!(store.acquire_migration_lock("second", ttl).await.unwrap());
+    assert!(store.primary().acquire_migration_lock("first", ttl).await.is_ok_and(|acquired| !acquired));
+
+    assert!(replica.calls().is_empty(), "{:?}", replica.calls());
+}
@@ -0,0 +1,89 @@
+//! Checks the timeout layer: queries use their own timeout over the layer's default, slow
+//! calls fail with a timeout, and a query with no time left fails without reaching the
+//! backend.
+
+use std::time::{Duration, Instant};
+
+use bson::{Bson, Uuid, doc};
+
+use doclayer_core::{
+    backend::{InsertPolicy, StoreBackend},
+    error::DocumentStoreError,
+    query::Query,
+    timeout::Timeout,
+};
+use doclayer_memory::InMemoryStore;
+use doclayer_test::{Chaos, Delay, Mock};
+
+async fn populated() -> InMemoryStore {
+    let store = InMemoryStore::new();
+    let id = Uuid::new();
+
+    store
+        .insert_documents(vec![(id, Bson::Document(doc! { "id": id.to_string() }))], "things", InsertPolicy::ErrorOnConflict)
+        .await
+        .unwrap();
+    store
+}
+
+fn assert_timeout<T: std::fmt::Debug>(result: Result<T, DocumentStoreError>) {
+    assert!(matches!(result, Err(DocumentStoreError::Timeout(_))), "{result:?}");
+}
+
+#[tokio::test]
+async fn timeout_fails_a_query_with_no_time_left_before_running_it() {
+    let mock = Mock::new();
+    let store = Timeout::new().wrap(mock.clone().wrap(populated().await));
+
+    // A deadline already passed
+    let query = Query::builder()
+        .deadline(Instant::now() - Duration::from_secs(1))
+        .build();
+    assert_timeout(store.query_documents(query.clone(), "things").await);
+    assert_timeout(store.count_documents(query, "things").await);
+
+    mock.assert_not_called("query_documents");
+    mock.assert_not_called("count_documents");
+}
+
+#[tokio::test]
+async fn timeout_fails_a_zero_default_before_running_the_operation() {
+    let mock = Mock::new();
+    let store = Timeout::new()
+        .with_default(Duration::ZERO)
+        .wrap(mock.clone().wrap(populated().await));
+
+    assert_timeout(store.list_collections().await);
+    mock.assert_not_called("list_collections");
+}
+
+#[tokio::test]
+async fn timeout_fails_calls_running_longer_than_their_timeout() {
+    let slow = Chaos::new(0).with_latency_for("query_documents", Delay::Fixed(Duration::from_millis(200)));
+    let store = Timeout::new()
+        .with_default(Duration::from_secs(5))
+        .wrap(slow.wrap(populated().await));
+
+    // The query's own timeout is used over the default
+    let query = Query::builder()
+        .timeout(Duration::from_millis(10))
+        .build();
+    assert_timeout(store.query_documents(query, "things").await);
+
+    // Other operations use the default
+    assert_eq!(store.count_documents(Query::default(), "things").await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn timeout_lets_a_query_extend_the_default() {
+    let slow = Chaos::new(0).with_latency_for("query_documents", Delay::Fixed(Duration::from_millis(50)));
+    let store = Timeout::new()
+        .with_default(Duration::from_millis(10))
+        .wrap(slow.wrap(populated().await));
+
+    let query = Query::builder()
+        .timeout(Duration::from_secs(5))
+        .build();
+    assert_eq!(store.query_documents(query, "things").await.unwrap().len(), 1);
+    assert_timeout(store.query_documents(Query::default(), "things").await);
+}
@@ -0,0 +1,83 @@
+//! Aggregate translation from doclayer aggregates to MongoDB aggregation pipelines.
+//!
+//! This module translates doclayer's [`Aggregate`] into a `$match` and `$group`
+//! pipeline and reshapes the grouped output into doclayer's result form.
+
+use bson::{Document, Bson, doc};
+
+use doclayer_core::{
+    aggregate::{Aggregate, Accumulator},
+    error::DocumentStoreResult,
+    query::{CustomOperators, QueryVisitor},
+};
+
+use crate::{query::MongoQueryTranslator, sanitizer::ValueSanitizer};
+
+
+/// Translates doclayer aggregates into MongoDB aggregation pipelines.
+///
+/// Group-by fields are keyed by position inside the `$group` `_id`, since dotted
+/// field names aren't valid keys there, and restored to their names afterwards.
+pub(crate) struct MongoAggregateTranslator;
+
+impl MongoAggregateTranslator {
+    pub fn pipeline(aggregate: &Aggregate, operators: &CustomOperators) -> DocumentStoreResult<Vec<Document>> {
+        let mut pipeline = Vec::new();
+
+        if let Some(filter) = &aggregate.filter {
+            pipeline.push(doc! { "$match": MongoQueryTranslator::new(operators).visit_expr(filter)? });
+        }
+
+        let mut group = doc! {
+            "_id": match aggregate.group_by.is_empty() {
+                true => Bson::Null,
+                false => Bson::Document(Document::from_iter(
+                    aggregate.group_by
+                        .iter()
+                        .enumerate()
+                        .map(|(index, field)| (Self::group_key(index), Bson::String(format!("${}", field))))
+                )),
+            },
+        };
+
+        for (name, accumulator) in &aggregate.accumulators {
+            group.insert(name, match accumulator {
+                // Summing 64-bit ones makes the count an Int64, like the other backends
+                Accumulator::Count => doc! { "$sum": Bson::Int64(1) },
+                Accumulator::Sum(field) => doc! { "$sum": format!("${}", field) },
+                Accumulator::Avg(field) => doc! { "$avg": format!("${}", field) },
+                Accumulator::Min(field) => doc! { "$min": format!("${}", field) },
+                Accumulator::Max(field) => doc! { "$max": format!("${}", field) },
+            });
+        }
+
+        pipeline.push(doc! { "$group": group });
+
+        Ok(pipeline)
+    }
+
+    pub fn restore(aggregate: &Aggregate, mut document: Document) -> Bson {
+        let key = document.remove("_id");
+        let mut result = Document::new();
+
+        for (index, field) in aggregate.group_by.iter().enumerate() {
+            result.insert(
+                field,
+                key.as_ref()
+                    .and_then(Bson::as_document)
+                    .and_then(|key| key.get(Self::group_key(index)))
+                    .cloned()
+                    .unwrap_or(Bson::Null),
+            );
+        }
+        for (name, _) in &aggregate.accumulators {
+            result.insert(name, document.remove(name).unwrap_or(Bson::Null));
+        }
+
+        ValueSanitizer::restore_value(&Bson::Document(result))
+    }
+
+    fn group_key(index: usize) -> String {
+        format!("g{}", index)
+    }
+}
@@ -14,8 +14,12 @@
 //!
 //! - **Persistent storage** - Data is persisted to MongoDB Atlas or self-hosted MongoDB
 //! - **Full query support** - Leverages MongoDB's query engine for filtering and sorting
+//! - **Raw BSON reads** - Query results are read as raw BSON and restored without decoding
+//!   every value; writes still build an owned document
 //! - **Async/await** - Fully asynchronous API built on MongoDB's async driver
 //! - **Indexing** - Support for creating and dropping MongoDB indexes
+//! - **Transactions** - Multi-document transactions over a client session, see [`transaction`]
+//! - **Schema validation** - doclayer JSON Schemas pushed as collection validators, see [`schema`]
 //! - **Schema migrations** - Compatible with the doclayer migration framework
 //!
 //! # Connection
@@ -44,5 +48,10 @@ extern crate self as doclayer_mongodb;
 pub mod store;
 pub mod query;
 pub mod sanitizer;
+pub mod update;
+pub mod aggregate;
+pub mod transaction;
+pub mod schema;
 
-pub use store::{MongoDbStore, MongoDbStoreBuilder};
+pub use store::{MongoDbStore, MongoDbStoreBuilder, MongoDbTlsConfig};
+pub use transaction::MongoDbTransaction;
@@ -3,10 +3,11 @@
 //! This module translates doclayer's abstract query expressions into
 //! MongoDB BSON documents for execution by the MongoDB query engine.
 
+use std::ops::Bound;
 use bson::{Document, Bson, doc};
 
 use doclayer_core::{
-    query::{QueryVisitor, Expr, FieldOp},
+    query::{QueryVisitor, CustomOperators, Expr, FieldOp},
     error::DocumentStoreError,
 };
 
@@ -14,14 +15,34 @@ use doclayer_core::{
 /// Translates doclayer query expressions into MongoDB query documents.
 ///
 /// This struct implements the [`QueryVisitor`] trait to convert abstract
-/// query expressions into MongoDB's native BSON query syntax.
-pub(crate) struct MongoQueryTranslator;
+/// query expressions into MongoDB's native BSON query syntax. Custom operators are
+/// translated by the handlers registered on the store.
+pub(crate) struct MongoQueryTranslator<'a> {
+    case_insensitive: bool,
+    operators: &'a CustomOperators,
+}
+
+impl<'a> MongoQueryTranslator<'a> {
+    pub fn new(operators: &'a CustomOperators) -> Self {
+        Self { case_insensitive: false, operators }
+    }
+
+    /// Returns the `$regex` options matching the current case sensitivity.
+    fn regex_options(&self) -> &'static str {
+        if self.case_insensitive { "i" } else { "" }
+    }
+}
 
-impl QueryVisitor for MongoQueryTranslator {
+impl QueryVisitor for MongoQueryTranslator<'_> {
     type Output = Document;
     type Error = DocumentStoreError;
 
     fn visit_and(&mut self, exprs: &[Expr]) -> Result<Self::Output, Self::Error> {
+        // `$and` must not be empty, and an empty conjunction matches everything
+        if exprs.is_empty() {
+            return Ok(Document::new());
+        }
+
         Ok(doc! {
             "$and": exprs
                 .iter()
@@ -45,13 +66,58 @@ impl QueryVisitor for MongoQueryTranslator {
         })
     }
 
+    fn visit_case_insensitive(&mut self, expr: &Expr) -> Result<Self::Output, Self::Error> {
+        let previous = std::mem::replace(&mut self.case_insensitive, true);
+        let result = self.visit_expr(expr);
+        self.case_insensitive = previous;
+
+        result
+    }
+
+    fn visit_elem_match(&mut self, field: &str, expr: &Expr) -> Result<Self::Output, Self::Error> {
+        Ok(doc! {
+            field: { "$elemMatch": self.visit_expr(expr)? },
+        })
+    }
+
+    fn visit_custom(&mut self, name: &str, payload: &Bson) -> Result<Self::Output, Self::Error> {
+        // Field names are the same in stored documents and within `$elemMatch`
+        self.operators.translate(name, payload, "")
+    }
+
     fn visit_exists(&mut self, field: &str, should_exist: bool) -> Result<Self::Output, Self::Error> {
         Ok(doc! {
             field: { "$exists": should_exist },
         })
     }
 
+    fn visit_range(&mut self, field: &str, low: &Bound<Bson>, high: &Bound<Bson>) -> Result<Self::Output, Self::Error> {
+        // A single field document lets the server use an index range scan
+        let mut range = Document::new();
+
+        match low {
+            Bound::Included(value) => { range.insert("$gte", value.clone()); },
+            Bound::Excluded(value) => { range.insert("$gt", value.clone()); },
+            Bound::Unbounded => {},
+        }
+        match high {
+            Bound::Included(value) => { range.insert("$lte", value.clone()); },
+            Bound::Excluded(value) => { range.insert("$lt", value.clone()); },
+            Bound::Unbounded => {},
+        }
+
+        if range.is_empty() {
+            return Ok(doc! {});
+        }
+
+        Ok(doc! {
+            field: range,
+        })
+    }
+
     fn visit_field(&mut self, field: &str, op: &FieldOp, value: &Bson) -> Result<Self::Output, Self::Error> {
+        let options = self.regex_options();
+
         Ok(doc! {
             field: match op {
                 FieldOp::Eq => doc! { "$eq": value },
@@ -61,26 +127,85 @@ impl QueryVisitor for MongoQueryTranslator {
                 FieldOp::Lt => doc! { "$lt": value },
                 FieldOp::Lte => doc! { "$lte": value },
                 FieldOp::Contains => match value {
-                    Bson::String(s) => doc! { "$regex": format!(".*{}.*", s), "$options": "i" },
+                    Bson::String(s) => doc! { "$regex": escape_regex(s), "$options": options },
                     Bson::Array(arr) => doc! { "$all": arr },
                     _ => return Err(DocumentStoreError::Backend("Contains operator requires a string or array value".to_string())),
                 },
                 FieldOp::NotContains => match value {
-                    Bson::String(s) => doc! { "$not": { "$regex": format!(".*{}.*", s), "$options": "i" } },
+                    Bson::String(s) => doc! { "$not": { "$regex": escape_regex(s), "$options": options } },
                     Bson::Array(arr) => doc! { "$nin": arr },
                     _ => return Err(DocumentStoreError::Backend("NotContains operator requires a string or array value".to_string())),
                 },
                 FieldOp::StartsWith => match value {
-                    Bson::String(s) => doc! { "$regex": format!("^{}", s), "$options": "i" },
+                    Bson::String(s) => doc! { "$regex": format!("^{}", escape_regex(s)), "$options": options },
                     _ => return Err(DocumentStoreError::Backend("StartsWith operator requires a string value".to_string())),
                 },
                 FieldOp::EndsWith => match value {
-                    Bson::String(s) => doc! { "$regex": format!("{}$", s), "$options": "i" },
+                    Bson::String(s) => doc! { "$regex": format!("{}$", escape_regex(s)), "$options": options },
                     _ => return Err(DocumentStoreError::Backend("EndsWith operator requires a string value".to_string())),
                 },
-                FieldOp::AnyOf => doc! { "$in": value },
-                FieldOp::NoneOf => doc! { "$nin": value },
+                // `$in` and `$nin` only accept arrays
+                FieldOp::AnyOf => doc! { "$in": FieldOp::set_values(value)? },
+                FieldOp::NoneOf => doc! { "$nin": FieldOp::set_values(value)? },
+                FieldOp::Regex => match value {
+                    Bson::String(s) => doc! { "$regex": s, "$options": options },
+                    _ => return Err(DocumentStoreError::Backend("Regex operator requires a string pattern".to_string())),
+                },
             }
         })
     }
 }
+
+
+/// Escapes the regular expression metacharacters in a string so it matches literally.
+///
+/// String operators are implemented with `$regex`, so the compared value must not be
+/// interpreted as a pattern. Intentional patterns go through [`FieldOp::Regex`] unescaped.
+fn escape_regex(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        if matches!(c, '\\' | '^' | '$' | '.' | '|' | '?' | '*' | '+' | '(' | ')' | '[' | ']' | '{' | '}' | '-' | '#') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bson::{Bson, Regex, doc};
+
+    use doclayer_core::{
+        query::{CustomOperators, Expr, FieldOp, Filter, QueryVisitor},
+        error::DocumentStoreError,
+    };
+
+    use super::MongoQueryTranslator;
+
+    fn translate(expr: &Expr) -> Result<bson::Document, DocumentStoreError> {
+        MongoQueryTranslator::new(&CustomOperators::default()).visit_expr(expr)
+    }
+
+    #[test]
+    fn membership_candidates_are_always_arrays() {
+        assert_eq!(translate(&Filter::any_of("n", vec![1, 2])).unwrap(), doc! { "n": { "$in": [1, 2] } });
+        assert_eq!(translate(&Filter::none_of("n", vec![1, 2])).unwrap(), doc! { "n": { "$nin": [1, 2] } });
+
+        // A scalar is a single candidate, which the server only accepts in an array
+        let scalar = |op| Expr::Field { field: "n".to_string(), op, value: Bson::Int32(1) };
+        assert_eq!(translate(&scalar(FieldOp::AnyOf)).unwrap(), doc! { "n": { "$in": [1] } });
+        assert_eq!(translate(&scalar(FieldOp::NoneOf)).unwrap(), doc! { "n": { "$nin": [1] } });
+    }
+
+    #[test]
+    fn membership_candidates_cant_be_regular_expressions() {
+        let pattern = Bson::RegularExpression(Regex { pattern: "^a".try_into().unwrap(), options: "".try_into().unwrap() });
+
+        assert!(translate(&Filter::any_of("s", vec![pattern.clone()])).is_err());
+        assert!(translate(&Filter::none_of("s", pattern)).is_err());
+    }
+}
@@ -5,7 +5,7 @@
 //! (keys) from containing certain characters like dots and dollar signs, which are
 //! used in MongoDB query syntax.
 
-use bson::Bson;
+use bson::{Bson, RawBsonRef, RawDocument};
 
 
 /// Sanitizes and restores BSON values to handle MongoDB field name restrictions.
@@ -91,4 +91,34 @@ impl ValueSanitizer {
         }
         restored
     }
+
+    /// Returns whether a raw document carries sanitization escapes in any key or string.
+    ///
+    /// Documents without escapes restore to themselves, so callers can skip the restore
+    /// pass and keep the raw bytes. Malformed elements report `true` so the slower path
+    /// surfaces the error.
+    pub(crate) fn is_escaped_raw(document: &RawDocument) -> bool {
+        document.iter().any(|element| match element {
+            Ok((key, value)) => Self::is_escaped_string(key.as_str()) || Self::is_escaped_raw_value(value),
+            Err(_) => true,
+        })
+    }
+
+    fn is_escaped_raw_value(value: RawBsonRef<'_>) -> bool {
+        match value {
+            RawBsonRef::String(s) => Self::is_escaped_string(s),
+            RawBsonRef::Document(doc) => Self::is_escaped_raw(doc),
+            RawBsonRef::Array(arr) => arr.into_iter().any(|item| match item {
+                Ok(value) => Self::is_escaped_raw_value(value),
+                Err(_) => true,
+            }),
+            _ => false,
+        }
+    }
+
+    fn is_escaped_string(input: &str) -> bool {
+        Self::REPLACEMENTS
+            .iter()
+            .any(|(_, replacement)| input.contains(*replacement))
+    }
 }
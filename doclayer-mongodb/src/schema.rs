@@ -0,0 +1,171 @@
+//! Translation of doclayer JSON Schemas into MongoDB `$jsonSchema` validators.
+//!
+//! MongoDB implements a dialect of JSON Schema draft 4 without `$ref`, `definitions`,
+//! `format`, `const` and the `integer` type, and checks BSON values rather than their JSON
+//! form. The translation inlines references, drops the annotations MongoDB rejects and
+//! replaces `type` with the `bsonType`s a value of that JSON type can be stored as, so the
+//! server accepts the same documents as [`JsonSchema::validate`](doclayer_core::schema::JsonSchema::validate).
+//! One difference remains: whole doubles are integers to doclayer but not to MongoDB.
+
+use bson::{Bson, Document};
+use serde_json::Value;
+
+use doclayer_core::schema::JsonSchema;
+
+/// How deeply `$ref`s are inlined before a recursive schema gives up on checking the rest.
+const MAX_REF_DEPTH: usize = 16;
+
+/// Keywords MongoDB rejects that don't constrain values, which are left out.
+const UNSUPPORTED_KEYWORDS: [&str; 11] = [
+    "$schema", "$id", "$comment", "definitions", "$defs", "format", "default", "examples",
+    "readOnly", "writeOnly", "deprecated",
+];
+
+/// Translates a schema into the `$jsonSchema` of a MongoDB collection validator.
+///
+/// Stored documents also hold their `_id`, which is allowed at the top level when the schema
+/// forbids fields it doesn't list.
+pub(crate) fn mongo_json_schema(schema: &JsonSchema) -> Document {
+    let root = schema.as_json();
+    let mut translated = match translate(root, root, 0) {
+        Bson::Document(translated) => translated,
+        _ => Document::new(),
+    };
+
+    if translated.get("additionalProperties") == Some(&Bson::Boolean(false))
+        && let Ok(properties) = translated.get_document_mut("properties")
+    {
+        properties.insert("_id", Document::new());
+    }
+
+    translated
+}
+
+fn translate(schema: &Value, root: &Value, depth: usize) -> Bson {
+    let keywords = match schema {
+        Value::Object(keywords) => keywords,
+        Value::Bool(true) => return Bson::Document(Document::new()),
+        Value::Bool(false) => return Bson::Document(bson::doc! { "not": {} }),
+        other => return json_to_bson(other),
+    };
+
+    if let Some(Value::String(reference)) = keywords.get("$ref") {
+        return match reference.strip_prefix('#').and_then(|pointer| root.pointer(pointer)) {
+            Some(referenced) if depth < MAX_REF_DEPTH => translate(referenced, root, depth + 1),
+            _ => Bson::Document(Document::new()),
+        };
+    }
+
+    let mut translated = Document::new();
+
+    for (keyword, value) in keywords {
+        match keyword.as_str() {
+            keyword if UNSUPPORTED_KEYWORDS.contains(&keyword) => {}
+            // `bsonType` takes precedence, as MongoDB doesn't allow both
+            "type" if keywords.contains_key("bsonType") => {}
+            "type" => {
+                translated.insert("bsonType", bson_types(value, keywords.get("format")));
+            }
+            "const" => {
+                translated.insert("enum", vec![json_to_bson(value)]);
+            }
+            "properties" | "patternProperties" => {
+                translated.insert(keyword, translate_each(value, root, depth));
+            }
+            "items" => {
+                translated.insert(keyword, match value {
+                    Value::Array(schemas) => Bson::Array(schemas.iter().map(|schema| translate(schema, root, depth)).collect()),
+                    schema => translate(schema, root, depth),
+                });
+            }
+            "additionalProperties" | "additionalItems" | "not" => {
+                translated.insert(keyword, match value {
+                    Value::Bool(allowed) => Bson::Boolean(*allowed),
+                    schema => translate(schema, root, depth),
+                });
+            }
+            "allOf" | "anyOf" | "oneOf" => {
+                translated.insert(keyword, match value {
+                    Value::Array(schemas) => Bson::Array(schemas.iter().map(|schema| translate(schema, root, depth)).collect()),
+                    other => json_to_bson(other),
+                });
+            }
+            _ => {
+                translated.insert(keyword, json_to_bson(value));
+            }
+        }
+    }
+
+    Bson::Document(translated)
+}
+
+/// Translates an object whose values are schemas, such as `properties`.
+fn translate_each(schemas: &Value, root: &Value, depth: usize) -> Bson {
+    match schemas {
+        Value::Object(schemas) => Bson::Document(
+            schemas
+                .iter()
+                .map(|(field, schema)| (field.clone(), translate(schema, root, depth)))
+                .collect()
+        ),
+        other => json_to_bson(other),
+    }
+}
+
+/// Returns the BSON types values of the JSON types in a `type` keyword are stored as.
+fn bson_types(types: &Value, format: Option<&Value>) -> Bson {
+    let names = match types {
+        Value::String(name) => vec![name.as_str()],
+        Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    let strings: &[&str] = match format.and_then(Value::as_str) {
+        Some("uuid") => &["string", "binData"],
+        Some("date-time" | "date") => &["string", "date"],
+        _ => &["string", "binData", "objectId", "date", "timestamp", "symbol"],
+    };
+
+    let mut bson_types = Vec::new();
+
+    for name in names {
+        let types: &[&str] = match name {
+            "object" => &["object"],
+            "array" => &["array"],
+            "boolean" => &["bool"],
+            "null" => &["null", "undefined"],
+            "number" => &["int", "long", "double", "decimal"],
+            "integer" => &["int", "long"],
+            "string" => strings,
+            _ => &[],
+        };
+
+        for bson_type in types {
+            if !bson_types.contains(bson_type) {
+                bson_types.push(*bson_type);
+            }
+        }
+    }
+
+    Bson::Array(bson_types.into_iter().map(Bson::from).collect())
+}
+
+/// Converts a JSON value into BSON, keeping integers as integers.
+fn json_to_bson(value: &Value) -> Bson {
+    match value {
+        Value::Null => Bson::Null,
+        Value::Bool(value) => Bson::Boolean(*value),
+        Value::Number(number) => match (number.as_i64(), number.as_f64()) {
+            (Some(integer), _) => Bson::Int64(integer),
+            (None, Some(float)) => Bson::Double(float),
+            (None, None) => Bson::Null,
+        },
+        Value::String(value) => Bson::String(value.clone()),
+        Value::Array(values) => Bson::Array(values.iter().map(json_to_bson).collect()),
+        Value::Object(fields) => Bson::Document(
+            fields
+                .iter()
+                .map(|(field, value)| (field.clone(), json_to_bson(value)))
+                .collect()
+        ),
+    }
+}
@@ -1,28 +1,138 @@
+use std::{collections::HashSet, env::VarError, path::PathBuf, str::FromStr, sync::Arc, time::Duration};
 use async_trait::async_trait;
 use futures::{stream::iter, StreamExt, TryStreamExt};
-use bson::{Document, Bson, Uuid, doc};
+use bson::{Document, Bson, RawDocumentBuf, Uuid, doc};
+use mea::mutex::Mutex;
+use serde::de::DeserializeOwned;
 use mongodb::{
-    Client, Collection as MongoCollection, IndexModel,
-    options::{ClientOptions, FindOptions, IndexOptions},
+    Client, ClientSession, Collection as MongoCollection, IndexModel,
+    action::{Aggregate as AggregateAction, Find},
+    error::{ErrorKind, WriteFailure},
+    options::{
+        ClientOptions, Collation, CollationStrength, CountOptions, FindOneAndDeleteOptions, FindOneAndUpdateOptions,
+        FindOptions, IndexOptions, ReturnDocument as MongoReturnDocument, Tls, TlsOptions,
+    },
 };
 use doclayer_core::{
-    backend::{StoreBackend, StoreBackendBuilder},
+    aggregate::Aggregate,
+    backend::{DocumentStream, InsertPolicy, MissingDocumentPolicy, QueryPlan, ReturnDocument, ScanStrategy, StoreBackend, StoreBackendBuilder, WriteReport},
+    encoding::Encoding,
     error::{DocumentStoreError, DocumentStoreResult},
-    query::{Query, QueryVisitor, SortDirection},
+    limit::{ConcurrencyLimiter, LimiterPermit},
+    query::{CustomOperator, CustomOperators, Expr, Query, QueryVisitor, Sort, SortComparator, SortComparators, SortDirection},
+    schema::{JsonSchema, SchemaRegistry},
+    transaction::Transaction,
+    update::Update,
+    versioned::document_revision,
 };
 
-use crate::{sanitizer::ValueSanitizer, query::MongoQueryTranslator};
+use crate::{
+    sanitizer::ValueSanitizer,
+    query::MongoQueryTranslator,
+    update::MongoUpdateTranslator,
+    aggregate::MongoAggregateTranslator,
+    transaction::MongoDbTransaction,
+    schema::mongo_json_schema,
+};
 
 
-#[derive(Debug)]
+/// Runs a driver action in the store's transaction, if the store belongs to one.
+macro_rules! in_session {
+    ($store:expr, $action:expr) => {
+        match &$store.session {
+            Some(session) => $action.session(&mut *session.lock().await).await,
+            None => $action.await,
+        }
+    };
+}
+
+#[derive(Debug, Clone)]
 pub struct MongoDbStore {
     client: Client,
     database: String,
+    limiter: Option<ConcurrencyLimiter>,
+    comparators: SortComparators,
+    operators: CustomOperators,
+    encoding: Encoding,
+    /// The session of the transaction the store's operations run in, if any
+    pub(crate) session: Option<Arc<Mutex<ClientSession>>>,
 }
 
 impl MongoDbStore {
+    /// Actions the store needs on its database for regular document operations.
+    const REQUIRED_ACTIONS: [&'static str; 4] = ["find", "insert", "update", "remove"];
+
+    /// Server error code returned when creating a collection that already exists.
+    const NAMESPACE_EXISTS: i32 = 48;
+
+    /// Server error code returned when an operation exceeds its `maxTimeMS`.
+    const MAX_TIME_MS_EXPIRED: i32 = 50;
+
+    /// Server error code returned when a write violates a unique index.
+    const DUPLICATE_KEY: i32 = 11000;
+
+    /// Server error codes of failures expected to clear up on their own, such as a primary
+    /// stepping down during a failover or a node shutting down.
+    const TRANSIENT_CODES: [i32; 11] = [6, 7, 89, 91, 189, 262, 9001, 10107, 11600, 11602, 13435];
+
     pub fn new(client: Client, database: String) -> Self {
-        Self { client, database }
+        Self { client, database, limiter: None, comparators: SortComparators::new(), operators: CustomOperators::new(), encoding: Encoding::new(), session: None }
+    }
+
+    /// Limits the operations this store runs concurrently.
+    ///
+    /// Operations beyond the limit wait for a permit instead of competing for pooled
+    /// connections. Streams from [`query_stream`](StoreBackend::query_stream) hold their
+    /// permit until they are dropped.
+    pub fn with_limiter(mut self, limiter: ConcurrencyLimiter) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// Orders a field with a built-in comparator when queries sort by it.
+    ///
+    /// Comparators are applied as a collation, which MongoDB applies to the whole query: to
+    /// every sort key and to the string comparisons of the filter. A
+    /// [`SortComparator::CaseInsensitive`] field makes the filter ignore case, and sort keys
+    /// with different comparators share the combined collation. Queries sorting by a field
+    /// with a [`SortComparator::Custom`] comparator fail, since MongoDB can't evaluate it.
+    pub fn with_comparator(mut self, collection: impl Into<String>, field: impl Into<String>, comparator: SortComparator) -> Self {
+        self.comparators.insert(collection, field, comparator);
+        self
+    }
+
+    /// Registers the handler of a custom filter operator, used by `Expr::Custom` filters
+    /// naming it.
+    ///
+    /// MongoDB evaluates filters on the server, so the handler must be a
+    /// [`CustomOperator::Translate`] building a query document. Field paths are passed
+    /// without a prefix.
+    pub fn with_operator(mut self, name: impl Into<String>, operator: CustomOperator) -> Self {
+        self.operators.insert(name, operator);
+        self
+    }
+
+    /// Sets the representation of UUIDs, enums and datetimes in stored documents.
+    ///
+    /// Document IDs are stored in `_id` using the configured UUID representation. With a
+    /// [compatibility](Encoding::with_compatibility) encoding, lookups by ID match either
+    /// representation, and a document stored under its old `_id` is moved to the new one
+    /// when it is written.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Returns the limiter guarding this store, if one is configured.
+    pub fn limiter(&self) -> Option<&ConcurrencyLimiter> {
+        self.limiter.as_ref()
+    }
+
+    async fn permit(&self) -> DocumentStoreResult<Option<LimiterPermit>> {
+        match &self.limiter {
+            Some(limiter) => Ok(Some(limiter.acquire().await?)),
+            None => Ok(None),
+        }
     }
 
     pub fn builder(dsn: &str, database: &str) -> MongoDbStoreBuilder {
@@ -35,135 +145,1044 @@ impl MongoDbStore {
             .collection(&ValueSanitizer::sanitize_string(collection_name))
     }
 
+    /// Returns a copy of the store running its operations in a session's transaction.
+    pub(crate) fn with_session(&self, session: Arc<Mutex<ClientSession>>) -> Self {
+        Self { session: Some(session), ..self.clone() }
+    }
+
+    /// Reads a collection as raw BSON, leaving decoding to the caller.
+    fn get_raw_collection(&self, collection_name: &str) -> MongoCollection<RawDocumentBuf> {
+        self.get_collection(collection_name).clone_with_type()
+    }
+
+    /// Runs a find and collects its documents, in the store's transaction if it belongs to one.
+    async fn find_all<T>(&self, find: Find<'_, T>) -> mongodb::error::Result<Vec<T>>
+    where
+        T: DeserializeOwned + Send + Sync + Unpin,
+    {
+        match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                find.session(&mut *session).await?.stream(&mut session).try_collect().await
+            },
+            None => find.await?.try_collect().await,
+        }
+    }
+
+    /// Runs an aggregation and collects its documents, in the store's transaction if it
+    /// belongs to one.
+    async fn aggregate_all(&self, aggregate: AggregateAction<'_>) -> mongodb::error::Result<Vec<Document>> {
+        match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                aggregate.session(&mut *session).await?.stream(&mut session).try_collect().await
+            },
+            None => aggregate.await?.try_collect().await,
+        }
+    }
+
+    /// Sanitizes a document for writing and sets its `_id`.
+    ///
+    /// Unlike reads, writes build an owned [`Document`]: backends are handed documents as
+    /// [`Bson`], and the driver serializes the result once when sending it.
     fn prepare_document(&self, id: &Uuid, document: &Bson) -> DocumentStoreResult<Document> {
-        Ok(Document::from_iter(
-            ValueSanitizer::sanitize_value(document)
-                .as_document()
-                .cloned()
-                .ok_or_else(|| DocumentStoreError::InvalidDocument("Expected document".into()))?
-                .into_iter()
-                .chain(vec![("_id".to_string(), id.into())].into_iter()),
+        let Bson::Document(document) = document else {
+            return Err(DocumentStoreError::InvalidDocument("Expected document".into()));
+        };
+
+        let mut prepared = document
+            .iter()
+            .map(|(k, v)| (ValueSanitizer::sanitize_string(k), ValueSanitizer::sanitize_value(v)))
+            .collect::<Document>();
+        prepared.insert("_id", self.encoding.encode_id(*id));
+
+        Ok(prepared)
+    }
+
+    /// Matches the `_id` of the given documents in every representation they may be stored in.
+    fn id_filter(&self, ids: impl IntoIterator<Item = Uuid>) -> Document {
+        doc! { "_id": { "$in": ids.into_iter().flat_map(|id| self.encoding.id_candidates(id)).collect::<Vec<_>>() } }
+    }
+
+    /// Strips the `_id` from a stored document and reverts sanitization.
+    ///
+    /// Documents without escapes are rewritten element by element without decoding
+    /// any values; only escaped documents take the full restore pass.
+    fn restore_raw_document(document: RawDocumentBuf) -> DocumentStoreResult<RawDocumentBuf> {
+        if ValueSanitizer::is_escaped_raw(&document) {
+            let restored = ValueSanitizer::restore_value(&Bson::Document(Document::try_from(&document)?));
+            let mut restored = match restored {
+                Bson::Document(doc) => doc,
+                _ => unreachable!("restoring a document yields a document"),
+            };
+            restored.remove("_id");
+            return Ok(RawDocumentBuf::try_from(&restored)?);
+        }
+
+        let mut restored = RawDocumentBuf::new();
+        for element in document.iter() {
+            let (key, value) = element?;
+            if key != "_id" {
+                restored.append(key, value);
+            }
+        }
+
+        Ok(restored)
+    }
+
+    fn restore_document(document: RawDocumentBuf) -> DocumentStoreResult<Bson> {
+        Ok(Bson::Document(Document::try_from(Self::restore_raw_document(document)?)?))
+    }
+
+    /// Converts an error of a query, reporting a query that exceeded its timeout as
+    /// [`DocumentStoreError::Timeout`].
+    fn query_error(error: mongodb::error::Error, query: &Query) -> DocumentStoreError {
+        match (&*error.kind, query.timeout) {
+            (ErrorKind::Command(err), Some(timeout)) if err.code == Self::MAX_TIME_MS_EXPIRED => {
+                DocumentStoreError::Timeout(format!("query exceeded its timeout of {:?}", timeout))
+            },
+            _ => Self::backend_error(error),
+        }
+    }
+
+    /// Returns the `maxTimeMS` of a query, rounded up to whole milliseconds.
+    ///
+    /// MongoDB takes a `maxTimeMS` of `0` as no limit, so a query with no time left, such as
+    /// one built from a deadline already passed, fails with [`DocumentStoreError::Timeout`]
+    /// before it's sent, and a timeout under a millisecond becomes one millisecond.
+    fn max_time(query: &Query) -> DocumentStoreResult<Option<Duration>> {
+        match query.timeout {
+            Some(timeout) if timeout.is_zero() => Err(DocumentStoreError::Timeout(
+                "query has no time left to run".to_string()
+            )),
+            Some(timeout) => Ok(Some(Duration::from_millis(timeout.as_nanos().div_ceil(1_000_000) as u64))),
+            None => Ok(None),
+        }
+    }
+
+    /// Converts a write violating a unique index other than the `_id` index into
+    /// [`DocumentStoreError::UniqueViolation`], reading the field and collection from the
+    /// server's message.
+    fn unique_violation(error: &mongodb::error::Error) -> Option<DocumentStoreError> {
+        let message = match &*error.kind {
+            ErrorKind::Write(WriteFailure::WriteError(err)) if err.code == Self::DUPLICATE_KEY => &err.message,
+            ErrorKind::InsertMany(err) => &err.write_errors
+                .iter()
+                .flatten()
+                .find(|err| err.code == Self::DUPLICATE_KEY)?
+                .message,
+            ErrorKind::Command(err) if err.code == Self::DUPLICATE_KEY => &err.message,
+            _ => return None,
+        };
+
+        // E11000 duplicate key error collection: db.users index: email_1 dup key: { email: "a@b.c" }
+        if message.contains("index: _id_ ") {
+            return None;
+        }
+
+        let namespace = message.split("collection: ").nth(1)?.split_whitespace().next()?;
+        let collection = namespace.split_once('.').map_or(namespace, |(_, name)| name);
+        let field = message.split("dup key: { ").nth(1)?.split(": ").next()?.trim_matches('"');
+
+        Some(DocumentStoreError::UniqueViolation(field.to_string(), collection.to_string()))
+    }
+
+    /// Converts a driver error, reporting network errors, server selection failures and
+    /// errors the server labels as retryable as [`DocumentStoreError::Unavailable`].
+    pub(crate) fn backend_error(error: mongodb::error::Error) -> DocumentStoreError {
+        if let Some(violation) = Self::unique_violation(&error) {
+            return violation;
+        }
+
+        let transient = match &*error.kind {
+            ErrorKind::Io(_) | ErrorKind::ServerSelection { .. } | ErrorKind::ConnectionPoolCleared { .. } => true,
+            ErrorKind::Command(err) => Self::TRANSIENT_CODES.contains(&err.code),
+            _ => false,
+        };
+
+        match transient || error.contains_label("RetryableWriteError") || error.contains_label("TransientTransactionError") {
+            true => DocumentStoreError::Unavailable(error.to_string()),
+            false => DocumentStoreError::Backend(error.to_string()),
+        }
+    }
+
+    /// Builds the find options carrying a query's limit, offset, sort, collation, projection and timeout.
+    fn find_options(&self, query: &Query, collection: &str) -> DocumentStoreResult<FindOptions> {
+        let mut options = FindOptions::default();
+
+        if let Some(limit) = query.limit {
+            options.limit = Some(limit as i64);
+        }
+        if let Some(skip) = query.offset {
+            options.skip = Some(skip as u64);
+        }
+        if !query.sort.is_empty() {
+            // Document keys keep insertion order, giving a compound sort in key order
+            options.sort = Some(Document::from_iter(
+                query.sort
+                    .iter()
+                    .map(|sort| (
+                        sort.field.clone(),
+                        Bson::Int32(match sort.direction {
+                            SortDirection::Asc => 1,
+                            SortDirection::Desc => -1,
+                        }),
+                    ))
+            ));
+        }
+        if let Some(fields) = &query.projection {
+            options.projection = Some(Document::from_iter(
+                fields
+                    .iter()
+                    .map(|field| (field.clone(), Bson::Int32(1)))
+            ));
+        }
+        options.collation = self.collation(query, collection)?;
+        options.max_time = Self::max_time(query)?;
+
+        Ok(options)
+    }
+
+    /// Builds the collation giving the sort fields of a query their registered comparators.
+    fn collation(&self, query: &Query, collection: &str) -> DocumentStoreResult<Option<Collation>> {
+        let (mut case_insensitive, mut numeric) = (false, false);
+
+        for sort in &query.sort {
+            match self.comparators.get(collection, &sort.field) {
+                Some(SortComparator::CaseInsensitive) => case_insensitive = true,
+                Some(SortComparator::Numeric) => numeric = true,
+                Some(SortComparator::Custom(_)) => return Err(DocumentStoreError::Backend(format!(
+                    "The custom comparator of {}.{} can't be evaluated by MongoDB",
+                    collection, sort.field
+                ))),
+                None => {},
+            }
+        }
+
+        if !case_insensitive && !numeric {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            Collation::builder()
+                .locale("en")
+                .strength(case_insensitive.then_some(CollationStrength::Secondary))
+                .numeric_ordering(numeric.then_some(true))
+                .build()
         ))
     }
 
-    fn restore_document(&self, document: &Document) -> DocumentStoreResult<Bson> {
-        Ok(ValueSanitizer::restore_value(&Bson::Document(
-            Document::from_iter(
-                document
-                    .clone()
-                    .into_iter()
-                    .filter(|(k, _)| !["_id"].contains(&k.as_str()))
-            )
-        )))
+    /// Collects the fields of the indexes used by an explained query plan.
+    ///
+    /// Plans are trees of stages nested under `inputStage`, `inputStages` or `queryPlan`
+    /// depending on the server version, so every nested document is searched.
+    fn index_fields(plan: &Bson, fields: &mut Vec<String>) {
+        match plan {
+            Bson::Document(stage) => {
+                match (stage.get_str("stage"), stage.get_document("keyPattern")) {
+                    (Ok(name), Ok(keys)) if name.contains("IXSCAN") => {
+                        for key in keys.keys() {
+                            if !fields.contains(key) {
+                                fields.push(key.clone());
+                            }
+                        }
+                    },
+                    // Lookups by ID use the implicit `_id` index without reporting its keys
+                    (Ok("IDHACK" | "EXPRESS_IXSCAN"), _) if !fields.iter().any(|f| f == "_id") => {
+                        fields.push("_id".to_string());
+                    },
+                    _ => {},
+                }
+
+                for value in stage.values() {
+                    Self::index_fields(value, fields);
+                }
+            },
+            Bson::Array(stages) => {
+                for value in stages {
+                    Self::index_fields(value, fields);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Replaces documents by ID, optionally inserting the ones that don't exist.
+    async fn replace_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        upsert: bool,
+    ) -> DocumentStoreResult<WriteReport> {
+        iter(documents)
+            .then(async |(id, doc)| {
+                // `_id` can't be replaced, so a document stored under a previous representation
+                // is deleted and inserted again under the current one
+                let moved = self.delete_previous_ids(id, collection).await?;
+
+                in_session!(self, self.get_collection(collection)
+                    .replace_one(
+                        doc! { "_id": self.encoding.encode_id(id) },
+                        self.prepare_document(&id, &doc)?,
+                    )
+                    .upsert(upsert || moved))
+                    .map(|result| (result, moved))
+                    .map_err(Self::backend_error)
+            })
+            .try_fold(WriteReport::default(), async |mut report, (result, moved)| {
+                report.matched += result.matched_count as usize + usize::from(moved);
+                report.modified += result.modified_count as usize + usize::from(moved);
+                report.upserted += usize::from(result.upserted_id.is_some() && !moved);
+                Ok(report)
+            })
+            .await
+    }
+
+    /// Replaces a document if its stored version is still `version`.
+    ///
+    /// Returns whether the document was modified, or `None` if no document with the ID was
+    /// at the version. A document stored under a previous representation of its ID is
+    /// deleted and inserted again under the current one, like in [`Self::replace_documents`].
+    async fn replace_if_version(
+        &self,
+        id: Uuid,
+        document: &Bson,
+        version_field: &str,
+        version: u64,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<bool>> {
+        let prepared = self.prepare_document(&id, document)?;
+        let current = self.encoding.encode_id(id);
+        let previous = self.encoding
+            .id_candidates(id)
+            .into_iter()
+            .filter(|candidate| *candidate != current)
+            .collect::<Vec<_>>();
+
+        // Documents written before their type had a version don't have the field
+        let version_filter = match version {
+            0 => doc! { "$or": [{ version_field: 0_i64 }, { version_field: { "$exists": false } }] },
+            version => doc! { version_field: version as i64 },
+        };
+
+        let result = in_session!(self, self.get_collection(collection)
+            .replace_one(doc! { "_id": &current, "$and": [&version_filter] }, prepared.clone()))
+            .map_err(Self::backend_error)?;
+
+        if result.matched_count > 0 {
+            return Ok(Some(result.modified_count > 0));
+        }
+        if previous.is_empty() {
+            return Ok(None);
+        }
+
+        let deleted = in_session!(self, self.get_collection(collection)
+            .delete_one(doc! { "_id": { "$in": previous }, "$and": [&version_filter] }))
+            .map_err(Self::backend_error)?
+            .deleted_count > 0;
+
+        if !deleted {
+            return Ok(None);
+        }
+
+        in_session!(self, self.get_collection(collection).insert_one(prepared))
+            .map_err(Self::backend_error)?;
+
+        Ok(Some(true))
+    }
+
+    /// Returns the stored document with the given ID if it's at the given revision.
+    ///
+    /// The document is returned as stored, so a write filtering on it with
+    /// [`Self::unchanged_filter`] only applies if nothing changed it since.
+    async fn stored_at_revision(&self, id: Uuid, revision: &str, collection: &str) -> DocumentStoreResult<Option<Document>> {
+        let stored = self.find_all(self.get_raw_collection(collection).find(self.id_filter([id])).limit(1))
+            .await
+            .map_err(Self::backend_error)?
+            .pop();
+
+        let Some(stored) = stored else {
+            return Ok(None);
+        };
+
+        Ok(
+            (document_revision(&Self::restore_document(stored.clone())?)? == revision)
+                .then(|| Document::try_from(&stored))
+                .transpose()?
+        )
+    }
+
+    /// Matches a document only if it's still exactly the given stored document.
+    fn unchanged_filter(stored: &Document) -> Document {
+        doc! {
+            "_id": stored.get("_id").cloned().unwrap_or(Bson::Null),
+            "$expr": { "$eq": ["$$ROOT", { "$literal": stored.clone() }] },
+        }
+    }
+
+    /// Replaces a document if its stored revision is still `revision`.
+    ///
+    /// Returns whether the document was modified, or `None` if no document with the ID was
+    /// at the revision.
+    async fn replace_if_match(&self, id: Uuid, document: &Bson, revision: &str, collection: &str) -> DocumentStoreResult<Option<bool>> {
+        let Some(stored) = self.stored_at_revision(id, revision, collection).await? else {
+            return Ok(None);
+        };
+
+        let prepared = self.prepare_document(&id, document)?;
+        let filter = Self::unchanged_filter(&stored);
+
+        if stored.get("_id") == Some(&self.encoding.encode_id(id)) {
+            let result = in_session!(self, self.get_collection(collection).replace_one(filter, prepared))
+                .map_err(Self::backend_error)?;
+
+            return Ok((result.matched_count > 0).then_some(result.modified_count > 0));
+        }
+
+        // `_id` can't be replaced, so a document stored under a previous representation is
+        // deleted and inserted again under the current one
+        let deleted = in_session!(self, self.get_collection(collection).delete_one(filter))
+            .map_err(Self::backend_error)?
+            .deleted_count > 0;
+
+        if !deleted {
+            return Ok(None);
+        }
+
+        in_session!(self, self.get_collection(collection).insert_one(prepared))
+            .map_err(Self::backend_error)?;
+
+        Ok(Some(true))
     }
 
-    async fn shutdown(self) -> DocumentStoreResult<()> {
-        self.client.shutdown().await;
+    /// Deletes a document stored under a representation of its ID other than the current one.
+    ///
+    /// Returns whether a document was deleted. Without a compatibility encoding there is no
+    /// other representation and nothing is deleted.
+    async fn delete_previous_ids(&self, id: Uuid, collection: &str) -> DocumentStoreResult<bool> {
+        let current = self.encoding.encode_id(id);
+        let previous = self.encoding
+            .id_candidates(id)
+            .into_iter()
+            .filter(|candidate| *candidate != current)
+            .collect::<Vec<_>>();
+
+        if previous.is_empty() {
+            return Ok(false);
+        }
+
+        Ok(
+            in_session!(self, self.get_collection(collection)
+                .delete_one(doc! { "_id": { "$in": previous } }))
+                .map_err(Self::backend_error)?
+                .deleted_count > 0
+        )
+    }
+
+    /// Round-trips a `ping` command to the server.
+    ///
+    /// The driver connects lazily, so this is the cheapest way to confirm that the
+    /// server is reachable and the credentials are accepted.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Unavailable`] if the server cannot be reached.
+    pub async fn ping(&self) -> DocumentStoreResult<()> {
+        self.client
+            .database("admin")
+            .run_command(doc! { "ping": 1 })
+            .await
+            .map_err(Self::backend_error)?;
 
         Ok(())
     }
+
+    /// Checks that the authenticated user may read and write documents in the configured database.
+    ///
+    /// Connections without authentication are assumed to have full access.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Backend`] if the privileges cannot be inspected, or
+    /// [`DocumentStoreError::Initialization`] listing the missing actions.
+    pub async fn verify_permissions(&self) -> DocumentStoreResult<()> {
+        let status = self.client
+            .database(&self.database)
+            .run_command(doc! { "connectionStatus": 1, "showPrivileges": true })
+            .await
+            .map_err(Self::backend_error)?;
+
+        let auth_info = status
+            .get_document("authInfo")
+            .map_err(|e| DocumentStoreError::Backend(e.to_string()))?;
+
+        if auth_info
+            .get_array("authenticatedUsers")
+            .map(|users| users.is_empty())
+            .unwrap_or(true)
+        {
+            return Ok(());
+        }
+
+        let granted = auth_info
+            .get_array("authenticatedUserPrivileges")
+            .map(|privileges| {
+                privileges
+                    .iter()
+                    .filter_map(Bson::as_document)
+                    .filter(|privilege| {
+                        privilege
+                            .get_document("resource")
+                            .map(|resource| self.covers_database(resource))
+                            .unwrap_or(false)
+                    })
+                    .filter_map(|privilege| privilege.get_array("actions").ok())
+                    .flatten()
+                    .filter_map(Bson::as_str)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        let missing = Self::REQUIRED_ACTIONS
+            .iter()
+            .filter(|action| !granted.contains(action))
+            .copied()
+            .collect::<Vec<_>>();
+
+        if !missing.is_empty() {
+            return Err(DocumentStoreError::Initialization(format!(
+                "Missing privileges on database '{}': {}",
+                self.database,
+                missing.join(", ")
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Pushes a JSON Schema to a collection as its MongoDB validator, creating the collection
+    /// if it doesn't exist.
+    ///
+    /// The server then rejects every write leaving an invalid document, partial updates
+    /// included, which [`SchemaStore`](doclayer_core::schema::SchemaStore) can't check. See
+    /// [`crate::schema`] for how the schema is translated into a `$jsonSchema`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Backend`] if the server rejects the validator, such as
+    /// for missing `collMod` privileges.
+    pub async fn apply_schema(&self, collection: &str, schema: &JsonSchema) -> DocumentStoreResult<()> {
+        self.ensure_collection(collection).await?;
+
+        let _permit = self.permit().await?;
+
+        self.client
+            .database(&self.database)
+            .run_command(doc! {
+                "collMod": ValueSanitizer::sanitize_string(collection),
+                "validator": { "$jsonSchema": mongo_json_schema(schema) },
+            })
+            .await
+            .map_err(Self::backend_error)?;
+
+        Ok(())
+    }
+
+    /// Pushes the schema of every collection in a registry as its validator, see
+    /// [`apply_schema`](Self::apply_schema).
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error applying a schema; the schemas after it aren't applied.
+    pub async fn apply_schemas(&self, schemas: &SchemaRegistry) -> DocumentStoreResult<()> {
+        for (collection, schema) in schemas.schemas() {
+            self.apply_schema(&collection, &schema).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether a privilege resource applies to every collection of the configured database.
+    fn covers_database(&self, resource: &Document) -> bool {
+        if resource.get_bool("anyResource").unwrap_or(false) {
+            return true;
+        }
+
+        let db = resource.get_str("db").unwrap_or_default();
+        let collection = resource.get_str("collection").unwrap_or_default();
+
+        (db.is_empty() || db == self.database) && collection.is_empty()
+    }
+
+    fn filter_document(&self, query: &Query) -> DocumentStoreResult<Document> {
+        match &query.filter {
+            Some(expr) => MongoQueryTranslator::new(&self.operators).visit_expr(expr),
+            None => Ok(doc! {}),
+        }
+    }
 }
 
 #[async_trait]
 impl StoreBackend for MongoDbStore {
-    async fn insert_documents(&self, documents: Vec<(Uuid, Bson)>, collection: &str) -> DocumentStoreResult<()> {
-        self.get_collection(collection)
-            .insert_many(
-                documents
-                    .iter()
-                    .map(|(id, doc)| self.prepare_document(id, doc))
-                    .collect::<DocumentStoreResult<Vec<Document>>>()?,
-            )
-            .await
-            .map_err(|e| DocumentStoreError::Backend(e.to_string()))?;
+    async fn insert_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: InsertPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        let _permit = self.permit().await?;
+
+        if policy == InsertPolicy::Replace {
+            return self.replace_documents(documents, collection, true).await;
+        }
+
+        if documents.is_empty() {
+            return Ok(WriteReport::default());
+        }
+
+        let existing = in_session!(self, self.get_collection(collection)
+            .distinct("_id", self.id_filter(documents.iter().map(|(id, _)| *id))))
+            .map_err(Self::backend_error)?;
+
+        let mut report = WriteReport::default();
+        let mut seen = HashSet::new();
+        let mut inserts = Vec::new();
+
+        // Check every ID up front, including repeats within the batch, so a conflict leaves
+        // the collection untouched
+        for (id, document) in &documents {
+            if seen.insert(*id) && !self.encoding.id_candidates(*id).iter().any(|candidate| existing.contains(candidate)) {
+                inserts.push((*id, self.prepare_document(id, document)?));
+            } else if policy == InsertPolicy::Skip {
+                report.matched += 1;
+            } else {
+                return Err(DocumentStoreError::DocumentAlreadyExists(id.to_string(), collection.to_string()));
+            }
+        }
+
+        if inserts.is_empty() {
+            return Ok(report);
+        }
+
+        let ids = inserts.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+
+        // A document inserted concurrently violates the `_id` index
+        in_session!(self, self.get_collection(collection).insert_many(inserts.into_iter().map(|(_, document)| document)))
+            .map_err(|error| {
+                let duplicate = match &*error.kind {
+                    ErrorKind::InsertMany(err) => err.write_errors
+                        .iter()
+                        .flatten()
+                        .find(|err| err.code == Self::DUPLICATE_KEY && err.message.contains("index: _id_ "))
+                        .and_then(|err| ids.get(err.index)),
+                    _ => None,
+                };
+
+                match duplicate {
+                    Some(id) => DocumentStoreError::DocumentAlreadyExists(id.to_string(), collection.to_string()),
+                    None => Self::backend_error(error),
+                }
+            })?;
+
+        report.upserted += ids.len();
+        Ok(report)
+    }
+
+    async fn update_documents(
+        &self,
+        documents: Vec<(Uuid, Bson)>,
+        collection: &str,
+        policy: MissingDocumentPolicy,
+    ) -> DocumentStoreResult<WriteReport> {
+        let permit = self.permit().await?;
+
+        // Check every ID up front so an error leaves the collection untouched
+        if policy == MissingDocumentPolicy::Error && !documents.is_empty() {
+            let existing = in_session!(self, self.get_collection(collection)
+                .distinct("_id", self.id_filter(documents.iter().map(|(id, _)| *id))))
+                .map_err(Self::backend_error)?;
+
+            if let Some((id, _)) = documents.iter().find(|(id, _)| !self.encoding.id_candidates(*id).iter().any(|candidate| existing.contains(candidate))) {
+                // The existence check takes its own permit
+                drop(permit);
+
+                if existing.is_empty() && !self.collection_exists(collection).await? {
+                    return Err(DocumentStoreError::CollectionNotFound(collection.to_string()));
+                }
+
+                return Err(DocumentStoreError::DocumentNotFound(id.to_string(), collection.to_string()));
+            }
+        }
+
+        self.replace_documents(documents, collection, policy == MissingDocumentPolicy::Upsert).await
+    }
+
+    async fn update_documents_if_version(
+        &self,
+        documents: Vec<(Uuid, Bson, u64)>,
+        version_field: &str,
+        collection: &str,
+    ) -> DocumentStoreResult<WriteReport> {
+        let permit = self.permit().await?;
+        let mut report = WriteReport::default();
+
+        // Each document is checked and replaced by a single filtered write, so a concurrent
+        // writer can't slip in between; run in a transaction to write all or none
+        for (id, document, version) in documents {
+            match self.replace_if_version(id, &document, version_field, version, collection).await? {
+                Some(modified) => {
+                    report.matched += 1;
+                    report.modified += usize::from(modified);
+                },
+                None => {
+                    // The existence checks take their own permit
+                    drop(permit);
+
+                    if self.get_documents(vec![id], collection).await?.is_empty() {
+                        if !self.collection_exists(collection).await? {
+                            return Err(DocumentStoreError::CollectionNotFound(collection.to_string()));
+                        }
+
+                        return Err(DocumentStoreError::DocumentNotFound(id.to_string(), collection.to_string()));
+                    }
+
+                    return Err(DocumentStoreError::VersionConflict(id.to_string(), collection.to_string(), version));
+                },
+            }
+        }
+
+        Ok(report)
+    }
+
+    async fn update_documents_if_match(&self, documents: Vec<(Uuid, Bson, String)>, collection: &str) -> DocumentStoreResult<WriteReport> {
+        let _permit = self.permit().await?;
+        let mut report = WriteReport::default();
+
+        // Each document is replaced by a write filtering on its content as it was checked, so
+        // a concurrent writer can't slip in between; run in a transaction to write all or none
+        for (id, document, revision) in documents {
+            let Some(modified) = self.replace_if_match(id, &document, &revision, collection).await? else {
+                return Err(DocumentStoreError::PreconditionFailed(id.to_string(), collection.to_string()));
+            };
+
+            report.matched += 1;
+            report.modified += usize::from(modified);
+        }
+
+        Ok(report)
+    }
+
+    async fn upsert_documents(&self, documents: Vec<(Uuid, Bson)>, collection: &str) -> DocumentStoreResult<()> {
+        let _permit = self.permit().await?;
+
+        self.replace_documents(documents, collection, true).await?;
 
         Ok(())
     }
 
-    async fn update_documents(&self, documents: Vec<(Uuid, Bson)>, collection: &str) -> DocumentStoreResult<()> {
-        iter(documents)
-            .then(async |(id, doc)| self.get_collection(collection)
-                .update_one(
-                    doc! { "_id": id },
-                    doc! { "$set": self.prepare_document(&id, &doc)? },
-                )
+    async fn delete_documents(&self, ids: Vec<Uuid>, collection: &str) -> DocumentStoreResult<usize> {
+        let _permit = self.permit().await?;
+
+        Ok(
+            in_session!(self, self.get_collection(collection)
+                .delete_many(self.id_filter(ids)))
+                .map_err(Self::backend_error)?
+                .deleted_count as usize
+        )
+    }
+
+    async fn delete_documents_if_match(&self, documents: Vec<(Uuid, String)>, collection: &str) -> DocumentStoreResult<usize> {
+        let _permit = self.permit().await?;
+        let mut deleted = 0;
+
+        for (id, revision) in documents {
+            let stored = self.stored_at_revision(id, &revision, collection).await?;
+            let removed = match stored {
+                Some(stored) => in_session!(self, self.get_collection(collection).delete_one(Self::unchanged_filter(&stored)))
+                    .map_err(Self::backend_error)?
+                    .deleted_count > 0,
+                None => false,
+            };
+
+            if !removed {
+                return Err(DocumentStoreError::PreconditionFailed(id.to_string(), collection.to_string()));
+            }
+
+            deleted += 1;
+        }
+
+        Ok(deleted)
+    }
+
+    async fn update_by_query(&self, filter: Expr, update: Update, collection: &str) -> DocumentStoreResult<WriteReport> {
+        if update.is_empty() {
+            return Ok(WriteReport {
+                matched: self.count_documents(Query::builder().filter(filter).build(), collection).await?,
+                ..WriteReport::default()
+            });
+        }
+
+        let _permit = self.permit().await?;
+
+        let result = in_session!(self, self.get_collection(collection)
+            .update_many(
+                MongoQueryTranslator::new(&self.operators).visit_expr(&filter)?,
+                MongoUpdateTranslator::translate(&update),
+            ))
+            .map_err(Self::backend_error)?;
+
+        Ok(WriteReport {
+            matched: result.matched_count as usize,
+            modified: result.modified_count as usize,
+            upserted: 0,
+        })
+    }
+
+    async fn delete_by_query(&self, filter: Expr, collection: &str) -> DocumentStoreResult<usize> {
+        let _permit = self.permit().await?;
+
+        Ok(
+            in_session!(self, self.get_collection(collection)
+                .delete_many(MongoQueryTranslator::new(&self.operators).visit_expr(&filter)?))
+                .map_err(Self::backend_error)?
+                .deleted_count as usize
+        )
+    }
+
+    async fn find_one_and_update(
+        &self,
+        filter: Expr,
+        sort: Vec<Sort>,
+        update: Update,
+        returned: ReturnDocument,
+        collection: &str,
+    ) -> DocumentStoreResult<Option<Bson>> {
+        // MongoDB rejects empty updates, and without one the first match is returned as is
+        if update.is_empty() {
+            let query = Query { filter: Some(filter), sort, limit: Some(1), ..Query::default() };
+
+            return Ok(self.query_documents(query, collection).await?.pop());
+        }
+
+        let _permit = self.permit().await?;
+        let find_options = self.find_options(&Query { sort, ..Query::default() }, collection)?;
+
+        let mut options = FindOneAndUpdateOptions::default();
+        options.sort = find_options.sort;
+        options.collation = find_options.collation;
+        options.return_document = Some(match returned {
+            ReturnDocument::Before => MongoReturnDocument::Before,
+            ReturnDocument::After => MongoReturnDocument::After,
+        });
+
+        in_session!(self, self.get_raw_collection(collection)
+            .find_one_and_update(
+                MongoQueryTranslator::new(&self.operators).visit_expr(&filter)?,
+                MongoUpdateTranslator::translate(&update),
+            )
+            .with_options(options))
+            .map_err(Self::backend_error)?
+            .map(Self::restore_document)
+            .transpose()
+    }
+
+    async fn find_one_and_delete(&self, filter: Expr, sort: Vec<Sort>, collection: &str) -> DocumentStoreResult<Option<Bson>> {
+        let _permit = self.permit().await?;
+        let find_options = self.find_options(&Query { sort, ..Query::default() }, collection)?;
+
+        let mut options = FindOneAndDeleteOptions::default();
+        options.sort = find_options.sort;
+        options.collation = find_options.collation;
+
+        in_session!(self, self.get_raw_collection(collection)
+            .find_one_and_delete(MongoQueryTranslator::new(&self.operators).visit_expr(&filter)?)
+            .with_options(options))
+            .map_err(Self::backend_error)?
+            .map(Self::restore_document)
+            .transpose()
+    }
+
+    async fn get_documents(&self, ids: Vec<Uuid>, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+        let _permit = self.permit().await?;
+
+        Ok(
+            self.find_all(self.get_raw_collection(collection).find(self.id_filter(ids)))
                 .await
-                .map_err(|e| DocumentStoreError::Backend(e.to_string()))
+                .map_err(Self::backend_error)?
+                .into_iter()
+                .map(Self::restore_document)
+                .collect::<DocumentStoreResult<Vec<Bson>>>()?
+        )
+    }
+
+    async fn query_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+        let _permit = self.permit().await?;
+
+        Ok(
+            self.find_all(
+                self.get_raw_collection(collection)
+                    .find(self.filter_document(&query)?)
+                    .with_options(self.find_options(&query, collection)?)
             )
-            .try_collect::<Vec<_>>()
-            .await?;
+                .await
+                .map_err(|e| Self::query_error(e, &query))?
+                .into_iter()
+                .map(Self::restore_document)
+                .collect::<DocumentStoreResult<Vec<Bson>>>()?
+        )
+    }
 
-        Ok(())
+    async fn query_stream(&self, query: Query, collection: &str) -> DocumentStoreResult<DocumentStream> {
+        // A transaction's cursor borrows its session, so a transaction reads the documents up front
+        if self.session.is_some() {
+            let documents = self.query_documents(query, collection).await?;
+            return Ok(iter(documents.into_iter().map(Ok)).boxed());
+        }
+
+        let permit = self.permit().await?;
+
+        Ok(
+            self.get_raw_collection(collection)
+                .find(self.filter_document(&query)?)
+                .with_options(self.find_options(&query, collection)?)
+                .await
+                .map_err(|e| Self::query_error(e, &query))?
+                .map(move |result| {
+                    let _ = &permit;
+                    result
+                        .map_err(|e| Self::query_error(e, &query))
+                        .and_then(Self::restore_document)
+                })
+                .boxed()
+        )
+    }
+
+    async fn query_raw_documents(
+        &self,
+        query: Query,
+        collection: &str,
+    ) -> DocumentStoreResult<Vec<RawDocumentBuf>> {
+        let _permit = self.permit().await?;
+
+        self.find_all(
+            self.get_raw_collection(collection)
+                .find(self.filter_document(&query)?)
+                .with_options(self.find_options(&query, collection)?)
+        )
+            .await
+            .map_err(|e| Self::query_error(e, &query))?
+            .into_iter()
+            .map(Self::restore_raw_document)
+            .collect()
+    }
+
+    async fn count_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<usize> {
+        let _permit = self.permit().await?;
+
+        let mut options = CountOptions::default();
+
+        if let Some(limit) = query.limit {
+            options.limit = Some(limit as u64);
+        }
+        if let Some(skip) = query.offset {
+            options.skip = Some(skip as u64);
+        }
+        options.max_time = Self::max_time(&query)?;
+
+        Ok(
+            in_session!(self, self.get_collection(collection)
+                .count_documents(self.filter_document(&query)?)
+                .with_options(options))
+                .map_err(|e| Self::query_error(e, &query))? as usize
+        )
     }
 
-    async fn delete_documents(&self, ids: Vec<Uuid>, collection: &str) -> DocumentStoreResult<()> {
-        self.get_collection(collection)
-            .delete_many(doc! { "_id": { "$in": ids } })
-            .await
-            .map_err(|e| DocumentStoreError::Backend(e.to_string()))?;
+    async fn distinct(&self, field: &str, filter: Option<Expr>, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+        let _permit = self.permit().await?;
 
-        Ok(())
+        let filter = match &filter {
+            Some(expr) => MongoQueryTranslator::new(&self.operators).visit_expr(expr)?,
+            None => doc! {},
+        };
+
+        Ok(
+            in_session!(self, self.get_collection(collection).distinct(field, filter))
+                .map_err(Self::backend_error)?
+                .iter()
+                .map(ValueSanitizer::restore_value)
+                .collect()
+        )
     }
 
-    async fn get_documents(&self, ids: Vec<Uuid>, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+    async fn aggregate(&self, aggregate: Aggregate, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
+        let _permit = self.permit().await?;
+
         Ok(
-            self.get_collection(collection)
-                .find(doc! { "_id": { "$in": ids } })
-                .await
-                .map_err(|e| DocumentStoreError::Backend(e.to_string()))?
-                .try_collect::<Vec<Document>>()
+            self.aggregate_all(
+                self.get_collection(collection)
+                    .aggregate(MongoAggregateTranslator::pipeline(&aggregate, &self.operators)?)
+            )
                 .await
-                .map_err(|e| DocumentStoreError::Backend(e.to_string()))?
+                .map_err(Self::backend_error)?
                 .into_iter()
-                .map(|doc| self.restore_document(&doc))
-                .collect::<DocumentStoreResult<Vec<Bson>>>()?
+                .map(|doc| MongoAggregateTranslator::restore(&aggregate, doc))
+                .collect()
         )
     }
 
-    async fn query_documents(&self, query: Query, collection: &str) -> DocumentStoreResult<Vec<Bson>> {
-        let mut options = FindOptions::default();
+    async fn explain(&self, query: Query, collection: &str) -> DocumentStoreResult<QueryPlan> {
+        let _permit = self.permit().await?;
 
-        if let Some(limit) = query.limit {
-            options.limit = Some(limit as i64);
+        let options = self.find_options(&query, collection)?;
+        let mut find = doc! {
+            "find": ValueSanitizer::sanitize_string(collection),
+            "filter": self.filter_document(&query)?,
+        };
+
+        if let Some(sort) = options.sort {
+            find.insert("sort", sort);
         }
-        if let Some(skip) = query.offset {
-            options.skip = Some(skip as u64);
+        if let Some(collation) = options.collation {
+            find.insert("collation", bson::serialize_to_bson(&collation)?);
         }
-        if let Some(sort) = &query.sort {
-            options.sort = Some(doc! {
-                sort.field.clone(): match sort.direction {
-                    SortDirection::Asc => 1,
-                    SortDirection::Desc => -1,
-                }
-            })
+        if let Some(projection) = options.projection {
+            find.insert("projection", projection);
+        }
+        if let Some(limit) = options.limit {
+            find.insert("limit", limit);
+        }
+        if let Some(skip) = options.skip {
+            find.insert("skip", skip as i64);
+        }
+        if let Some(max_time) = options.max_time {
+            find.insert("maxTimeMS", max_time.as_millis() as i64);
         }
 
-        Ok(
-            self.get_collection(collection)
-                .find(
-                    if let Some(expr) = &query.filter {
-                        MongoQueryTranslator.visit_expr(expr)?
-                    } else {
-                        doc! {}
-                    },
-                )
-                .with_options(options)
-                .await
-                .map_err(|e| DocumentStoreError::Backend(e.to_string()))?
-                .try_collect::<Vec<Document>>()
-                .await
-                .map_err(|e| DocumentStoreError::Backend(e.to_string()))?
-                .into_iter()
-                .map(|doc| self.restore_document(&doc))
-                .collect::<DocumentStoreResult<Vec<Bson>>>()?
-        )
+        let explained = self.client
+            .database(&self.database)
+            .run_command(doc! { "explain": find, "verbosity": "queryPlanner" })
+            .await
+            .map_err(Self::backend_error)?;
+
+        let mut fields = Vec::new();
+        if let Some(plan) = explained.get_document("queryPlanner").ok().and_then(|planner| planner.get("winningPlan")) {
+            Self::index_fields(plan, &mut fields);
+        }
+
+        Ok(QueryPlan {
+            strategy: match fields.is_empty() {
+                true => ScanStrategy::FullScan,
+                false => ScanStrategy::IndexScan(fields),
+            },
+            details: Bson::Document(explained),
+        })
     }
 
     async fn current_revision_id(&self) -> DocumentStoreResult<Option<String>> {
-        let result = self.get_collection("_revisions")
-            .find_one(doc! { "_id": 0 })
-            .await
-            .map_err(|e| DocumentStoreError::Backend(e.to_string()))?;
+        let _permit = self.permit().await?;
+
+        let result = in_session!(self, self.get_collection("_revisions").find_one(doc! { "_id": 0 }))
+            .map_err(Self::backend_error)?;
 
         if let Some(doc) = result {
             if let Some(Bson::String(rev_id)) = doc.get("revision_id") {
@@ -175,44 +1194,76 @@ impl StoreBackend for MongoDbStore {
     }
 
     async fn set_revision_id(&self, revision_id: &str) -> DocumentStoreResult<()> {
-        self.get_collection("_revisions")
+        let _permit = self.permit().await?;
+
+        in_session!(self, self.get_collection("_revisions")
             .update_one(
                 doc! { "_id": 0 },
                 doc! { "$set": { "revision_id": revision_id } },
             )
-            .upsert(true)
-            .await
-            .map_err(|e| DocumentStoreError::Backend(e.to_string()))?;
+            .upsert(true))
+            .map_err(Self::backend_error)?;
 
         Ok(())
     }
 
     async fn create_collection(&self, name: &str) -> DocumentStoreResult<()> {
-        self.client
+        let _permit = self.permit().await?;
+
+        in_session!(self, self.client
             .database(&self.database)
-            .create_collection(&ValueSanitizer::sanitize_string(name))
-            .await
-            .map_err(|e| DocumentStoreError::Backend(e.to_string()))?;
+            .create_collection(&ValueSanitizer::sanitize_string(name)))
+            .map_err(|e| match &*e.kind {
+                ErrorKind::Command(err) if err.code == Self::NAMESPACE_EXISTS => {
+                    DocumentStoreError::CollectionAlreadyExists(name.to_string())
+                },
+                _ => Self::backend_error(e),
+            })?;
 
         Ok(())
     }
 
+    async fn ensure_collection(&self, name: &str) -> DocumentStoreResult<()> {
+        // Creating directly and ignoring NamespaceExists avoids the check-then-create race
+        // between replicas starting up at the same time
+        match self.create_collection(name).await {
+            Err(DocumentStoreError::CollectionAlreadyExists(_)) => Ok(()),
+            result => result,
+        }
+    }
+
+    async fn collection_exists(&self, name: &str) -> DocumentStoreResult<bool> {
+        let _permit = self.permit().await?;
+
+        Ok(
+            !self.client
+                .database(&self.database)
+                .list_collection_names()
+                .filter(doc! { "name": ValueSanitizer::sanitize_string(name) })
+                .await
+                .map_err(Self::backend_error)?
+                .is_empty()
+        )
+    }
+
     async fn drop_collection(&self, name: &str) -> DocumentStoreResult<()> {
-        self.get_collection(name)
-            .drop()
-            .await
-            .map_err(|e| DocumentStoreError::Backend(e.to_string()))?;
+        let _permit = self.permit().await?;
+
+        in_session!(self, self.get_collection(name).drop())
+            .map_err(Self::backend_error)?;
 
         Ok(())
     }
 
     async fn list_collections(&self) -> DocumentStoreResult<Vec<String>> {
+        let _permit = self.permit().await?;
+
         Ok(
             self.client
                 .database(&self.database)
                 .list_collection_names()
                 .await
-                .map_err(|e| DocumentStoreError::Backend(e.to_string()))?
+                .map_err(Self::backend_error)?
                 .into_iter()
                 .filter(|name| name != "_revisions")
                 .collect()
@@ -220,43 +1271,48 @@ impl StoreBackend for MongoDbStore {
     }
 
     async fn add_field(&self, collection: &str, field: &str, default: Bson) -> DocumentStoreResult<()> {
-        self.get_collection(collection)
+        let _permit = self.permit().await?;
+
+        in_session!(self, self.get_collection(collection)
             .update_many(
                 doc! { field: { "$exists": false } },
                 doc! { "$set": { field: ValueSanitizer::sanitize_value(&default) } },
-            )
-            .await
-            .map_err(|e| DocumentStoreError::Backend(e.to_string()))?;
+            ))
+            .map_err(Self::backend_error)?;
 
         Ok(())
     }
 
     async fn drop_field(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
-        self.get_collection(collection)
+        let _permit = self.permit().await?;
+
+        in_session!(self, self.get_collection(collection)
             .update_many(
                 doc! {},
                 doc! { "$unset": { field: "" } },
-            )
-            .await
-            .map_err(|e| DocumentStoreError::Backend(e.to_string()))?;
+            ))
+            .map_err(Self::backend_error)?;
 
         Ok(())
     }
 
     async fn rename_field(&self, collection: &str, field: &str, new: &str) -> DocumentStoreResult<()> {
-        self.get_collection(collection)
+        let _permit = self.permit().await?;
+
+        in_session!(self, self.get_collection(collection)
             .update_many(
                 doc! { field: { "$exists": true } },
                 doc! { "$rename": { field: new } },
-            )
-            .await
-            .map_err(|e| DocumentStoreError::Backend(e.to_string()))?;
+            ))
+            .map_err(Self::backend_error)?;
 
         Ok(())
     }
 
     async fn add_index(&self, collection: &str, field: &str, unique: bool) -> DocumentStoreResult<()> {
-        self.get_collection(collection)
+        let _permit = self.permit().await?;
+
+        in_session!(self, self.get_collection(collection)
             .create_index(
                 IndexModel::builder()
                 .keys(doc! { field: 1 })
@@ -266,30 +1322,107 @@ impl StoreBackend for MongoDbStore {
                     .build()
                 )
                 .build()
-            )
-            .await
-            .map_err(|e| DocumentStoreError::Backend(e.to_string()))?;
+            ))
+            .map_err(Self::backend_error)?;
 
         Ok(())
     }
 
     async fn drop_index(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
-        self.get_collection(collection)
-            .drop_index(field)
-            .await
-            .map_err(|e| DocumentStoreError::Backend(e.to_string()))?;
+        let _permit = self.permit().await?;
+
+        in_session!(self, self.get_collection(collection).drop_index(field))
+            .map_err(Self::backend_error)?;
+
+        Ok(())
+    }
+
+    async fn expire_documents(&self, collection: &str, field: &str) -> DocumentStoreResult<()> {
+        let _permit = self.permit().await?;
+
+        // A TTL index expiring after zero seconds deletes each document at the datetime in its field
+        in_session!(self, self.get_collection(collection)
+            .create_index(
+                IndexModel::builder()
+                .keys(doc! { field: 1 })
+                .options(
+                    IndexOptions::builder()
+                    .expire_after(Duration::ZERO)
+                    .build()
+                )
+                .build()
+            ))
+            .map_err(Self::backend_error)?;
 
         Ok(())
     }
 
-    async fn shutdown(self) -> DocumentStoreResult<()> {
-        self.shutdown().await
+    async fn begin_transaction(&self) -> DocumentStoreResult<Transaction> {
+        if self.session.is_some() {
+            return Err(DocumentStoreError::Unsupported("MongoDB transactions can't be nested".into()));
+        }
+
+        let _permit = self.permit().await?;
+
+        let mut session = self.client
+            .start_session()
+            .await
+            .map_err(Self::backend_error)?;
+        session
+            .start_transaction()
+            .await
+            .map_err(Self::backend_error)?;
+
+        Ok(Transaction::new(MongoDbTransaction::new(self, session)))
+    }
+
+    fn encoding(&self) -> &Encoding {
+        &self.encoding
+    }
+
+    async fn close(&self) -> DocumentStoreResult<()> {
+        // A transaction shares the pool of the store it was begun from, which stays open
+        if self.session.is_some() {
+            return Ok(());
+        }
+
+        // Shutting down a clone closes the connection pool shared by every handle
+        self.client.clone().shutdown().await;
+
+        Ok(())
     }
 }
 
+/// Builder for constructing [`MongoDbStore`] instances.
+///
+/// The connection string is parsed when [`build`](StoreBackendBuilder::build) is called,
+/// after which any explicitly configured pool or TLS settings override the values
+/// found in the DSN.
 pub struct MongoDbStoreBuilder {
     dsn: String,
     database: String,
+    min_pool_size: Option<u32>,
+    max_pool_size: Option<u32>,
+    tls: Option<MongoDbTlsConfig>,
+    limiter: Option<ConcurrencyLimiter>,
+    comparators: SortComparators,
+    operators: CustomOperators,
+    encoding: Encoding,
+}
+
+/// TLS settings applied on top of the options parsed from the connection string.
+///
+/// Settings left as `None` keep the value of the connection string, such as its `tlsCAFile`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MongoDbTlsConfig {
+    /// Whether TLS should be used for the connection.
+    pub enabled: bool,
+    /// Path to the CA certificate file used to verify the server.
+    pub ca_file: Option<PathBuf>,
+    /// Path to the client certificate/key file presented to the server.
+    pub cert_key_file: Option<PathBuf>,
+    /// Whether invalid server certificates should be accepted. Never enable this in production.
+    pub allow_invalid_certificates: Option<bool>,
 }
 
 impl MongoDbStoreBuilder {
@@ -297,7 +1430,327 @@ impl MongoDbStoreBuilder {
         Self {
             dsn: dsn.to_string(),
             database: database.to_string(),
+            min_pool_size: None,
+            max_pool_size: None,
+            tls: None,
+            limiter: None,
+            comparators: SortComparators::new(),
+            operators: CustomOperators::new(),
+            encoding: Encoding::new(),
+        }
+    }
+
+    /// Creates a builder from environment variables sharing the given prefix.
+    ///
+    /// The following variables are read, where `{PREFIX}` is the given prefix
+    /// (a trailing `_` is added if missing, an empty prefix reads the bare names):
+    ///
+    /// | Variable | Required | Description |
+    /// |----------|----------|-------------|
+    /// | `{PREFIX}DSN` | yes | MongoDB connection string |
+    /// | `{PREFIX}DATABASE` | yes | Database name |
+    /// | `{PREFIX}MIN_POOL_SIZE` | no | Minimum connection pool size |
+    /// | `{PREFIX}MAX_POOL_SIZE` | no | Maximum connection pool size |
+    /// | `{PREFIX}MAX_CONCURRENT_OPERATIONS` | no | Maximum operations in flight at once |
+    /// | `{PREFIX}TLS_ENABLED` | no | Enables TLS (`true`/`false`, `1`/`0`, `yes`/`no`) |
+    /// | `{PREFIX}TLS_CA_FILE` | no | Path to the CA certificate file |
+    /// | `{PREFIX}TLS_CERT_KEY_FILE` | no | Path to the client certificate/key file |
+    /// | `{PREFIX}TLS_ALLOW_INVALID_CERTIFICATES` | no | Accepts invalid server certificates |
+    ///
+    /// Setting any of the `TLS_*` variables enables TLS unless `TLS_ENABLED` is explicitly false.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Initialization`] listing every missing or invalid
+    /// variable, so all configuration problems can be fixed in a single pass.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// // MONGODB_DSN=mongodb://localhost:27017 MONGODB_DATABASE=app
+    /// let store = MongoDbStoreBuilder::from_env("MONGODB")?.build().await?;
+    /// ```
+    pub fn from_env(prefix: &str) -> DocumentStoreResult<Self> {
+        EnvConfig::new(prefix, |key| std::env::var(key)).into_builder()
+    }
+
+    /// Sets the minimum number of connections kept open in the pool.
+    pub fn with_min_pool_size(mut self, size: u32) -> Self {
+        self.min_pool_size = Some(size);
+        self
+    }
+
+    /// Sets the maximum number of connections in the pool.
+    pub fn with_max_pool_size(mut self, size: u32) -> Self {
+        self.max_pool_size = Some(size);
+        self
+    }
+
+    /// Limits the number of operations in flight at once, queueing the rest.
+    ///
+    /// See [`MongoDbStore::with_limiter`]. Keep the limit at or below the maximum pool size
+    /// so queued operations wait on the limiter rather than on the driver's pool.
+    pub fn with_max_concurrent_operations(self, max: usize) -> Self {
+        self.with_limiter(ConcurrencyLimiter::new(max))
+    }
+
+    /// Limits operations with an existing limiter, e.g. one shared with other stores.
+    pub fn with_limiter(mut self, limiter: ConcurrencyLimiter) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// Orders a field with a built-in comparator when queries sort by it.
+    ///
+    /// See [`MongoDbStore::with_comparator`] for how comparators map to a collation.
+    pub fn with_comparator(mut self, collection: impl Into<String>, field: impl Into<String>, comparator: SortComparator) -> Self {
+        self.comparators.insert(collection, field, comparator);
+        self
+    }
+
+    /// Registers the handler of a custom filter operator.
+    ///
+    /// See [`MongoDbStore::with_operator`].
+    pub fn with_operator(mut self, name: impl Into<String>, operator: CustomOperator) -> Self {
+        self.operators.insert(name, operator);
+        self
+    }
+
+    /// Sets the representation of UUIDs, enums and datetimes in stored documents.
+    ///
+    /// See [`MongoDbStore::with_encoding`] for how document IDs are stored.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Sets the TLS configuration for the connection.
+    pub fn with_tls(mut self, tls: MongoDbTlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Builds the store and verifies the connection before returning it.
+    ///
+    /// [`build`](StoreBackendBuilder::build) succeeds even when the server is unreachable
+    /// because the driver connects lazily. This method pings the server and checks that
+    /// the configured credentials may read and write the database, so misconfiguration
+    /// fails at startup instead of on the first request.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DocumentStoreError::Initialization`] if the client cannot be created, the
+    /// server cannot be reached, or the user lacks the required privileges.
+    pub async fn build_and_verify(self) -> DocumentStoreResult<MongoDbStore> {
+        let store = self.build().await?;
+
+        store
+            .ping()
+            .await
+            .map_err(|e| DocumentStoreError::Initialization(format!("MongoDB ping failed: {}", e)))?;
+        store
+            .verify_permissions()
+            .await
+            .map_err(|e| match e {
+                DocumentStoreError::Initialization(_) => e,
+                other => DocumentStoreError::Initialization(format!(
+                    "Failed to verify MongoDB permissions: {}",
+                    other
+                )),
+            })?;
+
+        Ok(store)
+    }
+
+    async fn client_options(&self) -> DocumentStoreResult<ClientOptions> {
+        let mut options = ClientOptions::parse(&self.dsn)
+            .await
+            .map_err(|e| DocumentStoreError::Initialization(e.to_string()))?;
+
+        if let Some(size) = self.min_pool_size {
+            options.min_pool_size = Some(size);
+        }
+        if let Some(size) = self.max_pool_size {
+            options.max_pool_size = Some(size);
+        }
+        if let Some(tls) = &self.tls {
+            options.tls = Some(match tls.enabled {
+                true => {
+                    // Only the configured settings replace those of the connection string
+                    let mut tls_options = match options.tls.take() {
+                        Some(Tls::Enabled(tls_options)) => tls_options,
+                        _ => TlsOptions::default(),
+                    };
+
+                    if let Some(path) = &tls.ca_file {
+                        tls_options.ca_file_path = Some(path.clone());
+                    }
+                    if let Some(path) = &tls.cert_key_file {
+                        tls_options.cert_key_file_path = Some(path.clone());
+                    }
+                    if let Some(allow) = tls.allow_invalid_certificates {
+                        tls_options.allow_invalid_certificates = Some(allow);
+                    }
+
+                    Tls::Enabled(tls_options)
+                },
+                false => Tls::Disabled,
+            });
+        }
+
+        Ok(options)
+    }
+}
+
+/// Collects environment variables for [`MongoDbStoreBuilder::from_env`], recording
+/// every problem instead of stopping at the first one.
+///
+/// Variables are read with `vars`, which is [`std::env::var`] outside of tests.
+struct EnvConfig<F> {
+    prefix: String,
+    vars: F,
+    missing: Vec<String>,
+    invalid: Vec<String>,
+}
+
+impl<F: Fn(&str) -> Result<String, VarError>> EnvConfig<F> {
+    fn new(prefix: &str, vars: F) -> Self {
+        let prefix = match prefix.is_empty() || prefix.ends_with('_') {
+            true => prefix.to_string(),
+            false => format!("{}_", prefix),
+        };
+
+        Self { prefix, vars, missing: Vec::new(), invalid: Vec::new() }
+    }
+
+    fn key(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+
+    fn read(&mut self, name: &str) -> Option<String> {
+        let key = self.key(name);
+
+        match (self.vars)(&key) {
+            Ok(value) if !value.trim().is_empty() => Some(value.trim().to_string()),
+            Ok(_) | Err(VarError::NotPresent) => None,
+            Err(VarError::NotUnicode(_)) => {
+                self.invalid.push(format!("{} (not valid unicode)", key));
+                None
+            }
+        }
+    }
+
+    fn required(&mut self, name: &str) -> Option<String> {
+        let value = self.read(name);
+        let key = self.key(name);
+
+        // Non-unicode values are already reported as invalid, so only flag truly absent ones
+        if value.is_none() && !self.invalid.iter().any(|entry| entry.starts_with(&key)) {
+            self.missing.push(key);
+        }
+
+        value
+    }
+
+    fn parse<T: FromStr>(&mut self, name: &str, expected: &str) -> Option<T> {
+        let value = self.read(name)?;
+
+        match value.parse::<T>() {
+            Ok(parsed) => Some(parsed),
+            Err(_) => {
+                let key = self.key(name);
+                self.invalid.push(format!("{} ('{}': expected {})", key, value, expected));
+                None
+            }
+        }
+    }
+
+    fn flag(&mut self, name: &str) -> Option<bool> {
+        let value = self.read(name)?;
+
+        match value.to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => Some(true),
+            "false" | "0" | "no" | "off" => Some(false),
+            _ => {
+                let key = self.key(name);
+                self.invalid.push(format!("{} ('{}': expected a boolean)", key, value));
+                None
+            }
+        }
+    }
+
+    fn into_builder(mut self) -> DocumentStoreResult<MongoDbStoreBuilder> {
+        let dsn = self.required("DSN");
+        let database = self.required("DATABASE");
+        let min_pool_size = self.parse::<u32>("MIN_POOL_SIZE", "an unsigned integer");
+        let max_pool_size = self.parse::<u32>("MAX_POOL_SIZE", "an unsigned integer");
+        let max_concurrent = self.parse::<usize>("MAX_CONCURRENT_OPERATIONS", "an unsigned integer");
+        let tls_enabled = self.flag("TLS_ENABLED");
+        let ca_file = self.read("TLS_CA_FILE").map(PathBuf::from);
+        let cert_key_file = self.read("TLS_CERT_KEY_FILE").map(PathBuf::from);
+        let allow_invalid_certificates = self.flag("TLS_ALLOW_INVALID_CERTIFICATES");
+
+        if max_concurrent == Some(0) {
+            self.invalid.push(format!(
+                "{}MAX_CONCURRENT_OPERATIONS ('0': must be at least 1)",
+                self.prefix
+            ));
+        }
+
+        if let (Some(min), Some(max)) = (min_pool_size, max_pool_size)
+            && min > max
+        {
+            self.invalid.push(format!(
+                "{}MIN_POOL_SIZE ('{}': must not exceed {}MAX_POOL_SIZE '{}')",
+                self.prefix, min, self.prefix, max
+            ));
+        }
+
+        if !self.missing.is_empty() || !self.invalid.is_empty() {
+            let mut problems = Vec::new();
+
+            if !self.missing.is_empty() {
+                problems.push(format!("missing {}", self.missing.join(", ")));
+            }
+            if !self.invalid.is_empty() {
+                problems.push(format!("invalid {}", self.invalid.join(", ")));
+            }
+
+            return Err(DocumentStoreError::Initialization(format!(
+                "Invalid MongoDB environment configuration: {}",
+                problems.join("; ")
+            )));
+        }
+
+        let mut builder = MongoDbStoreBuilder::new(
+            &dsn.unwrap_or_default(),
+            &database.unwrap_or_default(),
+        );
+
+        if let Some(size) = min_pool_size {
+            builder = builder.with_min_pool_size(size);
+        }
+        if let Some(size) = max_pool_size {
+            builder = builder.with_max_pool_size(size);
+        }
+        if let Some(max) = max_concurrent {
+            builder = builder.with_max_concurrent_operations(max);
+        }
+
+        let tls_configured = ca_file.is_some()
+            || cert_key_file.is_some()
+            || allow_invalid_certificates.is_some();
+
+        if tls_enabled.is_some() || tls_configured {
+            builder = builder.with_tls(MongoDbTlsConfig {
+                enabled: tls_enabled.unwrap_or(true),
+                ca_file,
+                cert_key_file,
+                allow_invalid_certificates,
+            });
         }
+
+        Ok(builder)
     }
 }
 
@@ -306,14 +1759,217 @@ impl StoreBackendBuilder for MongoDbStoreBuilder {
     type Backend = MongoDbStore;
 
     async fn build(self) -> DocumentStoreResult<Self::Backend> {
-        Ok(MongoDbStore::new(
-            Client::with_options(
-                ClientOptions::parse(&self.dsn)
-                    .await
-                    .map_err(|e| DocumentStoreError::Initialization(e.to_string()))?,
-            )
-            .map_err(|e| DocumentStoreError::Initialization(e.to_string()))?,
+        let mut store = MongoDbStore::new(
+            Client::with_options(self.client_options().await?)
+                .map_err(|e| DocumentStoreError::Initialization(e.to_string()))?,
             self.database,
-        ))
+        );
+        store.comparators = self.comparators;
+        store.operators = self.operators;
+        store.encoding = self.encoding;
+
+        Ok(match self.limiter {
+            Some(limiter) => store.with_limiter(limiter),
+            None => store,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::{Duration, Instant}};
+    use mongodb::options::{Tls, TlsOptions};
+    use doclayer_core::{
+        backend::{StoreBackend, StoreBackendBuilder},
+        error::DocumentStoreError,
+        query::Query,
+    };
+
+    use std::{collections::HashMap, env::VarError};
+
+    use super::{EnvConfig, MongoDbStore, MongoDbStoreBuilder, MongoDbTlsConfig};
+
+    const DSN: &str = "mongodb://localhost:27017/?tls=true&tlsCAFile=/etc/ssl/dsn-ca.pem&tlsAllowInvalidCertificates=true";
+
+    /// Configures a builder from the given variables, with the `APP` prefix.
+    fn from_vars(vars: &[(&str, &str)]) -> Result<MongoDbStoreBuilder, DocumentStoreError> {
+        let vars = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+
+        EnvConfig::new("APP", move |key: &str| vars.get(key).cloned().ok_or(VarError::NotPresent)).into_builder()
+    }
+
+    /// Returns the message of the error configuring a builder from the given variables.
+    fn env_error(vars: &[(&str, &str)]) -> String {
+        match from_vars(vars) {
+            Err(DocumentStoreError::Initialization(message)) => message,
+            Err(other) => panic!("expected an initialization error, got {other:?}"),
+            Ok(_) => panic!("expected an initialization error"),
+        }
+    }
+
+    const REQUIRED: [(&str, &str); 2] = [("APP_DSN", "mongodb://localhost:27017"), ("APP_DATABASE", "app")];
+
+    async fn tls_options(tls: MongoDbTlsConfig) -> Option<Tls> {
+        MongoDbStoreBuilder::new(DSN, "app")
+            .with_tls(tls)
+            .client_options()
+            .await
+            .unwrap()
+            .tls
+    }
+
+    fn enabled(tls: Option<Tls>) -> TlsOptions {
+        match tls {
+            Some(Tls::Enabled(options)) => options,
+            other => panic!("expected TLS to be enabled, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn enabling_tls_keeps_the_dsn_settings() {
+        let options = enabled(tls_options(MongoDbTlsConfig { enabled: true, ..MongoDbTlsConfig::default() }).await);
+
+        assert_eq!(options.ca_file_path, Some(PathBuf::from("/etc/ssl/dsn-ca.pem")));
+        assert_eq!(options.allow_invalid_certificates, Some(true));
+    }
+
+    #[tokio::test]
+    async fn configured_settings_override_only_themselves() {
+        let options = enabled(tls_options(MongoDbTlsConfig {
+            enabled: true,
+            ca_file: Some(PathBuf::from("/etc/ssl/ca.pem")),
+            cert_key_file: Some(PathBuf::from("/etc/ssl/client.pem")),
+            allow_invalid_certificates: None,
+        }).await);
+
+        assert_eq!(options.ca_file_path, Some(PathBuf::from("/etc/ssl/ca.pem")));
+        assert_eq!(options.cert_key_file_path, Some(PathBuf::from("/etc/ssl/client.pem")));
+        assert_eq!(options.allow_invalid_certificates, Some(true));
+
+        let options = enabled(tls_options(MongoDbTlsConfig {
+            enabled: true,
+            allow_invalid_certificates: Some(false),
+            ..MongoDbTlsConfig::default()
+        }).await);
+
+        assert_eq!(options.ca_file_path, Some(PathBuf::from("/etc/ssl/dsn-ca.pem")));
+        assert_eq!(options.allow_invalid_certificates, Some(false));
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn disabling_tls_overrides_the_dsn() {
+        let tls = tls_options(MongoDbTlsConfig { enabled: false, ..MongoDbTlsConfig::default() }).await;
+
+        assert!(matches!(tls, Some(Tls::Disabled)), "{tls:?}");
+    }
+
+    #[test]
+    fn env_config_reads_every_variable() {
+        let builder = from_vars(&[
+            REQUIRED[0],
+            REQUIRED[1],
+            ("APP_MIN_POOL_SIZE", "2"),
+            ("APP_MAX_POOL_SIZE", " 20 "),
+            ("APP_TLS_CA_FILE", "/etc/ssl/ca.pem"),
+            ("APP_TLS_ALLOW_INVALID_CERTIFICATES", "no"),
+        ])
+        .unwrap();
+
+        assert_eq!(builder.database, "app");
+        assert_eq!((builder.min_pool_size, builder.max_pool_size), (Some(2), Some(20)));
+        assert_eq!(builder.tls, Some(MongoDbTlsConfig {
+            enabled: true,
+            ca_file: Some(PathBuf::from("/etc/ssl/ca.pem")),
+            cert_key_file: None,
+            allow_invalid_certificates: Some(false),
+        }));
+    }
+
+    #[test]
+    fn env_config_names_missing_variables() {
+        let message = env_error(&[("APP_DSN", "  ")]);
+
+        assert!(message.contains("missing APP_DSN, APP_DATABASE"), "{message}");
+    }
+
+    #[test]
+    fn env_config_names_a_non_numeric_pool_size() {
+        let message = env_error(&[REQUIRED[0], REQUIRED[1], ("APP_MAX_POOL_SIZE", "many")]);
+
+        assert!(message.contains("invalid APP_MAX_POOL_SIZE ('many': expected an unsigned integer)"), "{message}");
+    }
+
+    #[test]
+    fn env_config_names_an_invalid_tls_flag() {
+        let message = env_error(&[REQUIRED[0], REQUIRED[1], ("APP_TLS_ENABLED", "maybe")]);
+
+        assert!(message.contains("invalid APP_TLS_ENABLED ('maybe': expected a boolean)"), "{message}");
+    }
+
+    #[test]
+    fn env_config_names_a_variable_that_is_not_unicode() {
+        let vars = |key: &str| match key {
+            "APP_DSN" => Err(VarError::NotUnicode(Default::default())),
+            _ => Ok("app".to_string()),
+        };
+
+        let message = match EnvConfig::new("APP_", vars).into_builder() {
+            Err(DocumentStoreError::Initialization(message)) => message,
+            _ => panic!("expected an initialization error"),
+        };
+
+        assert!(message.contains("invalid APP_DSN (not valid unicode)"), "{message}");
+        assert!(!message.contains("missing"), "{message}");
+    }
+
+    #[test]
+    fn env_config_reports_every_problem_at_once() {
+        let message = env_error(&[
+            REQUIRED[0],
+            ("APP_MIN_POOL_SIZE", "10"),
+            ("APP_MAX_POOL_SIZE", "5"),
+            ("APP_MAX_CONCURRENT_OPERATIONS", "0"),
+            ("APP_TLS_ALLOW_INVALID_CERTIFICATES", "sometimes"),
+        ]);
+
+        for problem in [
+            "missing APP_DATABASE",
+            "APP_MIN_POOL_SIZE ('10': must not exceed APP_MAX_POOL_SIZE '5')",
+            "APP_MAX_CONCURRENT_OPERATIONS ('0': must be at least 1)",
+            "APP_TLS_ALLOW_INVALID_CERTIFICATES ('sometimes': expected a boolean)",
+        ] {
+            assert!(message.contains(problem), "{message} should contain {problem}");
+        }
+    }
+
+    #[test]
+    fn timeouts_round_up_to_whole_milliseconds() {
+        let max_time = |timeout| MongoDbStore::max_time(&Query::builder().timeout(timeout).build()).unwrap();
+
+        assert_eq!(max_time(Duration::from_nanos(1)), Some(Duration::from_millis(1)));
+        assert_eq!(max_time(Duration::from_micros(1500)), Some(Duration::from_millis(2)));
+        assert_eq!(max_time(Duration::from_millis(250)), Some(Duration::from_millis(250)));
+        assert_eq!(MongoDbStore::max_time(&Query::default()).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn queries_past_their_deadline_time_out_before_being_sent() {
+        // The driver connects lazily, so no server is needed for queries failing before they're sent
+        let store = MongoDbStoreBuilder::new("mongodb://localhost:27017", "app").build().await.unwrap();
+        let query = || Query::builder().deadline(Instant::now() - Duration::from_secs(1)).build();
+
+        let results = [
+            store.query_documents(query(), "things").await.map(|_| ()),
+            store.count_documents(query(), "things").await.map(|_| ()),
+            store.explain(query(), "things").await.map(|_| ()),
+        ];
+
+        for result in results {
+            assert!(matches!(result, Err(DocumentStoreError::Timeout(_))), "{result:?}");
+        }
+    }
+}
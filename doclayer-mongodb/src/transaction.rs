@@ -0,0 +1,72 @@
+//! MongoDB multi-document transactions.
+//!
+//! A [`MongoDbTransaction`] runs its operations in a transaction of a client session, so
+//! the server applies their writes together on commit. Transactions need a replica set or a
+//! sharded cluster; on a standalone server, the first operation of a transaction fails.
+//!
+//! The server limits the commands allowed in a transaction: collections and indexes can be
+//! created, on MongoDB 4.4 and later, but not dropped, and listing collections and
+//! explaining queries run outside the transaction. Streams returned by
+//! [`query_stream`](doclayer_core::backend::StoreBackend::query_stream) are read up front,
+//! since a transaction's cursor can't outlive the operation holding the session.
+//!
+//! Writes conflicting with another transaction fail with
+//! [`DocumentStoreError::Unavailable`](doclayer_core::error::DocumentStoreError::Unavailable),
+//! as the server labels them transient: retrying the whole transaction may succeed.
+
+use std::sync::Arc;
+use async_trait::async_trait;
+use mea::mutex::Mutex;
+use mongodb::ClientSession;
+use doclayer_core::{
+    backend::DynStoreBackend,
+    error::DocumentStoreResult,
+    transaction::TransactionBackend,
+};
+
+use crate::store::MongoDbStore;
+
+
+/// A transaction of a [`MongoDbStore`], begun with
+/// [`begin_transaction`](doclayer_core::backend::StoreBackend::begin_transaction).
+///
+/// Dropping the transaction without committing it aborts it on the server.
+#[derive(Debug)]
+pub struct MongoDbTransaction {
+    store: MongoDbStore,
+    session: Arc<Mutex<ClientSession>>,
+}
+
+impl MongoDbTransaction {
+    /// Wraps a session whose transaction has been started.
+    pub(crate) fn new(store: &MongoDbStore, session: ClientSession) -> Self {
+        let session = Arc::new(Mutex::new(session));
+
+        Self { store: store.with_session(session.clone()), session }
+    }
+}
+
+#[async_trait]
+impl TransactionBackend for MongoDbTransaction {
+    fn backend(&self) -> &dyn DynStoreBackend {
+        &self.store
+    }
+
+    async fn commit(self: Box<Self>) -> DocumentStoreResult<()> {
+        self.session
+            .lock()
+            .await
+            .commit_transaction()
+            .await
+            .map_err(MongoDbStore::backend_error)
+    }
+
+    async fn rollback(self: Box<Self>) -> DocumentStoreResult<()> {
+        self.session
+            .lock()
+            .await
+            .abort_transaction()
+            .await
+            .map_err(MongoDbStore::backend_error)
+    }
+}
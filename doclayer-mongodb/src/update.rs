@@ -0,0 +1,44 @@
+//! Update translation from doclayer updates to MongoDB update syntax.
+//!
+//! This module translates doclayer's [`Update`] operations into MongoDB
+//! update documents using the `$set`, `$unset`, `$inc`, `$push` and `$pull` operators.
+
+use bson::{Document, Bson};
+
+use doclayer_core::update::{Update, UpdateOp};
+
+use crate::sanitizer::ValueSanitizer;
+
+
+/// Translates doclayer updates into MongoDB update documents.
+///
+/// Values are sanitized the same way as stored documents so that updated
+/// fields round-trip through [`ValueSanitizer::restore_value`].
+pub(crate) struct MongoUpdateTranslator;
+
+impl MongoUpdateTranslator {
+    pub fn translate(update: &Update) -> Document {
+        let mut translated = Document::new();
+
+        for op in &update.ops {
+            let (operator, value) = match op {
+                UpdateOp::Set(_, value) => ("$set", ValueSanitizer::sanitize_value(value)),
+                UpdateOp::Unset(_) => ("$unset", Bson::String(String::new())),
+                UpdateOp::Inc(_, value) => ("$inc", value.clone()),
+                UpdateOp::Push(_, value) => ("$push", ValueSanitizer::sanitize_value(value)),
+                UpdateOp::Pull(_, value) => ("$pull", ValueSanitizer::sanitize_value(value)),
+            };
+
+            if !translated.contains_key(operator) {
+                translated.insert(operator, Document::new());
+            }
+
+            translated
+                .get_document_mut(operator)
+                .expect("operator document was just inserted")
+                .insert(op.field(), value);
+        }
+
+        translated
+    }
+}
@@ -0,0 +1,48 @@
+//! Runs generated filters against the in-memory store and a MongoDB server, which must match
+//! the same documents for every one of them.
+//!
+//! The server is configured like [`MongoDbStoreBuilder::from_env`] with the
+//! `DOCLAYER_TEST_MONGODB` prefix, such as `DOCLAYER_TEST_MONGODB_DSN` and
+//! `DOCLAYER_TEST_MONGODB_DATABASE`. The tests are ignored unless run with `--ignored`, and
+//! fail without a DSN.
+
+use std::env;
+
+use doclayer_core::{backend::StoreBackendBuilder, query::FieldOp};
+use doclayer_memory::InMemoryStore;
+use doclayer_mongodb::{MongoDbStore, MongoDbStoreBuilder};
+use doclayer_test::Differential;
+
+const PREFIX: &str = "DOCLAYER_TEST_MONGODB";
+
+/// Returns the configured MongoDB store.
+async fn mongodb() -> MongoDbStore {
+    assert!(env::var(format!("{PREFIX}_DSN")).is_ok(), "{PREFIX}_DSN must be set");
+
+    MongoDbStoreBuilder::from_env(PREFIX).unwrap().build().await.unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires DOCLAYER_TEST_MONGODB_DSN"]
+async fn mongodb_filters_membership_like_memory() {
+    let mongodb = mongodb().await;
+
+    Differential::new(3)
+        .with_operators([FieldOp::AnyOf, FieldOp::NoneOf])
+        .run(&InMemoryStore::new(), &mongodb)
+        .await
+        .unwrap()
+        .assert_consistent();
+}
+
+#[tokio::test]
+#[ignore = "requires DOCLAYER_TEST_MONGODB_DSN"]
+async fn mongodb_filters_like_memory() {
+    let mongodb = mongodb().await;
+
+    Differential::new(7)
+        .run(&InMemoryStore::new(), &mongodb)
+        .await
+        .unwrap()
+        .assert_consistent();
+}
@@ -0,0 +1,296 @@
+//! Fault injection for resilience tests.
+//!
+//! A [`Chaos`] layer makes a backend misbehave like one over a flaky network: calls fail at a
+//! configured rate, overall or per backend method, take a random time to answer, and batch
+//! writes are occasionally interrupted after applying only some of their documents. Wrapped
+//! around the in-memory store, it exercises retry, timeout and recovery code without a real
+//! database.
+//!
+//! Every random choice comes from a generator seeded by [`Chaos::new`], so a test making the
+//! same calls in the same order sees the same failures on every run. Calls made concurrently
+//! draw in the order they reach the layer, which may differ between runs.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer_test::chaos::{Chaos, Delay};
+//! use std::time::Duration;
+//!
+//! let chaos = Chaos::new(42)
+//!     .with_error_rate(0.1)
+//!     .with_error_rate_for("insert_documents", 0.5)
+//!     .with_latency(Delay::Uniform { min: Duration::from_millis(1), max: Duration::from_millis(20) })
+//!     .with_partial_batch_rate(0.2);
+//! let store = DocumentStore::new(chaos.clone().wrap(InMemoryStore::builder().build().await?))
+//!     .with_retry(5);
+//!
+//! import_orders(&store).await?;
+//! assert!(chaos.stats().errors > 0);
+//! ```
+//!
+//! Injected errors are [`DocumentStoreError::Unavailable`] by default, which retries treat as
+//! transient; [`Chaos::with_error`] makes others. Latency waits with `tokio::time::sleep`, so
+//! the store must run on a tokio runtime.
+
+use crate::rng::SplitMix64;
+use async_trait::async_trait;
+use doclayer_core::{
+    backend::{Layered, Next, Operation, Outcome, StoreBackend, StoreLayer},
+    error::{DocumentStoreError, DocumentStoreResult},
+};
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// A backend whose calls fail and slow down as configured by a [`Chaos`] layer.
+pub type ChaosStore<B> = Layered<Chaos, B>;
+
+type ErrorFactory = Arc<dyn Fn(&Operation) -> DocumentStoreError + Send + Sync>;
+
+/// A distribution of the latency added to calls.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Delay {
+    /// The same latency for every call.
+    Fixed(Duration),
+    /// A latency drawn evenly between `min` and `max`.
+    Uniform { min: Duration, max: Duration },
+    /// A latency drawn from an exponential distribution, mostly short with occasional long
+    /// outliers, like queueing in a loaded server.
+    Exponential { mean: Duration },
+}
+
+impl Delay {
+    fn sample(&self, rng: &mut SplitMix64) -> Duration {
+        match *self {
+            Delay::Fixed(delay) => delay,
+            Delay::Uniform { min, max } => {
+                min + max
+                    .saturating_sub(min)
+                    .mul_f64(rng.fraction())
+            }
+            Delay::Exponential { mean } => mean.mul_f64(-(1.0 - rng.fraction()).ln()),
+        }
+    }
+}
+
+/// Counts of the faults a [`Chaos`] layer has injected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChaosStats {
+    /// Number of calls the layer has seen.
+    pub calls: usize,
+    /// Number of calls failed without reaching the backend.
+    pub errors: usize,
+    /// Number of batch writes failed after applying some of their documents.
+    pub partial_batches: usize,
+    /// Number of calls delayed.
+    pub delayed: usize,
+}
+
+#[derive(Debug)]
+struct ChaosState {
+    rng: SplitMix64,
+    stats: ChaosStats,
+}
+
+/// A [`StoreLayer`] injecting failures and latency into the calls of a backend.
+///
+/// Clones share the random generator and the [`ChaosStats`], so a copy kept by the test sees
+/// the faults injected into the store it wraps. Configure the layer before cloning it.
+#[derive(Clone)]
+pub struct Chaos {
+    error_rate: f64,
+    error_rates: HashMap<&'static str, f64>,
+    latency: Option<Delay>,
+    latencies: HashMap<&'static str, Delay>,
+    partial_batch_rate: f64,
+    error: ErrorFactory,
+    state: Arc<Mutex<ChaosState>>,
+}
+
+impl Chaos {
+    /// Creates a layer injecting nothing, drawing its random choices from `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            error_rate: 0.0,
+            error_rates: HashMap::new(),
+            latency: None,
+            latencies: HashMap::new(),
+            partial_batch_rate: 0.0,
+            error: Arc::new(|operation| {
+                DocumentStoreError::Unavailable(format!("Injected failure in {}", operation.name()))
+            }),
+            state: Arc::new(Mutex::new(ChaosState {
+                rng: SplitMix64(seed),
+                stats: ChaosStats::default(),
+            })),
+        }
+    }
+
+    /// Fails each call with probability `rate`, between `0.0` and `1.0`.
+    pub fn with_error_rate(mut self, rate: f64) -> Self {
+        self.error_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Fails each call to a backend method, such as `insert_documents`, with probability
+    /// `rate`, instead of the overall rate.
+    pub fn with_error_rate_for(mut self, method: &'static str, rate: f64) -> Self {
+        self.error_rates
+            .insert(method, rate.clamp(0.0, 1.0));
+        self
+    }
+
+    /// Delays each call by a latency drawn from `delay`.
+    pub fn with_latency(mut self, delay: Delay) -> Self {
+        self.latency = Some(delay);
+        self
+    }
+
+    /// Delays each call to a backend method by a latency drawn from `delay`, instead of the
+    /// overall latency.
+    pub fn with_latency_for(mut self, method: &'static str, delay: Delay) -> Self {
+        self.latencies.insert(method, delay);
+        self
+    }
+
+    /// Interrupts each batch write of more than one document with probability `rate`: only
+    /// the first documents of the batch, at least one, are applied before the call fails.
+    ///
+    /// Calls already failed by the error rate are not interrupted as well.
+    pub fn with_partial_batch_rate(mut self, rate: f64) -> Self {
+        self.partial_batch_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Makes the injected errors with `error`, which receives the failed call.
+    pub fn with_error(
+        mut self,
+        error: impl Fn(&Operation) -> DocumentStoreError + Send + Sync + 'static,
+    ) -> Self {
+        self.error = Arc::new(error);
+        self
+    }
+
+    /// Wraps a backend so faults are injected into its calls.
+    pub fn wrap<B: StoreBackend + 'static>(self, backend: B) -> ChaosStore<B> {
+        Layered::new(self, backend)
+    }
+
+    /// Returns the faults injected so far.
+    pub fn stats(&self) -> ChaosStats {
+        self.state().stats
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, ChaosState> {
+        // The state is updated without panicking, so poisoning is ignored
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Draws the faults of a call: its latency, whether it fails, and how many documents of
+    /// an interrupted batch are applied.
+    fn plan(&self, operation: &Operation) -> (Option<Duration>, bool, Option<usize>) {
+        let mut state = self.state();
+        let ChaosState { rng, stats } = &mut *state;
+        let name = operation.name();
+
+        stats.calls += 1;
+
+        let delay = self
+            .latencies
+            .get(name)
+            .or(self.latency.as_ref())
+            .map(|delay| delay.sample(rng));
+        if delay.is_some() {
+            stats.delayed += 1;
+        }
+
+        let rate = self
+            .error_rates
+            .get(name)
+            .copied()
+            .unwrap_or(self.error_rate);
+        if rng.fraction() < rate {
+            stats.errors += 1;
+            return (delay, true, None);
+        }
+
+        let applied = batch_len(operation)
+            .filter(|len| *len > 1 && rng.fraction() < self.partial_batch_rate)
+            .map(|len| 1 + rng.below(len - 1));
+        if applied.is_some() {
+            stats.partial_batches += 1;
+        }
+
+        (delay, false, applied)
+    }
+}
+
+impl Debug for Chaos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chaos")
+            .field("error_rate", &self.error_rate)
+            .field("error_rates", &self.error_rates)
+            .field("latency", &self.latency)
+            .field("latencies", &self.latencies)
+            .field("partial_batch_rate", &self.partial_batch_rate)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl StoreLayer for Chaos {
+    async fn call(&self, mut operation: Operation, next: Next<'_>) -> DocumentStoreResult<Outcome> {
+        let (delay, fail, applied) = self.plan(&operation);
+
+        if let Some(delay) = delay {
+            tokio::time::sleep(delay).await;
+        }
+
+        if fail {
+            return Err((self.error)(&operation));
+        }
+
+        if let Some(applied) = applied {
+            let error = (self.error)(&operation);
+
+            truncate_batch(&mut operation, applied);
+            next.run(operation).await?;
+            return Err(error);
+        }
+
+        next.run(operation).await
+    }
+}
+
+/// Returns the number of documents of a batch write, or `None` for other calls.
+fn batch_len(operation: &Operation) -> Option<usize> {
+    match operation {
+        Operation::InsertDocuments { documents, .. }
+        | Operation::UpdateDocuments { documents, .. }
+        | Operation::UpsertDocuments { documents, .. } => Some(documents.len()),
+        Operation::UpdateDocumentsIfVersion { documents, .. } => Some(documents.len()),
+        Operation::UpdateDocumentsIfMatch { documents, .. } => Some(documents.len()),
+        Operation::DeleteDocumentsIfMatch { documents, .. } => Some(documents.len()),
+        Operation::DeleteDocuments { ids, .. } => Some(ids.len()),
+        _ => None,
+    }
+}
+
+/// Keeps only the first `len` documents of a batch write.
+fn truncate_batch(operation: &mut Operation, len: usize) {
+    match operation {
+        Operation::InsertDocuments { documents, .. }
+        | Operation::UpdateDocuments { documents, .. }
+        | Operation::UpsertDocuments { documents, .. } => documents.truncate(len),
+        Operation::UpdateDocumentsIfVersion { documents, .. } => documents.truncate(len),
+        Operation::UpdateDocumentsIfMatch { documents, .. } => documents.truncate(len),
+        Operation::DeleteDocumentsIfMatch { documents, .. } => documents.truncate(len),
+        Operation::DeleteDocuments { ids, .. } => ids.truncate(len),
+        _ => {}
+    }
+}
@@ -0,0 +1,1758 @@
+//! A behavioral test suite for backend implementations.
+//!
+//! The checks pin down the semantics every backend must share with the built-in ones: write
+//! policies and the errors they fail with, filter operators, sorting and paging, partial
+//! updates, unique indexes, collection management and the schema operations migrations rely
+//! on. A third-party [`StoreBackend`](doclayer_core::backend::StoreBackend) passing them
+//! behaves like the in-memory and MongoDB backends for the code written against them.
+//!
+//! [`backend_conformance!`](crate::backend_conformance) generates a test per check, building
+//! a backend for each from an expression:
+//!
+//! ```ignore
+//! // tests/conformance.rs, with tokio as a development dependency
+//! doclayer_test::backend_conformance!(my_backend, MyBackend::connect("test://").await.unwrap());
+//! ```
+//!
+//! [`run_all`] runs every check against one backend from other test harnesses, and each
+//! check is a public function of its own.
+//!
+//! Each check works in a collection of its own, named after it with the
+//! [`COLLECTION_PREFIX`], and drops it before and after running, so checks can run
+//! concurrently against one database. The `revisions` check sets the store's revision, so
+//! run the suite against a database used for testing only.
+
+use bson::{Bson, Uuid, doc};
+use futures::TryStreamExt;
+use std::fmt::{self, Debug};
+
+use doclayer_core::{
+    backend::{DynStoreBackend, InsertPolicy, MissingDocumentPolicy, ReturnDocument},
+    error::{DocumentStoreError, DocumentStoreResult},
+    query::{Expr, Filter, Query, Sort, SortDirection},
+    update::Update,
+    versioned::document_revision,
+};
+
+/// The prefix of the collections the checks work in.
+pub const COLLECTION_PREFIX: &str = "conformance_";
+
+/// A check a backend failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConformanceFailure {
+    /// The name of the check.
+    pub check: &'static str,
+    /// What the backend did differently.
+    pub message: String,
+}
+
+impl fmt::Display for ConformanceFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "conformance check {} failed: {}", self.check, self.message)
+    }
+}
+
+impl std::error::Error for ConformanceFailure {}
+
+/// The result of a conformance check.
+pub type CheckResult = Result<(), ConformanceFailure>;
+
+/// The outcome of [`run_all`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConformanceReport {
+    /// The checks the backend passed.
+    pub passed: Vec<&'static str>,
+    /// The checks the backend failed.
+    pub failures: Vec<ConformanceFailure>,
+}
+
+impl ConformanceReport {
+    /// Returns `true` if the backend passed every check.
+    pub fn is_conformant(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Calls `$callback!` with the given arguments followed by the name of every check, so the
+/// list of checks is kept in one place.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __for_each_conformance_check {
+    (($($callback:tt)*); $($args:tt)*) => {
+        $($callback)*! {
+            [$($args)*]
+            insert_and_get,
+            insert_policies,
+            update_policies,
+            upsert_and_delete,
+            conditional_writes,
+            comparison_operators,
+            string_operators,
+            logical_operators,
+            missing_and_null,
+            array_and_nested_fields,
+            sorting_and_paging,
+            projection,
+            query_stream,
+            distinct,
+            update_by_query,
+            delete_by_query,
+            find_one_and_modify,
+            collections,
+            field_operations,
+            unique_indexes,
+            revisions,
+        }
+    };
+}
+
+macro_rules! run_checks {
+    ([$backend:expr] $($check:ident,)*) => {{
+        let mut report = ConformanceReport::default();
+
+        $(
+            match $check($backend).await {
+                Ok(()) => report.passed.push(stringify!($check)),
+                Err(failure) => report.failures.push(failure),
+            }
+        )*
+
+        report
+    }};
+}
+
+/// Runs every check against a backend, one after the other.
+pub async fn run_all(backend: &dyn DynStoreBackend) -> ConformanceReport {
+    crate::__for_each_conformance_check!((run_checks); backend)
+}
+
+/// Generates a test module running every conformance check against a backend.
+///
+/// The expression is evaluated in each test, inside an async function, so it may `.await`.
+/// The tests run on tokio, which the crate using the macro must depend on with its `macros`
+/// and `rt` features.
+///
+/// ```ignore
+/// doclayer_test::backend_conformance!(memory, InMemoryStore::builder().build().await.unwrap());
+/// ```
+///
+/// A backend relying on something it doesn't own, such as the directory of a file store, can
+/// bind it first. The binding lives until the test ends, so a guard cleaning up on drop does
+/// so once the checks are done:
+///
+/// ```ignore
+/// doclayer_test::backend_conformance!(
+///     file,
+///     let dir = TempDir::new().unwrap();
+///     FileStore::builder(dir.path()).build().await.unwrap()
+/// );
+/// ```
+#[macro_export]
+macro_rules! backend_conformance {
+    ($name:ident, $(let $guard:ident = $setup:expr;)? $backend:expr) => {
+        mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            $crate::__for_each_conformance_check!(($crate::__conformance_tests); $(let $guard = $setup;)? $backend);
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __conformance_tests {
+    ($backend:tt $($check:ident,)*) => {
+        $(
+            #[tokio::test]
+            async fn $check() {
+                $crate::__conformance_test!($check, $backend);
+            }
+        )*
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __conformance_test {
+    ($check:ident, [$(let $guard:ident = $setup:expr;)? $backend:expr]) => {
+        $(let $guard = $setup;)?
+        let backend = $backend;
+
+        if let Err(failure) = $crate::conformance::$check(&backend).await {
+            panic!("{}", failure);
+        }
+    };
+}
+
+/// Runs the steps of a check in its own collection, turning unexpected results into
+/// failures.
+struct Checker<'a> {
+    check: &'static str,
+    backend: &'a dyn DynStoreBackend,
+    collection: String,
+}
+
+impl<'a> Checker<'a> {
+    async fn start(
+        check: &'static str,
+        backend: &'a dyn DynStoreBackend,
+    ) -> Result<Checker<'a>, ConformanceFailure> {
+        let checker = Self {
+            check,
+            backend,
+            collection: format!("{}{}", COLLECTION_PREFIX, check),
+        };
+
+        // A failed earlier run may have left the collection behind
+        checker.clean().await?;
+        Ok(checker)
+    }
+
+    async fn finish(self) -> CheckResult {
+        self.clean().await
+    }
+
+    async fn clean(&self) -> CheckResult {
+        if self.ok(
+            "checking the collection exists",
+            self.backend
+                .collection_exists(&self.collection)
+                .await,
+        )? {
+            self.ok(
+                "dropping the collection",
+                self.backend
+                    .drop_collection(&self.collection)
+                    .await,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn fail(&self, message: String) -> ConformanceFailure {
+        ConformanceFailure { check: self.check, message }
+    }
+
+    fn ok<T>(&self, step: &str, result: DocumentStoreResult<T>) -> Result<T, ConformanceFailure> {
+        result.map_err(|error| self.fail(format!("{}: unexpected error {:?}", step, error)))
+    }
+
+    fn expect_eq<T: PartialEq + Debug>(&self, step: &str, actual: T, expected: T) -> CheckResult {
+        match actual == expected {
+            true => Ok(()),
+            false => Err(self.fail(format!("{}: expected {:?}, got {:?}", step, expected, actual))),
+        }
+    }
+
+    fn expect_err<T: Debug>(
+        &self,
+        step: &str,
+        result: DocumentStoreResult<T>,
+        expected: &str,
+        matches: impl Fn(&DocumentStoreError) -> bool,
+    ) -> CheckResult {
+        match result {
+            Err(error) if matches(&error) => Ok(()),
+            Err(error) => {
+                Err(self.fail(format!("{}: expected {}, got error {:?}", step, expected, error)))
+            }
+            Ok(value) => {
+                Err(self.fail(format!("{}: expected {}, got {:?}", step, expected, value)))
+            }
+        }
+    }
+
+    async fn seed(&self) -> CheckResult {
+        self.ok(
+            "inserting the fixture documents",
+            self.backend
+                .insert_documents(people(), &self.collection, InsertPolicy::ErrorOnConflict)
+                .await,
+        )
+        .map(|_| ())
+    }
+
+    async fn get(&self, step: &str, key: &str) -> Result<Option<Bson>, ConformanceFailure> {
+        self.ok(
+            step,
+            self.backend
+                .get_documents(vec![id(key)], &self.collection)
+                .await,
+        )
+        .map(|mut documents| documents.pop())
+    }
+
+    async fn query(&self, step: &str, query: Query) -> Result<Vec<Bson>, ConformanceFailure> {
+        self.ok(
+            step,
+            self.backend
+                .query_documents(query, &self.collection)
+                .await,
+        )
+    }
+
+    /// Returns the sorted keys of the documents matching a filter.
+    async fn matching(&self, filter: Expr) -> Result<Vec<String>, ConformanceFailure> {
+        let step = format!("querying {}", filter.to_pretty_string());
+        let documents = self
+            .query(&step, Query::builder().filter(filter).build())
+            .await?;
+
+        Ok(sorted(keys(&documents)))
+    }
+
+    async fn expect_matching(&self, filter: Expr, expected: &[&str]) -> CheckResult {
+        let step = format!("filtering with {}", filter.to_pretty_string());
+        let actual = self.matching(filter).await?;
+
+        self.expect_eq(&step, actual, strings(expected))
+    }
+}
+
+/// The fixture documents, keyed by a `key` field also used to derive their IDs.
+fn people() -> Vec<(Uuid, Bson)> {
+    [
+        doc! {
+            "key": "a", "name": "Alice", "age": 30, "city": "Berlin", "active": true,
+            "score": 1.5, "tags": ["admin", "dev"], "nickname": "Ally",
+            "address": { "zip": "10115", "street": "Main St" },
+        },
+        doc! {
+            "key": "b", "name": "Bob", "age": 25, "city": "Paris", "active": false,
+            "score": 2.5, "tags": ["dev"], "nickname": Bson::Null,
+            "address": { "zip": "75001", "street": "Rue de Rivoli" },
+        },
+        doc! {
+            "key": "c", "name": "Carol", "age": 35, "city": "Berlin", "active": true,
+            "score": 3.0, "tags": [],
+            "address": { "zip": "10117", "street": "Unter den Linden" },
+        },
+        doc! {
+            "key": "d", "name": "dave", "age": 40, "city": "Oslo", "active": true,
+            "score": 0.5, "tags": ["ops"], "nickname": "D.",
+            "address": { "zip": "0150", "street": "Karl Johans gate" },
+        },
+        doc! {
+            "key": "e", "name": "Eve", "age": 25, "city": "Paris", "active": false,
+            "score": 2.0, "tags": ["admin"],
+            "address": { "zip": "75002", "street": "Rue Montmartre" },
+        },
+    ]
+    .into_iter()
+    .map(|mut document| {
+        let id = id(document
+            .get_str("key")
+            .expect("every fixture has a key"));
+
+        // Stored documents hold their ID, which wrappers such as audited stores rely on
+        document.insert("id", id);
+
+        (id, Bson::Document(document))
+    })
+    .collect()
+}
+
+/// Returns the fixture document with a key.
+fn person(key: &str) -> Bson {
+    people()
+        .into_iter()
+        .find(|(person, _)| *person == id(key))
+        .map(|(_, document)| document)
+        .expect("the key belongs to a fixture")
+}
+
+/// Derives a stable ID from a key, so checks can refer to documents by key.
+fn id(key: &str) -> Uuid {
+    let mut bytes = [0u8; 16];
+
+    for (byte, value) in bytes.iter_mut().zip(key.bytes()) {
+        *byte = value;
+    }
+    bytes[15] = 0xc0;
+    Uuid::from_bytes(bytes)
+}
+
+fn key(document: &Bson) -> String {
+    document
+        .as_document()
+        .and_then(|document| document.get_str("key").ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn keys(documents: &[Bson]) -> Vec<String> {
+    documents.iter().map(key).collect()
+}
+
+fn sorted(mut keys: Vec<String>) -> Vec<String> {
+    keys.sort();
+    keys
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values
+        .iter()
+        .map(|value| value.to_string())
+        .collect()
+}
+
+/// Inserted documents are read back unchanged, and missing IDs are left out.
+pub async fn insert_and_get(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("insert_and_get", backend).await?;
+
+    let report = c.ok(
+        "inserting into a new collection",
+        backend
+            .insert_documents(people(), &c.collection, InsertPolicy::ErrorOnConflict)
+            .await,
+    )?;
+    c.expect_eq("counting inserted documents", report.upserted, 5)?;
+    c.expect_eq(
+        "checking the collection was created",
+        c.ok(
+            "checking the collection exists",
+            backend
+                .collection_exists(&c.collection)
+                .await,
+        )?,
+        true,
+    )?;
+
+    let documents = c.ok(
+        "getting documents",
+        backend
+            .get_documents(vec![id("a"), id("missing"), id("c")], &c.collection)
+            .await,
+    )?;
+    c.expect_eq("getting two of three IDs", sorted(keys(&documents)), strings(&["a", "c"]))?;
+    for document in &documents {
+        c.expect_eq("reading a document back", document, &person(&key(document)))?;
+    }
+
+    c.expect_eq(
+        "getting from a missing collection",
+        c.ok(
+            "getting from a missing collection",
+            backend
+                .get_documents(vec![id("a")], "conformance_missing_collection")
+                .await,
+        )?,
+        Vec::new(),
+    )?;
+
+    c.finish().await
+}
+
+/// Insert policies treat existing IDs as documented by [`InsertPolicy`].
+pub async fn insert_policies(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("insert_policies", backend).await?;
+    c.seed().await?;
+
+    let changed = Bson::Document(doc! { "key": "a", "name": "Changed" });
+    let new = Bson::Document(doc! { "key": "f", "name": "Frank" });
+
+    c.expect_err(
+        "inserting an existing ID",
+        backend
+            .insert_documents(
+                vec![(id("f"), new.clone()), (id("a"), changed.clone())],
+                &c.collection,
+                InsertPolicy::ErrorOnConflict,
+            )
+            .await,
+        "DocumentAlreadyExists",
+        |error| matches!(error, DocumentStoreError::DocumentAlreadyExists(..)),
+    )?;
+    c.expect_eq(
+        "checking a failed insert wrote nothing",
+        c.get("getting the new document", "f")
+            .await?,
+        None,
+    )?;
+
+    c.expect_err(
+        "inserting an ID twice in one batch",
+        backend
+            .insert_documents(
+                vec![(id("f"), new.clone()), (id("f"), new.clone())],
+                &c.collection,
+                InsertPolicy::ErrorOnConflict,
+            )
+            .await,
+        "DocumentAlreadyExists",
+        |error| matches!(error, DocumentStoreError::DocumentAlreadyExists(..)),
+    )?;
+
+    let report = c.ok(
+        "inserting with InsertPolicy::Skip",
+        backend
+            .insert_documents(
+                vec![(id("a"), changed.clone()), (id("f"), new.clone())],
+                &c.collection,
+                InsertPolicy::Skip,
+            )
+            .await,
+    )?;
+    c.expect_eq(
+        "counting skipped and inserted documents",
+        (report.matched, report.upserted),
+        (1, 1),
+    )?;
+    c.expect_eq(
+        "checking a skipped document is unchanged",
+        c.get("getting the skipped document", "a")
+            .await?,
+        Some(person("a")),
+    )?;
+
+    let report = c.ok(
+        "inserting with InsertPolicy::Replace",
+        backend
+            .insert_documents(
+                vec![(id("a"), changed.clone())],
+                &c.collection,
+                InsertPolicy::Replace,
+            )
+            .await,
+    )?;
+    c.expect_eq(
+        "counting replaced documents",
+        (report.matched, report.modified, report.upserted),
+        (1, 1, 0),
+    )?;
+    c.expect_eq(
+        "checking a replaced document",
+        c.get("getting the replaced document", "a")
+            .await?,
+        Some(changed),
+    )?;
+
+    c.finish().await
+}
+
+/// Missing documents are handled as documented by [`MissingDocumentPolicy`].
+pub async fn update_policies(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("update_policies", backend).await?;
+
+    c.expect_err(
+        "updating in a missing collection",
+        backend
+            .update_documents(
+                vec![(id("a"), person("a"))],
+                &c.collection,
+                MissingDocumentPolicy::Error,
+            )
+            .await,
+        "CollectionNotFound or DocumentNotFound",
+        |error| {
+            matches!(
+                error,
+                DocumentStoreError::CollectionNotFound(_)
+                    | DocumentStoreError::DocumentNotFound(..)
+            )
+        },
+    )?;
+
+    // Not a fixture, so seeding the collection afterwards doesn't conflict with it
+    let grace = Bson::Document(doc! { "key": "g", "name": "Grace" });
+
+    let report = c.ok(
+        "updating in a missing collection with MissingDocumentPolicy::Skip",
+        backend
+            .update_documents(
+                vec![(id("g"), grace.clone())],
+                &c.collection,
+                MissingDocumentPolicy::Skip,
+            )
+            .await,
+    )?;
+    c.expect_eq(
+        "counting updates skipped in a missing collection",
+        (report.matched, report.modified, report.upserted),
+        (0, 0, 0),
+    )?;
+    c.expect_eq(
+        "checking a document skipped in a missing collection wasn't inserted",
+        c.get("getting the skipped document", "g")
+            .await?,
+        None,
+    )?;
+
+    let report = c.ok(
+        "updating in a missing collection with MissingDocumentPolicy::Upsert",
+        backend
+            .update_documents(
+                vec![(id("g"), grace.clone())],
+                &c.collection,
+                MissingDocumentPolicy::Upsert,
+            )
+            .await,
+    )?;
+    c.expect_eq(
+        "counting updates upserted in a missing collection",
+        (report.matched, report.modified, report.upserted),
+        (0, 0, 1),
+    )?;
+    c.expect_eq(
+        "checking a document upserted in a missing collection",
+        c.get("getting the upserted document", "g")
+            .await?,
+        Some(grace),
+    )?;
+
+    c.seed().await?;
+
+    let changed = Bson::Document(doc! { "key": "a", "name": "Changed" });
+    let new = Bson::Document(doc! { "key": "f", "name": "Frank" });
+
+    c.expect_err(
+        "updating a missing document",
+        backend
+            .update_documents(
+                vec![(id("a"), changed.clone()), (id("f"), new.clone())],
+                &c.collection,
+                MissingDocumentPolicy::Error,
+            )
+            .await,
+        "DocumentNotFound",
+        |error| matches!(error, DocumentStoreError::DocumentNotFound(..)),
+    )?;
+    c.expect_eq(
+        "checking a failed update wrote nothing",
+        c.get("getting the existing document", "a")
+            .await?,
+        Some(person("a")),
+    )?;
+
+    let report = c.ok(
+        "updating with MissingDocumentPolicy::Skip",
+        backend
+            .update_documents(
+                vec![(id("a"), changed.clone()), (id("f"), new.clone())],
+                &c.collection,
+                MissingDocumentPolicy::Skip,
+            )
+            .await,
+    )?;
+    c.expect_eq(
+        "counting skipped updates",
+        (report.matched, report.modified, report.upserted),
+        (1, 1, 0),
+    )?;
+    c.expect_eq(
+        "checking a skipped document wasn't inserted",
+        c.get("getting the skipped document", "f")
+            .await?,
+        None,
+    )?;
+
+    let report = c.ok(
+        "updating with MissingDocumentPolicy::Upsert",
+        backend
+            .update_documents(
+                vec![(id("a"), changed.clone()), (id("f"), new.clone())],
+                &c.collection,
+                MissingDocumentPolicy::Upsert,
+            )
+            .await,
+    )?;
+    c.expect_eq(
+        "counting upserted updates, one unchanged",
+        (report.matched, report.modified, report.upserted),
+        (1, 0, 1),
+    )?;
+    c.expect_eq(
+        "checking an upserted document",
+        c.get("getting the upserted document", "f")
+            .await?,
+        Some(new),
+    )?;
+
+    c.finish().await
+}
+
+/// Upserts never fail on existing IDs, and deletes skip missing ones.
+pub async fn upsert_and_delete(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("upsert_and_delete", backend).await?;
+    c.seed().await?;
+
+    let changed = Bson::Document(doc! { "key": "a", "name": "Changed" });
+    let new = Bson::Document(doc! { "key": "f", "name": "Frank" });
+
+    c.ok(
+        "upserting an existing and a new document",
+        backend
+            .upsert_documents(
+                vec![(id("a"), changed.clone()), (id("f"), new.clone())],
+                &c.collection,
+            )
+            .await,
+    )?;
+    c.expect_eq(
+        "checking an upserted document",
+        c.get("getting the replaced document", "a")
+            .await?,
+        Some(changed),
+    )?;
+    c.expect_eq(
+        "checking an upserted document",
+        c.get("getting the inserted document", "f")
+            .await?,
+        Some(new),
+    )?;
+
+    let deleted = c.ok(
+        "deleting existing and missing IDs",
+        backend
+            .delete_documents(vec![id("a"), id("missing"), id("f")], &c.collection)
+            .await,
+    )?;
+    c.expect_eq("counting deleted documents", deleted, 2)?;
+    c.expect_eq(
+        "checking deleted documents are gone",
+        c.get("getting a deleted document", "a")
+            .await?,
+        None,
+    )?;
+
+    let deleted = c.ok(
+        "deleting from a missing collection",
+        backend
+            .delete_documents(vec![id("a")], "conformance_missing_collection")
+            .await,
+    )?;
+    c.expect_eq("counting documents deleted from a missing collection", deleted, 0)?;
+
+    c.finish().await
+}
+
+/// Versioned and revision-checked writes fail on stale or missing documents.
+pub async fn conditional_writes(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("conditional_writes", backend).await?;
+
+    let versioned = |version: i64| Bson::Document(doc! { "key": "v", "version": version });
+    c.ok(
+        "inserting a versioned document",
+        backend
+            .insert_documents(
+                vec![(id("v"), versioned(1))],
+                &c.collection,
+                InsertPolicy::ErrorOnConflict,
+            )
+            .await,
+    )?;
+
+    let report = c.ok(
+        "updating at the stored version",
+        backend
+            .update_documents_if_version(vec![(id("v"), versioned(2), 1)], "version", &c.collection)
+            .await,
+    )?;
+    c.expect_eq("counting versioned updates", report.matched, 1)?;
+    c.expect_err(
+        "updating at a stale version",
+        backend
+            .update_documents_if_version(vec![(id("v"), versioned(2), 1)], "version", &c.collection)
+            .await,
+        "VersionConflict",
+        |error| matches!(error, DocumentStoreError::VersionConflict(..)),
+    )?;
+    c.expect_err(
+        "updating a missing document at a version",
+        backend
+            .update_documents_if_version(
+                vec![(id("missing"), versioned(2), 1)],
+                "version",
+                &c.collection,
+            )
+            .await,
+        "DocumentNotFound",
+        |error| matches!(error, DocumentStoreError::DocumentNotFound(..)),
+    )?;
+
+    let stored = c
+        .get("getting the versioned document", "v")
+        .await?
+        .ok_or_else(|| c.fail("the versioned document is missing".to_string()))?;
+    let revision = c.ok("computing the revision", document_revision(&stored))?;
+
+    c.ok(
+        "updating at the stored revision",
+        backend
+            .update_documents_if_match(
+                vec![(id("v"), versioned(3), revision.clone())],
+                &c.collection,
+            )
+            .await,
+    )?;
+    c.expect_err(
+        "updating at a stale revision",
+        backend
+            .update_documents_if_match(
+                vec![(id("v"), versioned(4), revision.clone())],
+                &c.collection,
+            )
+            .await,
+        "PreconditionFailed",
+        |error| matches!(error, DocumentStoreError::PreconditionFailed(..)),
+    )?;
+    c.expect_err(
+        "deleting a missing document at a revision",
+        backend
+            .delete_documents_if_match(vec![(id("missing"), revision)], &c.collection)
+            .await,
+        "PreconditionFailed",
+        |error| matches!(error, DocumentStoreError::PreconditionFailed(..)),
+    )?;
+
+    let stored = c
+        .get("getting the versioned document", "v")
+        .await?
+        .ok_or_else(|| c.fail("the versioned document is missing".to_string()))?;
+    let revision = c.ok("computing the revision", document_revision(&stored))?;
+    let deleted = c.ok(
+        "deleting at the stored revision",
+        backend
+            .delete_documents_if_match(vec![(id("v"), revision)], &c.collection)
+            .await,
+    )?;
+    c.expect_eq("counting documents deleted at a revision", deleted, 1)?;
+
+    c.finish().await
+}
+
+/// Equality, ordering and membership comparisons.
+pub async fn comparison_operators(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("comparison_operators", backend).await?;
+    c.seed().await?;
+
+    c.expect_matching(Filter::eq("city", "Paris"), &["b", "e"])
+        .await?;
+    c.expect_matching(Filter::eq("name", "Dave"), &[])
+        .await?;
+    c.expect_matching(Filter::eq("active", false), &["b", "e"])
+        .await?;
+    c.expect_matching(Filter::ne("city", "Paris"), &["a", "c", "d"])
+        .await?;
+    c.expect_matching(Filter::gt("age", 30), &["c", "d"])
+        .await?;
+    c.expect_matching(Filter::gte("age", 30), &["a", "c", "d"])
+        .await?;
+    c.expect_matching(Filter::lt("age", 30), &["b", "e"])
+        .await?;
+    c.expect_matching(Filter::lte("age", 30), &["a", "b", "e"])
+        .await?;
+    c.expect_matching(Filter::gt("score", 2.0), &["b", "c"])
+        .await?;
+    c.expect_matching(Filter::between("age", 25, 30), &["a", "b", "e"])
+        .await?;
+    c.expect_matching(Filter::between_exclusive("age", 25, 40), &["a", "c"])
+        .await?;
+    c.expect_matching(Filter::gt("name", "C"), &["c", "d", "e"])
+        .await?;
+    c.expect_matching(Filter::any_of("city", vec!["Oslo", "Paris"]), &["b", "d", "e"])
+        .await?;
+    c.expect_matching(Filter::none_of("city", vec!["Oslo", "Paris"]), &["a", "c"])
+        .await?;
+
+    c.finish().await
+}
+
+/// Substring, prefix, suffix and pattern matching, exact and case-insensitive.
+pub async fn string_operators(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("string_operators", backend).await?;
+    c.seed().await?;
+
+    c.expect_matching(Filter::starts_with("name", "Ca"), &["c"])
+        .await?;
+    c.expect_matching(Filter::ends_with("name", "e"), &["a", "d", "e"])
+        .await?;
+    c.expect_matching(Filter::contains("name", "ro"), &["c"])
+        .await?;
+    c.expect_matching(Filter::not_contains("name", "e"), &["b", "c"])
+        .await?;
+    c.expect_matching(Filter::matches("name", "^[A-C]"), &["a", "b", "c"])
+        .await?;
+    c.expect_matching(Filter::starts_with("name", "d"), &["d"])
+        .await?;
+    c.expect_matching(Filter::case_insensitive(Filter::starts_with("name", "D")), &["d"])
+        .await?;
+    c.expect_matching(Filter::case_insensitive(Filter::contains("name", "AL")), &["a"])
+        .await?;
+    // Operators match the value literally, not as a pattern
+    c.expect_matching(Filter::contains("nickname", "."), &["d"])
+        .await?;
+
+    c.finish().await
+}
+
+/// Conjunctions, disjunctions and negations.
+pub async fn logical_operators(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("logical_operators", backend).await?;
+    c.seed().await?;
+
+    c.expect_matching(Filter::and([Filter::eq("city", "Berlin"), Filter::gt("age", 30)]), &["c"])
+        .await?;
+    c.expect_matching(
+        Filter::or([Filter::eq("city", "Oslo"), Filter::lt("age", 26)]),
+        &["b", "d", "e"],
+    )
+    .await?;
+    c.expect_matching(Filter::eq("city", "Paris").not(), &["a", "c", "d"])
+        .await?;
+    c.expect_matching(
+        Filter::or([
+            Filter::and([Filter::eq("city", "Paris"), Filter::eq("name", "Bob")]),
+            Filter::eq("age", 40),
+        ]),
+        &["b", "d"],
+    )
+    .await?;
+
+    c.finish().await
+}
+
+/// Existence checks and membership tests on missing and null fields.
+pub async fn missing_and_null(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("missing_and_null", backend).await?;
+    c.seed().await?;
+
+    // `b` holds a null nickname, `c` and `e` have none
+    c.expect_matching(Filter::exists("nickname"), &["a", "b", "d"])
+        .await?;
+    c.expect_matching(Filter::not_exists("nickname"), &["c", "e"])
+        .await?;
+    c.expect_matching(Filter::exists("address.zip"), &["a", "b", "c", "d", "e"])
+        .await?;
+    c.expect_matching(Filter::not_exists("address.city"), &["a", "b", "c", "d", "e"])
+        .await?;
+    c.expect_matching(
+        Filter::any_of("nickname", vec![Bson::Null, Bson::String("Ally".to_string())]),
+        &["a", "b", "c", "e"],
+    )
+    .await?;
+    c.expect_matching(Filter::gt("nickname", "A"), &["a", "d"])
+        .await?;
+
+    c.finish().await
+}
+
+/// Comparisons reaching into arrays and embedded documents.
+pub async fn array_and_nested_fields(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("array_and_nested_fields", backend).await?;
+    c.seed().await?;
+
+    c.expect_matching(Filter::contains("tags", "admin"), &["a", "e"])
+        .await?;
+    c.expect_matching(Filter::not_contains("tags", "dev"), &["c", "d", "e"])
+        .await?;
+    c.expect_matching(Filter::any_of("tags", vec!["ops", "dev"]), &["a", "b", "d"])
+        .await?;
+    c.expect_matching(Filter::eq("address.zip", "75001"), &["b"])
+        .await?;
+    c.expect_matching(Filter::starts_with("address.zip", "101"), &["a", "c"])
+        .await?;
+
+    c.finish().await
+}
+
+/// Multi-key sorting, offsets and limits, and counts honouring them.
+pub async fn sorting_and_paging(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("sorting_and_paging", backend).await?;
+    c.seed().await?;
+
+    let by_age = Query::builder()
+        .sort("age", SortDirection::Asc)
+        .then_sort("name", SortDirection::Asc)
+        .build();
+    c.expect_eq(
+        "sorting by age, then name",
+        keys(
+            &c.query("sorting", by_age.clone())
+                .await?,
+        ),
+        strings(&["b", "e", "a", "c", "d"]),
+    )?;
+    c.expect_eq(
+        "sorting by age descending, then name descending",
+        keys(
+            &c.query(
+                "sorting",
+                Query::builder()
+                    .sort("age", SortDirection::Desc)
+                    .then_sort("name", SortDirection::Desc)
+                    .build(),
+            )
+            .await?,
+        ),
+        strings(&["d", "c", "a", "e", "b"]),
+    )?;
+    c.expect_eq(
+        "sorting by a nested field",
+        keys(
+            &c.query(
+                "sorting",
+                Query::builder()
+                    .sort("address.zip", SortDirection::Asc)
+                    .build(),
+            )
+            .await?,
+        ),
+        strings(&["d", "a", "c", "b", "e"]),
+    )?;
+
+    let page = Query {
+        offset: Some(1),
+        limit: Some(2),
+        ..by_age
+    };
+    c.expect_eq(
+        "paging sorted documents",
+        keys(&c.query("paging", page.clone()).await?),
+        strings(&["e", "a"]),
+    )?;
+    c.expect_eq(
+        "paging past the end",
+        c.query("paging", Query { offset: Some(10), ..page.clone() })
+            .await?,
+        Vec::new(),
+    )?;
+
+    c.expect_eq(
+        "counting with a filter",
+        c.ok(
+            "counting",
+            backend
+                .count_documents(
+                    Query::builder()
+                        .filter(Filter::eq("active", true))
+                        .build(),
+                    &c.collection,
+                )
+                .await,
+        )?,
+        3,
+    )?;
+    c.expect_eq(
+        "counting with an offset and limit",
+        c.ok(
+            "counting",
+            backend
+                .count_documents(page, &c.collection)
+                .await,
+        )?,
+        2,
+    )?;
+    c.expect_eq(
+        "counting a missing collection",
+        c.ok(
+            "counting",
+            backend
+                .count_documents(Query::default(), "conformance_missing_collection")
+                .await,
+        )?,
+        0,
+    )?;
+
+    c.finish().await
+}
+
+/// Projected queries return only the requested fields.
+pub async fn projection(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("projection", backend).await?;
+    c.seed().await?;
+
+    let documents = c
+        .query(
+            "querying with a projection",
+            Query::builder()
+                .filter(Filter::eq("key", "a"))
+                .project(["key", "name", "address.zip"])
+                .build(),
+        )
+        .await?;
+    let document = documents
+        .first()
+        .and_then(Bson::as_document)
+        .ok_or_else(|| c.fail(format!("projecting: expected a document, got {:?}", documents)))?;
+
+    c.expect_eq("projecting a field", document.get("name"), Some(&Bson::from("Alice")))?;
+    c.expect_eq(
+        "projecting a nested field",
+        document
+            .get_document("address")
+            .ok()
+            .and_then(|address| address.get("zip")),
+        Some(&Bson::from("10115")),
+    )?;
+    c.expect_eq("leaving out a field", document.get("age"), None)?;
+    c.expect_eq(
+        "leaving out a nested field",
+        document
+            .get_document("address")
+            .ok()
+            .and_then(|address| address.get("street")),
+        None,
+    )?;
+
+    c.finish().await
+}
+
+/// Streamed queries yield the same documents as `query_documents`.
+pub async fn query_stream(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("query_stream", backend).await?;
+    c.seed().await?;
+
+    for query in [
+        Query::default(),
+        Query::builder()
+            .filter(Filter::eq("city", "Berlin"))
+            .build(),
+        Query::builder()
+            .sort("age", SortDirection::Desc)
+            .then_sort("name", SortDirection::Asc)
+            .offset(1)
+            .limit(3)
+            .build(),
+    ] {
+        let queried = c
+            .query("querying", query.clone())
+            .await?;
+        let stream = c.ok(
+            "opening a stream",
+            backend
+                .query_stream(query.clone(), &c.collection)
+                .await,
+        )?;
+        let streamed = c.ok("reading the stream", stream.try_collect::<Vec<_>>().await)?;
+
+        match query.sort.is_empty() {
+            true => c.expect_eq(
+                "streaming an unsorted query",
+                sorted(keys(&streamed)),
+                sorted(keys(&queried)),
+            )?,
+            false => c.expect_eq("streaming a sorted query", keys(&streamed), keys(&queried))?,
+        }
+    }
+
+    c.finish().await
+}
+
+/// Distinct values of a field, with and without a filter.
+pub async fn distinct(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("distinct", backend).await?;
+    c.seed().await?;
+
+    let values = |values: Vec<Bson>| {
+        let mut values = values
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        values.sort();
+        values
+    };
+
+    c.expect_eq(
+        "listing distinct values",
+        values(
+            c.ok(
+                "listing distinct cities",
+                backend
+                    .distinct("city", None, &c.collection)
+                    .await,
+            )?,
+        ),
+        strings(&["\"Berlin\"", "\"Oslo\"", "\"Paris\""]),
+    )?;
+    c.expect_eq(
+        "listing distinct values of matching documents",
+        values(
+            c.ok(
+                "listing distinct cities",
+                backend
+                    .distinct("city", Some(Filter::eq("active", true)), &c.collection)
+                    .await,
+            )?,
+        ),
+        strings(&["\"Berlin\"", "\"Oslo\""]),
+    )?;
+
+    c.finish().await
+}
+
+/// Partial updates of every matching document, failing an increment that overflows a long.
+pub async fn update_by_query(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("update_by_query", backend).await?;
+    c.seed().await?;
+
+    let report = c.ok(
+        "updating by query",
+        backend
+            .update_by_query(
+                Filter::eq("city", "Paris"),
+                Update::builder()
+                    .inc("age", 1)
+                    .set("city", "Lyon")
+                    .unset("address")
+                    .push("tags", "moved")
+                    .build(),
+                &c.collection,
+            )
+            .await,
+    )?;
+    c.expect_eq("counting updated documents", (report.matched, report.modified), (2, 2))?;
+
+    let updated = c
+        .get("getting an updated document", "b")
+        .await?
+        .and_then(|document| document.as_document().cloned())
+        .ok_or_else(|| c.fail("an updated document is missing".to_string()))?;
+    c.expect_eq("incrementing a field", updated.get("age"), Some(&Bson::Int32(26)))?;
+    c.expect_eq("setting a field", updated.get("city"), Some(&Bson::from("Lyon")))?;
+    c.expect_eq("unsetting a field", updated.get("address"), None)?;
+    c.expect_eq(
+        "pushing to an array",
+        updated.get("tags"),
+        Some(&Bson::Array(vec!["dev".into(), "moved".into()])),
+    )?;
+    c.expect_eq(
+        "checking documents not matched are unchanged",
+        c.get("getting a document not matched", "a")
+            .await?,
+        Some(person("a")),
+    )?;
+
+    let report = c.ok(
+        "updating by query without matches",
+        backend
+            .update_by_query(
+                Filter::eq("city", "Paris"),
+                Update::builder()
+                    .set("city", "Nice")
+                    .build(),
+                &c.collection,
+            )
+            .await,
+    )?;
+    c.expect_eq("counting documents updated without matches", report.matched, 0)?;
+
+    c.ok(
+        "setting a field to the largest long",
+        backend
+            .update_by_query(
+                Filter::eq("key", "b"),
+                Update::builder()
+                    .set("visits", i64::MAX)
+                    .build(),
+                &c.collection,
+            )
+            .await,
+    )?;
+    c.expect_err(
+        "incrementing a long past its largest value",
+        backend
+            .update_by_query(
+                Filter::eq("key", "b"),
+                Update::builder()
+                    .inc("visits", 1)
+                    .build(),
+                &c.collection,
+            )
+            .await,
+        "an error",
+        |_| true,
+    )?;
+    let updated = c
+        .get("getting the document that overflowed", "b")
+        .await?
+        .and_then(|document| document.as_document().cloned())
+        .ok_or_else(|| c.fail("the document that overflowed is missing".to_string()))?;
+    c.expect_eq("keeping a long that would overflow", updated.get("visits"), Some(&Bson::Int64(i64::MAX)))?;
+
+    c.finish().await
+}
+
+/// Deletion of every matching document.
+pub async fn delete_by_query(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("delete_by_query", backend).await?;
+    c.seed().await?;
+
+    let deleted = c.ok(
+        "deleting by query",
+        backend
+            .delete_by_query(Filter::eq("city", "Berlin"), &c.collection)
+            .await,
+    )?;
+    c.expect_eq("counting deleted documents", deleted, 2)?;
+    c.expect_matching(Filter::exists("key"), &["b", "d", "e"])
+        .await?;
+
+    c.finish().await
+}
+
+/// Atomic find-and-modify of the first matching document in sort order.
+pub async fn find_one_and_modify(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("find_one_and_modify", backend).await?;
+    c.seed().await?;
+
+    let youngest_first = vec![
+        Sort {
+            field: "age".to_string(),
+            direction: SortDirection::Asc,
+        },
+        Sort {
+            field: "name".to_string(),
+            direction: SortDirection::Asc,
+        },
+    ];
+    let claim = || {
+        Update::builder()
+            .set("claimed", true)
+            .build()
+    };
+    let unclaimed = || Filter::not_exists("claimed");
+
+    let before = c.ok(
+        "updating the first match",
+        backend
+            .find_one_and_update(
+                unclaimed(),
+                youngest_first.clone(),
+                claim(),
+                ReturnDocument::Before,
+                &c.collection,
+            )
+            .await,
+    )?;
+    c.expect_eq("returning the document before the update", before, Some(person("b")))?;
+
+    let after = c.ok(
+        "updating the next match",
+        backend
+            .find_one_and_update(
+                unclaimed(),
+                youngest_first.clone(),
+                claim(),
+                ReturnDocument::After,
+                &c.collection,
+            )
+            .await,
+    )?;
+    c.expect_eq(
+        "returning the document after the update",
+        after.as_ref().map(key),
+        Some("e".to_string()),
+    )?;
+    c.expect_eq(
+        "returning the updated fields",
+        after
+            .as_ref()
+            .and_then(Bson::as_document)
+            .and_then(|document| document.get("claimed")),
+        Some(&Bson::Boolean(true)),
+    )?;
+
+    let none = c.ok(
+        "updating without matches",
+        backend
+            .find_one_and_update(
+                Filter::eq("city", "Rome"),
+                Vec::new(),
+                claim(),
+                ReturnDocument::After,
+                &c.collection,
+            )
+            .await,
+    )?;
+    c.expect_eq("returning no document without matches", none, None)?;
+
+    let deleted = c.ok(
+        "deleting the first match",
+        backend
+            .find_one_and_delete(unclaimed(), youngest_first, &c.collection)
+            .await,
+    )?;
+    c.expect_eq("returning the deleted document", deleted, Some(person("a")))?;
+    c.expect_eq(
+        "checking the document was deleted",
+        c.get("getting the deleted document", "a")
+            .await?,
+        None,
+    )?;
+
+    c.finish().await
+}
+
+/// Creating, listing and dropping collections.
+pub async fn collections(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("collections", backend).await?;
+
+    c.expect_eq(
+        "checking a missing collection",
+        c.ok(
+            "checking the collection exists",
+            backend
+                .collection_exists(&c.collection)
+                .await,
+        )?,
+        false,
+    )?;
+    c.ok(
+        "creating a collection",
+        backend
+            .create_collection(&c.collection)
+            .await,
+    )?;
+    c.expect_eq(
+        "checking a created collection",
+        c.ok(
+            "checking the collection exists",
+            backend
+                .collection_exists(&c.collection)
+                .await,
+        )?,
+        true,
+    )?;
+    c.expect_eq(
+        "listing collections",
+        c.ok("listing collections", backend.list_collections().await)?
+            .contains(&c.collection),
+        true,
+    )?;
+
+    match backend
+        .create_collection(&c.collection)
+        .await
+    {
+        Ok(()) | Err(DocumentStoreError::CollectionAlreadyExists(_)) => {}
+        Err(error) => {
+            return Err(c.fail(format!(
+                "creating an existing collection: expected success or CollectionAlreadyExists, got error {:?}",
+                error
+            )));
+        }
+    }
+    c.ok(
+        "ensuring an existing collection",
+        backend
+            .ensure_collection(&c.collection)
+            .await,
+    )?;
+
+    c.ok(
+        "dropping a collection",
+        backend
+            .drop_collection(&c.collection)
+            .await,
+    )?;
+    c.expect_eq(
+        "checking a dropped collection",
+        c.ok(
+            "checking the collection exists",
+            backend
+                .collection_exists(&c.collection)
+                .await,
+        )?,
+        false,
+    )?;
+    c.ok(
+        "ensuring a missing collection",
+        backend
+            .ensure_collection(&c.collection)
+            .await,
+    )?;
+    c.expect_eq(
+        "checking an ensured collection",
+        c.ok(
+            "checking the collection exists",
+            backend
+                .collection_exists(&c.collection)
+                .await,
+        )?,
+        true,
+    )?;
+
+    c.finish().await
+}
+
+/// The field operations migrations rely on.
+pub async fn field_operations(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("field_operations", backend).await?;
+    c.seed().await?;
+
+    c.ok(
+        "adding a field",
+        backend
+            .add_field(&c.collection, "country", Bson::from("unknown"))
+            .await,
+    )?;
+    c.expect_matching(Filter::eq("country", "unknown"), &["a", "b", "c", "d", "e"])
+        .await?;
+
+    c.ok(
+        "renaming a field",
+        backend
+            .rename_field(&c.collection, "nickname", "alias")
+            .await,
+    )?;
+    c.expect_matching(Filter::exists("nickname"), &[])
+        .await?;
+    c.expect_matching(Filter::exists("alias"), &["a", "b", "d"])
+        .await?;
+    c.expect_matching(Filter::eq("alias", "Ally"), &["a"])
+        .await?;
+
+    c.ok(
+        "dropping a field",
+        backend
+            .drop_field(&c.collection, "score")
+            .await,
+    )?;
+    c.expect_matching(Filter::exists("score"), &[])
+        .await?;
+    c.expect_matching(Filter::exists("name"), &["a", "b", "c", "d", "e"])
+        .await?;
+
+    c.finish().await
+}
+
+/// Unique indexes reject writes duplicating a value, counting missing fields as `null`, each
+/// element of an array as a value, and equal numbers of different types as the same value,
+/// while longs too large for a double stay apart.
+///
+/// Backends refusing unique indexes with [`DocumentStoreError::Unsupported`] pass.
+pub async fn unique_indexes(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("unique_indexes", backend).await?;
+    c.seed().await?;
+
+    match backend
+        .add_index(&c.collection, "name", true)
+        .await
+    {
+        Err(DocumentStoreError::Unsupported(_)) => return c.finish().await,
+        result => c.ok("adding a unique index", result)?,
+    }
+
+    let is_violation =
+        |error: &DocumentStoreError| matches!(error, DocumentStoreError::UniqueViolation(..));
+    let named = |key: &str, name: &str| Bson::Document(doc! { "key": key, "name": name });
+    let unnamed = |key: &str| Bson::Document(doc! { "key": key });
+
+    c.expect_err(
+        "inserting a duplicate value",
+        backend
+            .insert_documents(
+                vec![(id("f"), named("f", "Alice"))],
+                &c.collection,
+                InsertPolicy::ErrorOnConflict,
+            )
+            .await,
+        "UniqueViolation",
+        is_violation,
+    )?;
+    c.expect_eq(
+        "checking a violating insert wrote nothing",
+        c.get("getting the violating document", "f")
+            .await?,
+        None,
+    )?;
+    c.ok(
+        "inserting a unique value",
+        backend
+            .insert_documents(
+                vec![(id("f"), named("f", "Frank"))],
+                &c.collection,
+                InsertPolicy::ErrorOnConflict,
+            )
+            .await,
+    )?;
+    c.ok(
+        "replacing a document keeping its value",
+        backend
+            .upsert_documents(vec![(id("a"), named("a", "Alice"))], &c.collection)
+            .await,
+    )?;
+    c.expect_err(
+        "updating to a duplicate value",
+        backend
+            .update_documents(
+                vec![(id("b"), named("b", "Alice"))],
+                &c.collection,
+                MissingDocumentPolicy::Error,
+            )
+            .await,
+        "UniqueViolation",
+        is_violation,
+    )?;
+    c.expect_err(
+        "updating by query to a duplicate value",
+        backend
+            .update_by_query(
+                Filter::eq("city", "Paris"),
+                Update::builder()
+                    .set("name", "Same")
+                    .build(),
+                &c.collection,
+            )
+            .await,
+        "UniqueViolation",
+        is_violation,
+    )?;
+
+    c.ok(
+        "inserting a document without the field",
+        backend
+            .insert_documents(
+                vec![(id("g"), unnamed("g"))],
+                &c.collection,
+                InsertPolicy::ErrorOnConflict,
+            )
+            .await,
+    )?;
+    c.expect_err(
+        "inserting a second document without the field",
+        backend
+            .insert_documents(
+                vec![(id("h"), unnamed("h"))],
+                &c.collection,
+                InsertPolicy::ErrorOnConflict,
+            )
+            .await,
+        "UniqueViolation",
+        is_violation,
+    )?;
+
+    c.expect_err(
+        "inserting an array holding a duplicate value",
+        backend
+            .insert_documents(
+                vec![(id("i"), Bson::Document(doc! { "key": "i", "name": ["Ivy", "Alice"] }))],
+                &c.collection,
+                InsertPolicy::ErrorOnConflict,
+            )
+            .await,
+        "UniqueViolation",
+        is_violation,
+    )?;
+    c.ok(
+        "inserting an array of unique values",
+        backend
+            .insert_documents(
+                vec![(id("i"), Bson::Document(doc! { "key": "i", "name": ["Ivy", "Jo"] }))],
+                &c.collection,
+                InsertPolicy::ErrorOnConflict,
+            )
+            .await,
+    )?;
+    c.expect_err(
+        "inserting a value held in an array",
+        backend
+            .insert_documents(
+                vec![(id("j"), named("j", "Jo"))],
+                &c.collection,
+                InsertPolicy::ErrorOnConflict,
+            )
+            .await,
+        "UniqueViolation",
+        is_violation,
+    )?;
+    c.ok(
+        "inserting a number",
+        backend
+            .insert_documents(
+                vec![(id("j"), Bson::Document(doc! { "key": "j", "name": 1 }))],
+                &c.collection,
+                InsertPolicy::ErrorOnConflict,
+            )
+            .await,
+    )?;
+    c.expect_err(
+        "inserting an equal number of another type",
+        backend
+            .insert_documents(
+                vec![(id("k"), Bson::Document(doc! { "key": "k", "name": 1.0 }))],
+                &c.collection,
+                InsertPolicy::ErrorOnConflict,
+            )
+            .await,
+        "UniqueViolation",
+        is_violation,
+    )?;
+
+    // Longs above 2^53 are equal as doubles, but not as longs
+    let large = 1_i64 << 53;
+    c.ok(
+        "inserting longs too large for a double",
+        backend
+            .insert_documents(
+                vec![
+                    (id("l"), Bson::Document(doc! { "key": "l", "name": large })),
+                    (id("m"), Bson::Document(doc! { "key": "m", "name": large + 1 })),
+                ],
+                &c.collection,
+                InsertPolicy::ErrorOnConflict,
+            )
+            .await,
+    )?;
+    c.expect_err(
+        "inserting a double equal to a long",
+        backend
+            .insert_documents(
+                vec![(id("n"), Bson::Document(doc! { "key": "n", "name": large as f64 }))],
+                &c.collection,
+                InsertPolicy::ErrorOnConflict,
+            )
+            .await,
+        "UniqueViolation",
+        is_violation,
+    )?;
+
+    c.expect_err(
+        "adding a unique index to duplicate values",
+        backend
+            .add_index(&c.collection, "city", true)
+            .await,
+        "UniqueViolation",
+        is_violation,
+    )?;
+
+    c.finish().await
+}
+
+/// The store revision migrations are tracked by.
+pub async fn revisions(backend: &dyn DynStoreBackend) -> CheckResult {
+    let c = Checker::start("revisions", backend).await?;
+    let previous = c.ok("reading the revision", backend.current_revision_id().await)?;
+
+    c.ok(
+        "setting the revision",
+        backend
+            .set_revision_id("conformance_1")
+            .await,
+    )?;
+    c.expect_eq(
+        "reading the set revision",
+        c.ok("reading the revision", backend.current_revision_id().await)?,
+        Some("conformance_1".to_string()),
+    )?;
+    c.ok(
+        "replacing the revision",
+        backend
+            .set_revision_id("conformance_2")
+            .await,
+    )?;
+    c.expect_eq(
+        "reading the replaced revision",
+        c.ok("reading the revision", backend.current_revision_id().await)?,
+        Some("conformance_2".to_string()),
+    )?;
+
+    if let Some(previous) = previous {
+        c.ok("restoring the revision", backend.set_revision_id(&previous).await)?;
+    }
+
+    c.finish().await
+}
@@ -0,0 +1,564 @@
+//! Differential testing of query semantics between two backends.
+//!
+//! Operator mismatches between backends, such as how a comparison treats a `null` or missing
+//! field, or whether a string operator ignores case, rarely show up in hand-written tests.
+//! [`Differential`] generates random documents and filters from a seed, biased towards the
+//! cases backends disagree on: mixed types, `null` and missing fields, strings differing only
+//! in case, arrays and embedded documents. It inserts the same documents into two backends,
+//! runs every filter against both and reports the filters they match different documents for.
+//!
+//! Each [`Divergence`] is reduced to the smallest part of its filter that still diverges,
+//! usually a single comparison, and lists the documents only one backend matched. The same
+//! seed generates the same documents and filters, so a divergence found once reproduces.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer_test::differential::Differential;
+//!
+//! #[tokio::test]
+//! async fn mongodb_filters_like_memory() {
+//!     let memory = InMemoryStore::builder().build().await.unwrap();
+//!     let mongodb = MongoDBStore::builder(dsn, "differential").build().await.unwrap();
+//!
+//!     Differential::new(7)
+//!         .with_queries(1_000)
+//!         .run(&memory, &mongodb)
+//!         .await
+//!         .unwrap()
+//!         .assert_consistent();
+//! }
+//! ```
+
+use crate::rng::SplitMix64;
+use bson::{Bson, Uuid, doc};
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt,
+    ops::Bound,
+};
+
+use doclayer_core::{
+    backend::{DynStoreBackend, InsertPolicy},
+    error::DocumentStoreResult,
+    query::{Expr, FieldOp, Query},
+};
+
+/// The collection [`Differential`] works in by default.
+pub const DEFAULT_COLLECTION: &str = "differential";
+
+/// The fields filters compare, including one no document has.
+const FIELDS: &[&str] = &["n", "s", "flag", "tags", "nested.x", "nested.s", "absent"];
+
+const STRINGS: &[&str] = &[
+    "apple", "Apple", "APPLE", "app", "banana", "a.b", "", "éclair",
+];
+
+const PATTERNS: &[&str] = &["^a", "^A", "e$", "p+", "a.b", "^$"];
+
+const OPERATORS: &[FieldOp] = &[
+    FieldOp::Eq,
+    FieldOp::Ne,
+    FieldOp::Gt,
+    FieldOp::Gte,
+    FieldOp::Lt,
+    FieldOp::Lte,
+    FieldOp::Contains,
+    FieldOp::NotContains,
+    FieldOp::StartsWith,
+    FieldOp::EndsWith,
+    FieldOp::AnyOf,
+    FieldOp::NoneOf,
+    FieldOp::Regex,
+];
+
+/// Runs randomly generated filters against two backends and reports where they disagree.
+#[derive(Debug, Clone)]
+pub struct Differential {
+    seed: u64,
+    documents: usize,
+    queries: usize,
+    depth: usize,
+    operators: Vec<FieldOp>,
+    collection: String,
+}
+
+impl Differential {
+    /// Creates a harness generating its documents and filters from `seed`.
+    ///
+    /// Defaults to 100 documents, 500 filters nested up to 3 levels deep comparing fields
+    /// with every built-in operator, and the [`DEFAULT_COLLECTION`].
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            documents: 100,
+            queries: 500,
+            depth: 3,
+            operators: OPERATORS.to_vec(),
+            collection: DEFAULT_COLLECTION.to_string(),
+        }
+    }
+
+    /// Sets the number of documents generated.
+    pub fn with_documents(mut self, documents: usize) -> Self {
+        self.documents = documents;
+        self
+    }
+
+    /// Sets the number of filters generated.
+    pub fn with_queries(mut self, queries: usize) -> Self {
+        self.queries = queries;
+        self
+    }
+
+    /// Sets how deeply filters nest logical operators.
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Sets the operators field comparisons are generated with, to focus on some of them.
+    ///
+    /// Filters still check whether fields exist and compare ranges.
+    pub fn with_operators(mut self, operators: impl IntoIterator<Item = FieldOp>) -> Self {
+        self.operators = operators.into_iter().collect();
+        self
+    }
+
+    /// Sets the collection the documents are inserted into.
+    pub fn with_collection(mut self, collection: impl Into<String>) -> Self {
+        self.collection = collection.into();
+        self
+    }
+
+    /// Returns the documents the harness inserts, each with its position in a `key` field.
+    pub fn documents(&self) -> Vec<(Uuid, Bson)> {
+        let mut rng = SplitMix64(self.seed);
+
+        (0..self.documents)
+            .map(|key| (key_id(key), generate_document(&mut rng, key)))
+            .collect()
+    }
+
+    /// Returns the filters the harness runs.
+    pub fn filters(&self) -> Vec<Expr> {
+        // Filters draw from their own stream, so they don't change with the document count
+        let mut rng = SplitMix64(self.seed ^ 0x5eed_f11e_5eed_f11e);
+
+        (0..self.queries)
+            .map(|_| generate_expr(&mut rng, self.depth, &self.operators))
+            .collect()
+    }
+
+    /// Inserts the documents into both backends, runs every filter against them and reports
+    /// the filters they disagree on.
+    ///
+    /// The collection is dropped from both backends before and after the run. A filter one
+    /// backend rejects with an error and the other accepts is a divergence, while one both
+    /// reject is not.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if preparing or dropping the collection fails on either backend.
+    pub async fn run(
+        &self,
+        reference: &dyn DynStoreBackend,
+        candidate: &dyn DynStoreBackend,
+    ) -> DocumentStoreResult<DifferentialReport> {
+        let documents = self.documents();
+        let backends = [reference, candidate];
+
+        for backend in backends {
+            self.clean(backend).await?;
+            backend
+                .insert_documents(
+                    documents.clone(),
+                    &self.collection,
+                    InsertPolicy::ErrorOnConflict,
+                )
+                .await?;
+        }
+
+        let mut report = DifferentialReport {
+            seed: self.seed,
+            queries: 0,
+            divergences: Vec::new(),
+        };
+        let mut seen = HashSet::new();
+
+        for filter in self.filters() {
+            report.queries += 1;
+
+            if !self
+                .diverges(reference, candidate, &filter)
+                .await
+            {
+                continue;
+            }
+
+            let reduced = self
+                .reduce(reference, candidate, filter.clone())
+                .await;
+
+            // Many filters diverge because of the same comparison, which is reported once
+            if !seen.insert(reduced.to_string()) {
+                continue;
+            }
+
+            let reference_keys = self.matching(reference, &reduced).await;
+            let candidate_keys = self.matching(candidate, &reduced).await;
+            let differing = match (&reference_keys, &candidate_keys) {
+                (Ok(left), Ok(right)) => left
+                    .symmetric_difference(right)
+                    .filter_map(|key| documents.get(*key))
+                    .map(|(_, document)| document.clone())
+                    .collect(),
+                _ => Vec::new(),
+            };
+
+            report.divergences.push(Divergence {
+                filter,
+                reduced,
+                reference: reference_keys.map(|keys| keys.into_iter().collect()),
+                candidate: candidate_keys.map(|keys| keys.into_iter().collect()),
+                documents: differing,
+            });
+        }
+
+        for backend in backends {
+            self.clean(backend).await?;
+        }
+
+        Ok(report)
+    }
+
+    async fn clean(&self, backend: &dyn DynStoreBackend) -> DocumentStoreResult<()> {
+        if backend
+            .collection_exists(&self.collection)
+            .await?
+        {
+            backend
+                .drop_collection(&self.collection)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the keys of the documents a backend matches, or the error it fails with.
+    async fn matching(
+        &self,
+        backend: &dyn DynStoreBackend,
+        filter: &Expr,
+    ) -> Result<BTreeSet<usize>, String> {
+        let query = Query::builder()
+            .filter(filter.clone())
+            .build();
+
+        match backend
+            .query_documents(query, &self.collection)
+            .await
+        {
+            Ok(documents) => Ok(documents
+                .iter()
+                .filter_map(document_key)
+                .collect()),
+            Err(error) => Err(error.to_string()),
+        }
+    }
+
+    async fn diverges(
+        &self,
+        reference: &dyn DynStoreBackend,
+        candidate: &dyn DynStoreBackend,
+        filter: &Expr,
+    ) -> bool {
+        match (self.matching(reference, filter).await, self.matching(candidate, filter).await) {
+            (Ok(left), Ok(right)) => left != right,
+            (Err(_), Err(_)) => false,
+            _ => true,
+        }
+    }
+
+    /// Narrows a diverging filter to its smallest sub-expression that still diverges.
+    async fn reduce(
+        &self,
+        reference: &dyn DynStoreBackend,
+        candidate: &dyn DynStoreBackend,
+        mut filter: Expr,
+    ) -> Expr {
+        'narrow: loop {
+            let parts = match &filter {
+                Expr::And(exprs) | Expr::Or(exprs) => exprs.clone(),
+                Expr::Not(expr) | Expr::CaseInsensitive(expr) => vec![(**expr).clone()],
+                _ => return filter,
+            };
+
+            for part in parts {
+                if self
+                    .diverges(reference, candidate, &part)
+                    .await
+                {
+                    filter = part;
+                    continue 'narrow;
+                }
+            }
+
+            return filter;
+        }
+    }
+}
+
+/// The outcome of [`Differential::run`].
+#[derive(Debug, Clone)]
+pub struct DifferentialReport {
+    /// The seed the documents and filters were generated from.
+    pub seed: u64,
+    /// Number of filters run.
+    pub queries: usize,
+    /// The filters the backends disagreed on, one per reduced filter.
+    pub divergences: Vec<Divergence>,
+}
+
+impl DifferentialReport {
+    /// Returns `true` if the backends agreed on every filter.
+    pub fn is_consistent(&self) -> bool {
+        self.divergences.is_empty()
+    }
+
+    /// Panics, listing the divergences, if the backends disagreed on any filter.
+    #[track_caller]
+    pub fn assert_consistent(&self) {
+        if !self.is_consistent() {
+            panic!("{}", self);
+        }
+    }
+}
+
+impl fmt::Display for DifferentialReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} filters diverged (seed {})",
+            self.divergences.len(),
+            self.queries,
+            self.seed
+        )?;
+
+        for divergence in &self.divergences {
+            write!(f, "\n\n{}", divergence)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A filter two backends matched different documents for.
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    /// The generated filter.
+    pub filter: Expr,
+    /// The smallest part of the filter that still diverges.
+    pub reduced: Expr,
+    /// The keys of the documents the reference backend matched for the reduced filter, or
+    /// the error it failed with.
+    pub reference: Result<Vec<usize>, String>,
+    /// The keys of the documents the candidate backend matched for the reduced filter, or
+    /// the error it failed with.
+    pub candidate: Result<Vec<usize>, String>,
+    /// The documents only one of the backends matched.
+    pub documents: Vec<Bson>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "filter:    {}", self.reduced)?;
+        writeln!(f, "found in:  {}", self.filter)?;
+        writeln!(f, "reference: {}", describe(&self.reference))?;
+        write!(f, "candidate: {}", describe(&self.candidate))?;
+
+        for document in &self.documents {
+            write!(f, "\n  differs on {}", document)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn describe(result: &Result<Vec<usize>, String>) -> String {
+    match result {
+        Ok(keys) => format!("matched {:?}", keys),
+        Err(error) => format!("failed with {}", error),
+    }
+}
+
+fn key_id(key: usize) -> Uuid {
+    let mut bytes = [0u8; 16];
+
+    bytes[..8].copy_from_slice(&(key as u64).to_be_bytes());
+    bytes[15] = 0xd1;
+    Uuid::from_bytes(bytes)
+}
+
+fn document_key(document: &Bson) -> Option<usize> {
+    document
+        .as_document()?
+        .get_i64("key")
+        .ok()
+        .map(|key| key as usize)
+}
+
+/// Generates a document whose fields vary in type and presence, so comparisons meet every
+/// kind of value.
+fn generate_document(rng: &mut SplitMix64, key: usize) -> Bson {
+    let mut document = doc! { "key": key as i64 };
+
+    let fields = [
+        ("n", generate_number(rng)),
+        ("s", generate_string(rng)),
+        ("flag", Some(Bson::Boolean(rng.chance(0.5)))),
+        ("tags", generate_tags(rng)),
+        ("nested", generate_nested(rng)),
+    ];
+
+    for (field, value) in fields {
+        // Every field is sometimes null and sometimes missing
+        match rng.below(10) {
+            0 => {}
+            1 => {
+                document.insert(field, Bson::Null);
+            }
+            _ => {
+                if let Some(value) = value {
+                    document.insert(field, value);
+                }
+            }
+        }
+    }
+
+    Bson::Document(document)
+}
+
+fn generate_number(rng: &mut SplitMix64) -> Option<Bson> {
+    let value = rng.below(7) as i32 - 3;
+
+    Some(match rng.below(6) {
+        0 => Bson::Double(value as f64 + 0.5),
+        1 => Bson::Double(value as f64),
+        2 => Bson::Int64(value as i64),
+        // A number held as a string, which comparisons must not mix up with the number
+        3 => Bson::String(value.to_string()),
+        _ => Bson::Int32(value),
+    })
+}
+
+fn generate_string(rng: &mut SplitMix64) -> Option<Bson> {
+    Some(match rng.below(8) {
+        0 => Bson::Int32(rng.below(3) as i32),
+        _ => Bson::String(rng.pick(STRINGS).to_string()),
+    })
+}
+
+fn generate_tags(rng: &mut SplitMix64) -> Option<Bson> {
+    Some(match rng.below(5) {
+        // A single tag not held in an array
+        0 => Bson::String(rng.pick(STRINGS).to_string()),
+        _ => Bson::Array(
+            (0..rng.below(4))
+                .map(|_| Bson::String(rng.pick(STRINGS).to_string()))
+                .collect(),
+        ),
+    })
+}
+
+fn generate_nested(rng: &mut SplitMix64) -> Option<Bson> {
+    let mut nested = doc! {};
+
+    if let Some(x) = generate_number(rng).filter(|_| rng.chance(0.8)) {
+        nested.insert("x", x);
+    }
+    if let Some(s) = generate_string(rng).filter(|_| rng.chance(0.8)) {
+        nested.insert("s", s);
+    }
+
+    Some(Bson::Document(nested))
+}
+
+/// Generates a value to compare a field with, usually of the field's own kind.
+fn generate_value(rng: &mut SplitMix64, field: &str) -> Bson {
+    if rng.chance(0.1) {
+        return Bson::Null;
+    }
+
+    let value = match field {
+        "n" | "nested.x" => generate_number(rng),
+        "flag" => Some(Bson::Boolean(rng.chance(0.5))),
+        _ => generate_string(rng),
+    };
+
+    value.unwrap_or(Bson::Null)
+}
+
+fn generate_expr(rng: &mut SplitMix64, depth: usize, operators: &[FieldOp]) -> Expr {
+    if depth == 0 || rng.chance(0.4) {
+        return generate_leaf(rng, operators);
+    }
+
+    match rng.below(4) {
+        0 => Expr::And(
+            (0..2 + rng.below(2))
+                .map(|_| generate_expr(rng, depth - 1, operators))
+                .collect(),
+        ),
+        1 => Expr::Or(
+            (0..2 + rng.below(2))
+                .map(|_| generate_expr(rng, depth - 1, operators))
+                .collect(),
+        ),
+        2 => Expr::Not(Box::new(generate_expr(rng, depth - 1, operators))),
+        _ => Expr::CaseInsensitive(Box::new(generate_expr(rng, depth - 1, operators))),
+    }
+}
+
+fn generate_leaf(rng: &mut SplitMix64, operators: &[FieldOp]) -> Expr {
+    let field = rng.pick(FIELDS).to_string();
+
+    match rng.below(10) {
+        0 => Expr::Exists(field, rng.chance(0.5)),
+        1 => {
+            let low = generate_value(rng, &field);
+            let high = generate_value(rng, &field);
+
+            Expr::Range {
+                low: generate_bound(rng, low),
+                high: generate_bound(rng, high),
+                field,
+            }
+        }
+        _ if operators.is_empty() => Expr::Exists(field, rng.chance(0.5)),
+        _ => {
+            let op = rng.pick(operators).clone();
+            let value = match op {
+                // A value other than an array is a single candidate
+                FieldOp::AnyOf | FieldOp::NoneOf if rng.chance(0.25) => generate_value(rng, &field),
+                FieldOp::AnyOf | FieldOp::NoneOf => Bson::Array(
+                    (0..1 + rng.below(3))
+                        .map(|_| generate_value(rng, &field))
+                        .collect(),
+                ),
+                FieldOp::Regex => Bson::String(rng.pick(PATTERNS).to_string()),
+                _ => generate_value(rng, &field),
+            };
+
+            Expr::Field { field, op, value }
+        }
+    }
+}
+
+fn generate_bound(rng: &mut SplitMix64, value: Bson) -> Bound<Bson> {
+    match rng.below(3) {
+        0 => Bound::Included(value),
+        1 => Bound::Excluded(value),
+        _ => Bound::Unbounded,
+    }
+}
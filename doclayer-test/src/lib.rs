@@ -0,0 +1,54 @@
+//! Testing utilities for code built on doclayer.
+//!
+//! The in-memory backend is a faithful stand-in for a real database, but it never fails, so
+//! code handling backend errors can't be tested against it. This crate provides backends for
+//! those tests:
+//!
+//! - **Mock backend** ([`mock`]) - Scripted responses and errors for any backend method, with
+//!   the history of calls for assertions
+//! - **Fault injection** ([`chaos`]) - Seeded failures, latency and interrupted batch writes,
+//!   for testing retries and recovery
+//! - **Conformance suite** ([`conformance`]) - Behavioral checks a custom backend must pass to
+//!   behave like the built-in ones
+//! - **Differential testing** ([`differential`]) - Random filters run against two backends,
+//!   reporting those they match different documents for
+//!
+//! Add it as a development dependency:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! doclayer-test = "0.1"
+//! ```
+//!
+//! # Example
+//!
+//! ```ignore
+//! use doclayer::{error::DocumentStoreError, prelude::*};
+//! use doclayer_test::Mock;
+//!
+//! #[tokio::test]
+//! async fn reports_unavailable_backend() {
+//!     let mock = Mock::new();
+//!     let store = DocumentStore::new(mock.clone().wrap_in_memory().await.unwrap());
+//!
+//!     mock.on("insert_documents")
+//!         .collection("users")
+//!         .fails(|| DocumentStoreError::Unavailable("connection reset".to_string()));
+//!
+//!     assert!(signup(&store, "alice").await.is_err());
+//!     mock.assert_called("insert_documents", 1);
+//! }
+//! ```
+
+#[allow(unused_extern_crates)]
+extern crate self as doclayer_test;
+
+pub mod chaos;
+pub mod conformance;
+pub mod differential;
+pub mod mock;
+mod rng;
+
+pub use chaos::{Chaos, ChaosStats, ChaosStore, Delay};
+pub use differential::{Differential, DifferentialReport, Divergence};
+pub use mock::{Mock, MockStore, When};
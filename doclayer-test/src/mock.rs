@@ -0,0 +1,461 @@
+//! A scriptable mock backend.
+//!
+//! A [`Mock`] is a [`StoreLayer`] answering the calls it has an expectation for and passing
+//! the others on to the backend it wraps, usually an in-memory store. Tests set up the data
+//! they need through the store as usual, and script only the calls whose outcome matters:
+//!
+//! ```ignore
+//! let mock = Mock::new();
+//! let store = DocumentStore::new(mock.clone().wrap_in_memory().await?);
+//!
+//! // The first insert into "orders" times out, the next ones reach the backend
+//! mock.on("insert_documents")
+//!     .collection("orders")
+//!     .once()
+//!     .fails(|| DocumentStoreError::Timeout("insert_documents".to_string()));
+//!
+//! // Every count answers 10,000
+//! mock.on("count_documents")
+//!     .returns(Outcome::Count(10_000));
+//! ```
+//!
+//! Expectations are tried in the order they were added, and the first one matching a call
+//! answers it. One limited with [`When::times`] stops matching once it has answered that many
+//! calls, so several expectations for the same call answer in turn, like a queue. A
+//! [`strict`](Mock::strict) mock fails calls no expectation matches instead of passing them
+//! on.
+//!
+//! Every call is recorded, answered or not, for assertions:
+//!
+//! ```ignore
+//! mock.assert_called("insert_documents", 2);
+//! mock.assert_not_called("delete_documents");
+//!
+//! let inserts = mock.calls_to("insert_documents");
+//! assert_eq!(inserts[1].collection(), Some("orders"));
+//!
+//! // Panics if a limited expectation answered fewer calls than it was limited to
+//! mock.verify();
+//! ```
+//!
+//! Methods are named as in [`StoreBackend`], and calls are the [`Operation`]s the store
+//! layer sees. Methods with a default implementation on top of others, such as
+//! `acquire_migration_lock`, are seen as the calls they make.
+
+use async_trait::async_trait;
+use bson::Bson;
+use doclayer_core::{
+    backend::{Layered, Next, Operation, Outcome, StoreBackend, StoreBackendBuilder, StoreLayer},
+    error::{DocumentStoreError, DocumentStoreResult},
+};
+use doclayer_memory::InMemoryStore;
+use futures::{StreamExt, stream};
+use std::{
+    fmt::{self, Debug},
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+/// A backend whose calls are scripted and recorded by a [`Mock`].
+pub type MockStore<B = InMemoryStore> = Layered<Mock, B>;
+
+type Matcher = Box<dyn Fn(&Operation) -> bool + Send + Sync>;
+type Answer = Box<dyn Fn(&Operation) -> DocumentStoreResult<Outcome> + Send + Sync>;
+
+enum Response {
+    Answer(Answer),
+    PassThrough,
+}
+
+struct Expectation {
+    method: Option<&'static str>,
+    collection: Option<String>,
+    matcher: Option<Matcher>,
+    times: Option<usize>,
+    answered: usize,
+    response: Response,
+}
+
+impl Expectation {
+    fn matches(&self, operation: &Operation) -> bool {
+        self.times
+            .is_none_or(|times| self.answered < times)
+            && self
+                .method
+                .is_none_or(|method| method == operation.name())
+            && self
+                .collection
+                .as_deref()
+                .is_none_or(|collection| operation.collection() == Some(collection))
+            && self
+                .matcher
+                .as_ref()
+                .is_none_or(|matcher| matcher(operation))
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "{}{}{}",
+            self.method.unwrap_or("any method"),
+            self.collection
+                .as_deref()
+                .map(|collection| format!(" on '{}'", collection))
+                .unwrap_or_default(),
+            if self.matcher.is_some() {
+                " (matching)"
+            } else {
+                ""
+            }
+        )
+    }
+}
+
+impl Debug for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Expectation")
+            .field("method", &self.method)
+            .field("collection", &self.collection)
+            .field("times", &self.times)
+            .field("answered", &self.answered)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    strict: AtomicBool,
+    expectations: Mutex<Vec<Expectation>>,
+    calls: Mutex<Vec<Operation>>,
+}
+
+/// A [`StoreLayer`] answering calls from scripted expectations and recording every call.
+///
+/// Clones share the expectations and the recorded calls, so a copy kept by the test can
+/// script and inspect the store it wraps.
+#[derive(Debug, Clone, Default)]
+pub struct Mock {
+    state: Arc<MockState>,
+}
+
+impl Mock {
+    /// Creates a mock without expectations, passing every call on.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fails calls no expectation matches with [`DocumentStoreError::Backend`], instead of
+    /// passing them on to the backend.
+    pub fn strict(self) -> Self {
+        self.state
+            .strict
+            .store(true, Ordering::Relaxed);
+        self
+    }
+
+    /// Wraps a backend so its calls are scripted and recorded by this mock.
+    pub fn wrap<B: StoreBackend + 'static>(self, backend: B) -> MockStore<B> {
+        Layered::new(self, backend)
+    }
+
+    /// Wraps a new, empty in-memory store.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the in-memory store cannot be built.
+    pub async fn wrap_in_memory(self) -> DocumentStoreResult<MockStore> {
+        Ok(self.wrap(InMemoryStore::builder().build().await?))
+    }
+
+    /// Starts an expectation for calls to a backend method, such as `insert_documents`.
+    pub fn on(&self, method: &'static str) -> When<'_> {
+        When::new(self, Some(method))
+    }
+
+    /// Starts an expectation for calls to any backend method.
+    pub fn on_any(&self) -> When<'_> {
+        When::new(self, None)
+    }
+
+    /// Returns every recorded call, oldest first.
+    pub fn calls(&self) -> Vec<Operation> {
+        self.calls_guard().clone()
+    }
+
+    /// Returns the recorded calls to a backend method, oldest first.
+    pub fn calls_to(&self, method: &str) -> Vec<Operation> {
+        self.calls_guard()
+            .iter()
+            .filter(|operation| operation.name() == method)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the number of recorded calls to a backend method.
+    pub fn call_count(&self, method: &str) -> usize {
+        self.calls_guard()
+            .iter()
+            .filter(|operation| operation.name() == method)
+            .count()
+    }
+
+    /// Forgets the recorded calls, keeping the expectations.
+    pub fn clear_calls(&self) {
+        self.calls_guard().clear();
+    }
+
+    /// Removes every expectation, keeping the recorded calls.
+    pub fn clear_expectations(&self) {
+        self.expectations_guard().clear();
+    }
+
+    /// Panics unless a backend method was called exactly `times` times.
+    #[track_caller]
+    pub fn assert_called(&self, method: &str, times: usize) {
+        let count = self.call_count(method);
+
+        assert!(
+            count == times,
+            "expected {} to be called {} time(s), but it was called {} time(s)",
+            method,
+            times,
+            count
+        );
+    }
+
+    /// Panics if a backend method was called.
+    #[track_caller]
+    pub fn assert_not_called(&self, method: &str) {
+        self.assert_called(method, 0);
+    }
+
+    /// Panics if an expectation limited with [`When::times`] answered fewer calls than it
+    /// was limited to.
+    #[track_caller]
+    pub fn verify(&self) {
+        let unmet = self
+            .expectations_guard()
+            .iter()
+            .filter_map(|expectation| {
+                let times = expectation.times?;
+                (expectation.answered < times).then(|| {
+                    format!(
+                        "{}: answered {} of {} call(s)",
+                        expectation.describe(),
+                        expectation.answered,
+                        times
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+
+        assert!(unmet.is_empty(), "unmet mock expectations:\n  {}", unmet.join("\n  "));
+    }
+
+    fn calls_guard(&self) -> MutexGuard<'_, Vec<Operation>> {
+        // A panicking matcher or answer leaves the state consistent, so poisoning is ignored
+        self.state
+            .calls
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn expectations_guard(&self) -> MutexGuard<'_, Vec<Expectation>> {
+        self.state
+            .expectations
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Finds the expectation answering a call, or `None` to pass it on.
+    fn respond(&self, operation: &Operation) -> Option<DocumentStoreResult<Outcome>> {
+        let mut expectations = self.expectations_guard();
+
+        let Some(expectation) = expectations
+            .iter_mut()
+            .find(|expectation| expectation.matches(operation))
+        else {
+            return self
+                .state
+                .strict
+                .load(Ordering::Relaxed)
+                .then(|| {
+                    Err(DocumentStoreError::Backend(format!(
+                        "Unexpected call to {} on a strict mock",
+                        operation.name()
+                    )))
+                });
+        };
+
+        expectation.answered += 1;
+        match &expectation.response {
+            Response::Answer(answer) => Some(answer(operation)),
+            Response::PassThrough => None,
+        }
+    }
+}
+
+#[async_trait]
+impl StoreLayer for Mock {
+    async fn call(&self, operation: Operation, next: Next<'_>) -> DocumentStoreResult<Outcome> {
+        self.calls_guard()
+            .push(operation.clone());
+
+        match self.respond(&operation) {
+            Some(outcome) => adapt(&operation, outcome?),
+            None => next.run(operation).await,
+        }
+    }
+}
+
+/// Streams documents answered to `query_stream`, so expectations can answer it like
+/// `query_documents`.
+fn adapt(operation: &Operation, outcome: Outcome) -> DocumentStoreResult<Outcome> {
+    Ok(match (operation, outcome) {
+        (Operation::QueryStream { .. }, Outcome::Documents(documents)) => Outcome::Stream(
+            stream::iter(
+                documents
+                    .into_iter()
+                    .map(Ok::<Bson, DocumentStoreError>),
+            )
+            .boxed(),
+        ),
+        (_, outcome) => outcome,
+    })
+}
+
+/// Copies an outcome so an expectation can answer it more than once.
+///
+/// Streams can't be copied, so `None` is returned for them.
+fn duplicate(outcome: &Outcome) -> Option<Outcome> {
+    Some(match outcome {
+        Outcome::Done => Outcome::Done,
+        Outcome::Report(report) => Outcome::Report(*report),
+        Outcome::Count(count) => Outcome::Count(*count),
+        Outcome::Documents(documents) => Outcome::Documents(documents.clone()),
+        Outcome::Document(document) => Outcome::Document(document.clone()),
+        Outcome::Stream(_) => return None,
+        Outcome::RawDocuments(documents) => Outcome::RawDocuments(documents.clone()),
+        Outcome::Plan(plan) => Outcome::Plan(plan.clone()),
+        Outcome::Revision(revision) => Outcome::Revision(revision.clone()),
+        Outcome::Exists(exists) => Outcome::Exists(*exists),
+        Outcome::Collections(names) => Outcome::Collections(names.clone()),
+    })
+}
+
+/// An expectation being set up by [`Mock::on`].
+///
+/// Narrow the calls it matches, then finish it with the response, which adds it to the mock.
+#[must_use = "an expectation is only added once it's given a response"]
+pub struct When<'a> {
+    mock: &'a Mock,
+    method: Option<&'static str>,
+    collection: Option<String>,
+    matcher: Option<Matcher>,
+    times: Option<usize>,
+}
+
+impl<'a> When<'a> {
+    fn new(mock: &'a Mock, method: Option<&'static str>) -> Self {
+        Self {
+            mock,
+            method,
+            collection: None,
+            matcher: None,
+            times: None,
+        }
+    }
+
+    /// Only matches calls targeting a collection.
+    pub fn collection(mut self, name: impl Into<String>) -> Self {
+        self.collection = Some(name.into());
+        self
+    }
+
+    /// Only matches calls for which `matcher` returns `true`, to match on any argument.
+    ///
+    /// ```ignore
+    /// mock.on("get_documents")
+    ///     .matching(|operation| matches!(operation, Operation::GetDocuments { ids, .. } if ids.contains(&id)))
+    ///     .returns(Outcome::Documents(Vec::new()));
+    /// ```
+    pub fn matching(
+        mut self,
+        matcher: impl Fn(&Operation) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.matcher = Some(Box::new(matcher));
+        self
+    }
+
+    /// Answers at most `times` calls, after which later expectations and then the backend
+    /// answer.
+    pub fn times(mut self, times: usize) -> Self {
+        self.times = Some(times);
+        self
+    }
+
+    /// Answers a single call, like `times(1)`.
+    pub fn once(self) -> Self {
+        self.times(1)
+    }
+
+    /// Answers matching calls with an outcome.
+    ///
+    /// The outcome must be the variant the method returns, except that `query_stream` may be
+    /// answered with [`Outcome::Documents`], which are streamed. A [`Outcome::Stream`] can
+    /// only be answered once, and fails later calls.
+    pub fn returns(self, outcome: Outcome) -> &'a Mock {
+        let stored = Mutex::new(Some(outcome));
+
+        self.responds(move |operation| {
+            let mut stored = stored
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+            match stored.as_ref().and_then(duplicate) {
+                Some(outcome) => Ok(outcome),
+                None => stored.take().ok_or_else(|| {
+                    DocumentStoreError::Backend(format!(
+                        "The mocked stream for {} was already returned",
+                        operation.name()
+                    ))
+                }),
+            }
+        })
+    }
+
+    /// Fails matching calls with the error `error` makes.
+    pub fn fails(self, error: impl Fn() -> DocumentStoreError + Send + Sync + 'static) -> &'a Mock {
+        self.responds(move |_| Err(error()))
+    }
+
+    /// Answers matching calls with the result of `answer`, which receives the call.
+    pub fn responds(
+        self,
+        answer: impl Fn(&Operation) -> DocumentStoreResult<Outcome> + Send + Sync + 'static,
+    ) -> &'a Mock {
+        self.add(Response::Answer(Box::new(answer)))
+    }
+
+    /// Passes matching calls on to the backend, so a [`strict`](Mock::strict) mock allows
+    /// them and limited expectations count them.
+    pub fn passes_through(self) -> &'a Mock {
+        self.add(Response::PassThrough)
+    }
+
+    fn add(self, response: Response) -> &'a Mock {
+        self.mock
+            .expectations_guard()
+            .push(Expectation {
+                method: self.method,
+                collection: self.collection,
+                matcher: self.matcher,
+                times: self.times,
+                answered: 0,
+                response,
+            });
+
+        self.mock
+    }
+}
@@ -0,0 +1,37 @@
+/// A small, seedable random generator, so injected faults and generated data repeat between
+/// runs.
+#[derive(Debug, Clone)]
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `0.0..1.0`.
+    pub(crate) fn fraction(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns a number in `0..bound`.
+    pub(crate) fn below(&mut self, bound: usize) -> usize {
+        (self.fraction() * bound as f64) as usize
+    }
+
+    /// Returns `true` with probability `rate`.
+    pub(crate) fn chance(&mut self, rate: f64) -> bool {
+        self.fraction() < rate
+    }
+
+    /// Returns one of `items`, which must not be empty.
+    pub(crate) fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
@@ -7,9 +7,10 @@
 //! # Features
 //!
 //! - **Type-safe document storage** - Define your data structures with Serde and store them safely
-//! - **Multiple backends** - Support for in-memory and MongoDB storage with extensible trait system
+//! - **Multiple backends** - Support for in-memory, filesystem and MongoDB storage with extensible trait system
 //! - **Flexible querying** - Powerful, composable query API for filtering and sorting
-//! - **Schema migrations** - Versioned migrations for evolving your data models
+//! - **Schema migrations** - Versioned migrations for evolving your data models, runnable
+//!   from the command line with the `cli` feature
 //!
 //! # Quick Start
 //!
@@ -117,8 +118,7 @@
 //!     println!("Queried users: {:?}", results);
 //!     
 //!     // Shutdown the store
-//!     // The dynamic store must use the `shutdown_boxed` method
-//!     dyn_store.shutdown_boxed().await.unwrap();
+//!     dyn_store.shutdown().await.unwrap();
 //! }
 //! ```
 //! 
@@ -195,18 +195,49 @@
 //! # Backends
 //!
 //! - [`memory`] - Fast in-memory storage for development and testing
+//! - [`file`] - Directories of JSON files, for config-as-data and diffing in git
 //! - [`mongodb`] - Persistent MongoDB backend (requires `mongodb` feature)
+//! - [`couchdb`] - CouchDB over its HTTP API (requires `couchdb` feature)
+//! - [`indexeddb`] - The browser's IndexedDB, on `wasm32` targets (requires `indexeddb` feature)
 
 pub mod prelude;
 
-pub use doclayer_core::{collection, document, store, backend, query, migrate, error};
+#[cfg(feature = "cli")]
+pub mod cli;
+
+pub use doclayer_core::{aggregate, archive, audit, import, limit, rollup, collection, document, encoding, events, expiry, store, backend, query, migrate, lint, plugin, prefix, cache, mirror, split, shadow, slowlog, retry, schema, seed, timeout, timeseries, transaction, error, update, validate, versioned, page};
+
+/// Tracing spans around store operations.
+///
+/// This module is only available when the `tracing` feature is enabled.
+#[cfg(feature = "tracing")]
+pub use doclayer_core::trace;
+
+/// Metrics for store operations.
+///
+/// This module is only available when the `metrics` feature is enabled.
+#[cfg(feature = "metrics")]
+pub use doclayer_core::metrics;
+
+// Re-export derive macros
+pub use doclayer_macros::{Document, SchemaType};
+
+pub use doclayer_core::register_documents;
 
 // Re-export BSON types for convenience
 pub use bson;
 
+// Re-exported for the schemas generated by `#[derive(SchemaType)]`
+pub use serde_json;
+
 /// In-memory storage backend implementations.
 pub mod memory {
-    pub use doclayer_memory::{InMemoryStore, InMemoryStoreBuilder};
+    pub use doclayer_memory::{InMemoryStore, InMemoryStoreBuilder, IntegrityReport, CorruptEntry, IntegrityProblem};
+}
+
+/// Filesystem storage backend implementations.
+pub mod file {
+    pub use doclayer_memory::{FileStore, FileStoreBuilder};
 }
 
 /// MongoDB storage backend implementations.
@@ -214,5 +245,21 @@ pub mod memory {
 /// This module is only available when the `mongodb` feature is enabled.
 #[cfg(feature = "mongodb")]
 pub mod mongodb {
-    pub use doclayer_mongodb::{MongoDbStore, MongoDbStoreBuilder};
+    pub use doclayer_mongodb::{MongoDbStore, MongoDbStoreBuilder, MongoDbTlsConfig};
 }
+
+/// CouchDB storage backend implementations.
+///
+/// This module is only available when the `couchdb` feature is enabled.
+#[cfg(feature = "couchdb")]
+pub mod couchdb {
+    pub use doclayer_couchdb::{CouchDbStore, CouchDbStoreBuilder, DEFAULT_DATABASE_PREFIX};
+}
+
+/// IndexedDB storage backend implementations.
+///
+/// This module is only available when the `indexeddb` feature is enabled, on `wasm32` targets.
+#[cfg(all(feature = "indexeddb", target_arch = "wasm32"))]
+pub mod indexeddb {
+    pub use doclayer_indexeddb::{IndexedDbStore, IndexedDbStoreBuilder};
+}
\ No newline at end of file
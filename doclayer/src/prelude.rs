@@ -11,15 +11,46 @@
 //! - Document traits and implementations
 //! - Store backends and builders
 //! - Query construction and filtering
+//! - Partial updates and aggregations
 //! - Collection interfaces
-//! - Error types and migration tools
+//! - Error types, imports, migration tools, seeding, linting, schemas, plugins, archival policies, rollups, concurrency limits, prefixes, caching, mirroring, read/write splitting, shadow mode, slow query logs, retries, timeouts, transactions, revisions, time series and encodings
 
 pub use doclayer_core::{
     collection::{Collection, DynCollection},
     store::{DocumentStore, DynDocumentStore, DynDocumentStoreRef, AsDynDocumentStore, IntoDynDocumentStore, AsStaticDocumentStore, IntoStaticDocumentStore},
-    document::{Document, DocumentExt},
-    backend::{StoreBackend, DynStoreBackend, StoreBackendBuilder},
-    query::{Query, QueryVisitor, Expr, Sort, SortDirection, FieldOp, QueryBuilder, Filter},
-    migrate::{Migration, MigrationDirection, MigrationRef, MigrateOp, MigrationRunner, Migrations, Migrator},
+    document::{Document, DocumentExt, AnyDocument, DocumentRegistry, DocumentTypes, IndexDefinition, RawDoc},
+    backend::{StoreBackend, DynStoreBackend, StoreBackendBuilder, InsertPolicy, MissingDocumentPolicy, ReturnDocument, WriteReport, DocumentStream, QueryPlan, ScanStrategy, StoreLayer, Layered, Operation, Outcome, Next},
+    query::{Query, QueryVisitor, Expr, Sort, SortDirection, SortComparator, CustomOperator, FieldOp, QueryBuilder, Filter, Field, IntoFilter, TypedFields},
+    update::{Update, UpdateBuilder, UpdateOp},
+    aggregate::{Aggregate, AggregateBuilder, Accumulator},
+    page::{Page, PaginationParams, QueryPage, PageRequest, PageCursor},
+    migrate::{LockPolicy, MergeMigration, Migration, MigrationDirection, MigrationRecord, MigrationRef, MigrationStatus, MigrateOp, MigrationRunner, Migrations, Migrator},
+    lint::{LintReport, FieldMismatch, LintFix},
+    seed::{Environment, Seed, SeedHost, SeedOp, SeedRef, SeedReport, Seeder},
+    schema::SchemaType,
+    plugin::{DoclayerPlugin, PluginHost},
+    archive::{ArchivePolicy, ArchivePolicyBuilder, ArchiveTarget, ArchiveReport, ArchiveHost, Archiver},
+    import::{ImportOptions, ImportErrorPolicy, ImportReport},
+    limit::{ConcurrencyLimiter, ConcurrencyLimited, LimiterMetrics},
+    prefix::Prefixed,
+    cache::CachedStore,
+    mirror::{MirroredStore, MirrorPrimary, Divergence, DivergenceKind},
+    split::{SplitStore, ReplicaPolicy, ReplicaStats, RoundRobin, LowestLatency},
+    shadow::{Shadow, ShadowStore, ShadowWrite},
+    slowlog::{SlowQuery, SlowQueryLog, SlowQueryStore},
+    retry::{Retry, RetryStore},
+    timeout::{Timeout, TimeoutStore},
+    transaction::{Transaction, TransactionBackend},
+    versioned::Versioned,
+    timeseries::{TimeSeriesCollection, BucketSpan, Bucket},
+    encoding::{Encoding, UuidRepresentation, EnumRepresentation, DateTimePrecision},
+    rollup::{Rollup, RollupBuilder, RollupChange, RollupHost},
     error::{DocumentStoreError, DocumentStoreResult},
 };
+
+#[cfg(feature = "tracing")]
+pub use doclayer_core::trace::{Traced, TracedStore};
+#[cfg(feature = "metrics")]
+pub use doclayer_core::metrics::{Metrics, MetricsStore};
+
+pub use doclayer_macros::{Document, SchemaType};
@@ -0,0 +1,70 @@
+//! Runs typed queries built from the `<Name>Fields` a derived document passes to `filter_by`
+//! and the sorts taking a closure.
+
+use bson::Uuid;
+use serde::{Deserialize, Serialize};
+
+use doclayer::{memory::InMemoryStore, prelude::*};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Document)]
+#[document(collection = "users")]
+struct User {
+    id: Uuid,
+    name: String,
+    age: u64,
+}
+
+fn user(name: &str, age: u64) -> User {
+    User { id: Uuid::new(), name: name.to_string(), age }
+}
+
+async fn store() -> DocumentStore<InMemoryStore> {
+    let store = DocumentStore::new(InMemoryStore::new());
+
+    store
+        .typed_collection::<User>()
+        .insert(vec![user("Alice", 17), user("Alice", 30), user("Bob", 40), user("Alice", 25)])
+        .await
+        .unwrap();
+    store
+}
+
+fn ages(users: Vec<User>) -> Vec<u64> {
+    users.into_iter().map(|user| user.age).collect()
+}
+
+#[tokio::test]
+async fn find_filters_and_sorts_by_typed_fields() {
+    let store = store().await;
+    let users = store.typed_collection::<User>();
+
+    let adults = users
+        .find()
+        .filter_by(|f| f.name().eq("Alice").and_filter(f.age().gt(18u64)))
+        .sort_desc_by(|f| f.age())
+        .await
+        .unwrap();
+    assert_eq!(ages(adults), vec![30, 25]);
+
+    let youngest = users
+        .find()
+        .filter_by(|f| f.name().starts_with("B").or_filter(f.age().lt(20u64)))
+        .sort_asc_by(|f| f.age())
+        .await
+        .unwrap();
+    assert_eq!(ages(youngest), vec![17, 40]);
+}
+
+#[tokio::test]
+async fn find_fails_with_the_error_of_a_filter_that_failed_to_build() {
+    let store = store().await;
+
+    // BSON has no integers above `i64::MAX`
+    let result = store
+        .typed_collection::<User>()
+        .find()
+        .filter_by(|f| f.age().gt(u64::MAX))
+        .filter_by(|f| f.name().eq("Alice"))
+        .await;
+    assert!(matches!(result, Err(DocumentStoreError::Serialization(_))), "{result:?}");
+}